@@ -0,0 +1,50 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { abort_token_new, abort_token_abort } = require("./bin-package/index.node");
+const { AbortError, convertAbortError } = require('./error');
+
+// followSignal returns the native token aborted together with the signal,
+// and the function to stop following the signal once the operation is done.
+const followSignal = signal => {
+    const token = abort_token_new();
+    const abort = () => abort_token_abort(token);
+    signal.addEventListener('abort', abort, { once: true });
+    return [token, () => signal.removeEventListener('abort', abort)];
+};
+
+// runWithSignal calls run with the native token following the signal, or with null without the signal.
+// The operation stops at its next safe point once the signal is aborted, and it is rejected with AbortError.
+const runWithSignal = async (signal, run) => {
+    if (!signal) {
+        return run(null);
+    }
+    if (signal.aborted) {
+        throw new AbortError();
+    }
+    const [token, unfollow] = followSignal(signal);
+    try {
+        return await run(token);
+    } catch (err) {
+        throw convertAbortError(err);
+    } finally {
+        unfollow();
+    }
+};
+
+module.exports = {
+    followSignal,
+    runWithSignal,
+};
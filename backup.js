@@ -0,0 +1,45 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const {
+    backup_restore,
+    backup_purge,
+} = require("./bin-package/index.node");
+
+// restoreFromBackup restores the latest backup in the directory to the target path.
+// The database at the target path must be closed while restoring.
+const restoreFromBackup = async (dir, target) => new Promise((resolve, reject) => {
+    backup_restore(dir, target, err => {
+        if (err) {
+            return reject(err);
+        }
+        resolve();
+    });
+});
+
+// purgeOldBackups removes the backups in the directory except the latest ones.
+const purgeOldBackups = async (dir, numBackupsToKeep) => new Promise((resolve, reject) => {
+    backup_purge(dir, numBackupsToKeep, err => {
+        if (err) {
+            return reject(err);
+        }
+        resolve();
+    });
+});
+
+module.exports = {
+    restoreFromBackup,
+    purgeOldBackups,
+};
@@ -240,7 +240,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         common_db.write(write_batch.batch)?;
 
         let proof = tree.prove(
-            &mut db,
+            &db,
             &QUERY_KEYS
                 .iter()
                 .map(|k| hex::decode(k).unwrap())
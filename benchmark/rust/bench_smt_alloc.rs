@@ -0,0 +1,93 @@
+// bench_smt_alloc counts the heap allocations of the commit, the update and the proof of the tree kept in memory.
+// The counts do not depend on the machine, so they show the allocations saved by a change without the noise of the timings.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+use lisk_db::sparse_merkle_tree::smt::{SparseMerkleTree, UpdateData};
+use lisk_db::sparse_merkle_tree::smt_db::InMemorySmtDB;
+use lisk_db::types::{Cache, KeyLength, NestedVec, SharedKVPair};
+
+const DATA_LEN: usize = 10_000;
+const QUERY_LEN: usize = 1_000;
+
+/// CountingAllocator counts the allocations and the reallocations made through the system allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// count returns the result of f with the number of the allocations made while it runs.
+fn count<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - start)
+}
+
+fn hash(i: usize, salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(i.to_be_bytes());
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
+
+fn get_data(len: usize, salt: &[u8]) -> UpdateData {
+    let mut data = UpdateData::new_from(Cache::new());
+    for i in 0..len {
+        data.insert(SharedKVPair(&hash(i, &[]), &hash(i, salt)));
+    }
+
+    data
+}
+
+fn main() {
+    let data = get_data(DATA_LEN, &[1]);
+    let updates = get_data(DATA_LEN / 2, &[2]);
+    let query_keys: NestedVec = (0..QUERY_LEN).map(|i| hash(i, &[])).collect();
+
+    let mut db = InMemorySmtDB::default();
+    let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+    let (_, commit) = count(|| tree.commit(&mut db, &data).unwrap());
+    println!(
+        "commit of {} leaves: {} allocations, {:.1} per leaf",
+        DATA_LEN,
+        commit,
+        commit as f64 / DATA_LEN as f64
+    );
+
+    // the update decodes the subtrees on the paths of the updated keys
+    let (_, update) = count(|| tree.commit(&mut db, &updates).unwrap());
+    println!(
+        "update of {} leaves: {} allocations, {:.1} per leaf",
+        DATA_LEN / 2,
+        update,
+        update as f64 / (DATA_LEN / 2) as f64
+    );
+
+    let (_, prove) = count(|| tree.prove(&db, &query_keys).unwrap());
+    println!(
+        "proof of {} keys: {} allocations, {:.1} per key",
+        QUERY_LEN,
+        prove,
+        prove as f64 / QUERY_LEN as f64
+    );
+}
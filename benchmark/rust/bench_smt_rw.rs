@@ -1,7 +1,7 @@
 use std::sync::mpsc;
 use std::time::Duration;
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use rocksdb::{Options, DB};
 use sha2::{Digest, Sha256};
 use tempdir::TempDir;
@@ -12,7 +12,9 @@ use lisk_db::database::types::{DbMessage, Kind};
 use lisk_db::database::DB as LDB;
 use lisk_db::sparse_merkle_tree::smt::{SparseMerkleTree, UpdateData};
 use lisk_db::sparse_merkle_tree::smt_db;
-use lisk_db::types::{Cache, KeyLength, NestedVec, SharedKVPair};
+use lisk_db::types::{
+    hash_branches, Cache, HashKind, HashWithKind, KeyLength, NestedVec, SharedKVPair,
+};
 
 const DATA_LEN: usize = 100_000;
 const QUERY_LEN: usize = 1_000;
@@ -102,9 +104,76 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
+// hashing_benchmark compares hashing the branches one by one with hashing them in one call.
+// Run it with and without `--features asm` to compare the sha2 backends on the CPU.
+fn hashing_benchmark(c: &mut Criterion) {
+    let hashes: NestedVec = (0..DATA_LEN)
+        .map(|i| Sha256::digest(i.to_be_bytes()).to_vec())
+        .collect();
+
+    let mut group = c.benchmark_group("branch hashing");
+    group.throughput(Throughput::Elements((DATA_LEN / 2) as u64));
+    group.bench_function("concatenated", |b| {
+        b.iter(|| {
+            for pair in hashes.chunks(2) {
+                let branch = [pair[0].as_slice(), &pair[1]].concat();
+                black_box(branch.hash_with_kind(HashKind::Branch));
+            }
+        })
+    });
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            black_box(hash_branches(
+                hashes
+                    .chunks(2)
+                    .map(|pair| (pair[0].as_slice(), pair[1].as_slice())),
+            ))
+        })
+    });
+    group.finish();
+}
+
+// verify_benchmark measures the verification of the proof of QUERY_LEN keys without the commit and the proof generation.
+// calculate_root is the root calculation used before verify moved the queries up the tree by layer.
+fn verify_benchmark(c: &mut Criterion) {
+    let data = get_data();
+    let query_keys: NestedVec = get_query_keys()
+        .iter()
+        .map(|k| hex::decode(k).unwrap())
+        .collect();
+    let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+    let mut db = smt_db::InMemorySmtDB::default();
+    let root = tree.commit(&mut db, &data).unwrap();
+    let root = root.lock().unwrap().to_vec();
+    let proof = tree.prove(&db, &query_keys).unwrap();
+
+    let mut group = c.benchmark_group("proof verification");
+    group.throughput(Throughput::Elements(QUERY_LEN as u64));
+    group.bench_function("calculate_root", |b| {
+        b.iter(|| {
+            let mut queries: Vec<_> = SparseMerkleTree::prepare_queries_with_proof_map(&proof)
+                .unwrap()
+                .into_values()
+                .collect();
+            let computed = SparseMerkleTree::calculate_root(&proof.sibling_hashes, &mut queries);
+            assert_eq!(computed.unwrap(), root);
+        })
+    });
+    group.bench_function("verify", |b| {
+        b.iter(|| {
+            let is_valid =
+                SparseMerkleTree::verify(&query_keys, &proof, &root, KeyLength(32)).unwrap();
+            assert!(black_box(is_valid));
+        })
+    });
+    group.finish();
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().sample_size(50).measurement_time(Duration::from_secs(40));
     targets = criterion_benchmark
 }
-criterion_main!(benches);
+criterion_group!(hashing, hashing_benchmark);
+criterion_group!(verify, verify_benchmark);
+criterion_main!(benches, hashing, verify);
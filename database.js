@@ -19,14 +19,45 @@ const {
     db_close,
     db_get,
     db_exists,
+    db_get_many,
+    db_get_stream,
     db_set,
     db_del,
     db_write,
     db_iterate,
+    db_iterate_page,
     db_checkpoint,
+    db_create_backup,
+    db_compact_range,
+    db_set_auto_compaction,
+    db_flush,
+    db_sync_wal,
+    db_properties,
+    db_approximate_size,
+    db_write_stall,
+    db_snapshot_new,
+    db_snapshot_release,
+    db_snapshot_get,
+    db_snapshot_exists,
+    db_snapshot_iterate,
+    db_new_secondary,
+    db_try_catch_up_with_primary,
+    db_transaction_get,
+    db_transaction_iterate,
+    db_transaction_commit,
     batch_new,
     batch_set,
     batch_del,
+    batch_set_if_absent,
+    batch_del_if_equals,
+    batch_encode,
+    batch_decode,
+    batch_len,
+    batch_size,
+    batch_operations,
+    transaction_new,
+    transaction_set,
+    transaction_del,
     in_memory_db_new,
     in_memory_db_clone,
     in_memory_db_get,
@@ -37,20 +68,27 @@ const {
     in_memory_db_iterate,
 } = require("./bin-package/index.node");
 const { Readable } = require('stream');
-const { NotFoundError } = require('./error');
-const { Iterator } = require('./iterator');
+const { ErrorCode, NotFoundError, DatabaseClosedError, convertOpenError, convertBatchError } = require('./error');
+const { Iterator, PageIterator } = require('./iterator');
+const { ResourceTracker } = require('./resource_tracker');
 const { getOptionsWithDefault } = require('./options');
+const { followPrimary, watchWriteStall } = require('./utils');
+
+const DEFAULT_FOLLOW_INTERVAL = 1000;
+const DEFAULT_WRITE_STALL_INTERVAL = 1000;
+const DEFAULT_STREAM_CHUNK_SIZE = 64 * 1024;
 
 class Reader {
-    constructor(db) {
+    constructor(db, tracker) {
         this._db = db;
+        this._tracker = tracker;
     }
 
     async get(key) {
         return new Promise((resolve, reject) => {
             db_get.call(this._db, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
                     }
                     return reject(err);
@@ -72,24 +110,90 @@ class Reader {
     }
 
     iterate(options = {}) {
-        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options), this._tracker);
+    }
+
+    createReadStream(options = {}) {
+        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options), this._tracker);
+    }
+}
+
+// Snapshot reads the database as it was when the snapshot was created, until it is released
+class Snapshot {
+    constructor(db, tracker) {
+        this._tracker = tracker;
+        this._trackerID = tracker.acquire(this, 'snapshot');
+        this._db = db_snapshot_new(db);
+    }
+
+    release() {
+        db_snapshot_release.call(this._db);
+        this._tracker.release(this, this._trackerID);
+    }
+
+    async get(key) {
+        return new Promise((resolve, reject) => {
+            db_snapshot_get.call(this._db, key, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async has(key) {
+        return new Promise((resolve, reject) => {
+            db_snapshot_exists.call(this._db, key, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    iterate(options = {}) {
+        return new Iterator(this._db, db_snapshot_iterate, getOptionsWithDefault(options), this._tracker);
     }
 
     createReadStream(options = {}) {
-        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, db_snapshot_iterate, getOptionsWithDefault(options), this._tracker);
     }
 }
 
 class Database {
+    // With secondaryPath, the database at the path is opened as a read only secondary instance,
+    // which follows the primary opened by another process
     constructor(path, opts = {}) {
-        this._db = db_new(path, opts);
+        try {
+            this._handle = opts.secondaryPath
+                ? db_new_secondary(path, opts.secondaryPath, opts)
+                : db_new(path, opts);
+        } catch (err) {
+            throw convertOpenError(err);
+        }
+        this._tracker = new ResourceTracker(opts);
+        this._closed = false;
+        this._watchers = new Set();
+    }
+
+    // _db is the native handle, which every operation goes through, so that they throw DatabaseClosedError after close
+    get _db() {
+        if (this._closed) {
+            throw new DatabaseClosedError();
+        }
+        return this._handle;
     }
 
     async get(key) {
         return new Promise((resolve, reject) => {
             db_get.call(this._db, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
                     }
                     return reject(err);
@@ -99,6 +203,46 @@ class Database {
         });
     }
 
+    async getMany(keys) {
+        return new Promise((resolve, reject) => {
+            db_get_many.call(this._db, keys, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getStream calls onChunk with the value of the key in the chunks of chunkSize bytes, and resolves with the size of the value.
+    // The next chunk is read after onChunk returns, so the value is never held as a whole. onChunk stops the stream by returning false.
+    async getStream(key, onChunk, options = {}) {
+        const chunkSize = options.chunkSize === undefined ? DEFAULT_STREAM_CHUNK_SIZE : options.chunkSize;
+        let chunkError;
+        const handleChunk = chunk => {
+            try {
+                return onChunk(chunk) !== false;
+            } catch (err) {
+                chunkError = err;
+                return false;
+            }
+        };
+        return new Promise((resolve, reject) => {
+            db_get_stream.call(this._db, key, chunkSize, handleChunk, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
+                    }
+                    return reject(err);
+                }
+                if (chunkError) {
+                    return reject(chunkError);
+                }
+                resolve(result);
+            });
+        });
+    }
+
     async has(key) {
         return new Promise((resolve, reject) => {
             db_exists.call(this._db, key, (err, result) => {
@@ -132,11 +276,11 @@ class Database {
         });
     }
 
-    async write(batch) {
+    async write(batch, options = {}) {
         return new Promise((resolve, reject) => {
-            db_write.call(this._db, batch.inner, err => {
+            db_write.call(this._db, batch.inner, options, err => {
                 if (err) {
-                    return reject(err);
+                    return reject(convertBatchError(err));
                 }
                 resolve();
             });
@@ -144,11 +288,11 @@ class Database {
     }
 
     iterate(options = {}) {
-        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options));
+        return new PageIterator(this._db, db_iterate_page, getOptionsWithDefault(options), this._tracker, options.pageSize, options.signal);
     }
 
     createReadStream(options = {}) {
-        return new Iterator(this._db, db_iterate, getOptionsWithDefault(options));
+        return new PageIterator(this._db, db_iterate_page, getOptionsWithDefault(options), this._tracker, options.pageSize, options.signal);
     }
 
     async clear(options = {}) {
@@ -182,11 +326,36 @@ class Database {
     }
 
     newReader() {
-        return new Reader(this._db);
+        return new Reader(this._db, this._tracker);
     }
 
-    close() {
-        db_close.call(this._db);
+    snapshot() {
+        return new Snapshot(this._db, this._tracker);
+    }
+
+    getOpenResources() {
+        return this._tracker.openResources();
+    }
+
+    getLeakedResources() {
+        return this._tracker.leakedResources();
+    }
+
+    // close flushes the memtables and releases the database after the pending operations, and stops the watchers.
+    // The callback is called once the same path can be opened again.
+    close(cb) {
+        if (this._closed) {
+            if (cb) {
+                process.nextTick(cb, null);
+            }
+            return;
+        }
+        this._closed = true;
+        for (const stop of this._watchers) {
+            stop();
+        }
+        this._watchers.clear();
+        db_close.call(this._handle, cb);
     }
 
     async checkpoint(path) {
@@ -199,6 +368,299 @@ class Database {
             });
         });
     }
+
+    async createBackup(dir) {
+        return new Promise((resolve, reject) => {
+            db_create_backup.call(this._db, dir, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async compactRange(start, end) {
+        return new Promise((resolve, reject) => {
+            db_compact_range.call(this._db, start || null, end || null, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    setAutoCompaction(enabled) {
+        db_set_auto_compaction.call(this._db, enabled);
+    }
+
+    async flush() {
+        return new Promise((resolve, reject) => {
+            db_flush.call(this._db, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async syncWal() {
+        return new Promise((resolve, reject) => {
+            db_sync_wal.call(this._db, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async getProperties() {
+        return new Promise((resolve, reject) => {
+            db_properties.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async getWriteStall() {
+        return new Promise((resolve, reject) => {
+            db_write_stall.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // onWriteStall calls the listener when the writes are delayed or stopped by the background flush and compaction,
+    // and when they are back to normal
+    onWriteStall(listener, interval = DEFAULT_WRITE_STALL_INTERVAL) {
+        return this._watch(watchWriteStall(() => this.getWriteStall(), listener, interval));
+    }
+
+    // approximateSize estimates the size and the number of the keys between start and end from the SST files
+    async approximateSize(start, end) {
+        return new Promise((resolve, reject) => {
+            db_approximate_size.call(this._db, start || null, end || null, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    withPrefix(prefix) {
+        return new PrefixedDatabase(this, prefix);
+    }
+
+    async tryCatchUpWithPrimary() {
+        return new Promise((resolve, reject) => {
+            db_try_catch_up_with_primary.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // followPrimary calls the listener with the latest sequence number whenever the secondary catches up with new data
+    followPrimary(listener, interval = DEFAULT_FOLLOW_INTERVAL) {
+        return this._watch(followPrimary(() => this.tryCatchUpWithPrimary(), listener, interval));
+    }
+
+    newTransaction() {
+        return new Transaction(this._db);
+    }
+
+    // _watch keeps the stop function of the watcher to stop it on close
+    _watch(stop) {
+        this._watchers.add(stop);
+        return () => {
+            this._watchers.delete(stop);
+            stop();
+        };
+    }
+}
+
+// Transaction keeps the writes in memory and reads them back over the data in the database,
+// until they are written atomically by commit.
+class Transaction {
+    constructor(db) {
+        this._db = db;
+        this._transaction = transaction_new();
+    }
+
+    async get(key) {
+        return new Promise((resolve, reject) => {
+            db_transaction_get.call(this._db, this._transaction, key, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async has(key) {
+        try {
+            await this.get(key);
+            return true;
+        } catch (error) {
+            if (error instanceof NotFoundError) {
+                return false;
+            }
+            throw error;
+        }
+    }
+
+    set(key, value) {
+        transaction_set.call(this._transaction, key, value);
+    }
+
+    del(key) {
+        transaction_del.call(this._transaction, key);
+    }
+
+    iterate(options = {}) {
+        return new TransactionIterator(this._db, this._transaction, getOptionsWithDefault(options));
+    }
+
+    createReadStream(options = {}) {
+        return this.iterate(options);
+    }
+
+    async commit(options = {}) {
+        return new Promise((resolve, reject) => {
+            db_transaction_commit.call(this._db, this._transaction, options, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+}
+
+class TransactionIterator extends Readable {
+    constructor(db, transaction, options) {
+        super({ objectMode: true });
+        db_transaction_iterate.call(db, transaction, options, (err, results) => {
+            if (err) {
+                this.destroy(err);
+                return;
+            }
+            for (const result of results) {
+                this.push(result);
+            }
+            this.push(null);
+        });
+    }
+
+    _read() {
+    }
+}
+
+// prefixEnd returns the smallest key which is greater than all the keys with the prefix.
+// If there is no such key, it returns the prefix followed by the largest suffix which can be compared.
+const prefixEnd = prefix => {
+    const end = Buffer.from(prefix);
+    for (let i = end.length - 1; i >= 0; i -= 1) {
+        if (end[i] < 255) {
+            end[i] += 1;
+            return end.subarray(0, i + 1);
+        }
+    }
+    return Buffer.concat([prefix, Buffer.alloc(256, 255)]);
+};
+
+// PrefixedDatabase is a handle of the database which applies the prefix to all the keys,
+// and strips it from the keys returned by the iteration.
+class PrefixedDatabase {
+    constructor(db, prefix) {
+        this._db = db;
+        this._prefix = prefix;
+    }
+
+    _key(key) {
+        return Buffer.concat([this._prefix, key]);
+    }
+
+    async get(key) {
+        return this._db.get(this._key(key));
+    }
+
+    async getMany(keys) {
+        return this._db.getMany(keys.map(key => this._key(key)));
+    }
+
+    async getStream(key, onChunk, options = {}) {
+        return this._db.getStream(this._key(key), onChunk, options);
+    }
+
+    async has(key) {
+        return this._db.has(this._key(key));
+    }
+
+    async set(key, value) {
+        return this._db.set(this._key(key), value);
+    }
+
+    async del(key) {
+        return this._db.del(this._key(key));
+    }
+
+    withPrefix(prefix) {
+        return new PrefixedDatabase(this._db, this._key(prefix));
+    }
+
+    async approximateSize(start, end) {
+        return this._db.approximateSize(
+            this._key(start || Buffer.alloc(0)),
+            end ? this._key(end) : prefixEnd(this._prefix),
+        );
+    }
+
+    iterate(options = {}) {
+        const prefix = this._prefix;
+        const limit = options.limit !== undefined ? options.limit : -1;
+        // The limit is applied after the keys outside of the prefix are skipped
+        const stream = this._db.iterate({
+            gte: this._key(options.gte || Buffer.alloc(0)),
+            lte: options.lte ? this._key(options.lte) : prefixEnd(prefix),
+            reverse: options.reverse,
+            pageSize: options.pageSize,
+            signal: options.signal,
+        });
+        async function* strip() {
+            let count = 0;
+            for await (const { key, value } of stream) {
+                if (limit !== -1 && count >= limit) {
+                    break;
+                }
+                if (key.length >= prefix.length && key.subarray(0, prefix.length).equals(prefix)) {
+                    count += 1;
+                    yield { key: key.subarray(prefix.length), value };
+                }
+            }
+        }
+        return Readable.from(strip());
+    }
+
+    createReadStream(options = {}) {
+        return this.iterate(options);
+    }
 }
 
 class InMemoryIterator extends Readable {
@@ -228,8 +690,12 @@ class InMemoryIterator extends Readable {
 }
 
 class Batch {
-    constructor() {
-        this._batch = batch_new();
+    constructor(data) {
+        this._batch = data || batch_new();
+    }
+
+    static decode(data) {
+        return new Batch(batch_decode(data));
     }
 
     get inner() {
@@ -243,6 +709,34 @@ class Batch {
     del(key) {
         batch_del.call(this._batch, key);
     }
+
+    // setIfAbsent sets the value, and the write of the batch fails with BatchConflictError if the key exists
+    setIfAbsent(key, value) {
+        batch_set_if_absent.call(this._batch, key, value);
+    }
+
+    // delIfEquals deletes the key, and the write of the batch fails with BatchConflictError if the key does not have the value
+    delIfEquals(key, value) {
+        batch_del_if_equals.call(this._batch, key, value);
+    }
+
+    encode() {
+        return batch_encode.call(this._batch);
+    }
+
+    // length is the number of the operations in the batch
+    get length() {
+        return batch_len.call(this._batch);
+    }
+
+    // size is the size in bytes written to the database, which can be checked against the size limit before writing
+    get size() {
+        return batch_size.call(this._batch);
+    }
+
+    operations() {
+        return batch_operations.call(this._batch);
+    }
 }
 
 
@@ -256,7 +750,7 @@ class InMemoryDatabase {
         return new Promise((resolve, reject) => {
             in_memory_db_get.call(this._db, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Specified key ${key.toString('hex')} does not exist`));
                     }
                     return reject(err);
@@ -270,7 +764,7 @@ class InMemoryDatabase {
         return new Promise((resolve, reject) => {
             in_memory_db_get.call(this._db, key, (err) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return resolve(false);
                     }
                     return reject(err);
@@ -298,7 +792,7 @@ class InMemoryDatabase {
         return new Promise((resolve, reject) => {
             in_memory_db_write.call(this._db, batch.inner, err => {
                 if (err) {
-                    return reject(err);
+                    return reject(convertBatchError(err));
                 }
                 resolve();
             });
@@ -331,4 +825,5 @@ module.exports = {
     Database,
     InMemoryDatabase,
     Batch,
+    Transaction,
 };
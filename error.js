@@ -13,9 +13,120 @@
  */
 'use strict';
 
+// ErrorCode lists the stable codes set on the code property of the errors from the native module.
+const ErrorCode = Object.freeze({
+    NOT_FOUND: 'ERR_NOT_FOUND',
+    INVALID_ROOT: 'ERR_INVALID_ROOT',
+    INVALID_INPUT: 'ERR_INVALID_INPUT',
+    INVALID_USAGE: 'ERR_INVALID_USAGE',
+    ABORTED: 'ERR_ABORTED',
+    BUSY: 'ERR_BUSY',
+    LIMIT_EXCEEDED: 'ERR_LIMIT_EXCEEDED',
+    CORRUPTION: 'ERR_CORRUPTION',
+    IO: 'ERR_IO',
+    DATABASE: 'ERR_DATABASE',
+    UNKNOWN: 'ERR_UNKNOWN',
+    CLOSED: 'ERR_CLOSED',
+    INTERNAL: 'ERR_INTERNAL',
+});
+
 class NotFoundError extends Error {
+    constructor(message) {
+        super(message);
+        this.code = ErrorCode.NOT_FOUND;
+    }
+}
+
+class MaxOpenResourcesError extends Error {
+}
+
+// DatabaseClosedError is thrown by the operations called after the database is closed.
+class DatabaseClosedError extends Error {
+    constructor(message = 'Database is closed') {
+        super(message);
+        this.code = ErrorCode.CLOSED;
+    }
+}
+
+class AlreadyLockedError extends Error {
+    constructor(message, path, pid) {
+        super(message);
+        this.path = path;
+        // pid is null when the lock holder cannot be found
+        this.pid = pid;
+    }
+}
+
+// CommitConflictError is thrown when the current state is not the expected state of the commit,
+// because another commit has landed first.
+class CommitConflictError extends Error {
+    constructor(message, expected, current) {
+        super(message);
+        this.expected = expected;
+        this.current = current;
+    }
 }
 
+// BatchConflictError is thrown when a condition of the batch is not satisfied by the current state,
+// and nothing of the batch is written.
+class BatchConflictError extends Error {
+    constructor(message, key) {
+        super(message);
+        this.key = key;
+    }
+}
+
+// AbortError is thrown when the operation is stopped by the abort signal given to it.
+class AbortError extends Error {
+    constructor(message = 'operation was aborted') {
+        super(message);
+        this.code = ErrorCode.ABORTED;
+    }
+}
+
+// convertOpenError converts the error thrown while opening a database into the specific error if possible.
+const convertOpenError = err => {
+    if (err && err.code === 'ALREADY_LOCKED') {
+        return new AlreadyLockedError(err.message, err.path, err.pid);
+    }
+    return err;
+};
+
+// convertCommitError converts the error of the commit into the specific error if possible.
+const convertCommitError = err => {
+    if (err && err.code === 'COMMIT_CONFLICT') {
+        return new CommitConflictError(err.message, err.expected, err.current);
+    }
+    return err;
+};
+
+// convertBatchError converts the error of writing the batch into the specific error if possible.
+const convertBatchError = err => {
+    if (err && err.code === 'BATCH_CONFLICT') {
+        return new BatchConflictError(err.message, err.key);
+    }
+    return err;
+};
+
+// convertAbortError converts the error of the operation stopped by the abort signal into AbortError.
+const convertAbortError = err => {
+    if (err && err.code === ErrorCode.ABORTED) {
+        return new AbortError(err.message);
+    }
+    return err;
+};
+
 module.exports = {
+    ErrorCode,
     NotFoundError,
+    MaxOpenResourcesError,
+    DatabaseClosedError,
+    AlreadyLockedError,
+    CommitConflictError,
+    BatchConflictError,
+    AbortError,
+    convertOpenError,
+    convertCommitError,
+    convertBatchError,
+    convertAbortError,
 };
\ No newline at end of file
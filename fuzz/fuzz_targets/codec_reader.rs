@@ -0,0 +1,57 @@
+//! codec_reader reads the fields of the untrusted bytes in the order chosen by the input.
+//! The first byte is the mode in the highest bit and the number of the reads in the lowest 4 bits,
+//! and each following byte is a read with the field number in the higher 4 bits and the kind in the lower 4 bits.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lisk_db::Reader;
+
+fn read(reader: &mut Reader, op: u8) {
+    let field_number = u32::from(op >> 4);
+    let _ = match op & 0x0f {
+        0 => reader.read_bytes(field_number).map(drop),
+        1 => reader.read_bytes_slice(field_number).map(drop),
+        2 => reader.read_u32(field_number).map(drop),
+        3 => reader.read_u64(field_number).map(drop),
+        4 => reader.read_fixed32(field_number).map(drop),
+        5 => reader.read_fixed64(field_number).map(drop),
+        6 => reader.read_packed_u32(field_number).map(drop),
+        7 => reader.read_packed_u64(field_number).map(drop),
+        8 => reader.read_i32(field_number).map(drop),
+        9 => reader.read_i64(field_number).map(drop),
+        10 => reader.read_bool(field_number).map(drop),
+        11 => reader.read_message(field_number).map(|mut message| {
+            for op in 0..16 {
+                read(&mut message, op);
+            }
+            let _ = message.finish();
+        }),
+        12 => reader.read_messages(field_number).map(|messages| {
+            for mut message in messages {
+                let _ = message.read_bytes_slice(1);
+                let _ = message.finish();
+            }
+        }),
+        _ => reader.finish(),
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (head, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let count = usize::from(head & 0x0f);
+    if rest.len() < count {
+        return;
+    }
+    let (ops, payload) = rest.split_at(count);
+    let mut reader = if head & 0x80 != 0 {
+        Reader::strict(payload)
+    } else {
+        Reader::new(payload)
+    };
+    for op in ops {
+        read(&mut reader, *op);
+    }
+    let _ = reader.finish();
+});
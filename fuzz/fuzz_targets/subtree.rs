@@ -0,0 +1,25 @@
+//! subtree decodes the untrusted bytes as a node of the sparse merkle tree.
+//! The first byte selects the key length of the legacy encoding.
+//! A decoded subtree must be encoded to the bytes decoded to the same root.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use lisk_db::sparse_merkle_tree::smt::decode_subtree;
+use lisk_db::types::KeyLength;
+
+fuzz_target!(|data: &[u8]| {
+    let (head, data) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let key_length = if head & 1 == 0 {
+        KeyLength(32)
+    } else {
+        KeyLength(38)
+    };
+    if let Ok((root, encoded)) = decode_subtree(data, key_length) {
+        let (decoded_root, reencoded) =
+            decode_subtree(&encoded, key_length).expect("the encoded subtree must be decoded");
+        assert_eq!(decoded_root, root);
+        assert_eq!(reencoded, encoded);
+    }
+});
@@ -14,28 +14,68 @@
 'use strict';
 
 const { Readable } = require('stream');
+const { AbortError } = require('./error');
+const { followSignal } = require('./abort');
+
+// destroyOnAbort destroys the stream with AbortError once the signal is aborted.
+const destroyOnAbort = (stream, signal) => {
+    if (signal.aborted) {
+        process.nextTick(() => stream.destroy(new AbortError()));
+        return;
+    }
+    const abort = () => stream.destroy(new AbortError());
+    signal.addEventListener('abort', abort, { once: true });
+    stream.once('close', () => signal.removeEventListener('abort', abort));
+};
 
 class Iterator extends Readable {
-    constructor(db, iterateFunc, options) {
+    constructor(db, iterateFunc, options, tracker, signal) {
         super();
         this._db = db;
         this._iterateFunc = iterateFunc;
         this._options = options;
         this.queue = []
         Readable.call(this, { objectMode: true });
+        if (tracker) {
+            const id = tracker.acquire(this, 'iterator');
+            this.once('close', () => tracker.release(this, id));
+        }
+        let token = null;
+        if (signal) {
+            destroyOnAbort(this, signal);
+            if (signal.aborted) {
+                return;
+            }
+            // the iterate functions given the token stop reading at the next pair
+            const [followed, unfollow] = followSignal(signal);
+            token = followed;
+            this.once('close', unfollow);
+        }
         this._iterateFunc.call(
             this._db,
             this._options,
             (err, val) => {
+                if (this.destroyed) {
+                    return;
+                }
                 if (err) {
                     this.emit('error', err);
                     return;
                 }
                 this.push(val);
             },
-            () => {
+            // the iteration which failed before reaching the end is done with the error
+            err => {
+                if (this.destroyed) {
+                    return;
+                }
+                if (err) {
+                    this.emit('error', err);
+                    return;
+                }
                 this.push(null);
             },
+            token,
         );
     }
 
@@ -43,6 +83,72 @@ class Iterator extends Readable {
     }
 }
 
+const DEFAULT_PAGE_SIZE = 1000;
+
+// PageIterator reads the range one page at a time, only when the consumer asks for more data,
+// so scanning a large range does not load it all into memory.
+class PageIterator extends Readable {
+    constructor(db, iteratePageFunc, options, tracker, pageSize = DEFAULT_PAGE_SIZE, signal = undefined) {
+        super({ objectMode: true });
+        this._db = db;
+        this._iteratePageFunc = iteratePageFunc;
+        this._options = options;
+        this._pageSize = pageSize;
+        this._remaining = options.limit;
+        this._cursor = null;
+        this._reading = false;
+        if (tracker) {
+            const id = tracker.acquire(this, 'iterator');
+            this.once('close', () => tracker.release(this, id));
+        }
+        if (signal) {
+            // the pages are read one by one, so the iteration stops before the next page once it is destroyed
+            destroyOnAbort(this, signal);
+        }
+    }
+
+    _read() {
+        if (this._reading || this.destroyed) {
+            return;
+        }
+        const size = this._remaining === -1 ? this._pageSize : Math.min(this._pageSize, this._remaining);
+        if (size <= 0) {
+            this.push(null);
+            return;
+        }
+        this._reading = true;
+        this._iteratePageFunc.call(
+            this._db,
+            { ...this._options, limit: size },
+            this._cursor,
+            size,
+            (err, pairs) => {
+                this._reading = false;
+                if (this.destroyed) {
+                    return;
+                }
+                if (err) {
+                    this.destroy(err);
+                    return;
+                }
+                if (this._remaining !== -1) {
+                    this._remaining -= pairs.length;
+                }
+                if (pairs.length > 0) {
+                    this._cursor = pairs[pairs.length - 1].key;
+                }
+                for (const pair of pairs) {
+                    this.push(pair);
+                }
+                if (pairs.length < size) {
+                    this.push(null);
+                }
+            },
+        );
+    }
+}
+
 module.exports = {
     Iterator,
+    PageIterator,
 };
\ No newline at end of file
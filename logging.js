@@ -0,0 +1,26 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { logging_configure } = require("./bin-package/index.node");
+
+// configureLogging sets the level of the spans and events recorded by the database, and where they are written.
+// The records are appended to the file when it is given, otherwise onLog is called with each record.
+// Logging is off by default.
+const configureLogging = ({ level = 'off', file, onLog } = {}) =>
+    logging_configure(level, file || null, onLog || null);
+
+module.exports = {
+    configureLogging,
+};
@@ -14,15 +14,51 @@
 'use strict';
 
 const { Batch, Database, InMemoryDatabase } = require('./database');
-const { StateDB } = require('./state_db');
-const { NotFoundError } = require('./error');
+const { StateDB, InMemoryStateDB, StateWriter } = require('./state_db');
+const { MultiTreeDB } = require('./multi_tree_db');
+const {
+    ErrorCode,
+    NotFoundError,
+    MaxOpenResourcesError,
+    DatabaseClosedError,
+    AlreadyLockedError,
+    CommitConflictError,
+    BatchConflictError,
+    AbortError,
+} = require('./error');
 const { SparseMerkleTree } = require('./sparse_merkle_tree');
+const { getMemoryUsage, setMemoryBudget } = require('./memory');
+const { configureWorkerPool, shutdownWorkerPool, getWorkerPoolStats } = require('./worker_pool');
+const { getStats, setStatsEnabled, resetStats } = require('./metrics');
+const { configureLogging } = require('./logging');
+const { restoreFromBackup, purgeOldBackups } = require('./backup');
 
 module.exports = {
     Database,
     InMemoryDatabase,
     Batch,
     StateDB,
+    InMemoryStateDB,
+    StateWriter,
+    MultiTreeDB,
+    ErrorCode,
     NotFoundError,
+    MaxOpenResourcesError,
+    DatabaseClosedError,
+    AlreadyLockedError,
+    CommitConflictError,
+    BatchConflictError,
+    AbortError,
     SparseMerkleTree,
+    getMemoryUsage,
+    setMemoryBudget,
+    configureWorkerPool,
+    shutdownWorkerPool,
+    getWorkerPoolStats,
+    getStats,
+    setStatsEnabled,
+    resetStats,
+    configureLogging,
+    restoreFromBackup,
+    purgeOldBackups,
 };
\ No newline at end of file
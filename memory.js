@@ -0,0 +1,31 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const {
+    memory_usage,
+    memory_set_budget,
+} = require("./bin-package/index.node");
+
+// getMemoryUsage returns the bytes of keys and values held by the native batches, writers and in-memory stores.
+const getMemoryUsage = () => memory_usage();
+
+// setMemoryBudget limits the total bytes held natively. Operations which would exceed the budget are rejected.
+// Passing null removes the budget.
+const setMemoryBudget = budget => memory_set_budget(budget);
+
+module.exports = {
+    getMemoryUsage,
+    setMemoryBudget,
+};
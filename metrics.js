@@ -0,0 +1,35 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const {
+    metrics_get_stats,
+    metrics_set_stats_enabled,
+    metrics_reset_stats,
+} = require("./bin-package/index.node");
+
+// getStats returns the counters and latency histograms of the state tree recorded since the last reset.
+// Latencies are in microseconds.
+const getStats = () => metrics_get_stats();
+
+// setStatsEnabled starts or stops recording the stats. Recording is disabled by default.
+const setStatsEnabled = enabled => metrics_set_stats_enabled(enabled);
+
+const resetStats = () => metrics_reset_stats();
+
+module.exports = {
+    getStats,
+    setStatsEnabled,
+    resetStats,
+};
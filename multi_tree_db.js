@@ -0,0 +1,119 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const {
+    multi_tree_db_new,
+    multi_tree_db_close,
+    multi_tree_db_get_root,
+    multi_tree_db_update,
+    multi_tree_db_prove,
+} = require("./bin-package/index.node");
+
+const { SparseMerkleTree } = require('./sparse_merkle_tree');
+const { DatabaseClosedError, convertOpenError } = require('./error');
+
+const DEFAULT_KEY_LENGTH = 38;
+
+class MultiTreeDB {
+    constructor(path, opts = {}) {
+        try {
+            this._handle = multi_tree_db_new(path, opts);
+        } catch (err) {
+            throw convertOpenError(err);
+        }
+        this._keyLength = opts.keyLength || DEFAULT_KEY_LENGTH;
+        this._closed = false;
+    }
+
+    // _db is the native handle, which every operation goes through, so that they throw DatabaseClosedError after close
+    get _db() {
+        if (this._closed) {
+            throw new DatabaseClosedError();
+        }
+        return this._handle;
+    }
+
+    async getRoot(store) {
+        return new Promise((resolve, reject) => {
+            multi_tree_db_get_root.call(this._db, store, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async commit(store, kvpairs) {
+        const roots = await this.commitAll({ [store]: kvpairs });
+        return roots[store];
+    }
+
+    // commitAll updates all the given stores in a single atomic write.
+    async commitAll(updates) {
+        const input = Object.keys(updates).map(name => ({ name, pairs: updates[name] }));
+        return new Promise((resolve, reject) => {
+            multi_tree_db_update.call(this._db, input, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                const roots = {};
+                for (const { name, root } of result) {
+                    roots[name] = root;
+                }
+                resolve(roots);
+            });
+        });
+    }
+
+    async prove(store, queries) {
+        return new Promise((resolve, reject) => {
+            multi_tree_db_prove.call(this._db, store, queries, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                // If result is empty, force to use different memory space from what's given from binding
+                // Issue: https://github.com/nodejs/node/issues/32463
+                for (const query of result.queries) {
+                    if (query.value.length === 0) {
+                        query.value = Buffer.alloc(0);
+                    }
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async verify(root, queries, proof) {
+        return new SparseMerkleTree(this._keyLength).verify(root, queries, proof);
+    }
+
+    // close flushes the memtables and releases the database after the pending operations.
+    // The callback is called once the same path can be opened again.
+    close(cb) {
+        if (this._closed) {
+            if (cb) {
+                process.nextTick(cb, null);
+            }
+            return;
+        }
+        this._closed = true;
+        multi_tree_db_close.call(this._handle, cb);
+    }
+}
+
+module.exports = {
+    MultiTreeDB,
+};
@@ -0,0 +1,60 @@
+/*
+ * Copyright © 2022 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+// ProveCoalescer collects the prove calls for the same root arriving within the window, and proves them together
+// with a single traversal of the tree. The proof returned to each call is the same as the one proven separately.
+class ProveCoalescer {
+    // proveMany proves the sets of the queries together, and prove proves the queries of a single call
+    constructor(windowMs, proveMany, prove) {
+        this._windowMs = windowMs;
+        this._proveMany = proveMany;
+        this._prove = prove;
+        this._pending = new Map();
+    }
+
+    prove(root, queries, includeValues) {
+        const id = `${root.toString('hex')}:${includeValues}`;
+        let batch = this._pending.get(id);
+        if (batch === undefined) {
+            batch = { root, includeValues, requests: [] };
+            this._pending.set(id, batch);
+            setTimeout(() => this._flush(id, batch), this._windowMs);
+        }
+        return new Promise((resolve, reject) => {
+            batch.requests.push({ queries, resolve, reject });
+        });
+    }
+
+    async _flush(id, batch) {
+        this._pending.delete(id);
+        const { root, includeValues, requests } = batch;
+        if (requests.length > 1) {
+            try {
+                const proofs = await this._proveMany(root, requests.map(request => request.queries), includeValues);
+                requests.forEach((request, i) => request.resolve(proofs[i]));
+                return;
+            } catch (err) {
+                // the invalid queries of one call must not fail the others, so each call is proven separately
+            }
+        }
+        for (const request of requests) {
+            this._prove(root, request.queries, includeValues).then(request.resolve, request.reject);
+        }
+    }
+}
+
+module.exports = {
+    ProveCoalescer,
+};
@@ -0,0 +1,87 @@
+/*
+ * Copyright © 2022 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { MaxOpenResourcesError } = require('./error');
+
+// ResourceTracker keeps track of the native iterators and read views opened from a database handle.
+// In debug mode, it records the creation stack so that the ones which were never closed can be reported.
+class ResourceTracker {
+    constructor(options = {}) {
+        this._maxOpen = options.maxOpenIterators !== undefined ? options.maxOpenIterators : Infinity;
+        this._debug = options.debug !== undefined ? options.debug : false;
+        this._nextID = 0;
+        this._open = new Map();
+        this._leaked = [];
+        // FinalizationRegistry is not available before node 14.6, leak reporting is disabled there
+        this._registry = typeof FinalizationRegistry !== 'undefined' ? new FinalizationRegistry(id => this._onCollected(id)) : undefined;
+    }
+
+    acquire(resource, kind) {
+        if (this._open.size >= this._maxOpen) {
+            throw new MaxOpenResourcesError(`Maximum number of open iterators and readers (${this._maxOpen}) is reached.`);
+        }
+        const id = this._nextID;
+        this._nextID += 1;
+        this._open.set(id, {
+            kind,
+            stack: this._debug ? new Error().stack.split('\n').slice(2).join('\n') : undefined,
+        });
+        if (this._registry) {
+            this._registry.register(resource, id, resource);
+        }
+        return id;
+    }
+
+    release(resource, id) {
+        if (id === undefined || !this._open.has(id)) {
+            return;
+        }
+        this._open.delete(id);
+        if (this._registry) {
+            this._registry.unregister(resource);
+        }
+    }
+
+    get openCount() {
+        return this._open.size;
+    }
+
+    openResources() {
+        return [...this._open.values()].map(info => ({ ...info }));
+    }
+
+    leakedResources() {
+        return this._leaked.map(info => ({ ...info }));
+    }
+
+    _onCollected(id) {
+        const info = this._open.get(id);
+        if (!info) {
+            return;
+        }
+        this._open.delete(id);
+        if (this._debug) {
+            this._leaked.push(info);
+            process.emitWarning(`${info.kind} was garbage collected without being closed`, {
+                code: 'LISK_DB_RESOURCE_LEAK',
+                detail: info.stack,
+            });
+        }
+    }
+}
+
+module.exports = {
+    ResourceTracker,
+};
@@ -15,13 +15,33 @@
 const {
     in_memory_smt_new,
     in_memory_smt_update,
+    in_memory_smt_update_with_progress,
+    in_memory_smt_update_with_batch,
+    in_memory_smt_bulk_load,
+    in_memory_smt_new_bulk_loader,
+    in_memory_smt_add_to_bulk_loader,
+    in_memory_smt_finish_bulk_load,
     in_memory_smt_prove,
     in_memory_smt_verify,
     in_memory_smt_calculate_root,
+    in_memory_smt_to_visualization,
+    in_memory_smt_save,
+    in_memory_smt_load,
+    in_memory_smt_verify_chunk,
+    in_memory_smt_get_metrics,
+    in_memory_smt_verify_witness,
+    in_memory_smt_new_chunk_importer,
+    in_memory_smt_import_chunk,
+    in_memory_smt_finish_chunk_import,
 } = require("./bin-package/index.node");
-const { isInclusionProofForQueryKey } = require('./utils');
+const { isInclusionProofForQueryKey, formatVisualization, encodePairs } = require('./utils');
+const { Batch } = require('./database');
+const { runWithSignal } = require('./abort');
+const { AbortError } = require('./error');
 
 const DEFAULT_KEY_LENGTH = 38;
+// BULK_LOAD_BATCH_SIZE is the number of the pairs of the stream given to bulkLoad which are sent to the native side at once
+const BULK_LOAD_BATCH_SIZE = 10000;
 
 const copyBuffer = h => {
     const copied = Buffer.alloc(h.length);
@@ -34,9 +54,121 @@ class SparseMerkleTree {
         this._inner = in_memory_smt_new(keyLength);
     }
 
-    async update(root, kvpairs) {
+    // encodePairs encodes the key-value pairs into a single buffer, which can be given to update and bulkLoad
+    // instead of the array, so that the pairs are not converted one by one by the native side
+    static encodePairs(kvpairs) {
+        return encodePairs(kvpairs);
+    }
+
+    // kvpairs can be an array of key-value pairs or the buffer encoded by encodePairs
+    // emptyValue is 'deleteOnEmpty' by default, which deletes the keys with an empty value.
+    // 'storeEmptyHash' keeps them in the tree with the empty hash as the value, and a null value deletes the key.
+    async update(root, kvpairs, options = {}) {
+        const { onProgress, signal, emptyValue } = options;
+        if (!onProgress && !signal) {
+            return new Promise((resolve, reject) => {
+                in_memory_smt_update.call(this._inner, root, kvpairs, (err, result) => {
+                    if (err) {
+                        reject(err);
+                        return;
+                    }
+                    resolve(result);
+                }, emptyValue);
+            });
+        }
+        return runWithSignal(signal, token => new Promise((resolve, reject) => {
+            in_memory_smt_update_with_progress.call(this._inner, root, kvpairs, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            }, onProgress || null, token, emptyValue);
+        }));
+    }
+
+    async updateWithBatch(root, batch) {
+        // batch can be a Batch or the bytes encoded by Batch.encode
+        const target = Buffer.isBuffer(batch) ? Batch.decode(batch) : batch;
+        return new Promise((resolve, reject) => {
+            in_memory_smt_update_with_batch.call(this._inner, root, target.inner, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async bulkLoad(kvpairs, options = {}) {
+        // kvpairs can be an array or a stream of key-value pairs sorted by key, or the buffer encoded by encodePairs
+        if (Buffer.isBuffer(kvpairs) || Array.isArray(kvpairs)) {
+            return runWithSignal(options.signal, token => new Promise((resolve, reject) => {
+                in_memory_smt_bulk_load.call(this._inner, kvpairs, (err, result) => {
+                    if (err) {
+                        reject(err);
+                        return;
+                    }
+                    resolve(result);
+                }, token);
+            }));
+        }
+        // the stream is sent to the native side in batches, so it is never held in JS as a whole
+        const loader = in_memory_smt_new_bulk_loader.call(this._inner);
+        let batch = [];
+        for await (const pair of kvpairs) {
+            batch.push(pair);
+            if (batch.length >= BULK_LOAD_BATCH_SIZE) {
+                if (options.signal && options.signal.aborted) {
+                    throw new AbortError();
+                }
+                in_memory_smt_add_to_bulk_loader.call(this._inner, loader, batch);
+                batch = [];
+            }
+        }
+        in_memory_smt_add_to_bulk_loader.call(this._inner, loader, batch);
+        return runWithSignal(options.signal, token => new Promise((resolve, reject) => {
+            in_memory_smt_finish_bulk_load.call(this._inner, loader, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            }, token);
+        }));
+    }
+
+    // toVisualization returns all the nodes of the tree in pre-order as JSON serializable objects or CSV
+    async toVisualization(root, options = {}) {
+        const nodes = await new Promise((resolve, reject) => {
+            in_memory_smt_to_visualization.call(this._inner, root, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            });
+        });
+        return formatVisualization(nodes, options.format);
+    }
+
+    async save(path, root) {
+        return new Promise((resolve, reject) => {
+            in_memory_smt_save.call(this._inner, path, root, err => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve();
+            });
+        });
+    }
+
+    // load replaces the tree with the one written by save, and returns the root given to save
+    async load(path) {
         return new Promise((resolve, reject) => {
-            in_memory_smt_update.call(this._inner, root, kvpairs, (err, result) => {
+            in_memory_smt_load.call(this._inner, path, (err, result) => {
                 if (err) {
                     reject(err);
                     return;
@@ -46,8 +178,59 @@ class SparseMerkleTree {
         });
     }
 
-    async prove(root, queries) {
+    // getStats returns the stats recorded by the updates and the proofs of this tree in the same form as getStats
+    // of the module, which returns the totals of all the trees and the databases.
+    getStats() {
+        return in_memory_smt_get_metrics.call(this._inner);
+    }
+
+    async verifyChunk(root, chunk) {
         return new Promise((resolve, reject) => {
+            in_memory_smt_verify_chunk.call(this._inner, root, chunk, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // verifyWitness checks the witness created by StateDB.generateWitness against the root before the block,
+    // and returns its entries. The value of the entry is not set if the key does not exist in the state.
+    async verifyWitness(root, witness) {
+        return new Promise((resolve, reject) => {
+            in_memory_smt_verify_witness.call(this._inner, root, witness, (err, result) => {
+                if (err) {
+                    reject(err);
+                    return;
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // importChunks builds the tree from the chunks created by StateDB.exportChunks, and fails if they do not form the root
+    async importChunks(root, chunks) {
+        // chunks can be an array or a stream of encoded chunks in the order of the index.
+        // Each chunk is verified and written before the next one is read, and the root is checked after the last one.
+        const importer = in_memory_smt_new_chunk_importer.call(this._inner, root);
+        for await (const chunk of chunks) {
+            await new Promise((resolve, reject) => {
+                in_memory_smt_import_chunk.call(this._inner, importer, chunk, err => {
+                    if (err) {
+                        reject(err);
+                        return;
+                    }
+                    resolve();
+                });
+            });
+        }
+        return in_memory_smt_finish_chunk_import.call(this._inner, importer);
+    }
+
+    async prove(root, queries, options = {}) {
+        return runWithSignal(options.signal, token => new Promise((resolve, reject) => {
             in_memory_smt_prove.call(this._inner, root, queries, (err, result) => {
                 if (err) {
                     reject(err);
@@ -64,8 +247,8 @@ class SparseMerkleTree {
                         bitmap: copyBuffer(q.bitmap),
                     })),
                 });
-            });
-        });
+            }, token);
+        }));
     }
 
     async verify(root, queries, proof) {
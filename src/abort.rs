@@ -0,0 +1,100 @@
+/// abort provides the token to stop the long running operations from JS.
+/// The operation checks the token at its safe points, and fails with the aborted error once it is set.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+use thiserror::Error;
+
+use crate::error_code::{self, ErrorCode};
+
+/// Aborted is the error of the operation stopped by the token.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("operation was aborted")]
+pub struct Aborted;
+
+impl ErrorCode for Aborted {
+    fn code(&self) -> &'static str {
+        error_code::ABORTED
+    }
+}
+
+/// AbortToken is shared with the operation running in the background, which stops once it is set.
+#[derive(Clone, Default)]
+pub struct AbortToken(Arc<AtomicBool>);
+
+impl AbortToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn abort(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// check returns Aborted once the token is set.
+    pub fn check(&self) -> Result<(), Aborted> {
+        if self.is_aborted() {
+            return Err(Aborted);
+        }
+        Ok(())
+    }
+
+    /// flag returns the flag set by abort, to be checked by the operation.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.0)
+    }
+}
+
+#[cfg(feature = "neon")]
+impl Finalize for AbortToken {}
+
+#[cfg(feature = "neon")]
+impl AbortToken {
+    /// argument_opt returns the token at the position of the arguments, or None when it is not given.
+    pub fn argument_opt(ctx: &mut FunctionContext, pos: i32) -> Option<Self> {
+        ctx.argument_opt(pos)
+            .and_then(|val| val.downcast::<JsBox<AbortToken>, _>(ctx).ok())
+            .map(|token| AbortToken::clone(&token))
+    }
+
+    /// js_new is handler for JS ffi.
+    /// it creates a token to abort the operations it is given to.
+    pub fn js_new(mut ctx: FunctionContext) -> JsResult<JsBox<AbortToken>> {
+        Ok(ctx.boxed(AbortToken::new()))
+    }
+
+    /// js_abort is handler for JS ffi.
+    /// it aborts the operations using the token at their next safe point.
+    /// - @params(0) - AbortToken.
+    pub fn js_abort(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let token = ctx.argument::<JsBox<AbortToken>>(0)?;
+        token.abort();
+
+        Ok(ctx.undefined())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_token() {
+        let token = AbortToken::new();
+        let shared = token.clone();
+        let flag = token.flag();
+        assert!(!shared.is_aborted());
+        assert_eq!(shared.check(), Ok(()));
+        token.abort();
+        assert!(shared.is_aborted());
+        assert_eq!(shared.check(), Err(Aborted));
+        assert!(flag.load(Ordering::Relaxed));
+        assert_eq!(Aborted.code(), error_code::ABORTED);
+    }
+}
@@ -2,26 +2,88 @@
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+use crate::codec;
 use crate::database::traits::{DatabaseKind, JsNewWithArcMutex, NewDBWithKeyLength};
 use crate::database::types::{JsArcMutex, Kind as DBKind};
-use crate::types::{KVPair, KeyLength};
+use crate::memory;
+use crate::types::{KVPair, KeyLength, NestedVec, RecoverLock};
 
 pub type SendableWriteBatch = JsArcMutex<WriteBatch>;
 
+const OPERATION_PUT: u32 = 0;
+const OPERATION_DELETE: u32 = 1;
+
+const CONDITION_ABSENT: u32 = 0;
+const CONDITION_EQUALS: u32 = 1;
+
 pub trait BatchWriter {
     fn put(&mut self, pair: &KVPair);
     fn delete(&mut self, key: &[u8]);
+    /// delete_range deletes the keys from start to end, excluding end.
+    fn delete_range(&mut self, start: &[u8], end: &[u8]);
+}
+
+/// Condition is checked against the current state of the database when the batch is written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// Absent requires the key not to exist.
+    Absent(Vec<u8>),
+    /// Equals requires the key to exist with the value.
+    Equals(Vec<u8>, Vec<u8>),
+}
+
+/// BatchConflict is returned when a condition of the batch is not satisfied. Nothing of the batch is written.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Batch conflict: condition on key `{}` is not satisfied", hex::encode(.0.key()))]
+pub struct BatchConflict(pub Condition);
+
+#[derive(Error, Debug)]
+pub enum BatchWriteError {
+    #[error(transparent)]
+    Conflict(#[from] BatchConflict),
+    #[error(transparent)]
+    Database(#[from] rocksdb::Error),
 }
 
 /// WriteBatch is a container for rocksdb::WriteBatch
+/// The conditions are checked before writing the batch, and they are kept when the batch is replayed.
 pub struct WriteBatch {
     pub batch: rocksdb::WriteBatch,
+    pub conditions: Vec<Condition>,
+    usage: memory::Usage,
+}
+
+/// BatchEncoder collects the operations of rocksdb::WriteBatch in the order they were added.
+struct BatchEncoder {
+    operations: NestedVec,
+}
+
+/// PrefixAppender appends the operations to the batch with the prefix added to the keys.
+struct PrefixAppender<'b> {
+    batch: &'b mut rocksdb::WriteBatch,
+    prefix: &'b [u8],
+}
+
+/// Operation is a key and a value written by the batch. The value is None for the deletion.
+pub type Operation = (Vec<u8>, Option<Vec<u8>>);
+
+/// OperationCollector collects the operations of rocksdb::WriteBatch in the order they were added.
+struct OperationCollector {
+    operations: Vec<Operation>,
 }
 
 /// PrefixWriteBatch updates rocksdb batch with defined prefix.
 /// Prefix is used for splitting the data into buckets.
+/// The deleted ranges are kept apart from the batch, because the batch is routed to the column families
+/// by iterating its operations, which does not include the range deletions. They are written before the batch.
 pub struct PrefixWriteBatch<'a> {
     pub batch: rocksdb::WriteBatch,
+    pub ranges: Vec<(Vec<u8>, Vec<u8>)>,
     prefix: Option<&'a [u8]>,
 }
 
@@ -29,6 +91,7 @@ impl Clone for WriteBatch {
     fn clone(&self) -> Self {
         let mut cloned = WriteBatch::new_db_with_key_length(None);
         self.batch.iterate(&mut cloned);
+        cloned.conditions = self.conditions.clone();
         cloned
     }
 }
@@ -37,18 +100,171 @@ impl rocksdb::WriteBatchIterator for WriteBatch {
     /// Called with a key and value that were `put` into the batch.
     fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
         self.batch.put(key, value);
+        self.update_usage();
     }
     /// Called with a key that was `delete`d from the batch.
     fn delete(&mut self, key: Box<[u8]>) {
         self.batch.delete(key);
+        self.update_usage();
+    }
+}
+
+impl rocksdb::WriteBatchIterator for BatchEncoder {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, OPERATION_PUT);
+        writer.write_bytes(2, &key);
+        writer.write_bytes(3, &value);
+        self.operations.push(writer.result().to_vec());
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, OPERATION_DELETE);
+        writer.write_bytes(2, &key);
+        self.operations.push(writer.result().to_vec());
+    }
+}
+
+impl rocksdb::WriteBatchIterator for OperationCollector {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        self.operations.push((key.to_vec(), Some(value.to_vec())));
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        self.operations.push((key.to_vec(), None));
+    }
+}
+
+impl<'b> rocksdb::WriteBatchIterator for PrefixAppender<'b> {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        self.batch.put([self.prefix, key.as_ref()].concat(), value);
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        self.batch.delete([self.prefix, key.as_ref()].concat());
+    }
+}
+
+impl Condition {
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Self::Absent(key) => key,
+            Self::Equals(key, _) => key,
+        }
+    }
+
+    /// is_satisfied_by checks the condition with the current value of the key.
+    pub fn is_satisfied_by(&self, current: Option<&[u8]>) -> bool {
+        match self {
+            Self::Absent(_) => current.is_none(),
+            Self::Equals(_, value) => current == Some(value.as_slice()),
+        }
+    }
+}
+
+/// write_error_to_js_error creates the error of writing the batch.
+/// A conflict has the code BATCH_CONFLICT with the key of the condition.
+pub fn write_error_to_js_error<'a, C: Context<'a>>(
+    ctx: &mut C,
+    err: BatchWriteError,
+) -> NeonResult<Handle<'a, JsError>> {
+    let js_err = ctx.error(err.to_string())?;
+    if let BatchWriteError::Conflict(BatchConflict(condition)) = err {
+        let code = ctx.string("BATCH_CONFLICT");
+        js_err.set(ctx, "code", code)?;
+        let key = JsBuffer::external(ctx, condition.key().to_vec());
+        js_err.set(ctx, "key", key)?;
+    }
+    Ok(js_err)
+}
+
+/// operations returns all the operations in the batch in the order they were added.
+pub fn operations(batch: &rocksdb::WriteBatch) -> Vec<Operation> {
+    let mut collector = OperationCollector { operations: vec![] };
+    batch.iterate(&mut collector);
+    collector.operations
+}
+
+/// encode_batch encodes all the operations in the batch to bytes.
+/// Keys are written as they are in the batch, therefore prefixes are kept.
+/// encoding uses lisk-codec protocol.
+pub fn encode_batch(batch: &rocksdb::WriteBatch) -> Vec<u8> {
+    let mut encoder = BatchEncoder { operations: vec![] };
+    batch.iterate(&mut encoder);
+    let mut writer = codec::Writer::new();
+    writer.write_bytes_slice(1, &encoder.operations);
+    writer.result().to_vec()
+}
+
+/// decode_batch reconstructs the batch from the bytes created by encode_batch.
+pub fn decode_batch(val: &[u8]) -> Result<rocksdb::WriteBatch, codec::CodecError> {
+    let mut reader = codec::Reader::new(val);
+    read_operations(&mut reader)
+}
+
+/// encode_write_batch encodes the operations and the conditions of the batch to bytes,
+/// so the batch can be persisted or transported and replayed with decode_write_batch.
+/// The operations are encoded as encode_batch, followed by the conditions.
+pub fn encode_write_batch(batch: &WriteBatch) -> Vec<u8> {
+    let mut encoded = encode_batch(&batch.batch);
+    let mut writer = codec::Writer::new();
+    for condition in batch.conditions.iter() {
+        writer.write_message(2, |writer| match condition {
+            Condition::Absent(key) => {
+                writer.write_u32(1, CONDITION_ABSENT);
+                writer.write_bytes(2, key);
+            },
+            Condition::Equals(key, value) => {
+                writer.write_u32(1, CONDITION_EQUALS);
+                writer.write_bytes(2, key);
+                writer.write_bytes(3, value);
+            },
+        });
     }
+    encoded.extend(writer.result());
+    encoded
+}
+
+/// decode_write_batch reconstructs the batch with its conditions from the bytes created by encode_write_batch.
+/// The bytes created by encode_batch are decoded as the batch without the conditions.
+pub fn decode_write_batch(val: &[u8]) -> Result<WriteBatch, codec::CodecError> {
+    let mut reader = codec::Reader::new(val);
+    let batch = read_operations(&mut reader)?;
+    let mut conditions = vec![];
+    for mut condition in reader.read_messages(2)? {
+        let kind = condition.read_u32(1)?;
+        let key = condition.read_bytes(2)?;
+        conditions.push(match kind {
+            CONDITION_ABSENT => Condition::Absent(key),
+            CONDITION_EQUALS => Condition::Equals(key, condition.read_bytes(3)?),
+            _ => return Err(codec::CodecError::OutOfRange),
+        });
+    }
+    let mut write_batch = WriteBatch::new(batch);
+    write_batch.conditions = conditions;
+    Ok(write_batch)
+}
+
+fn read_operations(reader: &mut codec::Reader) -> Result<rocksdb::WriteBatch, codec::CodecError> {
+    let operations = reader.read_bytes_slice(1)?;
+    let mut batch = rocksdb::WriteBatch::default();
+    for operation in operations.iter() {
+        let mut reader = codec::Reader::new(operation);
+        let kind = reader.read_u32(1)?;
+        let key = reader.read_bytes(2)?;
+        match kind {
+            OPERATION_PUT => batch.put(key, reader.read_bytes(3)?),
+            OPERATION_DELETE => batch.delete(key),
+            _ => return Err(codec::CodecError::OutOfRange),
+        }
+    }
+    Ok(batch)
 }
 
 impl NewDBWithKeyLength for WriteBatch {
     fn new_db_with_key_length(_: Option<KeyLength>) -> Self {
-        Self {
-            batch: rocksdb::WriteBatch::default(),
-        }
+        Self::new(rocksdb::WriteBatch::default())
     }
 }
 
@@ -61,6 +277,35 @@ impl DatabaseKind for WriteBatch {
 impl JsNewWithArcMutex for WriteBatch {}
 impl Finalize for WriteBatch {}
 impl WriteBatch {
+    pub fn new(batch: rocksdb::WriteBatch) -> Self {
+        let mut write_batch = Self {
+            batch,
+            conditions: vec![],
+            usage: memory::Usage::new(memory::Component::WriteBatch),
+        };
+        write_batch.update_usage();
+        write_batch
+    }
+
+    fn update_usage(&mut self) {
+        self.usage.set(self.batch.size_in_bytes());
+    }
+
+    /// check_conditions checks the conditions in the order they were added against the values returned by get.
+    /// All the conditions are checked against the state before the batch, including the ones on the same key.
+    pub fn check_conditions(
+        &self,
+        mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error>,
+    ) -> Result<(), BatchWriteError> {
+        for condition in self.conditions.iter() {
+            let current = get(condition.key())?;
+            if !condition.is_satisfied_by(current.as_deref()) {
+                return Err(BatchConflict(condition.clone()).into());
+            }
+        }
+        Ok(())
+    }
+
     pub fn js_set(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
         let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
@@ -70,10 +315,13 @@ impl WriteBatch {
             .this()
             .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
 
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
         let batch = batch.borrow();
-        let mut inner_batch = batch.lock().unwrap();
+        let mut inner_batch = batch.lock_or_recover();
 
         inner_batch.batch.put(key, value);
+        inner_batch.update_usage();
 
         Ok(ctx.undefined())
     }
@@ -85,13 +333,144 @@ impl WriteBatch {
             .this()
             .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
 
+        memory::reserve(key.len()).or_else(|err| ctx.throw_error(err.to_string()))?;
         let batch = batch.borrow();
-        let mut inner_batch = batch.lock().unwrap();
+        let mut inner_batch = batch.lock_or_recover();
 
         inner_batch.batch.delete(key);
+        inner_batch.update_usage();
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_set_if_absent is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @params(0) - key to set.
+    /// - @params(1) - value to set.
+    /// The write of the batch fails with BATCH_CONFLICT if the key exists.
+    pub fn js_set_if_absent(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let batch = batch.borrow();
+        let mut inner_batch = batch.lock_or_recover();
+
+        inner_batch.batch.put(&key, value);
+        inner_batch.conditions.push(Condition::Absent(key));
+        inner_batch.update_usage();
 
         Ok(ctx.undefined())
     }
+
+    /// js_del_if_equals is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @params(0) - key to delete.
+    /// - @params(1) - value expected for the key.
+    /// The write of the batch fails with BATCH_CONFLICT if the key does not have the value.
+    pub fn js_del_if_equals(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        memory::reserve(key.len()).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let batch = batch.borrow();
+        let mut inner_batch = batch.lock_or_recover();
+
+        inner_batch.batch.delete(&key);
+        inner_batch.conditions.push(Condition::Equals(key, value));
+        inner_batch.update_usage();
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_encode is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @returns - [u8]. Encoded operations and conditions of the batch.
+    pub fn js_encode(mut ctx: FunctionContext) -> JsResult<JsBuffer> {
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        let batch = batch.borrow();
+        let encoded = encode_write_batch(&batch.lock_or_recover());
+
+        Ok(JsBuffer::external(&mut ctx, encoded))
+    }
+
+    /// js_decode is handler for JS ffi.
+    /// - @params(0) - bytes created by js_encode.
+    /// - @returns - Batch reconstructed from the bytes.
+    pub fn js_decode(mut ctx: FunctionContext) -> JsResult<SendableWriteBatch> {
+        let data = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let batch = decode_write_batch(&data).or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        memory::reserve(batch.batch.size_in_bytes())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.boxed(RefCell::new(Arc::new(Mutex::new(batch)))))
+    }
+
+    /// js_len is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @returns - number of the operations in the batch.
+    pub fn js_len(mut ctx: FunctionContext) -> JsResult<JsNumber> {
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        let batch = batch.borrow();
+        let len = batch.lock_or_recover().batch.len();
+
+        Ok(ctx.number(len as f64))
+    }
+
+    /// js_size is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @returns - size of the batch in bytes as written to RocksDB, including the header of the batch.
+    pub fn js_size(mut ctx: FunctionContext) -> JsResult<JsNumber> {
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        let batch = batch.borrow();
+        let size = batch.lock_or_recover().batch.size_in_bytes();
+
+        Ok(ctx.number(size as f64))
+    }
+
+    /// js_operations is handler for JS ffi.
+    /// js "this" - Batch.
+    /// - @returns - { type: 'put' | 'del', key, value }[] in the order they were added. value is undefined for 'del'.
+    pub fn js_operations(mut ctx: FunctionContext) -> JsResult<JsArray> {
+        let batch = ctx
+            .this()
+            .downcast_or_throw::<SendableWriteBatch, _>(&mut ctx)?;
+
+        let batch = batch.borrow();
+        let operations = operations(&batch.lock_or_recover().batch);
+        let result = ctx.empty_array();
+        for (i, (key, value)) in operations.into_iter().enumerate() {
+            let obj = ctx.empty_object();
+            let kind = ctx.string(if value.is_some() { "put" } else { "del" });
+            obj.set(&mut ctx, "type", kind)?;
+            let key = JsBuffer::external(&mut ctx, key);
+            obj.set(&mut ctx, "key", key)?;
+            if let Some(value) = value {
+                let value = JsBuffer::external(&mut ctx, value);
+                obj.set(&mut ctx, "value", value)?;
+            }
+            result.set(&mut ctx, i as u32, obj)?;
+        }
+
+        Ok(result)
+    }
 }
 
 impl<'a> BatchWriter for PrefixWriteBatch<'a> {
@@ -103,6 +482,12 @@ impl<'a> BatchWriter for PrefixWriteBatch<'a> {
     fn delete(&mut self, key: &[u8]) {
         self.batch.delete([self.prefix.unwrap(), key].concat());
     }
+
+    fn delete_range(&mut self, start: &[u8], end: &[u8]) {
+        let prefix = self.prefix.unwrap();
+        self.ranges
+            .push(([prefix, start].concat(), [prefix, end].concat()));
+    }
 }
 
 impl<'a> rocksdb::WriteBatchIterator for PrefixWriteBatch<'a> {
@@ -122,6 +507,7 @@ impl<'a> PrefixWriteBatch<'a> {
     pub fn new() -> Self {
         PrefixWriteBatch {
             batch: rocksdb::WriteBatch::default(),
+            ranges: vec![],
             prefix: None,
         }
     }
@@ -137,6 +523,27 @@ impl<'a> PrefixWriteBatch<'a> {
     pub fn delete(&mut self, key: &[u8]) {
         self.batch.delete([self.prefix.unwrap(), key].concat());
     }
+
+    /// extend appends the operations and the deleted ranges of the other batch, whose keys are already prefixed.
+    /// The prefix of the batch is not changed.
+    pub fn extend(&mut self, other: PrefixWriteBatch) {
+        other.batch.iterate(&mut PrefixAppender {
+            batch: &mut self.batch,
+            prefix: &[],
+        });
+        self.ranges.extend(other.ranges);
+    }
+
+    /// extend_with_prefixes appends the operations of the batches with the keys prefixed by the paired prefix
+    /// in a single pass, without changing the prefix of the batch.
+    pub fn extend_with_prefixes(&mut self, batches: &[(&[u8], &rocksdb::WriteBatch)]) {
+        for (prefix, batch) in batches.iter() {
+            batch.iterate(&mut PrefixAppender {
+                batch: &mut self.batch,
+                prefix,
+            });
+        }
+    }
 }
 
 impl<'a> Default for PrefixWriteBatch<'a> {
@@ -177,6 +584,179 @@ mod tests {
         assert_eq!(write_batch.batch.len(), 2);
     }
 
+    #[test]
+    fn test_delete_range_for_prefix_write_batch() {
+        let mut write_batch = PrefixWriteBatch::default();
+        write_batch.set_prefix(&consts::Prefix::STATE);
+        BatchWriter::delete_range(&mut write_batch, &[1], &[3]);
+
+        assert_eq!(write_batch.batch.len(), 0);
+        assert_eq!(
+            write_batch.ranges,
+            vec![(
+                [consts::Prefix::STATE, &[1]].concat(),
+                [consts::Prefix::STATE, &[3]].concat()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_conditions() {
+        let mut write_batch = WriteBatch::new_db_with_key_length(None);
+        write_batch.conditions = vec![
+            Condition::Absent(vec![1]),
+            Condition::Equals(vec![2], vec![3]),
+        ];
+        let current = |key: &[u8]| match key {
+            [2] => Ok(Some(vec![3])),
+            _ => Ok(None),
+        };
+        assert!(write_batch.check_conditions(current).is_ok());
+
+        write_batch
+            .conditions
+            .push(Condition::Equals(vec![4], vec![5]));
+        let err = write_batch.check_conditions(current).unwrap_err();
+        match err {
+            BatchWriteError::Conflict(conflict) => {
+                assert_eq!(conflict, BatchConflict(Condition::Equals(vec![4], vec![5])));
+                assert_eq!(
+                    conflict.to_string(),
+                    "Batch conflict: condition on key `04` is not satisfied"
+                );
+            },
+            _ => panic!("expected a conflict"),
+        }
+
+        let cloned = write_batch.clone();
+        assert_eq!(cloned.conditions, write_batch.conditions);
+    }
+
+    #[test]
+    fn test_extend_prefix_write_batch() {
+        let mut write_batch = PrefixWriteBatch::default();
+        write_batch.set_prefix(&consts::Prefix::STATE);
+        write_batch.put(&[1], &[2]);
+
+        let mut other = PrefixWriteBatch::default();
+        other.set_prefix(&consts::Prefix::DIFF);
+        other.delete(&[3]);
+        BatchWriter::delete_range(&mut other, &[4], &[5]);
+        write_batch.extend(other);
+
+        let mut smt = rocksdb::WriteBatch::default();
+        smt.put([6], [7]);
+        let mut root_history = rocksdb::WriteBatch::default();
+        root_history.delete([8]);
+        write_batch.extend_with_prefixes(&[
+            (consts::Prefix::SMT, &smt),
+            (consts::Prefix::ROOT_HISTORY, &root_history),
+        ]);
+        write_batch.put(&[9], &[10]);
+
+        let prefixed = |prefix: &[u8], key: u8| [prefix, &[key]].concat();
+        assert_eq!(
+            operations(&write_batch.batch),
+            vec![
+                (prefixed(consts::Prefix::STATE, 1), Some(vec![2])),
+                (prefixed(consts::Prefix::DIFF, 3), None),
+                (prefixed(consts::Prefix::SMT, 6), Some(vec![7])),
+                (prefixed(consts::Prefix::ROOT_HISTORY, 8), None),
+                (prefixed(consts::Prefix::STATE, 9), Some(vec![10])),
+            ]
+        );
+        assert_eq!(
+            write_batch.ranges,
+            vec![(
+                prefixed(consts::Prefix::DIFF, 4),
+                prefixed(consts::Prefix::DIFF, 5)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_encode_and_decode_batch() {
+        let mut write_batch = PrefixWriteBatch::default();
+        write_batch.set_prefix(&consts::Prefix::STATE);
+        write_batch.put(&[1, 2], &[3, 4]);
+        write_batch.delete(&[1, 2]);
+        write_batch.set_prefix(&consts::Prefix::SMT);
+        write_batch.put(&[5], &[]);
+
+        let encoded = encode_batch(&write_batch.batch);
+        let decoded = decode_batch(&encoded).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(encode_batch(&decoded), encoded);
+
+        let mut cloned = WriteBatch::new_db_with_key_length(None);
+        decoded.iterate(&mut cloned);
+        assert_eq!(encode_batch(&cloned.batch), encoded);
+    }
+
+    #[test]
+    fn test_encode_and_decode_write_batch() {
+        let mut write_batch = WriteBatch::new_db_with_key_length(None);
+        write_batch.batch.put([1, 2], [3, 4]);
+        write_batch.batch.delete([5]);
+        write_batch.conditions = vec![
+            Condition::Absent(vec![1, 2]),
+            Condition::Equals(vec![5], vec![]),
+        ];
+
+        let encoded = encode_write_batch(&write_batch);
+        let decoded = decode_write_batch(&encoded).unwrap();
+        assert_eq!(operations(&decoded.batch), operations(&write_batch.batch));
+        assert_eq!(decoded.conditions, write_batch.conditions);
+        assert_eq!(encode_write_batch(&decoded), encoded);
+
+        // the operations are decoded without the conditions in both ways
+        assert_eq!(decode_batch(&encoded).unwrap().len(), 2);
+        let decoded = decode_write_batch(&encode_batch(&write_batch.batch)).unwrap();
+        assert_eq!(decoded.batch.len(), 2);
+        assert!(decoded.conditions.is_empty());
+
+        let mut writer = codec::Writer::new();
+        writer.write_message(2, |writer| writer.write_u32(1, 2));
+        assert!(matches!(
+            decode_write_batch(writer.result()),
+            Err(codec::CodecError::OutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_operations() {
+        let mut write_batch = WriteBatch::new_db_with_key_length(None);
+        write_batch.batch.put([1, 2], [3, 4]);
+        write_batch.batch.delete([1, 2]);
+        write_batch.batch.put([5], []);
+
+        assert_eq!(
+            operations(&write_batch.batch),
+            vec![
+                (vec![1, 2], Some(vec![3, 4])),
+                (vec![1, 2], None),
+                (vec![5], Some(vec![])),
+            ]
+        );
+        assert!(operations(&rocksdb::WriteBatch::default()).is_empty());
+    }
+
+    #[test]
+    fn test_decode_batch_invalid_data() {
+        let mut write_batch = WriteBatch::new_db_with_key_length(None);
+        write_batch.batch.put([1, 2, 3], [4, 5, 6]);
+        let encoded = encode_batch(&write_batch.batch);
+
+        assert!(decode_batch(&encoded[..encoded.len() - 1]).is_err());
+
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, 5);
+        writer.write_bytes(2, &[1]);
+        let mut invalid = codec::Writer::new();
+        invalid.write_bytes(1, writer.result());
+        assert!(decode_batch(invalid.result()).is_err());
+    }
+
     #[test]
     fn test_set_prefix() {
         let mut write_batch = PrefixWriteBatch::default();
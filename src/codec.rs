@@ -4,6 +4,10 @@ use thiserror::Error;
 use crate::types::NestedVec;
 
 const MAX_VARINT_LEN: usize = 10;
+const MAX_VARINT_U32_LEN: usize = 5;
+
+/// Decoder reads a value from the bytes at the offset, and returns it with the size it used.
+type Decoder<T> = fn(&[u8], usize) -> Result<(T, usize), CodecError>;
 
 #[derive(Error, Debug)]
 pub enum CodecError {
@@ -15,13 +19,32 @@ pub enum CodecError {
     NoTermination,
     #[error("Invalid wire type")]
     InvalidWireType,
+    #[error("Unsupported version `{0}`")]
+    UnsupportedVersion(u8),
+    #[error("Field `{0}` is out of order after field `{1}`")]
+    FieldOutOfOrder(u32, u32),
+    #[error("Unexpected field `{0}`")]
+    UnexpectedField(u32),
+    #[error("Unexpected wire type `{1}` for field `{0}`")]
+    UnexpectedWireType(u32, u32),
+    #[error("Trailing bytes of length `{0}`")]
+    TrailingBytes(usize),
 }
 
+const WIRE_TYPE_VARINT: u32 = 0;
+const WIRE_TYPE_FIXED64: u32 = 1;
+const WIRE_TYPE_BYTES: u32 = 2;
+const WIRE_TYPE_FIXED32: u32 = 5;
+
 ///Reader maintains the bytes and the state of read bytes during the decoding.
+/// In the strict mode, the fields must be read in the order they are written,
+/// and the fields which are not read or have a different wire type are rejected.
 pub struct Reader<'a> {
     index: usize,
     end: usize,
     data: &'a [u8],
+    strict: bool,
+    last_field_number: u32,
 }
 
 /// Writer maintains the bytes written during the encoding.
@@ -30,7 +53,13 @@ pub struct Writer {
     size: usize,
 }
 
-fn write_varint(value: u32) -> Vec<u8> {
+/// write_varint encodes the value as unsigned varint.
+pub(crate) fn write_varint(value: u32) -> Vec<u8> {
+    write_varint_u64(u64::from(value))
+}
+
+/// write_varint_u64 encodes the 64 bits value as unsigned varint.
+pub(crate) fn write_varint_u64(value: u64) -> Vec<u8> {
     let mut value = value;
     let mut result = vec![0; MAX_VARINT_LEN];
     let mut index = 0;
@@ -44,9 +73,15 @@ fn write_varint(value: u32) -> Vec<u8> {
     result[0..index + 1].to_vec()
 }
 
+/// varint_size returns the number of the bytes used to encode the value as unsigned varint.
+pub(crate) fn varint_size(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
 /// read_varint from the given bytes starting from the offset.
 /// it returns the value read as varint and the size it used.
-fn read_varint(data: &[u8], offset: usize) -> Result<(u32, usize), CodecError> {
+pub(crate) fn read_varint(data: &[u8], offset: usize) -> Result<(u32, usize), CodecError> {
     let mut result: u32 = 0;
     let mut index = offset;
     let mut shift = 0;
@@ -56,7 +91,7 @@ fn read_varint(data: &[u8], offset: usize) -> Result<(u32, usize), CodecError> {
         }
         let bit = data[index] as u32;
         index += 1;
-        if index == offset + 5 && bit > 0x0f {
+        if index == offset + MAX_VARINT_U32_LEN && bit > 0x0f {
             return Err(CodecError::OutOfRange);
         }
         result |= (bit & 0x7f_u32) << shift;
@@ -69,9 +104,47 @@ fn read_varint(data: &[u8], offset: usize) -> Result<(u32, usize), CodecError> {
     Err(CodecError::NoTermination)
 }
 
+/// read_varint_u64 from the given bytes starting from the offset.
+/// it returns the 64 bits value read as varint and the size it used.
+pub(crate) fn read_varint_u64(data: &[u8], offset: usize) -> Result<(u64, usize), CodecError> {
+    let mut result: u64 = 0;
+    let mut index = offset;
+    let mut shift = 0;
+    while shift < 64 {
+        if index >= data.len() {
+            return Err(CodecError::InvalidBytesLength);
+        }
+        let bit = data[index] as u64;
+        index += 1;
+        if index == offset + MAX_VARINT_LEN && bit > 0x01 {
+            return Err(CodecError::OutOfRange);
+        }
+        result |= (bit & 0x7f_u64) << shift;
+        if (bit & 0x80) == 0 {
+            return Ok((result, index - offset));
+        }
+
+        shift += 7;
+    }
+    Err(CodecError::NoTermination)
+}
+
+/// zigzag_encode maps the signed value to the unsigned value so that the small absolute values have the small encoding.
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// zigzag_decode reverses zigzag_encode.
+fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
 fn read_key(val: u32) -> Result<(u32, u32), CodecError> {
     let wire_type = val & 7;
-    if wire_type != 0 && wire_type != 2 {
+    if !matches!(
+        wire_type,
+        WIRE_TYPE_VARINT | WIRE_TYPE_FIXED64 | WIRE_TYPE_BYTES | WIRE_TYPE_FIXED32
+    ) {
         return Err(CodecError::InvalidWireType);
     }
     let field_number = val >> 3;
@@ -79,40 +152,112 @@ fn read_key(val: u32) -> Result<(u32, u32), CodecError> {
 }
 
 impl<'a> Reader<'a> {
-    fn read_only_bytes(&mut self) -> Result<Vec<u8>, CodecError> {
+    fn read_only_slice(&mut self) -> Result<&'a [u8], CodecError> {
         let (result, size) = read_varint(self.data, self.index)?;
         self.index += size;
-        if result as usize > self.data.len() {
+        // the length is compared with the remaining bytes, since the sum overflows usize of 32 bits
+        let length = result as usize;
+        if length > self.data.len() - self.index {
             return Err(CodecError::InvalidBytesLength);
         }
-        let decoded = self.data[self.index..self.index + result as usize].to_vec();
-        self.index += result as usize;
+        let decoded = &self.data[self.index..self.index + length];
+        self.index += length;
 
         Ok(decoded)
     }
 
-    fn check(&mut self, field_number: u32) -> Result<bool, CodecError> {
+    fn read_only_bytes(&mut self) -> Result<Vec<u8>, CodecError> {
+        Ok(self.read_only_slice()?.to_vec())
+    }
+
+    fn read_only_fixed<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        if N > self.data.len() - self.index {
+            return Err(CodecError::InvalidBytesLength);
+        }
+        let mut result = [0; N];
+        result.copy_from_slice(&self.data[self.index..self.index + N]);
+        self.index += N;
+
+        Ok(result)
+    }
+
+    fn read_packed<T>(
+        &mut self,
+        field_number: u32,
+        read: Decoder<T>,
+    ) -> Result<Vec<T>, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_BYTES)? {
+            return Ok(vec![]);
+        }
+        let data = self.read_only_slice()?;
+        let mut result = vec![];
+        let mut index = 0;
+        while index < data.len() {
+            let (value, size) = read(data, index)?;
+            index += size;
+            result.push(value);
+        }
+
+        Ok(result)
+    }
+
+    fn check(&mut self, field_number: u32, wire_type: u32) -> Result<bool, CodecError> {
         if self.index >= self.end {
             return Ok(false);
         }
 
         let (key, size) = read_varint(self.data, self.index)?;
-        let (next_field_number, _) = read_key(key)?;
+        let (next_field_number, next_wire_type) = read_key(key)?;
+        if self.strict {
+            if next_field_number < self.last_field_number {
+                return Err(CodecError::FieldOutOfOrder(
+                    next_field_number,
+                    self.last_field_number,
+                ));
+            }
+            if next_field_number < field_number {
+                return Err(CodecError::UnexpectedField(next_field_number));
+            }
+            if next_field_number == field_number && next_wire_type != wire_type {
+                return Err(CodecError::UnexpectedWireType(field_number, next_wire_type));
+            }
+        }
         if field_number != next_field_number {
             return Ok(false);
         }
         self.index += size;
+        self.last_field_number = field_number;
         Ok(true)
     }
 
-    /// new creates new reader from the given data.
-    pub fn new(data: &'a [u8]) -> Self {
+    fn new_with_mode(data: &'a [u8], strict: bool) -> Self {
         let length = data.len();
         Self {
             data,
             index: 0,
             end: length,
+            strict,
+            last_field_number: 0,
+        }
+    }
+
+    /// new creates new reader from the given data.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::new_with_mode(data, false)
+    }
+
+    /// strict creates new reader in the strict mode from the given data.
+    /// It is used to decode the untrusted bytes, and finish must be called after reading all the fields.
+    pub fn strict(data: &'a [u8]) -> Self {
+        Self::new_with_mode(data, true)
+    }
+
+    /// finish returns TrailingBytes in the strict mode when the data is not read to the end.
+    pub fn finish(&self) -> Result<(), CodecError> {
+        if self.strict && self.index < self.end {
+            return Err(CodecError::TrailingBytes(self.end - self.index));
         }
+        Ok(())
     }
 
     /// read_bytes_slice reads next field as slice of bytes slice.
@@ -120,7 +265,7 @@ impl<'a> Reader<'a> {
     pub fn read_bytes_slice(&mut self, field_number: u32) -> Result<NestedVec, CodecError> {
         let mut result = vec![];
         while self.index < self.end {
-            let ok = self.check(field_number)?;
+            let ok = self.check(field_number, WIRE_TYPE_BYTES)?;
             if !ok {
                 return Ok(result);
             }
@@ -134,12 +279,120 @@ impl<'a> Reader<'a> {
     /// read_bytes reads next field as bytes.
     /// When next field does not match, it returns empty bytes.
     pub fn read_bytes(&mut self, field_number: u32) -> Result<Vec<u8>, CodecError> {
-        let ok = self.check(field_number)?;
+        let ok = self.check(field_number, WIRE_TYPE_BYTES)?;
         match ok {
             true => self.read_only_bytes(),
             false => Ok(vec![]),
         }
     }
+
+    /// read_message reads next field as a nested message and returns the reader of its fields.
+    /// When next field does not match, it returns the reader of an empty message.
+    pub fn read_message(&mut self, field_number: u32) -> Result<Reader<'a>, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_BYTES)? {
+            return Ok(Reader::new_with_mode(&[], self.strict));
+        }
+        Ok(Reader::new_with_mode(self.read_only_slice()?, self.strict))
+    }
+
+    /// read_messages reads next fields as repeated nested messages.
+    /// When next field does not match, it returns empty slice.
+    pub fn read_messages(&mut self, field_number: u32) -> Result<Vec<Reader<'a>>, CodecError> {
+        let mut result = vec![];
+        while self.check(field_number, WIRE_TYPE_BYTES)? {
+            result.push(Reader::new_with_mode(self.read_only_slice()?, self.strict));
+        }
+
+        Ok(result)
+    }
+
+    /// read_u32 reads next field as uint32.
+    /// When next field does not match, it returns 0.
+    pub fn read_u32(&mut self, field_number: u32) -> Result<u32, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_VARINT)? {
+            return Ok(0);
+        }
+        let (result, size) = read_varint(self.data, self.index)?;
+        self.index += size;
+
+        Ok(result)
+    }
+
+    /// read_u64 reads next field as uint64.
+    /// When next field does not match, it returns 0.
+    pub fn read_u64(&mut self, field_number: u32) -> Result<u64, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_VARINT)? {
+            return Ok(0);
+        }
+        let (result, size) = read_varint_u64(self.data, self.index)?;
+        self.index += size;
+
+        Ok(result)
+    }
+
+    /// read_fixed32 reads next field as fixed32.
+    /// When next field does not match, it returns 0.
+    pub fn read_fixed32(&mut self, field_number: u32) -> Result<u32, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_FIXED32)? {
+            return Ok(0);
+        }
+        Ok(u32::from_le_bytes(self.read_only_fixed()?))
+    }
+
+    /// read_fixed64 reads next field as fixed64.
+    /// When next field does not match, it returns 0.
+    pub fn read_fixed64(&mut self, field_number: u32) -> Result<u64, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_FIXED64)? {
+            return Ok(0);
+        }
+        Ok(u64::from_le_bytes(self.read_only_fixed()?))
+    }
+
+    /// read_packed_u32 reads next field as packed repeated uint32.
+    /// When next field does not match, it returns empty slice.
+    pub fn read_packed_u32(&mut self, field_number: u32) -> Result<Vec<u32>, CodecError> {
+        self.read_packed(field_number, read_varint)
+    }
+
+    /// read_packed_u64 reads next field as packed repeated uint64.
+    /// When next field does not match, it returns empty slice.
+    pub fn read_packed_u64(&mut self, field_number: u32) -> Result<Vec<u64>, CodecError> {
+        self.read_packed(field_number, read_varint_u64)
+    }
+
+    /// read_i32 reads next field as sint32 encoded with zigzag.
+    /// When next field does not match, it returns 0.
+    pub fn read_i32(&mut self, field_number: u32) -> Result<i32, CodecError> {
+        if !self.check(field_number, WIRE_TYPE_VARINT)? {
+            return Ok(0);
+        }
+        let (result, size) = read_varint(self.data, self.index)?;
+        self.index += size;
+
+        Ok(zigzag_decode(u64::from(result)) as i32)
+    }
+
+    /// read_i64 reads next field as sint64 encoded with zigzag.
+    /// When next field does not match, it returns 0.
+    pub fn read_i64(&mut self, field_number: u32) -> Result<i64, CodecError> {
+        Ok(zigzag_decode(self.read_u64(field_number)?))
+    }
+
+    /// read_bool reads next field as boolean.
+    /// When next field does not match, it returns false.
+    pub fn read_bool(&mut self, field_number: u32) -> Result<bool, CodecError> {
+        match self.read_u32(field_number)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(CodecError::OutOfRange),
+        }
+    }
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Writer {
@@ -165,12 +418,80 @@ impl Writer {
 
     /// write_bytes encodes bytes slice to the writer with specified field number
     pub fn write_bytes(&mut self, field_number: u32, value: &[u8]) {
-        self.write_key(2, field_number);
+        self.write_key(WIRE_TYPE_BYTES, field_number);
         self.write_varint(value.len() as u32);
         self.size += value.len();
         self.result.extend(value);
     }
 
+    /// write_message encodes the nested message to the writer with specified field number.
+    /// The fields of the message are written to the writer given to write_fields.
+    pub fn write_message<F: FnOnce(&mut Writer)>(&mut self, field_number: u32, write_fields: F) {
+        let mut writer = Writer::new();
+        write_fields(&mut writer);
+        self.write_bytes(field_number, writer.result());
+    }
+
+    /// write_u32 encodes uint32 to the writer with specified field number
+    pub fn write_u32(&mut self, field_number: u32, value: u32) {
+        self.write_key(WIRE_TYPE_VARINT, field_number);
+        self.write_varint(value);
+    }
+
+    /// write_u64 encodes uint64 to the writer with specified field number
+    pub fn write_u64(&mut self, field_number: u32, value: u64) {
+        self.write_key(WIRE_TYPE_VARINT, field_number);
+        self.size += varint_size(value);
+        self.result.extend(write_varint_u64(value));
+    }
+
+    /// write_fixed32 encodes fixed32 to the writer with specified field number
+    pub fn write_fixed32(&mut self, field_number: u32, value: u32) {
+        self.write_key(WIRE_TYPE_FIXED32, field_number);
+        self.size += 4;
+        self.result.extend(value.to_le_bytes());
+    }
+
+    /// write_fixed64 encodes fixed64 to the writer with specified field number
+    pub fn write_fixed64(&mut self, field_number: u32, value: u64) {
+        self.write_key(WIRE_TYPE_FIXED64, field_number);
+        self.size += 8;
+        self.result.extend(value.to_le_bytes());
+    }
+
+    /// write_packed_u32 encodes slice of uint32 as packed repeated field with specified field number
+    pub fn write_packed_u32(&mut self, field_number: u32, values: &[u32]) {
+        let values: Vec<u64> = values.iter().map(|val| u64::from(*val)).collect();
+        self.write_packed_u64(field_number, &values);
+    }
+
+    /// write_packed_u64 encodes slice of uint64 as packed repeated field with specified field number
+    pub fn write_packed_u64(&mut self, field_number: u32, values: &[u64]) {
+        if values.is_empty() {
+            return;
+        }
+        let packed: Vec<u8> = values
+            .iter()
+            .flat_map(|val| write_varint_u64(*val))
+            .collect();
+        self.write_bytes(field_number, &packed);
+    }
+
+    /// write_i32 encodes sint32 with zigzag to the writer with specified field number
+    pub fn write_i32(&mut self, field_number: u32, value: i32) {
+        self.write_u64(field_number, zigzag_encode(i64::from(value)));
+    }
+
+    /// write_i64 encodes sint64 with zigzag to the writer with specified field number
+    pub fn write_i64(&mut self, field_number: u32, value: i64) {
+        self.write_u64(field_number, zigzag_encode(value));
+    }
+
+    /// write_bool encodes boolean to the writer with specified field number
+    pub fn write_bool(&mut self, field_number: u32, value: bool) {
+        self.write_u32(field_number, u32::from(value));
+    }
+
     /// write_bytes encodes slice of bytes slice to the writer with specified field number
     pub fn write_bytes_slice(&mut self, field_number: u32, values: &[Vec<u8>]) {
         if values.is_empty() {
@@ -184,6 +505,11 @@ impl Writer {
     pub fn result(&self) -> &Vec<u8> {
         &self.result
     }
+
+    /// size returns the number of the bytes written.
+    pub fn size(&self) -> usize {
+        self.size
+    }
 }
 
 #[cfg(test)]
@@ -245,6 +571,256 @@ mod tests {
         assert_eq!(res, vec![]);
     }
 
+    #[test]
+    fn test_reader_read_u32() {
+        let mut writer = Writer::new();
+        writer.write_u32(1, 300);
+        writer.write_bytes(2, &[1, 2, 3]);
+
+        let mut reader = Reader::new(writer.result());
+
+        assert_eq!(reader.read_u32(1).unwrap(), 300);
+        assert_eq!(reader.read_u32(1).unwrap(), 0);
+        assert_eq!(reader.read_bytes(2).unwrap(), [1, 2, 3].to_vec());
+    }
+
+    #[test]
+    fn test_reader_read_u64() {
+        let mut writer = Writer::new();
+        writer.write_u64(1, u64::MAX);
+        writer.write_u64(2, 1 << 40);
+        writer.write_u32(3, 300);
+
+        let mut reader = Reader::new(writer.result());
+
+        assert_eq!(reader.read_u64(1).unwrap(), u64::MAX);
+        assert_eq!(reader.read_u64(2).unwrap(), 1 << 40);
+        assert_eq!(reader.read_u64(3).unwrap(), 300);
+        assert_eq!(reader.read_u64(3).unwrap(), 0);
+        assert_eq!(writer.size(), writer.result().len());
+    }
+
+    #[test]
+    fn test_varint_u64() {
+        let cases: [(u64, Vec<u8>); 5] = [
+            (0, vec![0]),
+            (300, vec![0xac, 0x02]),
+            (u32::MAX as u64, vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (1 << 35, vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+            (u64::MAX, [vec![0xff; 9], vec![0x01]].concat()),
+        ];
+        for (value, encoded) in cases.iter() {
+            assert_eq!(&write_varint_u64(*value), encoded);
+            assert_eq!(varint_size(*value), encoded.len());
+            let (decoded, size) = read_varint_u64(encoded, 0).unwrap();
+            assert_eq!((decoded, size), (*value, encoded.len()));
+        }
+        assert_eq!(write_varint(300), write_varint_u64(300));
+
+        let overflow = [vec![0xff; 9], vec![0x02]].concat();
+        assert!(matches!(
+            read_varint_u64(&overflow, 0),
+            Err(CodecError::OutOfRange)
+        ));
+        assert!(matches!(
+            read_varint(&overflow, 0),
+            Err(CodecError::OutOfRange)
+        ));
+        assert!(matches!(
+            read_varint_u64(&[0x80, 0x80], 0),
+            Err(CodecError::InvalidBytesLength)
+        ));
+    }
+
+    #[test]
+    fn test_zigzag() {
+        let cases: [(i64, u64); 6] = [
+            (0, 0),
+            (-1, 1),
+            (1, 2),
+            (-2, 3),
+            (i64::MAX, u64::MAX - 1),
+            (i64::MIN, u64::MAX),
+        ];
+        for (value, encoded) in cases.iter() {
+            assert_eq!(zigzag_encode(*value), *encoded);
+            assert_eq!(zigzag_decode(*encoded), *value);
+        }
+    }
+
+    #[test]
+    fn test_reader_read_signed_and_bool() {
+        let mut writer = Writer::new();
+        writer.write_i32(1, -300);
+        writer.write_i32(2, i32::MIN);
+        writer.write_i64(3, i64::MIN);
+        writer.write_i64(4, 1 << 40);
+        writer.write_bool(5, true);
+        writer.write_bool(6, false);
+        writer.write_u32(7, 2);
+
+        let mut reader = Reader::new(writer.result());
+
+        assert_eq!(reader.read_i32(1).unwrap(), -300);
+        assert_eq!(reader.read_i32(2).unwrap(), i32::MIN);
+        assert_eq!(reader.read_i64(3).unwrap(), i64::MIN);
+        assert_eq!(reader.read_i64(4).unwrap(), 1 << 40);
+        assert_eq!(reader.read_i64(4).unwrap(), 0);
+        assert!(reader.read_bool(5).unwrap());
+        assert!(!reader.read_bool(6).unwrap());
+        assert!(matches!(reader.read_bool(7), Err(CodecError::OutOfRange)));
+        assert_eq!(writer.result()[..3], [0x08, 0xd7, 0x04]);
+    }
+
+    #[test]
+    fn test_reader_read_message() {
+        let mut writer = Writer::new();
+        writer.write_message(1, |writer| {
+            writer.write_u32(1, 300);
+            writer.write_message(2, |writer| writer.write_bytes(1, &[1, 2, 3]));
+        });
+        for i in 0..3u32 {
+            writer.write_message(2, |writer| writer.write_u32(1, i));
+        }
+        writer.write_u32(3, 7);
+
+        let mut reader = Reader::new(writer.result());
+
+        let mut message = reader.read_message(1).unwrap();
+        assert_eq!(message.read_u32(1).unwrap(), 300);
+        let mut nested = message.read_message(2).unwrap();
+        assert_eq!(nested.read_bytes(1).unwrap(), [1, 2, 3].to_vec());
+        let values = reader
+            .read_messages(2)
+            .unwrap()
+            .iter_mut()
+            .map(|message| message.read_u32(1).unwrap())
+            .collect::<Vec<u32>>();
+        assert_eq!(values, vec![0, 1, 2]);
+        let mut missing = reader.read_message(2).unwrap();
+        assert_eq!(missing.read_u32(1).unwrap(), 0);
+        assert_eq!(reader.read_u32(3).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_reader_invalid_length() {
+        let data = [0x0a, 0xff, 0xff, 0xff, 0xff, 0x0f, 1];
+        assert!(matches!(
+            Reader::new(&data).read_bytes(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+        assert!(matches!(
+            Reader::new(&data).read_message(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+        assert!(matches!(
+            Reader::new(&[0x0d, 1, 2]).read_fixed32(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+        assert!(matches!(
+            Reader::new(&[0x0a, 2, 0x80, 0x80]).read_packed_u32(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+    }
+
+    #[test]
+    fn test_strict_reader() {
+        let mut writer = Writer::new();
+        writer.write_u32(1, 300);
+        writer.write_message(2, |writer| writer.write_bytes(1, &[1, 2, 3]));
+        writer.write_bytes(3, &[4]);
+        writer.write_bytes(3, &[5]);
+        let data = writer.result();
+
+        let mut reader = Reader::strict(data);
+        assert_eq!(reader.read_u32(1).unwrap(), 300);
+        let mut message = reader.read_message(2).unwrap();
+        assert_eq!(message.read_bytes(1).unwrap(), [1, 2, 3].to_vec());
+        message.finish().unwrap();
+        assert_eq!(reader.read_bytes_slice(3).unwrap(), vec![vec![4], vec![5]]);
+        reader.finish().unwrap();
+
+        // skipped field
+        let mut reader = Reader::strict(data);
+        assert!(matches!(
+            reader.read_message(2),
+            Err(CodecError::UnexpectedField(1))
+        ));
+        // different wire type
+        let mut reader = Reader::strict(data);
+        assert!(matches!(
+            reader.read_bytes(1),
+            Err(CodecError::UnexpectedWireType(1, 0))
+        ));
+        // not read to the end
+        let mut reader = Reader::strict(data);
+        reader.read_u32(1).unwrap();
+        assert!(matches!(reader.finish(), Err(CodecError::TrailingBytes(_))));
+        let mut reader = Reader::new(data);
+        reader.read_u32(1).unwrap();
+        reader.finish().unwrap();
+
+        let mut writer = Writer::new();
+        writer.write_u32(2, 1);
+        writer.write_u32(1, 1);
+        let mut reader = Reader::strict(writer.result());
+        assert_eq!(reader.read_u32(2).unwrap(), 1);
+        assert!(matches!(
+            reader.read_u32(3),
+            Err(CodecError::FieldOutOfOrder(1, 2))
+        ));
+        let mut reader = Reader::new(writer.result());
+        assert_eq!(reader.read_u32(1).unwrap(), 0);
+        assert_eq!(reader.read_u32(2).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reader_read_fixed_and_packed() {
+        let mut writer = Writer::new();
+        writer.write_fixed32(1, 0x01020304);
+        writer.write_fixed64(2, u64::MAX - 1);
+        writer.write_packed_u32(3, &[1, 300, u32::MAX]);
+        writer.write_packed_u64(4, &[]);
+        writer.write_packed_u64(5, &[u64::MAX]);
+        assert_eq!(writer.result()[..5], [0x0d, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(writer.size(), writer.result().len());
+
+        let mut reader = Reader::strict(writer.result());
+
+        assert_eq!(reader.read_fixed32(1).unwrap(), 0x01020304);
+        assert_eq!(reader.read_fixed64(2).unwrap(), u64::MAX - 1);
+        assert_eq!(reader.read_packed_u32(3).unwrap(), vec![1, 300, u32::MAX]);
+        assert_eq!(reader.read_packed_u64(4).unwrap(), vec![]);
+        assert_eq!(reader.read_packed_u64(5).unwrap(), vec![u64::MAX]);
+        reader.finish().unwrap();
+
+        let mut reader = Reader::new(&[0x0d, 0x01, 0x02]);
+        assert!(matches!(
+            reader.read_fixed32(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+        let mut writer = Writer::new();
+        writer.write_packed_u64(1, &[u64::from(u32::MAX) + 1]);
+        let mut reader = Reader::new(writer.result());
+        assert!(matches!(
+            reader.read_packed_u32(1),
+            Err(CodecError::OutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_reader_read_bytes_truncated() {
+        let mut writer = Writer::new();
+        writer.write_bytes(1, &[1, 2, 3, 4, 5]);
+        let data = &writer.result()[..4];
+
+        let mut reader = Reader::new(data);
+        assert!(matches!(
+            reader.read_bytes(1),
+            Err(CodecError::InvalidBytesLength)
+        ));
+    }
+
     #[test]
     fn test_new_writer() {
         let writer = Writer::new();
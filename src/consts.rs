@@ -23,4 +23,26 @@ impl Prefix {
     pub const DIFF: &'static [u8] = &[2];
     /// CURRENT_STATE maintains current version and the root hash of the state_db.
     pub const CURRENT_STATE: &'static [u8] = &[3];
+    /// ROOT_HISTORY maintains the state root of each committed version.
+    pub const ROOT_HISTORY: &'static [u8] = &[4];
+    /// AUDIT maintains the records of the removed diffs and SMT nodes when deletion is audited.
+    pub const AUDIT: &'static [u8] = &[5];
+    /// FINALIZED maintains the finalized version and its root when rollback protection is enabled.
+    pub const FINALIZED: &'static [u8] = &[6];
+    /// LIFETIME_STATS maintains the cumulative statistics of the commits.
+    pub const LIFETIME_STATS: &'static [u8] = &[7];
+    /// SMT_KEY_FILTER maintains the bloom filter of the leaf keys of each range of heights when the key filter is enabled.
+    pub const SMT_KEY_FILTER: &'static [u8] = &[8];
+    /// STORE_ROOT maintains the sub-root of each store prefix at the heights updating it when the store roots are enabled.
+    pub const STORE_ROOT: &'static [u8] = &[9];
+    /// STRUCTURE_LOG maintains the SMT nodes created and deleted by the commit of each height when the structure log is enabled.
+    pub const STRUCTURE_LOG: &'static [u8] = &[10];
+    /// HEIGHT_BY_ROOT maintains the versions committed with each state root.
+    pub const HEIGHT_BY_ROOT: &'static [u8] = &[12];
+    /// ROOT_HISTORY_MODE maintains whether the root history was kept when the state_db was created.
+    pub const ROOT_HISTORY_MODE: &'static [u8] = &[13];
+    /// RELEASED_NODES maintains the SMT nodes released by the commit of each version when root history is kept.
+    pub const RELEASED_NODES: &'static [u8] = &[14];
+    /// NODE_RELEASE maintains the version releasing each SMT node which is not created again since.
+    pub const NODE_RELEASE: &'static [u8] = &[15];
 }
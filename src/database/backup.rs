@@ -0,0 +1,128 @@
+/// backup provides incremental backups of the database using the BackupEngine of RocksDB.
+/// Each backup only copies the SST files which are not in the previous backups of the directory,
+/// so the backup can be taken periodically while the node keeps running.
+use std::path::Path;
+
+use neon::prelude::*;
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+
+use crate::database::utils;
+use crate::task_pool;
+
+/// create captures the current state of the database as a new backup in the directory.
+/// The memtable is flushed before the backup, so the writes which are not in the SST files yet are included.
+pub fn create<P: AsRef<Path>>(conn: &rocksdb::DB, dir: P) -> Result<(), rocksdb::Error> {
+    create_opt(conn, dir, true)
+}
+
+/// create_opt captures the database as a new backup without flushing the memtable if `flush` is false.
+/// The database opened read only cannot be flushed, and the writes in its WAL are backed up with the log files.
+pub fn create_opt<P: AsRef<Path>>(
+    conn: &rocksdb::DB,
+    dir: P,
+    flush: bool,
+) -> Result<(), rocksdb::Error> {
+    let mut engine = BackupEngine::open(&BackupEngineOptions::default(), dir)?;
+    engine.create_new_backup_flush(conn, flush)
+}
+
+/// restore restores the latest backup in the directory to the target path.
+/// The database at the target path must not be opened while restoring.
+pub fn restore<P: AsRef<Path>, T: AsRef<Path>>(dir: P, target: T) -> Result<(), rocksdb::Error> {
+    let mut engine = BackupEngine::open(&BackupEngineOptions::default(), dir)?;
+    engine.restore_from_latest_backup(&target, &target, &RestoreOptions::default())
+}
+
+/// purge removes the backups in the directory except the latest `keep` backups.
+pub fn purge<P: AsRef<Path>>(dir: P, keep: usize) -> Result<(), rocksdb::Error> {
+    let mut engine = BackupEngine::open(&BackupEngineOptions::default(), dir)?;
+    engine.purge_old_backups(keep)
+}
+
+fn send_result(channel: Channel, callback: Root<JsFunction>, result: Result<(), rocksdb::Error>) {
+    channel.send(move |mut ctx| {
+        let callback = callback.into_inner(&mut ctx);
+        let this = ctx.undefined();
+        let args: Vec<Handle<JsValue>> = match result {
+            Ok(()) => vec![ctx.null().upcast()],
+            Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+        };
+        callback.call(&mut ctx, this, args)?;
+
+        Ok(())
+    });
+}
+
+/// js_restore is handler for JS ffi.
+/// - @params(0) - directory of the backups.
+/// - @params(1) - path of the database to restore to.
+/// - @params(2) - callback to return the result.
+/// - @callback(0) - Error.
+pub fn js_restore(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let dir = ctx.argument::<JsString>(0)?.value(&mut ctx);
+    let target = ctx.argument::<JsString>(1)?.value(&mut ctx);
+    let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+    let channel = ctx.channel();
+
+    task_pool::shared()
+        .execute(move || {
+            let result = restore(dir, target);
+            send_result(channel, callback, result);
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+    Ok(ctx.undefined())
+}
+
+/// js_purge is handler for JS ffi.
+/// - @params(0) - directory of the backups.
+/// - @params(1) - number of the latest backups to keep.
+/// - @params(2) - callback to return the result.
+/// - @callback(0) - Error.
+pub fn js_purge(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let dir = ctx.argument::<JsString>(0)?.value(&mut ctx);
+    let keep = ctx.argument::<JsNumber>(1)?.value(&mut ctx) as usize;
+    let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+    let channel = ctx.channel();
+
+    task_pool::shared()
+        .execute(move || {
+            let result = purge(dir, keep);
+            send_result(channel, callback, result);
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+    Ok(ctx.undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let temp_dir = TempDir::new("test_backup").unwrap();
+        let backup_dir = temp_dir.path().join("backup");
+        let conn = rocksdb::DB::open_default(temp_dir.path().join("db")).unwrap();
+        conn.put([1], [2]).unwrap();
+        create(&conn, &backup_dir).unwrap();
+        conn.put([3], [4]).unwrap();
+        create(&conn, &backup_dir).unwrap();
+        conn.put([5], [6]).unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        restore(&backup_dir, &restored).unwrap();
+        {
+            let conn = rocksdb::DB::open_default(&restored).unwrap();
+            assert_eq!(conn.get([1]).unwrap(), Some(vec![2]));
+            assert_eq!(conn.get([3]).unwrap(), Some(vec![4]));
+            assert_eq!(conn.get([5]).unwrap(), None);
+        }
+
+        purge(&backup_dir, 1).unwrap();
+        let engine = BackupEngine::open(&BackupEngineOptions::default(), &backup_dir).unwrap();
+        assert_eq!(engine.get_backup_info().len(), 1);
+    }
+}
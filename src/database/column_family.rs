@@ -0,0 +1,757 @@
+/// column_family keeps the state, the SMT nodes and the diffs of the state_db in their own column families,
+/// so that each of them is flushed, compacted and tuned independently.
+/// The keys keep their prefix in the column family, and the other prefixes stay in the default column family.
+/// When the database does not have the column families, all the keys are read from and written to the default one.
+/// The state column family extracts the store prefix of the keys for the prefix bloom filters,
+/// so the iteration within a store prefix only reads the files and the memtables with the prefix.
+use std::path::Path;
+
+use rocksdb::WriteBatchIterator;
+
+use crate::consts::Prefix;
+use crate::database::types::{Compression, DbOptions};
+use crate::types::PREFIX_SIZE;
+use crate::utils;
+
+/// STATE is the column family of the keys with Prefix::STATE.
+pub const STATE: &str = "state";
+/// SMT is the column family of the keys with Prefix::SMT.
+pub const SMT: &str = "smt";
+/// DIFF is the column family of the keys with Prefix::DIFF.
+pub const DIFF: &str = "diff";
+/// COLUMN_FAMILIES are the column families with the prefix of the keys stored in them.
+pub const COLUMN_FAMILIES: [(&str, &[u8]); 3] = [
+    (STATE, Prefix::STATE),
+    (SMT, Prefix::SMT),
+    (DIFF, Prefix::DIFF),
+];
+/// MIGRATION_BATCH_SIZE is the number of the keys moved to the column family in one write.
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+/// STATE_PREFIX_LENGTH is the length of the prefix extracted from the keys of the state, which is Prefix::STATE and the store prefix.
+const STATE_PREFIX_LENGTH: usize = Prefix::STATE.len() + PREFIX_SIZE;
+/// MEMTABLE_PREFIX_BLOOM_RATIO is the ratio of the memtable used for the prefix bloom filter.
+const MEMTABLE_PREFIX_BLOOM_RATIO: f64 = 0.1;
+
+/// name returns the column family of the key.
+pub fn name(key: &[u8]) -> Option<&'static str> {
+    COLUMN_FAMILIES
+        .iter()
+        .find(|(_, prefix)| key.starts_with(prefix))
+        .map(|(name, _)| *name)
+}
+
+/// handle returns the column family of the key if the database has it.
+pub fn handle<'a>(conn: &'a rocksdb::DB, key: &[u8]) -> Option<&'a rocksdb::ColumnFamily> {
+    name(key).and_then(|name| conn.cf_handle(name))
+}
+
+/// get returns the value of the key, copied once out of the pinned slice into the vector,
+/// which is moved into the buffer given to JS. The pinned slice itself is not exposed to JS,
+/// since JS can write to the buffer, and the buffer would keep the database open until it is collected.
+pub fn get(conn: &rocksdb::DB, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+    match handle(conn, key) {
+        Some(cf) => conn.get_cf(cf, key),
+        None => conn.get(key),
+    }
+}
+
+/// compact_range compacts the keys with the prefix between start and end in the column family of the prefix.
+/// start and end do not contain the prefix, and all the keys with the prefix are compacted when they are not given.
+pub fn compact_range(conn: &rocksdb::DB, prefix: &[u8], start: Option<&[u8]>, end: Option<&[u8]>) {
+    let start = [prefix, start.unwrap_or_default()].concat();
+    let end = match end {
+        Some(end) => Some([prefix, end].concat()),
+        None => utils::prefix_end(prefix),
+    };
+    match handle(conn, prefix) {
+        Some(cf) => conn.compact_range_cf(cf, Some(start), end),
+        None => conn.compact_range(Some(start), end),
+    }
+}
+
+/// compact_smt compacts only the SMT nodes, which is where most of the space is reclaimed after pruning.
+pub fn compact_smt(conn: &rocksdb::DB) {
+    compact_range(conn, Prefix::SMT, None, None);
+}
+
+/// set_auto_compaction enables or disables the automatic compaction of the default and the other column families.
+pub fn set_auto_compaction(conn: &rocksdb::DB, enabled: bool) -> Result<(), rocksdb::Error> {
+    let disabled = if enabled { "false" } else { "true" };
+    let opts = [("disable_auto_compactions", disabled)];
+    conn.set_options(&opts)?;
+    for (name, _) in COLUMN_FAMILIES {
+        if let Some(cf) = conn.cf_handle(name) {
+            conn.set_options_cf(cf, &opts)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// multi_get returns the values of the keys in one call to the database.
+/// The keys are usually in the same column family, and they are read key by key only when they are not.
+pub fn multi_get(
+    conn: &rocksdb::DB,
+    keys: &[Vec<u8>],
+) -> Vec<Result<Option<Vec<u8>>, rocksdb::Error>> {
+    let handles: Vec<Option<&rocksdb::ColumnFamily>> =
+        keys.iter().map(|key| handle(conn, key)).collect();
+    if handles.iter().all(|cf| cf.is_none()) {
+        conn.multi_get(keys)
+    } else if handles.iter().all(|cf| cf.is_some()) {
+        conn.multi_get_cf(handles.into_iter().flatten().zip(keys))
+    } else {
+        keys.iter().map(|key| get(conn, key)).collect()
+    }
+}
+
+pub fn key_may_exist(conn: &rocksdb::DB, key: &[u8]) -> bool {
+    match handle(conn, key) {
+        Some(cf) => conn.key_may_exist_cf(cf, key),
+        None => conn.key_may_exist(key),
+    }
+}
+
+/// exists returns true if the key is in the database.
+/// The bloom filter rules out most of the missing keys, and the value of the existing key is read
+/// as a pinned slice, so it is never copied.
+pub fn exists(conn: &rocksdb::DB, key: &[u8]) -> Result<bool, rocksdb::Error> {
+    if !key_may_exist(conn, key) {
+        return Ok(false);
+    }
+    Ok(get_pinned(conn, key)?.is_some())
+}
+
+/// get_pinned returns the value of the key as a pinned slice, which is read without being copied.
+/// The slice keeps the block of the value in the cache until it is dropped.
+pub fn get_pinned<'a>(
+    conn: &'a rocksdb::DB,
+    key: &[u8],
+) -> Result<Option<rocksdb::DBPinnableSlice<'a>>, rocksdb::Error> {
+    match handle(conn, key) {
+        Some(cf) => conn.get_pinned_cf(cf, key),
+        None => conn.get_pinned(key),
+    }
+}
+
+/// is_within_store_prefix returns true if the state keys from lower to upper, which do not include Prefix::STATE,
+/// all have the same store prefix.
+pub fn is_within_store_prefix(lower: Option<&[u8]>, upper: Option<&[u8]>) -> bool {
+    match (lower, upper) {
+        (Some(lower), Some(upper)) => {
+            lower.len() >= PREFIX_SIZE
+                && upper.len() >= PREFIX_SIZE
+                && lower[..PREFIX_SIZE] == upper[..PREFIX_SIZE]
+        },
+        _ => false,
+    }
+}
+
+/// read_options returns the options of the iterator.
+/// The iterator within the prefix stops at the end of the extracted prefix of its start, and the prefix bloom filters
+/// skip the files without the prefix. The other iterators read the keys in order regardless of the prefix.
+fn read_options(within_prefix: bool) -> rocksdb::ReadOptions {
+    let mut read_options = rocksdb::ReadOptions::default();
+    if within_prefix {
+        read_options.set_prefix_same_as_start(true);
+    } else {
+        read_options.set_total_order_seek(true);
+    }
+    read_options
+}
+
+/// iterator returns the iterator of the column family of the prefix.
+pub fn iterator<'a>(
+    conn: &'a rocksdb::DB,
+    prefix: &[u8],
+    mode: rocksdb::IteratorMode,
+) -> rocksdb::DBIterator<'a> {
+    iterator_opt(conn, prefix, mode, false)
+}
+
+/// iterator_opt returns the iterator of the column family of the prefix.
+/// When `within_prefix` is true, it only reads the keys with the store prefix of the start of the mode,
+/// so it must only be set when is_within_store_prefix is true for the range.
+pub fn iterator_opt<'a>(
+    conn: &'a rocksdb::DB,
+    prefix: &[u8],
+    mode: rocksdb::IteratorMode,
+    within_prefix: bool,
+) -> rocksdb::DBIterator<'a> {
+    match handle(conn, prefix) {
+        Some(cf) => conn.iterator_cf_opt(cf, read_options(within_prefix), mode),
+        None => conn.iterator_opt(mode, read_options(within_prefix)),
+    }
+}
+
+/// Router moves each operation of the batch to the column family of its key.
+struct Router<'a> {
+    conn: &'a rocksdb::DB,
+    batch: rocksdb::WriteBatch,
+}
+
+impl<'a> WriteBatchIterator for Router<'a> {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        match handle(self.conn, &key) {
+            Some(cf) => self.batch.put_cf(cf, key, value),
+            None => self.batch.put(key, value),
+        }
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        match handle(self.conn, &key) {
+            Some(cf) => self.batch.delete_cf(cf, key),
+            None => self.batch.delete(key),
+        }
+    }
+}
+
+/// route returns the batch writing each key to its column family.
+/// The batch must only contain the operations of the default column family.
+pub fn route(conn: &rocksdb::DB, batch: rocksdb::WriteBatch) -> rocksdb::WriteBatch {
+    if COLUMN_FAMILIES
+        .iter()
+        .all(|(name, _)| conn.cf_handle(name).is_none())
+    {
+        return batch;
+    }
+    let mut router = Router {
+        conn,
+        batch: rocksdb::WriteBatch::default(),
+    };
+    batch.iterate(&mut router);
+    router.batch
+}
+
+pub fn write(conn: &rocksdb::DB, batch: rocksdb::WriteBatch) -> Result<(), rocksdb::Error> {
+    conn.write(route(conn, batch))
+}
+
+/// write_with_ranges deletes the ranges from start to end, excluding end, and then writes the batch atomically.
+/// The operations of the batch are applied after the range deletions, so a key written in the batch is kept.
+pub fn write_with_ranges(
+    conn: &rocksdb::DB,
+    ranges: &[(Vec<u8>, Vec<u8>)],
+    batch: rocksdb::WriteBatch,
+) -> Result<(), rocksdb::Error> {
+    write_with_ranges_opt(conn, ranges, batch, &rocksdb::WriteOptions::default())
+}
+
+/// write_with_ranges_opt writes like write_with_ranges with the write options.
+pub fn write_with_ranges_opt(
+    conn: &rocksdb::DB,
+    ranges: &[(Vec<u8>, Vec<u8>)],
+    batch: rocksdb::WriteBatch,
+    write_options: &rocksdb::WriteOptions,
+) -> Result<(), rocksdb::Error> {
+    if ranges.is_empty() {
+        return write_opt(conn, batch, write_options);
+    }
+    let mut router = Router {
+        conn,
+        batch: rocksdb::WriteBatch::default(),
+    };
+    for (start, end) in ranges {
+        match handle(conn, start) {
+            Some(cf) => router.batch.delete_range_cf(cf, start, end),
+            None => router.batch.delete_range(start, end),
+        }
+    }
+    batch.iterate(&mut router);
+    conn.write_opt(router.batch, write_options)
+}
+
+pub fn write_opt(
+    conn: &rocksdb::DB,
+    batch: rocksdb::WriteBatch,
+    write_options: &rocksdb::WriteOptions,
+) -> Result<(), rocksdb::Error> {
+    conn.write_opt(route(conn, batch), write_options)
+}
+
+/// flush flushes the memtables of the default and the other column families to the SST files.
+pub fn flush(conn: &rocksdb::DB) -> Result<(), rocksdb::Error> {
+    conn.flush()?;
+    for (name, _) in COLUMN_FAMILIES {
+        if let Some(cf) = conn.cf_handle(name) {
+            conn.flush_cf(cf)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Snapshot is the snapshot of the database reading the keys from their column families.
+pub struct Snapshot<'a> {
+    conn: &'a rocksdb::DB,
+    inner: rocksdb::Snapshot<'a>,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn new(conn: &'a rocksdb::DB) -> Self {
+        Self {
+            conn,
+            inner: conn.snapshot(),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        match handle(self.conn, key) {
+            Some(cf) => self.inner.get_cf(cf, key),
+            None => self.inner.get(key),
+        }
+    }
+
+    /// exists returns true if the key is in the snapshot without copying its value.
+    pub fn exists(&self, key: &[u8]) -> Result<bool, rocksdb::Error> {
+        let value = match handle(self.conn, key) {
+            Some(cf) => self.inner.get_pinned_cf(cf, key)?,
+            None => self.inner.get_pinned(key)?,
+        };
+        Ok(value.is_some())
+    }
+
+    /// iterator returns the iterator of the column family of the prefix.
+    pub fn iterator(&self, prefix: &[u8], mode: rocksdb::IteratorMode) -> rocksdb::DBIterator<'_> {
+        self.iterator_opt(prefix, mode, false)
+    }
+
+    /// iterator_opt is the same as the iterator_opt of the database, reading the snapshot.
+    pub fn iterator_opt(
+        &self,
+        prefix: &[u8],
+        mode: rocksdb::IteratorMode,
+        within_prefix: bool,
+    ) -> rocksdb::DBIterator<'_> {
+        match handle(self.conn, prefix) {
+            Some(cf) => self
+                .inner
+                .iterator_cf_opt(cf, read_options(within_prefix), mode),
+            None => self.inner.iterator_opt(mode, read_options(within_prefix)),
+        }
+    }
+}
+
+/// options returns the options of the column family.
+/// The SMT nodes are only read by their hash, so the bloom filter avoids reading the blocks for the missing nodes.
+/// The state is iterated by the store prefix, so its bloom filters also hold the store prefixes of the keys.
+/// The diffs are rarely read after they are written, so they are compressed with zstd unless the compression is set.
+fn options(name: &str, opts: &DbOptions) -> Result<rocksdb::Options, rocksdb::Error> {
+    let mut tuning = opts.tuning();
+    if name == DIFF && tuning.compression.is_none() {
+        tuning.compression = Some(Compression::Zstd);
+    }
+    let mut option = tuning.rocksdb_options()?;
+    if name == SMT || name == STATE {
+        option.set_block_based_table_factory(&tuning.block_options(true)?);
+    }
+    if name == STATE {
+        option.set_prefix_extractor(rocksdb::SliceTransform::create_fixed_prefix(
+            STATE_PREFIX_LENGTH,
+        ));
+        option.set_memtable_prefix_bloom_ratio(MEMTABLE_PREFIX_BLOOM_RATIO);
+    }
+
+    Ok(option)
+}
+
+/// open opens the state_db with the column families.
+/// The column families are created and the keys in the prefix-based layout are moved to them if missing.
+/// A readonly database is opened with the column families it has, so that the prefix-based layout can still be read.
+pub fn open(
+    option: &rocksdb::Options,
+    path: &Path,
+    opts: &DbOptions,
+) -> Result<rocksdb::DB, rocksdb::Error> {
+    if opts.is_readonly() {
+        let existing = rocksdb::DB::list_cf(option, path).unwrap_or_default();
+        let names = COLUMN_FAMILIES
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| existing.iter().any(|existing| existing == name));
+        return rocksdb::DB::open_cf_for_read_only(option, path, names, false);
+    }
+    let mut option = option.clone();
+    option.create_missing_column_families(true);
+    let mut descriptors = Vec::with_capacity(COLUMN_FAMILIES.len());
+    for (name, _) in COLUMN_FAMILIES {
+        descriptors.push(rocksdb::ColumnFamilyDescriptor::new(
+            name,
+            options(name, opts)?,
+        ));
+    }
+    let conn = rocksdb::DB::open_cf_descriptors(&option, path, descriptors)?;
+    migrate(&conn)?;
+
+    Ok(conn)
+}
+
+/// open_as_secondary opens the database at `primary` as a secondary instance, which keeps its own logs at `secondary`.
+/// The secondary only opens the column families which already exist in the primary.
+pub fn open_as_secondary(
+    option: &rocksdb::Options,
+    primary: &Path,
+    secondary: &Path,
+) -> Result<rocksdb::DB, rocksdb::Error> {
+    let mut option = option.clone();
+    // the secondary must keep the files of the primary opened, as they can be deleted by the primary
+    option.set_max_open_files(-1);
+    let existing = rocksdb::DB::list_cf(&option, primary).unwrap_or_default();
+    let names = COLUMN_FAMILIES
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| existing.iter().any(|existing| existing == name));
+    rocksdb::DB::open_cf_as_secondary(&option, primary, secondary, names)
+}
+
+/// migrate moves the keys of the column families from the default column family and returns the number of the moved keys.
+/// Each write moves the keys atomically, so that the migration continues from where it stopped when it is interrupted.
+pub fn migrate(conn: &rocksdb::DB) -> Result<u64, rocksdb::Error> {
+    let mut moved = 0;
+    for (name, prefix) in COLUMN_FAMILIES {
+        let cf = match conn.cf_handle(name) {
+            Some(cf) => cf,
+            None => continue,
+        };
+        let mut batch = rocksdb::WriteBatch::default();
+        let iter = conn.iterator(rocksdb::IteratorMode::From(
+            prefix,
+            rocksdb::Direction::Forward,
+        ));
+        for key_val in iter {
+            let (key, value) = key_val?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            batch.put_cf(cf, &key, value);
+            batch.delete(key);
+            moved += 1;
+            if batch.len() >= MIGRATION_BATCH_SIZE * 2 {
+                conn.write(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            conn.write(batch)?;
+        }
+    }
+
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::database::types::TuningOptions;
+    use crate::types::KeyLength;
+
+    fn db_options(readonly: bool) -> DbOptions {
+        DbOptions::new(readonly, KeyLength(32))
+    }
+
+    #[test]
+    fn test_migrate() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        {
+            let conn = rocksdb::DB::open_default(temp_dir.path()).unwrap();
+            conn.put([Prefix::STATE, &[1]].concat(), [2]).unwrap();
+            conn.put([Prefix::SMT, &[3]].concat(), [4]).unwrap();
+            conn.put([Prefix::DIFF, &[5]].concat(), [6]).unwrap();
+            conn.put(Prefix::CURRENT_STATE, [7]).unwrap();
+        }
+
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+        assert_eq!(migrate(&conn).unwrap(), 0);
+        for (name, prefix) in COLUMN_FAMILIES {
+            let cf = conn.cf_handle(name).unwrap();
+            assert!(conn.get([prefix, &[1]].concat()).unwrap().is_none());
+            assert_eq!(
+                conn.iterator_cf(cf, rocksdb::IteratorMode::Start).count(),
+                1
+            );
+        }
+        assert_eq!(
+            get(&conn, &[Prefix::STATE, &[1]].concat()).unwrap(),
+            Some(vec![2])
+        );
+        assert_eq!(
+            get(&conn, &[Prefix::SMT, &[3]].concat()).unwrap(),
+            Some(vec![4])
+        );
+        assert_eq!(
+            get(&conn, &[Prefix::DIFF, &[5]].concat()).unwrap(),
+            Some(vec![6])
+        );
+        assert_eq!(get(&conn, Prefix::CURRENT_STATE).unwrap(), Some(vec![7]));
+    }
+
+    #[test]
+    fn test_route() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[1]].concat(), [2]);
+        batch.put([Prefix::SMT, &[3]].concat(), [4]);
+        batch.put(Prefix::CURRENT_STATE, [5]);
+        write(&conn, batch).unwrap();
+        let state_cf = conn.cf_handle(STATE).unwrap();
+        assert_eq!(
+            conn.get_cf(state_cf, [Prefix::STATE, &[1]].concat())
+                .unwrap(),
+            Some(vec![2])
+        );
+        assert_eq!(conn.get([Prefix::STATE, &[1]].concat()).unwrap(), None);
+        assert_eq!(conn.get(Prefix::CURRENT_STATE).unwrap(), Some(vec![5]));
+
+        let snapshot = Snapshot::new(&conn);
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.delete([Prefix::SMT, &[3]].concat());
+        write(&conn, batch).unwrap();
+        assert_eq!(get(&conn, &[Prefix::SMT, &[3]].concat()).unwrap(), None);
+        assert_eq!(
+            snapshot.get(&[Prefix::SMT, &[3]].concat()).unwrap(),
+            Some(vec![4])
+        );
+        assert!(!exists(&conn, &[Prefix::SMT, &[3]].concat()).unwrap());
+        assert!(snapshot.exists(&[Prefix::SMT, &[3]].concat()).unwrap());
+        assert!(exists(&conn, &[Prefix::STATE, &[1]].concat()).unwrap());
+        assert!(exists(&conn, Prefix::CURRENT_STATE).unwrap());
+        let mode = rocksdb::IteratorMode::Start;
+        assert_eq!(snapshot.iterator(Prefix::STATE, mode).count(), 1);
+    }
+
+    #[test]
+    fn test_write_with_ranges() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        for i in 0..5u8 {
+            batch.put([Prefix::STATE, &[i]].concat(), [i]);
+        }
+        write(&conn, batch).unwrap();
+
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[2]].concat(), [20]);
+        let ranges = vec![(
+            [Prefix::STATE, &[1]].concat(),
+            [Prefix::STATE, &[4]].concat(),
+        )];
+        write_with_ranges(&conn, &ranges, batch).unwrap();
+
+        let values: Vec<_> = (0..5u8)
+            .map(|i| get(&conn, &[Prefix::STATE, &[i]].concat()).unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            vec![Some(vec![0]), None, Some(vec![20]), None, Some(vec![4])]
+        );
+    }
+
+    #[test]
+    fn test_multi_get() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[1]].concat(), [2]);
+        batch.put([Prefix::STATE, &[3]].concat(), [4]);
+        batch.put(Prefix::CURRENT_STATE, [5]);
+        write(&conn, batch).unwrap();
+
+        let keys = vec![
+            [Prefix::STATE, &[3]].concat(),
+            [Prefix::STATE, &[2]].concat(),
+            [Prefix::STATE, &[1]].concat(),
+        ];
+        let values: Vec<_> = multi_get(&conn, &keys)
+            .into_iter()
+            .map(|v| v.unwrap())
+            .collect();
+        assert_eq!(values, vec![Some(vec![4]), None, Some(vec![2])]);
+
+        let keys = vec![
+            Prefix::CURRENT_STATE.to_vec(),
+            [Prefix::STATE, &[1]].concat(),
+        ];
+        let values: Vec<_> = multi_get(&conn, &keys)
+            .into_iter()
+            .map(|v| v.unwrap())
+            .collect();
+        assert_eq!(values, vec![Some(vec![5]), Some(vec![2])]);
+    }
+
+    #[test]
+    fn test_compact_range() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+        set_auto_compaction(&conn, false).unwrap();
+
+        let mut batch = rocksdb::WriteBatch::default();
+        for i in 0..100u8 {
+            batch.put([Prefix::SMT, &[i]].concat(), [i]);
+            batch.put([Prefix::STATE, &[i]].concat(), [i]);
+        }
+        write(&conn, batch).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        for i in 0..100u8 {
+            batch.delete([Prefix::SMT, &[i]].concat());
+        }
+        write(&conn, batch).unwrap();
+        conn.flush_cf(conn.cf_handle(SMT).unwrap()).unwrap();
+
+        compact_smt(&conn);
+        let smt_cf = conn.cf_handle(SMT).unwrap();
+        let live_files = conn.live_files().unwrap();
+        assert!(live_files.iter().all(|file| file.column_family_name != SMT));
+        assert_eq!(
+            conn.iterator_cf(smt_cf, rocksdb::IteratorMode::Start)
+                .count(),
+            0
+        );
+
+        compact_range(&conn, Prefix::STATE, Some(&[10]), Some(&[20]));
+        assert_eq!(
+            get(&conn, &[Prefix::STATE, &[15]].concat()).unwrap(),
+            Some(vec![15])
+        );
+        set_auto_compaction(&conn, true).unwrap();
+    }
+
+    #[test]
+    fn test_open_readonly_without_column_families() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        {
+            let conn = rocksdb::DB::open_default(temp_dir.path()).unwrap();
+            conn.put([Prefix::STATE, &[1]].concat(), [2]).unwrap();
+        }
+
+        let option = rocksdb::Options::default();
+        let conn = open(&option, temp_dir.path(), &db_options(true)).unwrap();
+        assert!(conn.cf_handle(STATE).is_none());
+        assert_eq!(
+            get(&conn, &[Prefix::STATE, &[1]].concat()).unwrap(),
+            Some(vec![2])
+        );
+    }
+
+    #[test]
+    fn test_open_as_secondary() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let primary_path = temp_dir.path().join("primary");
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let primary = open(&option, &primary_path, &db_options(false)).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[1]].concat(), [2]);
+        write(&primary, batch).unwrap();
+
+        let secondary_path = temp_dir.path().join("secondary");
+        let secondary = open_as_secondary(&option, &primary_path, &secondary_path).unwrap();
+        assert!(secondary.cf_handle(STATE).is_some());
+        assert_eq!(
+            get(&secondary, &[Prefix::STATE, &[1]].concat()).unwrap(),
+            Some(vec![2])
+        );
+
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[3]].concat(), [4]);
+        write(&primary, batch).unwrap();
+        let before = secondary.latest_sequence_number();
+        secondary.try_catch_up_with_primary().unwrap();
+        assert!(secondary.latest_sequence_number() > before);
+        assert_eq!(
+            get(&secondary, &[Prefix::STATE, &[3]].concat()).unwrap(),
+            Some(vec![4])
+        );
+    }
+
+    #[test]
+    fn test_iterator_within_store_prefix() {
+        let store: &[u8] = &[0, 0, 0, 1, 0, 0];
+        assert!(is_within_store_prefix(
+            Some(store),
+            Some(&[0, 0, 0, 1, 0, 0, 255])
+        ));
+        assert!(!is_within_store_prefix(
+            Some(store),
+            Some(&[0, 0, 0, 2, 0, 0])
+        ));
+        assert!(!is_within_store_prefix(
+            Some(&store[..4]),
+            Some(&store[..4])
+        ));
+        assert!(!is_within_store_prefix(None, Some(store)));
+
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let option = TuningOptions::default().rocksdb_options().unwrap();
+        let conn = open(&option, temp_dir.path(), &db_options(false)).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        for index in 1..4u8 {
+            for key in 0..3u8 {
+                batch.put(
+                    [Prefix::STATE, &[0, 0, 0, index, 0, 0, key]].concat(),
+                    [key],
+                );
+            }
+        }
+        write(&conn, batch).unwrap();
+        flush(&conn).unwrap();
+
+        let start = [Prefix::STATE, &[0, 0, 0, 2, 0, 0]].concat();
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+        assert_eq!(iterator_opt(&conn, Prefix::STATE, mode, true).count(), 3);
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+        assert_eq!(iterator_opt(&conn, Prefix::STATE, mode, false).count(), 6);
+        let end = [Prefix::STATE, &[0, 0, 0, 2, 0, 0, 255]].concat();
+        let mode = rocksdb::IteratorMode::From(&end, rocksdb::Direction::Reverse);
+        let keys: Vec<Box<[u8]>> = iterator_opt(&conn, Prefix::STATE, mode, true)
+            .map(|key_val| key_val.unwrap().0)
+            .collect();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.iter().all(|key| key.starts_with(&start)));
+        let snapshot = Snapshot::new(&conn);
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+        assert_eq!(snapshot.iterator_opt(Prefix::STATE, mode, true).count(), 3);
+    }
+
+    #[test]
+    fn test_open_in_memory() {
+        let temp_dir = TempDir::new("test_column_family").unwrap();
+        let path = temp_dir.path().join("in_memory");
+        let tuning = TuningOptions {
+            in_memory: true,
+            ..Default::default()
+        };
+        let option = tuning.rocksdb_options().unwrap();
+        let conn = open(&option, &path, &db_options(false)).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put([Prefix::STATE, &[1]].concat(), [2]);
+        write(&conn, batch).unwrap();
+        assert_eq!(
+            get(&conn, &[Prefix::STATE, &[1]].concat()).unwrap(),
+            Some(vec![2])
+        );
+        assert!(!path.exists());
+
+        // the other database opened at the same path does not share the data
+        let other = open(
+            &tuning.rocksdb_options().unwrap(),
+            &path,
+            &db_options(false),
+        )
+        .unwrap();
+        assert_eq!(get(&other, &[Prefix::STATE, &[1]].concat()).unwrap(), None);
+    }
+}
@@ -1,15 +1,20 @@
 /// db is the interface for Database binding using rocksDB.
+use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
 
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 
 use crate::batch;
-use crate::database::options::IterationOption;
-use crate::database::traits::{JsNewWithBoxRef, Unwrap};
-use crate::database::types::JsBoxRef;
+use crate::database::column_family;
+use crate::database::options::{IterationOption, WriteOption};
+use crate::database::recovery::CallbackGuard;
+use crate::database::traits::{JsNewWithBoxRef, OptionsWithContext, Unwrap};
+use crate::database::transaction;
+use crate::database::types::{DbOptions, JsBoxRef, Kind};
 use crate::database::utils;
 use crate::database::DB;
+use crate::types::RecoverLock;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error {
@@ -30,7 +35,7 @@ impl Database {
             let this = ctx.undefined();
             let args: Vec<Handle<JsValue>> = match result {
                 Ok(_) => vec![ctx.null().upcast()],
-                Err(err) => vec![ctx.error(&err)?.upcast()],
+                Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
             };
 
             callback.call(&mut ctx, this, args)?;
@@ -53,13 +58,14 @@ impl Database {
 
         let conn = db.arc_clone();
         db.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let mut batch = rocksdb::WriteBatch::default();
             let conn_iter = conn.unwrap().iterator(rocksdb::IteratorMode::Start);
             for key_val in conn_iter {
                 batch.delete(&(key_val.unwrap().0));
             }
             let result = conn.unwrap().write(batch);
-            Database::send_over_channel(channel, callback, result);
+            Database::send_over_channel(channel, callback.take(), result);
         })
         .or_else(|err| ctx.throw_error(err.to_string()))?;
 
@@ -68,11 +74,14 @@ impl Database {
 
     /// js_close is handler for JS ffi.
     /// js "this" - DB.
+    /// - @params(0) - optional callback called when the database is released.
+    /// - @callback(0) - Error.
     pub fn js_close(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = utils::function_argument_opt(&mut ctx, 0);
         ctx.this()
             .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?
             .borrow_mut()
-            .close()
+            .close(callback)
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
@@ -98,7 +107,56 @@ impl Database {
         Ok(ctx.undefined())
     }
 
+    /// js_get_many is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - keys to get from db.
+    /// - @params(1) - callback to return the fetched values.
+    /// - @callback(0) - Error
+    /// - @callback(1) - ([u8] | undefined)[]. Values associated with the keys in the same order.
+    pub fn js_get_many(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let keys = utils::js_array_to_keys(&mut ctx, 0)?;
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.get_many(keys, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_stream is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - key to get from db.
+    /// - @params(1) - size of the chunks.
+    /// - @params(2) - callback to be called with each chunk. It stops the stream by returning false.
+    /// - @params(3) - callback to be called when completing the stream.
+    /// - @callback2(0) - [u8]. Chunk of the value.
+    /// - @callback(0) - Error
+    /// - @callback(1) - number. Size of the value.
+    pub fn js_get_stream(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let chunk_size = ctx.argument::<JsNumber>(1)?.value(&mut ctx);
+        if !chunk_size.is_finite() || chunk_size < 1.0 {
+            return ctx.throw_range_error("Chunk size must be a positive number");
+        }
+        let on_chunk = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.get_stream(key, chunk_size as usize, on_chunk, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
     /// js_exists is handler for JS ffi.
+    /// The value of the key is not read into JS, so it should be used when only the existence is needed.
     /// js "this" - DB.
     /// - @params(0) - key to check existence from db.
     /// - @params(1) - callback to return the fetched value.
@@ -167,13 +225,16 @@ impl Database {
     /// js_write is handler for JS ffi.
     /// js "this" - DB.
     /// - @params(0) - Batch
-    /// - @params(1) - callback to return the fetched value.
-    /// - @callback(0) - Error
+    /// - @params(1) - Options for write. {sync: bool, disableWAL: bool}.
+    /// - @params(2) - callback to return the fetched value.
+    /// - @callback(0) - Error. It has the code BATCH_CONFLICT if a condition of the batch is not satisfied.
     pub fn js_write(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let batch = ctx
             .argument::<batch::SendableWriteBatch>(0)?
             .downcast_or_throw::<batch::SendableWriteBatch, _>(&mut ctx)?;
-        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let option_inputs = ctx.argument::<JsObject>(1)?;
+        let write_option = WriteOption::new(&mut ctx, option_inputs);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
 
         let db = ctx
             .this()
@@ -183,12 +244,29 @@ impl Database {
         let batch = Arc::clone(&batch.borrow());
         let conn = db.arc_clone();
         db.send(move |channel| {
-            let write_batch = rocksdb::WriteBatch::default();
-            let inner_batch = batch.lock().unwrap();
-            let mut write_batch = batch::WriteBatch { batch: write_batch };
-            inner_batch.batch.iterate(&mut write_batch);
-            let result = conn.unwrap().write(write_batch.batch);
-            Database::send_over_channel(channel, callback, result);
+            let callback = CallbackGuard::new(callback, channel);
+            let inner_batch = batch.lock_or_recover();
+            // the conditions are checked on the database thread, so no other batch is written in between
+            let result = inner_batch
+                .check_conditions(|key| column_family::get(conn.unwrap(), key))
+                .and_then(|_| {
+                    let mut write_batch = batch::WriteBatch::new(rocksdb::WriteBatch::default());
+                    inner_batch.batch.iterate(&mut write_batch);
+                    conn.unwrap()
+                        .write_opt(write_batch.batch, &write_option.into())
+                        .map_err(batch::BatchWriteError::from)
+                });
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(_) => vec![ctx.null().upcast()],
+                    Err(err) => vec![batch::write_error_to_js_error(&mut ctx, err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
         })
         .or_else(|err| ctx.throw_error(err.to_string()))?;
 
@@ -217,6 +295,10 @@ impl Database {
         let callback_on_data = Arc::new(Mutex::new(callback_on_data));
         let conn = db.arc_clone();
         db.send(move |channel| {
+            let callback_done = CallbackGuard::new(callback_done, channel);
+            let _span =
+                tracing::info_span!("iterate", limit = options.limit, reverse = options.reverse)
+                    .entered();
             let iter =
                 conn.unwrap()
                     .iterator(utils::get_iteration_mode(&options, &mut vec![], false));
@@ -232,12 +314,12 @@ impl Database {
                 let callback_on_data = Arc::clone(&callback_on_data);
                 channel.send(move |mut ctx| {
                     let obj = ctx.empty_object();
-                    let key_res =
-                        JsBuffer::external(&mut ctx, key_val.as_ref().unwrap().0.clone());
-                    let val_res = JsBuffer::external(&mut ctx, key_val.unwrap().1);
+                    let (key, value) = key_val.unwrap();
+                    let key_res = JsBuffer::external(&mut ctx, key);
+                    let val_res = JsBuffer::external(&mut ctx, value);
                     obj.set(&mut ctx, "key", key_res)?;
                     obj.set(&mut ctx, "value", val_res)?;
-                    let callback = callback_on_data.lock().unwrap().to_inner(&mut ctx);
+                    let callback = callback_on_data.lock_or_recover().to_inner(&mut ctx);
                     let this = ctx.undefined();
                     let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), obj.upcast()];
                     callback.call(&mut ctx, this, args)?;
@@ -258,6 +340,367 @@ impl Database {
         Ok(ctx.undefined())
     }
 
+    /// js_iterate_page is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - Options for iteration. {limit: u32, reverse: bool, gte: &[u8], lte: &[u8]}.
+    /// - @params(1) - Last key of the previous page, or null to read the first page.
+    /// - @params(2) - Maximum number of pairs in the page.
+    /// - @params(3) - callback to return the page.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { key: &[u8], value: &[u8]}[].
+    pub fn js_iterate_page(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let option_inputs = ctx.argument::<JsObject>(0)?;
+        let options = IterationOption::new(&mut ctx, option_inputs);
+        let cursor = utils::bytes_argument_opt(&mut ctx, 1);
+        let size = ctx.argument::<JsNumber>(2)?.value(&mut ctx) as usize;
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let conn = db.arc_clone();
+        db.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = Database::iterate_page(conn.unwrap(), &options, cursor.as_deref(), size);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(pairs) => {
+                        let arr = utils::pairs_into_js_array(&mut ctx, pairs)?;
+                        vec![ctx.null().upcast(), arr.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_transaction_get is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - Transaction.
+    /// - @params(1) - key to get.
+    /// - @params(2) - callback to return the fetched value.
+    /// - @callback(0) - Error. If data is not found or deleted in the transaction, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - [u8]. Value associated with the key.
+    pub fn js_transaction_get(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let transaction = ctx
+            .argument::<transaction::SendableTransaction>(0)?
+            .downcast_or_throw::<transaction::SendableTransaction, _>(&mut ctx)?;
+        let key = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let transaction = Arc::clone(&transaction.borrow());
+        let conn = db.arc_clone();
+        db.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = transaction.lock_or_recover().get(conn.unwrap(), &key);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(Some(val)) => {
+                        let buffer = JsBuffer::external(&mut ctx, val);
+                        vec![ctx.null().upcast(), buffer.upcast()]
+                    },
+                    Ok(None) => vec![utils::not_found_error(&mut ctx)?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_transaction_iterate is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - Transaction.
+    /// - @params(1) - Options for iteration. {limit: u32, reverse: bool, gte: &[u8], lte: &[u8]}.
+    /// - @params(2) - callback to return the pairs.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { key: &[u8], value: &[u8]}[].
+    pub fn js_transaction_iterate(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let transaction = ctx
+            .argument::<transaction::SendableTransaction>(0)?
+            .downcast_or_throw::<transaction::SendableTransaction, _>(&mut ctx)?;
+        let option_inputs = ctx.argument::<JsObject>(1)?;
+        let options = IterationOption::new(&mut ctx, option_inputs);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let transaction = Arc::clone(&transaction.borrow());
+        let conn = db.arc_clone();
+        db.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = transaction.lock_or_recover().range(conn.unwrap(), &options);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(pairs) => {
+                        let arr = utils::pairs_into_js_array(&mut ctx, pairs)?;
+                        vec![ctx.null().upcast(), arr.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_transaction_commit is handler for JS ffi.
+    /// The writes of the transaction are written atomically, and the transaction becomes empty.
+    /// js "this" - DB.
+    /// - @params(0) - Transaction.
+    /// - @params(1) - Options for the write. {sync: bool, disableWAL: bool}.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_transaction_commit(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let transaction = ctx
+            .argument::<transaction::SendableTransaction>(0)?
+            .downcast_or_throw::<transaction::SendableTransaction, _>(&mut ctx)?;
+        let option_inputs = ctx.argument::<JsObject>(1)?;
+        let write_option = WriteOption::new(&mut ctx, option_inputs);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let transaction = Arc::clone(&transaction.borrow());
+        let conn = db.arc_clone();
+        db.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let batch = transaction.lock_or_recover().take_batch();
+            let result = conn.unwrap().write_opt(batch, &write_option.into());
+            Database::send_over_channel(channel, callback.take(), result);
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_create_backup is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - directory of the backups.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_create_backup(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let dir = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.create_backup(dir, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_compact_range is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - start key of the range, or null to compact from the first key.
+    /// - @params(1) - end key of the range, or null to compact to the last key.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_compact_range(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let start = utils::bytes_argument_opt(&mut ctx, 0);
+        let end = utils::bytes_argument_opt(&mut ctx, 1);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.compact_range(start, end, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_set_auto_compaction is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - true to enable the automatic compaction.
+    pub fn js_set_auto_compaction(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let enabled = ctx.argument::<JsBoolean>(0)?.value(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.set_auto_compaction(enabled)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_flush is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_flush(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.flush(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_sync_wal is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_sync_wal(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.sync_wal(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_properties is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { estimatedKeys, totalSstSize, memtableUsage, pendingCompactionBytes, columnFamilies }.
+    pub fn js_properties(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.properties(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_write_stall is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { condition: 'normal' | 'delayed' | 'stopped', delayedWriteRate }.
+    pub fn js_write_stall(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.write_stall(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_approximate_size is handler for JS ffi.
+    /// js "this" - DB.
+    /// - @params(0) - start key of the range, or null to estimate from the first key.
+    /// - @params(1) - end key of the range, or null to estimate to the last key.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { size, estimatedKeys }.
+    pub fn js_approximate_size(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let start = utils::bytes_argument_opt(&mut ctx, 0);
+        let end = utils::bytes_argument_opt(&mut ctx, 1);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.approximate_size(start, end, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_new_secondary is handler for JS ffi.
+    /// - @params(0) - path of the primary database.
+    /// - @params(1) - path to keep the logs of the secondary instance.
+    /// - @params(2) - options of the database.
+    /// - @returns - Database opened as a secondary instance of the primary.
+    pub fn js_new_secondary(mut ctx: FunctionContext) -> JsResult<SharedDatabase> {
+        let primary = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let secondary = ctx.argument::<JsString>(1)?.value(&mut ctx);
+        let options = ctx.argument_opt(2);
+        let db_opts = DbOptions::new_with_context(&mut ctx, options)?;
+        let db = Database::open_as_secondary(&mut ctx, primary, secondary, db_opts, Kind::Normal)
+            .or_else(|err| utils::throw_open_error(&mut ctx, err))?;
+
+        Ok(ctx.boxed(RefCell::new(db)))
+    }
+
+    /// js_try_catch_up_with_primary is handler for JS ffi.
+    /// js "this" - DB opened as a secondary instance.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { changed: bool, sequenceNumber: u64 }.
+    pub fn js_try_catch_up_with_primary(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        db.catch_up_with_primary(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
     /// js_checkpoint is handler for JS ffi.
     /// js "this" - DB.
     /// - @params(0) - path to create the checkpoint.
@@ -1,20 +1,59 @@
 /// db_base provides common functionality for Database.
-use std::sync::{mpsc, Arc};
+use std::mem;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use neon::context::Context;
 use neon::event::Channel;
 use neon::handle::{Handle, Root};
-use neon::types::{Finalize, JsBuffer, JsFunction, JsValue};
+use neon::object::Object;
+use neon::types::{Finalize, JsBoolean, JsBuffer, JsFunction, JsValue};
 use rocksdb::checkpoint::Checkpoint;
-
+use thiserror::Error;
+
+use crate::database::backup;
+use crate::database::column_family;
+use crate::database::lock::{self, OpenError};
+use crate::database::options::IterationOption;
+use crate::database::properties;
+use crate::database::recovery::{self, CallbackGuard};
 use crate::database::traits::{NewDBWithContext, Unwrap};
 use crate::database::types::{ArcOptionDB, DbMessage, DbOptions, Kind};
+use crate::database::utils;
+use crate::error_code::{self, ErrorCode};
+use crate::task_pool::{self, SerialQueue};
+use crate::types::{KVPair, RecoverLock};
+
+/// CLOSE_TIMEOUT is the time to wait on close for the other references to the database,
+/// such as the running compactions and the open snapshots, to be released.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// CLOSE_WAIT_INTERVAL is the interval to check whether the other references are released.
+const CLOSE_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Error, Debug)]
+pub enum CloseError {
+    #[error(transparent)]
+    Database(#[from] rocksdb::Error),
+    #[error("Database is still used by `{0}` open resources")]
+    InUse(usize),
+}
+
+impl ErrorCode for CloseError {
+    fn code(&self) -> &'static str {
+        match self {
+            CloseError::Database(err) => err.code(),
+            CloseError::InUse(_) => error_code::BUSY,
+        }
+    }
+}
 
 pub struct DB {
-    tx: mpsc::Sender<DbMessage>,
+    queue: SerialQueue<DbMessage>,
     db_kind: Kind,
     db: ArcOptionDB,
+    commit_lock: Mutex<()>,
 }
 
 impl Unwrap for ArcOptionDB {
@@ -29,65 +68,121 @@ impl NewDBWithContext for DB {
         path: String,
         opts: DbOptions,
         db_kind: Kind,
-    ) -> Result<Self, rocksdb::Error>
+    ) -> Result<Self, OpenError>
     where
         C: Context<'a>,
     {
-        // Channel for sending callbacks to execute on the sqlite connection thread
-        let (tx, rx) = mpsc::channel::<DbMessage>();
-
-        let channel = ctx.channel();
+        let option = opts.tuning().rocksdb_options()?;
 
-        let mut option = rocksdb::Options::default();
-        option.create_if_missing(true);
-
-        let db: rocksdb::DB = if opts.is_readonly() {
-            rocksdb::DB::open_for_read_only(&option, path, false)?
+        let db = if db_kind == Kind::State {
+            lock::open_with_column_families(&option, path, &opts)?
         } else {
-            rocksdb::DB::open(&option, path)?
+            lock::open(&option, path, &opts)?
         };
 
-        thread::spawn(move || {
-            while let Ok(message) = rx.recv() {
-                match message {
-                    DbMessage::Callback(f) => {
-                        f(&channel);
-                    },
-                    DbMessage::Close => return,
-                }
-            }
-        });
-
-        Ok(Self::new(db, tx, db_kind))
+        Ok(Self::spawn(ctx, db, db_kind))
     }
 }
 
 impl Finalize for DB {}
 impl DB {
+    /// spawn creates the queue which executes the callbacks sent to the database in order on the shared pool.
+    fn spawn<'a, C: Context<'a>>(ctx: &mut C, db: rocksdb::DB, db_kind: Kind) -> Self {
+        let channel = ctx.channel();
+
+        let queue = SerialQueue::new(move |message| match message {
+            DbMessage::Callback(f) => {
+                // The panic is reported by the callback guard of the request
+                let _ = recovery::run_request("database", || f(&channel));
+                true
+            },
+            DbMessage::Close => false,
+        });
+
+        Self::new(db, queue, db_kind)
+    }
+
+    /// open_as_secondary opens the database at `primary` as a read only secondary instance.
+    /// The secondary follows the primary by try_catch_up_with_primary, while the primary keeps running in another process.
+    pub fn open_as_secondary<'a, C: Context<'a>>(
+        ctx: &mut C,
+        primary: String,
+        secondary: String,
+        opts: DbOptions,
+        db_kind: Kind,
+    ) -> Result<Self, OpenError> {
+        let option = opts.tuning().rocksdb_options()?;
+        let db =
+            column_family::open_as_secondary(&option, Path::new(&primary), Path::new(&secondary))?;
+
+        Ok(Self::spawn(ctx, db, db_kind))
+    }
+
     fn db(&self) -> &rocksdb::DB {
         self.db.unwrap()
     }
 
-    pub fn new(db: rocksdb::DB, tx: mpsc::Sender<DbMessage>, db_kind: Kind) -> Self {
+    pub fn new(db: rocksdb::DB, queue: SerialQueue<DbMessage>, db_kind: Kind) -> Self {
         Self {
-            tx,
+            queue,
             db_kind,
             db: Arc::new(Some(db)),
+            commit_lock: Mutex::new(()),
         }
     }
 
+    /// lock_commit returns the guard which serializes the commits to the database.
+    /// A commit reads the current state and writes the next one while holding it.
+    pub fn lock_commit(&self) -> MutexGuard<'_, ()> {
+        self.commit_lock.lock_or_recover()
+    }
+
     // Idiomatic rust would take an owned `self` to prevent use after close
     // However, it's not possible to prevent JavaScript from continuing to hold a closed database
-    pub fn close(&mut self) -> Result<(), mpsc::SendError<DbMessage>> {
-        self.db = Arc::new(None);
-        self.tx.send(DbMessage::Close)
+    /// close stops the queue after the callbacks already sent to it, flushing the memtables and releasing the database.
+    /// Once the callback is called, the lock is released and the same directory can be opened again.
+    pub fn close(
+        &mut self,
+        callback: Option<Root<JsFunction>>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = mem::replace(&mut self.db, Arc::new(None));
+        self.queue
+            .send(DbMessage::Callback(Box::new(move |channel| {
+                let result = release(conn, CLOSE_TIMEOUT);
+                let callback = match callback {
+                    Some(callback) => callback,
+                    None => return,
+                };
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(()) => vec![ctx.null().upcast()],
+                        Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })))?;
+        self.queue.send(DbMessage::Close)
+    }
+
+    /// is_closed returns true once close is called.
+    pub fn is_closed(&self) -> bool {
+        self.db.is_none()
     }
 
     pub fn send(
         &self,
         callback: impl FnOnce(&Channel) + Send + 'static,
     ) -> Result<(), mpsc::SendError<DbMessage>> {
-        self.tx.send(DbMessage::Callback(Box::new(callback)))
+        let message = DbMessage::Callback(Box::new(callback));
+        // The queue stops on close, so the callbacks sent after it would never be called
+        if self.is_closed() {
+            return Err(mpsc::SendError(message));
+        }
+        self.queue.send(message)
     }
 
     pub fn get_by_key(
@@ -95,8 +190,16 @@ impl DB {
         key: Vec<u8>,
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<DbMessage>> {
-        let key = self.db_kind.key(key);
-        let result = self.get(&key);
+        self.get_by_prefixed_key(&self.db_kind.key(key), callback)
+    }
+
+    /// get_by_prefixed_key calls the callback with the value of the key which already contains the prefix.
+    pub fn get_by_prefixed_key(
+        &self,
+        key: &[u8],
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let result = self.get(key);
         self.send(move |channel| {
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
@@ -106,8 +209,107 @@ impl DB {
                         let buffer = JsBuffer::external(&mut ctx, val);
                         vec![ctx.null().upcast(), buffer.upcast()]
                     },
-                    Ok(None) => vec![ctx.error("No data")?.upcast()],
-                    Err(err) => vec![ctx.error(&err)?.upcast()],
+                    Ok(None) => vec![utils::not_found_error(&mut ctx)?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// get_many calls the callback with the values of the keys, or None for the missing keys.
+    pub fn get_many(
+        &self,
+        keys: Vec<Vec<u8>>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let keys: Vec<Vec<u8>> = keys.into_iter().map(|key| self.db_kind.key(key)).collect();
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result: Result<Vec<Option<Vec<u8>>>, rocksdb::Error> =
+                column_family::multi_get(conn.unwrap(), &keys)
+                    .into_iter()
+                    .collect();
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(values) => {
+                        let arr = ctx.empty_array();
+                        for (i, value) in values.into_iter().enumerate() {
+                            let value: Handle<JsValue> = match value {
+                                Some(val) => JsBuffer::external(&mut ctx, val).upcast(),
+                                None => ctx.undefined().upcast(),
+                            };
+                            arr.set(&mut ctx, i as u32, value)?;
+                        }
+                        vec![ctx.null().upcast(), arr.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// get_stream calls on_chunk with each chunk of the value of the key, and then calls the callback with the size of the value.
+    /// The value is read as a pinned slice, and each chunk is copied only after JS has received the previous one,
+    /// so a large value is never held as a whole outside of the block cache. on_chunk stops the stream by returning false.
+    pub fn get_stream(
+        &self,
+        key: Vec<u8>,
+        chunk_size: usize,
+        on_chunk: Root<JsFunction>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let key = self.db_kind.key(key);
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let on_chunk = Arc::new(Mutex::new(on_chunk));
+            let result = match column_family::get_pinned(conn.unwrap(), &key) {
+                Ok(Some(value)) => {
+                    for chunk in value.chunks(chunk_size) {
+                        let chunk = chunk.to_vec();
+                        let on_chunk = Arc::clone(&on_chunk);
+                        let next = channel
+                            .send(move |mut ctx| {
+                                let buffer = JsBuffer::external(&mut ctx, chunk);
+                                let on_chunk = on_chunk.lock_or_recover().to_inner(&mut ctx);
+                                let this = ctx.undefined();
+                                let result =
+                                    on_chunk.call(&mut ctx, this, vec![buffer.upcast()])?;
+                                let next = match result.downcast::<JsBoolean, _>(&mut ctx) {
+                                    Ok(next) => next.value(&mut ctx),
+                                    Err(_) => true,
+                                };
+                                Ok(next)
+                            })
+                            .join();
+                        // the stream is stopped by JS, or JS is no longer running
+                        if !matches!(next, Ok(true)) {
+                            break;
+                        }
+                    }
+                    Ok(Some(value.len()))
+                },
+                Ok(None) => Ok(None),
+                Err(err) => Err(err),
+            };
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(Some(size)) => vec![ctx.null().upcast(), ctx.number(size as f64).upcast()],
+                    Ok(None) => vec![utils::not_found_error(&mut ctx)?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
 
                 callback.call(&mut ctx, this, args)?;
@@ -123,11 +325,7 @@ impl DB {
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<DbMessage>> {
         let key = self.db_kind.key(key);
-        let result = if self.db().key_may_exist(&key) {
-            self.get(&key).map(|res| res.is_some())
-        } else {
-            Ok(false)
-        };
+        let result = column_family::exists(self.db(), &key);
         self.send(move |channel| {
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
@@ -137,7 +335,7 @@ impl DB {
                         let converted = ctx.boolean(val);
                         vec![ctx.null().upcast(), converted.upcast()]
                     },
-                    Err(err) => vec![ctx.error(&err)?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
 
                 callback.call(&mut ctx, this, args)?;
@@ -154,78 +352,413 @@ impl DB {
     ) -> Result<(), mpsc::SendError<DbMessage>> {
         let conn = Arc::clone(&self.db);
         self.send(move |channel| {
-            let result = Checkpoint::new(conn.unwrap());
+            let callback = CallbackGuard::new(callback, channel);
+            let result = DB::create_checkpoint(conn.unwrap(), &path);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(()) => {
+                        vec![ctx.null().upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
 
-            if result.is_err() {
-                let err = result.err().unwrap();
-                channel.send(move |mut ctx| {
-                    let callback = callback.into_inner(&mut ctx);
-                    let this = ctx.undefined();
-                    let args = vec![ctx.error(&err)?.upcast()];
+                callback.call(&mut ctx, this, args)?;
 
-                    callback.call(&mut ctx, this, args)?;
+                Ok(())
+            });
+        })
+    }
 
-                    Ok(())
-                });
-            } else if let Ok(checkpoint) = result {
-                let result = checkpoint.create_checkpoint(&path);
+    pub fn create_backup(
+        &self,
+        dir: String,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = backup::create(conn.unwrap(), &dir);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(()) => vec![ctx.null().upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// compact_range compacts the keys between start and end on the shared pool, so that the
+    /// reads and the writes are not blocked while compacting.
+    pub fn compact_range(
+        &self,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let prefix = self.db_kind.prefix();
+        self.compact(callback, move |conn| {
+            column_family::compact_range(conn, prefix, start.as_deref(), end.as_deref())
+        })
+    }
 
+    /// compact_smt compacts the SMT nodes on the shared pool.
+    pub fn compact_smt(
+        &self,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        self.compact(callback, column_family::compact_smt)
+    }
+
+    fn compact(
+        &self,
+        callback: Root<JsFunction>,
+        compact: impl FnOnce(&rocksdb::DB) + Send + 'static,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let channel = channel.clone();
+            let run = move || {
+                compact(conn.unwrap());
                 channel.send(move |mut ctx| {
                     let callback = callback.into_inner(&mut ctx);
                     let this = ctx.undefined();
-                    let args: Vec<Handle<JsValue>> = match result {
-                        Ok(()) => {
-                            vec![ctx.null().upcast()]
-                        },
-                        Err(err) => vec![ctx.error(&err)?.upcast()],
-                    };
-
+                    let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast()];
                     callback.call(&mut ctx, this, args)?;
 
                     Ok(())
                 });
+            };
+            // the compaction runs apart from the queue, so the other callbacks are not delayed by it.
+            // It runs on the queue instead when the pool rejects it.
+            let run = Arc::new(Mutex::new(Some(run)));
+            let job = Arc::clone(&run);
+            let result = task_pool::shared().execute(move || {
+                if let Some(run) = job.lock_or_recover().take() {
+                    run();
+                }
+            });
+            if result.is_err() {
+                if let Some(run) = run.lock_or_recover().take() {
+                    run();
+                }
             }
         })
     }
 
+    pub fn set_auto_compaction(&self, enabled: bool) -> Result<(), rocksdb::Error> {
+        column_family::set_auto_compaction(self.db(), enabled)
+    }
+
+    /// flush flushes the memtables to the SST files, so that the WAL is not needed to recover the writes.
+    pub fn flush(&self, callback: Root<JsFunction>) -> Result<(), mpsc::SendError<DbMessage>> {
+        self.send_result(callback, column_family::flush)
+    }
+
+    /// sync_wal syncs the WAL to the disk, which makes the writes without the sync option durable.
+    pub fn sync_wal(&self, callback: Root<JsFunction>) -> Result<(), mpsc::SendError<DbMessage>> {
+        self.send_result(callback, |conn| conn.flush_wal(true))
+    }
+
+    fn send_result(
+        &self,
+        callback: Root<JsFunction>,
+        operation: impl FnOnce(&rocksdb::DB) -> Result<(), rocksdb::Error> + Send + 'static,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = operation(conn.unwrap());
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(()) => vec![ctx.null().upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    pub fn properties(
+        &self,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = properties::get(conn.unwrap());
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(column_families) => {
+                        let obj = utils::column_family_properties_to_js_object(
+                            &mut ctx,
+                            &column_families,
+                        )?;
+                        vec![ctx.null().upcast(), obj.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// write_stall calls the callback with the current condition of the writes.
+    pub fn write_stall(
+        &self,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = properties::write_stall(conn.unwrap());
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(stall) => {
+                        let obj = utils::write_stall_to_js_object(&mut ctx, &stall)?;
+                        vec![ctx.null().upcast(), obj.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// approximate_size estimates the size and the number of the keys between start and end.
+    pub fn approximate_size(
+        &self,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        let prefix = self.db_kind.prefix();
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let result = properties::approximate_size(
+                conn.unwrap(),
+                prefix,
+                start.as_deref(),
+                end.as_deref(),
+            );
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(size) => {
+                        let obj = utils::range_size_to_js_object(&mut ctx, &size)?;
+                        vec![ctx.null().upcast(), obj.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// catch_up_with_primary applies the changes written by the primary to the secondary instance.
+    /// The callback receives the latest sequence number and whether it has advanced by catching up.
+    pub fn catch_up_with_primary(
+        &self,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let conn = Arc::clone(&self.db);
+        self.send(move |channel| {
+            let callback = CallbackGuard::new(callback, channel);
+            let conn = conn.unwrap();
+            let before = conn.latest_sequence_number();
+            let result = conn
+                .try_catch_up_with_primary()
+                .map(|_| conn.latest_sequence_number());
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(sequence) => {
+                        let obj = ctx.empty_object();
+                        let changed = ctx.boolean(sequence > before);
+                        obj.set(&mut ctx, "changed", changed)?;
+                        let sequence = ctx.number(sequence as f64);
+                        obj.set(&mut ctx, "sequenceNumber", sequence)?;
+                        vec![ctx.null().upcast(), obj.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    /// create_checkpoint creates a consistent point-in-time copy of the database at the path.
+    /// The SST files are hard-linked when the path is on the same filesystem, so it is cheap to
+    /// take while the database keeps being written. The path must not exist.
+    pub fn create_checkpoint<P: AsRef<Path>>(
+        conn: &rocksdb::DB,
+        path: P,
+    ) -> Result<(), rocksdb::Error> {
+        Checkpoint::new(conn)?.create_checkpoint(path)
+    }
+
+    /// conn returns the connection, which is borrowed for the lifetime of the DB.
+    pub fn conn(&self) -> &rocksdb::DB {
+        self.db()
+    }
+
     pub fn arc_clone(&self) -> ArcOptionDB {
         Arc::clone(&self.db)
     }
 
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), rocksdb::Error> {
-        self.db().put(key, value)
+        match column_family::handle(self.db(), key) {
+            Some(cf) => self.db().put_cf(cf, key, value),
+            None => self.db().put(key, value),
+        }
     }
 
     pub fn delete(&self, key: &[u8]) -> Result<(), rocksdb::Error> {
-        self.db().delete(key)
+        match column_family::handle(self.db(), key) {
+            Some(cf) => self.db().delete_cf(cf, key),
+            None => self.db().delete(key),
+        }
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
-        self.db().get(key)
+        column_family::get(self.db(), key)
     }
 
     pub fn write(&self, batch: rocksdb::WriteBatch) -> Result<(), rocksdb::Error> {
-        self.db().write(batch)
+        column_family::write(self.db(), batch)
+    }
+
+    /// write_with_ranges deletes the ranges and then writes the batch atomically.
+    pub fn write_with_ranges(
+        &self,
+        ranges: &[(Vec<u8>, Vec<u8>)],
+        batch: rocksdb::WriteBatch,
+    ) -> Result<(), rocksdb::Error> {
+        column_family::write_with_ranges(self.db(), ranges, batch)
+    }
+
+    /// write_with_ranges_opt writes like write_with_ranges with the write options.
+    pub fn write_with_ranges_opt(
+        &self,
+        ranges: &[(Vec<u8>, Vec<u8>)],
+        batch: rocksdb::WriteBatch,
+        write_options: &rocksdb::WriteOptions,
+    ) -> Result<(), rocksdb::Error> {
+        column_family::write_with_ranges_opt(self.db(), ranges, batch, write_options)
+    }
+
+    /// iterate_page reads at most `size` pairs in the range of `options`, starting right after
+    /// `cursor` when it is given. Reading the range page by page lets the caller stop or pause
+    /// between pages without holding the whole range in memory.
+    pub fn iterate_page(
+        conn: &rocksdb::DB,
+        options: &IterationOption,
+        cursor: Option<&[u8]>,
+        size: usize,
+    ) -> Result<Vec<KVPair>, rocksdb::Error> {
+        let _span = tracing::debug_span!("iterate_page", size).entered();
+        let mut start = vec![];
+        let mode = match cursor {
+            Some(key) => {
+                let direction = if options.reverse {
+                    rocksdb::Direction::Reverse
+                } else {
+                    rocksdb::Direction::Forward
+                };
+                rocksdb::IteratorMode::From(key, direction)
+            },
+            None => utils::get_iteration_mode(options, &mut start, false),
+        };
+        let mut pairs = vec![];
+        for key_val in conn.iterator(mode) {
+            let (key, value) = key_val?;
+            if cursor == Some(&*key) {
+                continue;
+            }
+            if pairs.len() >= size
+                || utils::is_key_out_of_range(options, &key, pairs.len() as i64, false)
+            {
+                break;
+            }
+            pairs.push(KVPair::from((key, value)));
+        }
+
+        Ok(pairs)
     }
 
-    pub fn path(&self) -> &std::path::Path {
+    pub fn path(&self) -> &Path {
         self.db().path()
     }
 }
 
+/// release flushes the memtables and drops the database once the other references are dropped,
+/// waiting for them at most for the timeout.
+fn release(mut conn: ArcOptionDB, timeout: Duration) -> Result<(), CloseError> {
+    if let Some(db) = conn.as_ref() {
+        match column_family::flush(db) {
+            // The read only and the secondary instances have nothing to flush
+            Err(err) if err.kind() == rocksdb::ErrorKind::NotSupported => {},
+            result => result?,
+        }
+    }
+    let started = Instant::now();
+    loop {
+        conn = match Arc::try_unwrap(conn) {
+            Ok(db) => {
+                drop(db);
+                return Ok(());
+            },
+            Err(conn) => conn,
+        };
+        if started.elapsed() >= timeout {
+            return Err(CloseError::InUse(Arc::strong_count(&conn) - 1));
+        }
+        thread::sleep(CLOSE_WAIT_INTERVAL);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::mpsc;
     use tempdir::TempDir;
 
     use super::*;
-    use crate::types::KVPair;
 
     fn temp_db() -> DB {
         let temp_dir = TempDir::new("test_db").unwrap();
         let rocks_db = rocksdb::DB::open_default(&temp_dir).unwrap();
-        let (tx, _) = mpsc::channel::<DbMessage>();
-        DB::new(rocks_db, tx, Kind::Normal)
+        DB::new(rocks_db, SerialQueue::closed(), Kind::Normal)
     }
 
     #[test]
@@ -257,4 +790,83 @@ mod tests {
             assert_eq!(db.get(pair.key()).unwrap().unwrap(), pair.value());
         }
     }
+
+    #[test]
+    fn test_create_checkpoint() {
+        let temp_dir = TempDir::new("test_db").unwrap();
+        let conn = rocksdb::DB::open_default(temp_dir.path().join("db")).unwrap();
+        conn.put([1], [2]).unwrap();
+        let path = temp_dir.path().join("checkpoint");
+        DB::create_checkpoint(&conn, &path).unwrap();
+        conn.put([3], [4]).unwrap();
+
+        let checkpoint = rocksdb::DB::open_default(&path).unwrap();
+        assert_eq!(checkpoint.get([1]).unwrap(), Some(vec![2]));
+        assert_eq!(checkpoint.get([3]).unwrap(), None);
+        assert!(DB::create_checkpoint(&conn, &path).is_err());
+    }
+
+    #[test]
+    fn test_iterate_page() {
+        let db = temp_db();
+        for i in 0..10u8 {
+            db.put(&[i], &[i + 100]).unwrap();
+        }
+        let options = IterationOption {
+            limit: -1,
+            reverse: false,
+            gte: Some(vec![2]),
+            lte: Some(vec![8]),
+            gt: None,
+            lt: None,
+        };
+        let page = DB::iterate_page(db.db(), &options, None, 4).unwrap();
+        assert_eq!(
+            page.iter().map(|p| p.key()[0]).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5]
+        );
+        let page = DB::iterate_page(db.db(), &options, Some(&[5]), 4).unwrap();
+        assert_eq!(
+            page.iter().map(|p| p.key()[0]).collect::<Vec<_>>(),
+            vec![6, 7, 8]
+        );
+        assert_eq!(page[0].value(), &[106]);
+
+        let options = IterationOption {
+            limit: 3,
+            reverse: true,
+            gte: Some(vec![2]),
+            lte: Some(vec![8]),
+            gt: None,
+            lt: None,
+        };
+        let page = DB::iterate_page(db.db(), &options, Some(&[7]), 10).unwrap();
+        assert_eq!(
+            page.iter().map(|p| p.key()[0]).collect::<Vec<_>>(),
+            vec![6, 5, 4]
+        );
+    }
+
+    #[test]
+    fn test_release() {
+        let temp_dir = TempDir::new("test_db").unwrap();
+        let conn = rocksdb::DB::open_default(&temp_dir).unwrap();
+        conn.put([1], [2]).unwrap();
+        let conn: ArcOptionDB = Arc::new(Some(conn));
+        let used = Arc::clone(&conn);
+        assert!(matches!(
+            release(Arc::clone(&conn), Duration::ZERO),
+            Err(CloseError::InUse(2))
+        ));
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(used);
+        });
+        release(conn, CLOSE_TIMEOUT).unwrap();
+        handle.join().unwrap();
+
+        let reopened = rocksdb::DB::open_default(&temp_dir).unwrap();
+        assert_eq!(reopened.get([1]).unwrap().unwrap(), vec![2]);
+    }
 }
@@ -9,7 +9,9 @@ use neon::types::buffer::TypedArray;
 use crate::batch;
 use crate::database::options::IterationOption;
 use crate::database::types::JsBoxRef;
-use crate::types::{Cache, KVPair};
+use crate::database::utils as DbUtils;
+use crate::memory;
+use crate::types::{Cache, KVPair, RecoverLock};
 use crate::utils;
 
 type SharedStateDB = JsBoxRef<Database>;
@@ -17,6 +19,7 @@ type SharedStateDB = JsBoxRef<Database>;
 #[derive(Clone, Debug)]
 pub struct CacheData {
     data: Cache,
+    usage: memory::Usage,
 }
 
 // Database to mock the rocksdb in memory.
@@ -53,14 +56,42 @@ fn get_key_value_pairs(db: RefMut<Database>, options: &IterationOption) -> Vec<K
     cached
 }
 
+impl CacheData {
+    fn new() -> Self {
+        Self {
+            data: Cache::new(),
+            usage: memory::Usage::new(memory::Component::InMemoryDatabase),
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        let key_len = key.len();
+        self.usage.grow(key_len + value.len());
+        if let Some(prev) = self.data.insert(key, value) {
+            self.usage.shrink(key_len + prev.len());
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(prev) = self.data.remove(key) {
+            self.usage.shrink(key.len() + prev.len());
+        }
+    }
+
+    fn clear(&mut self) {
+        self.data.clear();
+        self.usage.set(0);
+    }
+}
+
 impl rocksdb::WriteBatchIterator for CacheData {
     /// Called with a key and value that were `put` into the batch.
     fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
-        self.data.insert(key.to_vec(), value.to_vec());
+        self.insert(key.to_vec(), value.to_vec());
     }
     /// Called with a key that was `delete`d from the batch.
     fn delete(&mut self, key: Box<[u8]>) {
-        self.data.remove(&key.to_vec());
+        self.remove(&key);
     }
 }
 
@@ -87,17 +118,15 @@ impl Database {
     }
 
     fn clear(&mut self) {
-        self.cache.data.clear();
+        self.cache.clear();
     }
 
     fn set_key_value(&mut self, pair: &KVPair) {
-        self.cache
-            .data
-            .insert(pair.key_as_vec(), pair.value_as_vec());
+        self.cache.insert(pair.key_as_vec(), pair.value_as_vec());
     }
 
     fn del(&mut self, key: &[u8]) {
-        self.cache.data.remove(key);
+        self.cache.remove(key);
     }
 
     fn clone(&self) -> Self {
@@ -109,7 +138,7 @@ impl Database {
 impl Database {
     pub fn js_new(mut ctx: FunctionContext) -> JsResult<JsBoxRef<Database>> {
         let db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         let ref_db = RefCell::new(db);
 
@@ -129,7 +158,7 @@ impl Database {
                 let buffer = JsBuffer::external(&mut ctx, val.to_vec());
                 vec![ctx.null().upcast(), buffer.upcast()]
             },
-            None => vec![ctx.error("No data")?.upcast()],
+            None => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
         };
         callback.call(&mut ctx, this, args)?;
 
@@ -143,6 +172,8 @@ impl Database {
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
         let mut db = db.borrow_mut();
 
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
         db.set_key_value(&KVPair::new(&key, &value));
 
         Ok(ctx.undefined())
@@ -204,12 +235,20 @@ impl Database {
         let mut db = db.borrow_mut();
 
         let batch = Arc::clone(&batch.borrow());
-        let inner_batch = batch.lock().unwrap();
+        let inner_batch = batch.lock_or_recover();
 
-        inner_batch.batch.iterate(&mut db.cache);
+        memory::reserve(inner_batch.batch.size_in_bytes())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let result = inner_batch.check_conditions(|key| Ok(db.cache.data.get(key).cloned()));
+        if result.is_ok() {
+            inner_batch.batch.iterate(&mut db.cache);
+        }
 
         let this = ctx.undefined();
-        let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast()];
+        let args: Vec<Handle<JsValue>> = match result {
+            Ok(_) => vec![ctx.null().upcast()],
+            Err(err) => vec![batch::write_error_to_js_error(&mut ctx, err)?.upcast()],
+        };
         callback.call(&mut ctx, this, args)?;
 
         Ok(ctx.undefined())
@@ -235,7 +274,7 @@ mod tests {
     #[test]
     fn test_db_cache_range() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -256,7 +295,7 @@ mod tests {
     #[test]
     fn test_db_cache_all() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -277,7 +316,7 @@ mod tests {
     #[test]
     fn test_db_clear() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -291,7 +330,7 @@ mod tests {
     #[test]
     fn test_db_set_kv() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -309,7 +348,7 @@ mod tests {
     #[test]
     fn test_db_delete_key() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -324,7 +363,7 @@ mod tests {
     #[test]
     fn test_db_clone() {
         let mut db = Database {
-            cache: CacheData { data: Cache::new() },
+            cache: CacheData::new(),
         };
         db.set_key_value(&KVPair::new(&[1, 1, 1, 1], &[11, 11, 11, 11]));
         db.set_key_value(&KVPair::new(&[2, 2, 2, 2], &[22, 22, 22, 22]));
@@ -0,0 +1,170 @@
+/// lock opens RocksDB and reports which process holds the lock when the database is already opened.
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::database::column_family;
+use crate::database::types::DbOptions;
+
+/// LOCK_FILE is the file locked by RocksDB while the database is opened.
+const LOCK_FILE: &str = "LOCK";
+/// RETRY_INTERVAL is the interval to retry opening the database while waiting for the lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Error, Debug)]
+pub enum OpenError {
+    #[error("Database `{path}` is already locked by another process{}", pid_suffix(.pid))]
+    AlreadyLocked { path: String, pid: Option<u32> },
+    #[error("Database was created with keepRootHistory `{stored}`, but it is opened with `{}`", !.stored)]
+    RootHistoryMismatch { stored: bool },
+    #[error(transparent)]
+    Database(#[from] rocksdb::Error),
+}
+
+fn pid_suffix(pid: &Option<u32>) -> String {
+    pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default()
+}
+
+/// is_lock_error returns true if RocksDB failed to acquire the lock file.
+fn is_lock_error(err: &rocksdb::Error) -> bool {
+    if err.kind() != rocksdb::ErrorKind::IOError {
+        return false;
+    }
+    let message = err.as_ref();
+    message.contains("While lock file") || message.contains("lock hold by current process")
+}
+
+/// lock_holder returns the pid of the process holding the lock file if it can be found.
+/// It is only available on linux, where the locks are listed in /proc/locks.
+#[cfg(target_os = "linux")]
+fn lock_holder(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    let inode = std::fs::metadata(path.join(LOCK_FILE)).ok()?.ino();
+    let locks = std::fs::read_to_string("/proc/locks").ok()?;
+    // each line is "<id>: <type> <mode> <access> <pid> <major>:<minor>:<inode> <start> <end>"
+    locks.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // blocked requests are marked with "->" and shift the fields
+        let fields = if fields.get(1) == Some(&"->") {
+            &fields[1..]
+        } else {
+            &fields[..]
+        };
+        let lock_inode = fields.get(5)?.rsplit(':').next()?.parse::<u64>().ok()?;
+        if lock_inode != inode {
+            return None;
+        }
+        fields.get(4)?.parse::<u32>().ok()
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lock_holder(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// open opens the database at the path with the options.
+/// If the database is locked, it retries until the lock_timeout in the options elapses.
+pub fn open(
+    option: &rocksdb::Options,
+    path: impl AsRef<Path>,
+    opts: &DbOptions,
+) -> Result<rocksdb::DB, OpenError> {
+    let path = path.as_ref();
+    if opts.is_readonly() {
+        return Ok(rocksdb::DB::open_for_read_only(option, path, false)?);
+    }
+    open_with_retry(path, opts, || rocksdb::DB::open(option, path))
+}
+
+/// open_with_column_families opens the state_db with the column families in the same way as open.
+pub fn open_with_column_families(
+    option: &rocksdb::Options,
+    path: impl AsRef<Path>,
+    opts: &DbOptions,
+) -> Result<rocksdb::DB, OpenError> {
+    let path = path.as_ref();
+    open_with_retry(path, opts, || column_family::open(option, path, opts))
+}
+
+fn open_with_retry(
+    path: &Path,
+    opts: &DbOptions,
+    open: impl Fn() -> Result<rocksdb::DB, rocksdb::Error>,
+) -> Result<rocksdb::DB, OpenError> {
+    let deadline = opts.lock_timeout().map(|timeout| Instant::now() + timeout);
+    loop {
+        let err = match open() {
+            Ok(db) => return Ok(db),
+            Err(err) => err,
+        };
+        if !is_lock_error(&err) {
+            return Err(err.into());
+        }
+        match deadline {
+            Some(deadline) if Instant::now() < deadline => thread::sleep(RETRY_INTERVAL),
+            _ => {
+                return Err(OpenError::AlreadyLocked {
+                    path: path.to_string_lossy().to_string(),
+                    pid: lock_holder(path),
+                });
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::types::KeyLength;
+
+    fn options(lock_timeout: Option<Duration>) -> DbOptions {
+        DbOptions::new(false, KeyLength(32)).with_lock_timeout(lock_timeout)
+    }
+
+    #[test]
+    fn test_open_already_locked() {
+        let temp_dir = TempDir::new("test_lock").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let _db = open(&option, temp_dir.path(), &options(None)).unwrap();
+
+        let start = Instant::now();
+        let timeout = Some(Duration::from_millis(300));
+        let err = open(&option, temp_dir.path(), &options(timeout)).unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        if let OpenError::AlreadyLocked { path, pid } = err {
+            assert_eq!(path, temp_dir.path().to_string_lossy());
+            if cfg!(target_os = "linux") {
+                assert_eq!(pid, Some(std::process::id()));
+            }
+        } else {
+            panic!("the error should be AlreadyLocked");
+        }
+    }
+
+    #[test]
+    fn test_open_wait_for_lock() {
+        let temp_dir = TempDir::new("test_lock").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let db = open(&option, temp_dir.path(), &options(None)).unwrap();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(db);
+        });
+
+        assert!(open(
+            &option,
+            temp_dir.path(),
+            &options(Some(Duration::from_secs(5)))
+        )
+        .is_ok());
+        handle.join().unwrap();
+    }
+}
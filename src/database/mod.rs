@@ -1,11 +1,31 @@
+#[cfg(feature = "neon")]
+pub mod backup;
+#[cfg(feature = "neon")]
+pub mod column_family;
+#[cfg(feature = "neon")]
 pub mod db;
+#[cfg(feature = "neon")]
 pub mod in_memory;
+#[cfg(feature = "neon")]
+pub mod lock;
+#[cfg(feature = "neon")]
 pub mod options;
+#[cfg(feature = "neon")]
+pub mod properties;
+#[cfg(feature = "neon")]
 pub mod reader_writer;
+#[cfg(feature = "neon")]
+pub mod recovery;
 pub mod traits;
+#[cfg(feature = "neon")]
+pub mod transaction;
+#[cfg(feature = "neon")]
 pub mod types;
+#[cfg(feature = "neon")]
 pub mod utils;
 
+#[cfg(feature = "neon")]
 mod db_base;
 
+#[cfg(feature = "neon")]
 pub use db_base::DB;
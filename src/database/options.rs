@@ -1,10 +1,13 @@
-/// options provides functionality to read Database open and iteration options.
+/// options provides functionality to read Database open, iteration and write options.
+use std::time::Duration;
+
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 
 use crate::consts;
+use crate::database::column_family;
 use crate::database::traits::OptionsWithContext;
-use crate::database::types::DbOptions;
+use crate::database::types::{Compression, DbOptions, TuningOptions};
 use crate::types::{KeyLength, VecOption};
 
 /// IterationOption holds iterator option for the database.
@@ -14,6 +17,24 @@ pub struct IterationOption {
     pub reverse: bool,
     pub gte: VecOption,
     pub lte: VecOption,
+    pub gt: VecOption,
+    pub lt: VecOption,
+}
+
+/// WriteOption holds the durability option of a write.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOption {
+    pub sync: bool,
+    pub disable_wal: bool,
+}
+
+impl From<WriteOption> for rocksdb::WriteOptions {
+    fn from(option: WriteOption) -> Self {
+        let mut write_options = rocksdb::WriteOptions::default();
+        write_options.set_sync(option.sync);
+        write_options.disable_wal(option.disable_wal);
+        write_options
+    }
 }
 
 impl OptionsWithContext for DbOptions {
@@ -46,10 +67,150 @@ impl OptionsWithContext for DbOptions {
                 .unwrap_or_else(|| consts::KEY_LENGTH.into()),
         );
 
-        Ok(Self::new(readonly, key_length))
+        let keep_root_history = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "keepRootHistory")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        let audit_deletion = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "auditDeletion")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        let rollback_protection = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "rollbackProtection")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        let lock_timeout = obj
+            .get_opt::<JsNumber, _, _>(ctx, "lockTimeout")?
+            .map(|val| Duration::from_millis(val.value(ctx).max(0.0) as u64));
+
+        let tuning = tuning_options(ctx, obj)?;
+
+        let diff_retention = obj
+            .get_opt::<JsNumber, _, _>(ctx, "diffRetention")?
+            .map(|val| val.value(ctx).max(0.0) as u32);
+
+        let root_history_retention = obj
+            .get_opt::<JsNumber, _, _>(ctx, "rootHistoryRetention")?
+            .map(|val| val.value(ctx).max(0.0) as u32);
+
+        let smt_key_filter = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "smtKeyFilter")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        let store_roots = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "storeRoots")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        let structure_log = obj
+            .get_opt::<JsBoolean, _, _>(ctx, "structureLog")?
+            .map(|val| val.value(ctx))
+            .unwrap_or(false);
+
+        Ok(Self::new(readonly, key_length)
+            .with_keep_root_history(keep_root_history)
+            .with_audit_deletion(audit_deletion)
+            .with_rollback_protection(rollback_protection)
+            .with_lock_timeout(lock_timeout)
+            .with_tuning(tuning)
+            .with_diff_retention(diff_retention)
+            .with_root_history_retention(root_history_retention)
+            .with_smt_key_filter(smt_key_filter)
+            .with_store_roots(store_roots)
+            .with_structure_log(structure_log))
     }
 }
 
+/// tuning_options reads blockCacheSize, compression, maxOpenFiles, writeBufferSize, rateLimit,
+/// parallelism, maxBackgroundCompactions, maxBackgroundFlushes, directReads, cacheIndexAndFilterBlocks,
+/// pinL0FilterAndIndexBlocks and inMemory of the open options.
+fn tuning_options<'a, C>(ctx: &mut C, obj: Handle<JsObject>) -> NeonResult<TuningOptions>
+where
+    C: Context<'a>,
+{
+    let block_cache_size = obj
+        .get_opt::<JsNumber, _, _>(ctx, "blockCacheSize")?
+        .map(|val| val.value(ctx).max(0.0) as usize);
+
+    let compression = match obj.get_opt::<JsString, _, _>(ctx, "compression")? {
+        Some(val) => {
+            let name = val.value(ctx);
+            let compression = Compression::from_name(&name);
+            if compression.is_none() {
+                return ctx.throw_error(format!(
+                    "Invalid compression `{}`. It must be one of lz4, zstd or none",
+                    name
+                ));
+            }
+            compression
+        },
+        None => None,
+    };
+
+    let max_open_files = obj
+        .get_opt::<JsNumber, _, _>(ctx, "maxOpenFiles")?
+        .map(|val| val.value(ctx) as i32);
+
+    let write_buffer_size = obj
+        .get_opt::<JsNumber, _, _>(ctx, "writeBufferSize")?
+        .map(|val| val.value(ctx).max(0.0) as usize);
+
+    let rate_limit = obj
+        .get_opt::<JsNumber, _, _>(ctx, "rateLimit")?
+        .map(|val| val.value(ctx).max(1.0) as i64);
+
+    let parallelism = obj
+        .get_opt::<JsNumber, _, _>(ctx, "parallelism")?
+        .map(|val| val.value(ctx).max(1.0) as i32);
+
+    let max_background_compactions = obj
+        .get_opt::<JsNumber, _, _>(ctx, "maxBackgroundCompactions")?
+        .map(|val| val.value(ctx).max(1.0) as i32);
+
+    let max_background_flushes = obj
+        .get_opt::<JsNumber, _, _>(ctx, "maxBackgroundFlushes")?
+        .map(|val| val.value(ctx).max(1.0) as i32);
+
+    let direct_reads = obj
+        .get_opt::<JsBoolean, _, _>(ctx, "directReads")?
+        .map(|val| val.value(ctx))
+        .unwrap_or(false);
+
+    let cache_index_and_filter_blocks = obj
+        .get_opt::<JsBoolean, _, _>(ctx, "cacheIndexAndFilterBlocks")?
+        .map(|val| val.value(ctx))
+        .unwrap_or(false);
+
+    let pin_l0_filter_and_index_blocks = obj
+        .get_opt::<JsBoolean, _, _>(ctx, "pinL0FilterAndIndexBlocks")?
+        .map(|val| val.value(ctx))
+        .unwrap_or(false);
+
+    let in_memory = obj
+        .get_opt::<JsBoolean, _, _>(ctx, "inMemory")?
+        .map(|val| val.value(ctx))
+        .unwrap_or(false);
+
+    Ok(TuningOptions {
+        block_cache_size,
+        compression,
+        max_open_files,
+        write_buffer_size,
+        rate_limit,
+        parallelism,
+        max_background_compactions,
+        max_background_flushes,
+        direct_reads,
+        cache_index_and_filter_blocks,
+        pin_l0_filter_and_index_blocks,
+        in_memory,
+    })
+}
+
 impl Default for DbOptions {
     fn default() -> Self {
         Self::new(false, consts::KEY_LENGTH)
@@ -86,11 +247,77 @@ impl IterationOption {
             .map(|val| val.map(|v| v.as_slice(ctx).to_vec()))
             .unwrap_or(None);
 
+        let gt = input
+            .get_opt::<JsTypedArray<u8>, _, _>(ctx, "gt")
+            .map(|val| val.map(|v| v.as_slice(ctx).to_vec()))
+            .unwrap_or(None);
+
+        let lt = input
+            .get_opt::<JsTypedArray<u8>, _, _>(ctx, "lt")
+            .map(|val| val.map(|v| v.as_slice(ctx).to_vec()))
+            .unwrap_or(None);
+
         Self {
             limit: limit as i64,
             reverse,
             gte,
             lte,
+            gt,
+            lt,
         }
     }
+
+    /// lower_bound returns the lower bound of the range, which is gte or gt when gte is not given.
+    pub fn lower_bound(&self) -> Option<&Vec<u8>> {
+        self.gte.as_ref().or(self.gt.as_ref())
+    }
+
+    /// upper_bound returns the upper bound of the range, which is lte or lt when lte is not given.
+    pub fn upper_bound(&self) -> Option<&Vec<u8>> {
+        self.lte.as_ref().or(self.lt.as_ref())
+    }
+
+    /// is_within_store_prefix returns true if both bounds of the range of the state keys have the same store prefix.
+    pub fn is_within_store_prefix(&self) -> bool {
+        column_family::is_within_store_prefix(
+            self.lower_bound().map(Vec::as_slice),
+            self.upper_bound().map(Vec::as_slice),
+        )
+    }
+
+    /// contains returns true if the key is within all the bounds of the option.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        let above = |bound: &VecOption, inclusive: bool| {
+            bound
+                .as_deref()
+                .map_or(true, |b| key > b || (inclusive && key == b))
+        };
+        let below = |bound: &VecOption, inclusive: bool| {
+            bound
+                .as_deref()
+                .map_or(true, |b| key < b || (inclusive && key == b))
+        };
+        above(&self.gte, true)
+            && above(&self.gt, false)
+            && below(&self.lte, true)
+            && below(&self.lt, false)
+    }
+}
+
+impl WriteOption {
+    pub fn new<'a, C>(ctx: &mut C, input: Handle<JsObject>) -> Self
+    where
+        C: Context<'a>,
+    {
+        let sync = input
+            .get_opt::<JsBoolean, _, _>(ctx, "sync")
+            .map(|val| val.map(|v| v.value(ctx)).unwrap_or(false))
+            .unwrap_or(false);
+        let disable_wal = input
+            .get_opt::<JsBoolean, _, _>(ctx, "disableWAL")
+            .map(|val| val.map(|v| v.value(ctx)).unwrap_or(false))
+            .unwrap_or(false);
+
+        Self { sync, disable_wal }
+    }
 }
@@ -0,0 +1,234 @@
+/// properties reads the RocksDB properties of the database used for monitoring.
+/// The properties are read for each column family, and the total is the sum of them.
+use std::ffi::CStr;
+use std::ops::Add;
+
+use rocksdb::properties;
+
+use crate::database::column_family::{self, COLUMN_FAMILIES};
+use crate::utils;
+
+/// DEFAULT is the name of the default column family.
+pub const DEFAULT: &str = "default";
+
+/// Properties are the properties of a column family.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub estimated_keys: u64,
+    pub total_sst_size: u64,
+    pub memtable_usage: u64,
+    pub pending_compaction_bytes: u64,
+}
+
+impl Add for Properties {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            estimated_keys: self.estimated_keys + other.estimated_keys,
+            total_sst_size: self.total_sst_size + other.total_sst_size,
+            memtable_usage: self.memtable_usage + other.memtable_usage,
+            pending_compaction_bytes: self.pending_compaction_bytes
+                + other.pending_compaction_bytes,
+        }
+    }
+}
+
+fn read(
+    conn: &rocksdb::DB,
+    cf: Option<&rocksdb::ColumnFamily>,
+) -> Result<Properties, rocksdb::Error> {
+    let value = |name: &CStr| -> Result<u64, rocksdb::Error> {
+        let value = match cf {
+            Some(cf) => conn.property_int_value_cf(cf, name)?,
+            None => conn.property_int_value(name)?,
+        };
+        Ok(value.unwrap_or(0))
+    };
+
+    Ok(Properties {
+        estimated_keys: value(properties::ESTIMATE_NUM_KEYS)?,
+        total_sst_size: value(properties::TOTAL_SST_FILES_SIZE)?,
+        memtable_usage: value(properties::CUR_SIZE_ALL_MEM_TABLES)?,
+        pending_compaction_bytes: value(properties::ESTIMATE_PENDING_COMPACTION_BYTES)?,
+    })
+}
+
+/// get returns the properties of the default and the other column families of the database.
+pub fn get(conn: &rocksdb::DB) -> Result<Vec<(&'static str, Properties)>, rocksdb::Error> {
+    let mut result = vec![(DEFAULT, read(conn, None)?)];
+    for (name, _) in COLUMN_FAMILIES {
+        if let Some(cf) = conn.cf_handle(name) {
+            result.push((name, read(conn, Some(cf))?));
+        }
+    }
+
+    Ok(result)
+}
+
+/// total returns the sum of the properties of the column families.
+pub fn total(column_families: &[(&str, Properties)]) -> Properties {
+    column_families
+        .iter()
+        .fold(Properties::default(), |sum, (_, properties)| {
+            sum + *properties
+        })
+}
+
+/// WriteStall is the condition of the writes, which are delayed or stopped by RocksDB
+/// when the flush and the compaction cannot keep up with them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteStall {
+    Normal,
+    /// Delayed holds the rate of the delayed writes in bytes per second.
+    Delayed(u64),
+    Stopped,
+}
+
+impl WriteStall {
+    /// name returns the name of the condition used in JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WriteStall::Normal => "normal",
+            WriteStall::Delayed(_) => "delayed",
+            WriteStall::Stopped => "stopped",
+        }
+    }
+}
+
+/// write_stall returns the current condition of the writes to the database.
+pub fn write_stall(conn: &rocksdb::DB) -> Result<WriteStall, rocksdb::Error> {
+    let stopped = conn.property_int_value(properties::IS_WRITE_STOPPED)?;
+    let delayed_rate = conn.property_int_value(properties::ACTUAL_DELAYED_WRITE_RATE)?;
+
+    Ok(match (stopped, delayed_rate) {
+        (Some(stopped), _) if stopped > 0 => WriteStall::Stopped,
+        (_, Some(rate)) if rate > 0 => WriteStall::Delayed(rate),
+        _ => WriteStall::Normal,
+    })
+}
+
+/// RangeSize is the estimated size and the number of the keys in a range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RangeSize {
+    pub size: u64,
+    pub estimated_keys: u64,
+}
+
+fn overlaps(file: &rocksdb::LiveFile, start: &[u8], end: Option<&[u8]>) -> bool {
+    let after_start = file.end_key.as_deref().map_or(true, |last| last >= start);
+    let before_end = match (file.start_key.as_deref(), end) {
+        (Some(first), Some(end)) => first < end,
+        _ => true,
+    };
+    after_start && before_end
+}
+
+/// approximate_size estimates the size and the number of the keys with the prefix between start and end.
+/// start and end do not contain the prefix, and all the keys with the prefix are counted when they are not given.
+/// The estimation is made from the metadata of the SST files overlapping the range, so the writes in the memtable are not counted,
+/// and the files on the boundaries of the range are counted as a whole.
+pub fn approximate_size(
+    conn: &rocksdb::DB,
+    prefix: &[u8],
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+) -> Result<RangeSize, rocksdb::Error> {
+    let start = [prefix, start.unwrap_or_default()].concat();
+    let end = match end {
+        Some(end) => Some([prefix, end].concat()),
+        None => utils::prefix_end(prefix),
+    };
+    let cf_name = column_family::name(prefix)
+        .filter(|name| conn.cf_handle(name).is_some())
+        .unwrap_or(DEFAULT);
+
+    let size = conn
+        .live_files()?
+        .iter()
+        .filter(|file| file.column_family_name == cf_name)
+        .filter(|file| overlaps(file, &start, end.as_deref()))
+        .fold(RangeSize::default(), |sum, file| RangeSize {
+            size: sum.size + file.size as u64,
+            estimated_keys: sum.estimated_keys
+                + file.num_entries.saturating_sub(file.num_deletions),
+        });
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::consts::Prefix;
+    use crate::database::types::DbOptions;
+
+    #[test]
+    fn test_get() {
+        let temp_dir = TempDir::new("test_properties").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = column_family::open(&option, temp_dir.path(), &DbOptions::default()).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        for i in 0..10u8 {
+            batch.put([Prefix::STATE, &[i]].concat(), [i]);
+        }
+        batch.put(Prefix::CURRENT_STATE, [1]);
+        column_family::write(&conn, batch).unwrap();
+
+        let column_families = get(&conn).unwrap();
+        let names: Vec<&str> = column_families.iter().map(|(name, _)| *name).collect();
+        let expected = [
+            DEFAULT,
+            column_family::STATE,
+            column_family::SMT,
+            column_family::DIFF,
+        ];
+        assert_eq!(names, expected);
+        assert_eq!(column_families[0].1.estimated_keys, 1);
+        assert_eq!(column_families[1].1.estimated_keys, 10);
+        assert!(column_families[1].1.memtable_usage > 0);
+        assert_eq!(total(&column_families).estimated_keys, 11);
+    }
+
+    #[test]
+    fn test_write_stall() {
+        let temp_dir = TempDir::new("test_write_stall").unwrap();
+        let conn = rocksdb::DB::open_default(&temp_dir).unwrap();
+        conn.put([1], [2]).unwrap();
+
+        let stall = write_stall(&conn).unwrap();
+        assert_eq!(stall, WriteStall::Normal);
+        assert_eq!(stall.name(), "normal");
+        assert_eq!(WriteStall::Delayed(1024).name(), "delayed");
+    }
+
+    #[test]
+    fn test_approximate_size() {
+        let temp_dir = TempDir::new("test_approximate_size").unwrap();
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        let conn = column_family::open(&option, temp_dir.path(), &DbOptions::default()).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        for i in 0..10u8 {
+            batch.put([Prefix::STATE, &[i]].concat(), [i; 32]);
+        }
+        column_family::write(&conn, batch).unwrap();
+        let memtable_only = approximate_size(&conn, Prefix::STATE, None, None).unwrap();
+        assert_eq!(memtable_only, RangeSize::default());
+
+        conn.flush_cf(conn.cf_handle(column_family::STATE).unwrap())
+            .unwrap();
+        let all = approximate_size(&conn, Prefix::STATE, None, None).unwrap();
+        assert!(all.size > 0);
+        assert_eq!(all.estimated_keys, 10);
+        let range = approximate_size(&conn, Prefix::STATE, Some(&[2]), Some(&[5])).unwrap();
+        assert_eq!(range, all);
+        let outside = approximate_size(&conn, Prefix::STATE, Some(&[20]), None).unwrap();
+        assert_eq!(outside, RangeSize::default());
+        let smt = approximate_size(&conn, Prefix::SMT, None, None).unwrap();
+        assert_eq!(smt, RangeSize::default());
+    }
+}
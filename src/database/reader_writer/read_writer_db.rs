@@ -1,6 +1,7 @@
 /// read_writer is the interface for state read writer.
 /// State writer will snapshot the data and even if the change happen during the lifetime of reader writer, it will not be affected.
 /// writer will not store the data to physical storage until commit to the state db.
+use std::collections::BTreeMap;
 use std::sync::{mpsc, Arc};
 
 use neon::context::{Context, FunctionContext};
@@ -9,12 +10,16 @@ use neon::object::Object;
 use neon::result::JsResult;
 use neon::types::{buffer::TypedArray, JsBuffer, JsFunction, JsObject, JsTypedArray, JsUndefined};
 
+use crate::consts::Prefix;
 use crate::database::options;
 use crate::database::reader_writer::{ReaderBase, SharedReaderBase};
+use crate::database::recovery::CallbackGuard;
 use crate::database::types::{Kind, SnapshotMessage};
 use crate::database::utils::*;
+use crate::memory;
+use crate::sparse_merkle_tree::smt;
 use crate::state_writer;
-use crate::types::{ArcMutex, KVPair, SharedKVPair};
+use crate::types::{ArcMutex, KVPair, RecoverLock, SharedKVPair};
 
 pub type ReadWriter = ReaderBase;
 impl ReadWriter {
@@ -28,10 +33,11 @@ impl ReadWriter {
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
         let state_db_key = Kind::State.key(key.clone());
         self.send(move |conn, channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let value = conn.get(&state_db_key);
             channel.send(move |mut ctx| {
                 let args = {
-                    let mut writer = writer.lock().unwrap();
+                    let mut writer = writer.lock_or_recover();
                     let cached = writer.is_cached(&key);
                     if cached {
                         //  if the key already in cache so update it and returns
@@ -52,7 +58,7 @@ impl ReadWriter {
                         }
                     } else {
                         let err = value.err().unwrap();
-                        vec![ctx.error(&err)?.upcast()]
+                        vec![error_with_code(&mut ctx, &err)?.upcast()]
                     }
                 };
 
@@ -72,16 +78,17 @@ impl ReadWriter {
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
         let state_db_key = Kind::State.key(key.clone());
         self.send(move |conn, channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let value = conn.get(&state_db_key);
             channel.send(move |mut ctx| {
                 let args = {
-                    let mut writer = writer.lock().unwrap();
+                    let mut writer = writer.lock_or_recover();
                     let (cached_value, deleted, exists) = writer.get(&key);
                     if exists && !deleted {
                         let buffer = JsBuffer::external(&mut ctx, cached_value);
                         vec![ctx.null().upcast(), buffer.upcast()]
                     } else if deleted {
-                        vec![ctx.error("No data")?.upcast()]
+                        vec![not_found_error(&mut ctx)?.upcast()]
                     } else if let Ok(value) = &value {
                         // if found the value of the key then insert into cache
                         if value.is_some() {
@@ -91,11 +98,11 @@ impl ReadWriter {
                             let buffer = JsBuffer::external(&mut ctx, temp_value);
                             vec![ctx.null().upcast(), buffer.upcast()]
                         } else {
-                            vec![ctx.error("No data")?.upcast()]
+                            vec![not_found_error(&mut ctx)?.upcast()]
                         }
                     } else {
                         let err = value.err().unwrap();
-                        vec![ctx.error(&err)?.upcast()]
+                        vec![error_with_code(&mut ctx, &err)?.upcast()]
                     }
                 };
 
@@ -115,13 +122,14 @@ impl ReadWriter {
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
         let state_db_key = Kind::State.key(key.clone());
         self.send(move |conn, channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let value = conn.get(&state_db_key);
             channel.send(move |mut ctx| {
                 let this = ctx.undefined();
                 let callback = callback.into_inner(&mut ctx);
                 // the following scope use to release writer at the end of it
                 {
-                    let mut writer = writer.lock().unwrap();
+                    let mut writer = writer.lock_or_recover();
                     let cached = writer.is_cached(&key);
                     if !cached {
                         if let Ok(value) = &value {
@@ -133,7 +141,7 @@ impl ReadWriter {
                             }
                         } else {
                             let err = value.err().unwrap();
-                            let args = vec![ctx.error(&err)?.upcast()];
+                            let args = vec![error_with_code(&mut ctx, &err)?.upcast()];
                             callback.call(&mut ctx, this, args)?;
                         }
                     }
@@ -152,35 +160,58 @@ impl ReadWriter {
         writer: ArcMutex<state_writer::StateWriter>,
         options: options::IterationOption,
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
+        // The limit is applied after merging, since the keys deleted in the writer can hide the pairs in the database
+        let mut unlimited = options.clone();
+        unlimited.limit = -1;
         self.send(move |conn, channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let values = conn
-                .iterator(get_iteration_mode(&options, &mut vec![], true))
-                .map(|key_val| {
-                    KVPair::new(&key_val.as_ref().unwrap().0.clone(), &key_val.unwrap().1)
-                })
+                .iterator(
+                    Prefix::STATE,
+                    get_iteration_mode(&unlimited, &mut vec![], true),
+                )
+                .map(|key_val| KVPair::from(key_val.unwrap()))
                 .collect::<Vec<KVPair>>();
             channel.send(move |mut ctx| {
                 let result = {
-                    let mut writer = writer.lock().unwrap();
-                    let mut result = writer.get_range(&options);
-                    for (counter, pair) in values.iter().enumerate() {
-                        if is_key_out_of_range(&options, pair.key(), counter as i64, true) {
+                    let mut writer = writer.lock_or_recover();
+                    let mut merged = writer
+                        .get_range(&unlimited)
+                        .into_iter()
+                        .map(|pair| (pair.key_as_vec(), pair.value_as_vec()))
+                        .collect::<BTreeMap<Vec<u8>, Vec<u8>>>();
+                    for pair in values.iter() {
+                        if is_key_out_of_range(&unlimited, pair.key(), 0, true) {
                             break;
                         }
                         let (_, key_without_prefix) = pair.key().split_first().unwrap();
+                        if !unlimited.contains(key_without_prefix) {
+                            continue;
+                        }
                         let (cached_value, deleted, exists) = writer.get(key_without_prefix);
                         if exists && !deleted {
-                            result.insert(key_without_prefix.to_vec(), cached_value);
+                            merged.insert(key_without_prefix.to_vec(), cached_value);
                         } else if deleted {
                             continue;
                         } else {
                             // key is stored and returned without prefix
                             let shared_pair = SharedKVPair::new(key_without_prefix, pair.value());
                             writer.cache_existing(&shared_pair);
-                            result.insert(shared_pair.key_as_vec(), shared_pair.value_as_vec());
+                            merged.insert(shared_pair.key_as_vec(), shared_pair.value_as_vec());
                         }
                     }
-                    cache_to_js_array(&mut ctx, &result)?
+                    let pairs = merged.into_iter().map(|(key, value)| KVPair(key, value));
+                    let limit = if options.limit == -1 {
+                        usize::MAX
+                    } else {
+                        options.limit as usize
+                    };
+                    let pairs = if options.reverse {
+                        pairs.rev().take(limit).collect::<Vec<KVPair>>()
+                    } else {
+                        pairs.take(limit).collect::<Vec<KVPair>>()
+                    };
+                    pairs_into_js_array(&mut ctx, pairs)?
                 };
                 let this = ctx.undefined();
                 let callback = callback.into_inner(&mut ctx);
@@ -207,6 +238,15 @@ impl ReadWriter {
             .downcast_or_throw::<state_writer::SendableStateWriter, _>(&mut ctx)?;
         let key = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
         let value = ctx.argument::<JsTypedArray<u8>>(2)?.as_slice(&ctx).to_vec();
+        smt::check_state_key(&key).or_else(|err| ctx.throw_error(err.to_string()))?;
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        batch
+            .borrow()
+            .lock()
+            .unwrap()
+            .reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
         let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
         // Get the `this` value as a `SharedReaderBase`
         let db = ctx
@@ -259,6 +299,7 @@ impl ReadWriter {
             .argument::<state_writer::SendableStateWriter>(0)?
             .downcast_or_throw::<state_writer::SendableStateWriter, _>(&mut ctx)?;
         let key = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        smt::check_state_key(&key).or_else(|err| ctx.throw_error(err.to_string()))?;
         let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
         // Get the `this` value as a `SharedReaderBase`
         let db = ctx
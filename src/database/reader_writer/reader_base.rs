@@ -1,7 +1,6 @@
 /// reader_base provides base functionality for state reader.
 use std::cell::RefCell;
 use std::sync::mpsc;
-use std::thread;
 
 use neon::context::{Context, FunctionContext};
 use neon::event::Channel;
@@ -9,12 +8,41 @@ use neon::handle::{Handle, Root};
 use neon::result::JsResult;
 use neon::types::{Finalize, JsBuffer, JsFunction, JsUndefined, JsValue};
 
+use crate::database::column_family;
+use crate::database::db::SharedDatabase;
+use crate::database::recovery::{self, CallbackGuard};
 use crate::database::traits::Unwrap;
-use crate::database::types::{JsBoxRef, Kind, SnapshotMessage};
+use crate::database::types::{ArcOptionDB, JsBoxRef, Kind, SnapshotMessage};
+use crate::database::utils;
 use crate::state_db::SharedStateDB;
+use crate::task_pool::SerialQueue;
 
 pub struct ReaderBase {
-    tx: mpsc::Sender<SnapshotMessage>,
+    queue: SerialQueue<SnapshotMessage>,
+    db_kind: Kind,
+}
+
+/// OwnedSnapshot keeps the database of the snapshot open while the snapshot is used.
+struct OwnedSnapshot {
+    // declared first, so the snapshot is released before the database
+    snapshot: column_family::Snapshot<'static>,
+    _conn: ArcOptionDB,
+}
+
+impl OwnedSnapshot {
+    fn new(conn: ArcOptionDB) -> Self {
+        // SAFETY: the database is never moved or dropped while the Arc is held by the snapshot,
+        // so the reference lives as long as the snapshot
+        let db: &'static rocksdb::DB = unsafe { &*(conn.unwrap() as *const rocksdb::DB) };
+        Self {
+            snapshot: column_family::Snapshot::new(db),
+            _conn: conn,
+        }
+    }
+
+    fn snapshot(&self) -> &column_family::Snapshot<'_> {
+        &self.snapshot
+    }
 }
 
 impl Finalize for ReaderBase {
@@ -28,42 +56,63 @@ impl ReaderBase {
     /// Idiomatic rust would take an owned `self` to prevent use after close
     /// However, it's not possible to prevent JavaScript from continuing to hold a closed database
     fn close(&self) -> Result<(), mpsc::SendError<SnapshotMessage>> {
-        self.tx.send(SnapshotMessage::Close)
+        self.queue.send(SnapshotMessage::Close)
+    }
+
+    /// spawn takes the snapshot of the database, and creates the queue which executes the callbacks sent to the reader
+    /// in order on the shared pool. The snapshot is released when the reader is closed.
+    fn spawn<'a, C: Context<'a>>(ctx: &mut C, conn: ArcOptionDB, db_kind: Kind) -> Self {
+        let channel = ctx.channel();
+        let owned = OwnedSnapshot::new(conn);
+
+        let queue = SerialQueue::new(move |message| match message {
+            SnapshotMessage::Callback(f) => {
+                // The panic is reported by the callback guard of the request
+                let _ = recovery::run_request("reader", || f(owned.snapshot(), &channel));
+                true
+            },
+            SnapshotMessage::Close => false,
+        });
+
+        Self { queue, db_kind }
+    }
+
+    pub fn db_kind(&self) -> &Kind {
+        &self.db_kind
     }
 
     /// js_new is handler for JS ffi.
     /// - @params(0) - StateDB to create the reader from.
     /// - @returns - Reader where it is snapshot of stateDB.
     pub fn js_new(mut ctx: FunctionContext) -> JsResult<JsBoxRef<Self>> {
-        // Channel for sending callbacks to execute on the sqlite connection thread
-        let (tx, rx) = mpsc::channel::<SnapshotMessage>();
-        let channel = ctx.channel();
-
         let db = ctx
             .argument::<SharedStateDB>(0)?
             .downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
-        let db = db.borrow();
-        let conn = db.arc_clone();
-        thread::spawn(move || {
-            let snapshot = conn.unwrap().snapshot();
-            while let Ok(message) = rx.recv() {
-                match message {
-                    SnapshotMessage::Callback(f) => {
-                        f(&snapshot, &channel);
-                    },
-                    SnapshotMessage::Close => return,
-                }
-            }
-        });
+        let conn = db.borrow().arc_clone();
+        let reader = Self::spawn(&mut ctx, conn, Kind::State);
+
+        Ok(ctx.boxed(RefCell::new(reader)))
+    }
+
+    /// js_new_snapshot is handler for JS ffi.
+    /// - @params(0) - Database to create the snapshot from.
+    /// - @returns - Reader which only sees the data written before it is created.
+    pub fn js_new_snapshot(mut ctx: FunctionContext) -> JsResult<JsBoxRef<Self>> {
+        let db = ctx
+            .argument::<SharedDatabase>(0)?
+            .downcast_or_throw::<SharedDatabase, _>(&mut ctx)?;
+        let conn = db.borrow().arc_clone();
+        let reader = Self::spawn(&mut ctx, conn, Kind::Normal);
 
-        Ok(ctx.boxed(RefCell::new(Self { tx })))
+        Ok(ctx.boxed(RefCell::new(reader)))
     }
 
     pub fn send(
         &self,
-        callback: impl FnOnce(&rocksdb::Snapshot, &Channel) + Send + 'static,
+        callback: impl FnOnce(&column_family::Snapshot, &Channel) + Send + 'static,
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
-        self.tx.send(SnapshotMessage::Callback(Box::new(callback)))
+        self.queue
+            .send(SnapshotMessage::Callback(Box::new(callback)))
     }
 
     pub fn get_by_key(
@@ -71,8 +120,9 @@ impl ReaderBase {
         key: Vec<u8>,
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
-        let key = Kind::State.key(key);
+        let key = self.db_kind.key(key);
         self.send(move |conn, channel| {
+            let callback = CallbackGuard::new(callback, channel);
             let result = conn.get(&key);
 
             channel.send(move |mut ctx| {
@@ -83,8 +133,8 @@ impl ReaderBase {
                         let buffer = JsBuffer::external(&mut ctx, val);
                         vec![ctx.null().upcast(), buffer.upcast()]
                     },
-                    Ok(None) => vec![ctx.error("No data")?.upcast()],
-                    Err(err) => vec![ctx.error(&err)?.upcast()],
+                    Ok(None) => vec![utils::not_found_error(&mut ctx)?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
 
                 callback.call(&mut ctx, this, args)?;
@@ -1,5 +1,6 @@
 /// reader_db is the interface for state reader.
 /// State reader will snapshot the data and even if the change happen during the lifetime of reader, it will not be affected.
+/// The snapshot of the database is the same reader without the state prefix.
 use std::sync::{mpsc, Arc, Mutex};
 
 use neon::context::{Context, FunctionContext};
@@ -11,9 +12,10 @@ use neon::types::{JsBoolean, JsFunction, JsObject, JsTypedArray, JsUndefined, Js
 
 use crate::database::options::IterationOption;
 use crate::database::reader_writer::{ReaderBase, SharedReaderBase};
+use crate::database::recovery::CallbackGuard;
 use crate::database::types::{Kind, SnapshotMessage};
 use crate::database::utils::*;
-use crate::types::KVPair;
+use crate::types::{KVPair, RecoverLock};
 
 pub type Reader = ReaderBase;
 impl Reader {
@@ -22,22 +24,20 @@ impl Reader {
         key: Vec<u8>,
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<SnapshotMessage>> {
-        let key = Kind::State.key(key);
+        let key = self.db_kind().key(key);
         self.send(move |conn, channel| {
-            let result = conn.get(&key);
+            let callback = CallbackGuard::new(callback, channel);
+            let result = conn.exists(&key);
 
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
                 let this = ctx.undefined();
                 let args: Vec<Handle<JsValue>> = match result {
-                    Ok(Some(_)) => {
-                        vec![ctx.null().upcast(), JsBoolean::new(&mut ctx, true).upcast()]
-                    },
-                    Ok(None) => vec![
+                    Ok(exists) => vec![
                         ctx.null().upcast(),
-                        JsBoolean::new(&mut ctx, false).upcast(),
+                        JsBoolean::new(&mut ctx, exists).upcast(),
                     ],
-                    Err(err) => vec![ctx.error(&err)?.upcast()],
+                    Err(err) => vec![error_with_code(&mut ctx, &err)?.upcast()],
                 };
 
                 callback.call(&mut ctx, this, args)?;
@@ -105,27 +105,32 @@ impl Reader {
             .this()
             .downcast_or_throw::<SharedReaderBase, _>(&mut ctx)?;
         let db = db.borrow();
+        let prefix = db.db_kind().prefix();
+        let has_prefix = *db.db_kind() == Kind::State;
 
         let callback_on_data = Arc::new(Mutex::new(callback_on_data));
         db.send(move |conn, channel| {
-            let conn_iter = conn.iterator(get_iteration_mode(&options, &mut vec![], true));
+            let callback_done = CallbackGuard::new(callback_done, channel);
+            let mut start = vec![];
+            let mode = get_iteration_mode(&options, &mut start, has_prefix);
+            let within_prefix = has_prefix && options.is_within_store_prefix();
+            let conn_iter = conn.iterator_opt(prefix, mode, within_prefix);
             for (counter, key_val) in conn_iter.enumerate() {
                 if is_key_out_of_range(
                     &options,
                     &(key_val.as_ref().unwrap().0),
                     counter as i64,
-                    true,
+                    has_prefix,
                 ) {
                     break;
                 }
                 let callback_on_data = Arc::clone(&callback_on_data);
                 channel.send(move |mut ctx| {
-                    let (_, key_without_prefix) =
-                        key_val.as_ref().unwrap().0.split_first().unwrap();
-                    let temp_pair =
-                        KVPair::new(key_without_prefix, &(key_val.as_ref().unwrap().1));
-                    let obj = pair_to_js_object(&mut ctx, &temp_pair)?;
-                    let callback = callback_on_data.lock().unwrap().to_inner(&mut ctx);
+                    let (key, value) = key_val.unwrap();
+                    let key_without_prefix = &key[prefix.len()..];
+                    let temp_pair = KVPair(key_without_prefix.to_vec(), value.into_vec());
+                    let obj = pair_into_js_object(&mut ctx, temp_pair)?;
+                    let callback = callback_on_data.lock_or_recover().to_inner(&mut ctx);
                     let this = ctx.undefined();
                     let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), obj.upcast()];
                     callback.call(&mut ctx, this, args)?;
@@ -0,0 +1,131 @@
+/// recovery keeps the threads of the database alive after a request panics,
+/// and reports the panic to the JS callback of the request as the error with the code ERR_INTERNAL.
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use neon::context::Context;
+use neon::event::Channel;
+use neon::handle::{Handle, Root};
+use neon::types::{JsFunction, JsValue};
+use thiserror::Error;
+
+use crate::database::utils;
+use crate::error_code::{self, ErrorCode};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Request panicked: {0}")]
+pub struct PanicError(pub String);
+
+impl ErrorCode for PanicError {
+    fn code(&self) -> &'static str {
+        error_code::INTERNAL
+    }
+}
+
+/// panic_message returns the message of the panic, which is either &str or String.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+/// run_request runs the request on the thread of the database.
+/// The panic of the request is logged and returned, so the thread keeps serving the following requests.
+pub fn run_request(thread_name: &str, request: impl FnOnce()) -> Result<(), PanicError> {
+    panic::catch_unwind(AssertUnwindSafe(request)).map_err(|payload| {
+        let err = PanicError(panic_message(payload.as_ref()));
+        tracing::error!(thread = thread_name, "{}", err);
+        err
+    })
+}
+
+/// CallbackGuard holds the callback of the request running on the thread of the database.
+/// If the request panics before the callback is taken, the callback is called with PanicError,
+/// so the caller is not left waiting for the result.
+pub struct CallbackGuard {
+    callback: Option<Root<JsFunction>>,
+    channel: Channel,
+}
+
+impl CallbackGuard {
+    pub fn new(callback: Root<JsFunction>, channel: &Channel) -> Self {
+        Self {
+            callback: Some(callback),
+            channel: channel.clone(),
+        }
+    }
+
+    /// into_inner returns the callback like Root::into_inner.
+    pub fn into_inner<'a, C: Context<'a>>(self, ctx: &mut C) -> Handle<'a, JsFunction> {
+        self.take().into_inner(ctx)
+    }
+
+    /// take returns the callback, which is no longer called on panic.
+    pub fn take(mut self) -> Root<JsFunction> {
+        self.callback
+            .take()
+            .expect("callback is only taken once by value")
+    }
+}
+
+impl Drop for CallbackGuard {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        let callback = match self.callback.take() {
+            Some(callback) => callback,
+            None => return,
+        };
+        // The event loop may already be stopped, and panicking again while unwinding would abort
+        let _ = self.channel.try_send(move |mut ctx| {
+            let callback = callback.into_inner(&mut ctx);
+            let this = ctx.undefined();
+            let err = PanicError(String::from("the result was not returned"));
+            let err = utils::error_with_code(&mut ctx, &err)?;
+            let args: Vec<Handle<JsValue>> = vec![err.upcast()];
+            callback.call(&mut ctx, this, args)?;
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::types::RecoverLock;
+
+    #[test]
+    fn test_run_request() {
+        assert_eq!(run_request("test", || {}), Ok(()));
+        let err = run_request("test", || panic!("request failed")).unwrap_err();
+        assert_eq!(err, PanicError(String::from("request failed")));
+        assert_eq!(err.code(), error_code::INTERNAL);
+        let err = run_request("test", || panic!("{} failed", "request")).unwrap_err();
+        assert_eq!(err.to_string(), "Request panicked: request failed");
+    }
+
+    #[test]
+    fn test_recover_poisoned_lock() {
+        let data = Arc::new(Mutex::new(vec![1]));
+        let cloned = Arc::clone(&data);
+        let result = run_request("test", move || {
+            let mut data = cloned.lock_or_recover();
+            data.push(2);
+            panic!("request failed");
+        });
+        assert!(result.is_err());
+        assert!(data.is_poisoned());
+        // the data written before the panic stays, and the following requests can still use it
+        data.lock_or_recover().push(3);
+        assert_eq!(*data.lock_or_recover(), vec![1, 2, 3]);
+    }
+}
@@ -1,33 +1,52 @@
 /// traits provides common traits for database.
+#[cfg(feature = "neon")]
 use std::cell::RefCell;
-use std::sync::{Arc, Mutex};
+use std::error;
+#[cfg(feature = "neon")]
+use std::sync::{Arc, Mutex, RwLock};
 
+#[cfg(feature = "neon")]
 use neon::context::{Context, FunctionContext};
+#[cfg(feature = "neon")]
 use neon::handle::Handle;
+#[cfg(feature = "neon")]
 use neon::result::JsResult;
+#[cfg(feature = "neon")]
 use neon::types::{Finalize, JsNumber, JsString, JsValue};
 
-use crate::database::types::{DbOptions, JsArcMutex, JsBoxRef, Kind};
+#[cfg(feature = "neon")]
+use crate::database::lock::OpenError;
+#[cfg(feature = "neon")]
+use crate::database::types::{DbOptions, JsArcMutex, JsArcRwLock, JsBoxRef, Kind};
+#[cfg(feature = "neon")]
+use crate::database::utils;
 use crate::types::{KVPair, KeyLength, VecOption};
 
+#[cfg(feature = "neon")]
 pub trait Unwrap {
     fn unwrap(&self) -> &rocksdb::DB;
 }
 
+/// Actions is the storage of the SMT nodes.
+/// The error of the backend is reported by the tree as SMTError::Unknown with its message.
 pub trait Actions {
-    fn get(&self, key: &[u8]) -> Result<VecOption, rocksdb::Error>;
-    fn set(&mut self, pair: &KVPair) -> Result<(), rocksdb::Error>;
-    fn del(&mut self, key: &[u8]) -> Result<(), rocksdb::Error>;
+    type Error: error::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error>;
+    fn set(&mut self, pair: &KVPair) -> Result<(), Self::Error>;
+    fn del(&mut self, key: &[u8]) -> Result<(), Self::Error>;
 }
 
 pub trait NewDBWithKeyLength {
     fn new_db_with_key_length(len: Option<KeyLength>) -> Self;
 }
 
+#[cfg(feature = "neon")]
 pub trait DatabaseKind {
     fn db_kind() -> Kind;
 }
 
+#[cfg(feature = "neon")]
 pub trait OptionsWithContext {
     fn new_with_context<'a, C>(
         ctx: &mut C,
@@ -38,18 +57,20 @@ pub trait OptionsWithContext {
         Self: Sized;
 }
 
+#[cfg(feature = "neon")]
 pub trait NewDBWithContext {
     fn new_db_with_context<'a, C>(
         ctx: &mut C,
         path: String,
         opts: DbOptions,
         db_kind: Kind,
-    ) -> Result<Self, rocksdb::Error>
+    ) -> Result<Self, OpenError>
     where
         C: Context<'a>,
         Self: Sized;
 }
 
+#[cfg(feature = "neon")]
 pub trait JsNewWithBoxRef {
     fn js_new_with_box_ref<T: OptionsWithContext, U: NewDBWithContext + Send + Finalize>(
         mut ctx: FunctionContext,
@@ -58,13 +79,14 @@ pub trait JsNewWithBoxRef {
         let options = ctx.argument_opt(1);
         let db_opts = T::new_with_context(&mut ctx, options)?;
         let db = U::new_db_with_context(&mut ctx, path, db_opts, Kind::Normal)
-            .or_else(|err| ctx.throw_error(&err))?;
+            .or_else(|err| utils::throw_open_error(&mut ctx, err))?;
         let ref_db = RefCell::new(db);
 
         return Ok(ctx.boxed(ref_db));
     }
 }
 
+#[cfg(feature = "neon")]
 pub trait JsNewWithArcMutex {
     fn js_new_with_arc_mutex<T: NewDBWithKeyLength + Send + Finalize + DatabaseKind>(
         mut ctx: FunctionContext,
@@ -78,3 +100,18 @@ pub trait JsNewWithArcMutex {
         return Ok(ctx.boxed(ref_tree));
     }
 }
+
+#[cfg(feature = "neon")]
+pub trait JsNewWithArcRwLock {
+    fn js_new_with_arc_rw_lock<T: NewDBWithKeyLength + Send + Sync + Finalize + DatabaseKind>(
+        mut ctx: FunctionContext,
+    ) -> JsResult<JsArcRwLock<T>> {
+        let key_length = if T::db_kind() == Kind::InMemorySMT {
+            Some(ctx.argument::<JsNumber>(0)?.value(&mut ctx).into())
+        } else {
+            None
+        };
+        let ref_tree = RefCell::new(Arc::new(RwLock::new(T::new_db_with_key_length(key_length))));
+        Ok(ctx.boxed(ref_tree))
+    }
+}
@@ -0,0 +1,246 @@
+/// transaction provides a read-your-writes view over the database.
+/// The writes of the transaction are kept in memory and layered over the persistent data for reads and iteration,
+/// and they are written to the database atomically on commit.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+
+use crate::database::options::IterationOption;
+use crate::database::types::JsArcMutex;
+use crate::database::utils;
+use crate::memory;
+use crate::types::{KVPair, RecoverLock};
+
+pub type SendableTransaction = JsArcMutex<Transaction>;
+
+/// Transaction holds the uncommitted writes. A deleted key is kept with None as a value,
+/// so the value in the database is hidden until the transaction is committed.
+pub struct Transaction {
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    usage: memory::Usage,
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Self {
+            writes: BTreeMap::new(),
+            usage: memory::Usage::new(memory::Component::WriteBatch),
+        }
+    }
+}
+
+impl Finalize for Transaction {}
+
+fn in_range(options: &IterationOption, key: &[u8]) -> bool {
+    if let Some(gte) = &options.gte {
+        if key < gte.as_slice() {
+            return false;
+        }
+    }
+    if let Some(lte) = &options.lte {
+        if key > lte.as_slice() {
+            return false;
+        }
+    }
+    true
+}
+
+impl Transaction {
+    fn update_usage(&mut self) {
+        let size = self
+            .writes
+            .iter()
+            .map(|(key, value)| key.len() + value.as_ref().map_or(0, |val| val.len()))
+            .sum();
+        self.usage.set(size);
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.writes.insert(key, Some(value));
+        self.update_usage();
+    }
+
+    pub fn del(&mut self, key: Vec<u8>) {
+        self.writes.insert(key, None);
+        self.update_usage();
+    }
+
+    /// get returns the value written in the transaction, or the value in the database if the key is not written.
+    pub fn get(&self, conn: &rocksdb::DB, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        match self.writes.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => conn.get(key),
+        }
+    }
+
+    /// range returns the pairs in the range of the options with the writes of the transaction applied.
+    pub fn range(
+        &self,
+        conn: &rocksdb::DB,
+        options: &IterationOption,
+    ) -> Result<Vec<KVPair>, rocksdb::Error> {
+        let written: Vec<(&Vec<u8>, &Option<Vec<u8>>)> = self
+            .writes
+            .iter()
+            .filter(|(key, _)| in_range(options, key))
+            .collect();
+        // Each deletion can hide at most one pair in the database, so reading that many more pairs is enough
+        let mut db_options = options.clone();
+        if db_options.limit != -1 {
+            db_options.limit += written.iter().filter(|(_, value)| value.is_none()).count() as i64;
+        }
+
+        let mut merged = BTreeMap::new();
+        let mut opt = vec![];
+        let iter = conn.iterator(utils::get_iteration_mode(&db_options, &mut opt, false));
+        for (counter, key_val) in iter.enumerate() {
+            let (key, value) = key_val?;
+            if utils::is_key_out_of_range(&db_options, &key, counter as i64, false) {
+                break;
+            }
+            merged.insert(key.into_vec(), value.into_vec());
+        }
+        for (key, value) in written {
+            match value {
+                Some(value) => merged.insert(key.clone(), value.clone()),
+                None => merged.remove(key),
+            };
+        }
+
+        let pairs = merged.into_iter().map(|(key, value)| KVPair(key, value));
+        let limit = if options.limit == -1 {
+            usize::MAX
+        } else {
+            options.limit as usize
+        };
+        if options.reverse {
+            Ok(pairs.rev().take(limit).collect())
+        } else {
+            Ok(pairs.take(limit).collect())
+        }
+    }
+
+    /// take_batch moves the writes of the transaction into a batch, leaving the transaction empty.
+    pub fn take_batch(&mut self) -> rocksdb::WriteBatch {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (key, value) in std::mem::take(&mut self.writes) {
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+        self.update_usage();
+        batch
+    }
+
+    /// js_new is handler for JS ffi.
+    /// - @returns - Transaction without any write.
+    pub fn js_new(mut ctx: FunctionContext) -> JsResult<SendableTransaction> {
+        Ok(ctx.boxed(RefCell::new(Arc::new(Mutex::new(Transaction::default())))))
+    }
+
+    /// js_set is handler for JS ffi.
+    /// js "this" - Transaction.
+    /// - @params(0) - key to set.
+    /// - @params(1) - value to set.
+    pub fn js_set(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let transaction = ctx
+            .this()
+            .downcast_or_throw::<SendableTransaction, _>(&mut ctx)?;
+
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let transaction = transaction.borrow();
+        transaction.lock_or_recover().set(key, value);
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_del is handler for JS ffi.
+    /// js "this" - Transaction.
+    /// - @params(0) - key to delete.
+    pub fn js_del(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let transaction = ctx
+            .this()
+            .downcast_or_throw::<SendableTransaction, _>(&mut ctx)?;
+
+        memory::reserve(key.len()).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let transaction = transaction.borrow();
+        transaction.lock_or_recover().del(key);
+
+        Ok(ctx.undefined())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn options(
+        gte: Option<Vec<u8>>,
+        lte: Option<Vec<u8>>,
+        reverse: bool,
+        limit: i64,
+    ) -> IterationOption {
+        IterationOption {
+            limit,
+            reverse,
+            gte,
+            lte,
+            gt: None,
+            lt: None,
+        }
+    }
+
+    #[test]
+    fn test_read_your_writes() {
+        let temp_dir = TempDir::new("test_transaction").unwrap();
+        let conn = rocksdb::DB::open_default(&temp_dir).unwrap();
+        for i in 0..5u8 {
+            conn.put([i], [i]).unwrap();
+        }
+
+        let mut transaction = Transaction::default();
+        transaction.set(vec![1], vec![10]);
+        transaction.del(vec![2]);
+        transaction.set(vec![7], vec![7]);
+
+        assert_eq!(transaction.get(&conn, &[0]).unwrap(), Some(vec![0]));
+        assert_eq!(transaction.get(&conn, &[1]).unwrap(), Some(vec![10]));
+        assert_eq!(transaction.get(&conn, &[2]).unwrap(), None);
+        assert_eq!(transaction.get(&conn, &[7]).unwrap(), Some(vec![7]));
+        assert_eq!(conn.get([1]).unwrap(), Some(vec![1]));
+
+        let keys = |pairs: Vec<KVPair>| -> Vec<Vec<u8>> {
+            pairs.iter().map(|pair| pair.key_as_vec()).collect()
+        };
+        let all = transaction
+            .range(&conn, &options(None, None, false, -1))
+            .unwrap();
+        assert_eq!(keys(all), vec![vec![0], vec![1], vec![3], vec![4], vec![7]]);
+        let limited = transaction
+            .range(&conn, &options(Some(vec![1]), Some(vec![4]), false, 2))
+            .unwrap();
+        assert_eq!(limited[0].value_as_vec(), vec![10]);
+        assert_eq!(keys(limited), vec![vec![1], vec![3]]);
+        let reversed = transaction
+            .range(&conn, &options(Some(vec![1]), Some(vec![9]), true, 3))
+            .unwrap();
+        assert_eq!(keys(reversed), vec![vec![7], vec![4], vec![3]]);
+
+        conn.write(transaction.take_batch()).unwrap();
+        assert_eq!(conn.get([1]).unwrap(), Some(vec![10]));
+        assert_eq!(conn.get([2]).unwrap(), None);
+        assert_eq!(conn.get([7]).unwrap(), Some(vec![7]));
+        assert_eq!(transaction.get(&conn, &[1]).unwrap(), Some(vec![10]));
+        assert!(transaction.take_batch().is_empty());
+    }
+}
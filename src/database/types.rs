@@ -1,20 +1,28 @@
 use std::cell::RefCell;
 use std::sync::Arc;
+use std::time::Duration;
 
 use neon::event::Channel;
 use neon::types::JsBox;
 
 use crate::consts::Prefix;
-use crate::types::{ArcMutex, KeyLength, Options};
+use crate::database::column_family;
+use crate::metrics;
+use crate::types::{ArcMutex, ArcRwLock, KeyLength};
 
-type SnapshotCallback = Box<dyn FnOnce(&rocksdb::Snapshot, &Channel) + Send>;
+type SnapshotCallback = Box<dyn FnOnce(&column_family::Snapshot, &Channel) + Send>;
 type DbCallback = Box<dyn FnOnce(&Channel) + Send>;
-pub type DbOptions = Options<KeyLength>;
 
 pub type JsBoxRef<T> = JsBox<RefCell<T>>;
 pub type JsArcMutex<T> = JsBoxRef<ArcMutex<T>>;
+pub type JsArcRwLock<T> = JsBoxRef<ArcRwLock<T>>;
 pub type ArcOptionDB = Arc<Option<rocksdb::DB>>;
 
+/// RATE_LIMITER_REFILL_PERIOD is the period in microseconds to refill the rate limiter, which is the default of RocksDB.
+const RATE_LIMITER_REFILL_PERIOD: i64 = 100_000;
+/// RATE_LIMITER_FAIRNESS is the chance of the low priority requests to be served first, which is the default of RocksDB.
+const RATE_LIMITER_FAIRNESS: i32 = 10;
+
 /// Messages sent on the database channel
 pub enum Message<T> {
     /// Callback to be executed
@@ -26,6 +34,57 @@ pub enum Message<T> {
 pub type SnapshotMessage = Message<SnapshotCallback>;
 pub type DbMessage = Message<DbCallback>;
 
+/// DbOptions holds the options used to open the database.
+#[derive(Debug, Copy, Clone)]
+pub struct DbOptions {
+    readonly: bool,
+    key_length: KeyLength,
+    keep_root_history: bool,
+    audit_deletion: bool,
+    rollback_protection: bool,
+    lock_timeout: Option<Duration>,
+    tuning: TuningOptions,
+    diff_retention: Option<u32>,
+    root_history_retention: Option<u32>,
+    smt_key_filter: bool,
+    store_roots: bool,
+    structure_log: bool,
+    metrics: metrics::Instance,
+}
+
+/// Compression is the compression type of the blocks written to the disk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+/// TuningOptions holds the RocksDB options used to open the database.
+/// RocksDB default is used for the option which is not set.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TuningOptions {
+    pub block_cache_size: Option<usize>,
+    pub compression: Option<Compression>,
+    pub max_open_files: Option<i32>,
+    pub write_buffer_size: Option<usize>,
+    /// rate_limit is the bytes per second written by the flush and the compaction in the background.
+    pub rate_limit: Option<i64>,
+    /// parallelism is the number of the threads of the flush and the compaction in the background.
+    pub parallelism: Option<i32>,
+    pub max_background_compactions: Option<i32>,
+    pub max_background_flushes: Option<i32>,
+    /// direct_reads reads the files bypassing the page cache of the OS, so the block cache should be large enough.
+    pub direct_reads: bool,
+    /// cache_index_and_filter_blocks keeps the index and the filter blocks in the block cache instead of the heap,
+    /// so that their memory is bounded by the block cache.
+    pub cache_index_and_filter_blocks: bool,
+    /// pin_l0_filter_and_index_blocks keeps the index and the filter blocks of the level 0 files in the block cache.
+    pub pin_l0_filter_and_index_blocks: bool,
+    /// in_memory keeps the files of the database in memory instead of the path, and they are dropped on close.
+    pub in_memory: bool,
+}
+
 /// Kind represented the kind of the database
 #[derive(PartialEq, Eq)]
 pub enum Kind {
@@ -37,13 +96,269 @@ pub enum Kind {
 }
 
 impl DbOptions {
+    #[inline]
+    pub fn new(readonly: bool, key_length: KeyLength) -> Self {
+        Self {
+            readonly,
+            key_length,
+            keep_root_history: false,
+            audit_deletion: false,
+            rollback_protection: false,
+            lock_timeout: None,
+            tuning: TuningOptions::default(),
+            diff_retention: None,
+            root_history_retention: None,
+            smt_key_filter: false,
+            store_roots: false,
+            structure_log: false,
+            metrics: metrics::Instance::UNTAGGED,
+        }
+    }
+
+    /// with_keep_root_history returns the options which retain the nodes of the root of each height.
+    #[inline]
+    pub fn with_keep_root_history(mut self, enabled: bool) -> Self {
+        self.keep_root_history = enabled;
+        self
+    }
+
+    /// with_audit_deletion returns the options which record the removal of the diffs and the SMT nodes.
+    #[inline]
+    pub fn with_audit_deletion(mut self, enabled: bool) -> Self {
+        self.audit_deletion = enabled;
+        self
+    }
+
+    /// with_rollback_protection returns the options which refuse writing or reverting at or below the finalized height.
+    #[inline]
+    pub fn with_rollback_protection(mut self, enabled: bool) -> Self {
+        self.rollback_protection = enabled;
+        self
+    }
+
+    /// with_lock_timeout returns the options which wait for the lock held by another process up to `timeout` on open.
+    #[inline]
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// with_tuning returns the options with the RocksDB options used to open the database.
+    #[inline]
+    pub fn with_tuning(mut self, tuning: TuningOptions) -> Self {
+        self.tuning = tuning;
+        self
+    }
+
+    /// with_diff_retention returns the options which keep only the diffs of the latest `heights` heights.
+    #[inline]
+    pub fn with_diff_retention(mut self, heights: Option<u32>) -> Self {
+        self.diff_retention = heights;
+        self
+    }
+
+    /// with_root_history_retention returns the options which keep only the nodes of the roots of the latest `heights` heights
+    /// in the root history.
+    #[inline]
+    pub fn with_root_history_retention(mut self, heights: Option<u32>) -> Self {
+        self.root_history_retention = heights;
+        self
+    }
+
+    /// with_smt_key_filter returns the options which keep the bloom filter of the leaf keys of each root.
+    #[inline]
+    pub fn with_smt_key_filter(mut self, enabled: bool) -> Self {
+        self.smt_key_filter = enabled;
+        self
+    }
+
+    /// with_store_roots returns the options which keep the sub-root of each store prefix updated by the commit.
+    #[inline]
+    pub fn with_store_roots(mut self, enabled: bool) -> Self {
+        self.store_roots = enabled;
+        self
+    }
+
+    /// with_structure_log returns the options which record the SMT nodes created and deleted by each commit.
+    #[inline]
+    pub fn with_structure_log(mut self, enabled: bool) -> Self {
+        self.structure_log = enabled;
+        self
+    }
+
+    /// with_metrics returns the options which tag the metrics of the database with the instance.
+    #[inline]
+    pub fn with_metrics(mut self, instance: metrics::Instance) -> Self {
+        self.metrics = instance;
+        self
+    }
+
+    #[inline]
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
     #[inline]
     pub fn key_length(&self) -> KeyLength {
-        self.number
+        self.key_length
+    }
+
+    /// keep_root_history returns true if the root of each height should be retained with its nodes.
+    #[inline]
+    pub fn keep_root_history(&self) -> bool {
+        self.keep_root_history
+    }
+
+    /// audit_deletion returns true if the removal of the diffs and the SMT nodes should be recorded.
+    #[inline]
+    pub fn audit_deletion(&self) -> bool {
+        self.audit_deletion
+    }
+
+    /// rollback_protection returns true if writing or reverting at or below the finalized height should be refused.
+    #[inline]
+    pub fn rollback_protection(&self) -> bool {
+        self.rollback_protection
+    }
+
+    /// lock_timeout returns how long opening should wait for the lock held by another process.
+    #[inline]
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    #[inline]
+    pub fn tuning(&self) -> TuningOptions {
+        self.tuning
+    }
+
+    /// diff_retention returns the number of the latest heights whose diffs are kept on commit.
+    /// All the diffs are kept when it is None.
+    #[inline]
+    pub fn diff_retention(&self) -> Option<u32> {
+        self.diff_retention
+    }
+
+    /// root_history_retention returns the number of the latest heights whose nodes are kept by keepRootHistory.
+    /// The nodes of all the roots are kept when it is None.
+    #[inline]
+    pub fn root_history_retention(&self) -> Option<u32> {
+        self.root_history_retention
+    }
+
+    /// smt_key_filter returns true if the lookup of the SMT key should be skipped when the key filter rules it out.
+    #[inline]
+    pub fn smt_key_filter(&self) -> bool {
+        self.smt_key_filter
+    }
+
+    /// store_roots returns true if the sub-roots of the store prefixes should be written on commit.
+    #[inline]
+    pub fn store_roots(&self) -> bool {
+        self.store_roots
+    }
+
+    /// structure_log returns true if the SMT nodes created and deleted by the commit should be recorded.
+    #[inline]
+    pub fn structure_log(&self) -> bool {
+        self.structure_log
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> metrics::Instance {
+        self.metrics
+    }
+}
+
+impl Compression {
+    /// from_name returns the compression for the name used in JS.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "lz4" => Some(Compression::Lz4),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+impl From<Compression> for rocksdb::DBCompressionType {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => rocksdb::DBCompressionType::None,
+            Compression::Lz4 => rocksdb::DBCompressionType::Lz4,
+            Compression::Zstd => rocksdb::DBCompressionType::Zstd,
+        }
+    }
+}
+
+impl TuningOptions {
+    /// rocksdb_options returns the RocksDB options to open the database, which creates it if missing.
+    pub fn rocksdb_options(&self) -> Result<rocksdb::Options, rocksdb::Error> {
+        let mut option = rocksdb::Options::default();
+        option.create_if_missing(true);
+        option.set_block_based_table_factory(&self.block_options(false)?);
+        if let Some(compression) = self.compression {
+            option.set_compression_type(compression.into());
+        }
+        if let Some(max_open_files) = self.max_open_files {
+            option.set_max_open_files(max_open_files);
+        }
+        if let Some(size) = self.write_buffer_size {
+            option.set_write_buffer_size(size);
+        }
+        if let Some(rate) = self.rate_limit {
+            option.set_ratelimiter(rate, RATE_LIMITER_REFILL_PERIOD, RATE_LIMITER_FAIRNESS);
+        }
+        // RocksDB runs a single flush and a single compaction by default
+        if let Some(parallelism) = self.parallelism {
+            option.increase_parallelism(parallelism);
+        }
+        if let Some(compactions) = self.max_background_compactions {
+            option.set_max_background_compactions(compactions);
+        }
+        if let Some(flushes) = self.max_background_flushes {
+            option.set_max_background_flushes(flushes);
+        }
+        if self.in_memory {
+            // each database gets its own environment, so that the databases opened at the same path are separated
+            option.set_env(&rocksdb::Env::mem_env()?);
+        } else if self.direct_reads {
+            option.set_use_direct_reads(true);
+        }
+
+        Ok(option)
+    }
+
+    /// block_options returns the options of the blocks of the files, with the bloom filter of the keys if `bloom_filter` is true.
+    pub fn block_options(
+        &self,
+        bloom_filter: bool,
+    ) -> Result<rocksdb::BlockBasedOptions, rocksdb::Error> {
+        let mut block_options = rocksdb::BlockBasedOptions::default();
+        if let Some(size) = self.block_cache_size {
+            block_options.set_block_cache(&rocksdb::Cache::new_lru_cache(size)?);
+        }
+        if bloom_filter {
+            block_options.set_bloom_filter(10.0, false);
+        }
+        block_options.set_cache_index_and_filter_blocks(self.cache_index_and_filter_blocks);
+        block_options
+            .set_pin_l0_filter_and_index_blocks_in_cache(self.pin_l0_filter_and_index_blocks);
+
+        Ok(block_options)
     }
 }
 
 impl Kind {
+    /// prefix returns the prefix of the keys given by the user.
+    pub fn prefix(&self) -> &'static [u8] {
+        match self {
+            Kind::State => Prefix::STATE,
+            _ => &[],
+        }
+    }
+
     pub fn key(&self, key: Vec<u8>) -> Vec<u8> {
         match self {
             Kind::State => [Prefix::STATE, &key].concat(),
@@ -1,16 +1,24 @@
 /// utils provides common functionalities used in the DB, such as parsing JS context, iteration checks.
 use std::cmp;
+use std::fmt;
 
-use neon::context::Context;
-use neon::handle::Handle;
+use neon::context::{Context, FunctionContext};
+use neon::handle::{Handle, Root};
 use neon::object::Object;
 use neon::result::NeonResult;
-use neon::types::{JsArray, JsBuffer, JsObject, JsValue};
+use neon::types::buffer::TypedArray;
+use neon::types::{JsArray, JsBuffer, JsError, JsFunction, JsObject, JsTypedArray, JsValue};
 
 use crate::consts::Prefix;
+use crate::database::lock::OpenError;
 use crate::database::options;
+use crate::database::properties::{self, Properties, RangeSize, WriteStall};
+use crate::diff::{Diff, DiffStats, EntryStats};
+use crate::error_code::{self, ErrorCode};
+use crate::sparse_merkle_tree::witness::WitnessEntry;
+use crate::sparse_merkle_tree::{DumpNode, PrefixNode, PrefixProof, Proof};
 use crate::state_writer;
-use crate::types::{Cache, KVPair};
+use crate::types::{KVPair, NestedVec};
 use crate::utils::compare;
 
 pub fn pair_to_js_object<'a, C: Context<'a>>(
@@ -26,38 +34,377 @@ pub fn pair_to_js_object<'a, C: Context<'a>>(
     Ok(obj)
 }
 
+/// pair_into_js_object converts the pair to { key, value }, moving the key and the value
+/// into the buffers instead of copying them.
+pub fn pair_into_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    pair: KVPair,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let (key, value) = pair.into_parts();
+    let obj = ctx.empty_object();
+    let key = JsBuffer::external(ctx, key);
+    obj.set(ctx, "key", key)?;
+    let value = JsBuffer::external(ctx, value);
+    obj.set(ctx, "value", value)?;
+
+    Ok(obj)
+}
+
+pub fn proof_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    proof: &Proof,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let sibling_hashes = ctx.empty_array();
+    for (i, h) in proof.sibling_hashes.iter().enumerate() {
+        let hash = JsBuffer::external(ctx, h.to_vec());
+        sibling_hashes.set(ctx, i as u32, hash)?;
+    }
+    obj.set(ctx, "siblingHashes", sibling_hashes)?;
+    let queries = ctx.empty_array();
+    for (i, v) in proof.queries.iter().enumerate() {
+        let query = pair_to_js_object(ctx, &v.pair)?;
+        let bitmap = JsBuffer::external(ctx, v.bitmap.to_vec());
+        query.set(ctx, "bitmap", bitmap)?;
+        if let Some(stored_value) = &v.stored_value {
+            let stored_value = JsBuffer::external(ctx, stored_value.to_vec());
+            query.set(ctx, "storedValue", stored_value)?;
+        }
+        queries.set(ctx, i as u32, query)?;
+    }
+    obj.set(ctx, "queries", queries)?;
+
+    Ok(obj)
+}
+
+/// prefix_proof_to_js_object converts the proof of the prefix to
+/// { prefix, subtreeRoot, node: { kind, key?, value?, hash? }, siblingHashes }.
+pub fn prefix_proof_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    proof: &PrefixProof,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let prefix = JsBuffer::external(ctx, proof.prefix.to_vec());
+    obj.set(ctx, "prefix", prefix)?;
+    let subtree_root = JsBuffer::external(ctx, proof.subtree_root());
+    obj.set(ctx, "subtreeRoot", subtree_root)?;
+    let node = match &proof.node {
+        PrefixNode::Empty => {
+            let node = ctx.empty_object();
+            let kind = ctx.string("empty");
+            node.set(ctx, "kind", kind)?;
+            node
+        },
+        PrefixNode::Leaf { key, value } => {
+            let node = pair_to_js_object(ctx, &KVPair::new(key, value))?;
+            let kind = ctx.string("leaf");
+            node.set(ctx, "kind", kind)?;
+            node
+        },
+        PrefixNode::Branch(hash) => {
+            let node = ctx.empty_object();
+            let kind = ctx.string("branch");
+            node.set(ctx, "kind", kind)?;
+            let hash = JsBuffer::external(ctx, hash.to_vec());
+            node.set(ctx, "hash", hash)?;
+            node
+        },
+    };
+    obj.set(ctx, "node", node)?;
+    let sibling_hashes = ctx.empty_array();
+    for (i, h) in proof.sibling_hashes.iter().enumerate() {
+        let hash = JsBuffer::external(ctx, h.to_vec());
+        sibling_hashes.set(ctx, i as u32, hash)?;
+    }
+    obj.set(ctx, "siblingHashes", sibling_hashes)?;
+
+    Ok(obj)
+}
+
+/// witness_entries_to_js_array converts the entries of the witness to { key, value? }[].
+/// The value is not set for the key which does not exist in the state.
+pub fn witness_entries_to_js_array<'a, C: Context<'a>>(
+    ctx: &mut C,
+    entries: Vec<WitnessEntry>,
+) -> NeonResult<Handle<'a, JsArray>> {
+    let array = ctx.empty_array();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let obj = ctx.empty_object();
+        let key = JsBuffer::external(ctx, entry.key);
+        obj.set(ctx, "key", key)?;
+        if let Some(value) = entry.value {
+            let value = JsBuffer::external(ctx, value);
+            obj.set(ctx, "value", value)?;
+        }
+        array.set(ctx, i as u32, obj)?;
+    }
+
+    Ok(array)
+}
+
+/// dump_to_js_array converts the nodes of the tree dump to { type, height, hash, key, value }[].
+pub fn dump_to_js_array<'a, C: Context<'a>>(
+    ctx: &mut C,
+    nodes: &[DumpNode],
+) -> NeonResult<Handle<'a, JsArray>> {
+    let arr = ctx.empty_array();
+    for (i, node) in nodes.iter().enumerate() {
+        let obj = ctx.empty_object();
+        let kind = ctx.string(node.kind.name());
+        obj.set(ctx, "type", kind)?;
+        let height = ctx.number(node.height as f64);
+        obj.set(ctx, "height", height)?;
+        let hash = JsBuffer::external(ctx, node.hash.to_vec());
+        obj.set(ctx, "hash", hash)?;
+        let key = JsBuffer::external(ctx, node.key.to_vec());
+        obj.set(ctx, "key", key)?;
+        let value = JsBuffer::external(ctx, node.value.to_vec());
+        obj.set(ctx, "value", value)?;
+        arr.set(ctx, i as u32, obj)?;
+    }
+
+    Ok(arr)
+}
+
+fn properties_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    properties: &Properties,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let estimated_keys = ctx.number(properties.estimated_keys as f64);
+    obj.set(ctx, "estimatedKeys", estimated_keys)?;
+    let total_sst_size = ctx.number(properties.total_sst_size as f64);
+    obj.set(ctx, "totalSstSize", total_sst_size)?;
+    let memtable_usage = ctx.number(properties.memtable_usage as f64);
+    obj.set(ctx, "memtableUsage", memtable_usage)?;
+    let pending_compaction_bytes = ctx.number(properties.pending_compaction_bytes as f64);
+    obj.set(ctx, "pendingCompactionBytes", pending_compaction_bytes)?;
+
+    Ok(obj)
+}
+
+/// column_family_properties_to_js_object returns the total properties with the properties of each column family.
+pub fn column_family_properties_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    column_families: &[(&str, Properties)],
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = properties_to_js_object(ctx, &properties::total(column_families))?;
+    let cf_obj = ctx.empty_object();
+    for (name, properties) in column_families {
+        let value = properties_to_js_object(ctx, properties)?;
+        cf_obj.set(ctx, *name, value)?;
+    }
+    obj.set(ctx, "columnFamilies", cf_obj)?;
+
+    Ok(obj)
+}
+
+/// range_size_to_js_object returns the estimated size and the number of the keys in the range.
+pub fn range_size_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    size: &RangeSize,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let bytes = ctx.number(size.size as f64);
+    obj.set(ctx, "size", bytes)?;
+    let estimated_keys = ctx.number(size.estimated_keys as f64);
+    obj.set(ctx, "estimatedKeys", estimated_keys)?;
+
+    Ok(obj)
+}
+
+/// write_stall_to_js_object returns the condition of the writes with the rate of the delayed writes.
+pub fn write_stall_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    stall: &WriteStall,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let condition = ctx.string(stall.name());
+    obj.set(ctx, "condition", condition)?;
+    let rate = match stall {
+        WriteStall::Delayed(rate) => *rate,
+        _ => 0,
+    };
+    let delayed_write_rate = ctx.number(rate as f64);
+    obj.set(ctx, "delayedWriteRate", delayed_write_rate)?;
+
+    Ok(obj)
+}
+
+/// throw_open_error throws the error of opening the database.
+/// When the database is locked, the error has the code ALREADY_LOCKED with the path and the pid of the lock holder.
+pub fn throw_open_error<'a, C: Context<'a>, T>(ctx: &mut C, err: OpenError) -> NeonResult<T> {
+    let js_err = ctx.error(err.to_string())?;
+    if let OpenError::AlreadyLocked { path, pid } = err {
+        let code = ctx.string("ALREADY_LOCKED");
+        js_err.set(ctx, "code", code)?;
+        let path = ctx.string(path);
+        js_err.set(ctx, "path", path)?;
+        let pid: Handle<JsValue> = match pid {
+            Some(pid) => ctx.number(pid).upcast(),
+            None => ctx.null().upcast(),
+        };
+        js_err.set(ctx, "pid", pid)?;
+    }
+    ctx.throw(js_err)
+}
+
+/// error_with_code creates the error with the message and the stable code of the error as the `code` property.
+pub fn error_with_code<'a, C: Context<'a>, E: fmt::Display + ErrorCode>(
+    ctx: &mut C,
+    err: &E,
+) -> NeonResult<Handle<'a, JsError>> {
+    let js_err = ctx.error(err.to_string())?;
+    let code = ctx.string(err.code());
+    js_err.set(ctx, "code", code)?;
+    Ok(js_err)
+}
+
+/// throw_with_code throws the error created by error_with_code.
+pub fn throw_with_code<'a, C: Context<'a>, E: fmt::Display + ErrorCode, T>(
+    ctx: &mut C,
+    err: E,
+) -> NeonResult<T> {
+    let js_err = error_with_code(ctx, &err)?;
+    ctx.throw(js_err)
+}
+
+/// not_found_error creates the error of the missing data with the code ERR_NOT_FOUND.
+/// The message is kept as "No data" for the callers checking the message.
+pub fn not_found_error<'a, C: Context<'a>>(ctx: &mut C) -> NeonResult<Handle<'a, JsError>> {
+    let js_err = ctx.error("No data")?;
+    let code = ctx.string(error_code::NOT_FOUND);
+    js_err.set(ctx, "code", code)?;
+    Ok(js_err)
+}
+
 pub fn parse_update_result<'a, C: Context<'a>>(
     ctx: &mut C,
     result: Result<(), state_writer::StateWriterError>,
 ) -> NeonResult<Vec<Handle<'a, JsValue>>> {
-    if result.is_err() {
-        let err = result.err().unwrap().to_string();
-        Ok(vec![ctx.error(err)?.upcast()])
+    if let Err(err) = result {
+        Ok(vec![error_with_code(ctx, &err)?.upcast()])
     } else {
         Ok(vec![ctx.null().upcast()])
     }
 }
 
-pub fn cache_to_js_array<'a, C: Context<'a>>(
+/// pairs_to_js_array converts the pairs to { key, value }[] keeping the order of the pairs.
+pub fn pairs_to_js_array<'a, C: Context<'a>>(
     ctx: &mut C,
-    values: &Cache,
+    pairs: &[KVPair],
 ) -> NeonResult<Handle<'a, JsArray>> {
     let res_values = ctx.empty_array();
-    for (i, kv) in values.iter().enumerate() {
-        let temp_pair = KVPair::new(kv.0, kv.1);
-        let object = pair_to_js_object(ctx, &temp_pair)?;
+    for (i, pair) in pairs.iter().enumerate() {
+        let object = pair_to_js_object(ctx, pair)?;
         res_values.set(ctx, i as u32, object)?;
     }
 
     Ok(res_values)
 }
 
+/// pairs_into_js_array converts the pairs to { key, value }[] like pairs_to_js_array,
+/// moving the pairs into the buffers instead of copying them.
+pub fn pairs_into_js_array<'a, C: Context<'a>>(
+    ctx: &mut C,
+    pairs: Vec<KVPair>,
+) -> NeonResult<Handle<'a, JsArray>> {
+    let res_values = ctx.empty_array();
+    for (i, pair) in pairs.into_iter().enumerate() {
+        let object = pair_into_js_object(ctx, pair)?;
+        res_values.set(ctx, i as u32, object)?;
+    }
+
+    Ok(res_values)
+}
+
+/// diff_to_js_object converts the diff to { created, updated, deleted }.
+/// The updated and deleted are { key, value }[] with the original values.
+pub fn diff_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    diff: &Diff,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let created = ctx.empty_array();
+    for (i, key) in diff.created().iter().enumerate() {
+        let key = JsBuffer::external(ctx, key.to_vec());
+        created.set(ctx, i as u32, key)?;
+    }
+    obj.set(ctx, "created", created)?;
+    let updated = pairs_to_js_array(ctx, diff.updated())?;
+    obj.set(ctx, "updated", updated)?;
+    let deleted = pairs_to_js_array(ctx, diff.deleted())?;
+    obj.set(ctx, "deleted", deleted)?;
+
+    Ok(obj)
+}
+
+fn entry_stats_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    stats: &EntryStats,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let count = ctx.number(stats.count as f64);
+    obj.set(ctx, "count", count)?;
+    let bytes = ctx.number(stats.bytes as f64);
+    obj.set(ctx, "bytes", bytes)?;
+
+    Ok(obj)
+}
+
+/// diff_stats_to_js_object converts the statistics of the diff to { created, updated, deleted } of { count, bytes }.
+pub fn diff_stats_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    stats: &DiffStats,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let created = entry_stats_to_js_object(ctx, &stats.created)?;
+    obj.set(ctx, "created", created)?;
+    let updated = entry_stats_to_js_object(ctx, &stats.updated)?;
+    obj.set(ctx, "updated", updated)?;
+    let deleted = entry_stats_to_js_object(ctx, &stats.deleted)?;
+    obj.set(ctx, "deleted", deleted)?;
+
+    Ok(obj)
+}
+
+/// bytes_argument_opt parses the optional bytes at the position of the arguments.
+/// It returns None when the argument is not given, null or undefined.
+pub fn bytes_argument_opt(ctx: &mut FunctionContext, pos: i32) -> Option<Vec<u8>> {
+    ctx.argument_opt(pos)
+        .and_then(|val| val.downcast::<JsTypedArray<u8>, _>(ctx).ok())
+        .map(|val| val.as_slice(ctx).to_vec())
+}
+
+/// function_argument_opt parses the optional callback at the position of the arguments.
+pub fn function_argument_opt(ctx: &mut FunctionContext, pos: i32) -> Option<Root<JsFunction>> {
+    ctx.argument_opt(pos)
+        .and_then(|val| val.downcast::<JsFunction, _>(ctx).ok())
+        .map(|val| val.root(ctx))
+}
+
+/// js_array_to_keys parses the array of the keys at the position of the arguments.
+pub fn js_array_to_keys(ctx: &mut FunctionContext, pos: i32) -> NeonResult<NestedVec> {
+    let input = ctx.argument::<JsArray>(pos)?.to_vec(ctx)?;
+    input
+        .iter()
+        .map(|key| {
+            Ok(key
+                .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
+                .as_slice(ctx)
+                .to_vec())
+        })
+        .collect()
+}
+
 pub fn get_iteration_mode<'a>(
     options: &options::IterationOption,
     opt: &'a mut Vec<u8>,
     has_prefix: bool,
 ) -> rocksdb::IteratorMode<'a> {
-    let no_range = options.gte.is_none() && options.lte.is_none();
+    let lower = options.lower_bound();
+    let upper = options.upper_bound();
+    let no_range = lower.is_none() && upper.is_none();
     if no_range {
         if options.reverse {
             rocksdb::IteratorMode::End
@@ -65,10 +412,9 @@ pub fn get_iteration_mode<'a>(
             rocksdb::IteratorMode::Start
         }
     } else if options.reverse {
-        let lte = options
-            .lte
-            .clone()
-            .unwrap_or_else(|| vec![255; options.gte.as_ref().unwrap().len()]);
+        let lte = upper
+            .cloned()
+            .unwrap_or_else(|| vec![255; lower.unwrap().len()]);
         *opt = if has_prefix {
             [Prefix::STATE, lte.as_slice()].concat()
         } else {
@@ -76,10 +422,9 @@ pub fn get_iteration_mode<'a>(
         };
         rocksdb::IteratorMode::From(opt, rocksdb::Direction::Reverse)
     } else {
-        let gte = options
-            .gte
-            .clone()
-            .unwrap_or_else(|| vec![0; options.lte.as_ref().unwrap().len()]);
+        let gte = lower
+            .cloned()
+            .unwrap_or_else(|| vec![0; upper.unwrap().len()]);
         *opt = if has_prefix {
             [Prefix::STATE, gte.as_slice()].concat()
         } else {
@@ -98,27 +443,24 @@ pub fn is_key_out_of_range(
     if options.limit != -1 && counter >= options.limit {
         return true;
     }
-    if options.reverse {
-        if let Some(gte) = &options.gte {
-            let cmp = if has_prefix {
-                [Prefix::STATE, gte].concat()
-            } else {
-                gte.to_vec()
-            };
-            if compare(key, &cmp) == cmp::Ordering::Less {
-                return true;
-            }
-        }
-    } else if let Some(lte) = &options.lte {
-        let cmp = if has_prefix {
-            [Prefix::STATE, lte].concat()
+    let with_prefix = |bound: &[u8]| {
+        if has_prefix {
+            [Prefix::STATE, bound].concat()
         } else {
-            lte.to_vec()
-        };
-        if compare(key, &cmp) == cmp::Ordering::Greater {
-            return true;
+            bound.to_vec()
         }
+    };
+    let past = |bound: &Option<Vec<u8>>, beyond: cmp::Ordering, inclusive: bool| {
+        bound.as_ref().map_or(false, |bound| {
+            let order = compare(key, &with_prefix(bound));
+            order == beyond || (!inclusive && order == cmp::Ordering::Equal)
+        })
+    };
+    if options.reverse {
+        past(&options.gte, cmp::Ordering::Less, true)
+            || past(&options.gt, cmp::Ordering::Less, false)
+    } else {
+        past(&options.lte, cmp::Ordering::Greater, true)
+            || past(&options.lt, cmp::Ordering::Greater, false)
     }
-
-    false
 }
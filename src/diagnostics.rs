@@ -0,0 +1,116 @@
+/// diagnostics keeps the recent slow operations and the number of failed operations of the state_db,
+/// so that they can be attached to the bug reports by debugReport.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::types::RecoverLock;
+
+/// SLOW_OPERATION_THRESHOLD is the duration from which an operation is kept as a slow operation.
+pub const SLOW_OPERATION_THRESHOLD: Duration = Duration::from_millis(500);
+/// MAX_SLOW_OPERATIONS is the number of the slow operations kept. The oldest one is dropped first.
+pub const MAX_SLOW_OPERATIONS: usize = 32;
+
+static SLOW_OPERATIONS: Mutex<VecDeque<SlowOperation>> = Mutex::new(VecDeque::new());
+static ERRORS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Operation is the kind of the state_db operation tracked by diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Commit = 0,
+    Revert = 1,
+    Prove = 2,
+    ExportChunks = 3,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 4] = [
+        Operation::Commit,
+        Operation::Revert,
+        Operation::Prove,
+        Operation::ExportChunks,
+    ];
+
+    /// name returns the name used for reporting to JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Commit => "commit",
+            Operation::Revert => "revert",
+            Operation::Prove => "prove",
+            Operation::ExportChunks => "exportChunks",
+        }
+    }
+}
+
+/// SlowOperation is the operation which took longer than SLOW_OPERATION_THRESHOLD.
+/// timestamp is the unix time in milliseconds when the operation finished.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowOperation {
+    pub operation: Operation,
+    pub duration: Duration,
+    pub timestamp: u64,
+}
+
+/// observe records the operation started at the start with its result.
+pub fn observe<T, E>(operation: Operation, start: Instant, result: &Result<T, E>) {
+    if result.is_err() {
+        ERRORS[operation as usize].fetch_add(1, Ordering::Relaxed);
+    }
+    let duration = start.elapsed();
+    if duration < SLOW_OPERATION_THRESHOLD {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0);
+    let mut slow_operations = SLOW_OPERATIONS.lock_or_recover();
+    if slow_operations.len() == MAX_SLOW_OPERATIONS {
+        slow_operations.pop_front();
+    }
+    slow_operations.push_back(SlowOperation {
+        operation,
+        duration,
+        timestamp,
+    });
+}
+
+/// slow_operations returns the recent slow operations from the oldest.
+pub fn slow_operations() -> Vec<SlowOperation> {
+    SLOW_OPERATIONS.lock_or_recover().iter().cloned().collect()
+}
+
+/// error_count returns the number of failures of the operation since the process started.
+pub fn error_count(operation: Operation) -> u64 {
+    ERRORS[operation as usize].load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe() {
+        let errors = error_count(Operation::ExportChunks);
+        observe::<(), ()>(Operation::ExportChunks, Instant::now(), &Err(()));
+        observe::<(), ()>(Operation::ExportChunks, Instant::now(), &Ok(()));
+        assert_eq!(error_count(Operation::ExportChunks), errors + 1);
+
+        let start = Instant::now() - SLOW_OPERATION_THRESHOLD;
+        for _ in 0..MAX_SLOW_OPERATIONS + 1 {
+            observe::<(), ()>(Operation::Revert, start, &Ok(()));
+        }
+        let slow_operations = slow_operations();
+        assert_eq!(slow_operations.len(), MAX_SLOW_OPERATIONS);
+        let last = slow_operations.last().unwrap();
+        assert_eq!(last.operation, Operation::Revert);
+        assert!(last.duration >= SLOW_OPERATION_THRESHOLD);
+        assert!(last.timestamp > 0);
+    }
+}
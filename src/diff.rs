@@ -1,8 +1,20 @@
 /// diff provides data structure to revert the state for StateDB.
+use std::collections::BTreeMap;
+
 use crate::batch;
 use crate::codec;
+use crate::consts;
 use crate::types::{Cache, HashKind, HashWithKind, KVPair, KVPairCodec, NestedVec};
 
+/// DIFF_VERSION is the version of the diff encoding written by encode.
+/// The legacy encoding without the version starts with the key of a length-delimited field,
+/// whose wire type is 2, therefore the version must not end with the bits of the wire type.
+pub const DIFF_VERSION: u8 = 1;
+/// WIRE_TYPE_MASK is the mask of the wire type in the key of a field.
+const WIRE_TYPE_MASK: u8 = 7;
+/// WIRE_TYPE_BYTES is the wire type of the length-delimited fields.
+const WIRE_TYPE_BYTES: u8 = 2;
+
 /// Diff maintains difference between each state changes, and it is used when reverting the state.
 /// When updating state to next state, it maintains:
 /// - newly created keys.
@@ -19,18 +31,80 @@ pub struct Diff {
     deleted: Vec<KVPair>,
 }
 
+/// EntryStats is the number of the entries and the sum of their sizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EntryStats {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// DiffStats summarizes the entries of the diff.
+/// The bytes of the created entries are the sizes of the keys, and the bytes of the updated and deleted entries
+/// are the sizes of the keys and the original values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiffStats {
+    pub created: EntryStats,
+    pub updated: EntryStats,
+    pub deleted: EntryStats,
+}
+
+impl EntryStats {
+    fn from_pairs(pairs: &[KVPair]) -> Self {
+        Self {
+            count: pairs.len(),
+            bytes: pairs
+                .iter()
+                .map(|kv| kv.key().len() + kv.value().len())
+                .sum(),
+        }
+    }
+}
+
+/// read_pair reads the fields of the pair with the key as field 1 and the value as field 2.
+fn read_pair(reader: &mut codec::Reader) -> Result<KVPair, codec::CodecError> {
+    let key = reader.read_bytes(1)?;
+    let value = reader.read_bytes(2)?;
+    Ok(KVPair::new(&key, &value))
+}
+
+/// write_pair writes the fields of the pair read by read_pair.
+fn write_pair(writer: &mut codec::Writer, pair: &KVPair) {
+    writer.write_bytes(1, pair.key());
+    writer.write_bytes(2, pair.value());
+}
+
+/// encode_pairs encodes the pairs as the repeated messages of field 1 with the fields of write_pair,
+/// so that any number of pairs is given from JS in a single buffer.
+pub fn encode_pairs(pairs: &[KVPair]) -> Vec<u8> {
+    let mut writer = codec::Writer::new();
+    for pair in pairs {
+        writer.write_message(1, |writer| write_pair(writer, pair));
+    }
+    writer.result().to_vec()
+}
+
+/// decode_pairs decodes the pairs encoded by encode_pairs.
+/// The bytes come from JS, so they are read in the strict mode.
+pub fn decode_pairs(val: &[u8]) -> Result<Vec<KVPair>, codec::CodecError> {
+    let mut reader = codec::Reader::strict(val);
+    let mut pairs = vec![];
+    for mut message in reader.read_messages(1)? {
+        pairs.push(read_pair(&mut message)?);
+        message.finish()?;
+    }
+    reader.finish()?;
+
+    Ok(pairs)
+}
+
 impl KVPairCodec for KVPair {
     fn decode(val: &[u8]) -> Result<Self, codec::CodecError> {
-        let mut reader = codec::Reader::new(val);
-        let key = reader.read_bytes(1)?;
-        let value = reader.read_bytes(2)?;
-        Ok(Self::new(&key, &value))
+        read_pair(&mut codec::Reader::new(val))
     }
 
     fn encode(&self) -> Vec<u8> {
         let mut writer = codec::Writer::new();
-        writer.write_bytes(1, self.key());
-        writer.write_bytes(2, self.value());
+        write_pair(&mut writer, self);
         writer.result().to_vec()
     }
 }
@@ -43,21 +117,91 @@ impl Diff {
             deleted,
         }
     }
+
+    /// merge composes the diffs of the consecutive states in ascending order of the heights
+    /// into the net difference between the state before the first diff and the state after the last diff.
+    /// The keys are sorted, and a key created and deleted within the diffs is not included.
+    pub fn merge(diffs: Vec<Diff>) -> Self {
+        // original value before the first diff, which is None if the key did not exist, and if the key exists at the end
+        let mut changes: BTreeMap<Vec<u8>, (Option<Vec<u8>>, bool)> = BTreeMap::new();
+        for diff in diffs {
+            for key in diff.created {
+                changes.entry(key).or_insert((None, true)).1 = true;
+            }
+            for kv in diff.updated {
+                let (key, value) = (kv.key_as_vec(), kv.value_as_vec());
+                changes.entry(key).or_insert((Some(value), true)).1 = true;
+            }
+            for kv in diff.deleted {
+                let (key, value) = (kv.key_as_vec(), kv.value_as_vec());
+                changes.entry(key).or_insert((Some(value), false)).1 = false;
+            }
+        }
+
+        let mut result = Self::new(vec![], vec![], vec![]);
+        for (key, change) in changes {
+            match change {
+                (None, true) => result.created.push(key),
+                (Some(value), true) => result.updated.push(KVPair::new(&key, &value)),
+                (Some(value), false) => result.deleted.push(KVPair::new(&key, &value)),
+                (None, false) => {},
+            }
+        }
+        result
+    }
+
+    /// created returns the keys created in the state.
+    pub fn created(&self) -> &NestedVec {
+        &self.created
+    }
+
+    /// updated returns the keys updated in the state with the original values.
+    pub fn updated(&self) -> &[KVPair] {
+        &self.updated
+    }
+
+    /// deleted returns the keys deleted from the state with the original values.
+    pub fn deleted(&self) -> &[KVPair] {
+        &self.deleted
+    }
+
+    /// stats returns the number and the size of the created, updated and deleted entries.
+    pub fn stats(&self) -> DiffStats {
+        DiffStats {
+            created: EntryStats {
+                count: self.created.len(),
+                bytes: self.created.iter().map(|key| key.len()).sum(),
+            },
+            updated: EntryStats::from_pairs(&self.updated),
+            deleted: EntryStats::from_pairs(&self.deleted),
+        }
+    }
+
     /// decode bytes to diff struct.
-    /// decoding uses lisk-codec protocol.
+    /// decoding uses lisk-codec protocol following the version byte.
+    /// It accepts the legacy encoding without the version as well.
     pub fn decode(val: &[u8]) -> Result<Self, codec::CodecError> {
-        let mut reader = codec::Reader::new(val);
+        let fields = match val.first() {
+            Some(&version) if version & WIRE_TYPE_MASK != WIRE_TYPE_BYTES => {
+                if version != DIFF_VERSION {
+                    return Err(codec::CodecError::UnsupportedVersion(version));
+                }
+                &val[1..]
+            },
+            _ => val,
+        };
+        let mut reader = codec::Reader::new(fields);
         let created = reader.read_bytes_slice(1)?;
-        let updated_bytes = reader.read_bytes_slice(2)?;
-        let updated: Vec<KVPair> = updated_bytes
-            .iter()
-            .map(|value| KVPair::decode(value).unwrap())
-            .collect();
-        let deleted_bytes = reader.read_bytes_slice(3)?;
-        let deleted: Vec<KVPair> = deleted_bytes
-            .iter()
-            .map(|value| KVPair::decode(value).unwrap())
-            .collect();
+        let updated = reader
+            .read_messages(2)?
+            .iter_mut()
+            .map(read_pair)
+            .collect::<Result<Vec<KVPair>, codec::CodecError>>()?;
+        let deleted = reader
+            .read_messages(3)?
+            .iter_mut()
+            .map(read_pair)
+            .collect::<Result<Vec<KVPair>, codec::CodecError>>()?;
         Ok(Self {
             created,
             updated,
@@ -66,16 +210,19 @@ impl Diff {
     }
 
     /// encode diff to bytes.
-    /// encoding uses lisk-codec protocol.
+    /// encoding uses lisk-codec protocol with created as field 1, updated as field 2 and deleted as field 3,
+    /// following the version byte. The updated and deleted pairs are nested messages.
     pub fn encode(&self) -> Vec<u8> {
         let mut writer = codec::Writer::new();
         writer.write_bytes_slice(1, &self.created);
-        let updated: NestedVec = self.updated.iter().map(|v| v.encode()).collect();
-        writer.write_bytes_slice(2, &updated);
-        let deleted: NestedVec = self.deleted.iter().map(|v| v.encode()).collect();
-        writer.write_bytes_slice(3, &deleted);
+        for kv in self.updated.iter() {
+            writer.write_message(2, |writer| write_pair(writer, kv));
+        }
+        for kv in self.deleted.iter() {
+            writer.write_message(3, |writer| write_pair(writer, kv));
+        }
 
-        writer.result().to_vec()
+        [&[DIFF_VERSION], writer.result().as_slice()].concat()
     }
 
     /// revert_hashed_update returns cache value with original data.
@@ -112,12 +259,20 @@ impl Diff {
             batch.delete(key);
         }
     }
+
+    /// revert_batch returns the batch to revert the states, which restores the original values
+    /// of the updated and deleted keys and removes the created keys with the state prefix.
+    pub fn revert_batch(&self) -> batch::PrefixWriteBatch<'static> {
+        let mut batch = batch::PrefixWriteBatch::new();
+        batch.set_prefix(&consts::Prefix::STATE);
+        self.revert_commit(&mut batch);
+        batch
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::consts;
 
     #[test]
     fn test_kvpair_encode_decode() {
@@ -129,6 +284,23 @@ mod tests {
         assert_eq!(kvpair, decoded);
     }
 
+    #[test]
+    fn test_pairs_encode_decode() {
+        let pairs = vec![KVPair::new(&[1], &[2, 3]), KVPair::new(b"key", b"")];
+        let encoded = encode_pairs(&pairs);
+        assert_eq!(
+            &encoded[..9],
+            &[0x0a, 0x07, 0x0a, 0x01, 0x01, 0x12, 0x02, 0x02, 0x03]
+        );
+        assert_eq!(decode_pairs(&encoded).unwrap(), pairs);
+        assert_eq!(decode_pairs(&[]).unwrap(), vec![]);
+
+        let mut trailing = encoded.clone();
+        trailing.push(0x1a);
+        assert!(decode_pairs(&trailing).is_err());
+        assert!(decode_pairs(&encoded[..encoded.len() - 1]).is_err());
+    }
+
     #[test]
     fn test_diff_new() {
         let created = vec![b"test_key".to_vec()];
@@ -151,9 +323,88 @@ mod tests {
         let encoded = diff.encode();
         let decoded = Diff::decode(&encoded).unwrap();
 
+        assert_eq!(encoded[0], DIFF_VERSION);
         assert_eq!(diff, decoded);
     }
 
+    #[test]
+    fn test_diff_decode_versions() {
+        let created = vec![b"test_key".to_vec()];
+        let updated = vec![KVPair::new(b"test_key_updated", b"test_value_updated")];
+        let diff = Diff::new(created, updated, vec![]);
+
+        let encoded = diff.encode();
+        assert_eq!(Diff::decode(&encoded[1..]).unwrap(), diff);
+
+        let empty = Diff::new(vec![], vec![], vec![]);
+        assert_eq!(empty.encode(), vec![DIFF_VERSION]);
+        assert_eq!(Diff::decode(&empty.encode()).unwrap(), empty);
+        assert_eq!(Diff::decode(&[]).unwrap(), empty);
+
+        let unsupported = [&[DIFF_VERSION + 8], &encoded[1..]].concat();
+        assert!(matches!(
+            Diff::decode(&unsupported),
+            Err(codec::CodecError::UnsupportedVersion(9))
+        ));
+        assert!(Diff::decode(&[0x12, 3, 0x0a, 1]).is_err());
+    }
+
+    #[test]
+    fn test_diff_merge() {
+        let first = Diff::new(
+            vec![b"created".to_vec(), b"created_deleted".to_vec()],
+            vec![KVPair::new(b"updated", b"original")],
+            vec![KVPair::new(b"deleted_created", b"original")],
+        );
+        let second = Diff::new(
+            vec![b"deleted_created".to_vec()],
+            vec![
+                KVPair::new(b"created", b"first"),
+                KVPair::new(b"updated", b"first"),
+            ],
+            vec![KVPair::new(b"created_deleted", b"first")],
+        );
+        let third = Diff::new(vec![], vec![], vec![KVPair::new(b"updated", b"second")]);
+
+        let merged = Diff::merge(vec![first.clone(), second, third]);
+        let expected = Diff::new(
+            vec![b"created".to_vec()],
+            vec![KVPair::new(b"deleted_created", b"original")],
+            vec![KVPair::new(b"updated", b"original")],
+        );
+        assert_eq!(merged, expected);
+        assert_eq!(Diff::merge(vec![first.clone()]).created(), first.created());
+        assert_eq!(Diff::merge(vec![]), Diff::new(vec![], vec![], vec![]));
+    }
+
+    #[test]
+    fn test_diff_stats() {
+        let created = vec![b"test_key".to_vec(), b"key".to_vec()];
+        let updated = vec![KVPair::new(b"test_key_updated", b"test_value_updated")];
+        let diff = Diff::new(created, updated, vec![]);
+
+        let stats = diff.stats();
+        assert_eq!(
+            stats.created,
+            EntryStats {
+                count: 2,
+                bytes: 11
+            }
+        );
+        assert_eq!(
+            stats.updated,
+            EntryStats {
+                count: 1,
+                bytes: 34
+            }
+        );
+        assert_eq!(stats.deleted, EntryStats::default());
+        assert_eq!(
+            Diff::new(vec![], vec![], vec![]).stats(),
+            DiffStats::default()
+        );
+    }
+
     #[test]
     fn test_diff_revert_hashed_update() {
         let created = vec![b"test_key".to_vec()];
@@ -199,4 +450,26 @@ mod tests {
 
         assert_eq!(batch.batch.len(), 3);
     }
+
+    #[test]
+    fn test_diff_revert_batch() {
+        let created = vec![b"test_key".to_vec()];
+        let updated = vec![KVPair::new(b"test_key_updated", b"test_value_updated")];
+        let deleted = vec![KVPair::new(b"test_key_deleted", b"test_value_deleted")];
+        let diff = Diff::new(created, updated, deleted);
+
+        let batch = diff.revert_batch();
+
+        let mut expected = rocksdb::WriteBatch::default();
+        expected.put(
+            [consts::Prefix::STATE, b"test_key_updated"].concat(),
+            b"test_value_updated",
+        );
+        expected.put(
+            [consts::Prefix::STATE, b"test_key_deleted"].concat(),
+            b"test_value_deleted",
+        );
+        expected.delete([consts::Prefix::STATE, b"test_key"].concat());
+        assert_eq!(batch.batch.data(), expected.data());
+    }
 }
@@ -0,0 +1,117 @@
+/// error_code assigns stable codes to the errors surfaced to JS as the `code` property,
+/// so the callers can branch on the kind of the failure without parsing the message.
+use std::io;
+use std::sync::mpsc;
+
+use crate::codec::CodecError;
+use crate::memory::MemoryError;
+use crate::task_pool::TaskError;
+
+/// NOT_FOUND is the code when the requested data does not exist.
+pub const NOT_FOUND: &str = "ERR_NOT_FOUND";
+/// INVALID_ROOT is the code when the state root is not in the tree.
+pub const INVALID_ROOT: &str = "ERR_INVALID_ROOT";
+/// INVALID_INPUT is the code when the arguments or the encoded data are invalid.
+pub const INVALID_INPUT: &str = "ERR_INVALID_INPUT";
+/// INVALID_USAGE is the code when the operation is not allowed in the current state of the instance.
+pub const INVALID_USAGE: &str = "ERR_INVALID_USAGE";
+/// ABORTED is the code when the operation is aborted or cancelled before completion.
+pub const ABORTED: &str = "ERR_ABORTED";
+/// BUSY is the code when the operation can be retried later.
+pub const BUSY: &str = "ERR_BUSY";
+/// LIMIT_EXCEEDED is the code when the memory budget or a size cap is exceeded.
+pub const LIMIT_EXCEEDED: &str = "ERR_LIMIT_EXCEEDED";
+/// CORRUPTION is the code when the stored data is corrupted.
+pub const CORRUPTION: &str = "ERR_CORRUPTION";
+/// IO is the code when reading or writing the files fails.
+pub const IO: &str = "ERR_IO";
+/// DATABASE is the code of the other failures of RocksDB.
+pub const DATABASE: &str = "ERR_DATABASE";
+/// UNKNOWN is the code of the failures which are not classified.
+pub const UNKNOWN: &str = "ERR_UNKNOWN";
+/// CLOSED is the code when the database is already closed.
+pub const CLOSED: &str = "ERR_CLOSED";
+/// INTERNAL is the code when the operation panicked.
+pub const INTERNAL: &str = "ERR_INTERNAL";
+
+pub trait ErrorCode {
+    /// code returns the stable code of the error.
+    fn code(&self) -> &'static str;
+}
+
+#[cfg(feature = "neon")]
+impl ErrorCode for rocksdb::Error {
+    fn code(&self) -> &'static str {
+        match self.kind() {
+            rocksdb::ErrorKind::NotFound => NOT_FOUND,
+            rocksdb::ErrorKind::Busy
+            | rocksdb::ErrorKind::TimedOut
+            | rocksdb::ErrorKind::TryAgain
+            | rocksdb::ErrorKind::ShutdownInProgress => BUSY,
+            rocksdb::ErrorKind::InvalidArgument => INVALID_INPUT,
+            rocksdb::ErrorKind::Aborted => ABORTED,
+            rocksdb::ErrorKind::Corruption => CORRUPTION,
+            rocksdb::ErrorKind::IOError => IO,
+            _ => DATABASE,
+        }
+    }
+}
+
+impl ErrorCode for io::Error {
+    fn code(&self) -> &'static str {
+        match self.kind() {
+            io::ErrorKind::NotFound => NOT_FOUND,
+            _ => IO,
+        }
+    }
+}
+
+/// The channel to the thread of the database is closed only when the database is closed.
+impl<T> ErrorCode for mpsc::SendError<T> {
+    fn code(&self) -> &'static str {
+        CLOSED
+    }
+}
+
+impl ErrorCode for CodecError {
+    fn code(&self) -> &'static str {
+        INVALID_INPUT
+    }
+}
+
+impl ErrorCode for TaskError {
+    fn code(&self) -> &'static str {
+        match self {
+            TaskError::Cancelled => ABORTED,
+            TaskError::QueueFull(_) | TaskError::ShutDown => BUSY,
+        }
+    }
+}
+
+impl ErrorCode for MemoryError {
+    fn code(&self) -> &'static str {
+        LIMIT_EXCEEDED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "missing");
+        assert_eq!(err.code(), NOT_FOUND);
+        let err = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(err.code(), IO);
+        assert_eq!(CodecError::OutOfRange.code(), INVALID_INPUT);
+        assert_eq!(TaskError::QueueFull(1).code(), BUSY);
+        assert_eq!(TaskError::Cancelled.code(), ABORTED);
+        let err = MemoryError::BudgetExceeded {
+            budget: 1,
+            used: 1,
+            requested: 1,
+        };
+        assert_eq!(err.code(), LIMIT_EXCEEDED);
+    }
+}
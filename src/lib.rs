@@ -1,35 +1,82 @@
+#[cfg(not(any(feature = "neon", feature = "no-neon")))]
+compile_error!("Either the default neon feature or the no-neon feature must be enabled.");
+#[cfg(all(feature = "neon", target_arch = "wasm32"))]
+compile_error!("The neon feature cannot be built for wasm32, use the no-neon feature instead.");
+
+#[cfg(feature = "neon")]
 use neon::prelude::*;
 
+#[cfg(feature = "neon")]
+use crate::database::backup;
+#[cfg(feature = "neon")]
 use crate::database::db;
+#[cfg(feature = "neon")]
 use crate::database::in_memory::in_memory_db;
+#[cfg(feature = "neon")]
 use crate::database::reader_writer::read_writer_db;
+#[cfg(feature = "neon")]
 use crate::database::reader_writer::reader_db;
-use crate::database::traits::{JsNewWithArcMutex, JsNewWithBoxRef};
+#[cfg(feature = "neon")]
+use crate::database::traits::{JsNewWithArcMutex, JsNewWithArcRwLock, JsNewWithBoxRef};
+#[cfg(feature = "neon")]
+use crate::database::transaction::Transaction;
+#[cfg(feature = "neon")]
 use crate::database::types::DbOptions;
+#[cfg(feature = "neon")]
 use crate::sparse_merkle_tree::in_memory_smt;
+#[cfg(feature = "neon")]
+use crate::sparse_merkle_tree::multi_tree_db;
+#[cfg(feature = "neon")]
 use crate::state::state_db;
+#[cfg(feature = "neon")]
 use crate::state::state_writer;
 
+pub mod abort;
+#[cfg(feature = "neon")]
 pub mod batch;
 pub mod consts;
 pub mod database;
+pub mod diagnostics;
+pub mod error_code;
+pub mod logging;
+pub mod memory;
+pub mod metrics;
 pub mod sparse_merkle_tree;
+#[cfg(feature = "neon")]
 pub mod state;
+pub mod task_pool;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod types;
 
+// with no-neon, only the parts used by the proof verification are used.
+#[cfg_attr(not(feature = "neon"), allow(dead_code))]
 mod codec;
+// Reader is exported for the fuzz target of the codec.
+pub use codec::Reader;
+#[cfg(feature = "neon")]
 mod diff;
+#[cfg_attr(not(feature = "neon"), allow(dead_code))]
 mod utils;
 
+#[cfg(feature = "neon")]
 use batch::WriteBatch;
+#[cfg(feature = "neon")]
 use db::Database;
+#[cfg(feature = "neon")]
 use in_memory_smt::InMemorySMT;
+#[cfg(feature = "neon")]
+use multi_tree_db::MultiTreeDB;
+#[cfg(feature = "neon")]
 use read_writer_db::ReadWriter;
+#[cfg(feature = "neon")]
 use state_db::StateDB;
+#[cfg(feature = "neon")]
 use state_writer::StateWriter;
 
 /// main registers functions for JS ffi
 
+#[cfg(feature = "neon")]
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
     let db_new = Database::js_new_with_box_ref::<DbOptions, Database>;
@@ -38,11 +85,35 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("db_close", Database::js_close)?;
     cx.export_function("db_get", Database::js_get)?;
     cx.export_function("db_exists", Database::js_exists)?;
+    cx.export_function("db_get_many", Database::js_get_many)?;
+    cx.export_function("db_get_stream", Database::js_get_stream)?;
     cx.export_function("db_set", Database::js_set)?;
     cx.export_function("db_del", Database::js_del)?;
     cx.export_function("db_write", Database::js_write)?;
     cx.export_function("db_iterate", Database::js_iterate)?;
+    cx.export_function("db_iterate_page", Database::js_iterate_page)?;
     cx.export_function("db_checkpoint", Database::js_checkpoint)?;
+    cx.export_function("db_create_backup", Database::js_create_backup)?;
+    cx.export_function("db_compact_range", Database::js_compact_range)?;
+    cx.export_function("db_set_auto_compaction", Database::js_set_auto_compaction)?;
+    cx.export_function("db_flush", Database::js_flush)?;
+    cx.export_function("db_sync_wal", Database::js_sync_wal)?;
+    cx.export_function("db_properties", Database::js_properties)?;
+    cx.export_function("db_approximate_size", Database::js_approximate_size)?;
+    cx.export_function("db_write_stall", Database::js_write_stall)?;
+    cx.export_function("db_snapshot_new", reader_db::Reader::js_new_snapshot)?;
+    cx.export_function("db_snapshot_release", reader_db::Reader::js_close)?;
+    cx.export_function("db_snapshot_get", reader_db::Reader::js_get)?;
+    cx.export_function("db_snapshot_exists", reader_db::Reader::js_exists)?;
+    cx.export_function("db_snapshot_iterate", reader_db::Reader::js_iterate)?;
+    cx.export_function("db_new_secondary", Database::js_new_secondary)?;
+    cx.export_function(
+        "db_try_catch_up_with_primary",
+        Database::js_try_catch_up_with_primary,
+    )?;
+    cx.export_function("db_transaction_get", Database::js_transaction_get)?;
+    cx.export_function("db_transaction_iterate", Database::js_transaction_iterate)?;
+    cx.export_function("db_transaction_commit", Database::js_transaction_commit)?;
 
     cx.export_function("state_db_reader_new", reader_db::Reader::js_new)?;
     cx.export_function("state_db_reader_close", reader_db::Reader::js_close)?;
@@ -60,27 +131,117 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("batch_new", WriteBatch::js_new_with_arc_mutex::<WriteBatch>)?;
     cx.export_function("batch_set", WriteBatch::js_set)?;
     cx.export_function("batch_del", WriteBatch::js_del)?;
+    cx.export_function("batch_set_if_absent", WriteBatch::js_set_if_absent)?;
+    cx.export_function("batch_del_if_equals", WriteBatch::js_del_if_equals)?;
+    cx.export_function("batch_encode", WriteBatch::js_encode)?;
+    cx.export_function("batch_decode", WriteBatch::js_decode)?;
+    cx.export_function("batch_len", WriteBatch::js_len)?;
+    cx.export_function("batch_size", WriteBatch::js_size)?;
+    cx.export_function("batch_operations", WriteBatch::js_operations)?;
+
+    cx.export_function("transaction_new", Transaction::js_new)?;
+    cx.export_function("transaction_set", Transaction::js_set)?;
+    cx.export_function("transaction_del", Transaction::js_del)?;
 
     let state_db_new = StateDB::js_new_with_box_ref::<DbOptions, StateDB>;
     cx.export_function("state_db_new", state_db_new)?;
     cx.export_function("state_db_get_current_state", StateDB::js_get_current_state)?;
+    cx.export_function("state_db_get_root_at", StateDB::js_get_root_at)?;
+    cx.export_function(
+        "state_db_get_root_at_height",
+        StateDB::js_get_root_at_height,
+    )?;
+    cx.export_function(
+        "state_db_find_height_for_root",
+        StateDB::js_find_height_for_root,
+    )?;
+    cx.export_function("state_db_get_store_root", StateDB::js_get_store_root)?;
+    cx.export_function("state_db_get_structure_log", StateDB::js_get_structure_log)?;
+    cx.export_function("state_db_new_overlay", StateDB::js_new_overlay)?;
+    cx.export_function("state_db_overlay_update", StateDB::js_overlay_update)?;
+    cx.export_function(
+        "state_db_get_finalized_state",
+        StateDB::js_get_finalized_state,
+    )?;
+    cx.export_function(
+        "state_db_get_lifetime_stats",
+        StateDB::js_get_lifetime_stats,
+    )?;
+    cx.export_function("state_db_debug_report", StateDB::js_debug_report)?;
+    cx.export_function("state_db_get_audit_records", StateDB::js_get_audit_records)?;
+    cx.export_function(
+        "state_db_verify_audit_trail",
+        StateDB::js_verify_audit_trail,
+    )?;
     cx.export_function("state_db_close", StateDB::js_close)?;
     cx.export_function("state_db_get", StateDB::js_get)?;
+    cx.export_function("state_db_get_many", StateDB::js_get_many)?;
     cx.export_function("state_db_exists", StateDB::js_exists)?;
     cx.export_function("state_db_iterate", StateDB::js_iterate)?;
     cx.export_function("state_db_revert", StateDB::js_revert)?;
+    cx.export_function(
+        "state_db_calculate_diff_between",
+        StateDB::js_calculate_diff_between,
+    )?;
+    cx.export_function("state_db_get_diff_stats", StateDB::js_get_diff_stats)?;
     cx.export_function("state_db_commit", StateDB::js_commit)?;
+    cx.export_function("state_db_subscribe_commit", StateDB::js_subscribe_commit)?;
+    cx.export_function(
+        "state_db_unsubscribe_commit",
+        StateDB::js_unsubscribe_commit,
+    )?;
+    cx.export_function("state_db_get_metrics", StateDB::js_get_metrics)?;
     cx.export_function("state_db_prove", StateDB::js_prove)?;
+    cx.export_function("state_db_prove_many", StateDB::js_prove_many)?;
     cx.export_function("state_db_verify", StateDB::js_verify)?;
+    cx.export_function("state_db_prove_prefix", StateDB::js_prove_prefix)?;
+    cx.export_function("state_db_generate_witness", StateDB::js_generate_witness)?;
+    cx.export_function("state_db_get_value_hash", StateDB::js_get_value_hash)?;
+    cx.export_function("state_db_verify_prefix", StateDB::js_verify_prefix)?;
+    cx.export_function("state_db_to_visualization", StateDB::js_to_visualization)?;
+    cx.export_function("state_db_export_chunks", StateDB::js_export_chunks)?;
+    cx.export_function("state_db_verify_state_root", StateDB::js_verify_state_root)?;
     cx.export_function("state_db_clean_diff_until", StateDB::js_clean_diff_until)?;
     cx.export_function("state_db_checkpoint", StateDB::js_checkpoint)?;
+    cx.export_function("state_db_create_backup", StateDB::js_create_backup)?;
+    cx.export_function("state_db_compact_range", StateDB::js_compact_range)?;
+    cx.export_function("state_db_compact_smt", StateDB::js_compact_smt)?;
+    cx.export_function(
+        "state_db_set_auto_compaction",
+        StateDB::js_set_auto_compaction,
+    )?;
+    cx.export_function("state_db_flush", StateDB::js_flush)?;
+    cx.export_function("state_db_sync_wal", StateDB::js_sync_wal)?;
+    cx.export_function("state_db_properties", StateDB::js_properties)?;
+    cx.export_function("state_db_approximate_size", StateDB::js_approximate_size)?;
+    cx.export_function("state_db_write_stall", StateDB::js_write_stall)?;
+    cx.export_function("state_db_new_secondary", StateDB::js_new_secondary)?;
+    let state_db_try_catch_up_with_primary = StateDB::js_try_catch_up_with_primary;
+    cx.export_function(
+        "state_db_try_catch_up_with_primary",
+        state_db_try_catch_up_with_primary,
+    )?;
     cx.export_function("state_db_calculate_root", StateDB::js_calculate_root)?;
 
     let state_writer_new = StateWriter::js_new_with_arc_mutex::<StateWriter>;
     let restore_snapshot = StateWriter::js_restore_snapshot;
     cx.export_function("state_writer_new", state_writer_new)?;
+    cx.export_function("state_writer_new_with_db", StateWriter::js_new_with_db)?;
     cx.export_function("state_writer_close", StateWriter::js_close)?;
+    cx.export_function("state_writer_get", StateWriter::js_get)?;
+    cx.export_function("state_writer_set", StateWriter::js_set)?;
+    cx.export_function(
+        "state_writer_cache_existing",
+        StateWriter::js_cache_existing,
+    )?;
+    cx.export_function("state_writer_del", StateWriter::js_del)?;
+    cx.export_function("state_writer_range", StateWriter::js_range)?;
+    cx.export_function("state_writer_get_updated", StateWriter::js_get_updated)?;
+    cx.export_function("state_writer_changed_keys", StateWriter::js_changed_keys)?;
     cx.export_function("state_writer_snapshot", StateWriter::js_snapshot)?;
+    cx.export_function("state_writer_delete_range", StateWriter::js_delete_range)?;
+    cx.export_function("state_writer_size", StateWriter::js_size)?;
+    cx.export_function("state_writer_set_cap", StateWriter::js_set_cap)?;
     cx.export_function("state_writer_restore_snapshot", restore_snapshot)?;
 
     cx.export_function("in_memory_db_new", in_memory_db::Database::js_new)?;
@@ -92,13 +253,84 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("in_memory_db_write", in_memory_db::Database::js_write)?;
     cx.export_function("in_memory_db_iterate", in_memory_db::Database::js_iterate)?;
 
-    let in_memory_smt_new = InMemorySMT::js_new_with_arc_mutex::<InMemorySMT>;
+    let in_memory_smt_new = InMemorySMT::js_new_with_arc_rw_lock::<InMemorySMT>;
     let in_memory_smt_calculate_root = InMemorySMT::js_calculate_root;
     cx.export_function("in_memory_smt_new", in_memory_smt_new)?;
     cx.export_function("in_memory_smt_update", InMemorySMT::js_update)?;
+    cx.export_function(
+        "in_memory_smt_update_with_progress",
+        InMemorySMT::js_update_with_progress,
+    )?;
+    cx.export_function(
+        "in_memory_smt_update_with_batch",
+        InMemorySMT::js_update_with_batch,
+    )?;
+    cx.export_function("in_memory_smt_bulk_load", InMemorySMT::js_bulk_load)?;
+    cx.export_function(
+        "in_memory_smt_new_bulk_loader",
+        InMemorySMT::js_new_bulk_loader,
+    )?;
+    cx.export_function(
+        "in_memory_smt_add_to_bulk_loader",
+        InMemorySMT::js_add_to_bulk_loader,
+    )?;
+    cx.export_function(
+        "in_memory_smt_finish_bulk_load",
+        InMemorySMT::js_finish_bulk_load,
+    )?;
     cx.export_function("in_memory_smt_prove", InMemorySMT::js_prove)?;
     cx.export_function("in_memory_smt_verify", InMemorySMT::js_verify)?;
     cx.export_function("in_memory_smt_calculate_root", in_memory_smt_calculate_root)?;
+    cx.export_function(
+        "in_memory_smt_to_visualization",
+        InMemorySMT::js_to_visualization,
+    )?;
+    cx.export_function("in_memory_smt_save", InMemorySMT::js_save)?;
+    cx.export_function("in_memory_smt_load", InMemorySMT::js_load)?;
+    cx.export_function("in_memory_smt_get_metrics", InMemorySMT::js_get_metrics)?;
+    cx.export_function("in_memory_smt_verify_chunk", InMemorySMT::js_verify_chunk)?;
+    cx.export_function(
+        "in_memory_smt_verify_witness",
+        InMemorySMT::js_verify_witness,
+    )?;
+    cx.export_function(
+        "in_memory_smt_new_chunk_importer",
+        InMemorySMT::js_new_chunk_importer,
+    )?;
+    cx.export_function("in_memory_smt_import_chunk", InMemorySMT::js_import_chunk)?;
+    cx.export_function(
+        "in_memory_smt_finish_chunk_import",
+        InMemorySMT::js_finish_chunk_import,
+    )?;
+
+    cx.export_function("memory_usage", memory::js_memory_usage)?;
+    cx.export_function("memory_set_budget", memory::js_set_memory_budget)?;
+
+    cx.export_function("worker_pool_configure", task_pool::js_configure)?;
+    cx.export_function("worker_pool_shutdown", task_pool::js_shutdown)?;
+    cx.export_function("worker_pool_stats", task_pool::js_stats)?;
+
+    cx.export_function("abort_token_new", abort::AbortToken::js_new)?;
+    cx.export_function("abort_token_abort", abort::AbortToken::js_abort)?;
+
+    cx.export_function("metrics_get_stats", metrics::js_get_stats)?;
+    cx.export_function("metrics_set_stats_enabled", metrics::js_set_stats_enabled)?;
+    cx.export_function("metrics_reset_stats", metrics::js_reset_stats)?;
+
+    cx.export_function("logging_configure", logging::js_configure)?;
+
+    #[cfg(feature = "test-support")]
+    cx.export_function("test_support_generate", test_support::js_generate)?;
+
+    cx.export_function("backup_restore", backup::js_restore)?;
+    cx.export_function("backup_purge", backup::js_purge)?;
+
+    let multi_tree_db_new = MultiTreeDB::js_new_with_box_ref::<DbOptions, MultiTreeDB>;
+    cx.export_function("multi_tree_db_new", multi_tree_db_new)?;
+    cx.export_function("multi_tree_db_close", MultiTreeDB::js_close)?;
+    cx.export_function("multi_tree_db_get_root", MultiTreeDB::js_get_root)?;
+    cx.export_function("multi_tree_db_update", MultiTreeDB::js_update)?;
+    cx.export_function("multi_tree_db_prove", MultiTreeDB::js_prove)?;
 
     Ok(())
 }
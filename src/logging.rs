@@ -0,0 +1,390 @@
+/// logging forwards the spans and the events emitted with the `tracing` crate by the state tree, the writers
+/// and the databases to the registered sink, such as a file or a JS callback.
+/// The level is off by default, so the disabled spans cost a single atomic load.
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Once, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+use thiserror::Error;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry, SpanRef};
+
+#[cfg(feature = "neon")]
+use crate::database::utils;
+use crate::error_code::{self, ErrorCode};
+use crate::types::{RecoverLock, RecoverRwLock};
+
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+static SINK: RwLock<Option<Arc<dyn LogSink>>> = RwLock::new(None);
+static INSTALL: Once = Once::new();
+
+#[derive(Error, Debug)]
+pub enum LoggingError {
+    #[error("Invalid log level `{0}`")]
+    InvalidLevel(String),
+    #[error("Failed to open the log file `{0}`")]
+    File(#[from] io::Error),
+}
+
+impl ErrorCode for LoggingError {
+    fn code(&self) -> &'static str {
+        match self {
+            LoggingError::InvalidLevel(_) => error_code::INVALID_INPUT,
+            LoggingError::File(err) => err.code(),
+        }
+    }
+}
+
+/// LogRecord is the event, or the close of the span, passed to the sink.
+/// - spans: the spans the record belongs to from the root, such as `commit{height=1}:smt_commit{keys=3}`.
+/// - timestamp: unix time in milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub target: String,
+    pub spans: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// LogSink receives the records at or above the level.
+/// It is called from any thread, including the worker threads of the JS handlers.
+pub trait LogSink: Send + Sync {
+    fn write(&self, record: &LogRecord);
+}
+
+/// FileSink appends the records to the file, one line per record.
+pub struct FileSink(Mutex<File>);
+
+impl FileSink {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LoggingError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Mutex::new(file)))
+    }
+}
+
+impl LogSink for FileSink {
+    fn write(&self, record: &LogRecord) {
+        // Writing the logs must never fail the operation being logged
+        let _ = writeln!(self.0.lock_or_recover(), "{}", record);
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:>5} {}", self.timestamp, self.level, self.target)?;
+        if !self.spans.is_empty() {
+            write!(f, " {}", self.spans)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// level_value orders the levels from error (1) to trace (5), where 0 is off.
+fn level_value(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+/// parse_level parses the level name given from JS. "off" disables the logging.
+pub fn parse_level(name: &str) -> Result<Option<Level>, LoggingError> {
+    match name.to_ascii_lowercase().as_str() {
+        "off" => Ok(None),
+        "error" => Ok(Some(Level::ERROR)),
+        "warn" => Ok(Some(Level::WARN)),
+        "info" => Ok(Some(Level::INFO)),
+        "debug" => Ok(Some(Level::DEBUG)),
+        "trace" => Ok(Some(Level::TRACE)),
+        _ => Err(LoggingError::InvalidLevel(name.to_string())),
+    }
+}
+
+/// set_level sets the most verbose level passed to the sink, or disables the logging with None.
+/// The subscriber forwarding to the sink is installed as the global default the first time the logging is enabled.
+/// When the process has installed another global subscriber, the spans are sent to that one instead.
+pub fn set_level(level: Option<Level>) {
+    LEVEL.store(
+        level.as_ref().map(level_value).unwrap_or(0),
+        Ordering::Relaxed,
+    );
+    if level.is_some() {
+        INSTALL.call_once(|| {
+            let subscriber = Registry::default().with(SinkLayer);
+            let _ = tracing::subscriber::set_global_default(subscriber);
+        });
+    }
+}
+
+pub fn set_sink(sink: Option<Arc<dyn LogSink>>) {
+    *SINK.write_or_recover() = sink;
+}
+
+fn is_enabled(level: &Level) -> bool {
+    level_value(level) <= LEVEL.load(Ordering::Relaxed)
+}
+
+fn write_record(record: LogRecord) {
+    if let Some(sink) = SINK.read_or_recover().as_ref() {
+        sink.write(&record);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// FieldWriter formats the message and the fields as `message key=value`.
+#[derive(Default)]
+struct FieldWriter(String);
+
+impl Visit for FieldWriter {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+}
+
+/// SpanData is kept in the span to format it in the records and to measure its duration on close.
+struct SpanData {
+    fields: String,
+    start: Instant,
+}
+
+/// SinkLayer formats the records of the enabled spans and events for the sink.
+struct SinkLayer;
+
+impl SinkLayer {
+    fn spans<'a, S>(scope: impl Iterator<Item = SpanRef<'a, S>>) -> String
+    where
+        S: LookupSpan<'a> + 'a,
+    {
+        let mut spans = String::new();
+        for span in scope {
+            if !spans.is_empty() {
+                spans.push(':');
+            }
+            spans.push_str(span.name());
+            if let Some(data) = span.extensions().get::<SpanData>() {
+                if !data.fields.is_empty() {
+                    let _ = write!(spans, "{{{}}}", data.fields);
+                }
+            }
+        }
+        spans
+    }
+}
+
+impl<S> Layer<S> for SinkLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        // The level is changed at runtime, so the interest must not be cached
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _: Context<'_, S>) -> bool {
+        is_enabled(metadata.level())
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = FieldWriter::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanData {
+                fields: fields.0,
+                start: Instant::now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                let mut fields = FieldWriter(std::mem::take(&mut data.fields));
+                values.record(&mut fields);
+                data.fields = fields.0;
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut message = FieldWriter::default();
+        event.record(&mut message);
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| SinkLayer::spans(scope.from_root()))
+            .unwrap_or_default();
+        write_record(LogRecord {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            spans,
+            message: message.0,
+            timestamp: now(),
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let elapsed = match span.extensions().get::<SpanData>() {
+            Some(data) => data.start.elapsed(),
+            None => return,
+        };
+        write_record(LogRecord {
+            level: *span.metadata().level(),
+            target: span.metadata().target().to_string(),
+            spans: SinkLayer::spans(span.scope().from_root()),
+            message: format!("closed in {:?}", elapsed),
+            timestamp: now(),
+        });
+    }
+}
+
+/// CallbackSink calls the JS function with each record on the JS thread.
+#[cfg(feature = "neon")]
+struct CallbackSink {
+    channel: Channel,
+    callback: Arc<Root<JsFunction>>,
+}
+
+#[cfg(feature = "neon")]
+impl LogSink for CallbackSink {
+    fn write(&self, record: &LogRecord) {
+        let record = record.clone();
+        let callback = Arc::clone(&self.callback);
+        self.channel.send(move |mut ctx| {
+            let obj = ctx.empty_object();
+            let level = ctx.string(record.level.as_str().to_ascii_lowercase());
+            obj.set(&mut ctx, "level", level)?;
+            let target = ctx.string(&record.target);
+            obj.set(&mut ctx, "target", target)?;
+            let spans = ctx.string(&record.spans);
+            obj.set(&mut ctx, "spans", spans)?;
+            let message = ctx.string(&record.message);
+            obj.set(&mut ctx, "message", message)?;
+            let timestamp = ctx.number(record.timestamp as f64);
+            obj.set(&mut ctx, "timestamp", timestamp)?;
+            let callback = callback.to_inner(&mut ctx);
+            let this = ctx.undefined();
+            callback.call(&mut ctx, this, vec![obj])?;
+            Ok(())
+        });
+    }
+}
+
+/// js_configure is handler for JS ffi.
+/// - @params(0) - level of the records: "off", "error", "warn", "info", "debug" or "trace".
+/// - @params(1) - path of the file the records are appended to, or null.
+/// - @params(2) - function called with { level, target, spans, message, timestamp } of each record, or null.
+/// The file is used when both the file and the function are given. Without them, the records are dropped.
+#[cfg(feature = "neon")]
+pub fn js_configure(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let level = ctx.argument::<JsString>(0)?.value(&mut ctx);
+    let level = parse_level(&level).or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+    let path = ctx
+        .argument_opt(1)
+        .and_then(|val| val.downcast::<JsString, _>(&mut ctx).ok())
+        .map(|path| path.value(&mut ctx));
+    let callback = utils::function_argument_opt(&mut ctx, 2);
+
+    let sink: Option<Arc<dyn LogSink>> = match (path, callback) {
+        (Some(path), _) => {
+            let sink =
+                FileSink::open(path).or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+            Some(Arc::new(sink))
+        },
+        (None, Some(callback)) => {
+            let mut channel = ctx.channel();
+            // The logging must not keep the process running
+            channel.unref(&mut ctx);
+            Some(Arc::new(CallbackSink {
+                channel,
+                callback: Arc::new(callback),
+            }))
+        },
+        (None, None) => None,
+    };
+    set_sink(sink);
+    set_level(level);
+
+    Ok(ctx.undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MemorySink(Mutex<Vec<LogRecord>>);
+
+    impl LogSink for MemorySink {
+        fn write(&self, record: &LogRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("off").unwrap(), None);
+        assert_eq!(parse_level("DEBUG").unwrap(), Some(Level::DEBUG));
+        assert_eq!(
+            parse_level("verbose").unwrap_err().to_string(),
+            "Invalid log level `verbose`"
+        );
+    }
+
+    #[test]
+    fn test_sink_layer() {
+        let sink = Arc::new(MemorySink(Mutex::new(vec![])));
+        set_sink(Some(Arc::clone(&sink) as Arc<dyn LogSink>));
+        LEVEL.store(level_value(&Level::DEBUG), Ordering::Relaxed);
+
+        let subscriber = Registry::default().with(SinkLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("commit", height = 1).entered();
+            tracing::debug!(keys = 3, "updated");
+            tracing::trace!("skipped");
+            span.exit();
+        });
+        LEVEL.store(0, Ordering::Relaxed);
+        set_sink(None);
+
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].level, Level::DEBUG);
+        assert_eq!(records[0].spans, "commit{height=1}");
+        assert_eq!(records[0].message, "updated keys=3");
+        assert_eq!(records[1].level, Level::INFO);
+        assert!(records[1].message.starts_with("closed in "));
+    }
+}
@@ -0,0 +1,259 @@
+/// memory provides accounting of the native memory held by caches, writers and batches.
+/// The accounted size is the sum of the bytes of the keys and values, so it is deterministic regardless of the allocator.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+use thiserror::Error;
+
+/// NO_BUDGET indicates that the budget is not set.
+const NO_BUDGET: usize = usize::MAX;
+
+static BUDGET: AtomicUsize = AtomicUsize::new(NO_BUDGET);
+static USAGE: [AtomicUsize; 4] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+#[derive(Error, Debug)]
+pub enum MemoryError {
+    #[error("Memory budget of {budget} bytes is exceeded with {used} bytes in use and {requested} bytes requested")]
+    BudgetExceeded {
+        budget: usize,
+        used: usize,
+        requested: usize,
+    },
+}
+
+/// Component is the kind of the memory holder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    WriteBatch = 0,
+    StateWriter = 1,
+    InMemoryDatabase = 2,
+    InMemorySMT = 3,
+}
+
+impl Component {
+    pub const ALL: [Component; 4] = [
+        Component::WriteBatch,
+        Component::StateWriter,
+        Component::InMemoryDatabase,
+        Component::InMemorySMT,
+    ];
+
+    /// name returns the name used for reporting to JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::WriteBatch => "writeBatch",
+            Component::StateWriter => "stateWriter",
+            Component::InMemoryDatabase => "inMemoryDatabase",
+            Component::InMemorySMT => "inMemorySMT",
+        }
+    }
+
+    fn counter(&self) -> &'static AtomicUsize {
+        &USAGE[*self as usize]
+    }
+}
+
+/// Usage holds the bytes accounted for a single instance of the component.
+/// The bytes are released from the global usage when it is dropped.
+#[derive(Debug)]
+pub struct Usage {
+    component: Component,
+    bytes: usize,
+}
+
+impl Usage {
+    pub fn new(component: Component) -> Self {
+        Self {
+            component,
+            bytes: 0,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn grow(&mut self, size: usize) {
+        self.component.counter().fetch_add(size, Ordering::SeqCst);
+        self.bytes += size;
+    }
+
+    pub fn shrink(&mut self, size: usize) {
+        let size = size.min(self.bytes);
+        self.component.counter().fetch_sub(size, Ordering::SeqCst);
+        self.bytes -= size;
+    }
+
+    /// set updates the accounted bytes to the size.
+    pub fn set(&mut self, size: usize) {
+        if size > self.bytes {
+            self.grow(size - self.bytes);
+        } else {
+            self.shrink(self.bytes - size);
+        }
+    }
+}
+
+impl Clone for Usage {
+    fn clone(&self) -> Self {
+        let mut cloned = Self::new(self.component);
+        cloned.grow(self.bytes);
+        cloned
+    }
+}
+
+impl Drop for Usage {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
+
+/// usage returns the bytes currently held by all the instances of the component.
+pub fn usage(component: Component) -> usize {
+    component.counter().load(Ordering::SeqCst)
+}
+
+/// total returns the bytes currently held by all the components.
+pub fn total() -> usize {
+    Component::ALL.iter().copied().map(usage).sum()
+}
+
+/// budget returns the maximum bytes allowed, or None if unlimited.
+pub fn budget() -> Option<usize> {
+    match BUDGET.load(Ordering::SeqCst) {
+        NO_BUDGET => None,
+        val => Some(val),
+    }
+}
+
+pub fn set_budget(budget: Option<usize>) {
+    BUDGET.store(budget.unwrap_or(NO_BUDGET), Ordering::SeqCst);
+}
+
+/// reserve checks that the size can be added without exceeding the budget.
+/// It must be called before the data is added to the component.
+pub fn reserve(size: usize) -> Result<(), MemoryError> {
+    match budget() {
+        Some(budget) => check(budget, total(), size),
+        None => Ok(()),
+    }
+}
+
+/// check returns BudgetExceeded if the size can not be added to the used bytes within the budget.
+fn check(budget: usize, used: usize, size: usize) -> Result<(), MemoryError> {
+    if used.saturating_add(size) > budget {
+        return Err(MemoryError::BudgetExceeded {
+            budget,
+            used,
+            requested: size,
+        });
+    }
+    Ok(())
+}
+
+/// js_memory_usage is handler for JS ffi.
+/// - @returns - { budget: number | null, total: number, components: { [name]: number } }.
+#[cfg(feature = "neon")]
+pub fn js_memory_usage(mut ctx: FunctionContext) -> JsResult<JsObject> {
+    let obj = ctx.empty_object();
+    let budget: Handle<JsValue> = match budget() {
+        Some(val) => ctx.number(val as f64).upcast(),
+        None => ctx.null().upcast(),
+    };
+    obj.set(&mut ctx, "budget", budget)?;
+    let total = ctx.number(total() as f64);
+    obj.set(&mut ctx, "total", total)?;
+    let components = ctx.empty_object();
+    for component in Component::ALL.iter() {
+        let val = ctx.number(usage(*component) as f64);
+        components.set(&mut ctx, component.name(), val)?;
+    }
+    obj.set(&mut ctx, "components", components)?;
+
+    Ok(obj)
+}
+
+/// js_set_memory_budget is handler for JS ffi.
+/// - @params(0) - maximum bytes allowed. null or undefined removes the budget.
+#[cfg(feature = "neon")]
+pub fn js_set_memory_budget(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let budget = ctx.argument_opt(0);
+    let budget = match budget {
+        Some(val) if val.is_a::<JsNumber, _>(&mut ctx) => {
+            let val = val
+                .downcast_or_throw::<JsNumber, _>(&mut ctx)?
+                .value(&mut ctx);
+            if !val.is_finite() || val < 0.0 {
+                return ctx.throw_range_error("Memory budget must be a non-negative number");
+            }
+            Some(val as usize)
+        },
+        Some(val) if val.is_a::<JsNull, _>(&mut ctx) || val.is_a::<JsUndefined, _>(&mut ctx) => {
+            None
+        },
+        Some(_) => return ctx.throw_type_error("Memory budget must be a number"),
+        None => None,
+    };
+    set_budget(budget);
+
+    Ok(ctx.undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage::new(Component::WriteBatch);
+        usage.grow(10);
+        assert_eq!(usage.bytes(), 10);
+        usage.shrink(4);
+        assert_eq!(usage.bytes(), 6);
+        usage.shrink(100);
+        assert_eq!(usage.bytes(), 0);
+        usage.set(20);
+        assert_eq!(usage.bytes(), 20);
+
+        let cloned = usage.clone();
+        assert_eq!(cloned.bytes(), 20);
+        usage.set(5);
+        assert_eq!(cloned.bytes(), 20);
+    }
+
+    #[test]
+    fn test_reserve() {
+        assert!(matches!(
+            check(0, 0, 1),
+            Err(MemoryError::BudgetExceeded {
+                budget: 0,
+                used: 0,
+                requested: 1,
+            })
+        ));
+        assert!(check(10, 4, 6).is_ok());
+        assert!(matches!(
+            check(10, 5, 6),
+            Err(MemoryError::BudgetExceeded { used: 5, .. })
+        ));
+        assert!(check(10, usize::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_budget() {
+        // the budget is shared by the tests running in parallel, so it is never set to a limit they can exceed
+        set_budget(Some(usize::MAX - 1));
+        assert_eq!(budget(), Some(usize::MAX - 1));
+        assert!(reserve(1).is_ok());
+
+        set_budget(None);
+        assert_eq!(budget(), None);
+        assert!(reserve(usize::MAX).is_ok());
+    }
+}
@@ -0,0 +1,534 @@
+/// metrics records the counters and latencies of the state tree to the registered sink.
+/// Nothing is recorded until a sink is set, so the instrumentation costs a single atomic load by default.
+/// The counters of an operation are added to the thread local Scope, which takes the sink once and reports
+/// the totals when the operation ends, tagged with the instance of the database running it.
+use std::cell::RefCell;
+#[cfg(feature = "neon")]
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+#[cfg(feature = "neon")]
+use std::sync::Mutex;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+
+#[cfg(feature = "neon")]
+use crate::types::RecoverLock;
+use crate::types::RecoverRwLock;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SINK: RwLock<Option<Arc<dyn MetricsSink>>> = RwLock::new(None);
+static STATS: Stats = Stats::new();
+#[cfg(feature = "neon")]
+static INSTANCE_STATS: Mutex<Option<HashMap<Instance, Arc<Stats>>>> = Mutex::new(None);
+static NEXT_INSTANCE: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    /// LOCAL_COUNTERS are the counters of the Scope running on the thread.
+    static LOCAL_COUNTERS: RefCell<Option<[u64; 3]>> = const { RefCell::new(None) };
+}
+
+/// BUCKETS are the upper bounds of the latency buckets in microseconds. The last bucket has no upper bound.
+pub const BUCKETS: [u64; 6] = [100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+/// Instance identifies the database whose operations are recorded. UNTAGGED is used by the trees without a database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Instance(u32);
+
+impl Instance {
+    pub const UNTAGGED: Instance = Instance(0);
+
+    /// next returns the instance which is not used by any other database.
+    pub fn next() -> Self {
+        Self(NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Counter is the kind of the event counted by the sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// SubtreeCacheHits counts the subtrees resolved without reading the database, such as empty subtrees.
+    SubtreeCacheHits = 0,
+    DbGets = 1,
+    NodesHashed = 2,
+}
+
+/// Histogram is the kind of the latency observed by the sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Histogram {
+    CommitLatency = 0,
+    ProveLatency = 1,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 3] = [
+        Counter::SubtreeCacheHits,
+        Counter::DbGets,
+        Counter::NodesHashed,
+    ];
+
+    /// name returns the name used for reporting to JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::SubtreeCacheHits => "subtreeCacheHits",
+            Counter::DbGets => "dbGets",
+            Counter::NodesHashed => "nodesHashed",
+        }
+    }
+}
+
+impl Histogram {
+    pub const ALL: [Histogram; 2] = [Histogram::CommitLatency, Histogram::ProveLatency];
+
+    /// name returns the name used for reporting to JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Histogram::CommitLatency => "commitLatency",
+            Histogram::ProveLatency => "proveLatency",
+        }
+    }
+}
+
+/// MetricsSink receives the metrics recorded by the state tree.
+/// It is called from any thread, including the worker threads of the JS handlers.
+pub trait MetricsSink: Send + Sync {
+    fn increment(&self, instance: Instance, counter: Counter, value: u64);
+    fn observe(&self, instance: Instance, histogram: Histogram, duration: Duration);
+}
+
+/// HistogramStats holds the count, the sum and the maximum of the observed latencies in microseconds,
+/// and the number of observations per bucket of BUCKETS.
+pub struct HistogramStats {
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+}
+
+/// HistogramSnapshot is the copy of HistogramStats at a point in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: u64,
+    pub max: u64,
+    pub buckets: Vec<u64>,
+}
+
+/// Stats is the built-in sink which keeps the totals in memory. It is reported to JS by getStats.
+pub struct Stats {
+    counters: [AtomicU64; 3],
+    histograms: [HistogramStats; 2],
+}
+
+/// StatsSink registers the global Stats as the sink. The stats of each instance are kept as well.
+#[cfg(feature = "neon")]
+struct StatsSink;
+
+/// Scope counts the events of an operation on the current thread, and reports them to the sink taken at the start
+/// when it is dropped, with the latency of the operation if it has the histogram.
+pub struct Scope {
+    instance: Instance,
+    histogram: Option<Histogram>,
+    sink: Option<Arc<dyn MetricsSink>>,
+    start: Instant,
+    outer: Option<[u64; 3]>,
+}
+
+impl HistogramStats {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+            buckets: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(micros, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            counters: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)],
+            histograms: [HistogramStats::new(), HistogramStats::new()],
+        }
+    }
+
+    pub fn counter(&self, counter: Counter) -> u64 {
+        self.counters[counter as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn histogram(&self, histogram: Histogram) -> HistogramSnapshot {
+        self.histograms[histogram as usize].snapshot()
+    }
+
+    pub fn reset(&self) {
+        for counter in self.counters.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+        for histogram in self.histograms.iter() {
+            histogram.reset();
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsSink for Stats {
+    fn increment(&self, _instance: Instance, counter: Counter, value: u64) {
+        self.counters[counter as usize].fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn observe(&self, _instance: Instance, histogram: Histogram, duration: Duration) {
+        self.histograms[histogram as usize].observe(duration);
+    }
+}
+
+#[cfg(feature = "neon")]
+impl MetricsSink for StatsSink {
+    fn increment(&self, instance: Instance, counter: Counter, value: u64) {
+        STATS.increment(instance, counter, value);
+        instance_stats(instance).increment(instance, counter, value);
+    }
+
+    fn observe(&self, instance: Instance, histogram: Histogram, duration: Duration) {
+        STATS.observe(instance, histogram, duration);
+        instance_stats(instance).observe(instance, histogram, duration);
+    }
+}
+
+impl Scope {
+    /// start begins the operation whose latency is observed in the histogram.
+    pub fn start(instance: Instance, histogram: Histogram) -> Self {
+        Self::new(instance, Some(histogram))
+    }
+
+    /// count begins the part of an operation running on another thread, which reports only the counters.
+    pub fn count(instance: Instance) -> Self {
+        Self::new(instance, None)
+    }
+
+    fn new(instance: Instance, histogram: Option<Histogram>) -> Self {
+        let sink = if is_enabled() {
+            SINK.read_or_recover().clone()
+        } else {
+            None
+        };
+        // the scope nested in another one on the same thread keeps the counters of the outer scope aside
+        let outer = match sink {
+            Some(_) => LOCAL_COUNTERS.with(|local| local.borrow_mut().replace([0; 3])),
+            None => None,
+        };
+        Self {
+            instance,
+            histogram,
+            sink,
+            start: Instant::now(),
+            outer,
+        }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            None => return,
+        };
+        let counters = LOCAL_COUNTERS.with(|local| {
+            let mut local = local.borrow_mut();
+            let counters = local.take();
+            *local = self.outer.take();
+            counters
+        });
+        for (counter, value) in Counter::ALL.iter().zip(counters.unwrap_or_default()) {
+            if value > 0 {
+                sink.increment(self.instance, *counter, value);
+            }
+        }
+        if let Some(histogram) = self.histogram {
+            sink.observe(self.instance, histogram, self.start.elapsed());
+        }
+    }
+}
+
+/// set_sink registers the sink which receives all the metrics. None stops recording.
+pub fn set_sink(sink: Option<Arc<dyn MetricsSink>>) {
+    let mut current = SINK.write_or_recover();
+    ENABLED.store(sink.is_some(), Ordering::SeqCst);
+    *current = sink;
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// stats returns the built-in sink used by JS.
+pub fn stats() -> &'static Stats {
+    &STATS
+}
+
+/// instance_stats returns the stats of the instance kept by the built-in sink.
+#[cfg(feature = "neon")]
+fn instance_stats(instance: Instance) -> Arc<Stats> {
+    let mut stats = INSTANCE_STATS.lock_or_recover();
+    Arc::clone(
+        stats
+            .get_or_insert_with(HashMap::new)
+            .entry(instance)
+            .or_default(),
+    )
+}
+
+/// increment adds the value to the counter of the Scope running on the current thread.
+/// The value is reported to the sink directly as untagged if there is no Scope.
+pub fn increment(counter: Counter, value: u64) {
+    if !is_enabled() {
+        return;
+    }
+    let counted = LOCAL_COUNTERS.with(|local| match local.borrow_mut().as_mut() {
+        Some(counters) => {
+            counters[counter as usize] += value;
+            true
+        },
+        None => false,
+    });
+    if counted {
+        return;
+    }
+    if let Some(sink) = SINK.read_or_recover().as_ref() {
+        sink.increment(Instance::UNTAGGED, counter, value);
+    }
+}
+
+/// stats_to_js_object returns { enabled: boolean, counters: { [name]: number }, histograms: { [name]: { count, sum, max, buckets } } }.
+#[cfg(feature = "neon")]
+fn stats_to_js_object<'a, C: Context<'a>>(ctx: &mut C, stats: &Stats) -> JsResult<'a, JsObject> {
+    let obj = ctx.empty_object();
+    let enabled = ctx.boolean(is_enabled());
+    obj.set(ctx, "enabled", enabled)?;
+    let counters = ctx.empty_object();
+    for counter in Counter::ALL.iter() {
+        let val = ctx.number(stats.counter(*counter) as f64);
+        counters.set(ctx, counter.name(), val)?;
+    }
+    obj.set(ctx, "counters", counters)?;
+    let histograms = ctx.empty_object();
+    for histogram in Histogram::ALL.iter() {
+        let snapshot = stats.histogram(*histogram);
+        let hist_obj = ctx.empty_object();
+        let count = ctx.number(snapshot.count as f64);
+        hist_obj.set(ctx, "count", count)?;
+        let sum = ctx.number(snapshot.sum as f64);
+        hist_obj.set(ctx, "sum", sum)?;
+        let max = ctx.number(snapshot.max as f64);
+        hist_obj.set(ctx, "max", max)?;
+        let buckets = ctx.empty_array();
+        for (i, val) in snapshot.buckets.iter().enumerate() {
+            let bucket = ctx.empty_object();
+            let le: Handle<JsValue> = match BUCKETS.get(i) {
+                Some(bound) => ctx.number(*bound as f64).upcast(),
+                None => ctx.null().upcast(),
+            };
+            bucket.set(ctx, "le", le)?;
+            let val = ctx.number(*val as f64);
+            bucket.set(ctx, "count", val)?;
+            buckets.set(ctx, i as u32, bucket)?;
+        }
+        hist_obj.set(ctx, "buckets", buckets)?;
+        histograms.set(ctx, histogram.name(), hist_obj)?;
+    }
+    obj.set(ctx, "histograms", histograms)?;
+
+    Ok(obj)
+}
+
+/// instance_stats_to_js_object returns the stats of the instance in the same form as js_get_stats.
+#[cfg(feature = "neon")]
+pub fn instance_stats_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    instance: Instance,
+) -> JsResult<'a, JsObject> {
+    stats_to_js_object(ctx, &instance_stats(instance))
+}
+
+/// js_get_stats is handler for JS ffi.
+/// - @returns - { enabled: boolean, counters: { [name]: number }, histograms: { [name]: { count, sum, max, buckets } } }
+///   of all the instances.
+#[cfg(feature = "neon")]
+pub fn js_get_stats(mut ctx: FunctionContext) -> JsResult<JsObject> {
+    stats_to_js_object(&mut ctx, &STATS)
+}
+
+/// js_set_stats_enabled is handler for JS ffi.
+/// - @params(0) - true registers the built-in sink, false stops recording.
+#[cfg(feature = "neon")]
+pub fn js_set_stats_enabled(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let enabled = ctx.argument::<JsBoolean>(0)?.value(&mut ctx);
+    if enabled {
+        set_sink(Some(Arc::new(StatsSink)));
+    } else {
+        set_sink(None);
+    }
+
+    Ok(ctx.undefined())
+}
+
+/// js_reset_stats is handler for JS ffi.
+/// The stats of all the instances are reset as well.
+#[cfg(feature = "neon")]
+pub fn js_reset_stats(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    STATS.reset();
+    *INSTANCE_STATS.lock_or_recover() = None;
+
+    Ok(ctx.undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::new();
+        stats.increment(Instance::UNTAGGED, Counter::DbGets, 2);
+        stats.increment(Instance::UNTAGGED, Counter::DbGets, 3);
+        assert_eq!(stats.counter(Counter::DbGets), 5);
+        assert_eq!(stats.counter(Counter::NodesHashed), 0);
+
+        stats.observe(
+            Instance::UNTAGGED,
+            Histogram::ProveLatency,
+            Duration::from_micros(50),
+        );
+        stats.observe(
+            Instance::UNTAGGED,
+            Histogram::ProveLatency,
+            Duration::from_millis(5),
+        );
+        stats.observe(
+            Instance::UNTAGGED,
+            Histogram::ProveLatency,
+            Duration::from_secs(60),
+        );
+        let snapshot = stats.histogram(Histogram::ProveLatency);
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.sum, 60_005_050);
+        assert_eq!(snapshot.max, 60_000_000);
+        assert_eq!(snapshot.buckets, vec![1, 0, 1, 0, 0, 0, 1]);
+
+        stats.reset();
+        assert_eq!(stats.counter(Counter::DbGets), 0);
+        assert_eq!(
+            stats.histogram(Histogram::ProveLatency),
+            HistogramSnapshot {
+                buckets: vec![0; BUCKETS.len() + 1],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        records: std::sync::Mutex<Vec<(Instance, Counter, u64)>>,
+        observed: std::sync::Mutex<Vec<(Instance, Histogram)>>,
+    }
+
+    impl MetricsSink for Recorder {
+        fn increment(&self, instance: Instance, counter: Counter, value: u64) {
+            self.records
+                .lock()
+                .unwrap()
+                .push((instance, counter, value));
+        }
+
+        fn observe(&self, instance: Instance, histogram: Histogram, _duration: Duration) {
+            self.observed.lock().unwrap().push((instance, histogram));
+        }
+    }
+
+    #[test]
+    fn test_scope() {
+        let recorder = Arc::new(Recorder::default());
+        set_sink(Some(Arc::clone(&recorder) as Arc<dyn MetricsSink>));
+        let (first, second) = (Instance::next(), Instance::next());
+        {
+            let _scope = Scope::start(first, Histogram::CommitLatency);
+            increment(Counter::NodesHashed, 1);
+            {
+                let _scope = Scope::count(second);
+                increment(Counter::DbGets, 2);
+            }
+            increment(Counter::NodesHashed, 2);
+        }
+        set_sink(None);
+
+        // the counters of each scope are reported once, when the scope is dropped
+        let records = recorder.records.lock().unwrap();
+        let records = records
+            .iter()
+            .filter(|(instance, _, _)| *instance == first || *instance == second)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                &(second, Counter::DbGets, 2),
+                &(first, Counter::NodesHashed, 3)
+            ]
+        );
+        assert!(recorder
+            .observed
+            .lock()
+            .unwrap()
+            .contains(&(first, Histogram::CommitLatency)));
+        assert!(LOCAL_COUNTERS.with(|local| local.borrow().is_none()));
+    }
+}
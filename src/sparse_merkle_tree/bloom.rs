@@ -0,0 +1,152 @@
+// bloom keeps the leaf keys of the SMT in a bloom filter, so that the lookup of the key which is not in the tree can be skipped.
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+
+use crate::codec;
+
+/// BITS_PER_KEY is the number of the bits of the filter for each key of its capacity.
+const BITS_PER_KEY: usize = 10;
+/// NUM_HASHES is the number of the bits set for each key, which keeps the false positive rate around 1% at the capacity.
+const NUM_HASHES: u32 = 7;
+/// MIN_CAPACITY is the smallest number of the keys the filter is created for.
+const MIN_CAPACITY: usize = 1024;
+
+/// BloomFilter tells that the key is definitely not inserted, or that it may have been inserted.
+/// The keys are never removed, so the filter of the tree is a superset of its leaf keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    num_hashes: u32,
+    count: u32,
+}
+
+impl BloomFilter {
+    /// with_capacity creates the empty filter sized for `capacity` keys.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        Self {
+            bits: vec![0; capacity * BITS_PER_KEY / 8],
+            num_hashes: NUM_HASHES,
+            count: 0,
+        }
+    }
+
+    /// capacity returns the number of the keys the filter is sized for.
+    pub fn capacity(&self) -> usize {
+        self.bits.len() * 8 / BITS_PER_KEY
+    }
+
+    /// count returns the number of the distinct keys inserted.
+    /// The key already in the filter, or ruled in by a false positive, is not counted again.
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    /// is_full returns true once more keys than the capacity are inserted,
+    /// and the filter should be rebuilt with a larger capacity.
+    pub fn is_full(&self) -> bool {
+        self.count() > self.capacity()
+    }
+
+    /// insert adds the key to the filter, and returns true if it was not in the filter.
+    pub fn insert(&mut self, key: &[u8]) -> bool {
+        let mut inserted = false;
+        for pos in self.positions(key) {
+            let bit = 1 << (pos % 8);
+            inserted |= self.bits[pos / 8] & bit == 0;
+            self.bits[pos / 8] |= bit;
+        }
+        if inserted {
+            self.count = self.count.saturating_add(1);
+        }
+        inserted
+    }
+
+    /// contains returns false only if the key was never inserted.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|pos| self.bits[pos / 8] & (1 << (pos % 8)) != 0)
+    }
+
+    /// positions returns the bits of the key by the double hashing of the two halves of its sha256.
+    /// The hash is fixed, as the filter is persisted.
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = Sha256::digest(key);
+        let h1 = u64::from_be_bytes(hash[..8].try_into().unwrap());
+        let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap());
+        let num_bits = (self.bits.len() * 8) as u64;
+        (0..self.num_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, self.num_hashes);
+        writer.write_u32(2, self.count);
+        writer.write_bytes(3, &self.bits);
+        writer.result().to_vec()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, codec::CodecError> {
+        let mut reader = codec::Reader::new(data);
+        let num_hashes = reader.read_u32(1)?;
+        let count = reader.read_u32(2)?;
+        let bits = reader.read_bytes(3)?;
+        if bits.is_empty() {
+            return Err(codec::CodecError::InvalidBytesLength);
+        }
+        if num_hashes == 0 {
+            return Err(codec::CodecError::OutOfRange);
+        }
+
+        Ok(Self {
+            bits,
+            num_hashes,
+            count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::with_capacity(0);
+        assert_eq!(filter.capacity(), MIN_CAPACITY);
+        let keys: Vec<Vec<u8>> = (0..MIN_CAPACITY as u32)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect();
+        for key in keys.iter() {
+            assert!(!filter.is_full());
+            filter.insert(key);
+        }
+        assert!(keys.iter().all(|key| filter.contains(key)));
+        let count = filter.count();
+        // a few keys are ruled in by the false positives of the keys inserted before them
+        assert!(count <= MIN_CAPACITY && count > MIN_CAPACITY * 95 / 100);
+        // the keys already in the filter are not counted again
+        for key in keys.iter() {
+            assert!(!filter.insert(key));
+        }
+        assert_eq!(filter.count(), count);
+
+        let false_positives = (MIN_CAPACITY as u32..MIN_CAPACITY as u32 * 11)
+            .filter(|i| filter.contains(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < MIN_CAPACITY * 10 / 20);
+
+        let mut i = MIN_CAPACITY as u32;
+        while !filter.is_full() {
+            filter.insert(&i.to_be_bytes());
+            i += 1;
+        }
+        assert!(filter.count() > filter.capacity());
+
+        let decoded = BloomFilter::decode(&filter.encode()).unwrap();
+        assert_eq!(decoded, filter);
+        assert!(BloomFilter::decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,358 @@
+// chunk splits the leaves of the SMT into deterministic chunks which can be verified individually against the root.
+use std::cmp;
+use std::sync::Arc;
+
+use crate::codec;
+use crate::consts;
+use crate::database::traits::Actions;
+use crate::sparse_merkle_tree::smt::{LeafWalk, SMTError, UpdateData};
+use crate::sparse_merkle_tree::{Proof, QueryProof, SparseMerkleTree};
+use crate::types::{KVPair, KeyLength, NestedVec, RecoverLock, SharedVec};
+use crate::utils;
+
+/// Chunk holds the consecutive leaves of the tree with the inclusion proof of all of them.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub index: u32,
+    pub pairs: Vec<KVPair>,
+    pub proof: Proof,
+}
+
+impl Chunk {
+    pub fn encode(&self) -> Vec<u8> {
+        let bitmaps = self
+            .proof
+            .queries
+            .iter()
+            .map(|query| query.bitmap.to_vec())
+            .collect::<NestedVec>();
+
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, self.index);
+        for pair in self.pairs.iter() {
+            writer.write_message(2, |writer| {
+                writer.write_bytes(1, pair.key());
+                writer.write_bytes(2, pair.value());
+            });
+        }
+        writer.write_bytes_slice(3, &self.proof.sibling_hashes);
+        writer.write_bytes_slice(4, &bitmaps);
+        writer.result().to_vec()
+    }
+
+    /// decode returns the chunk created by encode. Queries of the proof are restored from the pairs.
+    /// The chunks are received from the other nodes, so the data is decoded in the strict mode.
+    pub fn decode(data: &[u8]) -> Result<Self, codec::CodecError> {
+        let mut reader = codec::Reader::strict(data);
+        let index = reader.read_u32(1)?;
+        let mut pairs = vec![];
+        for mut reader in reader.read_messages(2)? {
+            let key = reader.read_bytes(1)?;
+            let value = reader.read_bytes(2)?;
+            reader.finish()?;
+            pairs.push(KVPair::new(&key, &value));
+        }
+        let sibling_hashes = reader.read_bytes_slice(3)?;
+        let bitmaps = reader.read_bytes_slice(4)?;
+        reader.finish()?;
+        if bitmaps.len() != pairs.len() {
+            return Err(codec::CodecError::InvalidBytesLength);
+        }
+        let queries = pairs
+            .iter()
+            .zip(bitmaps)
+            .map(|(pair, bitmap)| QueryProof {
+                pair: Arc::new(pair.clone()),
+                bitmap: Arc::new(bitmap),
+                stored_value: None,
+            })
+            .collect();
+
+        Ok(Self {
+            index,
+            pairs,
+            proof: Proof {
+                sibling_hashes,
+                queries,
+            },
+        })
+    }
+
+    /// verify returns true if all the pairs of the chunk are included in the tree of the root.
+    pub fn verify(&self, root: &[u8], key_length: KeyLength) -> Result<bool, SMTError> {
+        if self.pairs.is_empty() || self.pairs.len() != self.proof.queries.len() {
+            return Ok(false);
+        }
+        for (pair, query) in self.pairs.iter().zip(self.proof.queries.iter()) {
+            if pair.is_empty_value() || pair.key() != query.key() || pair.value() != query.value()
+            {
+                return Ok(false);
+            }
+        }
+        let keys = self
+            .pairs
+            .iter()
+            .map(|pair| pair.key_as_vec())
+            .collect::<NestedVec>();
+
+        SparseMerkleTree::verify(&keys, &self.proof, root, key_length)
+    }
+}
+
+/// ChunkExporter returns the chunks of the leaves of the tree one by one in the order of the index.
+/// Only the chunk being exported and the subtrees on the path to its last leaf are held, so the proof of the chunk
+/// reads the path from memory. The same root and chunk_size always result in the same chunks.
+pub struct ChunkExporter<'a, T: Actions> {
+    tree: SparseMerkleTree,
+    walk: LeafWalk<'a, T>,
+    chunk_size: usize,
+    index: u32,
+}
+
+impl<'a, T: Actions> ChunkExporter<'a, T> {
+    pub fn new(
+        db: &'a T,
+        root: &[u8],
+        key_length: KeyLength,
+        chunk_size: usize,
+    ) -> Result<Self, SMTError> {
+        if chunk_size == 0 {
+            return Err(SMTError::InvalidInput(String::from(
+                "chunk size must be greater than zero",
+            )));
+        }
+        let tree = SparseMerkleTree::new(root, key_length, consts::SUBTREE_HEIGHT);
+        let walk = tree.leaf_walk(db);
+
+        Ok(Self {
+            tree,
+            walk,
+            chunk_size,
+            index: 0,
+        })
+    }
+
+    /// next_chunk returns the chunk of the next index, or None after the last chunk.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>, SMTError> {
+        let mut pairs = Vec::with_capacity(self.chunk_size);
+        while pairs.len() < self.chunk_size {
+            match self.walk.next_leaf()? {
+                Some(pair) => pairs.push(pair),
+                None => break,
+            }
+        }
+        if pairs.is_empty() {
+            return Ok(None);
+        }
+        let keys = pairs
+            .iter()
+            .map(|pair| pair.key_as_vec())
+            .collect::<NestedVec>();
+        let proof = self.tree.prove(&self.walk, &keys)?;
+        let chunk = Chunk {
+            index: self.index,
+            pairs,
+            proof,
+        };
+        self.index += 1;
+
+        Ok(Some(chunk))
+    }
+}
+
+impl<T: Actions> Iterator for ChunkExporter<'_, T> {
+    type Item = Result<Chunk, SMTError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+/// ChunkImporter builds the tree of the root into the db from the chunks received one by one.
+/// Each chunk is verified against the root and written to the db before the next one is accepted,
+/// and the rebuilt root is compared with the root once, when all the chunks are imported.
+pub struct ChunkImporter {
+    root: Vec<u8>,
+    tree: SparseMerkleTree,
+    key_length: KeyLength,
+    index: u32,
+    last_key: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "neon")]
+impl neon::types::Finalize for ChunkImporter {}
+
+impl ChunkImporter {
+    pub fn new(root: &[u8], key_length: KeyLength) -> Self {
+        Self {
+            root: root.to_vec(),
+            tree: SparseMerkleTree::new(&[], key_length, consts::SUBTREE_HEIGHT),
+            key_length,
+            index: 0,
+            last_key: None,
+        }
+    }
+
+    /// import verifies the chunk of the next index against the root and writes its pairs to the db.
+    /// The pairs must follow the pairs of the previous chunks, so a pair is never imported twice.
+    pub fn import(&mut self, db: &mut impl Actions, chunk: Chunk) -> Result<(), SMTError> {
+        if chunk.index != self.index {
+            return Err(SMTError::InvalidInput(format!(
+                "chunk {} is missing or out of order",
+                self.index
+            )));
+        }
+        if !chunk.verify(&self.root, self.key_length)? {
+            return Err(SMTError::InvalidInput(format!(
+                "chunk {} is not included in the root",
+                self.index
+            )));
+        }
+        let mut last_key = self.last_key.as_deref();
+        for pair in chunk.pairs.iter() {
+            if last_key.is_some_and(|last| utils::compare(last, pair.key()) != cmp::Ordering::Less)
+            {
+                return Err(SMTError::InvalidInput(format!(
+                    "chunk {} is not sorted after the previous chunks",
+                    self.index
+                )));
+            }
+            last_key = Some(pair.key());
+        }
+        self.last_key = last_key.map(|key| key.to_vec());
+        let data = UpdateData::new_from(
+            chunk
+                .pairs
+                .into_iter()
+                .map(|pair| (pair.key_as_vec(), pair.value_as_vec())),
+        );
+        self.tree.commit(db, &data)?;
+        self.index += 1;
+
+        Ok(())
+    }
+
+    /// finish returns the rebuilt root, which must be equal to the root.
+    pub fn finish(&self) -> Result<SharedVec, SMTError> {
+        let new_root = self.tree.root();
+        if **new_root.lock_or_recover() != self.root {
+            return Err(SMTError::InvalidRoot(String::from(
+                "imported chunks do not cover all the leaves of the root",
+            )));
+        }
+
+        Ok(new_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse_merkle_tree::smt_db::InMemorySmtDB;
+    use crate::types::Cache;
+
+    fn create_tree(count: u8) -> (InMemorySmtDB, Vec<u8>) {
+        let mut db = InMemorySmtDB::default();
+        let mut data = Cache::new();
+        for i in 0..count {
+            data.insert(vec![i; 32], vec![i + 1; 32]);
+        }
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), consts::SUBTREE_HEIGHT);
+        let root = tree.commit(&mut db, &UpdateData::new_from(data)).unwrap();
+        let root = root.lock().unwrap().to_vec();
+        (db, root)
+    }
+
+    fn export(db: &InMemorySmtDB, root: &[u8], chunk_size: usize) -> Vec<Chunk> {
+        ChunkExporter::new(db, root, KeyLength(32), chunk_size)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn import(
+        db: &mut InMemorySmtDB,
+        root: &[u8],
+        chunks: Vec<Chunk>,
+    ) -> Result<SharedVec, SMTError> {
+        let mut importer = ChunkImporter::new(root, KeyLength(32));
+        for chunk in chunks {
+            importer.import(db, chunk)?;
+        }
+        importer.finish()
+    }
+
+    #[test]
+    fn test_export_chunks() {
+        let (db, root) = create_tree(25);
+        let chunks = export(&db, &root, 10);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].pairs.len(), 5);
+        assert_eq!(chunks[0].pairs[0], KVPair::new(&[0; 32], &[1; 32]));
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.index as usize, index);
+            assert!(chunk.verify(&root, KeyLength(32)).unwrap());
+            let decoded = Chunk::decode(&chunk.encode()).unwrap();
+            assert_eq!(decoded.encode(), chunk.encode());
+            assert!(decoded.verify(&root, KeyLength(32)).unwrap());
+            assert!(!decoded.verify(&[0; 32], KeyLength(32)).unwrap());
+            let trailing = [chunk.encode(), vec![0x08, 0x01]].concat();
+            assert!(matches!(
+                Chunk::decode(&trailing),
+                Err(codec::CodecError::FieldOutOfOrder(1, 4))
+            ));
+        }
+        // the proof read from the walked path is the same as the proof from the db
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), consts::SUBTREE_HEIGHT);
+        let keys = chunks[1]
+            .pairs
+            .iter()
+            .map(|pair| pair.key_as_vec())
+            .collect::<NestedVec>();
+        let proof = tree.prove(&db, &keys).unwrap();
+        assert_eq!(proof.sibling_hashes, chunks[1].proof.sibling_hashes);
+
+        let mut exporter = ChunkExporter::new(&db, &root, KeyLength(32), 10).unwrap();
+        assert_eq!(exporter.next_chunk().unwrap().unwrap().index, 0);
+
+        let empty_db = InMemorySmtDB::default();
+        assert!(export(&empty_db, &[], 10).is_empty());
+        assert!(ChunkExporter::new(&db, &root, KeyLength(32), 0).is_err());
+    }
+
+    #[test]
+    fn test_import_chunks() {
+        let (db, root) = create_tree(25);
+        let chunks = export(&db, &root, 10);
+
+        let mut imported = InMemorySmtDB::default();
+        let new_root = import(&mut imported, &root, chunks.clone()).unwrap();
+        assert_eq!(**new_root.lock().unwrap(), root);
+        assert!(db
+            .cache()
+            .iter()
+            .all(|(key, value)| imported.cache().get(key) == Some(value)));
+
+        let mut missing = chunks.clone();
+        missing.pop();
+        assert!(matches!(
+            import(&mut InMemorySmtDB::default(), &root, missing),
+            Err(SMTError::InvalidRoot(_))
+        ));
+
+        let mut reordered = chunks.clone();
+        reordered.swap(0, 1);
+        assert!(matches!(
+            import(&mut InMemorySmtDB::default(), &root, reordered),
+            Err(SMTError::InvalidInput(_))
+        ));
+
+        let mut modified = chunks;
+        modified[1].pairs[0] = KVPair::new(modified[1].pairs[0].key(), &[0; 32]);
+        assert!(matches!(
+            import(&mut InMemorySmtDB::default(), &root, modified),
+            Err(SMTError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+/// differential_tests applies the same random operations to the tree stored in InMemorySmtDB and in the persistent
+/// databases, and checks that the roots, the proofs and the errors are identical.
+/// InMemorySmtDB reads its own writes, while SmtDB reads the nodes only after its batch is written,
+/// so a divergence between the two paths shows up as a different root or proof.
+use proptest::prelude::*;
+
+use crate::consts;
+use crate::database::traits::Actions;
+use crate::sparse_merkle_tree::smt::{SMTError, SparseMerkleTree, UpdateData, EMPTY_HASH};
+use crate::sparse_merkle_tree::smt_db::{InMemorySmtDB, StagedSmtDB};
+use crate::sparse_merkle_tree::Proof;
+use crate::types::{Cache, HashKind, HashWithKind, NestedVec};
+
+const KEY_POOL_SIZE: u32 = 48;
+
+/// Op is the operation applied to every backend.
+/// The key of None in the changes has an invalid length, so the commit fails.
+#[derive(Clone, Debug)]
+enum Op {
+    Commit(Vec<(Option<u32>, Option<u32>)>),
+    Prove(Vec<Option<u32>>),
+    /// ProveAt proves the keys at a previous root, whose nodes may have been removed.
+    ProveAt(usize, Vec<Option<u32>>),
+}
+
+/// Outcome is the comparable result of an operation.
+/// The proof is kept as the sibling hashes and the key, value and bitmap of each query.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Root(Vec<u8>),
+    Proof(NestedVec, Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>),
+    Error(SMTError),
+}
+
+impl From<Result<Proof, SMTError>> for Outcome {
+    fn from(result: Result<Proof, SMTError>) -> Self {
+        match result {
+            Ok(proof) => Outcome::Proof(
+                proof.sibling_hashes,
+                proof
+                    .queries
+                    .iter()
+                    .map(|query| {
+                        (
+                            query.key().to_vec(),
+                            query.value().to_vec(),
+                            query.bitmap.to_vec(),
+                        )
+                    })
+                    .collect(),
+            ),
+            Err(err) => Outcome::Error(err),
+        }
+    }
+}
+
+/// Backend stores the nodes of the tree. The nodes of a failed commit are discarded as the callers of the tree do.
+trait Backend {
+    fn commit(&mut self, root: &[u8], data: &UpdateData) -> Result<Vec<u8>, SMTError>;
+    fn prove(&self, root: &[u8], queries: &[Vec<u8>]) -> Result<Proof, SMTError>;
+}
+
+fn new_tree(root: &[u8]) -> SparseMerkleTree {
+    SparseMerkleTree::new(root, consts::KEY_LENGTH, consts::SUBTREE_HEIGHT)
+}
+
+fn commit_staged<T: Actions>(
+    db: &mut T,
+    root: &[u8],
+    data: &UpdateData,
+) -> Result<Vec<u8>, SMTError> {
+    let mut staged = StagedSmtDB::new(db);
+    let root = new_tree(root).commit(&mut staged, data)?;
+    staged
+        .apply()
+        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+    let root = root.lock().unwrap().to_vec();
+    Ok(root)
+}
+
+impl Backend for InMemorySmtDB {
+    fn commit(&mut self, root: &[u8], data: &UpdateData) -> Result<Vec<u8>, SMTError> {
+        commit_staged(self, root, data)
+    }
+
+    fn prove(&self, root: &[u8], queries: &[Vec<u8>]) -> Result<Proof, SMTError> {
+        new_tree(root).prove(self, queries)
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Backend for crate::sparse_merkle_tree::sled_db::SledSmtDB {
+    fn commit(&mut self, root: &[u8], data: &UpdateData) -> Result<Vec<u8>, SMTError> {
+        commit_staged(self, root, data)
+    }
+
+    fn prove(&self, root: &[u8], queries: &[Vec<u8>]) -> Result<Proof, SMTError> {
+        new_tree(root).prove(self, queries)
+    }
+}
+
+/// RocksBackend writes the batch of SmtDB with the SMT prefix once the commit succeeds, as the state_db does.
+#[cfg(feature = "neon")]
+struct RocksBackend {
+    db: crate::database::DB,
+    _temp_dir: tempdir::TempDir,
+}
+
+#[cfg(feature = "neon")]
+impl RocksBackend {
+    fn new() -> Self {
+        let temp_dir = tempdir::TempDir::new("test_differential").unwrap();
+        let rocks_db = rocksdb::DB::open_default(&temp_dir).unwrap();
+        Self {
+            db: crate::database::DB::new(
+                rocks_db,
+                crate::task_pool::SerialQueue::closed(),
+                crate::database::types::Kind::State,
+            ),
+            _temp_dir: temp_dir,
+        }
+    }
+}
+
+#[cfg(feature = "neon")]
+impl Backend for RocksBackend {
+    fn commit(&mut self, root: &[u8], data: &UpdateData) -> Result<Vec<u8>, SMTError> {
+        let mut smt_db = crate::sparse_merkle_tree::smt_db::SmtDB::new(&self.db);
+        let root = new_tree(root).commit(&mut smt_db, data)?;
+        let mut write_batch = crate::batch::PrefixWriteBatch::new();
+        write_batch.set_prefix(&consts::Prefix::SMT);
+        smt_db.batch.iterate(&mut write_batch);
+        self.db
+            .write(write_batch.batch)
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+        let root = root.lock().unwrap().to_vec();
+        Ok(root)
+    }
+
+    fn prove(&self, root: &[u8], queries: &[Vec<u8>]) -> Result<Proof, SMTError> {
+        let reader = crate::sparse_merkle_tree::smt_db::SmtReader::new(&self.db);
+        new_tree(root).prove(&reader, queries)
+    }
+}
+
+/// key returns the key of the index in the pool, so the operations update and delete the same keys.
+/// None returns the key with an invalid length.
+fn key(index: Option<u32>) -> Vec<u8> {
+    match index {
+        Some(index) => {
+            let hash = index.to_be_bytes().to_vec().hash_with_kind(HashKind::Value);
+            [&hash[..6], hash.as_slice()].concat()
+        },
+        None => vec![1; consts::KEY_LENGTH.0 as usize - 1],
+    }
+}
+
+fn index_strategy() -> BoxedStrategy<Option<u32>> {
+    prop_oneof![
+        20 => (0..KEY_POOL_SIZE).prop_map(Some),
+        1 => Just(None),
+    ]
+    .boxed()
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    let changes = prop::collection::vec(
+        (index_strategy(), prop::option::weighted(0.7, any::<u32>())),
+        0..24,
+    );
+    let queries = prop::collection::vec(index_strategy(), 0..8);
+    prop_oneof![
+        3 => changes.prop_map(Op::Commit),
+        2 => queries.clone().prop_map(Op::Prove),
+        1 => (0..8usize, queries).prop_map(|(back, queries)| Op::ProveAt(back, queries)),
+    ]
+}
+
+/// run applies the operations to the backend, and returns the outcome of each operation.
+fn run(backend: &mut dyn Backend, ops: &[Op]) -> Vec<Outcome> {
+    let mut roots = vec![EMPTY_HASH.to_vec()];
+    let mut outcomes = vec![];
+    for op in ops.iter() {
+        let root = roots.last().unwrap().clone();
+        let outcome = match op {
+            Op::Commit(changes) => {
+                let mut data = Cache::new();
+                for (index, value) in changes.iter() {
+                    let value = value.map_or(vec![], |value| {
+                        value.to_be_bytes().to_vec().hash_with_kind(HashKind::Value)
+                    });
+                    data.insert(key(*index), value);
+                }
+                match backend.commit(&root, &UpdateData::new_from(data)) {
+                    Ok(next_root) => {
+                        roots.push(next_root.clone());
+                        Outcome::Root(next_root)
+                    },
+                    Err(err) => Outcome::Error(err),
+                }
+            },
+            Op::Prove(indexes) => {
+                let queries: NestedVec = indexes.iter().map(|index| key(*index)).collect();
+                let result = backend.prove(&root, &queries);
+                if let Ok(proof) = &result {
+                    if !queries.is_empty() {
+                        let valid =
+                            SparseMerkleTree::verify(&queries, proof, &root, consts::KEY_LENGTH);
+                        assert_eq!(valid, Ok(true));
+                    }
+                }
+                result.into()
+            },
+            Op::ProveAt(back, indexes) => {
+                let root = &roots[roots.len() - 1 - back % roots.len()];
+                let queries: NestedVec = indexes.iter().map(|index| key(*index)).collect();
+                backend.prove(root, &queries).into()
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    outcomes
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_backends_are_identical(ops in prop::collection::vec(op_strategy(), 1..16)) {
+        let expected = run(&mut InMemorySmtDB::default(), &ops);
+
+        #[cfg(feature = "sled")]
+        {
+            let temp_dir = tempdir::TempDir::new("test_differential").unwrap();
+            let mut db = crate::sparse_merkle_tree::sled_db::SledSmtDB::open(temp_dir.path()).unwrap();
+            prop_assert_eq!(&run(&mut db, &ops), &expected);
+        }
+
+        #[cfg(feature = "neon")]
+        prop_assert_eq!(&run(&mut RocksBackend::new(), &ops), &expected);
+    }
+}
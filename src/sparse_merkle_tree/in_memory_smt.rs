@@ -1,21 +1,42 @@
 // in_memory_smt provides in memory SMT computation without a physical storage.
-use std::sync::Arc;
-use std::thread;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fs;
+use std::mem;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 
+use crate::abort::AbortToken;
+use crate::batch::SendableWriteBatch;
+use crate::codec;
 use crate::consts;
-use crate::database::traits::{DatabaseKind, JsNewWithArcMutex, NewDBWithKeyLength};
-use crate::database::types::{JsArcMutex, Kind as DBKind};
-use crate::sparse_merkle_tree::smt::{QueryProofWithProof, SMTError};
+use crate::database::traits::{DatabaseKind, JsNewWithArcRwLock, NewDBWithKeyLength};
+use crate::database::types::{JsArcMutex, JsArcRwLock, Kind as DBKind};
+use crate::database::utils;
+use crate::diff;
+use crate::memory;
+use crate::metrics;
+use crate::sparse_merkle_tree::chunk;
+use crate::sparse_merkle_tree::smt::{
+    BulkLoader, CommitHook, EmptyValue, QueryProofWithProof, SMTError,
+};
 use crate::sparse_merkle_tree::smt_db;
+use crate::sparse_merkle_tree::witness::Witness;
 use crate::sparse_merkle_tree::{Proof, QueryProof, SparseMerkleTree, UpdateData};
-use crate::types::{ArcMutex, Cache, KVPair, KeyLength, NestedVec};
+use crate::task_pool;
+use crate::types::{
+    ArcRwLock, Cache, KVPair, KeyLength, NestedVec, RecoverLock, RecoverRwLock, SharedKVPair,
+};
 
-type SharedInMemorySMT = JsArcMutex<InMemorySMT>;
-type DatabaseParameters = (ArcMutex<InMemorySMT>, Vec<u8>, Root<JsFunction>);
+type SharedInMemorySMT = JsArcRwLock<InMemorySMT>;
+type SharedChunkImporter = JsArcMutex<chunk::ChunkImporter>;
+type SharedBulkLoader = JsArcMutex<BulkLoader>;
+type DatabaseParameters = (ArcRwLock<InMemorySMT>, Vec<u8>, Root<JsFunction>);
 type VerifyParameters = (Vec<u8>, NestedVec, Proof, KeyLength, Root<JsFunction>);
+type ProgressParameters = (Option<Arc<Root<JsFunction>>>, Arc<AtomicBool>);
 
 struct JsFunctionContext<'a> {
     context: FunctionContext<'a>,
@@ -24,6 +45,7 @@ struct JsFunctionContext<'a> {
 pub struct InMemorySMT {
     db: smt_db::InMemorySmtDB,
     key_length: KeyLength,
+    metrics: metrics::Instance,
 }
 
 impl NewDBWithKeyLength for InMemorySMT {
@@ -31,6 +53,7 @@ impl NewDBWithKeyLength for InMemorySMT {
         Self {
             db: smt_db::InMemorySmtDB::default(),
             key_length: len.expect("The key_length should have a value"),
+            metrics: metrics::Instance::next(),
         }
     }
 }
@@ -41,9 +64,46 @@ impl DatabaseKind for InMemorySMT {
     }
 }
 
-impl JsNewWithArcMutex for InMemorySMT {}
+impl JsNewWithArcRwLock for InMemorySMT {}
 impl Finalize for InMemorySMT {}
 
+/// encode_snapshot encodes the key length, the root and all the nodes sorted by key.
+fn encode_snapshot(key_length: KeyLength, root: &[u8], db: &smt_db::InMemorySmtDB) -> Vec<u8> {
+    let mut keys = db.cache().keys().collect::<Vec<&Vec<u8>>>();
+    keys.sort();
+    let nodes = keys
+        .into_iter()
+        .map(|key| {
+            let mut writer = codec::Writer::new();
+            writer.write_bytes(1, key);
+            writer.write_bytes(2, &db.cache()[key]);
+            writer.result().to_vec()
+        })
+        .collect::<NestedVec>();
+
+    let mut writer = codec::Writer::new();
+    writer.write_u32(1, u16::from(key_length).into());
+    writer.write_bytes(2, root);
+    writer.write_bytes_slice(3, &nodes);
+    writer.result().to_vec()
+}
+
+/// decode_snapshot decodes the bytes created by encode_snapshot into the key length, the root and the nodes.
+fn decode_snapshot(data: &[u8]) -> Result<(KeyLength, Vec<u8>, Cache), codec::CodecError> {
+    let mut reader = codec::Reader::new(data);
+    let key_length = reader.read_u32(1)?;
+    let key_length = u16::try_from(key_length).map_err(|_| codec::CodecError::OutOfRange)?;
+    let root = reader.read_bytes(2)?;
+    let mut cache = Cache::new();
+    for node in reader.read_bytes_slice(3)?.iter() {
+        let mut reader = codec::Reader::new(node);
+        let key = reader.read_bytes(1)?;
+        let value = reader.read_bytes(2)?;
+        cache.insert(key, value);
+    }
+    Ok((KeyLength(key_length), root, cache))
+}
+
 impl JsFunctionContext<'_> {
     fn get_database_parameters(&mut self) -> NeonResult<DatabaseParameters> {
         let in_memory_smt = self
@@ -65,13 +125,68 @@ impl JsFunctionContext<'_> {
         Ok((in_memory_smt, state_root, callback))
     }
 
-    fn get_key_value_pairs(&mut self) -> NeonResult<Cache> {
+    /// get_encoded_pairs decodes the pairs at the position when they are given as the bytes encoded by
+    /// diff::encode_pairs, which crosses the FFI once instead of once per pair.
+    /// It returns None when the pairs are given as an array.
+    fn get_encoded_pairs(&mut self, pos: i32) -> NeonResult<Option<Vec<KVPair>>> {
+        let input = self.context.argument::<JsValue>(pos)?;
+        let encoded = match input.downcast::<JsTypedArray<u8>, _>(&mut self.context) {
+            Ok(encoded) => encoded,
+            Err(_) => return Ok(None),
+        };
+        match diff::decode_pairs(encoded.as_slice(&self.context)) {
+            Ok(pairs) => Ok(Some(pairs)),
+            Err(err) => utils::throw_with_code(&mut self.context, err),
+        }
+    }
+
+    /// get_update_data reads the pairs at @params(1) with the pairs of an empty value handled by empty_value.
+    /// A pair whose value is null or undefined deletes its key, which is how a key is deleted with StoreEmptyHash.
+    fn get_update_data(&mut self, empty_value: EmptyValue) -> NeonResult<UpdateData> {
+        if let Some(pairs) = self.get_encoded_pairs(1)? {
+            return Ok(UpdateData::new_with_empty_value(
+                pairs.into_iter().map(KVPair::into_parts),
+                empty_value,
+            ));
+        }
         let input = self
             .context
             .argument::<JsArray>(1)?
             .to_vec(&mut self.context)?;
 
-        let mut data = Cache::new();
+        let mut data = UpdateData::new_with_empty_value(Cache::new(), empty_value);
+        for key in input.iter() {
+            let obj = key.downcast_or_throw::<JsObject, _>(&mut self.context)?;
+            let key = obj
+                .get::<JsTypedArray<u8>, _, _>(&mut self.context, "key")?
+                .as_slice(&self.context)
+                .to_vec();
+            let value = obj.get::<JsValue, _, _>(&mut self.context, "value")?;
+            if value.is_a::<JsNull, _>(&mut self.context)
+                || value.is_a::<JsUndefined, _>(&mut self.context)
+            {
+                data.delete(&key);
+                continue;
+            }
+            let value = value
+                .downcast_or_throw::<JsTypedArray<u8>, _>(&mut self.context)?
+                .as_slice(&self.context)
+                .to_vec();
+            data.insert(SharedKVPair(&key, &value));
+        }
+        Ok(data)
+    }
+
+    fn get_sorted_key_value_pairs(&mut self, pos: u8) -> NeonResult<Vec<KVPair>> {
+        if let Some(pairs) = self.get_encoded_pairs(pos.into())? {
+            return Ok(pairs);
+        }
+        let input = self
+            .context
+            .argument::<JsArray>(pos.into())?
+            .to_vec(&mut self.context)?;
+
+        let mut data = Vec::with_capacity(input.len());
         for key in input.iter() {
             let obj = key.downcast_or_throw::<JsObject, _>(&mut self.context)?;
             let key = obj
@@ -82,39 +197,174 @@ impl JsFunctionContext<'_> {
                 .get::<JsTypedArray<u8>, _, _>(&mut self.context, "value")?
                 .as_slice(&self.context)
                 .to_vec();
-            data.insert(key, value);
+            data.push(KVPair(key, value));
         }
         Ok(data)
     }
 
-    fn update_database(&mut self, data: Cache) -> NeonResult<()> {
+    fn get_batch_update_data(&mut self) -> NeonResult<UpdateData> {
+        let batch = self.context.argument::<SendableWriteBatch>(1)?;
+        let batch = batch.borrow();
+        let inner_batch = batch.lock_or_recover();
+
+        UpdateData::new_from_batch(&inner_batch.batch)
+            .or_else(|err| self.context.throw_error(err.to_string()))
+    }
+
+    /// get_empty_value reads the optional name of EmptyValue at pos, which is DeleteOnEmpty by default.
+    fn get_empty_value(&mut self, pos: i32) -> NeonResult<EmptyValue> {
+        let name = match self.context.argument_opt(pos) {
+            Some(arg) if arg.is_a::<JsString, _>(&mut self.context) => arg
+                .downcast_or_throw::<JsString, _>(&mut self.context)?
+                .value(&mut self.context),
+            _ => return Ok(EmptyValue::default()),
+        };
+        EmptyValue::from_name(&name).or_else(|err| utils::throw_with_code(&mut self.context, err))
+    }
+
+    fn get_progress_parameters(&mut self) -> NeonResult<ProgressParameters> {
+        let on_progress = match self.context.argument_opt(3) {
+            Some(arg) if arg.is_a::<JsFunction, _>(&mut self.context) => {
+                let on_progress = arg
+                    .downcast_or_throw::<JsFunction, _>(&mut self.context)?
+                    .root(&mut self.context);
+                Some(Arc::new(on_progress))
+            },
+            _ => None,
+        };
+        let token = AbortToken::argument_opt(&mut self.context, 4).unwrap_or_default();
+
+        Ok((on_progress, token.flag()))
+    }
+
+    fn update_database(
+        &mut self,
+        update_data: UpdateData,
+        progress: Option<ProgressParameters>,
+    ) -> NeonResult<()> {
+        memory::reserve(update_data.size())
+            .or_else(|err| self.context.throw_error(err.to_string()))?;
         let (in_memory_smt, state_root, callback) = self.get_database_parameters()?;
         let channel = self.context.channel();
 
-        thread::spawn(move || {
-            let update_data = UpdateData::new_from(data);
-            let mut inner_smt = in_memory_smt.lock().unwrap();
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let mut inner_smt = in_memory_smt.write_or_recover();
 
             let mut tree =
-                SparseMerkleTree::new(&state_root, inner_smt.key_length, consts::SUBTREE_HEIGHT);
+                SparseMerkleTree::new(&state_root, inner_smt.key_length, consts::SUBTREE_HEIGHT)
+                    .with_metrics(inner_smt.metrics);
 
-            let result = tree.commit(&mut inner_smt.db, &update_data);
+            // nodes are staged and applied only when the commit succeeds, so the tree is untouched
+            // when the commit is aborted, fails or panics
+            let mut staged = smt_db::StagedSmtDB::new(&mut inner_smt.db);
+            let (result, on_progress) = match progress {
+                None => (tree.commit(&mut staged, &update_data), None),
+                Some((on_progress, cancel)) => {
+                    let mut report = |percent: u8| {
+                        if let Some(on_progress) = &on_progress {
+                            let on_progress = Arc::clone(on_progress);
+                            channel.send(move |mut ctx| {
+                                let on_progress = on_progress.to_inner(&mut ctx);
+                                let this = ctx.undefined();
+                                let args = vec![ctx.number(percent).upcast::<JsValue>()];
+                                on_progress.call(&mut ctx, this, args)?;
+                                Ok(())
+                            });
+                        }
+                    };
+                    let mut hook = CommitHook::new(&cancel, &mut report);
+                    let result = tree.commit_with_hook(&mut staged, &update_data, &mut hook);
+                    (result, on_progress)
+                },
+            };
+            let result = result.and_then(|root| {
+                staged
+                    .apply()
+                    .map_err(|err| SMTError::Unknown(err.to_string()))?;
+                Ok(root)
+            });
 
             channel.send(move |mut ctx| {
+                // progress reports are sent before the result, therefore no other reference remains
+                if let Some(Ok(on_progress)) = on_progress.map(Arc::try_unwrap) {
+                    on_progress.drop(&mut ctx);
+                }
                 let callback = callback.into_inner(&mut ctx);
                 let this = ctx.undefined();
                 let args: Vec<Handle<JsValue>> = match result {
                     Ok(val) => {
-                        let buffer = JsBuffer::external(&mut ctx, (**val.lock().unwrap()).clone());
+                        let root = (**val.lock_or_recover()).clone();
+                        let buffer = JsBuffer::external(&mut ctx, root);
                         vec![ctx.null().upcast(), buffer.upcast()]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| utils::throw_with_code(&mut self.context, err))?;
+
+        Ok(())
+    }
+
+    /// bulk_load builds a new tree on the shared pool from the pairs of the loader, and returns the root to the callback.
+    /// The callback and the optional AbortToken are read from params(1) and params(2).
+    fn bulk_load(
+        &mut self,
+        loader: impl FnOnce(KeyLength) -> Result<BulkLoader, SMTError> + Send + 'static,
+    ) -> NeonResult<()> {
+        let in_memory_smt = self
+            .context
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut self.context)?;
+        let in_memory_smt = Arc::clone(&in_memory_smt.borrow());
+        let callback = self
+            .context
+            .argument::<JsFunction>(1)?
+            .root(&mut self.context);
+        let token = AbortToken::argument_opt(&mut self.context, 2);
+        let channel = self.context.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let mut inner_smt = in_memory_smt.write_or_recover();
+            let mut tree =
+                SparseMerkleTree::new(&[], inner_smt.key_length, consts::SUBTREE_HEIGHT)
+                    .with_metrics(inner_smt.metrics);
+            if let Some(token) = token {
+                tree = tree.with_cancel(token.flag());
+            }
+
+            // nodes are staged, so the tree is untouched when the load is aborted
+            let result = loader(inner_smt.key_length).and_then(|loader| {
+                let mut staged = smt_db::StagedSmtDB::new(&mut inner_smt.db);
+                let root = tree.finish_bulk_load(&mut staged, loader)?;
+                staged
+                    .apply()
+                    .map_err(|err| SMTError::Unknown(err.to_string()))?;
+                Ok(root)
+            });
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(val) => {
+                        let root = (**val.lock_or_recover()).clone();
+                        let buffer = JsBuffer::external(&mut ctx, root);
+                        vec![ctx.null().upcast(), buffer.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut self.context, err))?;
 
         Ok(())
     }
@@ -138,14 +388,20 @@ impl JsFunctionContext<'_> {
 
     fn prove(&mut self, data: NestedVec) -> NeonResult<()> {
         let (in_memory_smt, state_root, callback) = self.get_database_parameters()?;
+        let token = AbortToken::argument_opt(&mut self.context, 3);
         let channel = self.context.channel();
 
-        thread::spawn(move || {
-            let mut inner_smt = in_memory_smt.lock().unwrap();
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let inner_smt = in_memory_smt.read_or_recover();
             let mut tree =
-                SparseMerkleTree::new(&state_root, inner_smt.key_length, consts::SUBTREE_HEIGHT);
+                SparseMerkleTree::new(&state_root, inner_smt.key_length, consts::SUBTREE_HEIGHT)
+                    .with_metrics(inner_smt.metrics);
+            if let Some(token) = token {
+                tree = tree.with_cancel(token.flag());
+            }
 
-            let result = tree.prove(&mut inner_smt.db, &data);
+            let result = tree.prove_parallel(&inner_smt.db, &data);
 
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
@@ -174,13 +430,14 @@ impl JsFunctionContext<'_> {
                         }
                         vec![ctx.null().upcast(), obj.upcast()]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| utils::throw_with_code(&mut self.context, err))?;
 
         Ok(())
     }
@@ -219,6 +476,7 @@ impl JsFunctionContext<'_> {
             queries.push(QueryProof {
                 pair: Arc::new(KVPair::new(&key, &value)),
                 bitmap: Arc::new(bitmap),
+                stored_value: None,
             });
         }
         let proof = Proof {
@@ -268,17 +526,147 @@ impl JsFunctionContext<'_> {
 impl InMemorySMT {
     /// js_update is handler for JS ffi.
     /// it is the similar to StateDB commit, but it uses in memory database.
+    /// - @params(0) - state root to update from.
+    /// - @params(1) - array of key-value pairs, where a null value deletes the key.
+    /// - @params(2) - callback to return the result.
+    /// - @params(3) - optional EmptyValue: "deleteOnEmpty" or "storeEmptyHash".
+    /// - @callback(0) - Error.
+    /// - @callback(1) - Root of the updated tree.
     pub fn js_update(ctx: FunctionContext) -> JsResult<JsUndefined> {
         let mut js_context = JsFunctionContext { context: ctx };
 
-        let data = js_context.get_key_value_pairs()?;
-        js_context.update_database(data)?;
+        let empty_value = js_context.get_empty_value(3)?;
+        let data = js_context.get_update_data(empty_value)?;
+        js_context.update_database(data, None)?;
+
+        Ok(js_context.context.undefined())
+    }
+
+    /// js_update_with_progress is handler for JS ffi.
+    /// it is the same as js_update, but reports the progress and can be aborted with the token.
+    /// The tree is not changed when the update is aborted.
+    /// - @params(0) - state root to update from.
+    /// - @params(1) - array of key-value pairs, where a null value deletes the key.
+    /// - @params(2) - callback to return the result.
+    /// - @params(3) - function called with the percentage of the root bins processed, or null.
+    /// - @params(4) - optional AbortToken.
+    /// - @params(5) - optional EmptyValue: "deleteOnEmpty" or "storeEmptyHash".
+    /// - @callback(0) - Error.
+    /// - @callback(1) - Root of the updated tree.
+    pub fn js_update_with_progress(ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let mut js_context = JsFunctionContext { context: ctx };
+
+        let empty_value = js_context.get_empty_value(5)?;
+        let data = js_context.get_update_data(empty_value)?;
+        let progress = js_context.get_progress_parameters()?;
+        js_context.update_database(data, Some(progress))?;
+
+        Ok(js_context.context.undefined())
+    }
+
+    /// js_update_with_batch is handler for JS ffi.
+    /// keys and values in the batch are hashed in the same way as StateDB before updating the tree.
+    /// - @params(0) - state root to update from.
+    /// - @params(1) - Batch.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - Root of the updated tree.
+    pub fn js_update_with_batch(ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let mut js_context = JsFunctionContext { context: ctx };
+
+        let data = js_context.get_batch_update_data()?;
+        js_context.update_database(data, None)?;
+
+        Ok(js_context.context.undefined())
+    }
+
+    /// js_bulk_load is handler for JS ffi.
+    /// it builds a new tree from the key-value pairs sorted by key, and returns the root.
+    /// The tree is not changed when the load is aborted.
+    /// - @params(0) - array of key-value pairs sorted by key.
+    /// - @params(1) - callback to return the result.
+    /// - @params(2) - optional AbortToken to stop before the next SubTree is built.
+    /// - @callback(0) - Error. It has the code ERR_ABORTED when it is aborted.
+    /// - @callback(1) - Root of the new tree.
+    pub fn js_bulk_load(ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let mut js_context = JsFunctionContext { context: ctx };
+        let data = js_context.get_sorted_key_value_pairs(0)?;
+        let size = data
+            .iter()
+            .map(|kv| kv.key().len() + kv.value().len())
+            .sum();
+        memory::reserve(size).or_else(|err| js_context.context.throw_error(err.to_string()))?;
+        js_context.bulk_load(move |key_length| {
+            let mut loader = BulkLoader::new(key_length);
+            loader.add(data)?;
+            Ok(loader)
+        })?;
+
+        Ok(js_context.context.undefined())
+    }
+
+    /// js_new_bulk_loader is handler for JS ffi.
+    /// it creates the loader which collects the sorted key-value pairs given in batches for js_finish_bulk_load.
+    pub fn js_new_bulk_loader(mut ctx: FunctionContext) -> JsResult<SharedBulkLoader> {
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let key_length = in_memory_smt.borrow().read_or_recover().key_length;
+        let loader = BulkLoader::new(key_length);
+
+        Ok(ctx.boxed(RefCell::new(Arc::new(Mutex::new(loader)))))
+    }
+
+    /// js_add_to_bulk_loader is handler for JS ffi.
+    /// it adds the batch of the pairs, which must follow the pairs added before.
+    /// - @params(0) - loader created by js_new_bulk_loader.
+    /// - @params(1) - array of key-value pairs sorted by key.
+    pub fn js_add_to_bulk_loader(ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let mut js_context = JsFunctionContext { context: ctx };
+        let loader = js_context.context.argument::<SharedBulkLoader>(0)?;
+        let loader = Arc::clone(&loader.borrow());
+        let data = js_context.get_sorted_key_value_pairs(1)?;
+        let size = data
+            .iter()
+            .map(|kv| kv.key().len() + kv.value().len())
+            .sum();
+        memory::reserve(size).or_else(|err| js_context.context.throw_error(err.to_string()))?;
+        let result = loader.lock_or_recover().add(data);
+        result.or_else(|err| utils::throw_with_code(&mut js_context.context, err))?;
+
+        Ok(js_context.context.undefined())
+    }
+
+    /// js_finish_bulk_load is handler for JS ffi.
+    /// it builds a new tree from the pairs added to the loader in the same way as js_bulk_load.
+    /// - @params(0) - loader created by js_new_bulk_loader.
+    /// - @params(1) - callback to return the result.
+    /// - @params(2) - optional AbortToken to stop before the next SubTree is built.
+    /// - @callback(0) - Error. It has the code ERR_ABORTED when it is aborted.
+    /// - @callback(1) - Root of the new tree.
+    pub fn js_finish_bulk_load(ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let mut js_context = JsFunctionContext { context: ctx };
+        let loader = js_context.context.argument::<SharedBulkLoader>(0)?;
+        let loader = Arc::clone(&loader.borrow());
+        // the pairs are moved out, so the loader does not keep them once the tree is built
+        js_context.bulk_load(move |key_length| {
+            Ok(mem::replace(
+                &mut *loader.lock_or_recover(),
+                BulkLoader::new(key_length),
+            ))
+        })?;
 
         Ok(js_context.context.undefined())
     }
 
     /// js_prove is handler for JS ffi.
     /// it is the similar to StateDB prove, but it uses in memory database.
+    /// - @params(0) - state root to prove.
+    /// - @params(1) - array of the query keys.
+    /// - @params(2) - callback to return the result.
+    /// - @params(3) - optional AbortToken to stop before the next query is proved.
+    /// - @callback(0) - Error. It has the code ERR_ABORTED when it is aborted.
+    /// - @callback(1) - Proof.
     pub fn js_prove(ctx: FunctionContext) -> JsResult<JsUndefined> {
         let mut js_context = JsFunctionContext { context: ctx };
 
@@ -297,7 +685,8 @@ impl InMemorySMT {
             js_context.get_verify_parameters()?;
         let channel = js_context.context.channel();
 
-        thread::spawn(move || {
+        let pool = task_pool::shared();
+        pool.execute(move || {
             let result =
                 SparseMerkleTree::verify(&parsed_query_keys, &proof, &state_root, key_length);
 
@@ -308,13 +697,14 @@ impl InMemorySMT {
                     Ok(val) => {
                         vec![ctx.null().upcast(), JsBoolean::new(&mut ctx, val).upcast()]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| utils::throw_with_code(&mut js_context.context, err))?;
 
         Ok(js_context.context.undefined())
     }
@@ -331,7 +721,8 @@ impl InMemorySMT {
             .root(&mut js_context.context);
         let channel = js_context.context.channel();
 
-        thread::spawn(move || {
+        let pool = task_pool::shared();
+        pool.execute(move || {
             let result: Result<Vec<u8>, SMTError> =
                 match SparseMerkleTree::prepare_queries_with_proof_map(&proof) {
                     Ok(filter_map) => {
@@ -357,14 +748,389 @@ impl InMemorySMT {
                             JsBuffer::external(&mut ctx, val).upcast(),
                         ]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| utils::throw_with_code(&mut js_context.context, err))?;
 
         Ok(js_context.context.undefined())
     }
+
+    /// js_to_visualization is handler for JS ffi.
+    /// it returns all the nodes of the tree in pre-order for debugging root mismatches.
+    /// - @params(0) - root of the tree.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { type: string; height: number; hash: &[u8]; key: &[u8]; value: &[u8]; }[]
+    pub fn js_to_visualization(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let in_memory_smt = Arc::clone(&in_memory_smt.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let inner_smt = in_memory_smt.read_or_recover();
+            let tree = SparseMerkleTree::new(&root, inner_smt.key_length, consts::SUBTREE_HEIGHT);
+            let result = tree.dump(&inner_smt.db);
+            drop(inner_smt);
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(nodes) => {
+                        let arr = utils::dump_to_js_array(&mut ctx, &nodes)?;
+                        vec![ctx.null().upcast(), arr.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_save is handler for JS ffi.
+    /// it writes the root and all the nodes of the tree to the file.
+    /// - @params(0) - path of the file.
+    /// - @params(1) - current root of the tree.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_save(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let path = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let root = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let in_memory_smt = Arc::clone(&in_memory_smt.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let inner_smt = in_memory_smt.read_or_recover();
+            let encoded = encode_snapshot(inner_smt.key_length, &root, &inner_smt.db);
+            drop(inner_smt);
+            let result = fs::write(path, encoded);
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(()) => vec![ctx.null().upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_load is handler for JS ffi.
+    /// it replaces all the nodes of the tree with the nodes in the file created by js_save.
+    /// - @params(0) - path of the file.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - [u8]. Root of the tree stored in the file.
+    pub fn js_load(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let path = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let in_memory_smt = Arc::clone(&in_memory_smt.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result = fs::read(path)
+                .map_err(|err| SMTError::Unknown(err.to_string()))
+                .and_then(|data| {
+                    decode_snapshot(&data).map_err(|err| SMTError::InvalidInput(err.to_string()))
+                })
+                .and_then(|(key_length, root, cache)| {
+                    let mut inner_smt = in_memory_smt.write_or_recover();
+                    if u16::from(key_length) != u16::from(inner_smt.key_length) {
+                        return Err(SMTError::InvalidInput(String::from(
+                            "Key length of the snapshot does not match",
+                        )));
+                    }
+                    let size = cache.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+                    memory::reserve(size.saturating_sub(inner_smt.db.size()))
+                        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+                    inner_smt.db.replace(cache);
+                    Ok(root)
+                });
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(root) => {
+                        vec![
+                            ctx.null().upcast(),
+                            JsBuffer::external(&mut ctx, root).upcast(),
+                        ]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_metrics is handler for JS ffi.
+    /// - @returns - stats recorded by the updates and the proofs of the tree, in the same form as getStats.
+    pub fn js_get_metrics(mut ctx: FunctionContext) -> JsResult<JsObject> {
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let instance = in_memory_smt.borrow().read_or_recover().metrics;
+
+        metrics::instance_stats_to_js_object(&mut ctx, instance)
+    }
+
+    /// js_verify_chunk is handler for JS ffi.
+    /// it checks that all the pairs of the chunk created by StateDB exportChunks are included in the root.
+    /// - @params(0) - root of the tree.
+    /// - @params(1) - encoded chunk.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - bool represents true if the chunk is valid.
+    pub fn js_verify_chunk(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let data = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let key_length = in_memory_smt.borrow().read_or_recover().key_length;
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result = chunk::Chunk::decode(&data)
+                .map_err(|err| SMTError::InvalidInput(err.to_string()))
+                .and_then(|chunk| chunk.verify(&root, key_length));
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(val) => {
+                        vec![ctx.null().upcast(), JsBoolean::new(&mut ctx, val).upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_verify_witness is handler for JS ffi.
+    /// it checks that the witness created by StateDB generateWitness proves its entries against the root.
+    /// - @params(0) - root of the tree before the block.
+    /// - @params(1) - encoded witness.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error. If the witness is not valid against the root, it is the error with INVALID_ROOT code.
+    /// - @callback(1) - { key: &[u8]; value?: &[u8]; }[]. Entries of the witness, where the value is not set for the missing key.
+    pub fn js_verify_witness(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let data = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let key_length = in_memory_smt.borrow().read_or_recover().key_length;
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result = Witness::decode(&data)
+                .map_err(|err| SMTError::InvalidInput(err.to_string()))
+                .and_then(|witness| {
+                    if witness.root != root || !witness.verify(key_length)? {
+                        return Err(SMTError::InvalidRoot(String::from(
+                            "witness is not valid against the root",
+                        )));
+                    }
+                    Ok(witness.entries)
+                });
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(entries) => {
+                        let array = utils::witness_entries_to_js_array(&mut ctx, entries)?;
+                        vec![ctx.null().upcast(), array.upcast()]
+                    },
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_new_chunk_importer is handler for JS ffi.
+    /// it creates the importer which builds the tree of the root from the chunks given one by one.
+    /// - @params(0) - root of the exported tree.
+    pub fn js_new_chunk_importer(mut ctx: FunctionContext) -> JsResult<SharedChunkImporter> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let key_length = in_memory_smt.borrow().read_or_recover().key_length;
+        let importer = chunk::ChunkImporter::new(&root, key_length);
+
+        Ok(ctx.boxed(RefCell::new(Arc::new(Mutex::new(importer)))))
+    }
+
+    /// js_import_chunk is handler for JS ffi.
+    /// it verifies the chunk of the next index against the root and writes its pairs to the tree.
+    /// - @params(0) - importer created by js_new_chunk_importer.
+    /// - @params(1) - encoded chunk.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_import_chunk(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let importer = ctx.argument::<SharedChunkImporter>(0)?;
+        let importer = Arc::clone(&importer.borrow());
+        let data = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        memory::reserve(data.len()).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let in_memory_smt = ctx
+            .this()
+            .downcast_or_throw::<SharedInMemorySMT, _>(&mut ctx)?;
+        let in_memory_smt = Arc::clone(&in_memory_smt.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result = chunk::Chunk::decode(&data)
+                .map_err(|err| SMTError::InvalidInput(err.to_string()))
+                .and_then(|chunk| {
+                    let mut inner_smt = in_memory_smt.write_or_recover();
+                    importer.lock_or_recover().import(&mut inner_smt.db, chunk)
+                });
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(_) => vec![ctx.null().upcast()],
+                    Err(err) => vec![utils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_finish_chunk_import is handler for JS ffi.
+    /// it checks that the chunks imported so far form the root.
+    /// - @params(0) - importer created by js_new_chunk_importer.
+    /// - @returns - [u8]. Root of the imported tree, which is equal to the root.
+    pub fn js_finish_chunk_import(mut ctx: FunctionContext) -> JsResult<JsBuffer> {
+        let importer = ctx.argument::<SharedChunkImporter>(0)?;
+        let result = importer.borrow().lock_or_recover().finish();
+        let root = result.or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+        let root = (**root.lock_or_recover()).clone();
+
+        Ok(JsBuffer::external(&mut ctx, root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+    use std::thread;
+
+    use super::*;
+    use crate::database::traits::Actions;
+
+    #[test]
+    fn test_snapshot_encoding() {
+        let mut db = smt_db::InMemorySmtDB::default();
+        db.set(&KVPair::new(&[2, 2], &[22, 22])).unwrap();
+        db.set(&KVPair::new(&[1, 1], &[11, 11])).unwrap();
+
+        let encoded = encode_snapshot(KeyLength(32), &[9; 32], &db);
+        assert_eq!(encoded, encode_snapshot(KeyLength(32), &[9; 32], &db));
+
+        let (key_length, root, cache) = decode_snapshot(&encoded).unwrap();
+        assert_eq!(u16::from(key_length), 32);
+        assert_eq!(root, vec![9; 32]);
+        assert_eq!(&cache, db.cache());
+
+        assert!(decode_snapshot(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_prove() {
+        let smt = Arc::new(RwLock::new(InMemorySMT::new_db_with_key_length(Some(
+            KeyLength(32),
+        ))));
+        let mut data = Cache::new();
+        data.insert(vec![1; 32], vec![2; 32]);
+        let root = {
+            let mut inner_smt = smt.write().unwrap();
+            let mut tree = SparseMerkleTree::new(&[], KeyLength(32), consts::SUBTREE_HEIGHT);
+            let root = tree
+                .commit(&mut inner_smt.db, &UpdateData::new_from(data))
+                .unwrap();
+            let root = root.lock().unwrap().to_vec();
+            root
+        };
+
+        // proving on another thread must not wait for the read lock held here
+        let held = smt.read().unwrap();
+        let shared = Arc::clone(&smt);
+        let query_root = root.clone();
+        let proof = thread::spawn(move || {
+            let inner_smt = shared.read().unwrap();
+            let mut tree =
+                SparseMerkleTree::new(&query_root, KeyLength(32), consts::SUBTREE_HEIGHT);
+            tree.prove(&inner_smt.db, &[vec![1; 32]]).unwrap()
+        })
+        .join()
+        .unwrap();
+        drop(held);
+
+        assert!(SparseMerkleTree::verify(&[vec![1; 32]], &proof, &root, KeyLength(32)).unwrap());
+    }
 }
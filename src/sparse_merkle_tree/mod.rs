@@ -1,5 +1,20 @@
+pub mod bloom;
+pub mod chunk;
+#[cfg(feature = "neon")]
 pub mod in_memory_smt;
+#[cfg(feature = "neon")]
+pub mod multi_tree_db;
+pub mod overlay;
+#[cfg(feature = "sled")]
+pub mod sled_db;
 pub mod smt;
 pub mod smt_db;
+pub mod witness;
 
-pub use smt::{Proof, QueryProof, QueryProofWithProof, SparseMerkleTree, UpdateData};
+#[cfg(test)]
+mod differential_tests;
+
+pub use smt::{
+    DumpNode, DumpNodeKind, EmptyValue, PrefixNode, PrefixProof, Proof, QueryProof,
+    QueryProofWithProof, SparseMerkleTree, UpdateData,
+};
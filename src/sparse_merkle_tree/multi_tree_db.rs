@@ -0,0 +1,407 @@
+/// multi_tree_db maintains multiple independent sparse merkle trees in a single database.
+use std::cell::RefCell;
+use std::sync::mpsc;
+
+use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+
+use crate::batch;
+use crate::consts;
+use crate::database::lock::OpenError;
+use crate::database::traits::{JsNewWithBoxRef, NewDBWithContext, OptionsWithContext};
+use crate::database::types::{DbMessage, DbOptions, JsBoxRef, Kind};
+use crate::database::utils as DbUtils;
+use crate::database::DB;
+use crate::sparse_merkle_tree::smt::{self, SMTError, EMPTY_HASH};
+use crate::sparse_merkle_tree::smt_db;
+use crate::types::{Cache, NestedVec, RecoverLock};
+
+pub type SharedMultiTreeDB = JsBoxRef<MultiTreeDB>;
+
+type StoreUpdate = (String, Cache);
+type StoreRoot = (String, Vec<u8>);
+
+/// NODE_PREFIX is the prefix of the nodes, followed by the length of the store name and the name.
+const NODE_PREFIX: u8 = 0;
+/// ROOT_PREFIX is the prefix of the current root, followed by the store name.
+const ROOT_PREFIX: u8 = 1;
+const MAX_STORE_NAME_LENGTH: usize = u8::MAX as usize;
+
+/// MultiTreeDB maintains sparse merkle trees identified by the store name.
+/// Each store has its own root, and the nodes are separated by the prefix derived from the name.
+pub struct MultiTreeDB {
+    common: DB,
+    options: DbOptions,
+}
+
+fn validate_store_name(name: &str) -> Result<(), SMTError> {
+    if name.is_empty() || name.len() > MAX_STORE_NAME_LENGTH {
+        return Err(SMTError::InvalidInput(format!(
+            "store name must be 1 to {} bytes",
+            MAX_STORE_NAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+fn node_prefix(name: &str) -> Vec<u8> {
+    [&[NODE_PREFIX, name.len() as u8], name.as_bytes()].concat()
+}
+
+fn root_key(name: &str) -> Vec<u8> {
+    [&[ROOT_PREFIX], name.as_bytes()].concat()
+}
+
+impl NewDBWithContext for MultiTreeDB {
+    fn new_db_with_context<'a, C>(
+        ctx: &mut C,
+        path: String,
+        db_options: DbOptions,
+        kind: Kind,
+    ) -> Result<Self, OpenError>
+    where
+        C: Context<'a>,
+    {
+        Ok(Self {
+            common: DB::new_db_with_context(ctx, path, db_options, kind)?,
+            options: db_options,
+        })
+    }
+}
+
+impl JsNewWithBoxRef for MultiTreeDB {
+    fn js_new_with_box_ref<T: OptionsWithContext, U: NewDBWithContext + Send + Finalize>(
+        mut ctx: FunctionContext,
+    ) -> JsResult<JsBoxRef<U>> {
+        let path = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let options = ctx.argument_opt(1);
+        let db_opts = T::new_with_context(&mut ctx, options)?;
+        let db = U::new_db_with_context(&mut ctx, path, db_opts, Kind::Normal)
+            .or_else(|err| DbUtils::throw_open_error(&mut ctx, err))?;
+
+        Ok(ctx.boxed(RefCell::new(db)))
+    }
+}
+
+impl Finalize for MultiTreeDB {}
+impl MultiTreeDB {
+    /// get_root returns the current root of the store, or empty hash if the store was never updated.
+    pub fn get_root(&self, name: &str) -> Result<Vec<u8>, SMTError> {
+        validate_store_name(name)?;
+        let root = self
+            .common
+            .get(&root_key(name))
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+        Ok(root.unwrap_or_else(|| EMPTY_HASH.to_vec()))
+    }
+
+    /// update applies the updates to each store and writes all the stores in a single batch.
+    /// If any of the updates fails, none of the stores are updated.
+    pub fn update(&self, updates: Vec<StoreUpdate>) -> Result<Vec<StoreRoot>, SMTError> {
+        for (i, (name, _)) in updates.iter().enumerate() {
+            validate_store_name(name)?;
+            if updates[..i].iter().any(|(prev, _)| prev == name) {
+                return Err(SMTError::InvalidInput(format!(
+                    "duplicate store name {}",
+                    name
+                )));
+            }
+        }
+        let prefixes = updates
+            .iter()
+            .map(|(name, _)| node_prefix(name))
+            .collect::<NestedVec>();
+        let prefix_slices = prefixes
+            .iter()
+            .map(|p| p.as_slice())
+            .collect::<Vec<&[u8]>>();
+
+        let mut write_batch = batch::PrefixWriteBatch::new();
+        let mut roots = Vec::with_capacity(updates.len());
+        for ((name, data), prefix) in updates.into_iter().zip(prefix_slices.iter()) {
+            let root = self.get_root(&name)?;
+            let mut smt_db = smt_db::SmtDB::new_with_prefix(&self.common, prefix);
+            let mut tree = smt::SparseMerkleTree::new(
+                &root,
+                self.options.key_length(),
+                consts::SUBTREE_HEIGHT,
+            );
+            let next_root = tree.commit(&mut smt_db, &smt::UpdateData::new_from(data))?;
+            let next_root = next_root.lock_or_recover().to_vec();
+
+            write_batch.extend_with_prefixes(&[(*prefix, &smt_db.batch)]);
+            write_batch.batch.put(root_key(&name), &next_root);
+            roots.push((name, next_root));
+        }
+        self.common
+            .write(write_batch.batch)
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+
+        Ok(roots)
+    }
+
+    /// prove returns the proof of the queries against the current root of the store.
+    pub fn prove(&self, name: &str, queries: &[Vec<u8>]) -> Result<smt::Proof, SMTError> {
+        let root = self.get_root(name)?;
+        let prefix = node_prefix(name);
+        let smt_db = smt_db::SmtReader::new_with_prefix(&self.common, &prefix);
+        let mut tree =
+            smt::SparseMerkleTree::new(&root, self.options.key_length(), consts::SUBTREE_HEIGHT);
+        tree.prove_parallel(&smt_db, queries)
+    }
+
+    fn send_result<T: Send + 'static>(
+        &self,
+        result: Result<T, SMTError>,
+        callback: Root<JsFunction>,
+        convert: impl for<'a> FnOnce(&mut TaskContext<'a>, T) -> JsResult<'a, JsValue> + Send + 'static,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        self.common.send(move |channel| {
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(val) => {
+                        let converted = convert(&mut ctx, val)?;
+                        vec![ctx.null().upcast(), converted]
+                    },
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+    }
+
+    fn parse_pairs(ctx: &mut FunctionContext, pairs: Handle<JsArray>) -> NeonResult<Cache> {
+        let mut data = Cache::new();
+        for pair in pairs.to_vec(ctx)?.iter() {
+            let obj = pair.downcast_or_throw::<JsObject, _>(ctx)?;
+            let key = obj
+                .get::<JsTypedArray<u8>, _, _>(ctx, "key")?
+                .as_slice(ctx)
+                .to_vec();
+            let value = obj
+                .get::<JsTypedArray<u8>, _, _>(ctx, "value")?
+                .as_slice(ctx)
+                .to_vec();
+            data.insert(key, value);
+        }
+        Ok(data)
+    }
+}
+
+impl MultiTreeDB {
+    /// js_close is handler for JS ffi.
+    /// js "this" - MultiTreeDB.
+    /// - @params(0) - optional callback called when the database is released.
+    /// - @callback(0) - Error.
+    pub fn js_close(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = DbUtils::function_argument_opt(&mut ctx, 0);
+        ctx.this()
+            .downcast_or_throw::<SharedMultiTreeDB, _>(&mut ctx)?
+            .borrow_mut()
+            .common
+            .close(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_root is handler for JS ffi.
+    /// js "this" - MultiTreeDB.
+    /// - @params(0) - store name.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - [u8]. Current root of the store.
+    pub fn js_get_root(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let name = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedMultiTreeDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let result = db.get_root(&name);
+        db.send_result(result, callback, |ctx, root| {
+            Ok(JsBuffer::external(ctx, root).upcast())
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_update is handler for JS ffi.
+    /// js "this" - MultiTreeDB.
+    /// - @params(0) - updates in format of { name: string, pairs: { key: &[u8], value: &[u8] }[] }[]
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { name: string, root: &[u8] }[]. New root of each updated store.
+    pub fn js_update(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let input = ctx.argument::<JsArray>(0)?.to_vec(&mut ctx)?;
+        let mut updates = Vec::with_capacity(input.len());
+        for item in input.iter() {
+            let obj = item.downcast_or_throw::<JsObject, _>(&mut ctx)?;
+            let name = obj.get::<JsString, _, _>(&mut ctx, "name")?.value(&mut ctx);
+            let pairs = obj.get::<JsArray, _, _>(&mut ctx, "pairs")?;
+            updates.push((name, Self::parse_pairs(&mut ctx, pairs)?));
+        }
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedMultiTreeDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let result = db.update(updates);
+        db.send_result(result, callback, |ctx, roots| {
+            let arr = ctx.empty_array();
+            for (i, (name, root)) in roots.into_iter().enumerate() {
+                let obj = ctx.empty_object();
+                let name = ctx.string(name);
+                obj.set(ctx, "name", name)?;
+                let root = JsBuffer::external(ctx, root);
+                obj.set(ctx, "root", root)?;
+                arr.set(ctx, i as u32, obj)?;
+            }
+            Ok(arr.upcast())
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_prove is handler for JS ffi.
+    /// js "this" - MultiTreeDB.
+    /// - @params(0) - store name.
+    /// - @params(1) - queries in format of &[&[u8]]
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; }[]; }
+    pub fn js_prove(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let name = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let input = ctx.argument::<JsArray>(1)?.to_vec(&mut ctx)?;
+        let mut queries = NestedVec::new();
+        for item in input.iter() {
+            let key = item
+                .downcast_or_throw::<JsTypedArray<u8>, _>(&mut ctx)?
+                .as_slice(&ctx)
+                .to_vec();
+            queries.push(key);
+        }
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let db = ctx
+            .this()
+            .downcast_or_throw::<SharedMultiTreeDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let result = db.prove(&name, &queries);
+        db.send_result(result, callback, |ctx, proof| {
+            Ok(DbUtils::proof_to_js_object(ctx, &proof)?.upcast())
+        })
+        .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::task_pool::SerialQueue;
+    use crate::types::KeyLength;
+
+    fn temp_db() -> (MultiTreeDB, TempDir) {
+        let temp_dir = TempDir::new("test_multi_tree_db").unwrap();
+        let rocks_db = rocksdb::DB::open_default(&temp_dir).unwrap();
+        let db = MultiTreeDB {
+            common: DB::new(rocks_db, SerialQueue::closed(), Kind::Normal),
+            options: DbOptions::new(false, KeyLength(32)),
+        };
+        (db, temp_dir)
+    }
+
+    fn update_data(seed: u8) -> Cache {
+        let mut data = Cache::new();
+        for i in 0..10 {
+            data.insert(vec![i; 32], vec![i + seed; 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_update_stores_independently() {
+        let (db, _temp_dir) = temp_db();
+        assert_eq!(db.get_root("state").unwrap(), EMPTY_HASH.to_vec());
+
+        let roots = db
+            .update(vec![
+                ("state".to_string(), update_data(1)),
+                ("events".to_string(), update_data(2)),
+            ])
+            .unwrap();
+        assert_eq!(roots.len(), 2);
+        assert_ne!(roots[0].1, roots[1].1);
+        assert_eq!(db.get_root("state").unwrap(), roots[0].1);
+        assert_eq!(db.get_root("events").unwrap(), roots[1].1);
+
+        let mut expected_tree =
+            smt::SparseMerkleTree::new(&[], KeyLength(32), consts::SUBTREE_HEIGHT);
+        let mut expected_db = smt_db::InMemorySmtDB::default();
+        let expected = expected_tree
+            .commit(&mut expected_db, &smt::UpdateData::new_from(update_data(2)))
+            .unwrap();
+        assert_eq!(roots[1].1, **expected.lock().unwrap());
+
+        let next = db
+            .update(vec![("events".to_string(), update_data(3))])
+            .unwrap();
+        assert_eq!(db.get_root("state").unwrap(), roots[0].1);
+        assert_eq!(db.get_root("events").unwrap(), next[0].1);
+    }
+
+    #[test]
+    fn test_prove_against_store() {
+        let (db, _temp_dir) = temp_db();
+        let roots = db
+            .update(vec![
+                ("state".to_string(), update_data(1)),
+                ("assets".to_string(), update_data(4)),
+            ])
+            .unwrap();
+
+        let queries = vec![vec![1; 32], vec![100; 32]];
+        for (name, root) in roots.iter() {
+            let proof = db.prove(name, &queries).unwrap();
+            assert!(smt::SparseMerkleTree::verify(&queries, &proof, root, KeyLength(32)).unwrap());
+        }
+        let proof = db.prove("state", &queries).unwrap();
+        assert!(
+            !smt::SparseMerkleTree::verify(&queries, &proof, &roots[1].1, KeyLength(32)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_update_is_atomic() {
+        let (db, _temp_dir) = temp_db();
+        let mut invalid = Cache::new();
+        invalid.insert(vec![1; 10], vec![1; 32]);
+
+        let result = db.update(vec![
+            ("state".to_string(), update_data(1)),
+            ("events".to_string(), invalid),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(db.get_root("state").unwrap(), EMPTY_HASH.to_vec());
+        assert!(db.get_root("").is_err());
+
+        let result = db.update(vec![
+            ("state".to_string(), update_data(1)),
+            ("state".to_string(), update_data(2)),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(db.get_root("state").unwrap(), EMPTY_HASH.to_vec());
+    }
+}
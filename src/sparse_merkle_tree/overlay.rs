@@ -0,0 +1,158 @@
+/// overlay applies tentative updates on top of a committed root purely in memory.
+/// The nodes written by the updates are kept by the overlay instead of the database, so the would-be root
+/// of a candidate block can be computed without writing to the database, and the overlay is dropped afterwards.
+use std::collections::HashMap;
+use std::mem;
+
+use crate::consts;
+use crate::database::traits::Actions;
+use crate::sparse_merkle_tree::smt::{SMTError, SparseMerkleTree, UpdateData};
+use crate::sparse_merkle_tree::smt_db::StagedSmtDB;
+use crate::types::{KeyLength, RecoverLock, VecOption};
+
+/// Overlay is the root after the updates applied so far, with the nodes written and deleted by them.
+pub struct Overlay {
+    root: Vec<u8>,
+    key_length: KeyLength,
+    staged: HashMap<Vec<u8>, VecOption>,
+}
+
+#[cfg(feature = "neon")]
+impl neon::types::Finalize for Overlay {}
+
+impl Overlay {
+    /// new creates the overlay on top of the committed root.
+    pub fn new(root: &[u8], key_length: KeyLength) -> Self {
+        Self {
+            root: root.to_vec(),
+            key_length,
+            staged: HashMap::new(),
+        }
+    }
+
+    /// root returns the root after the updates applied so far, which is the committed root before the first update.
+    pub fn root(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// size returns the number of the nodes written or deleted by the updates.
+    pub fn size(&self) -> usize {
+        self.staged.len()
+    }
+
+    /// update applies the data on top of the updates applied so far and returns the would-be root.
+    /// The nodes not written by the overlay are read from `db`, which is never written.
+    /// The overlay is not changed if the update fails, so the next update starts from the last root.
+    pub fn update<T: Actions>(
+        &mut self,
+        db: &mut T,
+        data: &UpdateData,
+    ) -> Result<Vec<u8>, SMTError> {
+        let mut base = StagedSmtDB::new_with_staged(db, mem::take(&mut self.staged));
+        let result = Self::commit(&mut base, &self.root, self.key_length, data);
+        self.staged = base.into_staged();
+        let root = result?;
+        self.root = root.clone();
+
+        Ok(root)
+    }
+
+    /// commit stages the nodes of the update separately, so the nodes of a failed update are discarded.
+    fn commit<T: Actions>(
+        db: &mut T,
+        root: &[u8],
+        key_length: KeyLength,
+        data: &UpdateData,
+    ) -> Result<Vec<u8>, SMTError> {
+        let mut staged = StagedSmtDB::new(db);
+        let mut tree = SparseMerkleTree::new(root, key_length, consts::SUBTREE_HEIGHT);
+        let root = tree.commit(&mut staged, data)?;
+        staged
+            .apply()
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+        let root = (**root.lock_or_recover()).clone();
+
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse_merkle_tree::smt::EMPTY_HASH;
+    use crate::sparse_merkle_tree::smt_db::InMemorySmtDB;
+    use crate::types::{HashKind, HashWithKind};
+
+    fn data(pairs: &[(&[u8], &[u8])]) -> UpdateData {
+        UpdateData::new_from(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_vec().hash_with_kind(HashKind::Key), value.to_vec())),
+        )
+    }
+
+    fn commit(db: &mut InMemorySmtDB, root: &[u8], data: &UpdateData) -> Vec<u8> {
+        let mut tree = SparseMerkleTree::new(root, consts::KEY_LENGTH, consts::SUBTREE_HEIGHT);
+        let root = tree.commit(db, data).unwrap();
+        let root = root.lock_or_recover();
+        (**root).clone()
+    }
+
+    #[test]
+    fn test_overlay_update() {
+        let first = data(&[(b"store_key1", &[1; 32]), (b"store_key2", &[2; 32])]);
+        let second = data(&[(b"store_key2", &[]), (b"store_key3", &[3; 32])]);
+        let mut db = InMemorySmtDB::default();
+        let committed = commit(&mut db, &EMPTY_HASH, &first);
+        let nodes = db.cache().clone();
+
+        let mut overlay = Overlay::new(&committed, consts::KEY_LENGTH);
+        assert_eq!(overlay.root(), committed.as_slice());
+        let next = overlay.update(&mut db, &second).unwrap();
+        let last = overlay
+            .update(&mut db, &data(&[(b"store_key4", &[4; 32])]))
+            .unwrap();
+        assert_eq!(overlay.root(), last.as_slice());
+        assert!(overlay.size() > 0);
+        // the nodes are kept by the overlay, and the db is not written
+        assert_eq!(db.cache(), &nodes);
+
+        let mut expected = InMemorySmtDB::default();
+        let root = commit(&mut expected, &EMPTY_HASH, &first);
+        let root = commit(&mut expected, &root, &second);
+        assert_eq!(next, root);
+        let root = commit(&mut expected, &root, &data(&[(b"store_key4", &[4; 32])]));
+        assert_eq!(last, root);
+    }
+
+    #[test]
+    fn test_overlay_failed_update() {
+        let mut db = InMemorySmtDB::default();
+        let committed = commit(&mut db, &EMPTY_HASH, &data(&[(b"store_key1", &[1; 32])]));
+
+        let mut overlay = Overlay::new(&committed, consts::KEY_LENGTH);
+        let root = overlay
+            .update(&mut db, &data(&[(b"store_key2", &[2; 32])]))
+            .unwrap();
+        let size = overlay.size();
+        let invalid = UpdateData::new_from(vec![(vec![1; 8], vec![3; 32])]);
+        assert!(overlay.update(&mut db, &invalid).is_err());
+        assert_eq!(overlay.root(), root.as_slice());
+        assert_eq!(overlay.size(), size);
+
+        let next = overlay
+            .update(&mut db, &data(&[(b"store_key3", &[3; 32])]))
+            .unwrap();
+        let mut expected = InMemorySmtDB::default();
+        let expected_root = commit(
+            &mut expected,
+            &EMPTY_HASH,
+            &data(&[
+                (b"store_key1", &[1; 32]),
+                (b"store_key2", &[2; 32]),
+                (b"store_key3", &[3; 32]),
+            ]),
+        );
+        assert_eq!(next, expected_root);
+    }
+}
@@ -0,0 +1,101 @@
+/// sled_db stores the nodes of the sparse merkle tree in sled, so the tree can be persisted without RocksDB.
+use std::path::Path;
+
+use crate::database::traits::Actions;
+use crate::types::{KVPair, VecOption};
+
+const TREE_NAME: &str = "smt";
+
+/// SledSmtDB stores the nodes in a tree of sled.
+/// The root of the sparse merkle tree is not stored, so it must be kept by the caller.
+pub struct SledSmtDB {
+    tree: sled::Tree,
+}
+
+impl SledSmtDB {
+    /// open opens the database under the path and stores the nodes in its "smt" tree.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, sled::Error> {
+        let db = sled::open(path)?;
+        Ok(Self::new(db.open_tree(TREE_NAME)?))
+    }
+
+    /// new stores the nodes in the tree, which may be shared with the other data of the caller.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// flush writes the nodes to the disk, and returns after they are durable.
+    pub fn flush(&self) -> Result<(), sled::Error> {
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+impl Actions for SledSmtDB {
+    type Error = sled::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
+        let result = self.tree.get(key)?;
+        Ok(result.map(|value| value.to_vec()))
+    }
+
+    fn set(&mut self, pair: &KVPair) -> Result<(), Self::Error> {
+        self.tree.insert(pair.key(), pair.value())?;
+        Ok(())
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<(), Self::Error> {
+        self.tree.remove(key)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::consts;
+    use crate::sparse_merkle_tree::{SparseMerkleTree, UpdateData};
+    use crate::types::{Cache, HashKind, HashWithKind, KeyLength, SharedKVPair};
+
+    #[test]
+    fn test_actions() {
+        let temp_dir = TempDir::new("test_sled_db").unwrap();
+        let mut db = SledSmtDB::open(temp_dir.path()).unwrap();
+
+        db.set(&KVPair::new(b"key", b"value")).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+        db.set(&KVPair::new(b"key", b"new_value")).unwrap();
+        assert_eq!(db.get(b"key").unwrap(), Some(b"new_value".to_vec()));
+        db.del(b"key").unwrap();
+        assert_eq!(db.get(b"key").unwrap(), None);
+    }
+
+    #[test]
+    fn test_reopen_tree() {
+        let temp_dir = TempDir::new("test_sled_db").unwrap();
+        let mut data = UpdateData::new_from(Cache::new());
+        let keys: Vec<Vec<u8>> = (0..50u32)
+            .map(|i| i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value))
+            .collect();
+        for key in keys.iter() {
+            data.insert(SharedKVPair(key, &key.hash_with_kind(HashKind::Value)));
+        }
+
+        let root = {
+            let mut db = SledSmtDB::open(temp_dir.path()).unwrap();
+            let mut tree = SparseMerkleTree::new(&[], KeyLength(32), consts::SUBTREE_HEIGHT);
+            let root = tree.commit(&mut db, &data).unwrap();
+            let root = root.lock().unwrap().to_vec();
+            db.flush().unwrap();
+            root
+        };
+
+        let db = SledSmtDB::open(temp_dir.path()).unwrap();
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), consts::SUBTREE_HEIGHT);
+        let proof = tree.prove(&db, &keys[..3]).unwrap();
+        assert!(SparseMerkleTree::verify(&keys[..3], &proof, &root, KeyLength(32)).unwrap());
+        assert!(tree.check_consistency(&db, &root).unwrap().is_empty());
+    }
+}
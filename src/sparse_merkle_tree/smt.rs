@@ -1,17 +1,24 @@
 /// SparseMerkleTree is optimized sparse merkle tree implementation based on [LIP-0039](https://github.com/LiskHQ/lips/blob/main/proposals/lip-0039.md).
 use std::cmp;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use sha2::{Digest, Sha256};
+use smallvec::SmallVec;
 use thiserror::Error;
 
+use crate::codec;
 use crate::consts::{PREFIX_EMPTY, PREFIX_LEAF_HASH};
 use crate::database::traits::Actions;
+use crate::error_code::{self, ErrorCode};
+use crate::metrics::{self, Counter, Histogram};
+use crate::sparse_merkle_tree::bloom::BloomFilter;
 use crate::types::{
-    ArcMutex, Cache, Hash256, HashKind, HashWithKind, Height, KVPair, KeyLength, NestedVec,
-    NestedVecOfSlices, SharedKVPair, SharedNestedVec, SharedVec, StructurePosition, SubtreeHeight,
-    VecOption,
+    hash_branch, hash_branch_array, hash_branches, ArcMutex, Hash256, HashKind, HashWithKind,
+    Height, KVPair, KeyLength, NestedVec, NestedVecOfSlices, SharedKVPair, SharedNestedVec,
+    SharedVec, StructurePosition, SubtreeHeight, SubtreeHeightKind, VecOption, PREFIX_SIZE,
 };
 use crate::utils;
 
@@ -21,8 +28,23 @@ const PREFIX_SUB_TREE_LEAF: u8 = 0;
 const PREFIX_SUB_TREE_BRANCH: u8 = 1;
 /// PREFIX_SUB_TREE_EMPTY is for empty prefix for sub tree.
 const PREFIX_SUB_TREE_EMPTY: u8 = 2;
+/// PREFIX_SUB_TREE_VERSION marks the versioned encoding of the sub tree.
+/// Legacy encoding starts with the number of nodes minus one, which is 255 only for a full sub tree of height 8,
+/// and then the first structure byte is 8. Therefore it is followed by a version other than 8.
+const PREFIX_SUB_TREE_VERSION: u8 = 0xff;
+/// SUB_TREE_VERSION is the version of the sub tree encoding written by encode.
+pub const SUB_TREE_VERSION: u8 = 1;
 /// Hash size used in the smt.
 const HASH_SIZE: usize = 32;
+/// NODE_BYTES_INLINE is the size of the key and the value of the node kept without allocating.
+const NODE_BYTES_INLINE: usize = 40;
+/// PARALLEL_PROOF_MIN_QUERIES is the number of queries from which prove_parallel generates the proofs in parallel.
+const PARALLEL_PROOF_MIN_QUERIES: usize = 16;
+/// MAX_PROOF_HELPERS is the number of the threads helping the callers of prove_parallel at the same time in the process.
+const MAX_PROOF_HELPERS: usize = 4;
+
+/// PROOF_HELPERS is the number of the threads currently helping the callers of prove_parallel.
+static PROOF_HELPERS: AtomicUsize = AtomicUsize::new(0);
 /// EMPTY_HASH using sha256.
 pub const EMPTY_HASH: [u8; 32] = [
     227, 176, 196, 66, 152, 252, 28, 20, 154, 251, 244, 200, 153, 111, 185, 36, 39, 174, 65, 228,
@@ -31,6 +53,14 @@ pub const EMPTY_HASH: [u8; 32] = [
 
 type SharedNode = ArcMutex<Node>;
 
+/// CommitHook observes a commit, reporting the percentage of the root SubTree bins processed.
+/// The commit is aborted with SMTError::Aborted before the next bin once cancel is set.
+pub struct CommitHook<'a> {
+    cancel: &'a AtomicBool,
+    on_progress: &'a mut dyn FnMut(u8),
+    last_progress: Option<u8>,
+}
+
 trait SortDescending {
     fn sort_descending(&mut self);
 }
@@ -47,6 +77,8 @@ pub enum SMTError {
     InvalidRoot(String),
     #[error("unknown data store error `{0}`")]
     Unknown(String),
+    #[error("operation was aborted")]
+    Aborted,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -57,10 +89,39 @@ enum NodeKind {
     Temp, // temp is a stub but only used during the calculation.
 }
 
+/// BatchUpdateData collects the UpdateData of the batch, and the error of the first key which is not a state key.
+#[cfg(feature = "neon")]
+#[derive(Default)]
+struct BatchUpdateData {
+    data: UpdateData,
+    error: Option<SMTError>,
+}
+
+/// EmptyValue selects what UpdateData does with the pairs whose value is empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyValue {
+    /// DeleteOnEmpty deletes the key, which is how the state and the batches are committed.
+    #[default]
+    DeleteOnEmpty,
+    /// StoreEmptyHash stores EMPTY_HASH as the value, so an empty byte string is kept in the tree and can be proven.
+    /// The keys are deleted with UpdateData::delete.
+    StoreEmptyHash,
+}
+
 /// UpdateData holds key-value pairs to update the SMT.
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The pairs are kept sorted by key, which is the order the tree is updated in.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct UpdateData {
-    data: Cache,
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+    empty_value: EmptyValue,
+}
+
+/// BulkLoader collects the key-value pairs sorted by key for the bulk load, so they can be given in several batches.
+/// The length and the order of the keys are checked as each batch is added.
+pub struct BulkLoader {
+    key_length: KeyLength,
+    pairs: Vec<KVPair>,
+    last_key: Option<Vec<u8>>,
 }
 
 /// Proof holds SMT proof.
@@ -70,11 +131,55 @@ pub struct Proof {
     pub queries: Vec<QueryProof>,
 }
 
+/// PrefixNode is the node covering the prefix in PrefixProof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrefixNode {
+    Empty,
+    /// Leaf is the only leaf below the node. Its key does not start with the prefix if it is above the prefix.
+    Leaf {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// Branch is the root of the subtree containing all the keys starting with the prefix.
+    Branch(Vec<u8>),
+}
+
+/// PrefixProof proves the root of the subtree covering all the keys starting with the prefix.
+/// The node is at the depth of the number of the sibling hashes, which are ordered from the root.
+/// The depth is less than the bits of the prefix when the tree is collapsed to an empty node or a leaf above it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixProof {
+    pub prefix: Vec<u8>,
+    pub node: PrefixNode,
+    pub sibling_hashes: NestedVec,
+}
+
+/// DumpNodeKind is the kind of the node in the dump of the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpNodeKind {
+    Branch,
+    Leaf,
+    Empty,
+}
+
+/// DumpNode is a node in the dump of the tree. height is the distance from the root.
+/// key and value are only set for the leaf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpNode {
+    pub kind: DumpNodeKind,
+    pub height: usize,
+    pub hash: Vec<u8>,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
 /// QueryProof is single proof for a query.
 #[derive(Clone, Debug)]
 pub struct QueryProof {
     pub pair: Arc<KVPair>,
     pub bitmap: Arc<Vec<u8>>,
+    /// stored_value is the full value of the query when it is requested. It is not a part of the proof.
+    pub stored_value: Option<Vec<u8>>,
 }
 
 #[derive(Clone, Debug)]
@@ -86,12 +191,27 @@ pub struct QueryProofWithProof {
     hash: Vec<u8>,
 }
 
+/// VerifyQuery is the query of the proof in the verification.
+/// The hash is kept on the stack and the bitmap is consumed by moving the offset,
+/// so that moving the query up a level does not allocate.
+struct VerifyQuery<'a> {
+    key: &'a [u8],
+    bitmap: Vec<bool>,
+    offset: usize,
+    hash: [u8; 32],
+}
+
+/// NodeBytes keeps the key and the value of the leaf inline, since they are at most 38 and 32 bytes
+/// in the trees of the state_db. The longer keys and values are kept on the heap.
+type NodeBytes = SmallVec<[u8; NODE_BYTES_INLINE]>;
+
 #[derive(Clone, Debug)]
 struct Node {
     kind: NodeKind,
-    // key is represented of the Data and value is represented of the Hash
-    hash: KVPair,
-    key: Vec<u8>,
+    hash: [u8; HASH_SIZE],
+    // key and value are the key and the value hash of the leaf, and they are empty for the other kinds
+    key: NodeBytes,
+    value: NodeBytes,
     index: usize,
 }
 
@@ -163,6 +283,10 @@ pub struct SparseMerkleTree {
     subtree_height: SubtreeHeight,
     /// height of the sub tree. Increase in the subtree height will increase number of hashes used while it decreases call to the storage.
     max_number_of_nodes: usize,
+    /// cancel stops the proof generation and the bulk load with SMTError::Aborted once it is set.
+    cancel: Option<Arc<AtomicBool>>,
+    /// metrics is the instance of the database which the metrics of the commits and the proofs are tagged with.
+    metrics: metrics::Instance,
 }
 
 #[derive(Clone)]
@@ -174,15 +298,20 @@ struct Hasher {
 
 impl Hash256 for KVPair {
     fn hash(&self) -> Vec<u8> {
-        let mut hasher = Sha256::new();
-        hasher.update(PREFIX_LEAF_HASH);
-        hasher.update(self.key());
-        hasher.update(self.value());
-        let result = hasher.finalize();
-        result.to_vec()
+        hash_leaf(self.key(), self.value()).to_vec()
     }
 }
 
+/// hash_leaf returns the hash of the leaf of the key and the value hash.
+fn hash_leaf(key: &[u8], value: &[u8]) -> [u8; HASH_SIZE] {
+    metrics::increment(Counter::NodesHashed, 1);
+    let mut hasher = Sha256::new();
+    hasher.update(PREFIX_LEAF_HASH);
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
 fn get_parent_node(
     layer_nodes: &[SharedNode],
     layer_structure: &[u8],
@@ -272,14 +401,46 @@ fn calculate_subtree(
     SubTree::from_data(&[0], &layer_nodes)
 }
 
+/// is_valid_structure checks that the structure can be folded into a single root by the Hasher.
+fn is_valid_structure(structure: &[u8]) -> bool {
+    let mut layer = structure.to_vec();
+    let mut height = match structure.iter().max() {
+        Some(h) if *h <= SubtreeHeightKind::Eight as u8 => *h,
+        _ => return false,
+    };
+    while layer.len() > 1 {
+        if height == 0 {
+            return false;
+        }
+        let mut next_layer = Vec::with_capacity(layer.len());
+        let mut i = 0;
+        while i < layer.len() {
+            if layer[i] != height {
+                next_layer.push(layer[i]);
+                i += 1;
+                continue;
+            }
+            if i + 1 >= layer.len() || layer[i + 1] != height {
+                return false;
+            }
+            next_layer.push(height - 1);
+            i += 2;
+        }
+        layer = next_layer;
+        height -= 1;
+    }
+
+    true
+}
+
 fn calculate_next_info(info: &mut QueryHashesInfo, next_info: &mut NextQueryHashesInfo, i: usize) {
     let layer_node = info.layer_nodes[i].lock().unwrap();
     let layer_node_next = info.layer_nodes[i + 1].lock().unwrap();
 
-    let mut parent_node = Node::new_branch(layer_node.hash.value(), layer_node_next.hash.value());
+    let mut parent_node = Node::new_branch(&layer_node.hash, &layer_node_next.hash);
     parent_node.index = info.extra.max_index + i;
     let parent_node_index = parent_node.index;
-    let parent_node_hash = parent_node.hash.value_as_vec();
+    let parent_node_hash = parent_node.hash.to_vec();
 
     next_info
         .layer_nodes
@@ -296,7 +457,7 @@ fn calculate_next_info(info: &mut QueryHashesInfo, next_info: &mut NextQueryHash
             info.ref_mut_vecs.binary_bitmap.push(true);
             info.ref_mut_vecs
                 .sibling_hashes
-                .push_front(layer_node_next.hash.value_as_vec());
+                .push_front(layer_node_next.hash.to_vec());
         }
     } else if next_info.target_id == layer_node_next.index {
         info.ref_mut_vecs
@@ -309,7 +470,7 @@ fn calculate_next_info(info: &mut QueryHashesInfo, next_info: &mut NextQueryHash
             info.ref_mut_vecs.binary_bitmap.push(true);
             info.ref_mut_vecs
                 .sibling_hashes
-                .push_front(layer_node.hash.value_as_vec());
+                .push_front(layer_node.hash.to_vec());
         }
     }
 }
@@ -408,19 +569,93 @@ impl std::convert::From<&SMTError> for SMTError {
     }
 }
 
-impl rocksdb::WriteBatchIterator for UpdateData {
+impl ErrorCode for SMTError {
+    fn code(&self) -> &'static str {
+        match self {
+            SMTError::InvalidBitmapLen | SMTError::InvalidInput(_) => error_code::INVALID_INPUT,
+            SMTError::NotFound(_) => error_code::NOT_FOUND,
+            SMTError::InvalidRoot(_) => error_code::INVALID_ROOT,
+            SMTError::Aborted => error_code::ABORTED,
+            SMTError::Unknown(_) => error_code::UNKNOWN,
+        }
+    }
+}
+
+#[cfg(feature = "neon")]
+impl BatchUpdateData {
+    fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        match hash_state_key(key) {
+            Ok(key) => {
+                self.data.data.insert(key, value);
+            },
+            Err(err) if self.error.is_none() => self.error = Some(err),
+            Err(_) => {},
+        }
+    }
+}
+
+#[cfg(feature = "neon")]
+impl rocksdb::WriteBatchIterator for BatchUpdateData {
     /// Called with a key and value that were `put` into the batch.
     fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
-        self.data.insert(
-            key.into_vec().hash_with_kind(HashKind::Key),
-            value.into_vec().hash_with_kind(HashKind::Value),
-        );
+        self.insert(&key, value.into_vec().hash_with_kind(HashKind::Value));
     }
     /// Called with a key that was `delete`d from the batch.
     fn delete(&mut self, key: Box<[u8]>) {
-        self.data
-            .insert(key.into_vec().hash_with_kind(HashKind::Key), vec![]);
+        self.insert(&key, vec![]);
+    }
+}
+
+impl EmptyValue {
+    /// from_name parses the name given from JS, which is "deleteOnEmpty" or "storeEmptyHash".
+    pub fn from_name(name: &str) -> Result<Self, SMTError> {
+        match name {
+            "deleteOnEmpty" => Ok(Self::DeleteOnEmpty),
+            "storeEmptyHash" => Ok(Self::StoreEmptyHash),
+            _ => Err(SMTError::InvalidInput(format!(
+                "Empty value must be deleteOnEmpty or storeEmptyHash, but got {}",
+                name
+            ))),
+        }
+    }
+
+    fn apply(self, value: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::StoreEmptyHash if value.is_empty() => EMPTY_HASH.to_vec(),
+            _ => value,
+        }
+    }
+}
+
+/// check_state_key returns SMTError::InvalidInput if the key is shorter than the store prefix,
+/// which is kept in the key of the tree.
+pub fn check_state_key(key: &[u8]) -> Result<(), SMTError> {
+    if key.len() < PREFIX_SIZE {
+        return Err(SMTError::InvalidInput(format!(
+            "State key must be at least {} bytes of the store prefix",
+            PREFIX_SIZE
+        )));
     }
+    Ok(())
+}
+
+/// decode_subtree decodes the subtree stored as a node of the tree, and returns its root with the encoding of the current version.
+/// The data is untrusted once the database is corrupted, so it never panics on the invalid data.
+pub fn decode_subtree(data: &[u8], key_length: KeyLength) -> Result<(Vec<u8>, Vec<u8>), SMTError> {
+    let subtree = SubTree::new(data, key_length)?;
+    Ok((subtree.root.to_vec(), subtree.encode()))
+}
+
+/// subtree_height decodes the subtree stored as a node of the tree, and returns the height of its deepest node.
+pub fn subtree_height(data: &[u8], key_length: KeyLength) -> Result<u8, SMTError> {
+    let subtree = SubTree::new(data, key_length)?;
+    Ok(subtree.structure.iter().copied().max().unwrap_or_default())
+}
+
+/// hash_state_key returns the key of the state in the tree, which is the store prefix followed by the hash of the rest of the key.
+pub fn hash_state_key(key: &[u8]) -> Result<Vec<u8>, SMTError> {
+    check_state_key(key)?;
+    Ok(key.to_vec().hash_with_kind(HashKind::Key))
 }
 
 impl Hasher {
@@ -436,17 +671,25 @@ impl Hasher {
         while self.node_hashes.len() != 1 {
             let mut next_hashes: Vec<Arc<Vec<u8>>> = Vec::with_capacity(self.node_hashes.len());
             let mut next_structure: Vec<u8> = Vec::with_capacity(self.node_hashes.len());
+            // the branches of the layer are hashed together before the layer is rebuilt
+            let mut branches = Vec::with_capacity(self.node_hashes.len() / 2);
             let mut i = 0;
+            while i < self.node_hashes.len() {
+                if self.structure[i] == self.height.into() {
+                    branches.push((
+                        self.node_hashes[i].as_slice(),
+                        self.node_hashes[i + 1].as_slice(),
+                    ));
+                    i += 1;
+                }
+                i += 1;
+            }
+            let mut branch_hashes = hash_branches(branches).into_iter();
 
+            let mut i = 0;
             while i < self.node_hashes.len() {
                 if self.structure[i] == self.height.into() {
-                    let branch = [
-                        (*self.node_hashes[i]).as_slice(),
-                        (*self.node_hashes[i + 1]).as_slice(),
-                    ]
-                    .concat();
-                    let hash = branch.hash_with_kind(HashKind::Branch);
-                    next_hashes.push(Arc::new(hash.to_vec()));
+                    next_hashes.push(Arc::new(branch_hashes.next().unwrap()));
                     next_structure.push(self.structure[i] - 1);
                     i += 1;
                 } else {
@@ -475,6 +718,7 @@ impl QueryProof {
         Self {
             pair,
             bitmap: Arc::new(utils::bools_to_bytes(binary_bitmap)),
+            stored_value: None,
         }
     }
 
@@ -499,24 +743,161 @@ impl QueryProof {
     }
 }
 
+impl<'a> CommitHook<'a> {
+    pub fn new(cancel: &'a AtomicBool, on_progress: &'a mut dyn FnMut(u8)) -> Self {
+        Self {
+            cancel,
+            on_progress,
+            last_progress: None,
+        }
+    }
+
+    /// check returns SMTError::Aborted if the commit is cancelled.
+    fn check(&self) -> Result<(), SMTError> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(SMTError::Aborted);
+        }
+        Ok(())
+    }
+
+    /// report calls on_progress only when the percentage has changed.
+    fn report(&mut self, processed: usize, total: usize) {
+        let percent = (processed * 100 / total.max(1)).min(100) as u8;
+        if self.last_progress == Some(percent) {
+            return;
+        }
+        self.last_progress = Some(percent);
+        (self.on_progress)(percent);
+    }
+}
+
+impl PrefixProof {
+    /// node_hash returns the hash of the node at the end of the path.
+    pub fn node_hash(&self) -> Vec<u8> {
+        match &self.node {
+            PrefixNode::Empty => EMPTY_HASH.to_vec(),
+            PrefixNode::Leaf { key, value } => KVPair::new(key, value).hash(),
+            PrefixNode::Branch(hash) => hash.clone(),
+        }
+    }
+
+    /// subtree_root returns the root of the subtree containing all the keys starting with the prefix.
+    pub fn subtree_root(&self) -> Vec<u8> {
+        match &self.node {
+            PrefixNode::Leaf { key, .. } if !key.starts_with(&self.prefix) => EMPTY_HASH.to_vec(),
+            _ => self.node_hash(),
+        }
+    }
+}
+
+impl DumpNodeKind {
+    /// name returns the name used for reporting to JS.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DumpNodeKind::Branch => "branch",
+            DumpNodeKind::Leaf => "leaf",
+            DumpNodeKind::Empty => "empty",
+        }
+    }
+}
+
+#[cfg(feature = "neon")]
+impl neon::types::Finalize for BulkLoader {}
+
+impl BulkLoader {
+    pub fn new(key_length: KeyLength) -> Self {
+        Self {
+            key_length,
+            pairs: vec![],
+            last_key: None,
+        }
+    }
+
+    /// add appends the pairs which follow the pairs added before. Pairs with an empty value are skipped.
+    pub fn add(&mut self, sorted_iter: impl IntoIterator<Item = KVPair>) -> Result<(), SMTError> {
+        let key_length: usize = self.key_length.into();
+        for pair in sorted_iter {
+            if pair.key().len() != key_length {
+                return Err(SMTError::InvalidInput(String::from(
+                    "all keys must have the same length",
+                )));
+            }
+            if let Some(last) = &self.last_key {
+                if utils::compare(last, pair.key()) != cmp::Ordering::Less {
+                    return Err(SMTError::InvalidInput(String::from(
+                        "keys must be sorted in ascending order without duplicates",
+                    )));
+                }
+            }
+            self.last_key = Some(pair.key_as_vec());
+            if !pair.is_empty_value() {
+                self.pairs.push(pair);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl UpdateData {
-    pub fn new_from(data: Cache) -> Self {
-        Self { data }
+    /// new_from creates UpdateData with the pairs, such as the Cache of the hashed keys and values.
+    /// The pairs with an empty value delete their keys.
+    pub fn new_from(data: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Self {
+        Self {
+            data: data.into_iter().collect(),
+            empty_value: EmptyValue::DeleteOnEmpty,
+        }
+    }
+
+    /// new_with_empty_value creates UpdateData like new_from, with the pairs of an empty value handled by empty_value.
+    /// The values are replaced here, so the tree never sees an empty value with StoreEmptyHash.
+    pub fn new_with_empty_value(
+        data: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+        empty_value: EmptyValue,
+    ) -> Self {
+        Self {
+            data: data
+                .into_iter()
+                .map(|(key, value)| (key, empty_value.apply(value)))
+                .collect(),
+            empty_value,
+        }
+    }
+
+    /// empty_value returns how the pairs with an empty value are handled.
+    pub fn empty_value(&self) -> EmptyValue {
+        self.empty_value
+    }
+
+    /// new_from_batch creates UpdateData with the hashed keys and values of the operations in the batch.
+    /// It returns SMTError::InvalidInput if a key of the batch is shorter than the store prefix.
+    #[cfg(feature = "neon")]
+    pub fn new_from_batch(batch: &rocksdb::WriteBatch) -> Result<Self, SMTError> {
+        let mut data = BatchUpdateData::default();
+        batch.iterate(&mut data);
+        match data.error {
+            Some(err) => Err(err),
+            None => Ok(data.data),
+        }
     }
 
     pub fn insert(&mut self, kv: SharedKVPair) {
-        self.data.insert(kv.key_as_vec(), kv.value_as_vec());
+        self.data
+            .insert(kv.key_as_vec(), self.empty_value.apply(kv.value_as_vec()));
+    }
+
+    /// delete removes the key from the tree, which is the only way to do it with StoreEmptyHash.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.data.insert(key.to_vec(), vec![]);
     }
 
-    pub fn entries(&self) -> (SharedNestedVec, SharedNestedVec) {
-        let mut kv_pair: Vec<SharedKVPair> =
-            self.data.iter().map(|(k, v)| SharedKVPair(k, v)).collect();
-        kv_pair.sort_by(|a, b| a.0.cmp(b.0));
-        let mut keys = Vec::with_capacity(kv_pair.len());
-        let mut values = Vec::with_capacity(kv_pair.len());
-        for kv in kv_pair {
-            keys.push(kv.0);
-            values.push(kv.1);
+    /// entries returns the keys and the values borrowed from the data, sorted by key.
+    pub fn entries(&self) -> (SharedNestedVec<'_>, SharedNestedVec<'_>) {
+        let mut keys = Vec::with_capacity(self.data.len());
+        let mut values = Vec::with_capacity(self.data.len());
+        for (key, value) in self.data.iter() {
+            keys.push(key.as_slice());
+            values.push(value.as_slice());
         }
         (keys, values)
     }
@@ -524,6 +905,11 @@ impl UpdateData {
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    /// size returns the total bytes of the keys and values.
+    pub fn size(&self) -> usize {
+        self.data.iter().map(|(k, v)| k.len() + v.len()).sum()
+    }
 }
 
 impl QueryProofWithProof {
@@ -592,55 +978,105 @@ impl QueryProofWithProof {
     }
 }
 
+impl<'a> VerifyQuery<'a> {
+    fn new(query: &'a QueryProof) -> Result<Self, SMTError> {
+        let bitmap = utils::strip_left_false(&utils::bytes_to_bools(&query.bitmap));
+        if bitmap.len() > query.key().len() * 8 {
+            return Err(SMTError::InvalidBitmapLen);
+        }
+        let mut hash = EMPTY_HASH;
+        if !query.pair.is_empty_value() {
+            hash.copy_from_slice(&query.pair.hash());
+        }
+
+        Ok(Self {
+            key: query.key(),
+            bitmap,
+            offset: 0,
+            hash,
+        })
+    }
+
+    fn height(&self) -> usize {
+        self.bitmap.len() - self.offset
+    }
+
+    /// is_bit_set returns the bit of the key at the height, which tells the side of the node at the height.
+    fn is_bit_set(&self, height: usize) -> bool {
+        utils::is_bit_set(self.key, height - 1)
+    }
+
+    /// has_common_path returns true if the paths of the queries are the same above the height.
+    fn has_common_path(&self, query: &VerifyQuery, height: usize) -> bool {
+        self.height() == query.height()
+            && (1..=height).all(|h| self.is_bit_set(h) == query.is_bit_set(h))
+    }
+
+    fn is_sibling_of(&self, query: &VerifyQuery) -> bool {
+        let height = self.height();
+        self.has_common_path(query, height - 1)
+            && self.is_bit_set(height) != query.is_bit_set(height)
+    }
+
+    /// is_bitmap_consistent returns false if the bitmap of the query at the current height does not match the sibling.
+    fn is_bitmap_consistent(&self, sibling: &VerifyQuery) -> bool {
+        self.bitmap[self.offset] != utils::is_empty_hash(&sibling.hash)
+    }
+}
+
+// The commit creates a node for each updated bin, so the hash, the key and the value are kept in the node
+// without allocating.
 impl Node {
     fn new_temp() -> Self {
         Self {
             kind: NodeKind::Temp,
-            hash: KVPair::new(&[], &[]),
-            key: vec![],
+            hash: [0; HASH_SIZE],
+            key: NodeBytes::new(),
+            value: NodeBytes::new(),
             index: 0,
         }
     }
 
-    fn new_stub(node_hash: &[u8]) -> Self {
-        let data = [&[PREFIX_SUB_TREE_BRANCH], node_hash].concat();
-        Self {
+    /// new_stub returns the node of the subtree of the hash, which must be HASH_SIZE bytes.
+    fn new_stub(node_hash: &[u8]) -> Result<Self, SMTError> {
+        let hash = node_hash.try_into().map_err(|_| {
+            SMTError::InvalidInput(String::from("Invalid data. node hash is invalid."))
+        })?;
+        Ok(Self {
             kind: NodeKind::Stub,
-            hash: KVPair::new(&data, node_hash),
-            key: vec![],
+            hash,
+            key: NodeBytes::new(),
+            value: NodeBytes::new(),
             index: 0,
-        }
+        })
     }
 
     fn new_branch(left_hash: &[u8], right_hash: &[u8]) -> Self {
-        let combined = [left_hash, right_hash].concat();
-        let data = [&[PREFIX_SUB_TREE_BRANCH], left_hash, right_hash].concat();
-        let hashed = combined.hash_with_kind(HashKind::Branch);
         Self {
             kind: NodeKind::Stub,
-            hash: KVPair::new(&data, &hashed),
-            key: vec![],
+            hash: hash_branch_array(left_hash, right_hash),
+            key: NodeBytes::new(),
+            value: NodeBytes::new(),
             index: 0,
         }
     }
 
-    fn new_leaf(pair: &KVPair) -> Self {
-        let h = pair.hash();
-        let data = [&[PREFIX_SUB_TREE_LEAF], pair.key(), pair.value()].concat();
+    fn new_leaf(key: &[u8], value: &[u8]) -> Self {
         Self {
             kind: NodeKind::Leaf,
-            hash: KVPair::new(&data, &h),
-            key: pair.key_as_vec(),
+            hash: hash_leaf(key, value),
+            key: NodeBytes::from_slice(key),
+            value: NodeBytes::from_slice(value),
             index: 0,
         }
     }
 
     fn new_empty() -> Self {
-        let data = [PREFIX_EMPTY].concat();
         Self {
             kind: NodeKind::Empty,
-            hash: KVPair::new(&data, &EMPTY_HASH),
-            key: vec![],
+            hash: EMPTY_HASH,
+            key: NodeBytes::new(),
+            value: NodeBytes::new(),
             index: 0,
         }
     }
@@ -648,11 +1084,75 @@ impl Node {
 
 impl SubTree {
     /// new returns decoded SubTree using the encoded data.
+    /// It accepts both the versioned encoding and the legacy encoding with fixed size keys and hashes.
     pub fn new(data: &[u8], key_length: KeyLength) -> Result<Self, SMTError> {
         if data.is_empty() {
             return Err(SMTError::InvalidInput(String::from("keys length is zero")));
         }
+        if data.len() > 1
+            && data[0] == PREFIX_SUB_TREE_VERSION
+            && data[1] != SubtreeHeightKind::Eight as u8
+        {
+            return SubTree::decode_versioned(&data[1..]);
+        }
+        SubTree::decode_legacy(data, key_length)
+    }
+
+    /// decode_versioned decodes the data following the version prefix.
+    fn decode_versioned(data: &[u8]) -> Result<Self, SMTError> {
+        let version = data.first().copied().unwrap_or_default();
+        if version != SUB_TREE_VERSION {
+            return Err(SMTError::InvalidInput(format!(
+                "Unsupported sub tree encoding version {}",
+                version
+            )));
+        }
+        let mut idx = 1;
+        let node_length = read_varint(data, &mut idx)?;
+        if node_length > data.len() - idx {
+            return Err(SMTError::InvalidInput(String::from(
+                "Invalid data. structure is too short.",
+            )));
+        }
+        let structure = &data[idx..idx + node_length];
+        idx += node_length;
+        let mut nodes: Vec<SharedNode> = Vec::with_capacity(node_length);
+        while idx < data.len() {
+            let prefix = data[idx];
+            idx += 1;
+            let node = match prefix {
+                PREFIX_SUB_TREE_LEAF => {
+                    let key = read_length_prefixed(data, &mut idx)?;
+                    let value = read_length_prefixed(data, &mut idx)?;
+                    Node::new_leaf(key, value)
+                },
+                PREFIX_SUB_TREE_BRANCH => Node::new_stub(read_length_prefixed(data, &mut idx)?)?,
+                PREFIX_SUB_TREE_EMPTY => Node::new_empty(),
+                _ => {
+                    return Err(SMTError::InvalidInput(String::from(
+                        "Invalid data. key prefix is invalid.",
+                    )));
+                },
+            };
+            nodes.push(Arc::new(Mutex::new(node)));
+        }
+        if nodes.len() != structure.len() || !is_valid_structure(structure) {
+            return Err(SMTError::InvalidInput(String::from(
+                "Invalid data. structure does not match nodes.",
+            )));
+        }
+
+        SubTree::from_data(structure, &nodes)
+    }
+
+    /// decode_legacy decodes the data where the node length is a byte and keys and hashes have fixed size.
+    fn decode_legacy(data: &[u8], key_length: KeyLength) -> Result<Self, SMTError> {
         let node_length: usize = data[0] as usize + 1;
+        if data.len() < node_length + 1 {
+            return Err(SMTError::InvalidInput(String::from(
+                "Invalid data. structure is too short.",
+            )));
+        }
         let structure = &data[1..node_length + 1];
         let node_data = &data[node_length + 1..];
         let mut nodes: Vec<SharedNode> = Vec::with_capacity(node_data.len());
@@ -660,22 +1160,31 @@ impl SubTree {
 
         let key_length: usize = key_length.into();
         while idx < node_data.len() {
+            let node_size = match node_data[idx] {
+                PREFIX_SUB_TREE_LEAF => [PREFIX_SUB_TREE_LEAF].len() + key_length + HASH_SIZE,
+                PREFIX_SUB_TREE_BRANCH => [PREFIX_SUB_TREE_BRANCH].len() + HASH_SIZE,
+                _ => PREFIX_EMPTY.len(),
+            };
+            if idx + node_size > node_data.len() {
+                return Err(SMTError::InvalidInput(String::from(
+                    "Invalid data. node is too short.",
+                )));
+            }
             match node_data[idx] {
                 PREFIX_SUB_TREE_LEAF => {
-                    let kv = KVPair::new(
+                    let node = Node::new_leaf(
                         &node_data[idx + [PREFIX_SUB_TREE_LEAF].len()
                             ..idx + [PREFIX_SUB_TREE_LEAF].len() + key_length],
                         &node_data[idx + [PREFIX_SUB_TREE_LEAF].len() + key_length
                             ..idx + [PREFIX_SUB_TREE_LEAF].len() + key_length + HASH_SIZE],
                     );
-                    let node = Node::new_leaf(&kv);
                     nodes.push(Arc::new(Mutex::new(node)));
                     idx += [PREFIX_SUB_TREE_LEAF].len() + key_length + HASH_SIZE;
                 },
                 PREFIX_SUB_TREE_BRANCH => {
                     let node_hash = &node_data[idx + [PREFIX_SUB_TREE_BRANCH].len()
                         ..idx + [PREFIX_SUB_TREE_BRANCH].len() + HASH_SIZE];
-                    nodes.push(Arc::new(Mutex::new(Node::new_stub(node_hash))));
+                    nodes.push(Arc::new(Mutex::new(Node::new_stub(node_hash)?)));
                     idx += [PREFIX_SUB_TREE_BRANCH].len() + HASH_SIZE;
                 },
                 PREFIX_SUB_TREE_EMPTY => {
@@ -689,6 +1198,11 @@ impl SubTree {
                 },
             }
         }
+        if nodes.len() != structure.len() || !is_valid_structure(structure) {
+            return Err(SMTError::InvalidInput(String::from(
+                "Invalid data. structure does not match nodes.",
+            )));
+        }
 
         SubTree::from_data(structure, &nodes)
     }
@@ -703,7 +1217,7 @@ impl SubTree {
 
         let node_hashes = nodes
             .iter()
-            .map(|n| Arc::new(n.lock().unwrap().hash.value_as_vec()))
+            .map(|n| Arc::new(n.lock().unwrap().hash.to_vec()))
             .collect::<Vec<Arc<Vec<u8>>>>();
         let mut hasher = Hasher::new(&node_hashes, structure, height);
         let calculated = hasher.execute();
@@ -724,25 +1238,60 @@ impl SubTree {
         Self {
             structure,
             nodes: node_hashes,
-            root: Arc::new(empty.hash.value_as_vec()),
+            root: Arc::new(empty.hash.to_vec()),
         }
     }
 
     /// encode SubTree into bytes slice, which can be used in "new".
+    /// The nodes are encoded with the length of the keys and hashes after the version prefix.
     pub fn encode(&self) -> Vec<u8> {
-        let node_length = (self.structure.len() - 1) as u8;
-        let node_hashes: NestedVec = self
-            .nodes
-            .iter()
-            .map(|n| n.lock().unwrap().hash.key_as_vec())
-            .collect();
-        [
-            &[node_length],
-            self.structure.as_slice(),
-            node_hashes.concat().as_slice(),
-        ]
-        .concat()
+        let mut result = vec![PREFIX_SUB_TREE_VERSION, SUB_TREE_VERSION];
+        result.extend(codec::write_varint(self.structure.len() as u32));
+        result.extend_from_slice(&self.structure);
+        for node in self.nodes.iter() {
+            let node = node.lock().unwrap();
+            match node.kind {
+                NodeKind::Leaf => {
+                    result.push(PREFIX_SUB_TREE_LEAF);
+                    write_length_prefixed(&mut result, &node.key);
+                    write_length_prefixed(&mut result, &node.value);
+                },
+                NodeKind::Stub => {
+                    result.push(PREFIX_SUB_TREE_BRANCH);
+                    write_length_prefixed(&mut result, &node.hash);
+                },
+                NodeKind::Empty | NodeKind::Temp => result.push(PREFIX_SUB_TREE_EMPTY),
+            }
+        }
+
+        result
+    }
+}
+
+fn read_varint(data: &[u8], idx: &mut usize) -> Result<usize, SMTError> {
+    let (value, size) =
+        codec::read_varint(data, *idx).map_err(|err| SMTError::InvalidInput(err.to_string()))?;
+    *idx += size;
+
+    Ok(value as usize)
+}
+
+fn read_length_prefixed<'a>(data: &'a [u8], idx: &mut usize) -> Result<&'a [u8], SMTError> {
+    let length = read_varint(data, idx)?;
+    if length > data.len() - *idx {
+        return Err(SMTError::InvalidInput(String::from(
+            "Invalid data. node is too short.",
+        )));
     }
+    let result = &data[*idx..*idx + length];
+    *idx += length;
+
+    Ok(result)
+}
+
+fn write_length_prefixed(result: &mut Vec<u8>, value: &[u8]) {
+    result.extend(codec::write_varint(value.len() as u32));
+    result.extend_from_slice(value);
 }
 
 impl QueryHashesExtraInfo {
@@ -819,6 +1368,7 @@ impl SparseMerkleTree {
             .map(|query| QueryProof {
                 pair: Arc::clone(&query.query_proof.pair),
                 bitmap: Arc::clone(&query.query_proof.bitmap),
+                stored_value: None,
             })
             .collect();
 
@@ -827,13 +1377,14 @@ impl SparseMerkleTree {
 
     fn generate_sibling_data(
         &mut self,
-        db: &mut impl Actions,
+        db: &impl Actions,
         queries: &[Vec<u8>],
     ) -> Result<(Vec<QueryProofWithProof>, NestedVec), SMTError> {
         let mut query_with_proofs: Vec<QueryProofWithProof> = Vec::with_capacity(queries.len());
         let mut root = self.get_subtree(db, &self.root.lock().unwrap())?;
         let mut ancestor_hashes = Vec::with_capacity(queries.len());
         for query in queries {
+            self.check_cancel()?;
             let query_proof = self.generate_query_proof(db, &mut root, query, Height(0))?;
             query_with_proofs.push(query_proof.clone());
             ancestor_hashes.extend(query_proof.ancestor_hashes);
@@ -842,6 +1393,91 @@ impl SparseMerkleTree {
         Ok((query_with_proofs, ancestor_hashes))
     }
 
+    /// generate_sibling_data_parallel partitions the queries by the bin of the root subtree,
+    /// and generates the proofs of each partition in a separate thread.
+    /// The result is in the order of the queries, so it is the same as generate_sibling_data.
+    fn generate_sibling_data_parallel(
+        &mut self,
+        db: &(impl Actions + Sync),
+        queries: &[Vec<u8>],
+    ) -> Result<(Vec<QueryProofWithProof>, NestedVec), SMTError> {
+        if queries.len() < PARALLEL_PROOF_MIN_QUERIES {
+            return self.generate_sibling_data(db, queries);
+        }
+        if queries
+            .iter()
+            .any(|query| query.len() != self.key_length.into())
+        {
+            return Err(SMTError::InvalidInput(String::from(
+                "Query key length must be equal to key length",
+            )));
+        }
+        let root_hash = Arc::clone(&self.root.lock().unwrap());
+        let root = self.get_subtree(db, &root_hash)?;
+        // keys in the same bin of the root subtree share the nodes below the root subtree.
+        let shift = 8 - cmp::min(self.subtree_height.u16(), 8);
+        let mut partitions: HashMap<u8, Vec<usize>> = HashMap::new();
+        for (i, query) in queries.iter().enumerate() {
+            partitions.entry(query[0] >> shift).or_default().push(i);
+        }
+
+        let key_length = self.key_length;
+        let subtree_height = self.subtree_height;
+        let cancel = &self.cancel;
+        let instance = self.metrics;
+        let helpers = ProofHelpers::acquire(partitions.len() - 1);
+        let partitions = Mutex::new(partitions.into_values().collect::<Vec<_>>());
+        // the caller and the helpers take the partitions one by one until all of them are proven
+        let work = || {
+            let _scope = metrics::Scope::count(instance);
+            let mut tree = SparseMerkleTree::new(&root_hash, key_length, subtree_height);
+            tree.cancel = cancel.clone();
+            let mut proofs = vec![];
+            loop {
+                let indexes = match partitions.lock().unwrap().pop() {
+                    Some(indexes) => indexes,
+                    None => return Ok(proofs),
+                };
+                let mut root = root.clone();
+                for i in indexes {
+                    tree.check_cancel()?;
+                    let proof =
+                        tree.generate_query_proof(db, &mut root, &queries[i], Height(0))?;
+                    proofs.push((i, proof));
+                }
+            }
+        };
+        let results = std::thread::scope(|scope| {
+            let handles = (0..helpers.count)
+                .map(|_| scope.spawn(work))
+                .collect::<Vec<_>>();
+            let mut results: Vec<Result<Vec<(usize, QueryProofWithProof)>, SMTError>> =
+                vec![work()];
+            results.extend(handles.into_iter().map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    Err(SMTError::Unknown(String::from("Proof generation panicked")))
+                })
+            }));
+            results
+        });
+        drop(helpers);
+
+        let mut ordered: Vec<Option<QueryProofWithProof>> = vec![None; queries.len()];
+        for result in results {
+            for (i, proof) in result? {
+                ordered[i] = Some(proof);
+            }
+        }
+        let mut query_with_proofs: Vec<QueryProofWithProof> = Vec::with_capacity(queries.len());
+        let mut ancestor_hashes = Vec::with_capacity(queries.len());
+        for query_proof in ordered.into_iter().flatten() {
+            ancestor_hashes.extend(query_proof.ancestor_hashes.iter().cloned());
+            query_with_proofs.push(query_proof);
+        }
+
+        Ok((query_with_proofs, ancestor_hashes))
+    }
+
     fn verify_query_keys(proof: &Proof, query_keys: &[Vec<u8>], key_length: KeyLength) -> bool {
         let mut queries: HashMap<&[u8], QueryProof> = HashMap::new();
         for (i, key) in query_keys.iter().enumerate() {
@@ -879,14 +1515,12 @@ impl SparseMerkleTree {
     /// get_subtree returns sub_tree based on the node_hash provided.
     /// if node_has is empty or equals to the empty hash, it returns empty SubTree.
     fn get_subtree(&self, db: &impl Actions, node_hash: &[u8]) -> Result<SubTree, SMTError> {
-        if node_hash.is_empty() {
-            return Ok(SubTree::new_empty());
-        }
-
-        if utils::is_empty_hash(node_hash) {
+        if node_hash.is_empty() || utils::is_empty_hash(node_hash) {
+            metrics::increment(Counter::SubtreeCacheHits, 1);
             return Ok(SubTree::new_empty());
         }
 
+        metrics::increment(Counter::DbGets, 1);
         let value = db
             .get(node_hash)
             .map_err(|err| SMTError::Unknown(err.to_string()))?
@@ -933,12 +1567,16 @@ impl SparseMerkleTree {
         key_bin: &'a [&'a [u8]],
         value_bin: &'a [&'a [u8]],
         height: Height,
+        mut hook: Option<&mut CommitHook>,
     ) -> Result<UpdatedInfo, SMTError> {
         let bins = self.calculate_bins(key_bin, value_bin, height)?;
         let mut nodes: Vec<SharedNode> = vec![];
         let mut structures: Vec<u8> = vec![];
         let mut bin_offset = 0;
         for i in 0..current_subtree.nodes.len() {
+            if let Some(hook) = hook.as_deref_mut() {
+                hook.check()?;
+            }
             let pos = current_subtree.structure[i];
             let current_node = Arc::clone(&current_subtree.nodes[i]);
             let new_offset = 1 << self.subtree_height.sub_to_usize(pos);
@@ -971,6 +1609,9 @@ impl SparseMerkleTree {
 
             structures.extend(heights);
             bin_offset += new_offset;
+            if let Some(hook) = hook.as_deref_mut() {
+                hook.report(bin_offset, self.max_number_of_nodes);
+            }
         }
 
         Ok(UpdatedInfo {
@@ -988,12 +1629,13 @@ impl SparseMerkleTree {
         value_bin: &'a [&'a [u8]],
         current_subtree: &SubTree,
         height: Height,
+        hook: Option<&mut CommitHook>,
     ) -> Result<SubTree, SMTError> {
         if key_bin.is_empty() {
             return Ok(current_subtree.clone());
         }
         let updated =
-            self.calculate_updated_info(db, current_subtree, key_bin, value_bin, height)?;
+            self.calculate_updated_info(db, current_subtree, key_bin, value_bin, height, hook)?;
         if updated.bin_offset != self.max_number_of_nodes {
             return Err(SMTError::Unknown(format!(
                 "bin_offset {} expected {}",
@@ -1036,8 +1678,7 @@ impl SparseMerkleTree {
 
         if current_node.kind == NodeKind::Empty {
             if !info.value_bins[idx][0].is_empty() {
-                let new_leaf =
-                    Node::new_leaf(&KVPair::new(info.key_bins[idx][0], info.value_bins[idx][0]));
+                let new_leaf = Node::new_leaf(info.key_bins[idx][0], info.value_bins[idx][0]);
                 return Ok(Some((Arc::new(Mutex::new(new_leaf)), info.structure_pos)));
             }
             return Ok(Some((Arc::clone(&info.current_node), info.structure_pos)));
@@ -1047,8 +1688,7 @@ impl SparseMerkleTree {
             && utils::is_bytes_equal(&current_node.key, info.key_bins[idx][0])
         {
             if !info.value_bins[idx][0].is_empty() {
-                let new_leaf =
-                    Node::new_leaf(&KVPair::new(info.key_bins[idx][0], info.value_bins[idx][0]));
+                let new_leaf = Node::new_leaf(info.key_bins[idx][0], info.value_bins[idx][0]);
                 return Ok(Some((Arc::new(Mutex::new(new_leaf)), info.structure_pos)));
             }
             return Ok(Some((
@@ -1068,15 +1708,12 @@ impl SparseMerkleTree {
         let current_node_kind = info.current_node.lock().unwrap().kind.clone();
         let btm_subtree = match current_node_kind {
             NodeKind::Stub => {
-                let subtree =
-                    self.get_subtree(db, info.current_node.lock().unwrap().hash.value())?;
-                db.del(info.current_node.lock().unwrap().hash.value())
+                let subtree = self.get_subtree(db, &info.current_node.lock().unwrap().hash)?;
+                db.del(&info.current_node.lock().unwrap().hash)
                     .map_err(|err| SMTError::Unknown(err.to_string()))?;
                 subtree
             },
-            NodeKind::Empty => {
-                self.get_subtree(db, info.current_node.lock().unwrap().hash.value())?
-            },
+            NodeKind::Empty => self.get_subtree(db, &info.current_node.lock().unwrap().hash)?,
             NodeKind::Leaf => SubTree::from_data(&[0], &[Arc::clone(&info.current_node)])?,
             _ => {
                 return Err(SMTError::Unknown(String::from("invalid node type")));
@@ -1091,11 +1728,12 @@ impl SparseMerkleTree {
             &info.value_bins[0],
             &btm_subtree,
             info.height + info.structure_pos.into(),
+            None,
         )?;
         if new_subtree.nodes.len() == 1 {
             return Ok((Arc::clone(&new_subtree.nodes[0]), info.structure_pos));
         }
-        let new_branch = Node::new_stub(&new_subtree.root);
+        let new_branch = Node::new_stub(&new_subtree.root)?;
 
         Ok((Arc::new(Mutex::new(new_branch)), info.structure_pos))
     }
@@ -1227,7 +1865,7 @@ impl SparseMerkleTree {
 
     fn calculate_query_proof_from_result(
         &mut self,
-        db: &mut impl Actions,
+        db: &impl Actions,
         d: &GenerateResultData,
     ) -> Result<QueryProofWithProof, SMTError> {
         let mut ancestor_hashes = d.query_hashes.ancestor_hashes.clone();
@@ -1244,12 +1882,8 @@ impl SparseMerkleTree {
         }
 
         if d.current_node.kind == NodeKind::Leaf {
-            ancestor_hashes.push_back(d.current_node.hash.value_as_vec());
-            let key_length: usize = self.key_length.into();
-            let pair = Arc::new(KVPair::new(
-                &d.current_node.key,
-                &d.current_node.hash.key()[[PREFIX_SUB_TREE_LEAF].len() + key_length..],
-            ));
+            ancestor_hashes.push_back(d.current_node.hash.to_vec());
+            let pair = Arc::new(KVPair::new(&d.current_node.key, &d.current_node.value));
             return Ok(QueryProofWithProof::new_with_pair(
                 pair,
                 // 0 index is the leaf prefix
@@ -1259,7 +1893,7 @@ impl SparseMerkleTree {
             ));
         }
 
-        let mut lower_subtree = self.get_subtree(db, d.current_node.hash.value())?;
+        let mut lower_subtree = self.get_subtree(db, &d.current_node.hash)?;
         let lower_query_proof = self.generate_query_proof(
             db,
             &mut lower_subtree,
@@ -1301,7 +1935,7 @@ impl SparseMerkleTree {
     /// generate_query_proof creates proof for single query according to the [LIP-0039](https://github.com/LiskHQ/lips/blob/main/proposals/lip-0039.md#proof-construction).
     fn generate_query_proof(
         &mut self,
-        db: &mut impl Actions,
+        db: &impl Actions,
         current_subtree: &mut SubTree,
         query_key: &[u8],
         height: Height,
@@ -1439,14 +2073,11 @@ impl SparseMerkleTree {
             }
             let d = query.binary_key()[query.height() - 1];
             let mut next_query = query.clone();
+            let sibling_hash = sibling_hash.unwrap();
             if !d {
-                next_query.hash = [query.hash.as_slice(), sibling_hash.unwrap().as_slice()]
-                    .concat()
-                    .hash_with_kind(HashKind::Branch);
+                next_query.hash = hash_branch(&query.hash, &sibling_hash);
             } else {
-                next_query.hash = [sibling_hash.unwrap().as_slice(), query.hash.as_slice()]
-                    .concat()
-                    .hash_with_kind(HashKind::Branch);
+                next_query.hash = hash_branch(&sibling_hash, &query.hash);
             }
             next_query.slice_bitmap();
             insert_and_filter_queries(next_query, &mut sorted_queries);
@@ -1468,21 +2099,71 @@ impl SparseMerkleTree {
             key_length,
             subtree_height,
             max_number_of_nodes,
+            cancel: None,
+            metrics: metrics::Instance::UNTAGGED,
         }
     }
 
-    /// commit updates the db with key-value pairs based on [LIP-0039](https://github.com/LiskHQ/lips/blob/main/proposals/lip-0039.md#root-hash-calculation) with SubTree optimization.
-    /// Nodes are batched to "SubTree" for defined height N (4 or 8) to reduce DB call with trade-off of # of hashes.
-    /// all the keys for the data must be unique and have the same length.
-    pub fn commit(
-        &mut self,
-        db: &mut impl Actions,
-        data: &UpdateData,
+    /// root returns the current root of the tree, which is updated by each commit.
+    pub fn root(&self) -> SharedVec {
+        Arc::clone(&self.root)
+    }
+
+    /// with_metrics tags the metrics of the commits and the proofs of the tree with the instance.
+    pub fn with_metrics(mut self, instance: metrics::Instance) -> Self {
+        self.metrics = instance;
+        self
+    }
+
+    /// with_cancel sets the flag checked before each query of the proofs and each SubTree of the bulk load.
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// check_cancel returns SMTError::Aborted if the cancel flag is set.
+    fn check_cancel(&self) -> Result<(), SMTError> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(SMTError::Aborted),
+            _ => Ok(()),
+        }
+    }
+
+    /// commit updates the db with key-value pairs based on [LIP-0039](https://github.com/LiskHQ/lips/blob/main/proposals/lip-0039.md#root-hash-calculation) with SubTree optimization.
+    /// Nodes are batched to "SubTree" for defined height N (4 or 8) to reduce DB call with trade-off of # of hashes.
+    /// all the keys for the data must be unique and have the same length.
+    pub fn commit(
+        &mut self,
+        db: &mut impl Actions,
+        data: &UpdateData,
+    ) -> Result<SharedVec, SMTError> {
+        self.commit_with_optional_hook(db, data, None)
+    }
+
+    /// commit_with_hook is the same as commit, but reports the progress to the hook and stops once it is cancelled.
+    /// The nodes written before the cancellation are left in the db, so the caller should stage the writes
+    /// and discard them when SMTError::Aborted is returned.
+    pub fn commit_with_hook(
+        &mut self,
+        db: &mut impl Actions,
+        data: &UpdateData,
+        hook: &mut CommitHook,
+    ) -> Result<SharedVec, SMTError> {
+        self.commit_with_optional_hook(db, data, Some(hook))
+    }
+
+    fn commit_with_optional_hook(
+        &mut self,
+        db: &mut impl Actions,
+        data: &UpdateData,
+        hook: Option<&mut CommitHook>,
     ) -> Result<SharedVec, SMTError> {
         if data.is_empty() {
             return Ok(Arc::clone(&self.root));
         }
+        let _scope = metrics::Scope::start(self.metrics, Histogram::CommitLatency);
         let (update_keys, update_values) = data.entries();
+        let _span = tracing::debug_span!("smt_commit", keys = update_keys.len()).entered();
         // check if all keys have the same length
         if !utils::have_all_arrays_same_length(&update_keys, self.key_length.into()) {
             return Err(SMTError::InvalidInput(String::from(
@@ -1492,41 +2173,265 @@ impl SparseMerkleTree {
         // get the root subtree
         let root = self.get_subtree(db, &self.root.lock().unwrap())?;
         // update using the key-value pairs starting from the root (height: 0).
-        let new_root = self.update_subtree(db, &update_keys, &update_values, &root, Height(0))?;
+        let new_root =
+            self.update_subtree(db, &update_keys, &update_values, &root, Height(0), hook)?;
+        self.root = Arc::new(Mutex::new(new_root.root));
+        Ok(Arc::clone(&self.root))
+    }
+
+    /// bulk_load builds the tree from key-value pairs sorted by key in ascending order.
+    /// Unlike commit, SubTrees are constructed bottom-up without reading the existing nodes, therefore the tree must be empty.
+    /// Pairs with an empty value are skipped.
+    pub fn bulk_load(
+        &mut self,
+        db: &mut impl Actions,
+        sorted_iter: impl IntoIterator<Item = KVPair>,
+    ) -> Result<SharedVec, SMTError> {
+        let mut loader = BulkLoader::new(self.key_length);
+        loader.add(sorted_iter)?;
+        self.finish_bulk_load(db, loader)
+    }
+
+    /// finish_bulk_load builds the tree from the pairs collected by the loader in the same way as bulk_load.
+    pub fn finish_bulk_load(
+        &mut self,
+        db: &mut impl Actions,
+        loader: BulkLoader,
+    ) -> Result<SharedVec, SMTError> {
+        if !utils::is_empty_hash(&self.root.lock().unwrap()) {
+            return Err(SMTError::InvalidInput(String::from(
+                "bulk load requires an empty tree",
+            )));
+        }
+        let pairs = loader.pairs;
+        if pairs.is_empty() {
+            return Ok(Arc::clone(&self.root));
+        }
+        let _span = tracing::debug_span!("smt_bulk_load", keys = pairs.len()).entered();
+        let keys: Vec<&[u8]> = pairs.iter().map(|p| p.key()).collect();
+        let values: Vec<&[u8]> = pairs.iter().map(|p| p.value()).collect();
+        let new_root = self.build_subtree(db, &keys, &values, Height(0))?;
         self.root = Arc::new(Mutex::new(new_root.root));
         Ok(Arc::clone(&self.root))
     }
 
+    /// build_subtree creates and stores the SubTree for the sorted keys starting from the height.
+    fn build_subtree(
+        &self,
+        db: &mut impl Actions,
+        keys: &[&[u8]],
+        values: &[&[u8]],
+        height: Height,
+    ) -> Result<SubTree, SMTError> {
+        let subtree = self.assemble_subtree(db, keys, values, height)?;
+        db.set(&KVPair::new(&subtree.root, &subtree.encode()))
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+
+        Ok(subtree)
+    }
+
+    /// assemble_subtree creates the SubTree for the sorted keys starting from the height.
+    /// The SubTrees below it are stored, but the SubTree itself is not.
+    fn assemble_subtree(
+        &self,
+        db: &mut impl Actions,
+        keys: &[&[u8]],
+        values: &[&[u8]],
+        height: Height,
+    ) -> Result<SubTree, SMTError> {
+        self.check_cancel()?;
+        let (nodes, structure) =
+            self.build_node(db, keys, values, height, StructurePosition(0))?;
+        let max_structure = structure
+            .iter()
+            .max()
+            .ok_or_else(|| SMTError::Unknown(String::from("Invalid structure")))?;
+        let mut tree_map = VecDeque::new();
+        calculate_subtree(&nodes, &structure, max_structure.into(), &mut tree_map)
+    }
+
+    /// build_node splits the sorted keys by the bit at the position and returns the nodes below.
+    fn build_node(
+        &self,
+        db: &mut impl Actions,
+        keys: &[&[u8]],
+        values: &[&[u8]],
+        height: Height,
+        structure_pos: StructurePosition,
+    ) -> Result<(Vec<SharedNode>, Vec<u8>), SMTError> {
+        if keys.is_empty() {
+            return Ok((
+                vec![Arc::new(Mutex::new(Node::new_empty()))],
+                vec![structure_pos.into()],
+            ));
+        }
+        if keys.len() == 1 {
+            let leaf = Node::new_leaf(keys[0], values[0]);
+            return Ok((vec![Arc::new(Mutex::new(leaf))], vec![structure_pos.into()]));
+        }
+        if structure_pos == self.subtree_height.into() {
+            let lower_subtree =
+                self.assemble_subtree(db, keys, values, height + structure_pos.into())?;
+            // the SubTree with a single node is collapsed into this one, so it is not stored
+            if lower_subtree.nodes.len() == 1 {
+                return Ok((
+                    vec![Arc::clone(&lower_subtree.nodes[0])],
+                    vec![structure_pos.into()],
+                ));
+            }
+            db.set(&KVPair::new(&lower_subtree.root, &lower_subtree.encode()))
+                .map_err(|err| SMTError::Unknown(err.to_string()))?;
+            let stub = Node::new_stub(&lower_subtree.root)?;
+            return Ok((vec![Arc::new(Mutex::new(stub))], vec![structure_pos.into()]));
+        }
+        let bit: usize = (height + structure_pos.into()).into();
+        let idx = keys.partition_point(|k| !utils::is_bit_set(k, bit));
+        let (mut nodes, mut structure) = self.build_node(
+            db,
+            &keys[..idx],
+            &values[..idx],
+            height,
+            structure_pos + StructurePosition(1),
+        )?;
+        let (right_nodes, right_structure) = self.build_node(
+            db,
+            &keys[idx..],
+            &values[idx..],
+            height,
+            structure_pos + StructurePosition(1),
+        )?;
+        nodes.extend(right_nodes);
+        structure.extend(right_structure);
+
+        Ok((nodes, structure))
+    }
+
     /// prove returns multi-proof based on the queries.
     /// proof can be inclusion or non-inclusion proof. In case of non-inclusion proof, it will be prove the query key is empty in the tree.
-    pub fn prove(
+    pub fn prove(&mut self, db: &impl Actions, queries: &[Vec<u8>]) -> Result<Proof, SMTError> {
+        let _scope = metrics::Scope::start(self.metrics, Histogram::ProveLatency);
+        let _span = tracing::debug_span!("smt_prove", queries = queries.len()).entered();
+        if queries.is_empty() {
+            return Ok(Proof {
+                queries: vec![],
+                sibling_hashes: vec![],
+            });
+        }
+        let (query_with_proofs, ancestor_hashes) = self.generate_sibling_data(db, queries)?;
+
+        Ok(self.merge_query_proofs(query_with_proofs, &ancestor_hashes))
+    }
+
+    /// prove_parallel returns the same multi-proof as prove.
+    /// For a large number of queries, the proofs of the queries in different bins of the root subtree are generated in parallel.
+    pub fn prove_parallel(
         &mut self,
-        db: &mut impl Actions,
+        db: &(impl Actions + Sync),
         queries: &[Vec<u8>],
     ) -> Result<Proof, SMTError> {
+        let _scope = metrics::Scope::start(self.metrics, Histogram::ProveLatency);
+        let _span = tracing::debug_span!("smt_prove", queries = queries.len()).entered();
         if queries.is_empty() {
             return Ok(Proof {
                 queries: vec![],
                 sibling_hashes: vec![],
             });
         }
-        let (mut query_with_proofs, ancestor_hashes) = self.generate_sibling_data(db, queries)?;
+        let (query_with_proofs, ancestor_hashes) =
+            self.generate_sibling_data_parallel(db, queries)?;
+
+        Ok(self.merge_query_proofs(query_with_proofs, &ancestor_hashes))
+    }
+
+    /// prove_many returns the multi-proof of each set of the queries, which is the same as the proof by prove for the set.
+    /// The queries of all the sets are proven with a single traversal of the tree, so the subtrees shared by the sets are read once.
+    pub fn prove_many(
+        &mut self,
+        db: &(impl Actions + Sync),
+        query_sets: &[NestedVec],
+    ) -> Result<Vec<Proof>, SMTError> {
+        let _scope = metrics::Scope::start(self.metrics, Histogram::ProveLatency);
+        let _span = tracing::debug_span!("smt_prove_many", sets = query_sets.len()).entered();
+        let mut queries: NestedVec = vec![];
+        let mut indexes: HashMap<&[u8], usize> = HashMap::new();
+        for query in query_sets.iter().flatten() {
+            if !indexes.contains_key(query.as_slice()) {
+                indexes.insert(query, queries.len());
+                queries.push(query.clone());
+            }
+        }
+        let (query_with_proofs, _) = match queries.is_empty() {
+            true => (vec![], vec![]),
+            false => self.generate_sibling_data_parallel(db, &queries)?,
+        };
+
+        let mut proofs = Vec::with_capacity(query_sets.len());
+        for query_set in query_sets.iter() {
+            let mut set_proofs = Vec::with_capacity(query_set.len());
+            let mut ancestor_hashes = vec![];
+            for query in query_set.iter() {
+                let query_proof = &query_with_proofs[indexes[query.as_slice()]];
+                ancestor_hashes.extend(query_proof.ancestor_hashes.iter().cloned());
+                set_proofs.push(query_proof.clone());
+            }
+            proofs.push(self.merge_query_proofs(set_proofs, &ancestor_hashes));
+        }
+
+        Ok(proofs)
+    }
+
+    /// get returns the value hash of the leaf of the key, or None if the key is not in the tree.
+    /// The subtrees are not read for the key which is ruled out by the filter of the root.
+    pub fn get(
+        &mut self,
+        db: &impl Actions,
+        key: &[u8],
+        filter: Option<&BloomFilter>,
+    ) -> Result<Option<Vec<u8>>, SMTError> {
+        if key.len() != self.key_length.into() {
+            return Err(SMTError::InvalidInput(String::from(
+                "Query key length must be equal to key length",
+            )));
+        }
+        if filter.is_some_and(|filter| !filter.contains(key)) {
+            return Ok(None);
+        }
+        let root = Arc::clone(&self.root.lock().unwrap());
+        let mut subtree = self.get_subtree(db, &root)?;
+        let mut height = Height(0);
+        loop {
+            let (node, query_height) = self.find_current_node(&subtree, key, height)?;
+            let node = node.lock().unwrap().clone();
+            match node.kind {
+                NodeKind::Stub => {
+                    subtree = self.get_subtree(db, &node.hash)?;
+                    height = height + query_height;
+                },
+                NodeKind::Leaf if node.key.as_slice() == key => {
+                    return Ok(Some(node.value.to_vec()));
+                },
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    fn merge_query_proofs(
+        &self,
+        mut query_with_proofs: Vec<QueryProofWithProof>,
+        ancestor_hashes: &[Vec<u8>],
+    ) -> Proof {
         let proof_queries = self.get_proof_queries(&query_with_proofs);
 
         query_with_proofs.sort_descending();
 
         let mut sibling_hashes = vec![];
         let mut query_with_proofs = VecDeque::from(query_with_proofs);
-        calculate_sibling_hashes(
-            &mut query_with_proofs,
-            &ancestor_hashes,
-            &mut sibling_hashes,
-        );
+        calculate_sibling_hashes(&mut query_with_proofs, ancestor_hashes, &mut sibling_hashes);
 
-        Ok(Proof {
+        Proof {
             queries: proof_queries,
             sibling_hashes,
-        })
+        }
     }
 
     /// verify checks if the provided proof is valid or not against the provided root.
@@ -1551,23 +2456,495 @@ impl SparseMerkleTree {
         if !Self::verify_query_keys(proof, query_keys, key_length) {
             return Ok(false);
         }
-        let filter_map = Self::prepare_queries_with_proof_map(proof)?;
-        let mut filtered_proof = filter_map
-            .values()
-            .cloned()
-            .collect::<Vec<QueryProofWithProof>>();
+        let queries = proof
+            .queries
+            .iter()
+            .map(VerifyQuery::new)
+            .collect::<Result<Vec<VerifyQuery>, SMTError>>()?;
 
-        match SparseMerkleTree::calculate_root(&proof.sibling_hashes, &mut filtered_proof) {
+        match SparseMerkleTree::calculate_root_by_layer(&proof.sibling_hashes, queries) {
             Ok(computed_root) => Ok(utils::is_bytes_equal(root, &computed_root)),
             Err(_) => Ok(false),
         }
     }
+
+    /// calculate_root_by_layer calculates the same root as calculate_root for the verification.
+    /// The queries are moved up the tree one layer at a time, in the order of the keys, between two buffers
+    /// allocated once, instead of being cloned and inserted back to the sorted queue for each level.
+    fn calculate_root_by_layer(
+        sibling_hashes: &[Vec<u8>],
+        mut queries: Vec<VerifyQuery>,
+    ) -> Result<Vec<u8>, SMTError> {
+        // the queries are popped from the highest
+        queries.sort_by_key(|query| query.height());
+        let mut height = match queries.last() {
+            Some(query) => query.height(),
+            None => return Ok(vec![]),
+        };
+        let mut layer: Vec<VerifyQuery> = Vec::with_capacity(queries.len());
+        let mut next_layer = Vec::with_capacity(queries.len());
+        let mut next_sibling_hash = 0;
+
+        loop {
+            while queries.last().is_some_and(|query| query.height() == height) {
+                layer.push(queries.pop().unwrap());
+            }
+            layer.sort_by(|a, b| a.key.cmp(b.key));
+            layer.dedup_by(|a, b| a.has_common_path(b, height));
+            if height == 0 {
+                if next_sibling_hash != sibling_hashes.len() {
+                    return Err(SMTError::InvalidInput(String::from(
+                        "Not all sibling hashes were used",
+                    )));
+                }
+                return Ok(layer[0].hash.to_vec());
+            }
+
+            let mut layer_queries = layer.drain(..).peekable();
+            while let Some(mut query) = layer_queries.next() {
+                let sibling_query;
+                let sibling_hash: &[u8] = if let Some(sibling) =
+                    layer_queries.next_if(|next| query.is_sibling_of(next))
+                {
+                    // We are merging two branches.
+                    // Check that the bitmap at the merging point is consistent with the nodes type.
+                    if !query.is_bitmap_consistent(&sibling)
+                        || !sibling.is_bitmap_consistent(&query)
+                    {
+                        return Err(SMTError::InvalidInput(String::from(
+                            "bitmap is not consistent with the nodes type",
+                        )));
+                    }
+                    if query.bitmap[query.offset + 1..] != sibling.bitmap[sibling.offset + 1..] {
+                        return Err(SMTError::InvalidInput(String::from(
+                            "nodes do not share common path",
+                        )));
+                    }
+                    sibling_query = sibling;
+                    &sibling_query.hash[..]
+                } else if !query.bitmap[query.offset] {
+                    &EMPTY_HASH[..]
+                } else {
+                    let hash = sibling_hashes.get(next_sibling_hash).ok_or_else(|| {
+                        SMTError::InvalidInput(String::from("no more sibling hashes available"))
+                    })?;
+                    next_sibling_hash += 1;
+                    hash.as_slice()
+                };
+                query.hash = if !query.is_bit_set(height) {
+                    hash_branch_array(&query.hash, sibling_hash)
+                } else {
+                    hash_branch_array(sibling_hash, &query.hash)
+                };
+                query.offset += 1;
+                next_layer.push(query);
+            }
+            drop(layer_queries);
+            std::mem::swap(&mut layer, &mut next_layer);
+            height -= 1;
+        }
+    }
+
+    /// check_consistency walks all the subtrees reachable from the root and re-hashes them.
+    /// It returns hashes of the nodes which are missing, cannot be decoded or whose stored encoding does not match the hash.
+    pub fn check_consistency(
+        &self,
+        db: &impl Actions,
+        root: &[u8],
+    ) -> Result<NestedVec, SMTError> {
+        let mut inconsistent_nodes = vec![];
+        let mut node_hashes = vec![root.to_vec()];
+        while let Some(node_hash) = node_hashes.pop() {
+            if node_hash.is_empty() || utils::is_empty_hash(&node_hash) {
+                continue;
+            }
+            let value = db
+                .get(&node_hash)
+                .map_err(|err| SMTError::Unknown(err.to_string()))?;
+            let subtree = match value.map(|v| SubTree::new(&v, self.key_length)) {
+                Some(Ok(subtree)) => subtree,
+                _ => {
+                    inconsistent_nodes.push(node_hash);
+                    continue;
+                },
+            };
+            if !utils::is_bytes_equal(&subtree.root, &node_hash) {
+                inconsistent_nodes.push(node_hash);
+                continue;
+            }
+            for node in subtree.nodes.iter() {
+                let node = node.lock().unwrap();
+                if node.kind == NodeKind::Stub {
+                    node_hashes.push(node.hash.to_vec());
+                }
+            }
+        }
+
+        Ok(inconsistent_nodes)
+    }
+
+    /// leaves returns all the key-value pairs of the tree sorted by key in ascending order.
+    pub fn leaves(&self, db: &impl Actions) -> Result<Vec<KVPair>, SMTError> {
+        let mut pairs = vec![];
+        let mut node_hashes = vec![self.root.lock().unwrap().to_vec()];
+        while let Some(node_hash) = node_hashes.pop() {
+            if node_hash.is_empty() || utils::is_empty_hash(&node_hash) {
+                continue;
+            }
+            let value = db
+                .get(&node_hash)
+                .map_err(|err| SMTError::Unknown(err.to_string()))?
+                .ok_or_else(|| SMTError::NotFound(hex::encode(&node_hash)))?;
+            let subtree = SubTree::new(&value, self.key_length)?;
+            for node in subtree.nodes.iter() {
+                let node = node.lock().unwrap();
+                match node.kind {
+                    NodeKind::Stub => node_hashes.push(node.hash.to_vec()),
+                    NodeKind::Leaf => {
+                        pairs.push(KVPair::new(&node.key, &node.value));
+                    },
+                    _ => {},
+                }
+            }
+        }
+        pairs.sort_by(|a, b| utils::compare(a.key(), b.key()));
+
+        Ok(pairs)
+    }
+
+    /// leaf_walk returns the walk visiting the leaves of the tree one by one in the order of the key.
+    pub fn leaf_walk<'a, T: Actions>(&self, db: &'a T) -> LeafWalk<'a, T> {
+        LeafWalk {
+            db,
+            key_length: self.key_length,
+            stack: vec![WalkItem::Subtree(self.root.lock().unwrap().to_vec())],
+            path: HashMap::new(),
+        }
+    }
+
+    /// dump returns all the nodes of the tree in pre-order, visiting the left child first.
+    /// The result is deterministic for the root, so that the dumps of different implementations can be compared.
+    pub fn dump(&self, db: &impl Actions) -> Result<Vec<DumpNode>, SMTError> {
+        let mut nodes = vec![];
+        let root = self.root.lock().unwrap().to_vec();
+        self.dump_subtree(db, &root, 0, &mut nodes)?;
+
+        Ok(nodes)
+    }
+
+    fn dump_subtree(
+        &self,
+        db: &impl Actions,
+        node_hash: &[u8],
+        height: usize,
+        nodes: &mut Vec<DumpNode>,
+    ) -> Result<(), SMTError> {
+        let subtree = self.get_subtree(db, node_hash)?;
+        let subtree_height = self.subtree_height.u16() as usize;
+        let ranges = subtree_ranges(&subtree, subtree_height);
+        self.dump_range(db, &ranges, (0, 1 << subtree_height), height, nodes)
+    }
+
+    fn dump_range(
+        &self,
+        db: &impl Actions,
+        ranges: &[(usize, usize, SharedNode)],
+        (lo, size): (usize, usize),
+        height: usize,
+        nodes: &mut Vec<DumpNode>,
+    ) -> Result<(), SMTError> {
+        let exact = ranges
+            .iter()
+            .find(|(start, len, _)| *start == lo && *len == size);
+        if let Some((_, _, node)) = exact {
+            let node = node.lock().unwrap();
+            match node.kind {
+                NodeKind::Stub => {
+                    return self.dump_subtree(db, &node.hash, height, nodes);
+                },
+                NodeKind::Leaf => {
+                    nodes.push(DumpNode {
+                        kind: DumpNodeKind::Leaf,
+                        height,
+                        hash: node.hash.to_vec(),
+                        key: node.key.to_vec(),
+                        value: node.value.to_vec(),
+                    });
+                },
+                _ => nodes.push(DumpNode {
+                    kind: DumpNodeKind::Empty,
+                    height,
+                    hash: EMPTY_HASH.to_vec(),
+                    key: vec![],
+                    value: vec![],
+                }),
+            }
+            return Ok(());
+        }
+        nodes.push(DumpNode {
+            kind: DumpNodeKind::Branch,
+            height,
+            hash: range_hash(ranges, lo, size),
+            key: vec![],
+            value: vec![],
+        });
+        let half = size / 2;
+        self.dump_range(db, ranges, (lo, half), height + 1, nodes)?;
+        self.dump_range(db, ranges, (lo + half, half), height + 1, nodes)
+    }
+
+    /// prove_prefix creates the proof of the subtree containing all the keys starting with the prefix,
+    /// so that the commitment of a module or a store can be extracted from the root.
+    pub fn prove_prefix(&self, db: &impl Actions, prefix: &[u8]) -> Result<PrefixProof, SMTError> {
+        if prefix.len() > self.key_length.into() {
+            return Err(SMTError::InvalidInput(String::from(
+                "prefix must not be longer than the key length",
+            )));
+        }
+        let path = utils::bytes_to_bools(prefix);
+        let height = self.subtree_height.u16() as usize;
+        let mut sibling_hashes = vec![];
+        let mut subtree = self.get_subtree(db, &self.root.lock().unwrap())?;
+        let proof = |node: PrefixNode, sibling_hashes: NestedVec| PrefixProof {
+            prefix: prefix.to_vec(),
+            node,
+            sibling_hashes,
+        };
+        loop {
+            let ranges = subtree_ranges(&subtree, height);
+            let (mut lo, mut size) = (0, 1 << height);
+            let next_root = loop {
+                let covering = ranges
+                    .iter()
+                    .find(|(start, len, _)| *start <= lo && lo + size <= start + len);
+                if let Some((_, _, node)) = covering {
+                    let node = node.lock().unwrap();
+                    match node.kind {
+                        NodeKind::Empty => return Ok(proof(PrefixNode::Empty, sibling_hashes)),
+                        NodeKind::Leaf => {
+                            let leaf = PrefixNode::Leaf {
+                                key: node.key.to_vec(),
+                                value: node.value.to_vec(),
+                            };
+                            return Ok(proof(leaf, sibling_hashes));
+                        },
+                        _ => {
+                            if sibling_hashes.len() == path.len() {
+                                let branch = PrefixNode::Branch(node.hash.to_vec());
+                                return Ok(proof(branch, sibling_hashes));
+                            }
+                            break node.hash.to_vec();
+                        },
+                    }
+                }
+                if sibling_hashes.len() == path.len() {
+                    let branch = PrefixNode::Branch(range_hash(&ranges, lo, size));
+                    return Ok(proof(branch, sibling_hashes));
+                }
+                size /= 2;
+                if path[sibling_hashes.len()] {
+                    sibling_hashes.push(range_hash(&ranges, lo, size));
+                    lo += size;
+                } else {
+                    sibling_hashes.push(range_hash(&ranges, lo + size, size));
+                }
+            };
+            subtree = self.get_subtree(db, &next_root)?;
+        }
+    }
+
+    /// verify_prefix checks the proof created by prove_prefix against the root.
+    pub fn verify_prefix(
+        root: &[u8],
+        proof: &PrefixProof,
+        key_length: KeyLength,
+    ) -> Result<bool, SMTError> {
+        if proof.prefix.len() > key_length.into() {
+            return Ok(false);
+        }
+        let path = utils::bytes_to_bools(&proof.prefix);
+        let depth = proof.sibling_hashes.len();
+        if depth > path.len() || proof.sibling_hashes.iter().any(|h| h.len() != HASH_SIZE) {
+            return Ok(false);
+        }
+        match &proof.node {
+            PrefixNode::Branch(hash) if depth != path.len() || hash.len() != HASH_SIZE => {
+                return Ok(false);
+            },
+            PrefixNode::Leaf { key, .. } => {
+                if key.len() != key_length.into() {
+                    return Ok(false);
+                }
+                // the leaf must be on the path to the prefix
+                if utils::bytes_to_bools(key)[..depth] != path[..depth] {
+                    return Ok(false);
+                }
+            },
+            _ => {},
+        }
+        let mut hash = proof.node_hash();
+        for (sibling_hash, is_right) in proof.sibling_hashes.iter().zip(path.iter()).rev() {
+            hash = if *is_right {
+                hash_branch(sibling_hash, &hash)
+            } else {
+                hash_branch(&hash, sibling_hash)
+            };
+        }
+
+        Ok(utils::is_bytes_equal(root, &hash))
+    }
+}
+
+/// WalkItem is the next item to visit in LeafWalk.
+enum WalkItem {
+    Subtree(Vec<u8>),
+    Leaf(KVPair),
+    /// Done is visited after all the nodes of the subtree, when the subtree leaves the path.
+    Done(Vec<u8>),
+}
+
+/// LeafWalk visits the leaves of the tree in the order of the key, reading one subtree at a time.
+/// Only the subtrees on the path to the last visited leaf are kept, and they are served by get before the db,
+/// so the proof of the visited leaves reads the path from memory.
+pub struct LeafWalk<'a, T: Actions> {
+    db: &'a T,
+    key_length: KeyLength,
+    stack: Vec<WalkItem>,
+    path: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl<T: Actions> LeafWalk<'_, T> {
+    /// next_leaf returns the next leaf, or None after the last leaf.
+    pub fn next_leaf(&mut self) -> Result<Option<KVPair>, SMTError> {
+        while let Some(item) = self.stack.pop() {
+            let node_hash = match item {
+                WalkItem::Leaf(pair) => return Ok(Some(pair)),
+                WalkItem::Done(node_hash) => {
+                    self.path.remove(&node_hash);
+                    continue;
+                },
+                WalkItem::Subtree(node_hash) => node_hash,
+            };
+            if node_hash.is_empty() || utils::is_empty_hash(&node_hash) {
+                continue;
+            }
+            let value = self
+                .db
+                .get(&node_hash)
+                .map_err(|err| SMTError::Unknown(err.to_string()))?
+                .ok_or_else(|| SMTError::NotFound(hex::encode(&node_hash)))?;
+            let subtree = SubTree::new(&value, self.key_length)?;
+            self.stack.push(WalkItem::Done(node_hash.clone()));
+            // the nodes are pushed in reverse, so the leftmost node is visited first
+            for node in subtree.nodes.iter().rev() {
+                let node = node.lock().unwrap();
+                match node.kind {
+                    NodeKind::Stub => self.stack.push(WalkItem::Subtree(node.hash.to_vec())),
+                    NodeKind::Leaf => {
+                        self.stack
+                            .push(WalkItem::Leaf(KVPair::new(&node.key, &node.value)));
+                    },
+                    _ => {},
+                }
+            }
+            self.path.insert(node_hash, value);
+        }
+
+        Ok(None)
+    }
+}
+
+impl<T: Actions> Actions for LeafWalk<'_, T> {
+    type Error = T::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
+        match self.path.get(key) {
+            Some(value) => Ok(Some(value.clone())),
+            None => self.db.get(key),
+        }
+    }
+
+    fn set(&mut self, _pair: &KVPair) -> Result<(), Self::Error> {
+        unreachable!("LeafWalk is read only")
+    }
+
+    fn del(&mut self, _key: &[u8]) -> Result<(), Self::Error> {
+        unreachable!("LeafWalk is read only")
+    }
+}
+
+/// ProofHelpers is the number of the threads taken from MAX_PROOF_HELPERS by prove_parallel.
+/// They are given back when it is dropped, so the threads proving at the same time stay bounded however many
+/// callers prove in parallel, and a caller proves all the partitions by itself when no thread is left.
+struct ProofHelpers {
+    count: usize,
+}
+
+impl ProofHelpers {
+    fn acquire(wanted: usize) -> Self {
+        let mut current = PROOF_HELPERS.load(Ordering::Relaxed);
+        loop {
+            let count = cmp::min(wanted, MAX_PROOF_HELPERS.saturating_sub(current));
+            match PROOF_HELPERS.compare_exchange_weak(
+                current,
+                current + count,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Self { count },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl Drop for ProofHelpers {
+    fn drop(&mut self) {
+        PROOF_HELPERS.fetch_sub(self.count, Ordering::AcqRel);
+    }
+}
+
+/// subtree_ranges returns the range of the bins covered by each node of the subtree as (start, size, node).
+fn subtree_ranges(subtree: &SubTree, height: usize) -> Vec<(usize, usize, SharedNode)> {
+    let mut ranges = Vec::with_capacity(subtree.nodes.len());
+    let mut start = 0;
+    for (node, structure) in subtree.nodes.iter().zip(subtree.structure.iter()) {
+        let size = 1 << (height - *structure as usize);
+        ranges.push((start, size, Arc::clone(node)));
+        start += size;
+    }
+    ranges
+}
+
+/// range_hash returns the hash of the bins from lo to lo + size in the subtree, where the ranges cover all the bins.
+fn range_hash(ranges: &[(usize, usize, SharedNode)], lo: usize, size: usize) -> Vec<u8> {
+    if let Some((_, _, node)) = ranges
+        .iter()
+        .find(|(start, len, _)| *start == lo && *len == size)
+    {
+        return node.lock().unwrap().hash.to_vec();
+    }
+    let half = size / 2;
+    let left = range_hash(ranges, lo, half);
+    let right = range_hash(ranges, lo + half, half);
+    hash_branch(&left, &right)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sparse_merkle_tree::smt_db;
+    use crate::types::Cache;
+
+    #[test]
+    fn test_smt_error_code() {
+        let err = SMTError::InvalidRoot(String::from("Not matching with expected"));
+        assert_eq!(err.code(), error_code::INVALID_ROOT);
+        let err = SMTError::NotFound(String::from("node_hash does not exist"));
+        assert_eq!(err.code(), error_code::NOT_FOUND);
+        assert_eq!(SMTError::InvalidBitmapLen.code(), error_code::INVALID_INPUT);
+        assert_eq!(SMTError::Aborted.code(), error_code::ABORTED);
+    }
 
     #[test]
     fn test_subtree() {
@@ -1593,17 +2970,118 @@ mod tests {
             ("0f0404040404040404040404040404040401bbacc7102a28f2eecd0e4de3c130064e653d0118b1dc4129095901f190e70034019dcb747007aca526d4b0782ed20a88a5d48a4ab6276378bada201ab5b6e4d75b01e89b7270dd0ad80207e11422bfc28f8cda8932d59b1082486fa1bf5626ea0aba01858c61150861b89516244e07cfd9d3ebcb12b2d44c2de4e7e2faed96717202eb01f9437e84b231d85f7fc2690ed54b09e85c2e0fc98b26430f10418065374e40bf0189ae2184c9a2e70656ce37c89c903b258198ad6e9db66f135780f66d8613a6fd01058c3bef2957b130622e752f0a81ee8dcf60b4685675eb88e39d5150c954fe220161543e80c5356f580f8e7e4548576486ee754ffe22f4dd122ef48e41bffc7adc01f55a1089a16835a4cbe8b5e12227575ecfd99cd951e34b409f9b2ace6f25a49701e5dfbf3ecaf909728248a751e1a75f3b626777094fe1aab03ae6f526ddac799a01f88ad8cd4aec6cc4f8d2c2bc4a5f368fc9b877685eb55673baa01d652fa4c82b0182f8fb577797274de4f48d8bd7cc5a77068ea3c60477e8552b38c926466eba1101c149d0c79bc1355d763d01690139fd187a84488d534e7e38e4772279c3826b9b01006afab486675b0e3f9b6b06283da947df6749269fb8621afe843d5df942bce7011ead1b569f80edffa2044bf9d8b8703b970ca741b821127d6da69da83b52294f01c1a9d57b050c3ba96aca78a26c5eebc76bb51acab78ce70ed3bdea1ca9143cd8", "5a2f1f740cbea0944d5182fe8ef9190d7a07e8601d0b9fc1137d48b94ce73407", vec![4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4]),
         ];
 
-        for (data, _, _) in test_data {
-            let decoded_data = hex::decode(data).unwrap();
-            let tree = SubTree::new(&decoded_data, KeyLength(32)).unwrap();
-            assert_eq!(tree.encode(), decoded_data);
-        }
+        for (data, _, structure) in test_data {
+            let decoded_data = hex::decode(data).unwrap();
+            let tree = SubTree::new(&decoded_data, KeyLength(32)).unwrap();
+            let encoded = tree.encode();
+            assert_eq!(encoded[..2], [PREFIX_SUB_TREE_VERSION, SUB_TREE_VERSION]);
+            let decoded = SubTree::new(&encoded, KeyLength(32)).unwrap();
+            assert_eq!(decoded.structure, structure);
+            assert_eq!(decoded.root, tree.root);
+            assert_eq!(decoded.encode(), encoded);
+        }
+    }
+
+    #[test]
+    fn test_subtree_versioned_encoding() {
+        let empty = SubTree::new_empty().encode();
+        assert_eq!(
+            empty,
+            vec![PREFIX_SUB_TREE_VERSION, SUB_TREE_VERSION, 1, 0, 2]
+        );
+        assert_eq!(
+            SubTree::new(&empty, KeyLength(32)).unwrap().root,
+            SubTree::new_empty().root
+        );
+
+        // keys are not bound to the key length of the tree
+        let leaf = [
+            vec![
+                PREFIX_SUB_TREE_VERSION,
+                SUB_TREE_VERSION,
+                1,
+                0,
+                0,
+                3,
+                1,
+                2,
+                3,
+                32,
+            ],
+            vec![7; 32],
+        ]
+        .concat();
+        let tree = SubTree::new(&leaf, KeyLength(32)).unwrap();
+        assert_eq!(tree.nodes[0].lock().unwrap().key.as_slice(), &[1, 2, 3]);
+        assert_eq!(tree.encode(), leaf);
+
+        // unsupported version
+        assert!(SubTree::new(&[PREFIX_SUB_TREE_VERSION, 2, 1, 0, 2], KeyLength(32)).is_err());
+        // hash is truncated
+        assert!(SubTree::new(&leaf[..leaf.len() - 1], KeyLength(32)).is_err());
+        // number of nodes does not match the structure
+        assert!(SubTree::new(
+            &[PREFIX_SUB_TREE_VERSION, SUB_TREE_VERSION, 2, 1, 1, 2],
+            KeyLength(32)
+        )
+        .is_err());
+        // height of the structure exceeds the subtree
+        assert!(SubTree::new(
+            &[PREFIX_SUB_TREE_VERSION, SUB_TREE_VERSION, 2, 9, 9, 2, 2],
+            KeyLength(32)
+        )
+        .is_err());
+        // length of the structure and the key exceed the data
+        assert!(SubTree::new(
+            &[
+                PREFIX_SUB_TREE_VERSION,
+                SUB_TREE_VERSION,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0x0f
+            ],
+            KeyLength(32)
+        )
+        .is_err());
+        assert!(SubTree::new(
+            &[
+                PREFIX_SUB_TREE_VERSION,
+                SUB_TREE_VERSION,
+                1,
+                0,
+                0,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0x0f
+            ],
+            KeyLength(32)
+        )
+        .is_err());
+        let (root, encoded) = decode_subtree(&leaf, KeyLength(32)).unwrap();
+        assert_eq!(encoded, leaf);
+        assert_eq!(root, tree.root.to_vec());
+        assert_eq!(subtree_height(&leaf, KeyLength(32)).unwrap(), 0);
+        assert_eq!(subtree_height(&empty, KeyLength(32)).unwrap(), 0);
+        let branch = SubTree::from_data(
+            &[1, 2, 2],
+            &[
+                Arc::new(Mutex::new(Node::new_leaf(&[1; 32], &[1; 32]))),
+                Arc::new(Mutex::new(Node::new_leaf(&[2; 32], &[2; 32]))),
+                Arc::new(Mutex::new(Node::new_empty())),
+            ],
+        )
+        .unwrap();
+        assert_eq!(subtree_height(&branch.encode(), KeyLength(32)).unwrap(), 2);
     }
 
     #[test]
     fn test_empty_tree() {
         let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-        let data = UpdateData { data: Cache::new() };
+        let data = UpdateData::default();
         let mut db = smt_db::InMemorySmtDB::default();
         let result = tree.commit(&mut db, &data);
 
@@ -1641,7 +3119,7 @@ mod tests {
 
         for (keys, values) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1670,7 +3148,7 @@ mod tests {
 
         for (keys, values, root) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1703,7 +3181,7 @@ mod tests {
 
         for (keys, values, root) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1744,7 +3222,7 @@ mod tests {
 
         for (keys, values, root) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1793,7 +3271,7 @@ mod tests {
 
         for (keys, values, root) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1821,7 +3299,7 @@ mod tests {
 
         for (keys, values, root, query_keys) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -1842,6 +3320,7 @@ mod tests {
                         hex::decode(values[0]).unwrap(),
                     )),
                     bitmap: Arc::new(vec![]),
+                    stored_value: None,
                 }],
             };
 
@@ -1908,6 +3387,7 @@ mod tests {
                             )
                             .unwrap(),
                         )),
+                        stored_value: None,
                     }],
                 ),
                 (
@@ -2004,6 +3484,7 @@ mod tests {
                             "1406e05881e299367766d313e26c05564ec91bf721d31726bd6e46e60689539a",
                         )
                         .unwrap(),)),
+                        stored_value: None,
                     },
                     QueryProof {
                         bitmap: Arc::new(hex::decode("bf").unwrap()),
@@ -2015,6 +3496,7 @@ mod tests {
                             "9c12cfdc04c74584d787ac3d23772132c18524bc7ab28dec4219b8fc5b425f70",
                         )
                         .unwrap(),)),
+                        stored_value: None,
                     },
                     QueryProof {
                         bitmap: Arc::new(hex::decode("2f").unwrap()),
@@ -2026,6 +3508,7 @@ mod tests {
                             "1cc3adea40ebfd94433ac004777d68150cce9db4c771bc7de1b297a7b795bbba",
                         )
                         .unwrap(),)),
+                        stored_value: None,
                     },
                 ],
                 ),
@@ -2033,7 +3516,7 @@ mod tests {
 
         for (keys, values, root, query_keys, sibling_hashes, queries) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -2047,7 +3530,7 @@ mod tests {
 
             let proof = tree
                 .prove(
-                    &mut db,
+                    &db,
                     &query_keys
                         .iter()
                         .map(|k| hex::decode(k).unwrap())
@@ -2225,6 +3708,7 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
                 QueryProof {
                     bitmap: Arc::new(hex::decode("bf").unwrap()),
@@ -2238,6 +3722,7 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
                 QueryProof {
                     bitmap: Arc::new(hex::decode("3f").unwrap()),
@@ -2251,6 +3736,7 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
                 QueryProof {
                     bitmap: Arc::new(hex::decode("9f").unwrap()),
@@ -2264,6 +3750,7 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
                 QueryProof {
                     bitmap: Arc::new(hex::decode("5f").unwrap()),
@@ -2277,6 +3764,7 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
                 QueryProof {
                     bitmap: Arc::new(hex::decode("015f").unwrap()),
@@ -2290,13 +3778,14 @@ mod tests {
                         )
                         .unwrap(),
                     )),
+                    stored_value: None,
                 },
             ],
         )];
 
         for (keys, values, root, query_keys, sibling_hashes, queries) in test_data {
             let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
-            let mut data = UpdateData { data: Cache::new() };
+            let mut data = UpdateData::default();
             for idx in 0..keys.len() {
                 data.data.insert(
                     hex::decode(keys[idx]).unwrap(),
@@ -2310,7 +3799,7 @@ mod tests {
 
             let proof = tree
                 .prove(
-                    &mut db,
+                    &db,
                     &query_keys
                         .iter()
                         .map(|k| hex::decode(k).unwrap())
@@ -2355,6 +3844,114 @@ mod tests {
         assert_eq!(data.data.get(&vec![7, 8, 9]).unwrap(), &vec![10, 11, 12]);
     }
 
+    #[test]
+    fn test_update_data_entries() {
+        let mut data = UpdateData::default();
+        data.insert(SharedKVPair(&[3, 0], &[1]));
+        data.insert(SharedKVPair(&[1, 2], &[2]));
+        data.insert(SharedKVPair(&[2, 1], &[]));
+        data.insert(SharedKVPair(&[1, 2], &[3]));
+
+        let (keys, values) = data.entries();
+        assert_eq!(keys, vec![&[1, 2][..], &[2, 1], &[3, 0]]);
+        assert_eq!(values, vec![&[3][..], &[], &[1]]);
+    }
+
+    #[test]
+    fn test_update_data_empty_value() {
+        let key = vec![1; 32];
+        let other_key = vec![2; 32];
+        let pairs = vec![(key.clone(), vec![]), (other_key.clone(), vec![3; 32])];
+
+        let deleted = UpdateData::new_with_empty_value(pairs.clone(), EmptyValue::DeleteOnEmpty);
+        assert_eq!(deleted, UpdateData::new_from(pairs.clone()));
+        let mut stored = UpdateData::new_with_empty_value(pairs, EmptyValue::StoreEmptyHash);
+        assert_eq!(stored.data.get(&key).unwrap(), &EMPTY_HASH.to_vec());
+        stored.insert(SharedKVPair(&[4; 32], &[]));
+        assert_eq!(stored.data.get(&vec![4; 32]).unwrap(), &EMPTY_HASH.to_vec());
+        stored.delete(&[4; 32]);
+        assert!(stored.data.get(&vec![4; 32]).unwrap().is_empty());
+
+        let mut db = smt_db::InMemorySmtDB::default();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let deleted_root = tree
+            .commit(&mut db, &deleted)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .to_vec();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let stored_root = tree
+            .commit(&mut db, &stored)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .to_vec();
+        assert_ne!(deleted_root, stored_root);
+
+        let queries = vec![key.clone()];
+        let proof = tree.prove(&db, &queries).unwrap();
+        assert_eq!(proof.queries[0].key(), queries[0].as_slice());
+        assert_eq!(proof.queries[0].value(), EMPTY_HASH);
+        assert!(SparseMerkleTree::verify(&queries, &proof, &stored_root, KeyLength(32)).unwrap());
+
+        // the stored key is removed only by delete, which leaves the tree without it
+        let mut removed = UpdateData::new_with_empty_value(vec![], EmptyValue::StoreEmptyHash);
+        removed.delete(&key);
+        let removed_root = tree
+            .commit(&mut db, &removed)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .to_vec();
+        assert_eq!(removed_root, deleted_root);
+
+        assert_eq!(
+            EmptyValue::from_name("storeEmptyHash"),
+            Ok(EmptyValue::StoreEmptyHash)
+        );
+        assert_eq!(
+            EmptyValue::from_name("deleteOnEmpty"),
+            Ok(EmptyValue::DeleteOnEmpty)
+        );
+        assert!(EmptyValue::from_name("delete").is_err());
+    }
+
+    #[cfg(feature = "neon")]
+    #[test]
+    fn test_update_data_new_from_batch() {
+        let key = vec![0, 0, 0, 1, 0, 0, 7];
+        let deleted_key = vec![0, 0, 0, 1, 0, 0, 8];
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put(&key, [1, 2, 3]);
+        batch.delete(&deleted_key);
+
+        let data = UpdateData::new_from_batch(&batch).unwrap();
+        assert_eq!(data.data.len(), 2);
+        assert_eq!(
+            data.data.get(&key.hash_with_kind(HashKind::Key)).unwrap(),
+            &vec![1, 2, 3].hash_with_kind(HashKind::Value)
+        );
+        assert!(data
+            .data
+            .get(&deleted_key.hash_with_kind(HashKind::Key))
+            .unwrap()
+            .is_empty());
+
+        batch.put([0, 0, 1], [1]);
+        assert_eq!(
+            UpdateData::new_from_batch(&batch),
+            Err(SMTError::InvalidInput(String::from(
+                "State key must be at least 6 bytes of the store prefix"
+            )))
+        );
+        assert!(hash_state_key(&[0; 5]).is_err());
+        assert_eq!(
+            hash_state_key(&key).unwrap(),
+            key.hash_with_kind(HashKind::Key)
+        );
+    }
+
     #[test]
     fn test_query_proof_with_proof() {
         let pair = Arc::new(KVPair(
@@ -2509,7 +4106,7 @@ mod tests {
 
         let mut proof = tree
             .prove(
-                &mut db,
+                &db,
                 &keys
                     .iter()
                     .map(|k| hex::decode(k).unwrap())
@@ -2554,27 +4151,21 @@ mod tests {
     fn test_node_new_temp() {
         let node = Node::new_temp();
         assert_eq!(node.kind, NodeKind::Temp);
-        assert_eq!(node.hash, KVPair(vec![], vec![]));
-        assert_eq!(node.key, vec![]);
+        assert_eq!(node.hash, [0; HASH_SIZE]);
+        assert!(node.key.is_empty());
+        assert!(node.value.is_empty());
         assert_eq!(node.index, 0);
     }
 
     #[test]
     fn test_node_new_stub() {
-        let node = Node::new_stub(&EMPTY_HASH);
+        let node = Node::new_stub(&EMPTY_HASH).unwrap();
         assert_eq!(node.kind, NodeKind::Stub);
-        assert_eq!(
-            node.hash,
-            KVPair(
-                vec![
-                    1, 227, 176, 196, 66, 152, 252, 28, 20, 154, 251, 244, 200, 153, 111, 185, 36,
-                    39, 174, 65, 228, 100, 155, 147, 76, 164, 149, 153, 27, 120, 82, 184, 85
-                ],
-                EMPTY_HASH.to_vec()
-            )
-        );
-        assert_eq!(node.key, vec![]);
+        assert_eq!(node.hash, EMPTY_HASH);
+        assert!(node.key.is_empty());
+        assert!(node.value.is_empty());
         assert_eq!(node.index, 0);
+        assert!(Node::new_stub(&EMPTY_HASH[1..]).is_err());
     }
 
     #[test]
@@ -2583,50 +4174,674 @@ mod tests {
         assert_eq!(node.kind, NodeKind::Stub);
         assert_eq!(
             node.hash,
-            KVPair(
-                vec![
-                    1, 227, 176, 196, 66, 152, 252, 28, 20, 154, 251, 244, 200, 153, 111, 185, 36,
-                    39, 174, 65, 228, 100, 155, 147, 76, 164, 149, 153, 27, 120, 82, 184, 85, 227,
-                    176, 196, 66, 152, 252, 28, 20, 154, 251, 244, 200, 153, 111, 185, 36, 39,
-                    174, 65, 228, 100, 155, 147, 76, 164, 149, 153, 27, 120, 82, 184, 85
-                ],
-                vec![
-                    75, 19, 46, 241, 48, 12, 27, 199, 145, 196, 180, 77, 220, 0, 183, 136, 246,
-                    186, 252, 25, 209, 147, 94, 123, 7, 53, 181, 96, 62, 196, 225, 67
-                ]
-            )
+            [
+                75, 19, 46, 241, 48, 12, 27, 199, 145, 196, 180, 77, 220, 0, 183, 136, 246, 186,
+                252, 25, 209, 147, 94, 123, 7, 53, 181, 96, 62, 196, 225, 67
+            ]
         );
-        assert_eq!(node.key, vec![]);
+        assert!(node.key.is_empty());
+        assert!(node.value.is_empty());
         assert_eq!(node.index, 0);
     }
 
     #[test]
     fn test_node_new_leaf() {
-        let node = Node::new_leaf(&KVPair(
-            vec![10, 11, 12, 13, 14, 15],
-            vec![16, 17, 18, 19, 20],
-        ));
+        let node = Node::new_leaf(&[10, 11, 12, 13, 14, 15], &[16, 17, 18, 19, 20]);
         assert_eq!(node.kind, NodeKind::Leaf);
         assert_eq!(
             node.hash,
-            KVPair(
-                vec![0, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20],
-                vec![
-                    189, 48, 148, 168, 3, 139, 17, 26, 138, 59, 6, 102, 38, 115, 95, 225, 41, 141,
-                    147, 173, 215, 231, 167, 69, 122, 198, 83, 105, 201, 165, 11, 128
-                ]
-            )
+            [
+                189, 48, 148, 168, 3, 139, 17, 26, 138, 59, 6, 102, 38, 115, 95, 225, 41, 141,
+                147, 173, 215, 231, 167, 69, 122, 198, 83, 105, 201, 165, 11, 128
+            ]
         );
-        assert_eq!(node.key, vec![10, 11, 12, 13, 14, 15]);
+        assert_eq!(node.key.as_slice(), &[10, 11, 12, 13, 14, 15]);
+        assert_eq!(node.value.as_slice(), &[16, 17, 18, 19, 20]);
         assert_eq!(node.index, 0);
+
+        // the keys and the values of the state_db are kept inline, and the longer ones on the heap
+        let node = Node::new_leaf(&[1; 38], &[2; HASH_SIZE]);
+        assert!(!node.key.spilled() && !node.value.spilled());
+        let node = Node::new_leaf(&[1; 64], &[2; HASH_SIZE]);
+        assert!(node.key.spilled());
+        assert_eq!(node.key.as_slice(), &[1; 64]);
     }
 
     #[test]
     fn test_node_new_empty() {
         let node = Node::new_empty();
         assert_eq!(node.kind, NodeKind::Empty);
-        assert_eq!(node.hash, KVPair(vec![2], EMPTY_HASH.to_vec()));
-        assert_eq!(node.key, vec![]);
+        assert_eq!(node.hash, EMPTY_HASH);
+        assert!(node.key.is_empty());
+        assert!(node.value.is_empty());
         assert_eq!(node.index, 0);
     }
+
+    #[test]
+    fn test_subtree_new_invalid_data() {
+        // structure is longer than the data
+        assert!(SubTree::new(&[3, 1, 1], KeyLength(32)).is_err());
+        // branch node is truncated
+        assert!(SubTree::new(&[1, 1, 1, 1, 1, 2], KeyLength(32)).is_err());
+        // structure cannot be folded into a root
+        assert!(SubTree::new(&[1, 1, 2, 2, 2], KeyLength(32)).is_err());
+        // number of nodes does not match the structure
+        assert!(SubTree::new(&[1, 1, 1, 2], KeyLength(32)).is_err());
+        assert!(SubTree::new(&[1, 1, 1, 2, 2], KeyLength(32)).is_ok());
+    }
+
+    #[test]
+    fn test_prove_parallel() {
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::default();
+        for i in 0..200u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+
+        // inclusion, non-inclusion and duplicate queries
+        let mut queries: NestedVec = (0..50u32)
+            .map(|i| {
+                (i * 7)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value)
+            })
+            .collect();
+        queries.push(vec![0; 32]);
+        queries.push(queries[3].clone());
+
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), Default::default());
+        let proof = tree.prove(&db, &queries).unwrap();
+        let parallel_proof = tree.prove_parallel(&db, &queries).unwrap();
+        assert_eq!(parallel_proof.sibling_hashes, proof.sibling_hashes);
+        assert_eq!(parallel_proof.queries.len(), proof.queries.len());
+        for (parallel_query, query) in parallel_proof.queries.iter().zip(proof.queries.iter()) {
+            assert_eq!(parallel_query.pair, query.pair);
+            assert_eq!(parallel_query.bitmap, query.bitmap);
+        }
+        assert!(
+            SparseMerkleTree::verify(&queries, &parallel_proof, &root, KeyLength(32)).unwrap()
+        );
+
+        queries.push(vec![0; 31]);
+        assert!(tree.prove_parallel(&db, &queries).is_err());
+    }
+
+    #[test]
+    fn test_proof_helpers() {
+        let first = ProofHelpers::acquire(MAX_PROOF_HELPERS * 2);
+        let second = ProofHelpers::acquire(MAX_PROOF_HELPERS * 2);
+        // the helpers taken by the other tests proving at the same time are within the bound as well
+        assert!(first.count + second.count <= MAX_PROOF_HELPERS);
+        assert_eq!(ProofHelpers::acquire(0).count, 0);
+    }
+
+    #[test]
+    fn test_prove_many() {
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::default();
+        for i in 0..200u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+
+        let query = |i: u32| i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value);
+        // overlapping sets with the inclusion, non-inclusion and duplicate queries
+        let query_sets: Vec<NestedVec> = vec![
+            (0..30).map(|i| query(i * 3)).collect(),
+            (0..30).map(|i| query(i * 5)).collect(),
+            vec![vec![0; 32], query(7), query(7)],
+            vec![],
+        ];
+
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), Default::default());
+        let proofs = tree.prove_many(&db, &query_sets).unwrap();
+        assert_eq!(proofs.len(), query_sets.len());
+        for (queries, proof) in query_sets.iter().zip(proofs.iter()) {
+            let expected = tree.prove(&db, queries).unwrap();
+            assert_eq!(proof.sibling_hashes, expected.sibling_hashes);
+            assert_eq!(proof.queries.len(), expected.queries.len());
+            for (query, expected) in proof.queries.iter().zip(expected.queries.iter()) {
+                assert_eq!(query.pair, expected.pair);
+                assert_eq!(query.bitmap, expected.bitmap);
+            }
+            if !queries.is_empty() {
+                assert!(SparseMerkleTree::verify(queries, proof, &root, KeyLength(32)).unwrap());
+            }
+        }
+
+        assert!(tree.prove_many(&db, &[vec![vec![0; 31]]]).is_err());
+    }
+
+    #[test]
+    fn test_calculate_root_by_layer() {
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::default();
+        for i in 0..500u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                i.to_be_bytes().to_vec(),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+
+        for step in [1usize, 3, 17, 101] {
+            let queries: NestedVec = (0..600u32)
+                .step_by(step)
+                .map(|i| i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value))
+                .collect();
+            let proof = tree.prove(&db, &queries).unwrap();
+
+            let mut expected_queries = SparseMerkleTree::prepare_queries_with_proof_map(&proof)
+                .unwrap()
+                .into_values()
+                .collect::<Vec<QueryProofWithProof>>();
+            let expected =
+                SparseMerkleTree::calculate_root(&proof.sibling_hashes, &mut expected_queries)
+                    .unwrap();
+            let verify_queries = proof.queries.iter().map(|q| VerifyQuery::new(q).unwrap());
+            let computed = SparseMerkleTree::calculate_root_by_layer(
+                &proof.sibling_hashes,
+                verify_queries.collect(),
+            )
+            .unwrap();
+            assert_eq!(computed, expected);
+            assert_eq!(computed, root);
+
+            // querying all the leaves requires no sibling hash
+            let mut invalid_proof = proof.clone();
+            if invalid_proof.sibling_hashes.pop().is_some() {
+                assert!(
+                    !SparseMerkleTree::verify(&queries, &invalid_proof, &root, KeyLength(32))
+                        .unwrap()
+                );
+            }
+            let mut invalid_proof = proof.clone();
+            invalid_proof.sibling_hashes.push(EMPTY_HASH.to_vec());
+            assert!(
+                !SparseMerkleTree::verify(&queries, &invalid_proof, &root, KeyLength(32)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::default();
+        let mut filter = BloomFilter::with_capacity(0);
+        for i in 0..200u32 {
+            let key = i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value);
+            filter.insert(&key);
+            data.data.insert(
+                key,
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), Default::default());
+        for (key, value) in data.data.iter() {
+            assert_eq!(tree.get(&db, key, None).unwrap().as_ref(), Some(value));
+            assert_eq!(
+                tree.get(&db, key, Some(&filter)).unwrap().as_ref(),
+                Some(value)
+            );
+        }
+        let missing = 200u32
+            .to_be_bytes()
+            .to_vec()
+            .hash_with_kind(HashKind::Value);
+        assert_eq!(tree.get(&db, &missing, None).unwrap(), None);
+        assert_eq!(tree.get(&db, &missing, Some(&filter)).unwrap(), None);
+        // the key ruled out by the filter is not looked up in the empty database
+        let empty_db = smt_db::InMemorySmtDB::default();
+        if !filter.contains(&missing) {
+            assert_eq!(tree.get(&empty_db, &missing, Some(&filter)).unwrap(), None);
+        }
+        assert!(tree.get(&empty_db, &missing, None).is_err());
+        assert!(tree.get(&db, &[0; 20], None).is_err());
+    }
+
+    #[test]
+    fn test_commit_with_hook() {
+        let mut data = UpdateData::default();
+        for i in 0..300u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let expected = tree.commit(&mut db, &data).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut progress = vec![];
+        let mut on_progress = |percent: u8| progress.push(percent);
+        let mut hook = CommitHook::new(&cancel, &mut on_progress);
+        let mut db = smt_db::InMemorySmtDB::default();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let root = tree.commit_with_hook(&mut db, &data, &mut hook).unwrap();
+        let root = Arc::clone(&root.lock().unwrap());
+        assert_eq!(root, *expected.lock().unwrap());
+        assert!(progress.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(progress.last(), Some(&100));
+
+        let cancel = AtomicBool::new(false);
+        let mut on_progress = |percent: u8| {
+            if percent >= 50 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        };
+        let mut hook = CommitHook::new(&cancel, &mut on_progress);
+        // the progress is reported for each node of the root subtree, so the tree must not be empty
+        let mut updated = UpdateData::default();
+        for (key, value) in data.data.iter() {
+            updated
+                .data
+                .insert(key.clone(), value.hash_with_kind(HashKind::Value));
+        }
+        let err = tree
+            .commit_with_hook(&mut db, &updated, &mut hook)
+            .unwrap_err();
+        assert_eq!(err, SMTError::Aborted);
+        assert_eq!(*tree.root.lock().unwrap(), root);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut data = UpdateData::default();
+        for i in 0..300u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+        let queries = data.data.keys().cloned().collect::<NestedVec>();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut tree = SparseMerkleTree::new(&root, KeyLength(32), Default::default())
+            .with_cancel(Arc::clone(&cancel));
+        assert!(tree.prove_parallel(&db, &queries).is_ok());
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(
+            tree.prove_parallel(&db, &queries).err(),
+            Some(SMTError::Aborted)
+        );
+        assert_eq!(
+            tree.prove(&db, &queries[..1]).err(),
+            Some(SMTError::Aborted)
+        );
+
+        let mut pairs = data
+            .data
+            .iter()
+            .map(|(key, value)| KVPair::new(key, value))
+            .collect::<Vec<KVPair>>();
+        pairs.sort_by(|a, b| a.key().cmp(b.key()));
+        let mut bulk_db = smt_db::InMemorySmtDB::default();
+        let mut tree =
+            SparseMerkleTree::new(&[], KeyLength(32), Default::default()).with_cancel(cancel);
+        assert_eq!(
+            tree.bulk_load(&mut bulk_db, pairs).err(),
+            Some(SMTError::Aborted)
+        );
+        assert!(utils::is_empty_hash(&tree.root.lock().unwrap()));
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut db = smt_db::InMemorySmtDB::default();
+        let tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let nodes = tree.dump(&db).unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].kind, DumpNodeKind::Empty);
+        assert_eq!(nodes[0].hash, EMPTY_HASH.to_vec());
+
+        for subtree_height in [
+            SubtreeHeight(SubtreeHeightKind::Four),
+            SubtreeHeight(SubtreeHeightKind::Eight),
+        ] {
+            let mut data = UpdateData::default();
+            for i in 0..30u32 {
+                let key = i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value);
+                data.data.insert(key, (i + 1).to_be_bytes().to_vec());
+            }
+            let mut tree = SparseMerkleTree::new(&[], KeyLength(32), subtree_height);
+            let root = tree.commit(&mut db, &data).unwrap();
+            let root = root.lock().unwrap().to_vec();
+
+            let nodes = tree.dump(&db).unwrap();
+            assert_eq!(nodes, tree.dump(&db).unwrap());
+            assert_eq!(nodes[0].kind, DumpNodeKind::Branch);
+            assert_eq!(nodes[0].height, 0);
+            assert_eq!(nodes[0].hash, root);
+            let leaves: Vec<&DumpNode> = nodes
+                .iter()
+                .filter(|n| n.kind == DumpNodeKind::Leaf)
+                .collect();
+            assert_eq!(leaves.len(), 30);
+            for leaf in leaves {
+                assert_eq!(data.data.get(&leaf.key), Some(&leaf.value));
+                assert_eq!(leaf.hash, KVPair::new(&leaf.key, &leaf.value).hash());
+            }
+
+            // rebuild the hashes from the bottom, where the children follow the parent
+            let mut hashes: Vec<Vec<u8>> = vec![];
+            for node in nodes.iter().rev() {
+                if node.kind == DumpNodeKind::Branch {
+                    let left = hashes.pop().unwrap();
+                    let right = hashes.pop().unwrap();
+                    assert_eq!(
+                        [left, right].concat().hash_with_kind(HashKind::Branch),
+                        node.hash
+                    );
+                }
+                hashes.push(node.hash.clone());
+            }
+            assert_eq!(hashes, vec![root]);
+        }
+    }
+
+    #[test]
+    fn test_prove_prefix() {
+        let key = |prefix: u8, i: u32| {
+            let mut key = i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value);
+            key[0] = prefix;
+            key
+        };
+        let value = |i: u32| {
+            (i + 1)
+                .to_be_bytes()
+                .to_vec()
+                .hash_with_kind(HashKind::Value)
+        };
+        for subtree_height in [
+            SubtreeHeight(SubtreeHeightKind::Four),
+            SubtreeHeight(SubtreeHeightKind::Eight),
+        ] {
+            let mut data = UpdateData::default();
+            for i in 0..50u32 {
+                data.data.insert(key(0x12, i), value(i));
+                data.data.insert(key(0x13, i), value(i));
+            }
+            data.data.insert(key(0x34, 0), value(0));
+            let mut db = smt_db::InMemorySmtDB::default();
+            let mut tree = SparseMerkleTree::new(&[], KeyLength(32), subtree_height);
+            let root = tree.commit(&mut db, &data).unwrap();
+            let root = root.lock().unwrap().to_vec();
+
+            // many keys under the prefix
+            let proof = tree.prove_prefix(&db, &[0x12]).unwrap();
+            assert_eq!(proof.sibling_hashes.len(), 8);
+            assert!(matches!(proof.node, PrefixNode::Branch(_)));
+            assert!(SparseMerkleTree::verify_prefix(&root, &proof, KeyLength(32)).unwrap());
+            let subtree_root = proof.subtree_root();
+
+            // a single key under the prefix is collapsed to the leaf
+            let proof = tree.prove_prefix(&db, &key(0x34, 0)[..2]).unwrap();
+            assert!(proof.sibling_hashes.len() < 16);
+            assert_eq!(
+                proof.node,
+                PrefixNode::Leaf {
+                    key: key(0x34, 0),
+                    value: value(0)
+                }
+            );
+            assert_eq!(
+                proof.subtree_root(),
+                KVPair::new(&key(0x34, 0), &value(0)).hash()
+            );
+            assert!(SparseMerkleTree::verify_prefix(&root, &proof, KeyLength(32)).unwrap());
+
+            // no key under the prefix
+            let proof = tree.prove_prefix(&db, &[0x56]).unwrap();
+            assert_eq!(proof.subtree_root(), EMPTY_HASH.to_vec());
+            assert!(SparseMerkleTree::verify_prefix(&root, &proof, KeyLength(32)).unwrap());
+
+            // empty prefix proves the root
+            let proof = tree.prove_prefix(&db, &[]).unwrap();
+            assert_eq!(proof.subtree_root(), root);
+            assert!(SparseMerkleTree::verify_prefix(&root, &proof, KeyLength(32)).unwrap());
+
+            // tampered proof
+            let mut proof = tree.prove_prefix(&db, &[0x13]).unwrap();
+            proof.sibling_hashes[7] = EMPTY_HASH.to_vec();
+            assert!(!SparseMerkleTree::verify_prefix(&root, &proof, KeyLength(32)).unwrap());
+            assert!(tree.prove_prefix(&db, &[0; 33]).is_err());
+
+            // updating the keys under the other prefix does not change the subtree root
+            let mut next = UpdateData::default();
+            next.data.insert(key(0x13, 0), value(100));
+            tree.commit(&mut db, &next).unwrap();
+            let proof = tree.prove_prefix(&db, &[0x12]).unwrap();
+            assert_eq!(proof.subtree_root(), subtree_root);
+            let mut next = UpdateData::default();
+            next.data.insert(key(0x12, 0), value(100));
+            tree.commit(&mut db, &next).unwrap();
+            let proof = tree.prove_prefix(&db, &[0x12]).unwrap();
+            assert_ne!(proof.subtree_root(), subtree_root);
+        }
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::default();
+        for i in 0..100u32 {
+            data.data.insert(
+                i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                (i + 1)
+                    .to_be_bytes()
+                    .to_vec()
+                    .hash_with_kind(HashKind::Value),
+            );
+        }
+        let mut db = smt_db::InMemorySmtDB::default();
+        let root = tree.commit(&mut db, &data).unwrap();
+        let root = root.lock().unwrap().to_vec();
+
+        assert!(tree.check_consistency(&db, &root).unwrap().is_empty());
+        assert!(tree.check_consistency(&db, &EMPTY_HASH).unwrap().is_empty());
+
+        let root_subtree = tree.get_subtree(&db, &root).unwrap();
+        let stub_hash = root_subtree
+            .nodes
+            .iter()
+            .map(|n| n.lock().unwrap().clone())
+            .find(|n| n.kind == NodeKind::Stub)
+            .unwrap()
+            .hash
+            .to_vec();
+
+        // stored encoding does not match the hash
+        db.set(&KVPair::new(&stub_hash, &SubTree::new_empty().encode()))
+            .unwrap();
+        assert_eq!(
+            tree.check_consistency(&db, &root).unwrap(),
+            vec![stub_hash.clone()]
+        );
+
+        // stored encoding cannot be decoded
+        db.set(&KVPair::new(&stub_hash, &[9, 9])).unwrap();
+        assert_eq!(
+            tree.check_consistency(&db, &root).unwrap(),
+            vec![stub_hash.clone()]
+        );
+
+        // node is missing
+        db.del(&stub_hash).unwrap();
+        assert_eq!(tree.check_consistency(&db, &root).unwrap(), vec![stub_hash]);
+        db.del(&root).unwrap();
+        assert_eq!(tree.check_consistency(&db, &root).unwrap(), vec![root]);
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        for size in [1, 2, 3, 17, 500] {
+            let mut pairs: Vec<KVPair> = (0..size as u32)
+                .map(|i| {
+                    KVPair::new(
+                        &i.to_be_bytes().to_vec().hash_with_kind(HashKind::Value),
+                        &(i + 1)
+                            .to_be_bytes()
+                            .to_vec()
+                            .hash_with_kind(HashKind::Value),
+                    )
+                })
+                .collect();
+            pairs.sort_by(|a, b| a.key().cmp(b.key()));
+
+            let mut data = UpdateData::default();
+            for pair in pairs.iter() {
+                data.insert(SharedKVPair(pair.key(), pair.value()));
+            }
+            let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+            let mut db = smt_db::InMemorySmtDB::default();
+            let expected = tree.commit(&mut db, &data).unwrap();
+
+            let mut bulk_tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+            let mut bulk_db = smt_db::InMemorySmtDB::default();
+            let root = bulk_tree.bulk_load(&mut bulk_db, pairs.clone()).unwrap();
+            let root = root.lock().unwrap().to_vec();
+            assert_eq!(root, **expected.lock().unwrap());
+            // no SubTree is stored apart from the ones of the tree
+            assert_eq!(bulk_db.size(), db.size());
+            assert!(bulk_tree
+                .check_consistency(&bulk_db, &root)
+                .unwrap()
+                .is_empty());
+
+            let query_keys = vec![pairs[0].key_as_vec(), pairs[size - 1].key_as_vec()];
+            let proof = bulk_tree.prove(&bulk_db, &query_keys).unwrap();
+            assert!(SparseMerkleTree::verify(&query_keys, &proof, &root, KeyLength(32)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_bulk_load_in_batches() {
+        let pairs: Vec<KVPair> = (0..10u8).map(|i| KVPair::new(&[i; 32], &[i + 1])).collect();
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut db = smt_db::InMemorySmtDB::default();
+        let expected = tree.bulk_load(&mut db, pairs.clone()).unwrap();
+
+        let mut loader = BulkLoader::new(KeyLength(32));
+        for batch in pairs.chunks(3) {
+            loader.add(batch.to_vec()).unwrap();
+        }
+        // the order is checked across the batches
+        assert_eq!(
+            loader.add(vec![KVPair::new(&[9; 32], &[1])]).err(),
+            Some(SMTError::InvalidInput(String::from(
+                "keys must be sorted in ascending order without duplicates"
+            )))
+        );
+        let mut batch_tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut batch_db = smt_db::InMemorySmtDB::default();
+        let root = batch_tree.finish_bulk_load(&mut batch_db, loader).unwrap();
+        assert_eq!(**root.lock().unwrap(), **expected.lock().unwrap());
+    }
+
+    #[test]
+    fn test_bulk_load_invalid_input() {
+        let pairs = vec![KVPair::new(&[2; 32], &[1]), KVPair::new(&[1; 32], &[1])];
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut db = smt_db::InMemorySmtDB::default();
+        assert_eq!(
+            tree.bulk_load(&mut db, pairs).err(),
+            Some(SMTError::InvalidInput(String::from(
+                "keys must be sorted in ascending order without duplicates"
+            )))
+        );
+
+        let pairs = vec![KVPair::new(&[1; 31], &[1])];
+        assert_eq!(
+            tree.bulk_load(&mut db, pairs).err(),
+            Some(SMTError::InvalidInput(String::from(
+                "all keys must have the same length"
+            )))
+        );
+
+        let pairs = vec![KVPair::new(&[1; 32], &[]), KVPair::new(&[2; 32], &[1])];
+        let root = tree.bulk_load(&mut db, pairs).unwrap();
+        assert_eq!(**root.lock().unwrap(), KVPair::new(&[2; 32], &[1]).hash());
+
+        let pairs = vec![KVPair::new(&[3; 32], &[1])];
+        assert_eq!(
+            tree.bulk_load(&mut db, pairs).err(),
+            Some(SMTError::InvalidInput(String::from(
+                "bulk load requires an empty tree"
+            )))
+        );
+    }
+
+    #[derive(Error, Debug)]
+    #[error("backend is unavailable")]
+    struct UnavailableError;
+
+    struct UnavailableDB;
+
+    impl Actions for UnavailableDB {
+        type Error = UnavailableError;
+
+        fn get(&self, _key: &[u8]) -> Result<VecOption, Self::Error> {
+            Err(UnavailableError)
+        }
+
+        fn set(&mut self, _pair: &KVPair) -> Result<(), Self::Error> {
+            Err(UnavailableError)
+        }
+
+        fn del(&mut self, _key: &[u8]) -> Result<(), Self::Error> {
+            Err(UnavailableError)
+        }
+    }
+
+    #[test]
+    fn test_backend_error() {
+        let mut db = UnavailableDB;
+        let expected = Err(SMTError::Unknown(String::from("backend is unavailable")));
+
+        let mut tree = SparseMerkleTree::new(&[], KeyLength(32), Default::default());
+        let mut data = UpdateData::new_from(Cache::new());
+        data.insert(SharedKVPair(&[1; 32], &[2; 32]));
+        assert_eq!(tree.commit(&mut db, &data).map(|_| ()), expected);
+
+        let mut tree = SparseMerkleTree::new(&[3; 32], KeyLength(32), Default::default());
+        assert_eq!(tree.get(&db, &[1; 32], None).map(|_| ()), expected);
+    }
 }
@@ -1,47 +1,184 @@
 // smt_db provides in memory interface for in memory SMT computation.
+use std::collections::HashMap;
+#[cfg(feature = "neon")]
+use std::collections::HashSet;
+use std::convert::Infallible;
+
+#[cfg(feature = "neon")]
 use crate::consts;
+#[cfg(feature = "neon")]
+use crate::database::column_family;
 use crate::database::traits::Actions;
+#[cfg(feature = "neon")]
 use crate::database::DB;
+use crate::memory;
 use crate::types::{Cache, KVPair, VecOption};
 
+#[cfg(feature = "neon")]
 pub struct SmtDB<'a> {
     db: &'a DB,
     pub batch: rocksdb::WriteBatch,
+    retain_nodes: bool,
+    released: HashSet<Vec<u8>>,
+    prefix: Vec<u8>,
+}
+
+/// SmtReader provides read only access to the nodes, so it can be shared by the threads generating the proofs.
+#[cfg(feature = "neon")]
+pub struct SmtReader<'a> {
+    db: &'a rocksdb::DB,
+    prefix: Vec<u8>,
 }
 
-#[derive(Default)]
 pub struct InMemorySmtDB {
     cache: Cache,
+    usage: memory::Usage,
+}
+
+/// StagedSmtDB keeps the writes in memory until apply is called, so they can be discarded.
+pub struct StagedSmtDB<'a, T: Actions> {
+    db: &'a mut T,
+    staged: HashMap<Vec<u8>, VecOption>,
+}
+
+impl Default for InMemorySmtDB {
+    fn default() -> Self {
+        Self {
+            cache: Cache::new(),
+            usage: memory::Usage::new(memory::Component::InMemorySMT),
+        }
+    }
 }
 
+#[cfg(feature = "neon")]
 impl Actions for SmtDB<'_> {
-    fn get(&self, key: &[u8]) -> Result<VecOption, rocksdb::Error> {
-        let result = self.db.get(&[consts::Prefix::SMT, key].concat())?;
+    type Error = rocksdb::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
+        let result = self.db.get(&[self.prefix.as_slice(), key].concat())?;
         Ok(result)
     }
 
-    fn set(&mut self, pair: &KVPair) -> Result<(), rocksdb::Error> {
+    fn set(&mut self, pair: &KVPair) -> Result<(), Self::Error> {
+        if self.retain_nodes {
+            self.released.remove(pair.key());
+        }
         self.batch.put(pair.key(), pair.value());
         Ok(())
     }
 
-    fn del(&mut self, key: &[u8]) -> Result<(), rocksdb::Error> {
+    fn del(&mut self, key: &[u8]) -> Result<(), Self::Error> {
+        // nodes of the previous roots are still referenced by the root history
+        if self.retain_nodes {
+            self.released.insert(key.to_vec());
+            return Ok(());
+        }
         self.batch.delete(key);
         Ok(())
     }
 }
 
+#[cfg(feature = "neon")]
+impl Actions for SmtReader<'_> {
+    type Error = rocksdb::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
+        let result = column_family::get(self.db, &[self.prefix.as_slice(), key].concat())?;
+        Ok(result)
+    }
+
+    fn set(&mut self, _pair: &KVPair) -> Result<(), Self::Error> {
+        unreachable!("SmtReader is read only")
+    }
+
+    fn del(&mut self, _key: &[u8]) -> Result<(), Self::Error> {
+        unreachable!("SmtReader is read only")
+    }
+}
+
+#[cfg(feature = "neon")]
+impl<'a> SmtReader<'a> {
+    pub fn new(db: &'a DB) -> Self {
+        Self::new_with_prefix(db, consts::Prefix::SMT)
+    }
+
+    /// new_with_prefix creates SmtReader which reads the nodes stored under the prefix.
+    pub fn new_with_prefix(db: &'a DB, prefix: &[u8]) -> Self {
+        Self {
+            db: db.conn(),
+            prefix: prefix.to_vec(),
+        }
+    }
+
+    /// new_with_conn creates SmtReader on the connection, so it can be used on the thread of the DB.
+    pub fn new_with_conn(conn: &'a rocksdb::DB) -> Self {
+        Self {
+            db: conn,
+            prefix: consts::Prefix::SMT.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "neon")]
 impl<'a> SmtDB<'a> {
     pub fn new(db: &'a DB) -> Self {
+        Self::new_with_retention(db, false)
+    }
+
+    /// new_with_retention creates SmtDB which releases the nodes instead of deleting them if retain_nodes is true.
+    pub fn new_with_retention(db: &'a DB, retain_nodes: bool) -> Self {
+        Self {
+            db,
+            batch: rocksdb::WriteBatch::default(),
+            retain_nodes,
+            released: HashSet::new(),
+            prefix: consts::Prefix::SMT.to_vec(),
+        }
+    }
+
+    /// new_with_prefix creates SmtDB which reads the nodes stored under the prefix.
+    /// The batch is not prefixed, and the caller must write it with the same prefix.
+    pub fn new_with_prefix(db: &'a DB, prefix: &[u8]) -> Self {
         Self {
             db,
             batch: rocksdb::WriteBatch::default(),
+            retain_nodes: false,
+            released: HashSet::new(),
+            prefix: prefix.to_vec(),
         }
     }
+
+    /// released returns the nodes deleted while the nodes are retained, which are not created again since.
+    /// They are still referenced by the previous roots, so they are deleted when the roots are pruned.
+    pub fn released(&self) -> &HashSet<Vec<u8>> {
+        &self.released
+    }
 }
 
+impl InMemorySmtDB {
+    /// cache returns all the nodes stored in the database.
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    /// size returns the total bytes of the keys and values of the nodes.
+    pub fn size(&self) -> usize {
+        self.usage.bytes()
+    }
+
+    /// replace discards all the nodes and stores the nodes in the cache instead.
+    pub fn replace(&mut self, cache: Cache) {
+        self.usage
+            .set(cache.iter().map(|(k, v)| k.len() + v.len()).sum());
+        self.cache = cache;
+    }
+}
+
+/// The nodes are kept in memory, so the operations never fail.
 impl Actions for InMemorySmtDB {
-    fn get(&self, key: &[u8]) -> Result<VecOption, rocksdb::Error> {
+    type Error = Infallible;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
         let result = self.cache.get(key);
         if let Some(value) = result {
             return Ok(Some(value.clone()));
@@ -49,33 +186,92 @@ impl Actions for InMemorySmtDB {
         Ok(None)
     }
 
-    fn set(&mut self, pair: &KVPair) -> Result<(), rocksdb::Error> {
-        self.cache.insert(pair.key_as_vec(), pair.value_as_vec());
+    fn set(&mut self, pair: &KVPair) -> Result<(), Self::Error> {
+        self.usage.grow(pair.key().len() + pair.value().len());
+        if let Some(prev) = self.cache.insert(pair.key_as_vec(), pair.value_as_vec()) {
+            self.usage.shrink(pair.key().len() + prev.len());
+        }
         Ok(())
     }
 
-    fn del(&mut self, key: &[u8]) -> Result<(), rocksdb::Error> {
-        self.cache.remove(key);
+    fn del(&mut self, key: &[u8]) -> Result<(), Self::Error> {
+        if let Some(prev) = self.cache.remove(key) {
+            self.usage.shrink(key.len() + prev.len());
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T: Actions> StagedSmtDB<'a, T> {
+    pub fn new(db: &'a mut T) -> Self {
+        Self {
+            db,
+            staged: HashMap::new(),
+        }
+    }
+
+    /// new_with_staged creates StagedSmtDB with the writes staged before, so the writes can be kept across the commits.
+    pub fn new_with_staged(db: &'a mut T, staged: HashMap<Vec<u8>, VecOption>) -> Self {
+        Self { db, staged }
+    }
+
+    /// into_staged returns the staged writes without writing them to the underlying database.
+    pub fn into_staged(self) -> HashMap<Vec<u8>, VecOption> {
+        self.staged
+    }
+
+    /// apply writes the staged nodes to the underlying database.
+    pub fn apply(self) -> Result<(), T::Error> {
+        for (key, value) in self.staged {
+            match value {
+                Some(value) => self.db.set(&KVPair(key, value))?,
+                None => self.db.del(&key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Actions> Actions for StagedSmtDB<'_, T> {
+    type Error = T::Error;
+
+    fn get(&self, key: &[u8]) -> Result<VecOption, Self::Error> {
+        if let Some(value) = self.staged.get(key) {
+            return Ok(value.clone());
+        }
+        self.db.get(key)
+    }
+
+    fn set(&mut self, pair: &KVPair) -> Result<(), Self::Error> {
+        self.staged
+            .insert(pair.key_as_vec(), Some(pair.value_as_vec()));
+        Ok(())
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<(), Self::Error> {
+        self.staged.insert(key.to_vec(), None);
         Ok(())
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "neon"))]
 mod tests {
-    use std::sync::mpsc;
     use tempdir::TempDir;
 
     use crate::batch::PrefixWriteBatch;
-    use crate::database::types::{DbMessage, Kind};
+    use crate::database::types::Kind;
     use crate::database::DB;
+    use crate::task_pool::SerialQueue;
 
     use super::*;
 
     fn temp_db() -> (DB, TempDir) {
         let temp_dir = TempDir::new("test_smt_db").unwrap();
         let rocks_db = rocksdb::DB::open_default(&temp_dir).unwrap();
-        let (tx, _) = mpsc::channel::<DbMessage>();
-        (DB::new(rocks_db, tx, Kind::Normal), temp_dir)
+        (
+            DB::new(rocks_db, SerialQueue::closed(), Kind::Normal),
+            temp_dir,
+        )
     }
 
     #[test]
@@ -133,6 +329,24 @@ mod tests {
         assert_eq!(smt_db.batch.len(), 2);
     }
 
+    #[test]
+    fn test_smt_db_del_with_retention() {
+        let (db, _) = temp_db();
+        let mut smt_db = SmtDB::new_with_retention(&db, true);
+
+        smt_db
+            .set(&KVPair::new(b"test_key", b"test_value"))
+            .unwrap();
+        smt_db.del(b"test_key").unwrap();
+        assert_eq!(smt_db.batch.len(), 1);
+        assert!(smt_db.released().contains(b"test_key".as_slice()));
+
+        smt_db
+            .set(&KVPair::new(b"test_key", b"test_value"))
+            .unwrap();
+        assert!(smt_db.released().is_empty());
+    }
+
     #[test]
     fn test_in_memory_smt_db_get() {
         let mut db = InMemorySmtDB::default();
@@ -163,4 +377,44 @@ mod tests {
         let result = db.get(b"test_key").unwrap();
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_in_memory_smt_db_usage() {
+        let mut db = InMemorySmtDB::default();
+
+        db.set(&KVPair::new(b"test_key", b"test_value")).unwrap();
+        assert_eq!(db.usage.bytes(), 18);
+        db.set(&KVPair::new(b"test_key", b"value")).unwrap();
+        assert_eq!(db.usage.bytes(), 13);
+        db.del(b"test_key").unwrap();
+        assert_eq!(db.usage.bytes(), 0);
+
+        let mut cache = Cache::new();
+        cache.insert(b"key".to_vec(), b"value".to_vec());
+        db.replace(cache);
+        assert_eq!(db.usage.bytes(), 8);
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn test_staged_smt_db() {
+        let mut db = InMemorySmtDB::default();
+        db.set(&KVPair::new(b"key1", b"value1")).unwrap();
+
+        let mut staged = StagedSmtDB::new(&mut db);
+        staged.set(&KVPair::new(b"key2", b"value2")).unwrap();
+        staged.del(b"key1").unwrap();
+        assert_eq!(staged.get(b"key1").unwrap(), None);
+        assert_eq!(staged.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+        drop(staged);
+        assert_eq!(db.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(db.get(b"key2").unwrap(), None);
+
+        let mut staged = StagedSmtDB::new(&mut db);
+        staged.set(&KVPair::new(b"key2", b"value2")).unwrap();
+        staged.del(b"key1").unwrap();
+        staged.apply().unwrap();
+        assert_eq!(db.get(b"key1").unwrap(), None);
+        assert_eq!(db.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+    }
 }
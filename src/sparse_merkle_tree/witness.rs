@@ -0,0 +1,272 @@
+// witness holds the state read by a block with the proof of it, so a stateless verifier can re-execute the block against only the prior root.
+use std::sync::Arc;
+
+use crate::codec;
+use crate::consts;
+use crate::database::traits::Actions;
+use crate::sparse_merkle_tree::smt::{hash_state_key, SMTError};
+use crate::sparse_merkle_tree::{Proof, QueryProof, SparseMerkleTree};
+use crate::types::{HashKind, HashWithKind, KVPair, KeyLength, NestedVec};
+
+/// WitnessEntry is the state key read by the block with its value, which is None if the key does not exist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessEntry {
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// Witness holds the entries with the inclusion and non-inclusion proof of them against the root.
+/// The queries of the proof are in the order of the entries.
+#[derive(Clone, Debug)]
+pub struct Witness {
+    pub root: Vec<u8>,
+    pub entries: Vec<WitnessEntry>,
+    pub proof: Proof,
+}
+
+impl Witness {
+    /// generate creates the witness of the state keys in the tree of the root.
+    /// The value of each included key is read by `get_value`, and it must match the value hash proven by the tree.
+    /// The duplicated keys are included once.
+    pub fn generate<F>(
+        db: &impl Actions,
+        root: &[u8],
+        keys: &[Vec<u8>],
+        key_length: KeyLength,
+        mut get_value: F,
+    ) -> Result<Self, SMTError>
+    where
+        F: FnMut(&[u8]) -> Result<Option<Vec<u8>>, SMTError>,
+    {
+        let mut state_keys: NestedVec = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            if !state_keys.contains(key) {
+                state_keys.push(key.clone());
+            }
+        }
+        let hashed_keys = state_keys
+            .iter()
+            .map(|key| hash_state_key(key))
+            .collect::<Result<NestedVec, SMTError>>()?;
+        let mut tree = SparseMerkleTree::new(root, key_length, consts::SUBTREE_HEIGHT);
+        let proof = tree.prove(db, &hashed_keys)?;
+
+        let mut entries = Vec::with_capacity(state_keys.len());
+        for ((key, hashed_key), query) in state_keys
+            .into_iter()
+            .zip(hashed_keys.iter())
+            .zip(proof.queries.iter())
+        {
+            if !is_included(hashed_key, query) {
+                entries.push(WitnessEntry { key, value: None });
+                continue;
+            }
+            let value = match get_value(&key)? {
+                Some(value) if value.hash_with_kind(HashKind::Value) == query.value() => value,
+                _ => {
+                    return Err(SMTError::NotFound(format!(
+                        "value of the key {} at the root",
+                        hex::encode(&key)
+                    )))
+                },
+            };
+            entries.push(WitnessEntry {
+                key,
+                value: Some(value),
+            });
+        }
+
+        Ok(Self {
+            root: root.to_vec(),
+            entries,
+            proof,
+        })
+    }
+
+    /// value returns the value of the state key in the witness, or None if the key is not in the witness.
+    /// The value is None in the result if the key does not exist in the state.
+    pub fn value(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.value.as_deref())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = codec::Writer::new();
+        writer.write_bytes(1, &self.root);
+        for entry in self.entries.iter() {
+            writer.write_message(2, |writer| {
+                writer.write_bytes(1, &entry.key);
+                writer.write_bool(2, entry.value.is_some());
+                if let Some(value) = &entry.value {
+                    writer.write_bytes(3, value);
+                }
+            });
+        }
+        writer.write_bytes_slice(3, &self.proof.sibling_hashes);
+        for query in self.proof.queries.iter() {
+            writer.write_message(4, |writer| {
+                writer.write_bytes(1, query.key());
+                writer.write_bytes(2, query.value());
+                writer.write_bytes(3, &query.bitmap);
+            });
+        }
+        writer.result().to_vec()
+    }
+
+    /// decode returns the witness created by encode.
+    /// The witness is received from the other nodes, so the data is decoded in the strict mode.
+    pub fn decode(data: &[u8]) -> Result<Self, codec::CodecError> {
+        let mut reader = codec::Reader::strict(data);
+        let root = reader.read_bytes(1)?;
+        let mut entries = vec![];
+        for mut reader in reader.read_messages(2)? {
+            let key = reader.read_bytes(1)?;
+            let exists = reader.read_bool(2)?;
+            let value = reader.read_bytes(3)?;
+            reader.finish()?;
+            entries.push(WitnessEntry {
+                key,
+                value: if exists { Some(value) } else { None },
+            });
+        }
+        let sibling_hashes = reader.read_bytes_slice(3)?;
+        let mut queries = vec![];
+        for mut reader in reader.read_messages(4)? {
+            let key = reader.read_bytes(1)?;
+            let value = reader.read_bytes(2)?;
+            let bitmap = reader.read_bytes(3)?;
+            reader.finish()?;
+            queries.push(QueryProof {
+                pair: Arc::new(KVPair::new(&key, &value)),
+                bitmap: Arc::new(bitmap),
+                stored_value: None,
+            });
+        }
+        reader.finish()?;
+        if queries.len() != entries.len() {
+            return Err(codec::CodecError::InvalidBytesLength);
+        }
+
+        Ok(Self {
+            root,
+            entries,
+            proof: Proof {
+                sibling_hashes,
+                queries,
+            },
+        })
+    }
+
+    /// verify returns true if the proof proves the value of each entry, or its absence, against the root.
+    pub fn verify(&self, key_length: KeyLength) -> Result<bool, SMTError> {
+        if self.entries.len() != self.proof.queries.len() {
+            return Ok(false);
+        }
+        let keys = self
+            .entries
+            .iter()
+            .map(|entry| hash_state_key(&entry.key))
+            .collect::<Result<NestedVec, SMTError>>()?;
+        for ((entry, key), query) in self
+            .entries
+            .iter()
+            .zip(keys.iter())
+            .zip(self.proof.queries.iter())
+        {
+            let valid = match &entry.value {
+                Some(value) => {
+                    is_included(key, query)
+                        && value.hash_with_kind(HashKind::Value) == query.value()
+                },
+                None => !is_included(key, query),
+            };
+            if !valid {
+                return Ok(false);
+            }
+        }
+
+        SparseMerkleTree::verify(&keys, &self.proof, &self.root, key_length)
+    }
+}
+
+/// is_included returns true if the query proves the inclusion of the key.
+/// Otherwise, the query has the empty value or the leaf at the path of the key.
+fn is_included(key: &[u8], query: &QueryProof) -> bool {
+    query.key() == key && !query.value().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::sparse_merkle_tree::smt_db::InMemorySmtDB;
+    use crate::sparse_merkle_tree::UpdateData;
+    use crate::types::Cache;
+
+    fn state_key(i: u8) -> Vec<u8> {
+        [&[0, 0, 0, 1, 0, 0][..], &[i; 4]].concat()
+    }
+
+    fn create_state(count: u8) -> (InMemorySmtDB, Vec<u8>, HashMap<Vec<u8>, Vec<u8>>) {
+        let mut db = InMemorySmtDB::default();
+        let mut state = HashMap::new();
+        let mut data = Cache::new();
+        for i in 0..count {
+            let value = vec![i + 1; 8];
+            data.insert(
+                hash_state_key(&state_key(i)).unwrap(),
+                value.hash_with_kind(HashKind::Value),
+            );
+            state.insert(state_key(i), value);
+        }
+        let mut tree = SparseMerkleTree::new(&[], consts::KEY_LENGTH, consts::SUBTREE_HEIGHT);
+        let root = tree.commit(&mut db, &UpdateData::new_from(data)).unwrap();
+        let root = root.lock().unwrap().to_vec();
+        (db, root, state)
+    }
+
+    #[test]
+    fn test_witness() {
+        let (db, root, state) = create_state(20);
+        let keys = vec![state_key(3), state_key(30), state_key(7), state_key(3)];
+        let witness = Witness::generate(&db, &root, &keys, consts::KEY_LENGTH, |key| {
+            Ok(state.get(key).cloned())
+        })
+        .unwrap();
+
+        assert_eq!(witness.entries.len(), 3);
+        assert_eq!(witness.value(&state_key(3)), Some(Some(&[4; 8][..])));
+        assert_eq!(witness.value(&state_key(30)), Some(None));
+        assert_eq!(witness.value(&state_key(8)), None);
+        assert!(witness.verify(consts::KEY_LENGTH).unwrap());
+
+        let decoded = Witness::decode(&witness.encode()).unwrap();
+        assert_eq!(decoded.encode(), witness.encode());
+        assert_eq!(decoded.entries, witness.entries);
+        assert!(decoded.verify(consts::KEY_LENGTH).unwrap());
+        let trailing = [witness.encode(), vec![0x08, 0x01]].concat();
+        assert!(Witness::decode(&trailing).is_err());
+
+        let mut modified = witness.clone();
+        modified.entries[0].value = Some(vec![0; 8]);
+        assert!(!modified.verify(consts::KEY_LENGTH).unwrap());
+        let mut modified = witness.clone();
+        modified.entries[1].value = Some(vec![0; 8]);
+        assert!(!modified.verify(consts::KEY_LENGTH).unwrap());
+        let mut modified = witness;
+        modified.root = vec![0; 32];
+        assert!(!modified.verify(consts::KEY_LENGTH).unwrap());
+    }
+
+    #[test]
+    fn test_witness_missing_value() {
+        let (db, root, _) = create_state(5);
+        let result = Witness::generate(&db, &root, &[state_key(1)], consts::KEY_LENGTH, |_| {
+            Ok(Some(vec![0; 8]))
+        });
+        assert!(matches!(result, Err(SMTError::NotFound(_))));
+    }
+}
@@ -0,0 +1,301 @@
+/// async_state_db provides the async API of the state_db for the Rust services embedding the crate.
+/// RocksDB and SMT computations run on the dedicated TaskPool, so the futures never block the async runtime.
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+use crate::consts;
+use crate::database::column_family;
+use crate::database::lock::{self, OpenError};
+use crate::database::options::IterationOption;
+use crate::database::traits::Unwrap;
+use crate::database::types::{DbOptions, Kind};
+use crate::database::utils as DbUtils;
+use crate::database::DB;
+use crate::metrics;
+use crate::sparse_merkle_tree::smt::{self, SMTError};
+use crate::sparse_merkle_tree::smt_db;
+use crate::state::root_history;
+use crate::state::state_db::{Commit, CommitData, CommitError, ExpectedState, StateDB};
+use crate::state::state_writer::StateWriter;
+use crate::task_pool::{SerialQueue, Task, TaskError, TaskPool};
+use crate::types::{BlockHeight, CommitOptions, KVPair, NestedVec, RecoverLock, SharedKVPair};
+
+#[derive(Error, Debug)]
+pub enum AsyncStateDBError {
+    #[error("Task error `{0}`")]
+    Task(#[from] TaskError),
+    #[error("Database error `{0}`")]
+    Database(#[from] rocksdb::Error),
+    #[error("SMT error `{0}`")]
+    SMT(#[from] SMTError),
+    #[error("Commit error `{0}`")]
+    Commit(#[from] CommitError),
+    #[error("Readonly DB cannot be committed")]
+    Readonly,
+}
+
+/// AsyncStateDB is the state_db whose operations return futures.
+pub struct AsyncStateDB {
+    conn: Arc<DB>,
+    options: DbOptions,
+    pool: TaskPool,
+}
+
+impl Drop for AsyncStateDB {
+    /// drop waits for the operations already spawned, so the database is released once it returns.
+    fn drop(&mut self) {
+        self.pool.shutdown();
+    }
+}
+
+impl AsyncStateDB {
+    /// open opens the state_db at the path with the number of worker threads.
+    pub fn open(
+        path: impl AsRef<Path>,
+        options: DbOptions,
+        workers: usize,
+    ) -> Result<Self, OpenError> {
+        let options = options.with_metrics(metrics::Instance::next());
+        let option = options.tuning().rocksdb_options()?;
+        let db = lock::open_with_column_families(&option, path, &options)?;
+        root_history::check_mode(&db, options.keep_root_history(), options.is_readonly())?;
+        // callbacks are never sent because the results are returned by the tasks
+
+        Ok(Self {
+            conn: Arc::new(DB::new(db, SerialQueue::closed(), Kind::State)),
+            options,
+            pool: TaskPool::new(workers),
+        })
+    }
+
+    /// get returns the value of the key in the state.
+    pub async fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, AsyncStateDBError> {
+        let conn = Arc::clone(&self.conn);
+        let task: Task<Result<_, rocksdb::Error>> =
+            self.pool.spawn(move || conn.get(&Kind::State.key(key)));
+        Ok(task.await??)
+    }
+
+    /// commit applies the pairs to the state at the height and returns the next root.
+    /// A pair with an empty value deletes the key.
+    pub async fn commit(
+        &self,
+        prev_root: Vec<u8>,
+        height: BlockHeight,
+        pairs: Vec<KVPair>,
+    ) -> Result<Vec<u8>, AsyncStateDBError> {
+        self.commit_with(prev_root, height, pairs, None).await
+    }
+
+    /// commit_on applies the pairs on top of the expected current state at the height.
+    /// It fails with CommitError::Conflict if another commit has changed the current state first,
+    /// so the candidate blocks can be executed speculatively on the same state.
+    pub async fn commit_on(
+        &self,
+        expected: ExpectedState,
+        height: BlockHeight,
+        pairs: Vec<KVPair>,
+    ) -> Result<Vec<u8>, AsyncStateDBError> {
+        let prev_root = expected.root.clone();
+        self.commit_with(prev_root, height, pairs, Some(expected))
+            .await
+    }
+
+    async fn commit_with(
+        &self,
+        prev_root: Vec<u8>,
+        height: BlockHeight,
+        pairs: Vec<KVPair>,
+        expected: Option<ExpectedState>,
+    ) -> Result<Vec<u8>, AsyncStateDBError> {
+        if self.options.is_readonly() {
+            return Err(AsyncStateDBError::Readonly);
+        }
+        let conn = Arc::clone(&self.conn);
+        let options = self.options;
+        let task = self
+            .pool
+            .spawn(move || -> Result<Vec<u8>, AsyncStateDBError> {
+                let writer = Mutex::new(StateWriter::default());
+                {
+                    let mut writer = writer.lock_or_recover();
+                    for pair in pairs.iter() {
+                        stage_pair(&conn, &mut writer, pair)?;
+                    }
+                }
+                let commit = Commit::new(vec![], CommitOptions::new(false, height), false)
+                    .with_expected_state(expected);
+                let commit_data = CommitData::new(commit, prev_root);
+                let root =
+                    StateDB::commit_writer(&conn, writer.lock_or_recover(), commit_data, options)?;
+                let root = root.lock_or_recover().to_vec();
+                Ok(root)
+            });
+        task.await?
+    }
+
+    /// prove returns the proof of the queries against the root.
+    pub async fn prove(
+        &self,
+        root: Vec<u8>,
+        queries: NestedVec,
+    ) -> Result<smt::Proof, AsyncStateDBError> {
+        let conn = Arc::clone(&self.conn);
+        let key_length = self.options.key_length();
+        let metrics = self.options.metrics();
+        let task = self.pool.spawn(move || {
+            let smt_db = smt_db::SmtReader::new(&conn);
+            let mut tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT)
+                .with_metrics(metrics);
+            tree.prove_parallel(&smt_db, &queries)
+        });
+        Ok(task.await??)
+    }
+
+    /// iterate returns the key-value pairs of the state in the range of the options.
+    pub async fn iterate(
+        &self,
+        options: IterationOption,
+    ) -> Result<Vec<KVPair>, AsyncStateDBError> {
+        let conn = Arc::clone(&self.conn);
+        let task = self
+            .pool
+            .spawn(move || -> Result<Vec<KVPair>, rocksdb::Error> {
+                let db = conn.arc_clone();
+                let mut start = vec![];
+                let mode = DbUtils::get_iteration_mode(&options, &mut start, true);
+                let iter = column_family::iterator_opt(
+                    db.unwrap(),
+                    consts::Prefix::STATE,
+                    mode,
+                    options.is_within_store_prefix(),
+                );
+                let mut pairs = vec![];
+                for (counter, key_val) in iter.enumerate() {
+                    let (key, value) = key_val?;
+                    if !key.starts_with(consts::Prefix::STATE)
+                        || DbUtils::is_key_out_of_range(&options, &key, counter as i64, true)
+                    {
+                        break;
+                    }
+                    pairs.push(KVPair::new(&key[consts::Prefix::STATE.len()..], &value));
+                }
+                Ok(pairs)
+            });
+        Ok(task.await??)
+    }
+}
+
+/// stage_pair adds the pair to the writer in the same way as StateReadWriter set and del.
+fn stage_pair(
+    conn: &DB,
+    writer: &mut StateWriter,
+    pair: &KVPair,
+) -> Result<(), AsyncStateDBError> {
+    let key = pair.key();
+    if !writer.is_cached(key) {
+        match conn.get(&Kind::State.key(key.to_vec()))? {
+            Some(value) => writer.cache_existing(&SharedKVPair::new(key, &value)),
+            None if pair.is_empty_value() => return Ok(()),
+            None => {
+                writer.cache_new(&SharedKVPair::new(key, pair.value()));
+                return Ok(());
+            },
+        }
+    }
+    if pair.is_empty_value() {
+        writer.delete(key);
+    } else {
+        writer
+            .update(pair)
+            .map_err(|err| SMTError::Unknown(err.to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::task_pool::tests::block_on;
+    use crate::types::KeyLength;
+
+    fn iteration_option() -> IterationOption {
+        IterationOption {
+            limit: -1,
+            reverse: false,
+            gte: Some(vec![0; 8]),
+            lte: Some(vec![255; 8]),
+            gt: None,
+            lt: None,
+        }
+    }
+
+    #[test]
+    fn test_commit_and_prove() {
+        let temp_dir = TempDir::new("test_async_state_db").unwrap();
+        let options = DbOptions::new(false, KeyLength(38));
+        let db = AsyncStateDB::open(temp_dir.path(), options, 2).unwrap();
+
+        let pairs = vec![
+            KVPair::new(&[1; 8], &[11]),
+            KVPair::new(&[2; 8], &[22]),
+            KVPair::new(&[3; 8], &[33]),
+        ];
+        let root = block_on(db.commit(vec![], BlockHeight(1), pairs)).unwrap();
+        assert_eq!(block_on(db.get(vec![2; 8])).unwrap(), Some(vec![22]));
+
+        let next_root = block_on(db.commit(
+            root.clone(),
+            BlockHeight(2),
+            vec![KVPair::new(&[2; 8], &[]), KVPair::new(&[3; 8], &[34])],
+        ))
+        .unwrap();
+        assert_ne!(next_root, root);
+        assert_eq!(block_on(db.get(vec![2; 8])).unwrap(), None);
+        assert_eq!(
+            block_on(db.iterate(iteration_option())).unwrap(),
+            vec![KVPair::new(&[1; 8], &[11]), KVPair::new(&[3; 8], &[34])]
+        );
+
+        let queries = vec![vec![7; 38]];
+        let proof = block_on(db.prove(next_root.clone(), queries.clone())).unwrap();
+        assert!(
+            smt::SparseMerkleTree::verify(&queries, &proof, &next_root, KeyLength(38)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_commit_on_conflict() {
+        let temp_dir = TempDir::new("test_async_state_db_conflict").unwrap();
+        let options = DbOptions::new(false, KeyLength(38));
+        let db = AsyncStateDB::open(temp_dir.path(), options, 2).unwrap();
+
+        let root =
+            block_on(db.commit(vec![], BlockHeight(1), vec![KVPair::new(&[1; 8], &[1])])).unwrap();
+        let expected = ExpectedState {
+            root: root.clone(),
+            version: BlockHeight(1),
+        };
+        let next_root = block_on(db.commit_on(
+            expected.clone(),
+            BlockHeight(2),
+            vec![KVPair::new(&[2; 8], &[2])],
+        ))
+        .unwrap();
+
+        let result =
+            block_on(db.commit_on(expected, BlockHeight(2), vec![KVPair::new(&[3; 8], &[3])]));
+        match result {
+            Err(AsyncStateDBError::Commit(CommitError::Conflict(conflict))) => {
+                assert_eq!(conflict.current.root, next_root);
+                assert_eq!(conflict.current.version, BlockHeight(2));
+            },
+            _ => panic!("the second commit on the same state must conflict"),
+        }
+        assert_eq!(block_on(db.get(vec![3; 8])).unwrap(), None);
+    }
+}
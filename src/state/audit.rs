@@ -0,0 +1,344 @@
+/// audit keeps the hash chained records of the data removed from the state_db.
+/// Each record includes the hash of the previous record, therefore removing or modifying a record breaks the chain.
+use std::convert::TryFrom;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::codec;
+use crate::consts;
+use crate::database::DB;
+use crate::sparse_merkle_tree::smt::EMPTY_HASH;
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Invalid audit record `{0}`")]
+    InvalidRecord(#[from] codec::CodecError),
+    #[error("Unknown audit error `{0}`")]
+    Unknown(String),
+}
+
+/// Policy is the retention rule which removed the data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// DiffPruning removes the diffs below the finalized height.
+    DiffPruning = 0,
+    /// NodeRemoval removes the SMT nodes which are no longer referenced by the latest root.
+    NodeRemoval = 1,
+}
+
+impl Policy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Policy::DiffPruning => "diffPruning",
+            Policy::NodeRemoval => "nodeRemoval",
+        }
+    }
+
+    fn from_u32(val: u32) -> Result<Self, AuditError> {
+        match val {
+            0 => Ok(Policy::DiffPruning),
+            1 => Ok(Policy::NodeRemoval),
+            _ => Err(AuditError::InvalidRecord(codec::CodecError::OutOfRange)),
+        }
+    }
+}
+
+/// AuditRecord describes a single removal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub sequence: u32,
+    pub policy: Policy,
+    pub height: u32,
+    pub removed_count: u32,
+    pub removed_bytes: u64,
+    pub written_count: u32,
+    pub written_bytes: u64,
+    /// removed_digest is the hash of all the removed keys in the order of removal.
+    pub removed_digest: Vec<u8>,
+    pub prev_hash: Vec<u8>,
+}
+
+/// append puts the record of the removal following `last` to the batch, and makes it the last record,
+/// so that the records written by the same batch are chained to each other.
+pub fn append(
+    batch: &mut rocksdb::WriteBatch,
+    last: &mut Option<AuditRecord>,
+    removal: Removal,
+    policy: Policy,
+    height: u32,
+) {
+    let record = removal.into_record(last.as_ref(), policy, height);
+    batch.put(record.key(), record.encode());
+    *last = Some(record);
+}
+
+/// entry_size returns the bytes of the key and the value.
+fn entry_size(key: &[u8], value_size: usize) -> u64 {
+    u64::try_from(key.len().saturating_add(value_size)).unwrap_or(u64::MAX)
+}
+
+/// Removal accumulates the removed and written data of a single operation.
+pub struct Removal {
+    removed_count: u32,
+    removed_bytes: u64,
+    written_count: u32,
+    written_bytes: u64,
+    hasher: Sha256,
+}
+
+/// NodeRemoval collects the SMT nodes removed and written by a commit.
+struct NodeRemoval<'a> {
+    conn: &'a DB,
+    removal: Removal,
+}
+
+impl Removal {
+    pub fn new() -> Self {
+        Self {
+            removed_count: 0,
+            removed_bytes: 0,
+            written_count: 0,
+            written_bytes: 0,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8], value_size: usize) {
+        self.hasher.update((key.len() as u32).to_be_bytes());
+        self.hasher.update(key);
+        self.removed_count = self.removed_count.saturating_add(1);
+        self.removed_bytes = self
+            .removed_bytes
+            .saturating_add(entry_size(key, value_size));
+    }
+
+    pub fn write(&mut self, key: &[u8], value_size: usize) {
+        self.written_count = self.written_count.saturating_add(1);
+        self.written_bytes = self
+            .written_bytes
+            .saturating_add(entry_size(key, value_size));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed_count == 0
+    }
+
+    /// into_record creates the record which follows the previous record.
+    pub fn into_record(
+        self,
+        prev: Option<&AuditRecord>,
+        policy: Policy,
+        height: u32,
+    ) -> AuditRecord {
+        let (sequence, prev_hash) = match prev {
+            Some(record) => (record.sequence + 1, record.hash()),
+            None => (0, EMPTY_HASH.to_vec()),
+        };
+        AuditRecord {
+            sequence,
+            policy,
+            height,
+            removed_count: self.removed_count,
+            removed_bytes: self.removed_bytes,
+            written_count: self.written_count,
+            written_bytes: self.written_bytes,
+            removed_digest: self.hasher.finalize().to_vec(),
+            prev_hash,
+        }
+    }
+}
+
+impl Default for Removal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl rocksdb::WriteBatchIterator for NodeRemoval<'_> {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        self.removal.write(&key, value.len());
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        let size = self
+            .conn
+            .get(&[consts::Prefix::SMT, &key].concat())
+            .ok()
+            .flatten()
+            .map_or(0, |val| val.len());
+        self.removal.remove(&key, size);
+    }
+}
+
+/// node_removal returns the removal of the SMT nodes in the batch created by SmtDB.
+pub fn node_removal(conn: &DB, batch: &rocksdb::WriteBatch) -> Removal {
+    let mut collector = NodeRemoval {
+        conn,
+        removal: Removal::new(),
+    };
+    batch.iterate(&mut collector);
+    collector.removal
+}
+
+impl AuditRecord {
+    pub fn key(&self) -> Vec<u8> {
+        [consts::Prefix::AUDIT, &self.sequence.to_be_bytes()].concat()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, self.sequence);
+        writer.write_u32(2, self.policy as u32);
+        writer.write_u32(3, self.height);
+        writer.write_u32(4, self.removed_count);
+        writer.write_u64(5, self.removed_bytes);
+        writer.write_u32(6, self.written_count);
+        writer.write_u64(7, self.written_bytes);
+        writer.write_bytes(8, &self.removed_digest);
+        writer.write_bytes(9, &self.prev_hash);
+        writer.result().to_vec()
+    }
+
+    pub fn decode(val: &[u8]) -> Result<Self, AuditError> {
+        let mut reader = codec::Reader::new(val);
+        Ok(Self {
+            sequence: reader.read_u32(1)?,
+            policy: Policy::from_u32(reader.read_u32(2)?)?,
+            height: reader.read_u32(3)?,
+            removed_count: reader.read_u32(4)?,
+            removed_bytes: reader.read_u64(5)?,
+            written_count: reader.read_u32(6)?,
+            written_bytes: reader.read_u64(7)?,
+            removed_digest: reader.read_bytes(8)?,
+            prev_hash: reader.read_bytes(9)?,
+        })
+    }
+
+    /// hash returns the hash of the encoded record, which is referred by the next record.
+    pub fn hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.encode());
+        hasher.finalize().to_vec()
+    }
+}
+
+/// last_record returns the latest record, or None if nothing was removed yet.
+pub fn last_record(conn: &rocksdb::DB) -> Result<Option<AuditRecord>, AuditError> {
+    let end = [consts::Prefix::AUDIT, &u32::MAX.to_be_bytes()].concat();
+    let mut iter = conn.iterator(rocksdb::IteratorMode::From(
+        &end,
+        rocksdb::Direction::Reverse,
+    ));
+    match iter.next() {
+        Some(Ok((key, value))) if key.starts_with(consts::Prefix::AUDIT) => {
+            Ok(Some(AuditRecord::decode(&value)?))
+        },
+        Some(Err(err)) => Err(AuditError::Unknown(err.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// get_records returns all the records in the order of the sequence.
+pub fn get_records(conn: &rocksdb::DB) -> Result<Vec<AuditRecord>, AuditError> {
+    let iter = conn.iterator(rocksdb::IteratorMode::From(
+        consts::Prefix::AUDIT,
+        rocksdb::Direction::Forward,
+    ));
+    let mut records = vec![];
+    for key_val in iter {
+        let (key, value) = key_val.map_err(|err| AuditError::Unknown(err.to_string()))?;
+        if !key.starts_with(consts::Prefix::AUDIT) {
+            break;
+        }
+        records.push(AuditRecord::decode(&value)?);
+    }
+    Ok(records)
+}
+
+/// verify_chain returns true if the records are continuous and each record refers to the hash of the previous record.
+pub fn verify_chain(records: &[AuditRecord]) -> bool {
+    let mut prev: Option<&AuditRecord> = None;
+    for record in records {
+        let (sequence, prev_hash) = match prev {
+            Some(prev) => (prev.sequence + 1, prev.hash()),
+            None => (0, EMPTY_HASH.to_vec()),
+        };
+        if record.sequence != sequence || record.prev_hash != prev_hash {
+            return false;
+        }
+        prev = Some(record);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_record(prev: Option<&AuditRecord>, keys: &[&[u8]]) -> AuditRecord {
+        let mut removal = Removal::new();
+        for key in keys {
+            removal.remove(key, 10);
+        }
+        removal.write(&[9; 4], 4);
+        removal.into_record(prev, Policy::DiffPruning, 5)
+    }
+
+    #[test]
+    fn test_removal_into_record() {
+        let record = create_record(None, &[&[1, 2], &[3, 4, 5]]);
+        assert_eq!(record.sequence, 0);
+        assert_eq!(record.removed_count, 2);
+        assert_eq!(record.removed_bytes, 25);
+        assert_eq!(record.written_count, 1);
+        assert_eq!(record.written_bytes, 8);
+        assert_eq!(record.prev_hash, EMPTY_HASH.to_vec());
+
+        let same = create_record(None, &[&[1, 2], &[3, 4, 5]]);
+        assert_eq!(record.removed_digest, same.removed_digest);
+        let different = create_record(None, &[&[1, 2, 3], &[4, 5]]);
+        assert_ne!(record.removed_digest, different.removed_digest);
+
+        let next = create_record(Some(&record), &[&[6]]);
+        assert_eq!(next.sequence, 1);
+        assert_eq!(next.prev_hash, record.hash());
+    }
+
+    #[test]
+    fn test_record_encoding() {
+        let record = create_record(None, &[&[1, 2]]);
+        let decoded = AuditRecord::decode(&record.encode()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.hash(), record.hash());
+    }
+
+    #[test]
+    fn test_removal_over_u32() {
+        let mut removal = Removal::new();
+        let size = u32::MAX as usize;
+        removal.remove(&[1], size);
+        removal.remove(&[2], size);
+        let record = removal.into_record(None, Policy::NodeRemoval, 1);
+        assert_eq!(record.removed_bytes, 2 * (u64::from(u32::MAX) + 1));
+        assert_eq!(AuditRecord::decode(&record.encode()).unwrap(), record);
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let first = create_record(None, &[&[1]]);
+        let second = create_record(Some(&first), &[&[2]]);
+        let third = create_record(Some(&second), &[&[3]]);
+        assert!(verify_chain(&[]));
+        assert!(verify_chain(&[
+            first.clone(),
+            second.clone(),
+            third.clone()
+        ]));
+        assert!(!verify_chain(&[first.clone(), third.clone()]));
+
+        let mut modified = second;
+        modified.removed_count += 1;
+        assert!(!verify_chain(&[first, modified, third]));
+    }
+}
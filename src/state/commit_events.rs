@@ -0,0 +1,89 @@
+/// commit_events notifies the listeners registered from JS whenever a commit is written to the state_db,
+/// so that the state can be indexed as it changes instead of polling the current state.
+use std::sync::{Arc, Mutex};
+
+use neon::prelude::*;
+
+use crate::database::utils as DbUtils;
+use crate::diff::DiffStats;
+use crate::types::{BlockHeight, RecoverLock};
+
+/// CommitEvent describes the commit written to the state_db.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitEvent {
+    pub root: Vec<u8>,
+    pub height: BlockHeight,
+    pub stats: DiffStats,
+}
+
+#[derive(Default)]
+struct Listeners {
+    next_id: u32,
+    listeners: Vec<(u32, Arc<Root<JsFunction>>)>,
+}
+
+/// CommitListeners keeps the listeners in the order of the subscription.
+/// Each listener is identified by the id returned on subscribe, which is used to unsubscribe it.
+#[derive(Clone, Default)]
+pub struct CommitListeners(Arc<Mutex<Listeners>>);
+
+impl CommitListeners {
+    pub fn subscribe(&self, listener: Root<JsFunction>) -> u32 {
+        let mut inner = self.0.lock_or_recover();
+        let id = inner.next_id;
+        inner.next_id = inner.next_id.wrapping_add(1);
+        inner.listeners.push((id, Arc::new(listener)));
+        id
+    }
+
+    /// unsubscribe returns false if the listener was not subscribed.
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        let mut inner = self.0.lock_or_recover();
+        let len = inner.listeners.len();
+        inner
+            .listeners
+            .retain(|(listener_id, _)| *listener_id != id);
+        inner.listeners.len() != len
+    }
+
+    pub fn clear(&self) {
+        self.0.lock_or_recover().listeners.clear();
+    }
+
+    /// emit calls the listeners with the event.
+    /// The listeners are taken before calling them, so that they can unsubscribe from the listener.
+    pub fn emit<'a, C: Context<'a>>(&self, ctx: &mut C, event: &CommitEvent) -> NeonResult<()> {
+        let listeners: Vec<Arc<Root<JsFunction>>> = self
+            .0
+            .lock()
+            .unwrap()
+            .listeners
+            .iter()
+            .map(|(_, listener)| Arc::clone(listener))
+            .collect();
+        for listener in listeners {
+            let listener = listener.to_inner(ctx);
+            let this = ctx.undefined();
+            let obj = commit_event_to_js_object(ctx, event)?;
+            listener.call(ctx, this, vec![obj])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// commit_event_to_js_object converts the event to { root, height, diffStats }.
+fn commit_event_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    event: &CommitEvent,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let root = JsBuffer::external(ctx, event.root.clone());
+    obj.set(ctx, "root", root)?;
+    let height = ctx.number(u32::from(event.height));
+    obj.set(ctx, "height", height)?;
+    let stats = DbUtils::diff_stats_to_js_object(ctx, &event.stats)?;
+    obj.set(ctx, "diffStats", stats)?;
+
+    Ok(obj)
+}
@@ -0,0 +1,92 @@
+/// debug_report collects the statistics of the keys stored under each prefix of the state_db.
+use thiserror::Error;
+
+use crate::consts;
+use crate::database::column_family;
+
+/// PREFIXES are the prefixes reported by prefix_stats with their names.
+pub const PREFIXES: [(&str, &[u8]); 15] = [
+    ("state", consts::Prefix::STATE),
+    ("smt", consts::Prefix::SMT),
+    ("diff", consts::Prefix::DIFF),
+    ("currentState", consts::Prefix::CURRENT_STATE),
+    ("rootHistory", consts::Prefix::ROOT_HISTORY),
+    ("audit", consts::Prefix::AUDIT),
+    ("finalized", consts::Prefix::FINALIZED),
+    ("lifetimeStats", consts::Prefix::LIFETIME_STATS),
+    ("smtKeyFilter", consts::Prefix::SMT_KEY_FILTER),
+    ("storeRoot", consts::Prefix::STORE_ROOT),
+    ("structureLog", consts::Prefix::STRUCTURE_LOG),
+    ("heightByRoot", consts::Prefix::HEIGHT_BY_ROOT),
+    ("rootHistoryMode", consts::Prefix::ROOT_HISTORY_MODE),
+    ("releasedNodes", consts::Prefix::RELEASED_NODES),
+    ("nodeRelease", consts::Prefix::NODE_RELEASE),
+];
+
+#[derive(Error, Debug)]
+pub enum DebugReportError {
+    #[error("Unknown debug report error `{0}`")]
+    Unknown(String),
+}
+
+/// PrefixStats is the number of the keys and the total bytes of the keys and values under the prefix.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrefixStats {
+    pub name: &'static str,
+    pub prefix: Vec<u8>,
+    pub keys: u64,
+    pub bytes: u64,
+}
+
+/// prefix_stats scans the database and returns the statistics of each prefix in PREFIXES.
+pub fn prefix_stats(conn: &rocksdb::DB) -> Result<Vec<PrefixStats>, DebugReportError> {
+    let mut result = Vec::with_capacity(PREFIXES.len());
+    for (name, prefix) in PREFIXES {
+        let mut stats = PrefixStats {
+            name,
+            prefix: prefix.to_vec(),
+            ..Default::default()
+        };
+        let mode = rocksdb::IteratorMode::From(prefix, rocksdb::Direction::Forward);
+        let iter = column_family::iterator(conn, prefix, mode);
+        for key_val in iter {
+            let (key, value) =
+                key_val.map_err(|err| DebugReportError::Unknown(err.to_string()))?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            stats.keys += 1;
+            stats.bytes += (key.len() + value.len()) as u64;
+        }
+        result.push(stats);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_prefix_stats() {
+        let temp_dir = TempDir::new("test_debug_report").unwrap();
+        let conn = rocksdb::DB::open_default(temp_dir.path()).unwrap();
+        conn.put([consts::Prefix::STATE, &[1, 2]].concat(), [3, 4, 5])
+            .unwrap();
+        conn.put([consts::Prefix::STATE, &[6]].concat(), [7])
+            .unwrap();
+        conn.put([consts::Prefix::SMT, &[8]].concat(), [9]).unwrap();
+
+        let stats = prefix_stats(&conn).unwrap();
+        assert_eq!(stats.len(), PREFIXES.len());
+        assert_eq!(stats[0].name, "state");
+        assert_eq!(stats[0].keys, 2);
+        assert_eq!(stats[0].bytes, 9);
+        assert_eq!(stats[1].keys, 1);
+        assert_eq!(stats[1].bytes, 3);
+        assert!(stats[2..].iter().all(|s| s.keys == 0 && s.bytes == 0));
+    }
+}
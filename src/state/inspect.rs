@@ -0,0 +1,223 @@
+/// inspect reads the data directory of the state_db outside of the node for lisk-db-tool.
+/// The database is opened read only, so it can be inspected while the node is stopped or running.
+use std::convert::TryInto;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::codec::CodecError;
+use crate::consts;
+use crate::database::backup;
+use crate::database::column_family;
+use crate::database::types::{DbOptions, Kind};
+use crate::database::DB;
+use crate::diff::Diff;
+use crate::sparse_merkle_tree::smt::{SMTError, EMPTY_HASH};
+use crate::state::root_check::{self, RootCheck};
+use crate::state::state_db::CurrentState;
+use crate::task_pool::SerialQueue;
+use crate::types::{BlockHeight, KVPair, KeyLength, NestedVec};
+
+#[derive(Error, Debug)]
+pub enum InspectError {
+    #[error(transparent)]
+    Database(#[from] rocksdb::Error),
+    #[error("Invalid diff at height {height}: {source}")]
+    InvalidDiff { height: u32, source: CodecError },
+    #[error(transparent)]
+    Tree(#[from] SMTError),
+}
+
+/// DiffRecord is the keys changed by the commit of the height, which are restored by its revert.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffRecord {
+    pub height: u32,
+    pub created: NestedVec,
+    pub updated: NestedVec,
+    pub deleted: NestedVec,
+}
+
+/// open opens the database at the path read only, with the column families it has.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<rocksdb::DB, InspectError> {
+    let opts = DbOptions::new(true, consts::KEY_LENGTH);
+    let conn = column_family::open(&rocksdb::Options::default(), path.as_ref(), &opts)?;
+    Ok(conn)
+}
+
+/// current_state returns the root and the version of the latest commit.
+pub fn current_state(conn: &rocksdb::DB) -> Result<(Vec<u8>, BlockHeight), InspectError> {
+    match column_family::get(conn, consts::Prefix::CURRENT_STATE)? {
+        Some(bytes) => {
+            let state = CurrentState::from_bytes(&bytes);
+            Ok((state.root.to_vec(), state.version))
+        },
+        None => Ok((EMPTY_HASH.to_vec(), BlockHeight(0))),
+    }
+}
+
+/// pairs returns the pairs with the prefix in the order of the keys.
+/// With the empty prefix, the keys of each column family follow the keys of the default one.
+pub fn pairs<'a>(
+    conn: &'a rocksdb::DB,
+    prefix: &'a [u8],
+) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + 'a {
+    let mut prefixes = vec![prefix];
+    if prefix.is_empty() {
+        prefixes.extend(
+            column_family::COLUMN_FAMILIES
+                .iter()
+                .filter(|(name, _)| conn.cf_handle(name).is_some())
+                .map(|(_, prefix)| *prefix),
+        );
+    }
+    prefixes
+        .into_iter()
+        .flat_map(move |start| {
+            let mode = rocksdb::IteratorMode::From(start, rocksdb::Direction::Forward);
+            column_family::iterator(conn, start, mode)
+        })
+        .take_while(move |key_val| match key_val {
+            Ok((key, _)) => key.starts_with(prefix),
+            Err(_) => true,
+        })
+}
+
+/// diffs returns the diffs of the heights from `from` to `to` inclusive.
+pub fn diffs(conn: &rocksdb::DB, from: u32, to: u32) -> Result<Vec<DiffRecord>, InspectError> {
+    let start = [consts::Prefix::DIFF, &from.to_be_bytes()].concat();
+    let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+    let mut records = vec![];
+    for key_val in column_family::iterator(conn, consts::Prefix::DIFF, mode) {
+        let (key, value) = key_val?;
+        if !key.starts_with(consts::Prefix::DIFF) {
+            break;
+        }
+        let height = match key[consts::Prefix::DIFF.len()..].try_into() {
+            Ok(height) => u32::from_be_bytes(height),
+            Err(_) => continue,
+        };
+        if height > to {
+            break;
+        }
+        let diff =
+            Diff::decode(&value).map_err(|source| InspectError::InvalidDiff { height, source })?;
+        let keys = |pairs: &[KVPair]| -> NestedVec {
+            pairs.iter().map(|pair| pair.key_as_vec()).collect()
+        };
+        records.push(DiffRecord {
+            height,
+            created: diff.created().clone(),
+            updated: keys(diff.updated()),
+            deleted: keys(diff.deleted()),
+        });
+    }
+
+    Ok(records)
+}
+
+/// verify checks the expected root, which is the current root if it is not given, against the state.
+/// The subtrees are never repaired, since the database is read only.
+pub fn verify(
+    conn: rocksdb::DB,
+    expected_root: Option<&[u8]>,
+    key_length: KeyLength,
+) -> Result<RootCheck, InspectError> {
+    let (current_root, _) = current_state(&conn)?;
+    // the callbacks are never sent to the database, so the receiver is not used
+    let db = DB::new(conn, SerialQueue::closed(), Kind::State);
+    let expected_root = expected_root.unwrap_or(&current_root);
+    let check = root_check::verify(&db, expected_root, &current_root, key_length, false)?;
+    Ok(check)
+}
+
+/// export_backup creates a new backup of the database in the directory.
+pub fn export_backup<P: AsRef<Path>>(conn: &rocksdb::DB, dir: P) -> Result<(), InspectError> {
+    backup::create_opt(conn, dir, false)?;
+    Ok(())
+}
+
+/// import_backup restores the latest backup in the directory to the path of the database, which must be closed.
+pub fn import_backup<P: AsRef<Path>, T: AsRef<Path>>(dir: P, path: T) -> Result<(), InspectError> {
+    backup::restore(dir, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn write_state(path: &Path) {
+        let opts = DbOptions::new(false, consts::KEY_LENGTH);
+        let conn = column_family::open(&rocksdb::Options::default(), path, &opts).unwrap();
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put(
+            [consts::Prefix::STATE, &[0, 0, 0, 1, 0, 0, 1]].concat(),
+            [1],
+        );
+        batch.put(
+            [consts::Prefix::STATE, &[0, 0, 0, 1, 0, 0, 2]].concat(),
+            [2],
+        );
+        batch.put([consts::Prefix::SMT, &[3]].concat(), [3]);
+        batch.put(
+            consts::Prefix::CURRENT_STATE,
+            [&[7; 32][..], &5u32.to_be_bytes()].concat(),
+        );
+        for height in [4u32, 5] {
+            let diff = Diff::new(
+                vec![vec![height as u8]],
+                vec![KVPair::new(&[1], &[2])],
+                vec![],
+            );
+            batch.put(
+                [consts::Prefix::DIFF, &height.to_be_bytes()].concat(),
+                diff.encode(),
+            );
+        }
+        column_family::write(&conn, batch).unwrap();
+    }
+
+    #[test]
+    fn test_inspect() {
+        let temp_dir = TempDir::new("test_inspect").unwrap();
+        write_state(temp_dir.path());
+        let conn = open(temp_dir.path()).unwrap();
+
+        assert_eq!(current_state(&conn).unwrap(), (vec![7; 32], BlockHeight(5)));
+
+        let keys: NestedVec = pairs(&conn, consts::Prefix::STATE)
+            .map(|key_val| key_val.unwrap().0.to_vec())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![vec![0, 0, 0, 0, 1, 0, 0, 1], vec![0, 0, 0, 0, 1, 0, 0, 2]]
+        );
+        assert_eq!(pairs(&conn, &[]).count(), 6);
+
+        let records = diffs(&conn, 0, u32::MAX).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].height, 4);
+        assert_eq!(records[0].created, vec![vec![4]]);
+        assert_eq!(records[0].updated, vec![vec![1]]);
+        assert!(records[0].deleted.is_empty());
+        assert_eq!(diffs(&conn, 5, 5).unwrap().len(), 1);
+        assert!(diffs(&conn, 6, u32::MAX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backup() {
+        let temp_dir = TempDir::new("test_inspect").unwrap();
+        let path = temp_dir.path().join("data");
+        let backup_dir = temp_dir.path().join("backup");
+        let restored = temp_dir.path().join("restored");
+        write_state(&path);
+
+        let conn = open(&path).unwrap();
+        export_backup(&conn, &backup_dir).unwrap();
+        import_backup(&backup_dir, &restored).unwrap();
+        let conn = open(&restored).unwrap();
+        assert_eq!(current_state(&conn).unwrap(), (vec![7; 32], BlockHeight(5)));
+    }
+}
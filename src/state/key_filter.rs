@@ -0,0 +1,353 @@
+/// key_filter keeps the bloom filter of the leaf keys of the state_db for each range of heights.
+/// The keys are never removed from the filter, so the filter of a range holds the leaf keys of the roots of all its heights.
+/// The latest range is extended by each commit, and it is stored once under its first height instead of once per root.
+/// The lookup of the key ruled out by the filter of the height of the root returns without reading the subtrees.
+/// Once the latest filter is full, or the state_db does not have one, a larger filter is rebuilt from the state
+/// in the background, and the keys committed meanwhile are added to it, so the commit never walks the tree.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+#[cfg(test)]
+use std::thread;
+#[cfg(test)]
+use std::time::Duration;
+
+use crate::codec;
+use crate::consts;
+use crate::database::column_family;
+use crate::database::traits::Unwrap;
+use crate::database::DB;
+use crate::sparse_merkle_tree::bloom::BloomFilter;
+use crate::sparse_merkle_tree::smt::{SMTError, UpdateData};
+use crate::state::root_registry;
+use crate::state::state_db::CurrentState;
+use crate::task_pool;
+use crate::types::{BlockHeight, HashKind, HashWithKind, RecoverLock};
+
+/// REBUILDS are the filters rebuilt in the background, by the path of their database.
+static REBUILDS: Mutex<Option<HashMap<PathBuf, Rebuild>>> = Mutex::new(None);
+static NEXT_REBUILD: AtomicU64 = AtomicU64::new(1);
+
+/// Rebuild is the filter rebuilt from the state, with the keys committed since the rebuild started.
+/// The rebuilt filter is taken by the next commit, which adds the pending keys to it.
+struct Rebuild {
+    id: u64,
+    built: Option<KeyFilter>,
+    pending: Vec<Vec<u8>>,
+}
+
+/// KeyFilter is the filter of the leaf keys of the roots from `from` to `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyFilter {
+    from: BlockHeight,
+    to: BlockHeight,
+    filter: BloomFilter,
+    /// replaced is the first height of the latest range replaced by the rebuilt filter.
+    replaced: Option<BlockHeight>,
+}
+
+impl KeyFilter {
+    #[inline]
+    pub fn from(&self) -> BlockHeight {
+        self.from
+    }
+
+    #[inline]
+    pub fn to(&self) -> BlockHeight {
+        self.to
+    }
+
+    #[inline]
+    pub fn filter(&self) -> &BloomFilter {
+        &self.filter
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = codec::Writer::new();
+        writer.write_u32(1, self.to.0);
+        writer.write_bytes(2, &self.filter.encode());
+        writer.result().to_vec()
+    }
+
+    fn decode(from: BlockHeight, data: &[u8]) -> Result<Self, codec::CodecError> {
+        let mut reader = codec::Reader::new(data);
+        let to = reader.read_u32(1)?;
+        let filter = BloomFilter::decode(&reader.read_bytes(2)?)?;
+
+        Ok(Self {
+            from,
+            to: BlockHeight(to),
+            filter,
+            replaced: None,
+        })
+    }
+}
+
+fn key(from: BlockHeight) -> Vec<u8> {
+    [consts::Prefix::SMT_KEY_FILTER, &from.to_be_bytes()].concat()
+}
+
+/// find returns the range with the highest first height at or below the version, which may end before it.
+fn find(conn: &rocksdb::DB, version: BlockHeight) -> Result<Option<KeyFilter>, SMTError> {
+    let prefix = consts::Prefix::SMT_KEY_FILTER;
+    let mut iter = conn.iterator(rocksdb::IteratorMode::From(
+        &key(version),
+        rocksdb::Direction::Reverse,
+    ));
+    match iter.next() {
+        Some(Ok((key, value))) if key.starts_with(prefix) && key.len() == prefix.len() + 4 => {
+            let mut from = [0; 4];
+            from.copy_from_slice(&key[prefix.len()..]);
+            KeyFilter::decode(BlockHeight(u32::from_be_bytes(from)), &value)
+                .map(Some)
+                .map_err(|err| SMTError::Unknown(err.to_string()))
+        },
+        Some(Err(err)) => Err(SMTError::Unknown(err.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// get returns the filter of the range with the version, or None if the version is not in any range.
+pub fn get(conn: &rocksdb::DB, version: BlockHeight) -> Result<Option<KeyFilter>, SMTError> {
+    Ok(find(conn, version)?.filter(|filter| filter.to.0 >= version.0))
+}
+
+/// get_for_root returns the filter of the height of the root, or None if the root does not have it.
+pub fn get_for_root(conn: &rocksdb::DB, root: &[u8]) -> Result<Option<BloomFilter>, SMTError> {
+    let version = root_registry::find_height(conn, root)
+        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+    match version {
+        Some(version) => Ok(get(conn, version)?.map(|filter| filter.filter)),
+        None => Ok(None),
+    }
+}
+
+/// next returns the filter of the version with the keys inserted by the update, which extends the latest range.
+/// None is returned if the latest range does not end at the previous version, and the filter is rebuilt for the next commits.
+/// The rebuild is also started once the filter is full, and the rebuilt filter replaces the latest range when it is ready.
+pub fn next(
+    conn: &DB,
+    version: BlockHeight,
+    data: &UpdateData,
+) -> Result<Option<KeyFilter>, SMTError> {
+    let (keys, values) = data.entries();
+    // the empty value deletes the key from the tree
+    let inserted: Vec<Vec<u8>> = keys
+        .iter()
+        .zip(values.iter())
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, _)| key.to_vec())
+        .collect();
+    let latest = find(conn.arc_clone().unwrap(), BlockHeight(u32::MAX))?;
+    let path = conn.path().to_path_buf();
+    let mut rebuilds = REBUILDS.lock_or_recover();
+    let rebuilds = rebuilds.get_or_insert_with(HashMap::new);
+    let built = rebuilds
+        .get(&path)
+        .is_some_and(|rebuild| rebuild.built.is_some());
+    if built {
+        if let Some(Rebuild {
+            built: Some(mut filter),
+            pending,
+            ..
+        }) = rebuilds.remove(&path)
+        {
+            for key in pending.iter().chain(inserted.iter()) {
+                filter.filter.insert(key);
+            }
+            filter.to = version;
+            filter.replaced = latest.map(|latest| latest.from);
+            return Ok(Some(filter));
+        }
+    }
+    if let Some(rebuild) = rebuilds.get_mut(&path) {
+        rebuild.pending.extend(inserted.iter().cloned());
+    }
+    let rebuilding = rebuilds.contains_key(&path);
+    let mut filter = match latest {
+        Some(filter) if filter.to.0.checked_add(1) == Some(version.0) => filter,
+        _ => {
+            if !rebuilding {
+                start_rebuild(conn, rebuilds, path, inserted);
+            }
+            return Ok(None);
+        },
+    };
+    for key in inserted.iter() {
+        filter.filter.insert(key);
+    }
+    filter.to = version;
+    if filter.filter.is_full() && !rebuilding {
+        start_rebuild(conn, rebuilds, path, inserted);
+    }
+
+    Ok(Some(filter))
+}
+
+/// start_rebuild builds the filter from the state on the shared task pool.
+/// The keys of the running commit are pending, since the state may be read before the commit is written.
+fn start_rebuild(
+    conn: &DB,
+    rebuilds: &mut HashMap<PathBuf, Rebuild>,
+    path: PathBuf,
+    pending: Vec<Vec<u8>>,
+) {
+    let id = NEXT_REBUILD.fetch_add(1, Ordering::Relaxed);
+    rebuilds.insert(
+        path.clone(),
+        Rebuild {
+            id,
+            built: None,
+            pending,
+        },
+    );
+    let db = conn.arc_clone();
+    let task_path = path.clone();
+    let result = task_pool::shared().execute(move || {
+        let built = build(db.unwrap());
+        drop(db);
+        let mut rebuilds = REBUILDS.lock_or_recover();
+        let rebuilds = rebuilds.get_or_insert_with(HashMap::new);
+        // the rebuild is dropped by the revert
+        if !rebuilds
+            .get(&task_path)
+            .is_some_and(|rebuild| rebuild.id == id)
+        {
+            return;
+        }
+        match built {
+            Ok(filter) => {
+                if let Some(rebuild) = rebuilds.get_mut(&task_path) {
+                    rebuild.built = Some(filter);
+                }
+            },
+            Err(err) => {
+                tracing::warn!(error = %err, "key filter rebuild failed");
+                rebuilds.remove(&task_path);
+            },
+        }
+    });
+    // the filter is rebuilt by the next commit
+    if result.is_err() {
+        rebuilds.remove(&path);
+    }
+}
+
+/// build returns the filter of the leaf keys of the current state, which has the room for twice the keys.
+/// The state is read from the snapshot, so the filter is of the version of the current state in it.
+fn build(conn: &rocksdb::DB) -> Result<KeyFilter, rocksdb::Error> {
+    let snapshot = column_family::Snapshot::new(conn);
+    let version = match snapshot.get(consts::Prefix::CURRENT_STATE)? {
+        Some(state) => CurrentState::from_bytes(&state).version,
+        None => BlockHeight(0),
+    };
+    let state_keys = || {
+        snapshot
+            .iterator(
+                consts::Prefix::STATE,
+                rocksdb::IteratorMode::From(consts::Prefix::STATE, rocksdb::Direction::Forward),
+            )
+            .take_while(|key_val| {
+                key_val
+                    .as_ref()
+                    .map_or(true, |(key, _)| key.starts_with(consts::Prefix::STATE))
+            })
+    };
+    let mut count = 0;
+    for key_val in state_keys() {
+        key_val?;
+        count += 1;
+    }
+    let mut filter = BloomFilter::with_capacity(count * 2);
+    for key_val in state_keys() {
+        let (key, _) = key_val?;
+        let state_key = key[consts::Prefix::STATE.len()..].to_vec();
+        filter.insert(&state_key.hash_with_kind(HashKind::Key));
+    }
+
+    Ok(KeyFilter {
+        from: version,
+        to: version,
+        filter,
+        replaced: None,
+    })
+}
+
+/// write puts the filter to the batch under the first height of its range.
+/// The range replaced by the rebuilt filter is deleted unless it is kept for the roots in the root history,
+/// in which case it is deleted by prune.
+pub fn write(batch: &mut rocksdb::WriteBatch, filter: &KeyFilter, keep_prev: bool) {
+    if let Some(replaced) = filter.replaced {
+        if !keep_prev && replaced != filter.from {
+            batch.delete(key(replaced));
+        }
+    }
+    batch.put(key(filter.from), filter.encode());
+}
+
+/// revert ends the latest range before the reverted version, or deletes it if it starts at the version.
+/// The filter rebuilt in the background is dropped, since it may have the state of the reverted version.
+pub fn revert(
+    conn: &DB,
+    batch: &mut rocksdb::WriteBatch,
+    version: BlockHeight,
+) -> Result<(), SMTError> {
+    if let Some(rebuilds) = REBUILDS.lock_or_recover().as_mut() {
+        rebuilds.remove(conn.path());
+    }
+    let mut filter = match find(conn.arc_clone().unwrap(), BlockHeight(u32::MAX))? {
+        Some(filter) if filter.to.0 >= version.0 => filter,
+        _ => return Ok(()),
+    };
+    if filter.from.0 >= version.0 {
+        batch.delete(key(filter.from));
+    } else {
+        filter.to = version - BlockHeight(1);
+        batch.put(key(filter.from), filter.encode());
+    }
+
+    Ok(())
+}
+
+/// prune adds the deletion of the ranges which are not needed for the heights from `until` to the batch of the block,
+/// since the next range starts at or below it. The filters are not read, so only the keys of the ranges are iterated.
+pub fn prune(
+    conn: &rocksdb::DB,
+    batch: &mut rocksdb::WriteBatch,
+    until: BlockHeight,
+) -> Result<(), String> {
+    let prefix = consts::Prefix::SMT_KEY_FILTER;
+    let last = key(until);
+    let mut prev: Option<Box<[u8]>> = None;
+    let mut iter = conn.raw_iterator();
+    iter.seek(prefix);
+    while let Some(range) = iter.key() {
+        if !range.starts_with(prefix) || range > last.as_slice() {
+            break;
+        }
+        if range.len() == prefix.len() + 4 {
+            if let Some(prev) = prev.replace(range.into()) {
+                batch.delete(prev);
+            }
+        }
+        iter.next();
+    }
+    iter.status().map_err(|err| err.to_string())
+}
+
+/// wait_for_rebuild blocks until the filter rebuilt for the database is ready to be taken by the next commit.
+#[cfg(test)]
+pub(crate) fn wait_for_rebuild(conn: &DB) {
+    loop {
+        let rebuilding = REBUILDS
+            .lock_or_recover()
+            .as_ref()
+            .and_then(|rebuilds| rebuilds.get(conn.path()))
+            .is_some_and(|rebuild| rebuild.built.is_none());
+        if !rebuilding {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,121 @@
+/// lifetime_stats keeps the cumulative statistics of the commits, so they survive restarts of the node.
+/// The statistics are written in the same batch as the commit, so they always match the committed state.
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::consts;
+
+const ENCODED_SIZE: usize = 24;
+
+#[derive(Error, Debug)]
+pub enum LifetimeStatsError {
+    #[error("Invalid lifetime stats")]
+    InvalidStats,
+    #[error("Unknown lifetime stats error `{0}`")]
+    Unknown(String),
+}
+
+/// LifetimeStats holds the totals since the database was created.
+/// - commits: number of the commits written to the database.
+/// - keys_written: number of the state keys set or deleted by the commits.
+/// - bytes_written: size of the write batches of the commits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LifetimeStats {
+    pub commits: u64,
+    pub keys_written: u64,
+    pub bytes_written: u64,
+}
+
+impl LifetimeStats {
+    pub fn encode(&self) -> Vec<u8> {
+        [
+            self.commits.to_be_bytes(),
+            self.keys_written.to_be_bytes(),
+            self.bytes_written.to_be_bytes(),
+        ]
+        .concat()
+    }
+
+    pub fn decode(val: &[u8]) -> Result<Self, LifetimeStatsError> {
+        if val.len() != ENCODED_SIZE {
+            return Err(LifetimeStatsError::InvalidStats);
+        }
+        Ok(Self {
+            commits: u64::from_be_bytes(val[..8].try_into().unwrap()),
+            keys_written: u64::from_be_bytes(val[8..16].try_into().unwrap()),
+            bytes_written: u64::from_be_bytes(val[16..].try_into().unwrap()),
+        })
+    }
+
+    /// add_commit returns the stats including a commit of the keys and the bytes.
+    pub fn add_commit(&self, keys_written: usize, bytes_written: usize) -> Self {
+        Self {
+            commits: self.commits.saturating_add(1),
+            keys_written: self.keys_written.saturating_add(keys_written as u64),
+            bytes_written: self.bytes_written.saturating_add(bytes_written as u64),
+        }
+    }
+}
+
+/// get returns the stats stored in the conn. It returns zeros if nothing was committed yet.
+pub fn get(conn: &rocksdb::DB) -> Result<LifetimeStats, LifetimeStatsError> {
+    match conn
+        .get(consts::Prefix::LIFETIME_STATS)
+        .map_err(|err| LifetimeStatsError::Unknown(err.to_string()))?
+    {
+        Some(val) => LifetimeStats::decode(&val),
+        None => Ok(LifetimeStats::default()),
+    }
+}
+
+/// record adds the stats including the commit to the batch.
+pub fn record(
+    conn: &rocksdb::DB,
+    batch: &mut rocksdb::WriteBatch,
+    keys_written: usize,
+    bytes_written: usize,
+) -> Result<(), LifetimeStatsError> {
+    let stats = get(conn)?.add_commit(keys_written, bytes_written);
+    batch.put(consts::Prefix::LIFETIME_STATS, stats.encode());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_lifetime_stats_encoding() {
+        let stats = LifetimeStats {
+            commits: 3,
+            keys_written: 10,
+            bytes_written: u64::MAX,
+        };
+        assert_eq!(LifetimeStats::decode(&stats.encode()).unwrap(), stats);
+        assert!(LifetimeStats::decode(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        let temp_dir = TempDir::new("test_lifetime_stats_").unwrap();
+        let conn = rocksdb::DB::open_default(temp_dir.path()).unwrap();
+        assert_eq!(get(&conn).unwrap(), LifetimeStats::default());
+
+        for _ in 0..2 {
+            let mut batch = rocksdb::WriteBatch::default();
+            record(&conn, &mut batch, 5, 100).unwrap();
+            conn.write(batch).unwrap();
+        }
+        assert_eq!(
+            get(&conn).unwrap(),
+            LifetimeStats {
+                commits: 2,
+                keys_written: 10,
+                bytes_written: 200,
+            }
+        );
+    }
+}
@@ -1,4 +1,30 @@
+/// async_state_db provides the async API of the state_db.
+pub mod async_state_db;
+/// audit records the data removed from the state_db.
+pub mod audit;
+/// commit_events notifies the listeners about the commits written to the state_db.
+pub mod commit_events;
+/// debug_report collects the statistics attached to the bug reports.
+pub mod debug_report;
+/// inspect reads the data directory of the state_db for lisk-db-tool.
+pub mod inspect;
+/// key_filter keeps the bloom filter of the SMT leaf keys of each root.
+pub mod key_filter;
+/// lifetime_stats keeps the statistics of the state_db across restarts.
+pub mod lifetime_stats;
+/// root_check recomputes the state root and repairs the SMT nodes of the root.
+pub mod root_check;
+/// root_history retains the SMT nodes of the root history and prunes them out of the retention window.
+pub mod root_history;
+/// root_registry keeps the state root of every committed height.
+pub mod root_registry;
+/// sentinel protects the finalized state from being rolled back.
+pub mod sentinel;
 /// state_db provides authenticated data storage using sparse merkle tree.
 pub mod state_db;
 /// state_writer provides batch writer for the state_db.
 pub mod state_writer;
+/// store_roots keeps the sub-root of each store prefix of the state.
+pub mod store_roots;
+/// structure_log records the SMT nodes created and deleted by each commit.
+pub mod structure_log;
@@ -0,0 +1,106 @@
+/// root_check recomputes the state root after an unclean shutdown, and repairs the SMT nodes of the root
+/// when they are missing or corrupted while the flat state still matches the root.
+use crate::consts;
+use crate::database::column_family;
+use crate::database::traits::Unwrap;
+use crate::database::DB;
+use crate::sparse_merkle_tree::smt::{self, SMTError, SparseMerkleTree};
+use crate::sparse_merkle_tree::smt_db::{InMemorySmtDB, SmtReader};
+use crate::types::{HashKind, HashWithKind, KVPair, KeyLength, NestedVec, RecoverLock};
+
+/// RootCheck is the result of verifying the expected root against the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootCheck {
+    pub expected_root: Vec<u8>,
+    /// current_root is the root of the current state.
+    pub current_root: Vec<u8>,
+    /// computed_root is the root recomputed from the flat state.
+    pub computed_root: Vec<u8>,
+    /// inconsistent_nodes are the subtrees of the expected root which were missing or corrupted.
+    pub inconsistent_nodes: NestedVec,
+    /// repaired is true if the subtrees of the expected root are rewritten from the flat state.
+    pub repaired: bool,
+}
+
+impl RootCheck {
+    /// is_valid returns true if the current state, the flat state and the subtrees match the expected root.
+    pub fn is_valid(&self) -> bool {
+        self.current_root == self.expected_root
+            && self.computed_root == self.expected_root
+            && (self.inconsistent_nodes.is_empty() || self.repaired)
+    }
+}
+
+/// compute_root builds the tree from all the keys of the flat state in memory.
+/// It returns the root with the nodes of the tree, which are written by the repair.
+pub fn compute_root(
+    conn: &DB,
+    key_length: KeyLength,
+) -> Result<(Vec<u8>, InMemorySmtDB), SMTError> {
+    let rocks_db = conn.arc_clone();
+    let iter = column_family::iterator(
+        rocks_db.unwrap(),
+        consts::Prefix::STATE,
+        rocksdb::IteratorMode::From(consts::Prefix::STATE, rocksdb::Direction::Forward),
+    );
+    let mut pairs = vec![];
+    for key_val in iter {
+        let (key, value) = key_val.map_err(|err| SMTError::Unknown(err.to_string()))?;
+        // without the column families, the other prefixes follow the state in the default one
+        if !key.starts_with(consts::Prefix::STATE) {
+            break;
+        }
+        let hashed_key = smt::hash_state_key(&key[consts::Prefix::STATE.len()..])?;
+        let hashed_value = value.to_vec().hash_with_kind(HashKind::Value);
+        pairs.push(KVPair::new(&hashed_key, &hashed_value));
+    }
+    pairs.sort_by(|a, b| a.key().cmp(b.key()));
+
+    let mut db = InMemorySmtDB::default();
+    let mut tree = SparseMerkleTree::new(&[], key_length, consts::SUBTREE_HEIGHT);
+    let root = tree.bulk_load(&mut db, pairs)?;
+    let root = root.lock_or_recover().to_vec();
+
+    Ok((root, db))
+}
+
+/// verify compares the expected root with the current state, the flat state and the stored subtrees.
+/// With `repair`, the subtrees of the expected root are rewritten from the flat state if only the subtrees diverge.
+/// The current state and the flat state are never changed, since the expected root cannot restore them.
+pub fn verify(
+    conn: &DB,
+    expected_root: &[u8],
+    current_root: &[u8],
+    key_length: KeyLength,
+    repair: bool,
+) -> Result<RootCheck, SMTError> {
+    let tree = SparseMerkleTree::new(expected_root, key_length, consts::SUBTREE_HEIGHT);
+    let inconsistent_nodes = tree.check_consistency(&SmtReader::new(conn), expected_root)?;
+    let (computed_root, nodes) = compute_root(conn, key_length)?;
+    let mut check = RootCheck {
+        expected_root: expected_root.to_vec(),
+        current_root: current_root.to_vec(),
+        computed_root,
+        inconsistent_nodes,
+        repaired: false,
+    };
+    if !repair
+        || check.inconsistent_nodes.is_empty()
+        || check.current_root != check.expected_root
+        || check.computed_root != check.expected_root
+    {
+        return Ok(check);
+    }
+
+    let mut batch = rocksdb::WriteBatch::default();
+    for (key, value) in nodes.cache().iter() {
+        batch.put([consts::Prefix::SMT, key].concat(), value);
+    }
+    // The nodes must not be removed by a commit while they are rewritten
+    let _guard = conn.lock_commit();
+    conn.write(batch)
+        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+    check.repaired = true;
+
+    Ok(check)
+}
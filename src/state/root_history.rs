@@ -0,0 +1,146 @@
+/// root_history retains the SMT nodes of the roots in the root history until the roots leave the retention window.
+/// With keepRootHistory, the nodes deleted by a commit are released at the height instead of being deleted,
+/// and the release is cleared when the node is created again. When the lowest retained height moves forward,
+/// the nodes released at or below it are deleted, since no retained root references them any more.
+/// The roots themselves stay in the root history, which is written by root_registry for every height.
+/// The mode is stored when the state_db is created, because the nodes deleted without keepRootHistory can not be restored.
+use std::collections::HashSet;
+
+use crate::consts;
+use crate::database::column_family;
+use crate::database::lock::OpenError;
+use crate::state::audit;
+use crate::types::BlockHeight;
+
+fn released_key(version: BlockHeight, node: &[u8]) -> Vec<u8> {
+    [consts::Prefix::RELEASED_NODES, &version.to_be_bytes(), node].concat()
+}
+
+fn release_key(node: &[u8]) -> Vec<u8> {
+    [consts::Prefix::NODE_RELEASE, node].concat()
+}
+
+/// Created clears the release of the nodes put by the batch created by SmtDB.
+struct Created<'a> {
+    batch: &'a mut rocksdb::WriteBatch,
+}
+
+impl rocksdb::WriteBatchIterator for Created<'_> {
+    fn put(&mut self, key: Box<[u8]>, _value: Box<[u8]>) {
+        self.batch.delete(release_key(&key));
+    }
+
+    fn delete(&mut self, _key: Box<[u8]>) {}
+}
+
+/// Put collects the nodes put by the batch created by SmtDB.
+#[derive(Default)]
+struct Put {
+    nodes: HashSet<Box<[u8]>>,
+}
+
+impl rocksdb::WriteBatchIterator for Put {
+    fn put(&mut self, key: Box<[u8]>, _value: Box<[u8]>) {
+        self.nodes.insert(key);
+    }
+
+    fn delete(&mut self, _key: Box<[u8]>) {}
+}
+
+/// check_mode returns RootHistoryMismatch if the state_db was created with the other keepRootHistory.
+/// The mode is stored on the first open, unless the state_db is opened as readonly.
+pub fn check_mode(
+    conn: &rocksdb::DB,
+    keep_root_history: bool,
+    readonly: bool,
+) -> Result<(), OpenError> {
+    match conn.get(consts::Prefix::ROOT_HISTORY_MODE)? {
+        Some(mode) => {
+            let stored = mode.as_slice() == [1];
+            if stored != keep_root_history {
+                return Err(OpenError::RootHistoryMismatch { stored });
+            }
+            Ok(())
+        },
+        None if readonly => Ok(()),
+        None => {
+            conn.put(
+                consts::Prefix::ROOT_HISTORY_MODE,
+                [u8::from(keep_root_history)],
+            )?;
+            Ok(())
+        },
+    }
+}
+
+/// release puts the nodes released at the version to the batch, and clears the release of the nodes
+/// created by `smt_batch`. The release of the node created and deleted by the same batch is kept.
+pub fn release(
+    batch: &mut rocksdb::WriteBatch,
+    version: BlockHeight,
+    smt_batch: &rocksdb::WriteBatch,
+    released: &HashSet<Vec<u8>>,
+) {
+    smt_batch.iterate(&mut Created { batch });
+    for node in released.iter() {
+        batch.put(released_key(version, node), []);
+        batch.put(release_key(node), version.to_be_bytes());
+    }
+}
+
+/// prune adds the deletion of the nodes released at or below `until` to the batch of the block, unless they are
+/// created again since or by `smt_batch`, which is written by the same batch. The deleted nodes are recorded
+/// following `last_audit` when `audit_deletion` is enabled.
+pub fn prune(
+    conn: &rocksdb::DB,
+    batch: &mut rocksdb::WriteBatch,
+    until: BlockHeight,
+    smt_batch: &rocksdb::WriteBatch,
+    last_audit: &mut Option<audit::AuditRecord>,
+    audit_deletion: bool,
+) -> Result<(), String> {
+    let mut created = Put::default();
+    smt_batch.iterate(&mut created);
+    let last = released_key(until, &[]);
+    let mut removal = audit::Removal::new();
+    let iter = conn.iterator(rocksdb::IteratorMode::From(
+        consts::Prefix::RELEASED_NODES,
+        rocksdb::Direction::Forward,
+    ));
+    for key_val in iter {
+        let (key, _) = key_val.map_err(|err| err.to_string())?;
+        if !key.starts_with(consts::Prefix::RELEASED_NODES)
+            || key.len() < last.len()
+            || &key[..last.len()] > last.as_slice()
+        {
+            break;
+        }
+        let (height, node) = key[consts::Prefix::RELEASED_NODES.len()..].split_at(4);
+        // the node created again after the release is referenced by the latest roots
+        let release = conn.get(release_key(node)).map_err(|err| err.to_string())?;
+        if release.as_deref() == Some(height) && !created.nodes.contains(node) {
+            let node_key = [consts::Prefix::SMT, node].concat();
+            if audit_deletion {
+                let size = column_family::get(conn, &node_key)
+                    .map_err(|err| err.to_string())?
+                    .map_or(0, |val| val.len());
+                removal.remove(node, size);
+            }
+            batch.delete(node_key);
+            batch.delete(release_key(node));
+        }
+        batch.delete(key);
+    }
+
+    if audit_deletion && !removal.is_empty() {
+        audit::append(
+            batch,
+            last_audit,
+            removal,
+            audit::Policy::NodeRemoval,
+            until.into(),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+/// root_registry keeps the state root of every committed height in the root history, and the heights of every root.
+/// The registry is always written, while the nodes of the roots are retained only with keepRootHistory,
+/// so only the roots are known for the heights out of the retention.
+/// Both indexes are keyed in the big endian order, so a lookup is a seek which binary searches the index blocks
+/// of the files instead of scanning the heights.
+use crate::consts;
+use crate::types::BlockHeight;
+
+fn root_key(version: BlockHeight) -> Vec<u8> {
+    [consts::Prefix::ROOT_HISTORY, &version.to_be_bytes()].concat()
+}
+
+fn height_key(root: &[u8], version: BlockHeight) -> Vec<u8> {
+    [consts::Prefix::HEIGHT_BY_ROOT, root, &version.to_be_bytes()].concat()
+}
+
+/// write puts the root of the version to the batch.
+pub fn write(batch: &mut rocksdb::WriteBatch, version: BlockHeight, root: &[u8]) {
+    batch.put(root_key(version), root);
+    batch.put(height_key(root, version), []);
+}
+
+/// remove deletes the root of the reverted version from the batch.
+pub fn remove(batch: &mut rocksdb::WriteBatch, version: BlockHeight, root: &[u8]) {
+    batch.delete(root_key(version));
+    batch.delete(height_key(root, version));
+}
+
+/// get_root returns the root committed at the version, or None if the version is not registered.
+pub fn get_root(
+    conn: &rocksdb::DB,
+    version: BlockHeight,
+) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+    conn.get(root_key(version))
+}
+
+/// find_height returns the highest version committed with the root, or None if the root is not registered.
+/// The same root is committed at several heights when the blocks do not change the state.
+pub fn find_height(
+    conn: &rocksdb::DB,
+    root: &[u8],
+) -> Result<Option<BlockHeight>, rocksdb::Error> {
+    let prefix = [consts::Prefix::HEIGHT_BY_ROOT, root].concat();
+    let end = height_key(root, BlockHeight(u32::MAX));
+    let mut iter = conn.iterator(rocksdb::IteratorMode::From(
+        &end,
+        rocksdb::Direction::Reverse,
+    ));
+    match iter.next() {
+        Some(Ok((key, _))) if key.starts_with(&prefix) && key.len() == prefix.len() + 4 => {
+            let mut height = [0; 4];
+            height.copy_from_slice(&key[prefix.len()..]);
+            Ok(Some(BlockHeight(u32::from_be_bytes(height))))
+        },
+        Some(Err(err)) => Err(err),
+        _ => Ok(None),
+    }
+}
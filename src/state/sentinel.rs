@@ -0,0 +1,157 @@
+/// sentinel keeps the finalized height and its root to refuse writing or reverting the finalized state.
+/// The sentinel only moves forward, and it is written with fsync so that it survives a crash.
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::consts;
+use crate::types::BlockHeight;
+
+#[derive(Error, Debug)]
+pub enum SentinelError {
+    #[error("Height {height} is at or below the finalized height {finalized}")]
+    Finalized { height: u32, finalized: u32 },
+    #[error("Invalid sentinel")]
+    InvalidSentinel,
+    #[error("Unknown sentinel error `{0}`")]
+    Unknown(String),
+}
+
+/// Sentinel is the latest finalized height and the root of the height.
+/// The root is empty if it was not known at the time of finalization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sentinel {
+    pub height: BlockHeight,
+    pub root: Vec<u8>,
+}
+
+impl Sentinel {
+    pub fn new(height: BlockHeight, root: Vec<u8>) -> Self {
+        Self { height, root }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        [self.height.to_be_bytes().as_slice(), &self.root].concat()
+    }
+
+    pub fn decode(val: &[u8]) -> Result<Self, SentinelError> {
+        if val.len() < 4 {
+            return Err(SentinelError::InvalidSentinel);
+        }
+        let height = u32::from_be_bytes(val[..4].try_into().unwrap());
+        Ok(Self::new(height.into(), val[4..].to_vec()))
+    }
+
+    /// check returns an error if the height is at or below the finalized height.
+    pub fn check(&self, height: BlockHeight) -> Result<(), SentinelError> {
+        if u32::from(height) > u32::from(self.height) {
+            return Ok(());
+        }
+        Err(SentinelError::Finalized {
+            height: height.into(),
+            finalized: self.height.into(),
+        })
+    }
+}
+
+/// get returns the sentinel, or None if nothing was finalized yet.
+pub fn get(conn: &rocksdb::DB) -> Result<Option<Sentinel>, SentinelError> {
+    conn.get(consts::Prefix::FINALIZED)
+        .map_err(|err| SentinelError::Unknown(err.to_string()))?
+        .map(|val| Sentinel::decode(&val))
+        .transpose()
+}
+
+/// check returns an error if the height is at or below the finalized height stored in the conn.
+pub fn check(conn: &rocksdb::DB, height: BlockHeight) -> Result<(), SentinelError> {
+    match get(conn)? {
+        Some(sentinel) => sentinel.check(height),
+        None => Ok(()),
+    }
+}
+
+/// advance adds the sentinel to the batch if it is higher than the current one.
+/// The batch must be written with sync write options.
+pub fn advance(
+    conn: &rocksdb::DB,
+    batch: &mut rocksdb::WriteBatch,
+    sentinel: Sentinel,
+) -> Result<(), SentinelError> {
+    if let Some(current) = get(conn)? {
+        if u32::from(current.height) >= u32::from(sentinel.height) {
+            return Ok(());
+        }
+    }
+    batch.put(consts::Prefix::FINALIZED, sentinel.encode());
+    Ok(())
+}
+
+/// sync_write_options returns the write options which flushes the write to the disk before returning.
+pub fn sync_write_options() -> rocksdb::WriteOptions {
+    let mut options = rocksdb::WriteOptions::default();
+    options.set_sync(true);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_sentinel_encoding() {
+        let sentinel = Sentinel::new(BlockHeight(10), vec![1; 32]);
+        let decoded = Sentinel::decode(&sentinel.encode()).unwrap();
+        assert_eq!(decoded, sentinel);
+
+        let empty_root = Sentinel::new(BlockHeight(3), vec![]);
+        assert_eq!(Sentinel::decode(&empty_root.encode()).unwrap(), empty_root);
+        assert!(Sentinel::decode(&[0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_sentinel_check() {
+        let sentinel = Sentinel::new(BlockHeight(10), vec![1; 32]);
+        assert!(sentinel.check(BlockHeight(11)).is_ok());
+        assert!(matches!(
+            sentinel.check(BlockHeight(10)),
+            Err(SentinelError::Finalized {
+                height: 10,
+                finalized: 10
+            })
+        ));
+        assert!(sentinel.check(BlockHeight(0)).is_err());
+    }
+
+    #[test]
+    fn test_advance() {
+        let temp_dir = TempDir::new("test_sentinel_").unwrap();
+        let conn = rocksdb::DB::open_default(temp_dir.path()).unwrap();
+        assert_eq!(get(&conn).unwrap(), None);
+        assert!(check(&conn, BlockHeight(0)).is_ok());
+
+        let mut batch = rocksdb::WriteBatch::default();
+        advance(
+            &conn,
+            &mut batch,
+            Sentinel::new(BlockHeight(5), vec![5; 32]),
+        )
+        .unwrap();
+        conn.write_opt(batch, &sync_write_options()).unwrap();
+        assert!(check(&conn, BlockHeight(5)).is_err());
+
+        let mut batch = rocksdb::WriteBatch::default();
+        advance(
+            &conn,
+            &mut batch,
+            Sentinel::new(BlockHeight(3), vec![3; 32]),
+        )
+        .unwrap();
+        assert!(batch.is_empty());
+        assert_eq!(
+            get(&conn).unwrap(),
+            Some(Sentinel::new(BlockHeight(5), vec![5; 32]))
+        );
+    }
+}
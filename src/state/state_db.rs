@@ -1,32 +1,57 @@
 use core::cell::RefCell;
 /// state_db is an authenticated storage using Sparse Merkle Tree extending Database using rocksdb.
 use std::cmp;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::sync::{mpsc, Arc, Mutex, MutexGuard};
-use std::thread;
+use std::time::Instant;
 
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 use thiserror::Error;
 
-use crate::batch;
+use crate::abort::AbortToken;
+use crate::batch::{self, SendableWriteBatch};
 use crate::consts;
+use crate::database::column_family;
+use crate::database::lock::OpenError;
 use crate::database::options;
+use crate::database::recovery::CallbackGuard;
 use crate::database::traits::{JsNewWithBoxRef, NewDBWithContext, OptionsWithContext, Unwrap};
 use crate::database::types::{ArcOptionDB, DbMessage, DbOptions, JsBoxRef, Kind};
 use crate::database::utils as DbUtils;
-use crate::database::utils::pair_to_js_object;
+use crate::database::utils::pair_into_js_object;
 use crate::database::DB;
+use crate::diagnostics::{self, Operation};
 use crate::diff;
+use crate::error_code::{self, ErrorCode};
+use crate::metrics;
+use crate::sparse_merkle_tree::chunk;
+use crate::sparse_merkle_tree::overlay::Overlay;
 use crate::sparse_merkle_tree::smt::{self, SMTError, EMPTY_HASH};
 use crate::sparse_merkle_tree::smt_db;
+use crate::sparse_merkle_tree::witness::Witness;
+use crate::state::audit;
+use crate::state::commit_events::{CommitEvent, CommitListeners};
+use crate::state::debug_report;
+use crate::state::key_filter;
+use crate::state::lifetime_stats;
+use crate::state::root_check::{self, RootCheck};
+use crate::state::root_history;
+use crate::state::root_registry;
+use crate::state::sentinel;
 use crate::state::state_writer;
+use crate::state::store_roots::{self, StoreRoot};
+use crate::state::structure_log::{self, StructureLog};
+use crate::task_pool;
 use crate::types::{
-    ArcMutex, BlockHeight, CommitOptions, KVPair, KeyLength, NestedVec, SharedVec,
+    ArcMutex, BlockHeight, CommitOptions, HashKind, HashWithKind, KVPair, NestedVec, RecoverLock,
+    SharedVec, PREFIX_SIZE,
 };
 use crate::utils;
 
 pub type SharedStateDB = JsBoxRef<StateDB>;
+pub type SharedOverlay = JsBoxRef<Overlay>;
 
 #[derive(Error, Debug)]
 pub enum DataStoreError {
@@ -34,21 +59,69 @@ pub enum DataStoreError {
     Unknown(String),
     #[error("Diff not found for height: `{0}`")]
     DiffNotFound(usize),
+    #[error("Rollback protection: {0}")]
+    Sentinel(#[from] sentinel::SentinelError),
+    #[error("Cannot revert height `{0}` from the current height `{1}`")]
+    NotCurrentHeight(u32, u32),
+    #[error("Invalid height range from `{0}` to `{1}`")]
+    InvalidHeightRange(u32, u32),
+}
+
+impl ErrorCode for DataStoreError {
+    fn code(&self) -> &'static str {
+        match self {
+            DataStoreError::Unknown(_) => error_code::UNKNOWN,
+            DataStoreError::DiffNotFound(_) => error_code::NOT_FOUND,
+            DataStoreError::Sentinel(_) => error_code::INVALID_USAGE,
+            DataStoreError::NotCurrentHeight(_, _) | DataStoreError::InvalidHeightRange(_, _) => {
+                error_code::INVALID_INPUT
+            },
+        }
+    }
+}
+
+/// CommitConflict is returned when the current state is not the one the commit expects,
+/// because another commit has landed first.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error(
+    "Commit conflict: expected the current state at height `{}`, but it is at height `{}`",
+    u32::from(.expected.version),
+    u32::from(.current.version)
+)]
+pub struct CommitConflict {
+    pub expected: ExpectedState,
+    pub current: ExpectedState,
+}
+
+#[derive(Error, Debug)]
+pub enum CommitError {
+    #[error(transparent)]
+    Conflict(#[from] CommitConflict),
+    #[error(transparent)]
+    SMT(#[from] SMTError),
+}
+
+/// ExpectedState is the state root and the height which a commit expects to be current.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedState {
+    pub root: Vec<u8>,
+    pub version: BlockHeight,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct CurrentState<'a> {
-    root: &'a [u8],
-    version: BlockHeight,
+pub(crate) struct CurrentState<'a> {
+    pub(crate) root: &'a [u8],
+    pub(crate) version: BlockHeight,
 }
 
-struct Commit {
+pub(crate) struct Commit {
     options: CommitOptions,
     check_expected: bool,
     expected: Vec<u8>,
+    expected_state: Option<ExpectedState>,
 }
 
-struct CommitData {
+pub(crate) struct CommitData {
     data: Commit,
     prev_root: Vec<u8>,
 }
@@ -56,12 +129,17 @@ struct CommitData {
 struct CommitResultInfo {
     next_root: Result<SharedVec, smt::SMTError>,
     data: Commit,
+    /// key_filter is the key filter of the range extended to the next root.
+    key_filter: Option<key_filter::KeyFilter>,
+    /// store_roots are the sub-roots of the stores updated by the commit.
+    store_roots: Vec<StoreRoot>,
 }
 
 /// StateDB maintains instance of database for authenticated storage using sparse merkle tree.
 pub struct StateDB {
     common: DB,
     options: DbOptions,
+    commit_listeners: CommitListeners,
 }
 
 impl<'a> CurrentState<'a> {
@@ -73,7 +151,7 @@ impl<'a> CurrentState<'a> {
         [self.root, &self.version.to_be_bytes()].concat()
     }
 
-    fn from_bytes(bytes: &'a [u8]) -> Self {
+    pub(crate) fn from_bytes(bytes: &'a [u8]) -> Self {
         let version_point = bytes.len() - 4;
         let root = &bytes[..version_point];
         let version = u32::from_be_bytes(bytes[version_point..].try_into().unwrap()).into();
@@ -82,24 +160,46 @@ impl<'a> CurrentState<'a> {
 }
 
 impl Commit {
-    fn new(expected: Vec<u8>, options: CommitOptions, check_expected: bool) -> Self {
+    pub(crate) fn new(expected: Vec<u8>, options: CommitOptions, check_expected: bool) -> Self {
         Self {
             options,
             check_expected,
             expected,
+            expected_state: None,
         }
     }
+
+    /// with_expected_state makes the commit fail with CommitConflict unless the current state is `expected`.
+    pub(crate) fn with_expected_state(mut self, expected: Option<ExpectedState>) -> Self {
+        self.expected_state = expected;
+        self
+    }
 }
 
 impl CommitData {
-    fn new(data: Commit, prev_root: Vec<u8>) -> Self {
+    pub(crate) fn new(data: Commit, prev_root: Vec<u8>) -> Self {
         Self { data, prev_root }
     }
 }
 
 impl CommitResultInfo {
     fn new(next_root: Result<SharedVec, smt::SMTError>, data: Commit) -> Self {
-        Self { data, next_root }
+        Self {
+            data,
+            next_root,
+            key_filter: None,
+            store_roots: vec![],
+        }
+    }
+
+    fn with_key_filter(mut self, filter: Option<key_filter::KeyFilter>) -> Self {
+        self.key_filter = filter;
+        self
+    }
+
+    fn with_store_roots(mut self, roots: Vec<StoreRoot>) -> Self {
+        self.store_roots = roots;
+        self
     }
 }
 
@@ -109,13 +209,21 @@ impl NewDBWithContext for StateDB {
         path: String,
         db_options: DbOptions,
         kind: Kind,
-    ) -> Result<Self, rocksdb::Error>
+    ) -> Result<Self, OpenError>
     where
         C: Context<'a>,
     {
+        let db_options = db_options.with_metrics(metrics::Instance::next());
+        let common = DB::new_db_with_context(ctx, path, db_options, kind)?;
+        root_history::check_mode(
+            common.arc_clone().unwrap(),
+            db_options.keep_root_history(),
+            db_options.is_readonly(),
+        )?;
         Ok(Self {
-            common: DB::new_db_with_context(ctx, path, db_options, kind)?,
+            common,
             options: db_options,
+            commit_listeners: CommitListeners::default(),
         })
     }
 }
@@ -128,21 +236,246 @@ impl JsNewWithBoxRef for StateDB {
         let options = ctx.argument_opt(1);
         let db_opts = T::new_with_context(&mut ctx, options)?;
         let db = U::new_db_with_context(&mut ctx, path, db_opts, Kind::State)
-            .or_else(|err| ctx.throw_error(&err))?;
+            .or_else(|err| DbUtils::throw_open_error(&mut ctx, err))?;
         let ref_db = RefCell::new(db);
 
         return Ok(ctx.boxed(ref_db));
     }
 }
 
+fn audit_record_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    record: &audit::AuditRecord,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let numbers = [
+        ("sequence", f64::from(record.sequence)),
+        ("height", f64::from(record.height)),
+        ("removedCount", f64::from(record.removed_count)),
+        ("removedBytes", record.removed_bytes as f64),
+        ("writtenCount", f64::from(record.written_count)),
+        ("writtenBytes", record.written_bytes as f64),
+    ];
+    for (name, val) in numbers.iter() {
+        let val = ctx.number(*val);
+        obj.set(ctx, *name, val)?;
+    }
+    let policy = ctx.string(record.policy.name());
+    obj.set(ctx, "policy", policy)?;
+    let removed_digest = JsBuffer::external(ctx, record.removed_digest.clone());
+    obj.set(ctx, "removedDigest", removed_digest)?;
+    let prev_hash = JsBuffer::external(ctx, record.prev_hash.clone());
+    obj.set(ctx, "prevHash", prev_hash)?;
+    let hash = JsBuffer::external(ctx, record.hash());
+    obj.set(ctx, "hash", hash)?;
+
+    Ok(obj)
+}
+
+fn root_check_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    check: RootCheck,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let valid = ctx.boolean(check.is_valid());
+    obj.set(ctx, "valid", valid)?;
+    let repaired = ctx.boolean(check.repaired);
+    obj.set(ctx, "repaired", repaired)?;
+    for (name, root) in [
+        ("expectedRoot", check.expected_root),
+        ("currentRoot", check.current_root),
+        ("computedRoot", check.computed_root),
+    ] {
+        let root = JsBuffer::external(ctx, root);
+        obj.set(ctx, name, root)?;
+    }
+    let nodes = ctx.empty_array();
+    for (i, node) in check.inconsistent_nodes.into_iter().enumerate() {
+        let node = JsBuffer::external(ctx, node);
+        nodes.set(ctx, i as u32, node)?;
+    }
+    obj.set(ctx, "inconsistentNodes", nodes)?;
+
+    Ok(obj)
+}
+
+fn options_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    options: DbOptions,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    let flags = [
+        ("readonly", options.is_readonly()),
+        ("keepRootHistory", options.keep_root_history()),
+        ("auditDeletion", options.audit_deletion()),
+        ("rollbackProtection", options.rollback_protection()),
+    ];
+    for (name, val) in flags.iter() {
+        let val = ctx.boolean(*val);
+        obj.set(ctx, *name, val)?;
+    }
+    let key_length = ctx.number(u16::from(options.key_length()));
+    obj.set(ctx, "keyLength", key_length)?;
+    let lock_timeout: Handle<JsValue> = match options.lock_timeout() {
+        Some(timeout) => ctx.number(timeout.as_millis() as f64).upcast(),
+        None => ctx.null().upcast(),
+    };
+    obj.set(ctx, "lockTimeout", lock_timeout)?;
+    let diff_retention: Handle<JsValue> = match options.diff_retention() {
+        Some(heights) => ctx.number(heights).upcast(),
+        None => ctx.null().upcast(),
+    };
+    obj.set(ctx, "diffRetention", diff_retention)?;
+    let root_history_retention: Handle<JsValue> = match options.root_history_retention() {
+        Some(heights) => ctx.number(heights).upcast(),
+        None => ctx.null().upcast(),
+    };
+    obj.set(ctx, "rootHistoryRetention", root_history_retention)?;
+
+    Ok(obj)
+}
+
+/// structure_log_to_js_object converts the log to { created, deleted } with { hash, height } of each subtree.
+fn structure_log_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    log: StructureLog,
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+    for (name, changes) in [("created", log.created), ("deleted", log.deleted)] {
+        let arr = ctx.empty_array();
+        for (i, change) in changes.into_iter().enumerate() {
+            let item = ctx.empty_object();
+            let hash = JsBuffer::external(ctx, change.hash);
+            item.set(ctx, "hash", hash)?;
+            let height = ctx.number(change.height);
+            item.set(ctx, "height", height)?;
+            arr.set(ctx, i as u32, item)?;
+        }
+        obj.set(ctx, name, arr)?;
+    }
+
+    Ok(obj)
+}
+
+/// commit_error_to_js_error creates the error of the commit.
+/// A conflict has the code COMMIT_CONFLICT with the expected and the current state,
+/// and the other errors have the code of the SMT error.
+fn commit_error_to_js_error<'a, C: Context<'a>>(
+    ctx: &mut C,
+    err: CommitError,
+) -> NeonResult<Handle<'a, JsError>> {
+    let conflict = match err {
+        CommitError::SMT(err) => return DbUtils::error_with_code(ctx, &err),
+        CommitError::Conflict(conflict) => conflict,
+    };
+    let js_err = ctx.error(conflict.to_string())?;
+    let code = ctx.string("COMMIT_CONFLICT");
+    js_err.set(ctx, "code", code)?;
+    for (name, state) in [
+        ("expected", conflict.expected),
+        ("current", conflict.current),
+    ] {
+        let obj = ctx.empty_object();
+        let root = JsBuffer::external(ctx, state.root);
+        obj.set(ctx, "root", root)?;
+        let version = ctx.number::<u32>(state.version.into());
+        obj.set(ctx, "version", version)?;
+        js_err.set(ctx, name, obj)?;
+    }
+    Ok(js_err)
+}
+
+fn debug_report_to_js_object<'a, C: Context<'a>>(
+    ctx: &mut C,
+    options: DbOptions,
+    prefixes: &[debug_report::PrefixStats],
+    slow_operations: &[diagnostics::SlowOperation],
+) -> NeonResult<Handle<'a, JsObject>> {
+    let obj = ctx.empty_object();
+
+    let format_version = ctx.empty_object();
+    let package = ctx.string(env!("CARGO_PKG_VERSION"));
+    format_version.set(ctx, "package", package)?;
+    let subtree_encoding = ctx.number(smt::SUB_TREE_VERSION);
+    format_version.set(ctx, "subtreeEncoding", subtree_encoding)?;
+    obj.set(ctx, "formatVersion", format_version)?;
+
+    let options = options_to_js_object(ctx, options)?;
+    obj.set(ctx, "options", options)?;
+
+    let prefix_list = ctx.empty_array();
+    for (i, stats) in prefixes.iter().enumerate() {
+        let item = ctx.empty_object();
+        let name = ctx.string(stats.name);
+        item.set(ctx, "name", name)?;
+        let prefix = ctx.string(hex::encode(&stats.prefix));
+        item.set(ctx, "prefix", prefix)?;
+        let keys = ctx.number(stats.keys as f64);
+        item.set(ctx, "keys", keys)?;
+        let bytes = ctx.number(stats.bytes as f64);
+        item.set(ctx, "bytes", bytes)?;
+        prefix_list.set(ctx, i as u32, item)?;
+    }
+    obj.set(ctx, "prefixes", prefix_list)?;
+
+    let slow_list = ctx.empty_array();
+    for (i, slow) in slow_operations.iter().enumerate() {
+        let item = ctx.empty_object();
+        let operation = ctx.string(slow.operation.name());
+        item.set(ctx, "operation", operation)?;
+        let duration = ctx.number(slow.duration.as_secs_f64() * 1000.0);
+        item.set(ctx, "durationMs", duration)?;
+        let timestamp = ctx.number(slow.timestamp as f64);
+        item.set(ctx, "timestamp", timestamp)?;
+        slow_list.set(ctx, i as u32, item)?;
+    }
+    obj.set(ctx, "slowOperations", slow_list)?;
+
+    let errors = ctx.empty_object();
+    for operation in diagnostics::Operation::ALL.iter() {
+        let count = ctx.number(diagnostics::error_count(*operation) as f64);
+        errors.set(ctx, operation.name(), count)?;
+    }
+    obj.set(ctx, "errors", errors)?;
+
+    Ok(obj)
+}
+
 impl Finalize for StateDB {}
 impl StateDB {
+    /// get_current_root returns the root of the current state, which must be at `version`.
+    fn get_current_root(conn: &DB, version: BlockHeight) -> Result<Vec<u8>, DataStoreError> {
+        let bytes = conn
+            .get(consts::Prefix::CURRENT_STATE)
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
+        let current = match &bytes {
+            Some(bytes) => CurrentState::from_bytes(bytes),
+            None => CurrentState::new(&EMPTY_HASH, BlockHeight(0)),
+        };
+        if current.version != version {
+            return Err(DataStoreError::NotCurrentHeight(
+                version.into(),
+                current.version.into(),
+            ));
+        }
+        Ok(current.root.to_vec())
+    }
+
+    /// get_revert_result reverts the state of `version` with its diff, and returns the root before the version.
+    /// The root of the current state is used when `state_root` is not given.
     fn get_revert_result(
         conn: &DB,
         version: BlockHeight,
-        state_root: &[u8],
-        key_length: KeyLength,
+        state_root: Option<Vec<u8>>,
+        options: DbOptions,
     ) -> Result<SharedVec, DataStoreError> {
+        if options.rollback_protection() {
+            sentinel::check(conn.arc_clone().unwrap(), version)?;
+        }
+        let state_root = match state_root {
+            Some(root) => root,
+            None => StateDB::get_current_root(conn, version)?,
+        };
         let diff_bytes = conn
             .get(&[consts::Prefix::DIFF, &version.to_be_bytes()].concat())
             .map_err(|err| DataStoreError::Unknown(err.to_string()))?
@@ -151,23 +484,47 @@ impl StateDB {
         let diff = diff::Diff::decode(&diff_bytes)
             .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
         let data = smt::UpdateData::new_from(diff.revert_hashed_update());
-        let mut smt_db = smt_db::SmtDB::new(conn);
-        let mut tree = smt::SparseMerkleTree::new(state_root, key_length, consts::SUBTREE_HEIGHT);
+        let mut smt_db = smt_db::SmtDB::new_with_retention(conn, options.keep_root_history());
+        let mut tree =
+            smt::SparseMerkleTree::new(&state_root, options.key_length(), consts::SUBTREE_HEIGHT)
+                .with_metrics(options.metrics());
         let prev_root = tree
             .commit(&mut smt_db, &data)
             .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
 
-        let mut write_batch = batch::PrefixWriteBatch::new();
         // Insert state batch with diff
-        write_batch.set_prefix(&consts::Prefix::STATE);
-        diff.revert_commit(&mut write_batch);
+        let mut write_batch = diff.revert_batch();
         write_batch.set_prefix(&consts::Prefix::DIFF);
         write_batch.delete(&version.to_be_bytes());
+        root_registry::remove(&mut write_batch.batch, version, &state_root);
+        if options.keep_root_history() {
+            // the nodes deleted by the revert are not referenced by the root of the previous version
+            root_history::release(
+                &mut write_batch.batch,
+                version - BlockHeight(1),
+                &smt_db.batch,
+                smt_db.released(),
+            );
+        }
+        if options.store_roots() {
+            store_roots::remove(&mut write_batch.batch, version, &store_roots::stores(&data));
+        }
+        // the nodes changed by the revert itself are not recorded
+        structure_log::remove(&mut write_batch.batch, version);
 
         // insert SMT batch
-        write_batch.set_prefix(&consts::Prefix::SMT);
-        smt_db.batch.iterate(&mut write_batch);
-        // insert diff
+        write_batch.extend_with_prefixes(&[(consts::Prefix::SMT, &smt_db.batch)]);
+        // insert current state
+        let value = (**prev_root.lock_or_recover()).clone();
+        // the filter of the reverted version still has all the keys of the previous version
+        if options.smt_key_filter() {
+            key_filter::revert(conn, &mut write_batch.batch, version)
+                .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
+        }
+        let state_info = CurrentState::new(&value, version - BlockHeight(1));
+        write_batch
+            .batch
+            .put(consts::Prefix::CURRENT_STATE, state_info.to_bytes());
         conn.write(write_batch.batch)
             .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
 
@@ -177,28 +534,23 @@ impl StateDB {
     fn revert(
         &mut self,
         version: BlockHeight,
-        state_root: Vec<u8>,
+        state_root: Option<Vec<u8>>,
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<DbMessage>> {
-        let key_length = self.options.key_length();
-        let result = StateDB::get_revert_result(&self.common, version, &state_root, key_length);
-        if result.is_ok() {
-            let value = (**result.as_ref().unwrap().lock().unwrap()).clone();
-            let state_info = CurrentState::new(&value, version - BlockHeight(1));
-            self.common
-                .put(consts::Prefix::CURRENT_STATE, &state_info.to_bytes())
-                .expect("Update state info should not be failed");
-        }
+        let start = Instant::now();
+        let result = StateDB::get_revert_result(&self.common, version, state_root, self.options);
+        diagnostics::observe(Operation::Revert, start, &result);
         self.common.send(move |channel| {
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
                 let this = ctx.undefined();
                 let args: Vec<Handle<JsValue>> = match result {
                     Ok(val) => {
-                        let buffer = JsBuffer::external(&mut ctx, (**val.lock().unwrap()).clone());
+                        let root = (**val.lock_or_recover()).clone();
+                        let buffer = JsBuffer::external(&mut ctx, root);
                         vec![ctx.null().upcast(), buffer.upcast()]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
 
                 callback.call(&mut ctx, this, args)?;
@@ -211,46 +563,258 @@ impl StateDB {
     fn handle_commit_result(
         conn: &DB,
         smt_db: &smt_db::SmtDB,
-        writer: MutexGuard<state_writer::StateWriter>,
+        state: batch::PrefixWriteBatch<'static>,
+        diff: diff::Diff,
         info: CommitResultInfo,
-    ) -> Result<SharedVec, smt::SMTError> {
-        info.next_root.as_ref()?;
-        let root = info.next_root.unwrap();
+        options: DbOptions,
+    ) -> Result<(SharedVec, Option<CommitEvent>), CommitError> {
+        let root = info.next_root?;
         if info.data.check_expected
-            && utils::compare(&info.data.expected, &root.lock().unwrap()) != cmp::Ordering::Equal
+            && utils::compare(&info.data.expected, &root.lock_or_recover()) != cmp::Ordering::Equal
         {
-            return Err(smt::SMTError::InvalidRoot(String::from(
-                "Not matching with expected",
-            )));
+            return Err(
+                smt::SMTError::InvalidRoot(String::from("Not matching with expected")).into(),
+            );
         }
         if info.data.options.is_readonly() {
-            return Ok(root);
+            return Ok((root, None));
         }
-        // Create global batch
-        let mut write_batch = batch::PrefixWriteBatch::new();
-        // Insert state batch with diff
-        write_batch.set_prefix(&consts::Prefix::STATE);
-        let diff = writer.commit(&mut write_batch);
+        // The current state must not change until the block is written
+        let _guard = conn.lock_commit();
+        if let Some(expected) = info.data.expected_state {
+            StateDB::check_current_state(conn, expected)?;
+        }
+        if options.rollback_protection() {
+            sentinel::check(conn.arc_clone().unwrap(), info.data.options.version())
+                .map_err(|err| smt::SMTError::InvalidInput(err.to_string()))?;
+        }
+        let version = info.data.options.version();
+        let value = (**root.lock_or_recover()).clone();
+        let mut write_batch =
+            StateDB::commit_block(conn, smt_db, state, &diff, version, &value, options)?;
+        if let Some(filter) = &info.key_filter {
+            key_filter::write(&mut write_batch.batch, filter, options.keep_root_history());
+        }
+        if let Some(until) = StateDB::root_retention_boundary(version, options) {
+            if options.smt_key_filter() {
+                key_filter::prune(conn.arc_clone().unwrap(), &mut write_batch.batch, until)
+                    .map_err(smt::SMTError::Unknown)?;
+            }
+        }
+        store_roots::write(&mut write_batch.batch, version, &info.store_roots);
+        // the sentinel advanced by the pruning of the diffs is written with fsync
+        let write_options = if options.rollback_protection()
+            && StateDB::retention_boundary(version, options.diff_retention()).is_some()
+        {
+            sentinel::sync_write_options()
+        } else {
+            rocksdb::WriteOptions::default()
+        };
+        conn.write_with_ranges_opt(&write_batch.ranges, write_batch.batch, &write_options)
+            .map_err(|err| smt::SMTError::Unknown(err.to_string()))?;
+        let event = CommitEvent {
+            root: value,
+            height: version,
+            stats: diff.stats(),
+        };
+
+        Ok((root, Some(event)))
+    }
+
+    /// check_current_state returns CommitConflict if the current state is not the expected one.
+    /// The current state of an empty database is the empty root at height 0.
+    fn check_current_state(conn: &DB, expected: ExpectedState) -> Result<(), CommitError> {
+        let bytes = conn
+            .get(consts::Prefix::CURRENT_STATE)
+            .map_err(|err| smt::SMTError::Unknown(err.to_string()))?;
+        let current = match &bytes {
+            Some(bytes) => CurrentState::from_bytes(bytes),
+            None => CurrentState::new(&EMPTY_HASH, BlockHeight(0)),
+        };
+        if current.version == expected.version
+            && utils::is_bytes_equal(current.root, &expected.root)
+        {
+            return Ok(());
+        }
+        Err(CommitConflict {
+            expected,
+            current: ExpectedState {
+                root: current.root.to_vec(),
+                version: current.version,
+            },
+        }
+        .into())
+    }
+
+    /// retention_boundary returns the lowest height whose diff or root is kept after committing `version`
+    /// with the retention window of `retention` heights. It returns None if nothing has to be pruned.
+    fn retention_boundary(version: BlockHeight, retention: Option<u32>) -> Option<BlockHeight> {
+        let until = (u32::from(version) + 1).checked_sub(retention?)?;
+        if until == 0 {
+            return None;
+        }
+        Some(BlockHeight(until))
+    }
+
+    /// root_retention_boundary returns the lowest height whose root is retained with its nodes after committing `version`,
+    /// or None if nothing has to be pruned.
+    fn root_retention_boundary(version: BlockHeight, options: DbOptions) -> Option<BlockHeight> {
+        if !options.keep_root_history() {
+            return None;
+        }
+        StateDB::retention_boundary(version, options.root_history_retention())
+    }
+
+    /// commit_block assembles everything written for the block into one batch: the state changes,
+    /// the diff, the SMT nodes, the released nodes, the root registry, the audit records, the lifetime stats,
+    /// the pruning of the diffs and the nodes out of the retention and the current state.
+    /// The block is then written with a single write, so a crash can never leave the tree and the state inconsistent.
+    /// The state changes are given as the batch committed from the writer with the diff of them.
+    /// The deleted state ranges are returned with the batch, and they must be written with it.
+    pub(crate) fn commit_block(
+        conn: &DB,
+        smt_db: &smt_db::SmtDB,
+        state: batch::PrefixWriteBatch<'static>,
+        diff: &diff::Diff,
+        version: BlockHeight,
+        root: &[u8],
+        options: DbOptions,
+    ) -> Result<batch::PrefixWriteBatch<'static>, smt::SMTError> {
+        // The global batch starts with the state changes committed from the writer
+        let mut write_batch = state;
+        let keys_written = write_batch.batch.len();
         write_batch.set_prefix(&consts::Prefix::DIFF);
-        let key = info.data.options.version().to_be_bytes();
+        let key = version.to_be_bytes();
         write_batch.put(&key, diff.encode().as_ref());
 
         // insert SMT batch
-        write_batch.set_prefix(&consts::Prefix::SMT);
-        smt_db.batch.iterate(&mut write_batch);
-        // insert diff
-        let result = conn.write(write_batch.batch);
-        let version = info.data.options.version();
-        match result {
-            Ok(_) => {
-                let value = (**root.as_ref().lock().unwrap()).clone();
-                let state_info = CurrentState::new(&value, version);
-                conn.put(consts::Prefix::CURRENT_STATE, &state_info.to_bytes())
-                    .expect("Update state info should not be failed");
-                Ok(root)
-            },
-            Err(err) => Err(smt::SMTError::Unknown(err.to_string())),
+        write_batch.extend_with_prefixes(&[(consts::Prefix::SMT, &smt_db.batch)]);
+        if options.keep_root_history() {
+            root_history::release(
+                &mut write_batch.batch,
+                version,
+                &smt_db.batch,
+                smt_db.released(),
+            );
+        }
+        root_registry::write(&mut write_batch.batch, version, root);
+        let mut last_audit = if options.audit_deletion() {
+            audit::last_record(conn.arc_clone().unwrap())
+                .map_err(|err| smt::SMTError::Unknown(err.to_string()))?
+        } else {
+            None
+        };
+        if options.audit_deletion() {
+            let removal = audit::node_removal(conn, &smt_db.batch);
+            if !removal.is_empty() {
+                audit::append(
+                    &mut write_batch.batch,
+                    &mut last_audit,
+                    removal,
+                    audit::Policy::NodeRemoval,
+                    version.into(),
+                );
+            }
+        }
+        if options.structure_log() {
+            let log = StructureLog::collect(conn, &smt_db.batch, options.key_length());
+            structure_log::write(&mut write_batch.batch, version, &log);
         }
+        let bytes_written = write_batch.batch.size_in_bytes();
+        lifetime_stats::record(
+            conn.arc_clone().unwrap(),
+            &mut write_batch.batch,
+            keys_written,
+            bytes_written,
+        )
+        .map_err(|err| smt::SMTError::Unknown(err.to_string()))?;
+        // the diffs are pruned by the same write, so the block is never reported as failed after it is written
+        if let Some(until) = StateDB::retention_boundary(version, options.diff_retention()) {
+            let committed = if until == version { Some(root) } else { None };
+            StateDB::add_diff_pruning(
+                conn.arc_clone().unwrap(),
+                &mut write_batch.batch,
+                until,
+                committed,
+                &mut last_audit,
+                options.audit_deletion(),
+                options.rollback_protection(),
+            )
+            .map_err(smt::SMTError::Unknown)?;
+        }
+        if let Some(until) = StateDB::root_retention_boundary(version, options) {
+            root_history::prune(
+                conn.arc_clone().unwrap(),
+                &mut write_batch.batch,
+                until,
+                &smt_db.batch,
+                &mut last_audit,
+                options.audit_deletion(),
+            )
+            .map_err(smt::SMTError::Unknown)?;
+        }
+        // insert current state
+        let state_info = CurrentState::new(root, version);
+        write_batch
+            .batch
+            .put(consts::Prefix::CURRENT_STATE, state_info.to_bytes());
+
+        Ok(write_batch)
+    }
+
+    /// commit_writer computes the next root with the writer and writes the result to the conn.
+    pub(crate) fn commit_writer(
+        conn: &DB,
+        writer: MutexGuard<state_writer::StateWriter>,
+        commit_data: CommitData,
+        options: DbOptions,
+    ) -> Result<SharedVec, CommitError> {
+        StateDB::commit_writer_with_event(conn, writer, commit_data, options).map(|(root, _)| root)
+    }
+
+    /// commit_writer_with_event commits like commit_writer, and also returns the event of the commit
+    /// unless it is readonly.
+    fn commit_writer_with_event(
+        conn: &DB,
+        mut writer: MutexGuard<state_writer::StateWriter>,
+        commit_data: CommitData,
+        options: DbOptions,
+    ) -> Result<(SharedVec, Option<CommitEvent>), CommitError> {
+        let height = u32::from(commit_data.data.options.version());
+        let _span = tracing::info_span!("commit", height).entered();
+        writer
+            .cache_deleted_ranges(conn.arc_clone().unwrap())
+            .map_err(|err| smt::SMTError::Unknown(err.to_string()))?;
+        // The tree is updated with exactly the changes written to the state batch
+        let mut state = batch::PrefixWriteBatch::new();
+        state.set_prefix(&consts::Prefix::STATE);
+        let (diff, data) = writer.commit_with_update_data(&mut state);
+        let mut smt_db = smt_db::SmtDB::new_with_retention(conn, options.keep_root_history());
+        let mut tree = smt::SparseMerkleTree::new(
+            &commit_data.prev_root,
+            options.key_length(),
+            consts::SUBTREE_HEIGHT,
+        )
+        .with_metrics(options.metrics());
+        let (root, roots) = if options.store_roots() && !commit_data.data.options.is_readonly() {
+            match store_roots::commit(&mut tree, &mut smt_db, &data) {
+                Ok((root, roots)) => (Ok(root), roots),
+                Err(err) => (Err(err), vec![]),
+            }
+        } else {
+            (tree.commit(&mut smt_db, &data), vec![])
+        };
+        let filter =
+            if options.smt_key_filter() && root.is_ok() && !commit_data.data.options.is_readonly()
+            {
+                key_filter::next(conn, commit_data.data.options.version(), &data)?
+            } else {
+                None
+            };
+        let result_info = CommitResultInfo::new(root, commit_data.data)
+            .with_key_filter(filter)
+            .with_store_roots(roots);
+        StateDB::handle_commit_result(conn, &smt_db, state, diff, result_info, options)
     }
 
     fn commit(
@@ -259,44 +823,101 @@ impl StateDB {
         commit_data: CommitData,
         callback: Root<JsFunction>,
     ) -> Result<(), mpsc::SendError<DbMessage>> {
-        let key_length = self.options.key_length();
-        let w = writer.lock().unwrap();
-        let data = smt::UpdateData::new_from(w.get_hashed_updated());
-        let mut smt_db = smt_db::SmtDB::new(&self.common);
-        let mut tree =
-            smt::SparseMerkleTree::new(&commit_data.prev_root, key_length, consts::SUBTREE_HEIGHT);
-        let root = tree.commit(&mut smt_db, &data);
-        let result_info = CommitResultInfo::new(root, commit_data.data);
-        let result = StateDB::handle_commit_result(&self.common, &smt_db, w, result_info);
+        let w = writer.lock_or_recover();
+        let start = Instant::now();
+        let result = StateDB::commit_writer_with_event(&self.common, w, commit_data, self.options);
+        diagnostics::observe(Operation::Commit, start, &result);
+        let listeners = self.commit_listeners.clone();
         self.common.send(move |channel| {
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
                 let this = ctx.undefined();
-                let args: Vec<Handle<JsValue>> = match result {
-                    Ok(val) => {
-                        let buffer = JsBuffer::external(&mut ctx, (**val.lock().unwrap()).clone());
-                        vec![ctx.null().upcast(), buffer.upcast()]
+                let (args, event): (Vec<Handle<JsValue>>, _) = match result {
+                    Ok((val, event)) => {
+                        let root = (**val.lock_or_recover()).clone();
+                        let buffer = JsBuffer::external(&mut ctx, root);
+                        (vec![ctx.null().upcast(), buffer.upcast()], event)
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => (
+                        vec![commit_error_to_js_error(&mut ctx, err)?.upcast()],
+                        None,
+                    ),
                 };
 
                 callback.call(&mut ctx, this, args)?;
+                // The listeners are called after the commit is resolved, so they see the committed state
+                if let Some(event) = event {
+                    listeners.emit(&mut ctx, &event)?;
+                }
 
                 Ok(())
             });
         })
     }
 
+    /// attach_stored_values sets the stored value of the queries which are included in the tree.
+    /// The value is taken from the current state, so it is attached only if it matches the proven value hash.
+    fn attach_stored_values(
+        &self,
+        proof: &mut smt::Proof,
+        state_keys: &HashMap<Vec<u8>, Vec<u8>>,
+    ) -> Result<(), DataStoreError> {
+        for query in proof.queries.iter_mut() {
+            if query.value().is_empty() {
+                continue;
+            }
+            let state_key = match state_keys.get(query.key()) {
+                Some(key) => key,
+                None => continue,
+            };
+            let value = self
+                .common
+                .get(&[consts::Prefix::STATE, state_key].concat())
+                .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
+            if let Some(value) = value {
+                if utils::is_bytes_equal(&value.hash_with_kind(HashKind::Value), query.value()) {
+                    query.stored_value = Some(value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn prove(
         &self,
         root: Vec<u8>,
         queries: NestedVec,
+        include_values: bool,
         callback: Root<JsFunction>,
     ) -> Result<(), DataStoreError> {
+        let _span =
+            tracing::info_span!("prove", queries = queries.len(), include_values).entered();
         let key_length = self.options.key_length();
-        let mut tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT);
-        let mut smtdb = smt_db::SmtDB::new(&self.common);
-        let result = tree.prove(&mut smtdb, &queries);
+        let start = Instant::now();
+        let mut tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT)
+            .with_metrics(self.options.metrics());
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let result = if include_values {
+            // queries are the state keys, and the proof is generated for the hashed keys.
+            queries
+                .iter()
+                .map(|key| smt::hash_state_key(key))
+                .collect::<Result<NestedVec, SMTError>>()
+                .and_then(|hashed_keys| {
+                    let mut proof = tree.prove_parallel(&smtdb, &hashed_keys)?;
+                    let state_keys = hashed_keys
+                        .into_iter()
+                        .zip(queries)
+                        .collect::<HashMap<Vec<u8>, Vec<u8>>>();
+                    self.attach_stored_values(&mut proof, &state_keys)
+                        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+                    Ok(proof)
+                })
+        } else {
+            tree.prove_parallel(&smtdb, &queries)
+        };
+        diagnostics::observe(Operation::Prove, start, &result);
 
         self.common
             .send(move |channel| {
@@ -305,25 +926,10 @@ impl StateDB {
                     let this = ctx.undefined();
                     let args: Vec<Handle<JsValue>> = match result {
                         Ok(val) => {
-                            let obj: Handle<JsObject> = ctx.empty_object();
-                            let sibling_hashes = ctx.empty_array();
-                            for (i, h) in val.sibling_hashes.iter().enumerate() {
-                                let val_res = JsBuffer::external(&mut ctx, h.to_vec());
-                                sibling_hashes.set(&mut ctx, i as u32, val_res)?;
-                            }
-                            obj.set(&mut ctx, "siblingHashes", sibling_hashes)?;
-                            let queries = ctx.empty_array();
-                            for (i, v) in val.queries.iter().enumerate() {
-                                let obj = pair_to_js_object(&mut ctx, &v.pair)?;
-                                let bitmap = JsBuffer::external(&mut ctx, v.bitmap.to_vec());
-                                obj.set(&mut ctx, "bitmap", bitmap)?;
-
-                                queries.set(&mut ctx, i as u32, obj)?;
-                            }
-                            obj.set(&mut ctx, "queries", queries)?;
+                            let obj = DbUtils::proof_to_js_object(&mut ctx, &val)?;
                             vec![ctx.null().upcast(), obj.upcast()]
                         },
-                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
                     };
                     callback.call(&mut ctx, this, args)?;
 
@@ -333,46 +939,65 @@ impl StateDB {
             .map_err(|err| DataStoreError::Unknown(err.to_string()))
     }
 
-    fn clean_diff_until(
+    /// prove_many returns the proof of each set of the queries against the root, which are generated with a single traversal of the tree.
+    /// It is used to serve the coalesced prove calls, so each proof is the same as the one returned by prove for the set.
+    fn prove_many(
         &self,
-        version: BlockHeight,
+        root: Vec<u8>,
+        query_sets: Vec<NestedVec>,
+        include_values: bool,
         callback: Root<JsFunction>,
     ) -> Result<(), DataStoreError> {
-        if version.is_equal_to(0) {
-            return Ok(());
-        }
-        let conn = self.common.arc_clone();
-        self.common
-            .send(move |channel| {
-                let start = [consts::Prefix::DIFF, 0_u32.to_be_bytes().as_slice()].concat();
-                let bytes = (version - BlockHeight(1)).to_be_bytes();
-                let end = [consts::Prefix::DIFF, &bytes].concat();
-                let mut batch = rocksdb::WriteBatch::default();
-
-                let conn_iter = conn.unwrap().iterator(rocksdb::IteratorMode::From(
-                    end.as_ref(),
-                    rocksdb::Direction::Reverse,
-                ));
-
-                for key_val in conn_iter {
-                    if utils::compare(&(key_val.as_ref().unwrap().0), &start)
-                        == cmp::Ordering::Less
+        let _span =
+            tracing::info_span!("prove_many", sets = query_sets.len(), include_values).entered();
+        let key_length = self.options.key_length();
+        let start = Instant::now();
+        let mut tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT)
+            .with_metrics(self.options.metrics());
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let result = if include_values {
+            // queries are the state keys, and the proofs are generated for the hashed keys.
+            query_sets
+                .iter()
+                .map(|queries| {
+                    queries
+                        .iter()
+                        .map(|key| smt::hash_state_key(key))
+                        .collect::<Result<NestedVec, SMTError>>()
+                })
+                .collect::<Result<Vec<NestedVec>, SMTError>>()
+                .and_then(|hashed_sets| {
+                    let mut proofs = tree.prove_many(&smtdb, &hashed_sets)?;
+                    for ((proof, hashed_keys), queries) in
+                        proofs.iter_mut().zip(hashed_sets).zip(query_sets)
                     {
-                        break;
+                        let state_keys: HashMap<Vec<u8>, Vec<u8>> =
+                            hashed_keys.into_iter().zip(queries).collect();
+                        self.attach_stored_values(proof, &state_keys)
+                            .map_err(|err| SMTError::Unknown(err.to_string()))?;
                     }
-                    batch.delete(&(key_val.unwrap().0));
-                }
-
-                let result = conn.unwrap().write(batch);
+                    Ok(proofs)
+                })
+        } else {
+            tree.prove_many(&smtdb, &query_sets)
+        };
+        diagnostics::observe(Operation::Prove, start, &result);
 
+        self.common
+            .send(move |channel| {
                 channel.send(move |mut ctx| {
                     let callback = callback.into_inner(&mut ctx);
                     let this = ctx.undefined();
                     let args: Vec<Handle<JsValue>> = match result {
-                        Ok(_) => {
-                            vec![ctx.null().upcast()]
+                        Ok(proofs) => {
+                            let array = ctx.empty_array();
+                            for (i, proof) in proofs.iter().enumerate() {
+                                let obj = DbUtils::proof_to_js_object(&mut ctx, proof)?;
+                                array.set(&mut ctx, i as u32, obj)?;
+                            }
+                            vec![ctx.null().upcast(), array.upcast()]
                         },
-                        Err(err) => vec![ctx.error(&err)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
                     };
                     callback.call(&mut ctx, this, args)?;
 
@@ -382,150 +1007,1282 @@ impl StateDB {
             .map_err(|err| DataStoreError::Unknown(err.to_string()))
     }
 
-    fn proof(ctx: &mut FunctionContext, pos: u8) -> NeonResult<smt::Proof> {
-        let raw_proof = ctx.argument::<JsObject>(pos.into())?;
-        let raw_sibling_hashes = raw_proof
-            .get::<JsArray, _, _>(ctx, "siblingHashes")?
-            .to_vec(ctx)?;
-        let sibling_hashes = raw_sibling_hashes
-            .iter()
-            .map(|key| {
-                Ok(key
-                    .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
-                    .as_slice(ctx)
-                    .to_vec())
-            })
-            .collect::<NeonResult<NestedVec>>()?;
-
-        let raw_queries = raw_proof
-            .get::<JsArray, _, _>(ctx, "queries")?
-            .to_vec(ctx)?;
-        let mut queries: Vec<smt::QueryProof> = Vec::with_capacity(raw_queries.len());
-        for key in raw_queries.iter() {
-            let obj = key.downcast_or_throw::<JsObject, _>(ctx)?;
-            let key = obj
-                .get::<JsTypedArray<u8>, _, _>(ctx, "key")?
-                .as_slice(ctx)
-                .to_vec();
-            let value = obj
-                .get::<JsTypedArray<u8>, _, _>(ctx, "value")?
-                .as_slice(ctx)
-                .to_vec();
-            let bitmap = obj
-                .get::<JsTypedArray<u8>, _, _>(ctx, "bitmap")?
-                .as_slice(ctx)
-                .to_vec();
-            queries.push(smt::QueryProof {
-                pair: Arc::new(KVPair::new(&key, &value)),
-                bitmap: Arc::new(bitmap),
-            });
-        }
+    /// get_value_hash returns the value hash of the key in the tree of the root.
+    /// With the key filter, the key ruled out by the filter of the height of the root is not looked up in the tree.
+    fn get_value_hash(
+        &self,
+        root: Vec<u8>,
+        key: Vec<u8>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let key_length = self.options.key_length();
+        let mut tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT)
+            .with_metrics(self.options.metrics());
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let filter = match self.options.smt_key_filter() {
+            true => key_filter::get_for_root(self.common.arc_clone().unwrap(), &root),
+            false => Ok(None),
+        };
+        let result = filter.and_then(|filter| tree.get(&smtdb, &key, filter.as_ref()));
 
-        Ok(smt::Proof {
-            queries,
-            sibling_hashes,
-        })
-    }
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(val)) => {
+                            let buffer = JsBuffer::external(&mut ctx, val);
+                            vec![ctx.null().upcast(), buffer.upcast()]
+                        },
+                        Ok(None) => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
 
-    fn parse_query_keys(ctx: &mut FunctionContext) -> NeonResult<NestedVec> {
-        let query_keys = ctx.argument::<JsArray>(1)?.to_vec(ctx)?;
-        let parsed_query_keys = query_keys
-            .iter()
-            .map(|key| {
-                Ok(key
-                    .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
-                    .as_slice(ctx)
-                    .to_vec())
+                    Ok(())
+                });
             })
-            .collect::<NeonResult<NestedVec>>()?;
-
-        Ok(parsed_query_keys)
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
     }
 
-    fn get_current_state(
+    /// generate_witness returns the encoded witness of the state keys against the root.
+    /// The values are taken from the current state, so the root must be the one of the current state
+    /// unless the keys are not changed after the root.
+    fn generate_witness(
         &self,
+        root: Vec<u8>,
+        keys: NestedVec,
         callback: Root<JsFunction>,
-    ) -> Result<(), mpsc::SendError<DbMessage>> {
-        let result = self.common.get(consts::Prefix::CURRENT_STATE);
-        self.common.send(move |channel| {
-            channel.send(move |mut ctx| {
-                let callback = callback.into_inner(&mut ctx);
-                let this = ctx.undefined();
-                let args: Vec<Handle<JsValue>> = match result {
-                    Ok(value) => {
-                        let temp_value: Vec<u8>;
-                        let current_state_info = if let Some(value) = value {
-                            temp_value = value;
-                            CurrentState::from_bytes(&temp_value)
-                        } else {
-                            CurrentState::new(&EMPTY_HASH, BlockHeight(0))
-                        };
-                        let root = JsBuffer::external(&mut ctx, current_state_info.root.to_vec());
-                        let version = ctx.number::<u32>(current_state_info.version.into());
-                        let object = ctx.empty_object();
-                        object.set(&mut ctx, "root", root)?;
-                        object.set(&mut ctx, "version", version)?;
-                        vec![ctx.null().upcast(), object.upcast()]
-                    },
-                    Err(err) => vec![ctx.error(&err)?.upcast()],
-                };
-                callback.call(&mut ctx, this, args)?;
-                Ok(())
-            });
+    ) -> Result<(), DataStoreError> {
+        let _span = tracing::info_span!("generate_witness", keys = keys.len()).entered();
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let result = Witness::generate(&smtdb, &root, &keys, self.options.key_length(), |key| {
+            self.common
+                .get(&[consts::Prefix::STATE, key].concat())
+                .map_err(|err| SMTError::Unknown(err.to_string()))
         })
-    }
+        .map(|witness| witness.encode());
 
-    pub fn arc_clone(&self) -> ArcOptionDB {
-        self.common.arc_clone()
-    }
-}
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(val) => {
+                            let buffer = JsBuffer::external(&mut ctx, val);
+                            vec![ctx.null().upcast(), buffer.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// get_store_root returns the sub-root of the store at the version.
+    fn get_store_root(
+        &self,
+        store: Vec<u8>,
+        version: BlockHeight,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let result = store_roots::get(self.common.arc_clone().unwrap(), &store, version);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(val)) => {
+                            let buffer = JsBuffer::external(&mut ctx, val);
+                            vec![ctx.null().upcast(), buffer.upcast()]
+                        },
+                        Ok(None) => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// get_retained_root returns the root of the version if its nodes are retained, which is when keepRootHistory is set
+    /// and the version is the current one or within rootHistoryRetention of it.
+    fn get_retained_root(
+        conn: &rocksdb::DB,
+        version: BlockHeight,
+        options: &DbOptions,
+    ) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        if !options.keep_root_history() {
+            return Ok(None);
+        }
+        let current = match conn.get(consts::Prefix::CURRENT_STATE)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let current = CurrentState::from_bytes(&current);
+        if current.version == version {
+            return Ok(Some(current.root.to_vec()));
+        }
+        let until = StateDB::retention_boundary(current.version, options.root_history_retention());
+        if u32::from(version) > u32::from(current.version)
+            || until.is_some_and(|until| u32::from(version) < u32::from(until))
+        {
+            return Ok(None);
+        }
+        root_registry::get_root(conn, version)
+    }
+
+    /// get_root_at returns the root of the version if its nodes are retained.
+    fn get_root_at(
+        &self,
+        version: BlockHeight,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let result =
+            StateDB::get_retained_root(self.common.arc_clone().unwrap(), version, &self.options);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(root)) => {
+                            let root = JsBuffer::external(&mut ctx, root);
+                            vec![ctx.null().upcast(), root.upcast()]
+                        },
+                        Ok(None) => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// find_height_for_root returns the highest version committed with the root.
+    fn find_height_for_root(
+        &self,
+        root: Vec<u8>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let result = root_registry::find_height(self.common.arc_clone().unwrap(), &root);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(version)) => {
+                            vec![ctx.null().upcast(), ctx.number(u32::from(version)).upcast()]
+                        },
+                        Ok(None) => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// get_structure_log returns the SMT nodes created and deleted by the commit of the version.
+    fn get_structure_log(
+        &self,
+        version: BlockHeight,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let result = structure_log::get(self.common.arc_clone().unwrap(), version);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(log)) => {
+                            let obj = structure_log_to_js_object(&mut ctx, log)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Ok(None) => vec![DbUtils::not_found_error(&mut ctx)?.upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// update_overlay applies the data to the overlay, reading the nodes which are not in the overlay from the database.
+    /// The database is never written, so the overlay can be discarded without affecting the state.
+    fn update_overlay(
+        &self,
+        overlay: &mut Overlay,
+        data: smt::UpdateData,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let mut reader = smt_db::SmtReader::new(&self.common);
+        let result = overlay.update(&mut reader, &data);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(root) => {
+                            let buffer = JsBuffer::external(&mut ctx, root);
+                            vec![ctx.null().upcast(), buffer.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    fn prove_prefix(
+        &self,
+        root: Vec<u8>,
+        prefix: Vec<u8>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let key_length = self.options.key_length();
+        let tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT);
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let result = tree.prove_prefix(&smtdb, &prefix);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(val) => {
+                            let obj = DbUtils::prefix_proof_to_js_object(&mut ctx, &val)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    fn dump(&self, root: Vec<u8>, callback: Root<JsFunction>) -> Result<(), DataStoreError> {
+        let key_length = self.options.key_length();
+        let tree = smt::SparseMerkleTree::new(&root, key_length, consts::SUBTREE_HEIGHT);
+        let smtdb = smt_db::SmtReader::new(&self.common);
+        let result = tree.dump(&smtdb);
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(nodes) => {
+                            let arr = DbUtils::dump_to_js_array(&mut ctx, &nodes)?;
+                            vec![ctx.null().upcast(), arr.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// export_chunks calls on_chunk with each encoded chunk of the root in the order of the index, and then calls
+    /// the callback with the number of the chunks. The chunks are created on the thread of the DB, and the next one
+    /// is created only after JS has received the previous one. on_chunk stops the export by returning false.
+    fn export_chunks(
+        &self,
+        root: Vec<u8>,
+        chunk_size: usize,
+        on_chunk: Root<JsFunction>,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let key_length = self.options.key_length();
+        let conn = self.common.arc_clone();
+        self.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let on_chunk = Arc::new(Mutex::new(on_chunk));
+                let start = Instant::now();
+                let smtdb = smt_db::SmtReader::new_with_conn(conn.unwrap());
+                let result = chunk::ChunkExporter::new(&smtdb, &root, key_length, chunk_size)
+                    .and_then(|mut exporter| {
+                        let mut count: u32 = 0;
+                        while let Some(chunk) = exporter.next_chunk()? {
+                            count += 1;
+                            let encoded = chunk.encode();
+                            let on_chunk = Arc::clone(&on_chunk);
+                            let next = channel
+                                .send(move |mut ctx| {
+                                    let buffer = JsBuffer::external(&mut ctx, encoded);
+                                    let on_chunk = on_chunk.lock_or_recover().to_inner(&mut ctx);
+                                    let this = ctx.undefined();
+                                    let result =
+                                        on_chunk.call(&mut ctx, this, vec![buffer.upcast()])?;
+                                    let next = match result.downcast::<JsBoolean, _>(&mut ctx) {
+                                        Ok(next) => next.value(&mut ctx),
+                                        Err(_) => true,
+                                    };
+                                    Ok(next)
+                                })
+                                .join();
+                            // the export is stopped by JS, or JS is no longer running
+                            if !matches!(next, Ok(true)) {
+                                break;
+                            }
+                        }
+                        Ok(count)
+                    });
+                diagnostics::observe(Operation::ExportChunks, start, &result);
+
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(count) => vec![ctx.null().upcast(), ctx.number(count as f64).upcast()],
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// verify_state_root recomputes the root from the stored subtrees and the flat state, and compares them
+    /// with the expected root. The subtrees are rewritten from the flat state if `repair` is true.
+    fn verify_state_root(
+        &self,
+        expected_root: Vec<u8>,
+        repair: bool,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        let _span = tracing::info_span!("verify_state_root", repair).entered();
+        let key_length = self.options.key_length();
+        let result = self
+            .common
+            .get(consts::Prefix::CURRENT_STATE)
+            .map_err(|err| SMTError::Unknown(err.to_string()))
+            .and_then(|bytes| {
+                let current_root = match &bytes {
+                    Some(bytes) => CurrentState::from_bytes(bytes).root.to_vec(),
+                    None => EMPTY_HASH.to_vec(),
+                };
+                root_check::verify(
+                    &self.common,
+                    &expected_root,
+                    &current_root,
+                    key_length,
+                    repair,
+                )
+            });
+
+        self.common
+            .send(move |channel| {
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(check) => {
+                            let obj = root_check_to_js_object(&mut ctx, check)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    /// get_finalized_root returns the root of the version from the root registry or the current state.
+    /// It returns an empty root if the root of the version is no longer known.
+    fn get_finalized_root(
+        conn: &rocksdb::DB,
+        version: BlockHeight,
+    ) -> Result<Vec<u8>, rocksdb::Error> {
+        if let Some(root) = root_registry::get_root(conn, version)? {
+            return Ok(root);
+        }
+        match conn.get(consts::Prefix::CURRENT_STATE)? {
+            Some(bytes) => {
+                let current = CurrentState::from_bytes(&bytes);
+                if current.version.is_equal_to(version.into()) {
+                    Ok(current.root.to_vec())
+                } else {
+                    Ok(vec![])
+                }
+            },
+            None => Ok(vec![]),
+        }
+    }
+
+    /// calculate_diff_between returns the net difference from the state at `from` to the state at `to`
+    /// by merging the diffs of the heights after `from` up to `to`.
+    pub(crate) fn calculate_diff_between(
+        conn: &rocksdb::DB,
+        from: BlockHeight,
+        to: BlockHeight,
+    ) -> Result<diff::Diff, DataStoreError> {
+        let (start, end): (u32, u32) = (from.into(), to.into());
+        if start > end {
+            return Err(DataStoreError::InvalidHeightRange(start, end));
+        }
+        let mut diffs = Vec::with_capacity((end - start) as usize);
+        for height in start + 1..=end {
+            let bytes = StateDB::get_diff_bytes(conn, BlockHeight(height))?;
+            let diff = diff::Diff::decode(&bytes)
+                .map_err(|err| DataStoreError::Unknown(err.to_string()))?;
+            diffs.push(diff);
+        }
+
+        Ok(diff::Diff::merge(diffs))
+    }
+
+    /// get_diff_bytes returns the encoded diff of `version`.
+    fn get_diff_bytes(
+        conn: &rocksdb::DB,
+        version: BlockHeight,
+    ) -> Result<Vec<u8>, DataStoreError> {
+        let key = [consts::Prefix::DIFF, &version.to_be_bytes()].concat();
+        column_family::get(conn, &key)
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))?
+            .ok_or_else(|| DataStoreError::DiffNotFound(version.into()))
+    }
+
+    /// get_diff_stats returns the statistics of the entries and the encoded size of the diff of `version`.
+    pub(crate) fn get_diff_stats(
+        conn: &rocksdb::DB,
+        version: BlockHeight,
+    ) -> Result<(diff::DiffStats, usize), DataStoreError> {
+        let bytes = StateDB::get_diff_bytes(conn, version)?;
+        let diff =
+            diff::Diff::decode(&bytes).map_err(|err| DataStoreError::Unknown(err.to_string()))?;
+
+        Ok((diff.stats(), bytes.len()))
+    }
+
+    /// prune_diffs removes the diffs of the heights lower than `version`, so the state can no longer be reverted below it.
+    /// The removal is recorded in the audit log when `audit_deletion` is enabled, and the rollback sentinel
+    /// is advanced to `version` when `rollback_protection` is enabled.
+    pub(crate) fn prune_diffs(
+        conn: &rocksdb::DB,
+        version: BlockHeight,
+        audit_deletion: bool,
+        rollback_protection: bool,
+    ) -> Result<(), String> {
+        let mut batch = rocksdb::WriteBatch::default();
+        let mut last_audit = if audit_deletion {
+            audit::last_record(conn).map_err(|err| err.to_string())?
+        } else {
+            None
+        };
+        StateDB::add_diff_pruning(
+            conn,
+            &mut batch,
+            version,
+            None,
+            &mut last_audit,
+            audit_deletion,
+            rollback_protection,
+        )?;
+        if !rollback_protection {
+            return column_family::write(conn, batch).map_err(|err| err.to_string());
+        }
+        column_family::write_opt(conn, batch, &sentinel::sync_write_options())
+            .map_err(|err| err.to_string())
+    }
+
+    /// add_diff_pruning adds the removal of the diffs of the heights lower than `version` to the batch.
+    /// The removal is recorded following `last_audit` when `audit_deletion` is enabled, and the rollback sentinel
+    /// is advanced to `version` when `rollback_protection` is enabled, so the batch must then be written with fsync.
+    /// `committed` is the root of `version` when it is written by the same batch, which is not in the conn yet.
+    fn add_diff_pruning(
+        conn: &rocksdb::DB,
+        batch: &mut rocksdb::WriteBatch,
+        version: BlockHeight,
+        committed: Option<&[u8]>,
+        last_audit: &mut Option<audit::AuditRecord>,
+        audit_deletion: bool,
+        rollback_protection: bool,
+    ) -> Result<(), String> {
+        if version.is_equal_to(0) {
+            return Ok(());
+        }
+        let start = [consts::Prefix::DIFF, 0_u32.to_be_bytes().as_slice()].concat();
+        let bytes = (version - BlockHeight(1)).to_be_bytes();
+        let end = [consts::Prefix::DIFF, &bytes].concat();
+        let mut removal = audit::Removal::new();
+
+        let conn_iter = column_family::iterator(
+            conn,
+            consts::Prefix::DIFF,
+            rocksdb::IteratorMode::From(end.as_ref(), rocksdb::Direction::Reverse),
+        );
+
+        for key_val in conn_iter {
+            if utils::compare(&(key_val.as_ref().unwrap().0), &start) == cmp::Ordering::Less {
+                break;
+            }
+            let (key, value) = key_val.unwrap();
+            removal.remove(&key, value.len());
+            batch.delete(&key);
+        }
+
+        if audit_deletion && !removal.is_empty() {
+            audit::append(
+                batch,
+                last_audit,
+                removal,
+                audit::Policy::DiffPruning,
+                version.into(),
+            );
+        }
+        if !rollback_protection {
+            return Ok(());
+        }
+        let root = match committed {
+            Some(root) => root.to_vec(),
+            None => StateDB::get_finalized_root(conn, version).map_err(|err| err.to_string())?,
+        };
+        sentinel::advance(conn, batch, sentinel::Sentinel::new(version, root))
+            .map_err(|err| err.to_string())
+    }
+
+    fn clean_diff_until(
+        &self,
+        version: BlockHeight,
+        callback: Root<JsFunction>,
+    ) -> Result<(), DataStoreError> {
+        if version.is_equal_to(0) {
+            return Ok(());
+        }
+        let conn = self.common.arc_clone();
+        let audit_deletion = self.options.audit_deletion();
+        let rollback_protection = self.options.rollback_protection();
+        self.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = StateDB::prune_diffs(
+                    conn.unwrap(),
+                    version,
+                    audit_deletion,
+                    rollback_protection,
+                );
+
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(_) => {
+                            vec![ctx.null().upcast()]
+                        },
+                        Err(err) => vec![ctx.error(&err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .map_err(|err| DataStoreError::Unknown(err.to_string()))
+    }
+
+    fn proof(ctx: &mut FunctionContext, pos: u8) -> NeonResult<smt::Proof> {
+        let raw_proof = ctx.argument::<JsObject>(pos.into())?;
+        let raw_sibling_hashes = raw_proof
+            .get::<JsArray, _, _>(ctx, "siblingHashes")?
+            .to_vec(ctx)?;
+        let sibling_hashes = raw_sibling_hashes
+            .iter()
+            .map(|key| {
+                Ok(key
+                    .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
+                    .as_slice(ctx)
+                    .to_vec())
+            })
+            .collect::<NeonResult<NestedVec>>()?;
+
+        let raw_queries = raw_proof
+            .get::<JsArray, _, _>(ctx, "queries")?
+            .to_vec(ctx)?;
+        let mut queries: Vec<smt::QueryProof> = Vec::with_capacity(raw_queries.len());
+        for key in raw_queries.iter() {
+            let obj = key.downcast_or_throw::<JsObject, _>(ctx)?;
+            let key = obj
+                .get::<JsTypedArray<u8>, _, _>(ctx, "key")?
+                .as_slice(ctx)
+                .to_vec();
+            let value = obj
+                .get::<JsTypedArray<u8>, _, _>(ctx, "value")?
+                .as_slice(ctx)
+                .to_vec();
+            let bitmap = obj
+                .get::<JsTypedArray<u8>, _, _>(ctx, "bitmap")?
+                .as_slice(ctx)
+                .to_vec();
+            queries.push(smt::QueryProof {
+                pair: Arc::new(KVPair::new(&key, &value)),
+                bitmap: Arc::new(bitmap),
+                stored_value: None,
+            });
+        }
+
+        Ok(smt::Proof {
+            queries,
+            sibling_hashes,
+        })
+    }
+
+    fn prefix_proof(ctx: &mut FunctionContext, pos: u8) -> NeonResult<smt::PrefixProof> {
+        let raw_proof = ctx.argument::<JsObject>(pos.into())?;
+        let prefix = raw_proof
+            .get::<JsTypedArray<u8>, _, _>(ctx, "prefix")?
+            .as_slice(ctx)
+            .to_vec();
+        let raw_sibling_hashes = raw_proof
+            .get::<JsArray, _, _>(ctx, "siblingHashes")?
+            .to_vec(ctx)?;
+        let sibling_hashes = raw_sibling_hashes
+            .iter()
+            .map(|key| {
+                Ok(key
+                    .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
+                    .as_slice(ctx)
+                    .to_vec())
+            })
+            .collect::<NeonResult<NestedVec>>()?;
+
+        let raw_node = raw_proof.get::<JsObject, _, _>(ctx, "node")?;
+        let kind = raw_node.get::<JsString, _, _>(ctx, "kind")?.value(ctx);
+        let node = match kind.as_str() {
+            "empty" => smt::PrefixNode::Empty,
+            "leaf" => smt::PrefixNode::Leaf {
+                key: raw_node
+                    .get::<JsTypedArray<u8>, _, _>(ctx, "key")?
+                    .as_slice(ctx)
+                    .to_vec(),
+                value: raw_node
+                    .get::<JsTypedArray<u8>, _, _>(ctx, "value")?
+                    .as_slice(ctx)
+                    .to_vec(),
+            },
+            "branch" => smt::PrefixNode::Branch(
+                raw_node
+                    .get::<JsTypedArray<u8>, _, _>(ctx, "hash")?
+                    .as_slice(ctx)
+                    .to_vec(),
+            ),
+            _ => return ctx.throw_error(format!("Invalid prefix proof node kind `{}`", kind)),
+        };
+
+        Ok(smt::PrefixProof {
+            prefix,
+            node,
+            sibling_hashes,
+        })
+    }
+
+    fn parse_query_keys(ctx: &mut FunctionContext) -> NeonResult<NestedVec> {
+        let query_keys = ctx.argument::<JsArray>(1)?.to_vec(ctx)?;
+        let parsed_query_keys = query_keys
+            .iter()
+            .map(|key| {
+                Ok(key
+                    .downcast_or_throw::<JsTypedArray<u8>, _>(ctx)?
+                    .as_slice(ctx)
+                    .to_vec())
+            })
+            .collect::<NeonResult<NestedVec>>()?;
+
+        Ok(parsed_query_keys)
+    }
+
+    fn get_current_state(
+        &self,
+        callback: Root<JsFunction>,
+    ) -> Result<(), mpsc::SendError<DbMessage>> {
+        let result = self.common.get(consts::Prefix::CURRENT_STATE);
+        self.common.send(move |channel| {
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(value) => {
+                        let temp_value: Vec<u8>;
+                        let current_state_info = if let Some(value) = value {
+                            temp_value = value;
+                            CurrentState::from_bytes(&temp_value)
+                        } else {
+                            CurrentState::new(&EMPTY_HASH, BlockHeight(0))
+                        };
+                        let root = JsBuffer::external(&mut ctx, current_state_info.root.to_vec());
+                        let version = ctx.number::<u32>(current_state_info.version.into());
+                        let object = ctx.empty_object();
+                        object.set(&mut ctx, "root", root)?;
+                        object.set(&mut ctx, "version", version)?;
+                        vec![ctx.null().upcast(), object.upcast()]
+                    },
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+                Ok(())
+            });
+        })
+    }
+
+    pub fn arc_clone(&self) -> ArcOptionDB {
+        self.common.arc_clone()
+    }
+}
 
 impl StateDB {
     /// js_close is handler for JS ffi.
     /// js "this" - StateDB.
-    pub fn js_close(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
-        // Get the `this` value as a `JsBox<Database>`
-        ctx.this()
-            .downcast_or_throw::<SharedStateDB, _>(&mut ctx)?
-            .borrow_mut()
-            .common
-            .close()
+    /// - @params(0) - optional callback called when the database is released.
+    /// - @callback(0) - Error.
+    pub fn js_close(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = DbUtils::function_argument_opt(&mut ctx, 0);
+        // Get the `this` value as a `JsBox<Database>`
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let mut db = db.borrow_mut();
+        db.commit_listeners.clear();
+        db.common
+            .close(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_subscribe_commit is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - listener called after each commit is written.
+    /// - @callback(0) - { root: &[u8], height: u32, diffStats: DiffStats }.
+    /// - @returns - id of the listener to unsubscribe it.
+    pub fn js_subscribe_commit(mut ctx: FunctionContext) -> JsResult<JsNumber> {
+        let listener = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let id = db.borrow().commit_listeners.subscribe(listener);
+
+        Ok(ctx.number(id))
+    }
+
+    /// js_unsubscribe_commit is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - id of the listener returned by subscribe.
+    /// - @returns - false if the listener was not subscribed.
+    pub fn js_unsubscribe_commit(mut ctx: FunctionContext) -> JsResult<JsBoolean> {
+        let id = ctx.argument::<JsNumber>(0)?.value(&mut ctx) as u32;
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let removed = db.borrow().commit_listeners.unsubscribe(id);
+
+        Ok(ctx.boolean(removed))
+    }
+
+    /// js_get_metrics is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @returns - stats recorded by the commits and the proofs of the state_db, in the same form as getStats.
+    pub fn js_get_metrics(mut ctx: FunctionContext) -> JsResult<JsObject> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let instance = db.borrow().options.metrics();
+
+        metrics::instance_stats_to_js_object(&mut ctx, instance)
+    }
+
+    /// js_get is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - key to get from state db.
+    /// - @params(1) - callback to return the fetched value.
+    /// - @callback(0) - Error. If data is not found, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - [u8]. Value associated with the key.
+    pub fn js_get(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        // Get the `this` value as a `JsBox<Database>`
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow_mut();
+        db.common
+            .get_by_key(key, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_many is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - keys to get from state db.
+    /// - @params(1) - callback to return the fetched values.
+    /// - @callback(0) - Error
+    /// - @callback(1) - ([u8] | undefined)[]. Values associated with the keys in the same order.
+    pub fn js_get_many(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let keys = DbUtils::js_array_to_keys(&mut ctx, 0)?;
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        // Get the `this` value as a `JsBox<Database>`
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        db.common
+            .get_many(keys, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_current_state is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the fetched value.
+    /// - @callback(0) - Error
+    /// - @callback(1) - { root: [u8], version: u32 }.
+    pub fn js_get_current_state(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        // Get the `this` value as a `JsBox<Database>`
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+        db.get_current_state(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_root_at is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - version of the state to get the root of.
+    /// - @params(1) - callback to return the fetched value.
+    /// - @callback(0) - Error. If the nodes of the root are not retained, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - [u8]. State root of the version.
+    pub fn js_get_root_at(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let version: BlockHeight = ctx.argument::<JsNumber>(0)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        // Get the `this` value as a `JsBox<Database>`
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        db.get_root_at(version, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_root_at_height is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - version of the state to get the root of.
+    /// - @params(1) - callback to return the fetched value.
+    /// - @callback(0) - Error. If the version is not registered, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - [u8]. State root committed at the version.
+    pub fn js_get_root_at_height(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let version: BlockHeight = ctx.argument::<JsNumber>(0)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let key = [consts::Prefix::ROOT_HISTORY, &version.to_be_bytes()].concat();
+        db.common
+            .get_by_prefixed_key(&key, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_find_height_for_root is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root to find.
+    /// - @params(1) - callback to return the fetched value.
+    /// - @callback(0) - Error. If the root is not registered, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - number. Highest version committed with the root.
+    pub fn js_find_height_for_root(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        db.find_height_for_root(root, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_store_root is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - store prefix of 6 bytes.
+    /// - @params(1) - version of the state to get the sub-root at.
+    /// - @params(2) - callback to return the fetched value.
+    /// - @callback(0) - Error. If the store has no sub-root at the version, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - [u8]. Sub-root of the store at the version.
+    pub fn js_get_store_root(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let store = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        if store.len() != PREFIX_SIZE {
+            return ctx.throw_error(format!("Store prefix must be {} bytes", PREFIX_SIZE));
+        }
+        let version: BlockHeight = ctx.argument::<JsNumber>(1)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        db.get_store_root(store, version, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_structure_log is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - version of the commit.
+    /// - @params(1) - callback to return the fetched value.
+    /// - @callback(0) - Error. If the commit of the version is not recorded, it will call the callback with "No data" as a first args.
+    /// - @callback(1) - { created: { hash, height }[], deleted: { hash, height }[] }.
+    pub fn js_get_structure_log(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let version: BlockHeight = ctx.argument::<JsNumber>(0)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        db.get_structure_log(version, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_new_overlay is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - committed state root to apply the updates on.
+    /// - @returns - Overlay which keeps the nodes of the updates in memory.
+    pub fn js_new_overlay(mut ctx: FunctionContext) -> JsResult<SharedOverlay> {
+        let root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let key_length = db.borrow().options.key_length();
+
+        Ok(ctx.boxed(RefCell::new(Overlay::new(&root, key_length))))
+    }
+
+    /// js_overlay_update is handler for JS ffi.
+    /// The keys and the values in the batch are hashed in the same way as the commit, and a deleted key is removed from the tree.
+    /// js "this" - StateDB.
+    /// - @params(0) - Overlay created by js_new_overlay.
+    /// - @params(1) - Batch with the tentative updates.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - root after the updates applied to the overlay so far.
+    pub fn js_overlay_update(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let overlay = ctx.argument::<SharedOverlay>(0)?;
+        let batch = ctx.argument::<SendableWriteBatch>(1)?;
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let data = {
+            let batch = batch.borrow();
+            let inner_batch = batch.lock_or_recover();
+            smt::UpdateData::new_from_batch(&inner_batch.batch)
+        }
+        .or_else(|err| DbUtils::throw_with_code(&mut ctx, err))?;
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let mut overlay = overlay.borrow_mut();
+        db.update_overlay(&mut overlay, data, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_calculate_diff_between is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - height of the state to calculate the difference from.
+    /// - @params(1) - height of the state to calculate the difference to.
+    /// - @params(2) - callback to return the difference.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { created: Buffer[], updated: { key, value }[], deleted: { key, value }[] } with the original values.
+    pub fn js_calculate_diff_between(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let from: BlockHeight = ctx.argument::<JsNumber>(0)?.value(&mut ctx).into();
+        let to: BlockHeight = ctx.argument::<JsNumber>(1)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = StateDB::calculate_diff_between(conn.unwrap(), from, to);
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(diff) => {
+                            let obj = DbUtils::diff_to_js_object(&mut ctx, &diff)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_diff_stats is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - height of the diff.
+    /// - @params(1) - callback to return the statistics.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { created: { count, bytes }, updated: { count, bytes }, deleted: { count, bytes }, size }.
+    pub fn js_get_diff_stats(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let version: BlockHeight = ctx.argument::<JsNumber>(0)?.value(&mut ctx).into();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = StateDB::get_diff_stats(conn.unwrap(), version);
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok((stats, size)) => {
+                            let obj = DbUtils::diff_stats_to_js_object(&mut ctx, &stats)?;
+                            let size = ctx.number(size as f64);
+                            obj.set(&mut ctx, "size", size)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_finalized_state is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the fetched value.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { height: u32, root: [u8] } | null. Sentinel of the rollback protection.
+    pub fn js_get_finalized_state(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = sentinel::get(conn.unwrap());
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(Some(val)) => {
+                            let obj = ctx.empty_object();
+                            let height = ctx.number(u32::from(val.height));
+                            obj.set(&mut ctx, "height", height)?;
+                            let root = JsBuffer::external(&mut ctx, val.root);
+                            obj.set(&mut ctx, "root", root)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Ok(None) => vec![ctx.null().upcast(), ctx.null().upcast()],
+                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_lifetime_stats is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the fetched value.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { commits: u64, keysWritten: u64, bytesWritten: u64 }.
+    pub fn js_get_lifetime_stats(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = lifetime_stats::get(conn.unwrap());
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(val) => {
+                            let obj = ctx.empty_object();
+                            let commits = ctx.number(val.commits as f64);
+                            obj.set(&mut ctx, "commits", commits)?;
+                            let keys_written = ctx.number(val.keys_written as f64);
+                            obj.set(&mut ctx, "keysWritten", keys_written)?;
+                            let bytes_written = ctx.number(val.bytes_written as f64);
+                            obj.set(&mut ctx, "bytesWritten", bytes_written)?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
     }
 
-    /// js_get is handler for JS ffi.
+    /// js_debug_report is handler for JS ffi.
+    /// It collects the information attached to the bug reports. The prefix statistics scan the whole database.
     /// js "this" - StateDB.
-    /// - @params(0) - key to get from state db.
-    /// - @params(1) - callback to return the fetched value.
-    /// - @callback(0) - Error. If data is not found, it will call the callback with "No data" as a first args.
-    /// - @callback(1) - [u8]. Value associated with the key.
-    pub fn js_get(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
-        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
-        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
-        // Get the `this` value as a `JsBox<Database>`
+    /// - @params(0) - callback to return the report.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { formatVersion, options, prefixes: { name, prefix, keys, bytes }[], slowOperations: { operation, durationMs, timestamp }[], errors }.
+    pub fn js_debug_report(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
 
-        let db = db.borrow_mut();
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        let options = db.options;
         db.common
-            .get_by_key(key, callback)
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = debug_report::prefix_stats(conn.unwrap());
+                let slow_operations = diagnostics::slow_operations();
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(prefixes) => {
+                            let obj = debug_report_to_js_object(
+                                &mut ctx,
+                                options,
+                                &prefixes,
+                                &slow_operations,
+                            )?;
+                            vec![ctx.null().upcast(), obj.upcast()]
+                        },
+                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
     }
 
-    /// js_get_current_state is handler for JS ffi.
+    /// js_get_audit_records is handler for JS ffi.
     /// js "this" - StateDB.
     /// - @params(0) - callback to return the fetched value.
-    /// - @callback(0) - Error
-    /// - @callback(1) - { root: [u8], version: u32 }.
-    pub fn js_get_current_state(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { sequence: u32, policy: string, height: u32, removedCount: u32, removedBytes: u32, writtenCount: u32, writtenBytes: u32, removedDigest: [u8], prevHash: [u8], hash: [u8] }[].
+    pub fn js_get_audit_records(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
-        // Get the `this` value as a `JsBox<Database>`
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
         let db = db.borrow();
-        db.get_current_state(callback)
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result = audit::get_records(conn.unwrap());
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(records) => {
+                            let arr = ctx.empty_array();
+                            for (i, record) in records.iter().enumerate() {
+                                let obj = audit_record_to_js_object(&mut ctx, record)?;
+                                arr.set(&mut ctx, i as u32, obj)?;
+                            }
+                            vec![ctx.null().upcast(), arr.upcast()]
+                        },
+                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_verify_audit_trail is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - bool. True if none of the audit records are removed or modified.
+    pub fn js_verify_audit_trail(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+
+        let db = db.borrow();
+        let conn = db.common.arc_clone();
+        db.common
+            .send(move |channel| {
+                let callback = CallbackGuard::new(callback, channel);
+                let result =
+                    audit::get_records(conn.unwrap()).map(|records| audit::verify_chain(&records));
+                channel.send(move |mut ctx| {
+                    let callback = callback.into_inner(&mut ctx);
+                    let this = ctx.undefined();
+                    let args: Vec<Handle<JsValue>> = match result {
+                        Ok(val) => vec![ctx.null().upcast(), ctx.boolean(val).upcast()],
+                        Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    };
+                    callback.call(&mut ctx, this, args)?;
+
+                    Ok(())
+                });
+            })
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
@@ -553,13 +2310,13 @@ impl StateDB {
 
     /// js_revert is handler for JS ffi.
     /// js "this" - StateDB.
-    /// - @params(0) - State root of to revert back from.
+    /// - @params(0) - State root of to revert back from, or null to use the root of the current state.
     /// - @params(1) - Version of the state DB to revert back from.
     /// - @params(2) - callback to return the result.
     /// - @callback(0) - Error.
     /// - @callback(1) - &[u8] State root after the revert.
     pub fn js_revert(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
-        let prev_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let prev_root = DbUtils::bytes_argument_opt(&mut ctx, 0);
         let height = ctx.argument::<JsNumber>(1)?.value(&mut ctx).into();
         let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
         // Get the `this` value as a `JsBox<Database>`
@@ -577,7 +2334,8 @@ impl StateDB {
     /// - @params(0) - Options for iteration. {limit: u32, reverse: bool, gte: &[u8], lte: &[u8]}.
     /// - @params(1) - Callback to be called on each data iteration.
     /// - @params(2) - callback to be called when completing the iteration.
-    /// - @callback1(0) - Error.
+    /// - @params(3) - optional AbortToken to stop before the next pair is read.
+    /// - @callback1(0) - Error. It has the code ERR_ABORTED when it is aborted, and the iteration does not complete.
     /// - @callback1(1) - { key: &[u8], value: &[u8]}.
     /// - @callback(0) - void.
     pub fn js_iterate(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
@@ -585,6 +2343,7 @@ impl StateDB {
         let options = options::IterationOption::new(&mut ctx, option_inputs);
         let callback_on_data = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
         let callback_done = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let token = AbortToken::argument_opt(&mut ctx, 3);
         // Get the `this` value as a `JsBox<Database>`
 
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
@@ -594,12 +2353,34 @@ impl StateDB {
         let conn = db.common.arc_clone();
         db.common
             .send(move |channel| {
-                let conn_iter = conn.unwrap().iterator(DbUtils::get_iteration_mode(
-                    &options,
-                    &mut vec![],
-                    true,
-                ));
+                let callback_done = CallbackGuard::new(callback_done, channel);
+                let _span = tracing::info_span!(
+                    "iterate",
+                    limit = options.limit,
+                    reverse = options.reverse
+                )
+                .entered();
+                let mut start = vec![];
+                let mode = DbUtils::get_iteration_mode(&options, &mut start, true);
+                let conn_iter = column_family::iterator_opt(
+                    conn.unwrap(),
+                    consts::Prefix::STATE,
+                    mode,
+                    options.is_within_store_prefix(),
+                );
                 for (counter, key_val) in conn_iter.enumerate() {
+                    if let Some(Err(err)) = token.as_ref().map(AbortToken::check) {
+                        channel.send(move |mut ctx| {
+                            callback_done.take().drop(&mut ctx);
+                            let callback = callback_on_data.lock_or_recover().to_inner(&mut ctx);
+                            let this = ctx.undefined();
+                            let args: Vec<Handle<JsValue>> =
+                                vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()];
+                            callback.call(&mut ctx, this, args)?;
+                            Ok(())
+                        });
+                        return;
+                    }
                     if DbUtils::is_key_out_of_range(
                         &options,
                         &(key_val.as_ref().unwrap().0),
@@ -610,12 +2391,11 @@ impl StateDB {
                     }
                     let callback_on_data = Arc::clone(&callback_on_data);
                     channel.send(move |mut ctx| {
-                        let (_, key_without_prefix) =
-                            key_val.as_ref().unwrap().0.split_first().unwrap();
-                        let temp_pair =
-                            KVPair::new(key_without_prefix, &(key_val.as_ref().unwrap().1));
-                        let obj = pair_to_js_object(&mut ctx, &temp_pair)?;
-                        let callback = callback_on_data.lock().unwrap().to_inner(&mut ctx);
+                        let (key, value) = key_val.unwrap();
+                        let (_, key_without_prefix) = key.split_first().unwrap();
+                        let temp_pair = KVPair(key_without_prefix.to_vec(), value.into_vec());
+                        let obj = pair_into_js_object(&mut ctx, temp_pair)?;
+                        let callback = callback_on_data.lock_or_recover().to_inner(&mut ctx);
                         let this = ctx.undefined();
                         let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), obj.upcast()];
                         callback.call(&mut ctx, this, args)?;
@@ -644,8 +2424,9 @@ impl StateDB {
     /// - @params(3) - readonly not update the state to the physical storage.
     /// - @params(4) - expected state root to compare.
     /// - @params(5) - whether to check the root before storing to the physical storage.
-    /// - @params(6) - callback to return the result.
-    /// - @callback(0) - Error.
+    /// - @params(6) - expected current state { root: &[u8], version: u32 }, or null not to check it.
+    /// - @params(7) - callback to return the result.
+    /// - @callback(0) - Error. It has the code COMMIT_CONFLICT if the current state is not the expected one.
     /// - @callback(1) - &[u8] State root after the commit.
     pub fn js_commit(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let writer = ctx.argument::<state_writer::SendableStateWriter>(0)?;
@@ -659,75 +2440,293 @@ impl StateDB {
         let expected = ctx.argument::<JsTypedArray<u8>>(4)?.as_slice(&ctx).to_vec();
 
         let check_root = ctx.argument::<JsBoolean>(5)?.value(&mut ctx);
-        let callback = ctx.argument::<JsFunction>(6)?.root(&mut ctx);
+        let expected_state = match ctx.argument_opt(6) {
+            Some(val) if val.is_a::<JsObject, _>(&mut ctx) => {
+                let obj = val.downcast_or_throw::<JsObject, _>(&mut ctx)?;
+                let root = obj
+                    .get::<JsTypedArray<u8>, _, _>(&mut ctx, "root")?
+                    .as_slice(&ctx)
+                    .to_vec();
+                let version = obj
+                    .get::<JsNumber, _, _>(&mut ctx, "version")?
+                    .value(&mut ctx)
+                    .into();
+                Some(ExpectedState { root, version })
+            },
+            _ => None,
+        };
+        let callback = ctx.argument::<JsFunction>(7)?.root(&mut ctx);
         // Get the `this` value as a `JsBox<Database>`
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
 
-        let mut db = db.borrow_mut();
-        if db.options.is_readonly() {
-            return ctx.throw_error(String::from("Readonly DB cannot be committed."));
+        let mut db = db.borrow_mut();
+        if db.options.is_readonly() {
+            return ctx.throw_error(String::from("Readonly DB cannot be committed."));
+        }
+        let options = CommitOptions::new(readonly, version);
+        let commit =
+            Commit::new(expected, options, check_root).with_expected_state(expected_state);
+        let writer = Arc::clone(&writer.borrow());
+        let commit_data = CommitData::new(commit, prev_root);
+        db.commit(writer, commit_data, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_prove is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - current state root (required).
+    /// - @params(1) - queries in format of &[&[u8]]. If values are included, the queries are the state keys.
+    /// - @params(2) - whether to include the stored values of the queries.
+    /// - @params(3) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; storedValue?: &[u8]; }[]; }
+    pub fn js_prove(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+
+        let input = ctx.argument::<JsArray>(1)?.to_vec(&mut ctx)?;
+        let mut queries = NestedVec::new();
+        for item in input.iter() {
+            let obj = item.downcast_or_throw::<JsTypedArray<u8>, _>(&mut ctx)?;
+            let key = obj.as_slice(&ctx).to_vec();
+            queries.push(key);
+        }
+
+        let include_values = ctx.argument::<JsBoolean>(2)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+
+        db.prove(state_root, queries, include_values, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_prove_many is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root to prove.
+    /// - @params(1) - sets of the query keys, which are the state keys when the values are included.
+    /// - @params(2) - true if the stored values of the included queries should be returned.
+    /// - @params(3) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - proof of each set in the order of the sets.
+    pub fn js_prove_many(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+
+        let input = ctx.argument::<JsArray>(1)?.to_vec(&mut ctx)?;
+        let mut query_sets = Vec::with_capacity(input.len());
+        for set in input.iter() {
+            let set = set
+                .downcast_or_throw::<JsArray, _>(&mut ctx)?
+                .to_vec(&mut ctx)?;
+            let mut queries = NestedVec::with_capacity(set.len());
+            for item in set.iter() {
+                let obj = item.downcast_or_throw::<JsTypedArray<u8>, _>(&mut ctx)?;
+                queries.push(obj.as_slice(&ctx).to_vec());
+            }
+            query_sets.push(queries);
+        }
+
+        let include_values = ctx.argument::<JsBoolean>(2)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+
+        db.prove_many(state_root, query_sets, include_values, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_export_chunks is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root to export.
+    /// - @params(1) - number of key-value pairs in a chunk.
+    /// - @params(2) - callback to be called with each chunk. It stops the export by returning false.
+    /// - @params(3) - callback to be called when completing the export.
+    /// - @callback2(0) - [u8]. Encoded chunk. The chunks are in the order of the index.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - number. Number of the exported chunks.
+    pub fn js_export_chunks(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let chunk_size = ctx.argument::<JsNumber>(1)?.value(&mut ctx) as usize;
+        let on_chunk = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+
+        db.export_chunks(state_root, chunk_size, on_chunk, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_verify_state_root is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root expected to be the current state.
+    /// - @params(1) - whether to rewrite the subtrees of the root from the flat state when only they diverge.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { valid: bool; repaired: bool; expectedRoot: &[u8]; currentRoot: &[u8]; computedRoot: &[u8]; inconsistentNodes: &[u8][]; }
+    pub fn js_verify_state_root(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let expected_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let repair = ctx.argument::<JsBoolean>(1)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+        if repair && db.options.is_readonly() {
+            return ctx.throw_error(String::from("Readonly DB cannot be repaired."));
+        }
+
+        db.verify_state_root(expected_root, repair, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_verify is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - current state root.
+    /// - @params(1) - queries in format of &[&[u8]]
+    /// - @params(2) - proof { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; }[]; }
+    /// - @params(3) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - bool represents true if proof is valid.
+    pub fn js_verify(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+        let key_length = db.options.key_length();
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+
+        let proof = Self::proof(&mut ctx, 2)?;
+        let parsed_query_keys = Self::parse_query_keys(&mut ctx)?;
+        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result =
+                smt::SparseMerkleTree::verify(&parsed_query_keys, &proof, &state_root, key_length);
+
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let args: Vec<Handle<JsValue>> = match result {
+                    Ok(val) => {
+                        vec![ctx.null().upcast(), JsBoolean::new(&mut ctx, val).upcast()]
+                    },
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
+                };
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            })
+        })
+        .or_else(|err| DbUtils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_value_hash is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root of the tree.
+    /// - @params(1) - key in the tree, which is the hashed state key.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error. NotFound if the key is not in the tree.
+    /// - @callback(1) - [u8]. Value hash of the key.
+    pub fn js_get_value_hash(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let key = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        db.get_value_hash(state_root, key, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_prove_prefix is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root to prove.
+    /// - @params(1) - key prefix such as the module or the store prefix. It must not be longer than 6 bytes.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { prefix: &[u8]; subtreeRoot: &[u8]; node: { kind: string; key?: &[u8]; value?: &[u8]; hash?: &[u8]; }; siblingHashes: &[&[u8]]; }
+    pub fn js_prove_prefix(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let prefix = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        if prefix.len() > PREFIX_SIZE {
+            return ctx.throw_error(format!(
+                "Prefix must not be longer than {} bytes",
+                PREFIX_SIZE
+            ));
         }
-        let options = CommitOptions::new(readonly, version);
-        let commit = Commit::new(expected, options, check_root);
-        let writer = Arc::clone(&writer.borrow());
-        let commit_data = CommitData::new(commit, prev_root);
-        db.commit(writer, commit_data, callback)
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        db.prove_prefix(state_root, prefix, callback)
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
     }
 
-    /// js_prove is handler for JS ffi.
+    /// js_generate_witness is handler for JS ffi.
     /// js "this" - StateDB.
-    /// - @params(0) - current state root (required).
-    /// - @params(1) - queries in format of &[&[u8]]
+    /// - @params(0) - state root before the block.
+    /// - @params(1) - state keys read during the execution of the block.
     /// - @params(2) - callback to return the result.
-    /// - @callback(0) - Error.
-    /// - @callback(1) - { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; }[]; }
-    pub fn js_prove(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    /// - @callback(0) - Error. If the value of an included key is not in the current state, it is the error with NOT_FOUND code.
+    /// - @callback(1) - [u8]. Encoded witness with the values and the proof of the keys.
+    pub fn js_generate_witness(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
         let db = db.borrow();
 
         let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
-
         let input = ctx.argument::<JsArray>(1)?.to_vec(&mut ctx)?;
-        let mut queries = NestedVec::new();
-        for item in input.iter() {
-            let obj = item.downcast_or_throw::<JsTypedArray<u8>, _>(&mut ctx)?;
-            let key = obj.as_slice(&ctx).to_vec();
-            queries.push(key);
+        let mut keys = NestedVec::with_capacity(input.len());
+        for key in input.iter() {
+            let key = key.downcast_or_throw::<JsTypedArray<u8>, _>(&mut ctx)?;
+            keys.push(key.as_slice(&ctx).to_vec());
         }
-
         let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
 
-        db.prove(state_root, queries, callback)
+        db.generate_witness(state_root, keys, callback)
             .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
     }
 
-    /// js_verify is handler for JS ffi.
+    /// js_verify_prefix is handler for JS ffi.
     /// js "this" - StateDB.
-    /// - @params(0) - current state root.
-    /// - @params(1) - queries in format of &[&[u8]]
-    /// - @params(2) - proof { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; }[]; }
-    /// - @params(3) - callback to return the result.
+    /// - @params(0) - state root.
+    /// - @params(1) - proof { prefix: &[u8]; node: { kind: string; key?: &[u8]; value?: &[u8]; hash?: &[u8]; }; siblingHashes: &[&[u8]]; }
+    /// - @params(2) - callback to return the result.
     /// - @callback(0) - Error.
     /// - @callback(1) - bool represents true if proof is valid.
-    pub fn js_verify(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    pub fn js_verify_prefix(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
         let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
         let db = db.borrow();
         let key_length = db.options.key_length();
         let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
 
-        let proof = Self::proof(&mut ctx, 2)?;
-        let parsed_query_keys = Self::parse_query_keys(&mut ctx)?;
-        let callback = ctx.argument::<JsFunction>(3)?.root(&mut ctx);
+        let proof = Self::prefix_proof(&mut ctx, 1)?;
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
         let channel = ctx.channel();
 
-        thread::spawn(move || {
-            let result =
-                smt::SparseMerkleTree::verify(&parsed_query_keys, &proof, &state_root, key_length);
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let result = smt::SparseMerkleTree::verify_prefix(&state_root, &proof, key_length);
 
             channel.send(move |mut ctx| {
                 let callback = callback.into_inner(&mut ctx);
@@ -736,13 +2735,33 @@ impl StateDB {
                     Ok(val) => {
                         vec![ctx.null().upcast(), JsBoolean::new(&mut ctx, val).upcast()]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| DbUtils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_to_visualization is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - state root to dump.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { type: string; height: number; hash: &[u8]; key: &[u8]; value: &[u8]; }[] in pre-order.
+    pub fn js_to_visualization(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let state_root = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+
+        db.dump(state_root, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
 
         Ok(ctx.undefined())
     }
@@ -785,6 +2804,210 @@ impl StateDB {
         Ok(ctx.undefined())
     }
 
+    /// js_create_backup is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - directory of the backups.
+    /// - @params(1) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_create_backup(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let dir = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+
+        db.common
+            .create_backup(dir, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_compact_range is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - start key of the range, or null to compact from the first key.
+    /// - @params(1) - end key of the range, or null to compact to the last key.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_compact_range(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let start = DbUtils::bytes_argument_opt(&mut ctx, 0);
+        let end = DbUtils::bytes_argument_opt(&mut ctx, 1);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        db.common
+            .compact_range(start, end, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_compact_smt is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_compact_smt(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .compact_smt(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_set_auto_compaction is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - true to enable the automatic compaction.
+    pub fn js_set_auto_compaction(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let enabled = ctx.argument::<JsBoolean>(0)?.value(&mut ctx);
+
+        db.common
+            .set_auto_compaction(enabled)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_flush is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_flush(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .flush(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_sync_wal is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    pub fn js_sync_wal(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .sync_wal(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_properties is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { estimatedKeys, totalSstSize, memtableUsage, pendingCompactionBytes, columnFamilies }.
+    pub fn js_properties(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .properties(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_write_stall is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { condition: 'normal' | 'delayed' | 'stopped', delayedWriteRate }.
+    pub fn js_write_stall(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .write_stall(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_approximate_size is handler for JS ffi.
+    /// js "this" - StateDB.
+    /// - @params(0) - start key of the range, or null to estimate from the first key.
+    /// - @params(1) - end key of the range, or null to estimate to the last key.
+    /// - @params(2) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { size, estimatedKeys }.
+    pub fn js_approximate_size(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let start = DbUtils::bytes_argument_opt(&mut ctx, 0);
+        let end = DbUtils::bytes_argument_opt(&mut ctx, 1);
+        let callback = ctx.argument::<JsFunction>(2)?.root(&mut ctx);
+
+        db.common
+            .approximate_size(start, end, callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_new_secondary is handler for JS ffi.
+    /// - @params(0) - path of the primary state_db.
+    /// - @params(1) - path to keep the logs of the secondary instance.
+    /// - @params(2) - options of the state_db.
+    /// - @returns - StateDB opened as a secondary instance of the primary.
+    pub fn js_new_secondary(mut ctx: FunctionContext) -> JsResult<SharedStateDB> {
+        let primary = ctx.argument::<JsString>(0)?.value(&mut ctx);
+        let secondary = ctx.argument::<JsString>(1)?.value(&mut ctx);
+        let options = ctx.argument_opt(2);
+        let db_opts = DbOptions::new_with_context(&mut ctx, options)?;
+        let common = DB::open_as_secondary(&mut ctx, primary, secondary, db_opts, Kind::State)
+            .or_else(|err| DbUtils::throw_open_error(&mut ctx, err))?;
+        let db = Self {
+            common,
+            options: db_opts,
+            commit_listeners: CommitListeners::default(),
+        };
+
+        Ok(ctx.boxed(RefCell::new(db)))
+    }
+
+    /// js_try_catch_up_with_primary is handler for JS ffi.
+    /// js "this" - StateDB opened as a secondary instance.
+    /// - @params(0) - callback to return the result.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { changed: bool, sequenceNumber: u64 }.
+    pub fn js_try_catch_up_with_primary(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let db = ctx.this().downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let db = db.borrow();
+
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+
+        db.common
+            .catch_up_with_primary(callback)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+
+        Ok(ctx.undefined())
+    }
+
     /// js_calculate_root is handler for JS ffi.
     /// js "this" - StateDB.
     /// - @params(0) - proof { siblingHashes: &[&[u8]]; queries: { key: &[u8]; value: &[u8]; bitmap: &[u8]; }[]; }
@@ -796,7 +3019,8 @@ impl StateDB {
         let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
         let channel = ctx.channel();
 
-        thread::spawn(move || {
+        let pool = task_pool::shared();
+        pool.execute(move || {
             let result: Result<Vec<u8>, SMTError> =
                 match smt::SparseMerkleTree::prepare_queries_with_proof_map(&proof) {
                     Ok(filter_map) => {
@@ -822,13 +3046,14 @@ impl StateDB {
                             JsBuffer::external(&mut ctx, val).upcast(),
                         ]
                     },
-                    Err(err) => vec![ctx.error(err.to_string())?.upcast()],
+                    Err(err) => vec![DbUtils::error_with_code(&mut ctx, &err)?.upcast()],
                 };
                 callback.call(&mut ctx, this, args)?;
 
                 Ok(())
             })
-        });
+        })
+        .or_else(|err| DbUtils::throw_with_code(&mut ctx, err))?;
 
         Ok(ctx.undefined())
     }
@@ -836,8 +3061,20 @@ impl StateDB {
 
 #[cfg(test)]
 mod tests {
+    use tempdir::TempDir;
+
     use super::*;
-    use crate::types::BlockHeight;
+    use crate::task_pool::SerialQueue;
+    use crate::types::{BlockHeight, KeyLength, SharedKVPair};
+
+    fn temp_state_conn() -> (DB, TempDir) {
+        let temp_dir = TempDir::new("test_state_db").unwrap();
+        let rocks_db = rocksdb::DB::open_default(&temp_dir).unwrap();
+        (
+            DB::new(rocks_db, SerialQueue::closed(), Kind::State),
+            temp_dir,
+        )
+    }
 
     #[test]
     fn test_current_state_convert() {
@@ -869,4 +3106,595 @@ mod tests {
             assert_eq!(CurrentState::from_bytes(&state_as_bytes), state_as_struct);
         }
     }
+
+    #[test]
+    fn test_commit_block() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38)).with_keep_root_history(true);
+
+        let mut writer = state_writer::StateWriter::default();
+        let key = [0, 0, 0, 1, 0, 0, 1, 2, 3, 4];
+        writer.cache_new(&SharedKVPair::new(&key, &[5, 6]));
+        let mut state = batch::PrefixWriteBatch::new();
+        state.set_prefix(&consts::Prefix::STATE);
+        let (diff, data) = writer.commit_with_update_data(&mut state);
+        let mut smt_db = smt_db::SmtDB::new_with_retention(&conn, options.keep_root_history());
+        let mut tree =
+            smt::SparseMerkleTree::new(&EMPTY_HASH, options.key_length(), consts::SUBTREE_HEIGHT);
+        let root = tree.commit(&mut smt_db, &data).unwrap();
+        let root = (**root.lock().unwrap()).clone();
+
+        let version = BlockHeight(1);
+        let batch =
+            StateDB::commit_block(&conn, &smt_db, state, &diff, version, &root, options).unwrap();
+        assert_eq!(conn.get(consts::Prefix::CURRENT_STATE).unwrap(), None);
+        assert_eq!(
+            conn.get(&[consts::Prefix::STATE, &key].concat()).unwrap(),
+            None
+        );
+        assert!(batch.ranges.is_empty());
+
+        conn.write_with_ranges(&batch.ranges, batch.batch).unwrap();
+        let current = conn.get(consts::Prefix::CURRENT_STATE).unwrap().unwrap();
+        assert_eq!(
+            CurrentState::from_bytes(&current),
+            CurrentState::new(&root, version)
+        );
+        let state_key = [consts::Prefix::STATE, &key].concat();
+        assert_eq!(conn.get(&state_key).unwrap(), Some(vec![5, 6]));
+        let diff_key = [consts::Prefix::DIFF, &version.to_be_bytes()].concat();
+        assert!(conn.get(&diff_key).unwrap().is_some());
+        let history_key = [consts::Prefix::ROOT_HISTORY, &version.to_be_bytes()].concat();
+        assert_eq!(conn.get(&history_key).unwrap(), Some(root));
+    }
+
+    fn commit_at(
+        conn: &DB,
+        writer: state_writer::StateWriter,
+        prev_root: &[u8],
+        height: u32,
+        options: DbOptions,
+    ) -> Vec<u8> {
+        let commit = Commit::new(
+            vec![],
+            CommitOptions::new(false, BlockHeight(height)),
+            false,
+        );
+        let commit_data = CommitData::new(commit, prev_root.to_vec());
+        let writer = Mutex::new(writer);
+        let root =
+            StateDB::commit_writer(conn, writer.lock().unwrap(), commit_data, options).unwrap();
+        let value = root.lock().unwrap().to_vec();
+        value
+    }
+
+    #[test]
+    fn test_commit_deleted_range() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+
+        let mut writer = state_writer::StateWriter::default();
+        for i in 1..=3u8 {
+            writer.cache_new(&SharedKVPair::new(&[i; 8], &[i]));
+        }
+        let root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.delete_range(&[1; 8], &[3; 8]);
+        let added = [1, 1, 1, 1, 1, 1, 1, 1, 9];
+        writer.cache_new(&SharedKVPair::new(&added, &[9]));
+        let root = commit_at(&conn, writer, &root, 2, options);
+
+        let state_key = |key: &[u8]| [consts::Prefix::STATE, key].concat();
+        assert_eq!(conn.get(&state_key(&[1; 8])).unwrap(), None);
+        assert_eq!(conn.get(&state_key(&[2; 8])).unwrap(), None);
+        assert_eq!(conn.get(&state_key(&[3; 8])).unwrap(), Some(vec![3]));
+        assert_eq!(conn.get(&state_key(&added)).unwrap(), Some(vec![9]));
+        let diff_key = [consts::Prefix::DIFF, &2u32.to_be_bytes()].concat();
+        let diff = diff::Diff::decode(&conn.get(&diff_key).unwrap().unwrap()).unwrap();
+        assert_eq!(diff.revert_hashed_update().len(), 3);
+
+        // The root must be the same as the one of the state written without the range
+        let (expected_conn, _expected_dir) = temp_state_conn();
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[3; 8], &[3]));
+        writer.cache_new(&SharedKVPair::new(&added, &[9]));
+        let expected = commit_at(&expected_conn, writer, &EMPTY_HASH, 1, options);
+        assert_eq!(root, expected);
+    }
+
+    #[test]
+    fn test_revert_current_state() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let first_root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.cache_existing(&SharedKVPair::new(&[2; 8], &[2]));
+        writer.update(&KVPair::new(&[1; 8], &[10])).unwrap();
+        writer.delete(&[2; 8]);
+        writer.cache_new(&SharedKVPair::new(&[3; 8], &[3]));
+        commit_at(&conn, writer, &first_root, 2, options);
+
+        let err = StateDB::get_revert_result(&conn, BlockHeight(1), None, options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot revert height `1` from the current height `2`"
+        );
+
+        let root = StateDB::get_revert_result(&conn, BlockHeight(2), None, options).unwrap();
+        assert_eq!(**root.lock().unwrap(), first_root);
+        let state_key = |key: &[u8]| [consts::Prefix::STATE, key].concat();
+        assert_eq!(conn.get(&state_key(&[1; 8])).unwrap(), Some(vec![1]));
+        assert_eq!(conn.get(&state_key(&[2; 8])).unwrap(), Some(vec![2]));
+        assert_eq!(conn.get(&state_key(&[3; 8])).unwrap(), None);
+        let current = conn.get(consts::Prefix::CURRENT_STATE).unwrap().unwrap();
+        let expected = CurrentState::new(&first_root, BlockHeight(1));
+        assert_eq!(CurrentState::from_bytes(&current), expected);
+    }
+
+    #[test]
+    fn test_commit_key_filter() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38)).with_smt_key_filter(true);
+        let tree_key = |key: &[u8]| key.to_vec().hash_with_kind(HashKind::Key);
+
+        // the state_db without the filter rebuilds it in the background
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        let first_root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        assert!(
+            key_filter::get_for_root(conn.arc_clone().unwrap(), &first_root)
+                .unwrap()
+                .is_none()
+        );
+        key_filter::wait_for_rebuild(&conn);
+
+        // the rebuilt filter is taken by the next commit with the keys committed meanwhile
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.delete(&[1; 8]);
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let second_root = commit_at(&conn, writer, &first_root, 2, options);
+        let range = key_filter::get(conn.arc_clone().unwrap(), BlockHeight(2))
+            .unwrap()
+            .unwrap();
+        assert!(range.from().0 <= 1);
+        assert_eq!(range.to(), BlockHeight(2));
+        let filter = key_filter::get_for_root(conn.arc_clone().unwrap(), &second_root)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&filter, range.filter());
+        // the deleted key stays in the filter until it is rebuilt
+        assert!(filter.contains(&tree_key(&[1; 8])));
+        assert!(filter.contains(&tree_key(&[2; 8])));
+        assert_eq!(filter.count(), 2);
+
+        let mut tree =
+            smt::SparseMerkleTree::new(&second_root, KeyLength(38), consts::SUBTREE_HEIGHT);
+        let smtdb = smt_db::SmtReader::new(&conn);
+        let value_hash = tree.get(&smtdb, &tree_key(&[2; 8]), Some(&filter)).unwrap();
+        assert_eq!(value_hash, Some(vec![2u8].hash_with_kind(HashKind::Value)));
+        assert_eq!(
+            tree.get(&smtdb, &tree_key(&[1; 8]), Some(&filter)).unwrap(),
+            None
+        );
+
+        // the next commit extends the same range, which is stored once
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[3; 8], &[3]));
+        commit_at(&conn, writer, &second_root, 3, options);
+        let range = key_filter::get(conn.arc_clone().unwrap(), BlockHeight(3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(range.to(), BlockHeight(3));
+        assert!(range.filter().contains(&tree_key(&[3; 8])));
+        let ranges = conn
+            .arc_clone()
+            .unwrap()
+            .prefix_iterator(consts::Prefix::SMT_KEY_FILTER)
+            .filter_map(Result::ok)
+            .take_while(|(key, _)| key.starts_with(consts::Prefix::SMT_KEY_FILTER))
+            .count();
+        assert_eq!(ranges, 1);
+
+        // the range ends before the reverted version
+        StateDB::get_revert_result(&conn, BlockHeight(3), None, options).unwrap();
+        assert!(key_filter::get(conn.arc_clone().unwrap(), BlockHeight(3))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            key_filter::get_for_root(conn.arc_clone().unwrap(), &second_root).unwrap(),
+            Some(range.filter().clone())
+        );
+    }
+
+    #[test]
+    fn test_commit_store_roots() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38)).with_store_roots(true);
+        let store_root = |store: &[u8], version: u32| {
+            store_roots::get(conn.arc_clone().unwrap(), store, BlockHeight(version)).unwrap()
+        };
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let first_root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        let first_store_root = store_root(&[1; 6], 1).unwrap();
+        let tree = smt::SparseMerkleTree::new(&first_root, KeyLength(38), consts::SUBTREE_HEIGHT);
+        let smtdb = smt_db::SmtReader::new(&conn);
+        let proof = tree.prove_prefix(&smtdb, &[1; 6]).unwrap();
+        assert_eq!(proof.subtree_root(), first_store_root);
+        assert!(smt::SparseMerkleTree::verify_prefix(&first_root, &proof, KeyLength(38)).unwrap());
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.update(&KVPair::new(&[1; 8], &[10])).unwrap();
+        commit_at(&conn, writer, &first_root, 2, options);
+        assert_ne!(store_root(&[1; 6], 2), Some(first_store_root.clone()));
+        // the store not updated at the height keeps the sub-root of the previous height
+        assert_eq!(store_root(&[2; 6], 2), store_root(&[2; 6], 1));
+        assert_eq!(store_root(&[3; 6], 2), None);
+
+        StateDB::get_revert_result(&conn, BlockHeight(2), None, options).unwrap();
+        assert_eq!(store_root(&[1; 6], 2), Some(first_store_root));
+    }
+
+    #[test]
+    fn test_commit_root_registry() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+        let root_at = |version: u32| {
+            root_registry::get_root(conn.arc_clone().unwrap(), BlockHeight(version)).unwrap()
+        };
+        let height_of = |root: &[u8]| {
+            root_registry::find_height(conn.arc_clone().unwrap(), root)
+                .unwrap()
+                .map(u32::from)
+        };
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        let first_root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        let second_root = commit_at(
+            &conn,
+            state_writer::StateWriter::default(),
+            &first_root,
+            2,
+            options,
+        );
+        assert_eq!(second_root, first_root);
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let third_root = commit_at(&conn, writer, &second_root, 3, options);
+
+        assert_eq!(root_at(1), Some(first_root.clone()));
+        assert_eq!(root_at(3), Some(third_root.clone()));
+        assert_eq!(root_at(4), None);
+        // the root committed at several heights is found at the highest of them
+        assert_eq!(height_of(&first_root), Some(2));
+        assert_eq!(height_of(&third_root), Some(3));
+        assert_eq!(height_of(&EMPTY_HASH), None);
+        assert_eq!(
+            StateDB::get_finalized_root(conn.arc_clone().unwrap(), BlockHeight(1)).unwrap(),
+            first_root
+        );
+        // the nodes of the roots are not retained without keepRootHistory
+        assert_eq!(
+            StateDB::get_retained_root(conn.arc_clone().unwrap(), BlockHeight(3), &options)
+                .unwrap(),
+            None
+        );
+
+        StateDB::get_revert_result(&conn, BlockHeight(3), None, options).unwrap();
+        assert_eq!(root_at(3), None);
+        assert_eq!(height_of(&third_root), None);
+        StateDB::get_revert_result(&conn, BlockHeight(2), None, options).unwrap();
+        assert_eq!(height_of(&first_root), Some(1));
+    }
+
+    #[test]
+    fn test_root_history_retention() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38))
+            .with_keep_root_history(true)
+            .with_root_history_retention(Some(2));
+        let history = |version: u32| {
+            conn.get(&[consts::Prefix::ROOT_HISTORY, &version.to_be_bytes()].concat())
+                .unwrap()
+        };
+        let node = |root: &[u8]| conn.get(&[consts::Prefix::SMT, root].concat()).unwrap();
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        let mut roots = vec![commit_at(&conn, writer, &EMPTY_HASH, 1, options)];
+        // the state of the height 3 is the one of the height 1, so the released nodes are created again
+        for (height, prev, value) in [(2, 1, 2), (3, 2, 1), (4, 1, 4)] {
+            let mut writer = state_writer::StateWriter::default();
+            writer.cache_existing(&SharedKVPair::new(&[1; 8], &[prev]));
+            writer.update(&KVPair::new(&[1; 8], &[value])).unwrap();
+            let root = commit_at(&conn, writer, roots.last().unwrap(), height, options);
+            roots.push(root);
+        }
+        assert_eq!(roots[0], roots[2]);
+
+        // the roots stay in the root history after their nodes are pruned
+        assert_eq!(history(1), Some(roots[0].clone()));
+        assert_eq!(history(2), Some(roots[1].clone()));
+        assert_eq!(history(3), Some(roots[2].clone()));
+        assert_eq!(history(4), Some(roots[3].clone()));
+        assert!(node(&roots[1]).is_none());
+        assert!(node(&roots[2]).is_some());
+        assert!(node(&roots[3]).is_some());
+        let retained = |version: u32| {
+            StateDB::get_retained_root(conn.arc_clone().unwrap(), BlockHeight(version), &options)
+                .unwrap()
+        };
+        assert_eq!(retained(2), None);
+        assert_eq!(retained(3), Some(roots[2].clone()));
+        assert_eq!(retained(4), Some(roots[3].clone()));
+        assert_eq!(retained(5), None);
+
+        // the nodes deleted by the revert are released at the previous height
+        StateDB::get_revert_result(&conn, BlockHeight(4), None, options).unwrap();
+        assert!(node(&roots[3]).is_some());
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.update(&KVPair::new(&[1; 8], &[5])).unwrap();
+        let root = commit_at(&conn, writer, &roots[2], 4, options);
+        assert_eq!(history(4), Some(root.clone()));
+        assert!(node(&roots[3]).is_none());
+        assert!(node(&roots[2]).is_some());
+        assert!(node(&root).is_some());
+    }
+
+    #[test]
+    fn test_root_history_mode() {
+        let (db, _temp_dir) = temp_state_conn();
+        let conn = db.conn();
+
+        root_history::check_mode(conn, true, true).unwrap();
+        assert_eq!(conn.get(consts::Prefix::ROOT_HISTORY_MODE).unwrap(), None);
+        root_history::check_mode(conn, true, false).unwrap();
+        root_history::check_mode(conn, true, false).unwrap();
+        let err = root_history::check_mode(conn, false, true).unwrap_err();
+        assert!(matches!(
+            err,
+            OpenError::RootHistoryMismatch { stored: true }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Database was created with keepRootHistory `true`, but it is opened with `false`"
+        );
+    }
+
+    #[test]
+    fn test_commit_structure_log() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38)).with_structure_log(true);
+        let log = |version: u32| {
+            structure_log::get(conn.arc_clone().unwrap(), BlockHeight(version)).unwrap()
+        };
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let first_root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        let first = log(1).unwrap();
+        assert!(first
+            .created
+            .iter()
+            .any(|change| change.hash == first_root && change.height > 0));
+        assert!(first.deleted.is_empty());
+
+        // the keys of both stores are below a stub, which is replaced by the update
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.update(&KVPair::new(&[1; 8], &[10])).unwrap();
+        let second_root = commit_at(&conn, writer, &first_root, 2, options);
+        let second = log(2).unwrap();
+        assert!(!second.deleted.is_empty());
+        for change in second.deleted.iter() {
+            assert!(first.created.contains(change));
+        }
+
+        StateDB::get_revert_result(&conn, BlockHeight(2), None, options).unwrap();
+        assert_eq!(log(2), None);
+        assert_eq!(log(1), Some(first));
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.update(&KVPair::new(&[1; 8], &[10])).unwrap();
+        let options = options.with_structure_log(false);
+        assert_eq!(
+            commit_at(&conn, writer, &first_root, 2, options),
+            second_root
+        );
+        assert_eq!(log(2), None);
+    }
+
+    #[test]
+    fn test_commit_event() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+
+        let commit_with_event = |writer: state_writer::StateWriter, readonly: bool| {
+            let commit = Commit::new(vec![], CommitOptions::new(readonly, BlockHeight(1)), false);
+            let commit_data = CommitData::new(commit, EMPTY_HASH.to_vec());
+            let writer = Mutex::new(writer);
+            let guard = writer.lock().unwrap();
+            StateDB::commit_writer_with_event(&conn, guard, commit_data, options).unwrap()
+        };
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1, 2]));
+        let (_, event) = commit_with_event(writer, true);
+        assert_eq!(event, None);
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1, 2]));
+        let (root, event) = commit_with_event(writer, false);
+        let event = event.unwrap();
+        assert_eq!(event.root, **root.lock().unwrap());
+        assert_eq!(event.height, BlockHeight(1));
+        assert_eq!(event.stats.created.count, 1);
+        assert_eq!(event.stats.updated.count, 0);
+        assert_eq!(event.stats.deleted.count, 0);
+    }
+
+    #[test]
+    fn test_calculate_diff_between() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1; 8], &[1]));
+        let root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1; 8], &[1]));
+        writer.update(&KVPair::new(&[1; 8], &[10])).unwrap();
+        writer.cache_new(&SharedKVPair::new(&[2; 8], &[2]));
+        let root = commit_at(&conn, writer, &root, 2, options);
+        let mut writer = state_writer::StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[2; 8], &[2]));
+        writer.delete(&[2; 8]);
+        commit_at(&conn, writer, &root, 3, options);
+
+        let arc_db = conn.arc_clone();
+        let rocks_db = arc_db.unwrap();
+        let diff = StateDB::calculate_diff_between(rocks_db, BlockHeight(1), BlockHeight(3));
+        let expected = diff::Diff::new(vec![], vec![KVPair::new(&[1; 8], &[1])], vec![]);
+        assert_eq!(diff.unwrap(), expected);
+        let diff = StateDB::calculate_diff_between(rocks_db, BlockHeight(0), BlockHeight(2));
+        let expected = diff::Diff::new(vec![vec![1; 8], vec![2; 8]], vec![], vec![]);
+        assert_eq!(diff.unwrap(), expected);
+
+        let err = StateDB::calculate_diff_between(rocks_db, BlockHeight(3), BlockHeight(2));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Invalid height range from `3` to `2`"
+        );
+        let err = StateDB::calculate_diff_between(rocks_db, BlockHeight(1), BlockHeight(4));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Diff not found for height: `4`"
+        );
+
+        let (stats, size) = StateDB::get_diff_stats(rocks_db, BlockHeight(2)).unwrap();
+        assert_eq!(stats.created, diff::EntryStats { count: 1, bytes: 8 });
+        assert_eq!(stats.updated, diff::EntryStats { count: 1, bytes: 9 });
+        assert_eq!(stats.deleted, diff::EntryStats::default());
+        let key = [consts::Prefix::DIFF, &2u32.to_be_bytes()].concat();
+        assert_eq!(size, conn.get(&key).unwrap().unwrap().len());
+        assert!(StateDB::get_diff_stats(rocks_db, BlockHeight(4)).is_err());
+    }
+
+    #[test]
+    fn test_retention_boundary() {
+        let test_data = vec![
+            (BlockHeight(10), None, None),
+            (BlockHeight(10), Some(20), None),
+            (BlockHeight(10), Some(11), None),
+            (BlockHeight(10), Some(10), Some(BlockHeight(1))),
+            (BlockHeight(10), Some(3), Some(BlockHeight(8))),
+        ];
+        for (version, retention, expected) in test_data {
+            assert_eq!(StateDB::retention_boundary(version, retention), expected);
+        }
+    }
+
+    #[test]
+    fn test_prune_diffs() {
+        let (db, _temp_dir) = temp_state_conn();
+        let conn = db.conn();
+        for height in 1..=5u32 {
+            let key = [consts::Prefix::DIFF, &height.to_be_bytes()].concat();
+            conn.put(key, [1]).unwrap();
+        }
+
+        StateDB::prune_diffs(conn, BlockHeight(3), false, false).unwrap();
+        for height in 1..=5u32 {
+            let key = [consts::Prefix::DIFF, &height.to_be_bytes()].concat();
+            assert_eq!(conn.get(key).unwrap().is_some(), height >= 3);
+        }
+    }
+
+    #[test]
+    fn test_commit_prune_diffs() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38))
+            .with_audit_deletion(true)
+            .with_rollback_protection(true)
+            .with_diff_retention(Some(1));
+        let mut root = EMPTY_HASH.to_vec();
+        for height in 1..=3u32 {
+            let mut writer = state_writer::StateWriter::default();
+            writer.cache_new(&SharedKVPair::new(&[height as u8; 8], &[1]));
+            root = commit_at(&conn, writer, &root, height, options);
+        }
+
+        // the diffs are pruned and the sentinel is advanced to the committed height by the write of the block
+        for height in 1..=3u32 {
+            let key = [consts::Prefix::DIFF, &height.to_be_bytes()].concat();
+            assert_eq!(conn.get(key).unwrap().is_some(), height == 3);
+        }
+        let finalized = sentinel::get(conn.arc_clone().unwrap()).unwrap();
+        assert_eq!(
+            finalized,
+            Some(sentinel::Sentinel::new(BlockHeight(3), root))
+        );
+        // the records of the node removal and the diff pruning written by the same block are chained
+        let records = audit::get_records(conn.arc_clone().unwrap()).unwrap();
+        assert!(records.len() >= 2);
+        assert!(audit::verify_chain(&records));
+    }
+
+    #[test]
+    fn test_verify_state_root() {
+        let (conn, _temp_dir) = temp_state_conn();
+        let options = DbOptions::new(false, KeyLength(38));
+        let key_length = options.key_length();
+
+        let mut writer = state_writer::StateWriter::default();
+        for i in 1..=50u8 {
+            writer.cache_new(&SharedKVPair::new(&[i; 8], &[i]));
+        }
+        let root = commit_at(&conn, writer, &EMPTY_HASH, 1, options);
+
+        let check = root_check::verify(&conn, &root, &root, key_length, false).unwrap();
+        assert!(check.is_valid());
+        assert_eq!(check.computed_root, root);
+        let check = root_check::verify(&conn, &EMPTY_HASH, &root, key_length, false).unwrap();
+        assert!(!check.is_valid());
+
+        // the subtree of the root is lost, while the flat state is intact
+        let rocks_db = conn.arc_clone();
+        let node_key = [consts::Prefix::SMT, &root].concat();
+        rocks_db.unwrap().delete(&node_key).unwrap();
+        let check = root_check::verify(&conn, &root, &root, key_length, false).unwrap();
+        assert!(!check.is_valid());
+        assert_eq!(check.inconsistent_nodes, vec![root.clone()]);
+        assert!(!check.repaired);
+
+        let check = root_check::verify(&conn, &root, &root, key_length, true).unwrap();
+        assert!(check.is_valid());
+        assert!(check.repaired);
+        let check = root_check::verify(&conn, &root, &root, key_length, false).unwrap();
+        assert!(check.is_valid());
+        assert!(check.inconsistent_nodes.is_empty());
+
+        // the flat state diverged from the root, which cannot be repaired
+        let state_key = [consts::Prefix::STATE, &[1; 8]].concat();
+        rocks_db.unwrap().put(&state_key, [2]).unwrap();
+        rocks_db.unwrap().delete(&node_key).unwrap();
+        let check = root_check::verify(&conn, &root, &root, key_length, true).unwrap();
+        assert_ne!(check.computed_root, root);
+        assert!(!check.repaired);
+        assert!(!check.is_valid());
+    }
 }
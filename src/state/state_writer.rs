@@ -1,17 +1,30 @@
 // state_wirter provides batch feature for StateDB. The data written to the writer will not be stored to the physical storage unless "commit" using StateDB.
+use std::cell::RefCell;
 use std::cmp;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
 use neon::prelude::*;
+use neon::types::buffer::TypedArray;
 use thiserror::Error;
 
 use crate::batch;
+use crate::consts::Prefix;
+use crate::database::column_family;
 use crate::database::options::IterationOption;
 use crate::database::traits::{DatabaseKind, JsNewWithArcMutex, NewDBWithKeyLength};
-use crate::database::types::{JsArcMutex, Kind as DBKind};
+use crate::database::types::{ArcOptionDB, JsArcMutex, Kind as DBKind};
+use crate::database::utils::{self, pairs_to_js_array};
 use crate::diff;
-use crate::types::{Cache, HashKind, HashWithKind, KVPair, KeyLength, SharedKVPair, VecOption};
+use crate::error_code::{self, ErrorCode};
+use crate::memory;
+use crate::sparse_merkle_tree::smt;
+use crate::state_db::SharedStateDB;
+use crate::task_pool;
+use crate::types::{
+    Cache, HashKind, HashWithKind, KVPair, KeyLength, NestedVec, RecoverLock, SharedKVPair,
+    VecOption, PREFIX_SIZE,
+};
 use crate::utils;
 
 pub type SendableStateWriter = JsArcMutex<StateWriter>;
@@ -25,6 +38,24 @@ trait Batch {
 pub enum StateWriterError {
     #[error("Invalid usage")]
     InvalidUsage,
+    #[error("StateWriter cap of {cap} bytes is exceeded with {size} bytes cached and {requested} bytes requested")]
+    CapExceeded {
+        cap: usize,
+        size: usize,
+        requested: usize,
+    },
+    #[error("Failed to read the database `{0}`")]
+    ReadFailed(String),
+}
+
+impl ErrorCode for StateWriterError {
+    fn code(&self) -> &'static str {
+        match self {
+            StateWriterError::InvalidUsage => error_code::INVALID_USAGE,
+            StateWriterError::CapExceeded { .. } => error_code::LIMIT_EXCEEDED,
+            StateWriterError::ReadFailed(_) => error_code::DATABASE,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -35,12 +66,72 @@ pub struct StateCache {
     deleted: bool,
 }
 
+/// ChangedKeys are the keys changed by the writer under a store prefix, sorted by the key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangedKeys {
+    pub created: NestedVec,
+    pub updated: NestedVec,
+    pub deleted: NestedVec,
+}
+
+/// JournalEntry is the previous cache of the key before it was changed. None means the key was not cached.
+type JournalEntry = (Vec<u8>, Option<StateCache>);
+
+/// SnapshotPoint is the length of the journal and the number of the deleted ranges when the snapshot is taken.
+#[derive(Clone, Copy, Debug)]
+struct SnapshotPoint {
+    journal: usize,
+    ranges: usize,
+}
+
 /// StateWriter holds batch of operation for state_db.
-#[derive(Default)]
+/// While a snapshot exists, the previous cache of each changed key is appended to the journal,
+/// so taking a snapshot does not copy the cache, and restoring it only undoes the changes made after it.
+/// The deleted ranges are only appended, so a snapshot keeps the number of the ranges to restore them.
+/// The cached bytes including the journal are accounted, and the writes are rejected when they exceed the cap.
+/// When the writer holds the database, the values not cached are read from the state of it on the first access.
 pub struct StateWriter {
     counter: u32,
-    pub backup: HashMap<u32, HashMap<Vec<u8>, StateCache>>,
+    snapshots: HashMap<u32, SnapshotPoint>,
+    journal: Vec<JournalEntry>,
     pub cache: HashMap<Vec<u8>, StateCache>,
+    deleted_ranges: Vec<(Vec<u8>, Vec<u8>)>,
+    usage: memory::Usage,
+    cap: Option<usize>,
+    db: Option<ArcOptionDB>,
+}
+
+/// cache_size returns the accounted bytes of the cache.
+fn cache_size(cache: &HashMap<Vec<u8>, StateCache>) -> usize {
+    cache.iter().map(|(key, val)| key.len() + val.size()).sum()
+}
+
+/// ranges_size returns the accounted bytes of the deleted ranges.
+fn ranges_size(ranges: &[(Vec<u8>, Vec<u8>)]) -> usize {
+    ranges
+        .iter()
+        .map(|(start, end)| start.len() + end.len())
+        .sum()
+}
+
+fn in_range(key: &[u8], start: &[u8], end: &[u8]) -> bool {
+    utils::compare(key, start) != cmp::Ordering::Less
+        && utils::compare(key, end) == cmp::Ordering::Less
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self {
+            counter: 0,
+            snapshots: HashMap::new(),
+            journal: vec![],
+            cache: HashMap::new(),
+            deleted_ranges: vec![],
+            usage: memory::Usage::new(memory::Component::StateWriter),
+            cap: None,
+            db: None,
+        }
+    }
 }
 
 impl DatabaseKind for StateWriter {
@@ -53,6 +144,12 @@ impl Clone for StateWriter {
     fn clone(&self) -> Self {
         let mut cloned = StateWriter::default();
         cloned.cache.clone_from(&self.cache);
+        cloned.deleted_ranges.clone_from(&self.deleted_ranges);
+        cloned.cap = self.cap;
+        cloned.db = self.db.clone();
+        cloned
+            .usage
+            .set(cache_size(&cloned.cache) + ranges_size(&cloned.deleted_ranges));
         cloned
     }
 }
@@ -84,35 +181,121 @@ impl StateCache {
             deleted: false,
         }
     }
+
+    fn size(&self) -> usize {
+        self.value.len() + self.init.as_ref().map_or(0, |val| val.len())
+    }
 }
 
 impl StateWriter {
     /// empty makes StateWriter as an empty HashMap to handle of releasing the memory from JS.
     fn empty(&mut self) {
-        self.backup = HashMap::new();
+        self.snapshots = HashMap::new();
+        self.journal = vec![];
         self.cache = HashMap::new();
+        self.deleted_ranges = vec![];
+        self.usage.set(0);
+        self.db = None;
+    }
+
+    /// record appends the current cache of the key to the journal before it is changed.
+    /// Nothing is recorded when there is no snapshot to restore.
+    fn record(&mut self, key: &[u8]) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        let prev = self.cache.get(key).cloned();
+        self.usage
+            .grow(key.len() + prev.as_ref().map_or(0, |cache| cache.size()));
+        self.journal.push((key.to_vec(), prev));
+    }
+
+    /// new_with_db creates StateWriter which reads the values not cached from the state of the database.
+    pub fn new_with_db(conn: ArcOptionDB) -> Self {
+        Self {
+            db: Some(conn),
+            ..Default::default()
+        }
+    }
+
+    /// load caches the value stored in the database as an existing value if the key is not cached,
+    /// so that the later changes of the key are committed as updates or deletions.
+    /// It does nothing if the writer does not hold the database or the key is in a deleted range.
+    pub fn load(&mut self, key: &[u8]) -> Result<(), StateWriterError> {
+        let conn = match &self.db {
+            Some(conn) if !self.get(key).2 => Arc::clone(conn),
+            _ => return Ok(()),
+        };
+        let conn = conn
+            .as_ref()
+            .as_ref()
+            .ok_or_else(|| StateWriterError::ReadFailed("database is closed".to_string()))?;
+        let value = column_family::get(conn, &[Prefix::STATE, key].concat())
+            .map_err(|err| StateWriterError::ReadFailed(err.to_string()))?;
+        if let Some(value) = value {
+            self.cache_existing(&SharedKVPair::new(key, &value));
+        }
+        Ok(())
+    }
+
+    /// size returns the bytes of the keys and values held by the writer, including the journal of the snapshots.
+    pub fn size(&self) -> usize {
+        self.usage.bytes()
+    }
+
+    /// set_cap limits the bytes held by the writer. None removes the limit.
+    pub fn set_cap(&mut self, cap: Option<usize>) {
+        self.cap = cap;
+    }
+
+    /// reserve checks that the size can be written without exceeding the cap.
+    /// The values read from the database are cached regardless of the cap, so only the writes are checked.
+    pub fn reserve(&self, size: usize) -> Result<(), StateWriterError> {
+        match self.cap {
+            Some(cap) if self.size().saturating_add(size) > cap => {
+                Err(StateWriterError::CapExceeded {
+                    cap,
+                    size: self.size(),
+                    requested: size,
+                })
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_cache(&mut self, key: Vec<u8>, cache: StateCache) {
+        self.record(&key);
+        let key_len = key.len();
+        self.usage.grow(key_len + cache.size());
+        if let Some(prev) = self.cache.insert(key, cache) {
+            self.usage.shrink(key_len + prev.size());
+        }
     }
 
     /// cache_new inserts key-value pair as new value.
     pub fn cache_new(&mut self, pair: &SharedKVPair) {
         let cache = StateCache::new(pair.value());
-        self.cache.insert(pair.key_as_vec(), cache);
+        self.insert_cache(pair.key_as_vec(), cache);
     }
 
     /// cache_existing inserts key-value pair as updated value.
     pub fn cache_existing(&mut self, pair: &SharedKVPair) {
         let cache = StateCache::new_existing(pair.value());
-        self.cache.insert(pair.key_as_vec(), cache);
+        self.insert_cache(pair.key_as_vec(), cache);
     }
 
     /// get returns the value associated with the key.
     /// it returns value, deleted, exists.
     /// - if the value does not exist in the writer it returns ([], false, false).
     /// - if the value exist in the writer but mark as deleted, it returns (val, true, true).
+    /// - if the value is not cached but in a deleted range, it returns ([], true, true).
     /// - if the value exists, it returns (val, false, true).
     pub fn get(&self, key: &[u8]) -> (Vec<u8>, bool, bool) {
         let val = self.cache.get(key);
         if val.is_none() {
+            if self.is_range_deleted(key) {
+                return (vec![], true, true);
+            }
             return (vec![], false, false);
         }
         let val = val.unwrap();
@@ -128,31 +311,41 @@ impl StateWriter {
         self.cache.get(key).is_some()
     }
 
-    /// get_range key-value pairs with option specified.
-    pub fn get_range(&self, options: &IterationOption) -> Cache {
-        let start = options.gte.as_ref().unwrap();
-        let end = options.lte.as_ref().unwrap();
-        self.cache
+    /// get_range returns the key-value pairs in the cache which are within the bounds of the option.
+    /// The pairs are sorted by the key, in descending order if reverse is specified, and at most limit pairs are returned.
+    pub fn get_range(&self, options: &IterationOption) -> Vec<KVPair> {
+        let mut pairs = self
+            .cache
             .iter()
-            .filter_map(|(k, v)| {
-                if utils::compare(k, start) != cmp::Ordering::Less
-                    && utils::compare(k, end) != cmp::Ordering::Greater
-                    && !v.deleted
-                {
-                    Some((k.to_vec(), v.value.to_vec()))
-                } else {
-                    None
-                }
-            })
-            .collect::<Cache>()
+            .filter(|(k, v)| !v.deleted && options.contains(k))
+            .map(|(k, v)| KVPair::new(k, &v.value))
+            .collect::<Vec<KVPair>>();
+        pairs.sort_by(|a, b| {
+            let order = utils::compare(a.key(), b.key());
+            if options.reverse {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        if options.limit != -1 {
+            pairs.truncate(options.limit as usize);
+        }
+        pairs
     }
 
     /// update the key with corresponding value.
     pub fn update(&mut self, pair: &KVPair) -> Result<(), StateWriterError> {
+        if !self.is_cached(pair.key()) {
+            return Err(StateWriterError::InvalidUsage);
+        }
+        self.record(pair.key());
         let cached = self
             .cache
             .get_mut(pair.key())
             .ok_or(StateWriterError::InvalidUsage)?;
+        self.usage.shrink(cached.value.len());
+        self.usage.grow(pair.value().len());
         cached.value = pair.value_as_vec();
         cached.dirty = true;
         cached.deleted = false;
@@ -161,38 +354,155 @@ impl StateWriter {
 
     /// delete the key in the cache.
     pub fn delete(&mut self, key: &[u8]) {
-        let cached = self.cache.get_mut(key);
-        if cached.is_none() {
+        if !self.is_cached(key) {
             return;
         }
-        let cached = cached.unwrap();
+        self.record(key);
+        let cached = self.cache.get_mut(key).unwrap();
         if cached.init.is_none() {
+            let size = key.len() + cached.size();
             self.cache.remove(key);
+            self.usage.shrink(size);
             return;
         }
         cached.dirty = false;
         cached.deleted = true;
     }
 
+    /// delete_range deletes the keys from start to end, excluding end.
+    /// The cached keys in the range are deleted, and the range is recorded so that the keys
+    /// which are not cached are deleted on commit.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) {
+        let keys: Vec<Vec<u8>> = self
+            .cache
+            .keys()
+            .filter(|key| in_range(key, start, end))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.delete(&key);
+        }
+        self.usage.grow(start.len() + end.len());
+        self.deleted_ranges.push((start.to_vec(), end.to_vec()));
+    }
+
+    /// is_range_deleted returns true if the key is in one of the deleted ranges.
+    pub fn is_range_deleted(&self, key: &[u8]) -> bool {
+        self.deleted_ranges
+            .iter()
+            .any(|(start, end)| in_range(key, start, end))
+    }
+
+    /// cache_deleted_ranges caches the keys in the deleted ranges stored in the database as deleted,
+    /// so that all of them are included in the diff and the updates of the tree.
+    pub fn cache_deleted_ranges(&mut self, conn: &rocksdb::DB) -> Result<(), rocksdb::Error> {
+        let ranges = self.deleted_ranges.clone();
+        for (start, end) in ranges {
+            let from = [Prefix::STATE, &start].concat();
+            let to = [Prefix::STATE, &end].concat();
+            let mode = rocksdb::IteratorMode::From(&from, rocksdb::Direction::Forward);
+            let within_prefix = column_family::is_within_store_prefix(
+                Some(start.as_slice()),
+                Some(end.as_slice()),
+            );
+            for key_val in column_family::iterator_opt(conn, Prefix::STATE, mode, within_prefix) {
+                let (key, value) = key_val?;
+                if utils::compare(&key, &to) != cmp::Ordering::Less {
+                    break;
+                }
+                let key = &key[Prefix::STATE.len()..];
+                if !self.is_cached(key) {
+                    self.cache_existing(&SharedKVPair::new(key, &value));
+                    self.delete(key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// snapshot creates snapshot of the current writer and return the snapshot id.
     fn snapshot(&mut self) -> u32 {
-        self.backup.insert(self.counter, self.cache.clone());
+        let point = SnapshotPoint {
+            journal: self.journal.len(),
+            ranges: self.deleted_ranges.len(),
+        };
+        self.snapshots.insert(self.counter, point);
         let index = self.counter;
         self.counter += 1;
         index
     }
 
     /// restore_snapshot reverts the writer to the snapshot id.
+    /// The changes are undone in the reverse order, and all the snapshots are discarded.
     fn restore_snapshot(&mut self, index: u32) -> Result<(), StateWriterError> {
-        let backup = self
-            .backup
+        let point = *self
+            .snapshots
             .get(&index)
             .ok_or(StateWriterError::InvalidUsage)?;
-        self.cache.clone_from(backup);
-        self.backup = HashMap::new();
+        for (key, prev) in self.journal.drain(point.journal..).rev() {
+            match prev {
+                Some(cache) => self.cache.insert(key, cache),
+                None => self.cache.remove(&key),
+            };
+        }
+        self.deleted_ranges.truncate(point.ranges);
+        self.snapshots = HashMap::new();
+        self.journal = vec![];
+        self.usage
+            .set(cache_size(&self.cache) + ranges_size(&self.deleted_ranges));
         Ok(())
     }
 
+    /// set updates the key if it is cached, and caches it as a new value otherwise.
+    /// The keys existing in the database must be cached with cache_existing before, so that they are not committed as created.
+    pub fn set(&mut self, pair: &KVPair) {
+        if self.is_cached(pair.key()) {
+            // update cannot fail for the cached key
+            let _ = self.update(pair);
+            return;
+        }
+        self.cache_new(&SharedKVPair::new(pair.key(), pair.value()));
+    }
+
+    /// get_updated returns the created or updated pairs and the deleted keys, both sorted by the key.
+    pub fn get_updated(&self) -> (Vec<KVPair>, NestedVec) {
+        let mut updated = vec![];
+        let mut deleted = vec![];
+        for (key, value) in self.cache.iter() {
+            if value.init.is_none() || value.dirty {
+                updated.push(KVPair::new(key, &value.value));
+            } else if value.deleted {
+                deleted.push(key.to_vec());
+            }
+        }
+        updated.sort_by(|a, b| utils::compare(a.key(), b.key()));
+        deleted.sort_by(|a, b| utils::compare(a, b));
+        (updated, deleted)
+    }
+
+    /// changed_keys returns the changed keys starting with the prefix, grouped by the store prefix,
+    /// which is the first PREFIX_SIZE bytes of the key.
+    pub fn changed_keys(&self, prefix: &[u8]) -> BTreeMap<Vec<u8>, ChangedKeys> {
+        let mut result: BTreeMap<Vec<u8>, ChangedKeys> = BTreeMap::new();
+        for (key, value) in self.cache.iter().filter(|(key, _)| key.starts_with(prefix)) {
+            let store = key[..key.len().min(PREFIX_SIZE)].to_vec();
+            if value.init.is_none() {
+                result.entry(store).or_default().created.push(key.to_vec());
+            } else if value.deleted {
+                result.entry(store).or_default().deleted.push(key.to_vec());
+            } else if value.dirty {
+                result.entry(store).or_default().updated.push(key.to_vec());
+            }
+        }
+        for changes in result.values_mut() {
+            changes.created.sort();
+            changes.updated.sort();
+            changes.deleted.sort();
+        }
+        result
+    }
+
     /// get_hashed_updated returns all the updated key-value pairs.
     /// if the key is removed, value will be empty slice.
     pub fn get_hashed_updated(&self) -> Cache {
@@ -212,25 +522,74 @@ impl StateWriter {
         result
     }
 
+    /// commit writes the changes of the writer to the batch and returns the diff of them.
+    /// The deleted ranges are written first, and the deleted keys covered by them are only recorded in the diff.
+    /// The keys in the ranges which are not cached must be cached by cache_deleted_ranges before commit.
     pub fn commit(&self, batch: &mut impl batch::BatchWriter) -> diff::Diff {
+        self.write_changes(batch, None)
+    }
+
+    /// commit_with_update_data commits like commit, and also returns the update data of the tree
+    /// with the hashed keys and values of the committed changes. The deleted keys have empty values.
+    pub fn commit_with_update_data(
+        &self,
+        batch: &mut impl batch::BatchWriter,
+    ) -> (diff::Diff, smt::UpdateData) {
+        let _span = tracing::debug_span!("state_writer_commit").entered();
+        let mut data = smt::UpdateData::new_from(Cache::new());
+        let diff = self.write_changes(batch, Some(&mut data));
+        let stats = diff.stats();
+        tracing::debug!(
+            created = stats.created.count,
+            updated = stats.updated.count,
+            deleted = stats.deleted.count,
+            ranges = self.deleted_ranges.len(),
+            "changes written"
+        );
+        (diff, data)
+    }
+
+    fn write_changes(
+        &self,
+        batch: &mut impl batch::BatchWriter,
+        mut data: Option<&mut smt::UpdateData>,
+    ) -> diff::Diff {
         let mut created = vec![];
         let mut updated = vec![];
         let mut deleted = vec![];
+        // the deleted key is recorded with None, and it is inserted to the update data with an empty value
+        let mut record = |key: &Vec<u8>, value: Option<&Vec<u8>>| {
+            if let Some(data) = data.as_mut() {
+                let hashed_value = value.map_or(vec![], |val| val.hash_with_kind(HashKind::Value));
+                data.insert(SharedKVPair::new(
+                    &key.hash_with_kind(HashKind::Key),
+                    &hashed_value,
+                ));
+            }
+        };
+        for (start, end) in self.deleted_ranges.iter() {
+            batch.delete_range(start, end);
+        }
         for (key, value) in self.cache.iter() {
             let kv = KVPair::new(key, &value.value);
             if value.init.is_none() {
                 created.push(key.to_vec());
                 batch.put(&kv);
+                record(key, Some(&value.value));
                 continue;
             }
             if value.deleted {
                 deleted.push(KVPair::new(key, &value.value));
-                batch.delete(key);
+                if !self.is_range_deleted(key) {
+                    batch.delete(key);
+                }
+                record(key, None);
                 continue;
             }
             if value.dirty {
                 updated.push(KVPair::new(key, value.init.as_ref().unwrap()));
                 batch.put(&kv);
+                record(key, Some(&value.value));
                 continue;
             }
         }
@@ -247,7 +606,7 @@ impl StateWriter {
             .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
 
         let batch = Arc::clone(&writer.borrow());
-        let mut inner_writer = batch.lock().unwrap();
+        let mut inner_writer = batch.lock_or_recover();
         inner_writer.empty();
 
         Ok(ctx.undefined())
@@ -262,13 +621,301 @@ impl StateWriter {
             .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
 
         let batch = Arc::clone(&writer.borrow());
-        let mut inner_writer = batch.lock().unwrap();
+        let mut inner_writer = batch.lock_or_recover();
 
         let index = inner_writer.snapshot();
 
         Ok(ctx.number(index))
     }
 
+    /// js_delete_range is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @params(0) - start key of the range.
+    /// - @params(1) - end key of the range, which is not deleted.
+    pub fn js_delete_range(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let start = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let end = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        memory::reserve(start.len() + end.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer
+            .reserve(start.len() + end.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        inner_writer.delete_range(&start, &end);
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_new_with_db is handler for JS ffi.
+    /// - @params(0) - StateDB to read the values not cached from.
+    /// - @returns - StateWriter which reads through to the state of the database.
+    pub fn js_new_with_db(mut ctx: FunctionContext) -> JsResult<SendableStateWriter> {
+        let db = ctx
+            .argument::<SharedStateDB>(0)?
+            .downcast_or_throw::<SharedStateDB, _>(&mut ctx)?;
+        let conn = db.borrow().arc_clone();
+        let writer = StateWriter::new_with_db(conn);
+
+        Ok(ctx.boxed(RefCell::new(Arc::new(Mutex::new(writer)))))
+    }
+
+    /// js_get is handler for JS ffi.
+    /// The value is read from the database and cached if the writer holds the database and the key is not cached.
+    /// js "this" - StateWriter.
+    /// - @params(0) - key to get from the writer.
+    /// - @returns - value of the key, or undefined if the key is not cached or deleted.
+    pub fn js_get(mut ctx: FunctionContext) -> JsResult<JsValue> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer
+            .load(&key)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let (value, deleted, exists) = inner_writer.get(&key);
+        if !exists || deleted {
+            return Ok(ctx.undefined().upcast());
+        }
+
+        Ok(JsBuffer::external(&mut ctx, value).upcast())
+    }
+
+    /// js_set is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @params(0) - key to set.
+    /// - @params(1) - value to set.
+    pub fn js_set(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        smt::check_state_key(&key).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer
+            .reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        inner_writer
+            .load(&key)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        inner_writer.set(&KVPair::new(&key, &value));
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_cache_existing is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @params(0) - key existing in the database.
+    /// - @params(1) - value stored in the database.
+    pub fn js_cache_existing(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let value = ctx.argument::<JsTypedArray<u8>>(1)?.as_slice(&ctx).to_vec();
+        smt::check_state_key(&key).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        memory::reserve(key.len() + value.len())
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer.cache_existing(&SharedKVPair::new(&key, &value));
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_del is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @params(0) - key to delete.
+    pub fn js_del(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let key = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        smt::check_state_key(&key).or_else(|err| ctx.throw_error(err.to_string()))?;
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer
+            .load(&key)
+            .or_else(|err| ctx.throw_error(err.to_string()))?;
+        inner_writer.delete(&key);
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_range is handler for JS ffi.
+    /// The range is read on the shared worker pool, so the cache is not scanned on the JS thread.
+    /// js "this" - StateWriter.
+    /// - @params(0) - iteration options.
+    /// - @params(1) - callback to return the pairs.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { key, value }[] sorted by the key.
+    pub fn js_range(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let option_inputs = ctx.argument::<JsObject>(0)?;
+        let options = IterationOption::new(&mut ctx, option_inputs);
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+        let batch = Arc::clone(&writer.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let pairs = batch.lock_or_recover().get_range(&options);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let result = pairs_to_js_array(&mut ctx, &pairs)?;
+                let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), result.upcast()];
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_get_updated is handler for JS ffi.
+    /// The changes are collected on the shared worker pool, so the cache is not scanned on the JS thread.
+    /// js "this" - StateWriter.
+    /// - @params(0) - callback to return the changes.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { updated: { key, value }[], deleted: Buffer[] } sorted by the key.
+    pub fn js_get_updated(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let callback = ctx.argument::<JsFunction>(0)?.root(&mut ctx);
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+        let batch = Arc::clone(&writer.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let (updated, deleted) = batch.lock_or_recover().get_updated();
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let result = ctx.empty_object();
+                let updated = pairs_to_js_array(&mut ctx, &updated)?;
+                result.set(&mut ctx, "updated", updated)?;
+                let deleted_keys = ctx.empty_array();
+                for (i, key) in deleted.into_iter().enumerate() {
+                    let key = JsBuffer::external(&mut ctx, key);
+                    deleted_keys.set(&mut ctx, i as u32, key)?;
+                }
+                result.set(&mut ctx, "deleted", deleted_keys)?;
+                let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), result.upcast()];
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_changed_keys is handler for JS ffi.
+    /// The keys are collected on the shared worker pool, so the cache is not scanned on the JS thread.
+    /// js "this" - StateWriter.
+    /// - @params(0) - prefix of the keys.
+    /// - @params(1) - callback to return the changed keys.
+    /// - @callback(0) - Error.
+    /// - @callback(1) - { storePrefix: Buffer, created: Buffer[], updated: Buffer[], deleted: Buffer[] }[] sorted by the store prefix.
+    pub fn js_changed_keys(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let prefix = ctx.argument::<JsTypedArray<u8>>(0)?.as_slice(&ctx).to_vec();
+        let callback = ctx.argument::<JsFunction>(1)?.root(&mut ctx);
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+        let batch = Arc::clone(&writer.borrow());
+        let channel = ctx.channel();
+
+        let pool = task_pool::shared();
+        pool.execute(move || {
+            let changed = batch.lock_or_recover().changed_keys(&prefix);
+            channel.send(move |mut ctx| {
+                let callback = callback.into_inner(&mut ctx);
+                let this = ctx.undefined();
+                let result = ctx.empty_array();
+                for (i, (store, changes)) in changed.into_iter().enumerate() {
+                    let obj = ctx.empty_object();
+                    let store = JsBuffer::external(&mut ctx, store);
+                    obj.set(&mut ctx, "storePrefix", store)?;
+                    for (name, keys) in [
+                        ("created", changes.created),
+                        ("updated", changes.updated),
+                        ("deleted", changes.deleted),
+                    ] {
+                        let arr = ctx.empty_array();
+                        for (j, key) in keys.into_iter().enumerate() {
+                            let key = JsBuffer::external(&mut ctx, key);
+                            arr.set(&mut ctx, j as u32, key)?;
+                        }
+                        obj.set(&mut ctx, name, arr)?;
+                    }
+                    result.set(&mut ctx, i as u32, obj)?;
+                }
+                let args: Vec<Handle<JsValue>> = vec![ctx.null().upcast(), result.upcast()];
+                callback.call(&mut ctx, this, args)?;
+
+                Ok(())
+            });
+        })
+        .or_else(|err| utils::throw_with_code(&mut ctx, err))?;
+
+        Ok(ctx.undefined())
+    }
+
+    /// js_size is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @returns - bytes of the keys and values held by the writer.
+    pub fn js_size(mut ctx: FunctionContext) -> JsResult<JsNumber> {
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        let batch = Arc::clone(&writer.borrow());
+        let inner_writer = batch.lock_or_recover();
+
+        Ok(ctx.number(inner_writer.size() as f64))
+    }
+
+    /// js_set_cap is handler for JS ffi.
+    /// js "this" - StateWriter.
+    /// - @params(0) - maximum bytes held by the writer. null or undefined removes the limit.
+    pub fn js_set_cap(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+        let cap = ctx
+            .argument_opt(0)
+            .and_then(|val| val.downcast::<JsNumber, _>(&mut ctx).ok())
+            .map(|val| val.value(&mut ctx) as usize);
+        let writer = ctx
+            .this()
+            .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
+
+        let batch = Arc::clone(&writer.borrow());
+        let mut inner_writer = batch.lock_or_recover();
+        inner_writer.set_cap(cap);
+
+        Ok(ctx.undefined())
+    }
+
     /// js_restore_snapshot is handler for JS ffi.
     /// js "this" - StateWriter.
     /// - @params(0) - snapshot id
@@ -278,7 +925,7 @@ impl StateWriter {
             .downcast_or_throw::<SendableStateWriter, _>(&mut ctx)?;
 
         let batch = Arc::clone(&writer.borrow());
-        let mut inner_writer = batch.lock().unwrap();
+        let mut inner_writer = batch.lock_or_recover();
         let index = ctx.argument::<JsNumber>(0)?.value(&mut ctx) as u32;
 
         match inner_writer.restore_snapshot(index) {
@@ -291,14 +938,12 @@ impl StateWriter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::consts::Prefix;
 
-    use std::cell::RefCell;
     use std::convert::TryInto;
-    use std::sync::Mutex;
     use std::thread;
 
     use rand::RngCore;
+    use tempdir::TempDir;
 
     #[test]
     fn test_multi_thread() {
@@ -447,6 +1092,146 @@ mod tests {
         assert!(result.2);
     }
 
+    #[test]
+    fn test_state_writer_get_range() {
+        let mut writer = StateWriter::default();
+        for i in [3u8, 1, 4, 0, 2, 5] {
+            writer.cache_new(&SharedKVPair::new(&[i], &[i + 10]));
+        }
+        writer.delete(&[2]);
+        let option = |gte, gt, lte, lt, reverse, limit| IterationOption {
+            limit,
+            reverse,
+            gte,
+            lte,
+            gt,
+            lt,
+        };
+        let keys = |option: IterationOption| -> Vec<u8> {
+            writer
+                .get_range(&option)
+                .iter()
+                .map(|pair| pair.key()[0])
+                .collect()
+        };
+
+        assert_eq!(
+            keys(option(None, None, None, None, false, -1)),
+            vec![0, 1, 3, 4, 5]
+        );
+        let inclusive = option(Some(vec![1]), None, Some(vec![4]), None, false, -1);
+        assert_eq!(keys(inclusive), vec![1, 3, 4]);
+        let exclusive = option(None, Some(vec![1]), None, Some(vec![4]), false, -1);
+        assert_eq!(keys(exclusive), vec![3]);
+        assert_eq!(
+            keys(option(None, Some(vec![0]), None, None, true, 3)),
+            vec![5, 4, 3]
+        );
+        let limited = writer.get_range(&option(Some(vec![1]), None, None, None, false, 2));
+        assert_eq!(
+            limited,
+            vec![KVPair::new(&[1], &[11]), KVPair::new(&[3], &[13])]
+        );
+    }
+
+    #[test]
+    fn test_state_writer_set_and_get_updated() {
+        let mut writer = StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[3], &[30]));
+        writer.cache_existing(&SharedKVPair::new(&[2], &[20]));
+        writer.cache_existing(&SharedKVPair::new(&[4], &[40]));
+        writer.set(&KVPair::new(&[3], &[31]));
+        writer.set(&KVPair::new(&[1], &[10]));
+        writer.delete(&[2]);
+
+        assert_eq!(writer.get(&[1]), (vec![10], false, true));
+        assert_eq!(writer.get(&[3]), (vec![31], false, true));
+        let (updated, deleted) = writer.get_updated();
+        assert_eq!(
+            updated,
+            vec![KVPair::new(&[1], &[10]), KVPair::new(&[3], &[31])]
+        );
+        assert_eq!(deleted, vec![vec![2]]);
+
+        let mut write_batch = batch::PrefixWriteBatch::new();
+        write_batch.set_prefix(&Prefix::STATE);
+        let diff = writer.commit(&mut write_batch);
+        let expected = diff::Diff::new(
+            vec![vec![1]],
+            vec![KVPair::new(&[3], &[30])],
+            vec![KVPair::new(&[2], &[20])],
+        );
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn test_state_writer_changed_keys() {
+        let mut writer = StateWriter::default();
+        let key = |store: u8, suffix: u8| vec![0, 0, 0, 1, 0, store, suffix];
+        writer.cache_existing(&SharedKVPair::new(&key(1, 2), &[1]));
+        writer.cache_existing(&SharedKVPair::new(&key(1, 3), &[1]));
+        writer.cache_existing(&SharedKVPair::new(&key(2, 1), &[1]));
+        writer.cache_existing(&SharedKVPair::new(&key(3, 1), &[1]));
+        writer.cache_new(&SharedKVPair::new(&key(1, 1), &[1]));
+        writer.set(&KVPair::new(&key(1, 3), &[2]));
+        writer.delete(&key(1, 2));
+        writer.delete(&key(2, 1));
+        writer.cache_new(&SharedKVPair::new(&[0, 0, 0, 2, 0, 1, 1], &[1]));
+
+        let changed = writer.changed_keys(&[0, 0, 0, 1]);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(
+            changed.get(&key(1, 0)[..6]).unwrap(),
+            &ChangedKeys {
+                created: vec![key(1, 1)],
+                updated: vec![key(1, 3)],
+                deleted: vec![key(1, 2)],
+            }
+        );
+        assert_eq!(
+            changed.get(&key(2, 0)[..6]).unwrap().deleted,
+            vec![key(2, 1)]
+        );
+        assert_eq!(writer.changed_keys(&[]).len(), 3);
+    }
+
+    #[test]
+    fn test_state_writer_load() {
+        let temp_dir = TempDir::new("test_state_writer_load").unwrap();
+        let conn = rocksdb::DB::open_default(&temp_dir).unwrap();
+        conn.put([Prefix::STATE, &[1]].concat(), [10]).unwrap();
+        conn.put([Prefix::STATE, &[2]].concat(), [20]).unwrap();
+        conn.put([Prefix::STATE, &[3]].concat(), [30]).unwrap();
+        let mut writer = StateWriter::new_with_db(Arc::new(Some(conn)));
+
+        writer.load(&[1]).unwrap();
+        assert_eq!(writer.get(&[1]), (vec![10], false, true));
+        writer.load(&[2]).unwrap();
+        writer.set(&KVPair::new(&[2], &[21]));
+        writer.load(&[3]).unwrap();
+        writer.delete(&[3]);
+        writer.load(&[4]).unwrap();
+        assert!(!writer.is_cached(&[4]));
+        writer.set(&KVPair::new(&[4], &[40]));
+        // the changed value is not overwritten by the value in the database
+        writer.load(&[2]).unwrap();
+        assert_eq!(writer.get(&[2]), (vec![21], false, true));
+
+        let mut write_batch = batch::PrefixWriteBatch::new();
+        write_batch.set_prefix(&Prefix::STATE);
+        let diff = writer.commit(&mut write_batch);
+        let expected = diff::Diff::new(
+            vec![vec![4]],
+            vec![KVPair::new(&[2], &[20])],
+            vec![KVPair::new(&[3], &[30])],
+        );
+        assert_eq!(diff, expected);
+
+        let mut writer = StateWriter::default();
+        writer.load(&[1]).unwrap();
+        assert!(!writer.is_cached(&[1]));
+    }
+
     #[test]
     fn test_state_writer_update() {
         let mut writer = StateWriter::default();
@@ -537,6 +1322,86 @@ mod tests {
 
         writer.restore_snapshot(1).unwrap();
         assert_eq!(writer.cache.len(), 3);
+        assert!(writer.restore_snapshot(0).is_err());
+    }
+
+    #[test]
+    fn test_state_writer_snapshot_journal() {
+        let mut writer = StateWriter::default();
+        writer.cache_existing(&SharedKVPair::new(&[1], &[1]));
+        writer.cache_existing(&SharedKVPair::new(&[2], &[2]));
+
+        let first = writer.snapshot();
+        writer.update(&KVPair::new(&[1], &[10])).unwrap();
+        writer.delete(&[2]);
+        writer.cache_new(&SharedKVPair::new(&[3], &[3]));
+        let second = writer.snapshot();
+        writer.update(&KVPair::new(&[1], &[11])).unwrap();
+        writer.delete(&[3]);
+        writer.delete_range(&[0], &[9]);
+        assert_eq!(writer.journal.len(), 7);
+
+        writer.restore_snapshot(second).unwrap();
+        assert_eq!(writer.get(&[1]), (vec![10], false, true));
+        assert_eq!(writer.get(&[2]), (vec![], true, true));
+        assert_eq!(writer.get(&[3]), (vec![3], false, true));
+        assert!(!writer.is_range_deleted(&[4]));
+        assert!(writer.journal.is_empty());
+        assert!(writer.restore_snapshot(first).is_err());
+
+        let third = writer.snapshot();
+        writer.set(&KVPair::new(&[2], &[20]));
+        writer.restore_snapshot(third).unwrap();
+        assert_eq!(writer.get(&[2]), (vec![], true, true));
+        assert_eq!(writer.size(), cache_size(&writer.cache));
+    }
+
+    #[test]
+    fn test_state_writer_usage() {
+        let mut writer = StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1, 2, 3, 4], &[10, 20, 30, 50]));
+        writer.cache_existing(&SharedKVPair::new(&[5, 6, 7, 8], &[50, 60, 70, 80]));
+        assert_eq!(writer.usage.bytes(), 20);
+
+        writer.update(&KVPair::new(&[5, 6, 7, 8], &[1, 2])).unwrap();
+        assert_eq!(writer.usage.bytes(), 18);
+
+        // the snapshot does not copy the cache, and the removed key is kept in the journal
+        writer.snapshot();
+        assert_eq!(writer.usage.bytes(), 18);
+        writer.delete(&[1, 2, 3, 4]);
+        assert_eq!(writer.usage.bytes(), 18);
+        writer.update(&KVPair::new(&[5, 6, 7, 8], &[3])).unwrap();
+        assert_eq!(writer.usage.bytes(), 27);
+
+        writer.restore_snapshot(0).unwrap();
+        assert_eq!(writer.usage.bytes(), 18);
+
+        writer.empty();
+        assert_eq!(writer.usage.bytes(), 0);
+    }
+
+    #[test]
+    fn test_state_writer_cap() {
+        let mut writer = StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1, 2, 3, 4], &[10, 20, 30, 50]));
+        assert_eq!(writer.size(), 8);
+        assert!(writer.reserve(100).is_ok());
+
+        writer.set_cap(Some(10));
+        assert!(writer.reserve(2).is_ok());
+        assert!(matches!(
+            writer.reserve(3),
+            Err(StateWriterError::CapExceeded {
+                cap: 10,
+                size: 8,
+                requested: 3,
+            })
+        ));
+        assert!(writer.clone().reserve(3).is_err());
+
+        writer.set_cap(None);
+        assert!(writer.reserve(3).is_ok());
     }
 
     #[test]
@@ -560,4 +1425,62 @@ mod tests {
         diff.revert_commit(&mut batch);
         assert_eq!(batch.batch.len(), 3);
     }
+
+    #[test]
+    fn test_state_writer_commit_with_update_data() {
+        let mut writer = StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1, 2, 3, 4], &[]));
+        writer.cache_existing(&SharedKVPair::new(&[5, 6, 7, 8], &[50, 60, 70, 80]));
+        writer.cache_existing(&SharedKVPair::new(&[9, 10, 11, 12], &[90, 100, 110, 120]));
+        writer.cache_existing(&SharedKVPair::new(&[13, 14, 15, 16], &[130]));
+        writer.delete(&[5, 6, 7, 8]);
+        writer
+            .update(&KVPair::new(&[9, 10, 11, 12], &[130, 140, 150, 160]))
+            .unwrap();
+
+        let mut write_batch = batch::PrefixWriteBatch::new();
+        write_batch.set_prefix(&Prefix::STATE);
+        let (diff, data) = writer.commit_with_update_data(&mut write_batch);
+        let mut expected_batch = batch::PrefixWriteBatch::new();
+        expected_batch.set_prefix(&Prefix::STATE);
+        assert_eq!(diff, writer.commit(&mut expected_batch));
+        assert_eq!(write_batch.batch.len(), 3);
+        assert_eq!(data, smt::UpdateData::new_from(writer.get_hashed_updated()));
+        assert_eq!(data.entries().0.len(), 3);
+    }
+
+    #[test]
+    fn test_state_writer_delete_range() {
+        let mut writer = StateWriter::default();
+        writer.cache_new(&SharedKVPair::new(&[1, 1], &[1]));
+        writer.cache_existing(&SharedKVPair::new(&[1, 2], &[2]));
+        writer.cache_existing(&SharedKVPair::new(&[2, 0], &[3]));
+
+        writer.snapshot();
+        writer.delete_range(&[1], &[2]);
+        assert!(!writer.is_cached(&[1, 1]));
+        assert_eq!(writer.get(&[1, 2]), (vec![], true, true));
+        assert_eq!(writer.get(&[1, 3]), (vec![], true, true));
+        assert_eq!(writer.get(&[2, 0]), (vec![3], false, true));
+        assert_eq!(writer.get(&[2, 1]), (vec![], false, false));
+
+        writer.cache_new(&SharedKVPair::new(&[1, 4], &[4]));
+        let mut write_batch = batch::PrefixWriteBatch::new();
+        write_batch.set_prefix(&Prefix::STATE);
+        let diff = writer.commit(&mut write_batch);
+        assert_eq!(
+            write_batch.ranges,
+            vec![(
+                [Prefix::STATE, &[1]].concat(),
+                [Prefix::STATE, &[2]].concat()
+            )]
+        );
+        // [1, 2] is deleted by the range, and only [1, 4] is written
+        assert_eq!(write_batch.batch.len(), 1);
+        assert_eq!(diff.revert_hashed_update().len(), 2);
+
+        writer.restore_snapshot(0).unwrap();
+        assert!(!writer.is_range_deleted(&[1, 3]));
+        assert_eq!(writer.get(&[1, 2]), (vec![2], false, true));
+    }
 }
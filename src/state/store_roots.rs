@@ -0,0 +1,84 @@
+/// store_roots keeps the sub-root of each store prefix, which is the root of the subtree with all the keys of the store.
+/// The sub-roots are written with the SMT nodes only for the stores updated at the height,
+/// so the sub-root of a store at a height is the one written by the latest height updating it.
+/// The sub-root is linked to the state root by the prefix proof of the store prefix.
+use std::collections::BTreeSet;
+
+use crate::consts;
+use crate::database::traits::Actions;
+use crate::sparse_merkle_tree::smt::{SMTError, SparseMerkleTree, UpdateData};
+use crate::sparse_merkle_tree::smt_db::StagedSmtDB;
+use crate::types::{BlockHeight, SharedVec, PREFIX_SIZE};
+
+/// StoreRoot is the store prefix with its sub-root.
+pub type StoreRoot = (Vec<u8>, Vec<u8>);
+
+fn key(store: &[u8], version: BlockHeight) -> Vec<u8> {
+    [consts::Prefix::STORE_ROOT, store, &version.to_be_bytes()].concat()
+}
+
+/// stores returns the store prefixes of the keys updated by the data in the order of the prefixes.
+pub fn stores(data: &UpdateData) -> Vec<Vec<u8>> {
+    let (keys, _) = data.entries();
+    let stores: BTreeSet<&[u8]> = keys
+        .iter()
+        .filter(|key| key.len() >= PREFIX_SIZE)
+        .map(|key| &key[..PREFIX_SIZE])
+        .collect();
+    stores.into_iter().map(|store| store.to_vec()).collect()
+}
+
+/// commit updates the tree with the data, and returns the next root with the sub-roots of the updated stores.
+/// The nodes are staged until the sub-roots are computed, since the nodes written to `db` are not readable until its batch is written.
+pub fn commit<T: Actions>(
+    tree: &mut SparseMerkleTree,
+    db: &mut T,
+    data: &UpdateData,
+) -> Result<(SharedVec, Vec<StoreRoot>), SMTError> {
+    let mut staged = StagedSmtDB::new(db);
+    let root = tree.commit(&mut staged, data)?;
+    let mut roots = vec![];
+    for store in stores(data) {
+        let proof = tree.prove_prefix(&staged, &store)?;
+        roots.push((store, proof.subtree_root()));
+    }
+    staged
+        .apply()
+        .map_err(|err| SMTError::Unknown(err.to_string()))?;
+
+    Ok((root, roots))
+}
+
+/// get returns the sub-root of the store at the version, which is written by the latest height updating the store up to the version.
+/// It returns None if the store has not been updated since the store roots are enabled.
+pub fn get(
+    conn: &rocksdb::DB,
+    store: &[u8],
+    version: BlockHeight,
+) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+    let start = key(store, version);
+    let prefix = [consts::Prefix::STORE_ROOT, store].concat();
+    let mut iter = conn.iterator(rocksdb::IteratorMode::From(
+        &start,
+        rocksdb::Direction::Reverse,
+    ));
+    match iter.next() {
+        Some(Ok((key, value))) if key.starts_with(&prefix) => Ok(Some(value.to_vec())),
+        Some(Err(err)) => Err(err),
+        _ => Ok(None),
+    }
+}
+
+/// write puts the sub-roots of the stores updated at the version to the batch.
+pub fn write(batch: &mut rocksdb::WriteBatch, version: BlockHeight, roots: &[StoreRoot]) {
+    for (store, root) in roots.iter() {
+        batch.put(key(store, version), root);
+    }
+}
+
+/// remove deletes the sub-roots written at the version, so that the stores have the sub-roots before it once it is reverted.
+pub fn remove(batch: &mut rocksdb::WriteBatch, version: BlockHeight, stores: &[Vec<u8>]) {
+    for store in stores.iter() {
+        batch.delete(key(store, version));
+    }
+}
@@ -0,0 +1,186 @@
+/// structure_log records the SMT nodes created and deleted by the commit of each height, with the height of each subtree.
+/// The record is written with the block, so the growth of the tree can be analysed and the garbage collection of the nodes
+/// can be verified later. The nodes retained by keepRootHistory are deleted when they leave the root history instead of on commit,
+/// therefore they are not recorded as deleted.
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+use crate::codec;
+use crate::consts;
+use crate::database::DB;
+use crate::error_code::{self, ErrorCode};
+use crate::sparse_merkle_tree::smt::subtree_height;
+use crate::types::{BlockHeight, KeyLength};
+
+#[derive(Error, Debug)]
+pub enum StructureLogError {
+    #[error("Invalid structure log `{0}`")]
+    InvalidRecord(#[from] codec::CodecError),
+    #[error(transparent)]
+    Database(#[from] rocksdb::Error),
+}
+
+impl ErrorCode for StructureLogError {
+    fn code(&self) -> &'static str {
+        match self {
+            StructureLogError::InvalidRecord(_) => error_code::CORRUPTION,
+            StructureLogError::Database(err) => err.code(),
+        }
+    }
+}
+
+/// SubtreeChange is the subtree created or deleted by the commit.
+/// The height is the height of the deepest node in the subtree, which is 0 if the node can not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubtreeChange {
+    pub hash: Vec<u8>,
+    pub height: u8,
+}
+
+/// StructureLog is the subtrees created and deleted by the commit in the order of the batch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StructureLog {
+    pub created: Vec<SubtreeChange>,
+    pub deleted: Vec<SubtreeChange>,
+}
+
+/// Collector collects the subtrees put and deleted by the batch created by SmtDB.
+struct Collector<'a> {
+    conn: &'a DB,
+    key_length: KeyLength,
+    log: StructureLog,
+}
+
+impl rocksdb::WriteBatchIterator for Collector<'_> {
+    fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
+        let height = subtree_height(&value, self.key_length).unwrap_or_default();
+        self.log.created.push(SubtreeChange {
+            hash: key.into_vec(),
+            height,
+        });
+    }
+
+    fn delete(&mut self, key: Box<[u8]>) {
+        // the deleted node is still readable, since the batch is not written yet
+        let height = self
+            .conn
+            .get(&[consts::Prefix::SMT, &key].concat())
+            .ok()
+            .flatten()
+            .and_then(|val| subtree_height(&val, self.key_length).ok())
+            .unwrap_or_default();
+        self.log.deleted.push(SubtreeChange {
+            hash: key.into_vec(),
+            height,
+        });
+    }
+}
+
+fn key(version: BlockHeight) -> Vec<u8> {
+    [consts::Prefix::STRUCTURE_LOG, &version.to_be_bytes()].concat()
+}
+
+fn write_changes(writer: &mut codec::Writer, field_number: u32, changes: &[SubtreeChange]) {
+    for change in changes.iter() {
+        writer.write_message(field_number, |writer| {
+            writer.write_bytes(1, &change.hash);
+            writer.write_u32(2, change.height.into());
+        });
+    }
+}
+
+fn read_changes(
+    reader: &mut codec::Reader,
+    field_number: u32,
+) -> Result<Vec<SubtreeChange>, codec::CodecError> {
+    let mut changes = vec![];
+    for mut reader in reader.read_messages(field_number)? {
+        let hash = reader.read_bytes(1)?;
+        let height =
+            u8::try_from(reader.read_u32(2)?).map_err(|_| codec::CodecError::OutOfRange)?;
+        changes.push(SubtreeChange { hash, height });
+    }
+    Ok(changes)
+}
+
+impl StructureLog {
+    /// collect returns the log of the batch created by SmtDB before it is written.
+    pub fn collect(conn: &DB, batch: &rocksdb::WriteBatch, key_length: KeyLength) -> Self {
+        let mut collector = Collector {
+            conn,
+            key_length,
+            log: StructureLog::default(),
+        };
+        batch.iterate(&mut collector);
+        collector.log
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = codec::Writer::new();
+        write_changes(&mut writer, 1, &self.created);
+        write_changes(&mut writer, 2, &self.deleted);
+        writer.result().to_vec()
+    }
+
+    pub fn decode(val: &[u8]) -> Result<Self, StructureLogError> {
+        let mut reader = codec::Reader::new(val);
+        Ok(Self {
+            created: read_changes(&mut reader, 1)?,
+            deleted: read_changes(&mut reader, 2)?,
+        })
+    }
+}
+
+/// write puts the log of the commit at the version to the batch.
+pub fn write(batch: &mut rocksdb::WriteBatch, version: BlockHeight, log: &StructureLog) {
+    batch.put(key(version), log.encode());
+}
+
+/// remove deletes the log of the version, so that a reverted commit is not left in the log.
+pub fn remove(batch: &mut rocksdb::WriteBatch, version: BlockHeight) {
+    batch.delete(key(version));
+}
+
+/// get returns the log of the commit at the version, or None if it was not recorded.
+pub fn get(
+    conn: &rocksdb::DB,
+    version: BlockHeight,
+) -> Result<Option<StructureLog>, StructureLogError> {
+    let val = conn.get(key(version))?;
+    val.map(|val| StructureLog::decode(&val)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_structure_log_encoding() {
+        let log = StructureLog {
+            created: vec![
+                SubtreeChange {
+                    hash: vec![1; 32],
+                    height: 4,
+                },
+                SubtreeChange {
+                    hash: vec![2; 32],
+                    height: 0,
+                },
+            ],
+            deleted: vec![SubtreeChange {
+                hash: vec![3; 32],
+                height: 2,
+            }],
+        };
+        assert_eq!(StructureLog::decode(&log.encode()).unwrap(), log);
+        assert_eq!(
+            StructureLog::decode(&StructureLog::default().encode()).unwrap(),
+            StructureLog::default()
+        );
+
+        let mut writer = codec::Writer::new();
+        writer.write_message(1, |writer| writer.write_u32(2, 256));
+        assert!(StructureLog::decode(writer.result()).is_err());
+    }
+}
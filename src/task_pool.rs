@@ -0,0 +1,560 @@
+/// task_pool runs blocking jobs on dedicated threads and returns the results as futures.
+/// The futures only rely on the std Waker, so they can be awaited on any async runtime such as tokio.
+/// The JS bindings run their jobs on the shared pool instead of spawning a thread per call.
+use std::collections::VecDeque;
+use std::future::Future;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+use thiserror::Error;
+
+type Job = Box<dyn FnOnce() + Send>;
+type Handler<M> = Box<dyn FnMut(M) -> bool + Send>;
+
+/// QUEUE_CAPACITY_PER_WORKER is the number of the jobs waiting for each worker when the capacity is not given.
+const QUEUE_CAPACITY_PER_WORKER: usize = 256;
+
+/// SHARED is the pool used by the JS bindings. It is created on first use.
+static SHARED: Mutex<Option<Arc<TaskPool>>> = Mutex::new(None);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TaskError {
+    #[error("Task was cancelled before completion")]
+    Cancelled,
+    #[error("Task queue is full with `{0}` jobs waiting")]
+    QueueFull(usize),
+    #[error("Task pool is shut down")]
+    ShutDown,
+}
+
+struct Slot<T> {
+    result: Option<Result<T, TaskError>>,
+    waker: Option<Waker>,
+}
+
+/// Task is the future of the result of the job spawned on the pool.
+pub struct Task<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+/// Completer sets the result of the task. The task is cancelled if it is dropped without the result.
+struct Completer<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+/// TaskPool holds the worker threads which run the jobs in the order of spawning.
+/// The jobs waiting for a worker are bounded by the capacity of the queue, and the jobs exceeding it are rejected.
+pub struct TaskPool {
+    tx: Option<mpsc::SyncSender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    capacity: usize,
+}
+
+/// SerialQueue runs the messages sent to it one at a time in the order of sending on the shared pool.
+/// A worker is only taken while messages are waiting, so that an open database does not hold a thread of its own.
+pub struct SerialQueue<M> {
+    inner: Option<Arc<Serial<M>>>,
+}
+
+struct Serial<M> {
+    state: Mutex<SerialState<M>>,
+    handler: Mutex<Option<Handler<M>>>,
+}
+
+struct SerialState<M> {
+    messages: VecDeque<M>,
+    // a job draining the messages is queued or running on the pool
+    running: bool,
+    // the handler stopped, so the messages are rejected
+    closed: bool,
+    // the queue is dropped, so the handler is released once the messages sent before are handled
+    released: bool,
+}
+
+impl<T> Future for Task<T> {
+    type Output = Result<T, TaskError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<T> Completer<T> {
+    fn complete(&self, result: Result<T, TaskError>) {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.result.is_some() {
+            return;
+        }
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.complete(Err(TaskError::Cancelled));
+    }
+}
+
+impl TaskPool {
+    /// new creates the pool with the number of worker threads. At least one thread is created.
+    pub fn new(size: usize) -> Self {
+        Self::with_capacity(size, size.max(1) * QUEUE_CAPACITY_PER_WORKER)
+    }
+
+    /// with_capacity creates the pool with the number of worker threads and the capacity of the queue.
+    /// At least one thread is created and one job can wait.
+    pub fn with_capacity(size: usize, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (tx, rx) = mpsc::sync_channel::<Job>(capacity);
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                thread::spawn(move || loop {
+                    let job = rx.lock().unwrap().recv();
+                    match job {
+                        // panic of the job cancels its task but keeps the worker alive
+                        Ok(job) => {
+                            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                        },
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            tx: Some(tx),
+            workers,
+            capacity,
+        }
+    }
+
+    /// size returns the number of the worker threads.
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// capacity returns the number of the jobs which can wait for a worker.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn send(&self, job: Job) -> Result<(), (TaskError, Job)> {
+        let tx = match &self.tx {
+            Some(tx) => tx,
+            None => return Err((TaskError::ShutDown, job)),
+        };
+        tx.try_send(job).map_err(|err| match err {
+            mpsc::TrySendError::Full(job) => (TaskError::QueueFull(self.capacity), job),
+            mpsc::TrySendError::Disconnected(job) => (TaskError::ShutDown, job),
+        })
+    }
+
+    /// execute runs the job on one of the workers without waiting for its result.
+    /// It returns an error without running the job if the queue is full or the pool is shut down.
+    pub fn execute<F>(&self, job: F) -> Result<(), TaskError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.send(Box::new(job)).map_err(|(err, _)| err)
+    }
+
+    /// spawn runs the job on one of the workers and returns the future of its result.
+    pub fn spawn<T, F>(&self, job: F) -> Task<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        let completer = Completer {
+            slot: Arc::clone(&slot),
+        };
+        let sent = self.send(Box::new(move || {
+            let result = job();
+            completer.complete(Ok(result));
+        }));
+        if let Err((err, job)) = sent {
+            // the error is set before the job is dropped, otherwise the dropped completer cancels the task
+            Completer {
+                slot: Arc::clone(&slot),
+            }
+            .complete(Err(err));
+            drop(job);
+        }
+
+        Task { slot }
+    }
+
+    /// shutdown stops accepting the jobs and waits for the workers to finish the jobs already queued.
+    /// The worker calling it is not waited for, since it cannot finish before returning.
+    pub fn shutdown(&mut self) {
+        self.tx = None;
+        let current = thread::current().id();
+        for worker in self.workers.drain(..) {
+            if worker.thread().id() != current {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+impl Drop for TaskPool {
+    /// drop stops accepting the jobs without waiting for the workers.
+    /// The workers exit once they finish the jobs already queued, so dropping the pool never blocks the JS thread.
+    fn drop(&mut self) {
+        self.tx = None;
+    }
+}
+
+impl<M: Send + 'static> SerialQueue<M> {
+    /// new creates the queue calling the handler with each message. The handler returns false to stop,
+    /// then it is released and the messages waiting or sent afterwards are rejected.
+    pub fn new(handler: impl FnMut(M) -> bool + Send + 'static) -> Self {
+        Self {
+            inner: Some(Arc::new(Serial {
+                state: Mutex::new(SerialState {
+                    messages: VecDeque::new(),
+                    running: false,
+                    closed: false,
+                    released: false,
+                }),
+                handler: Mutex::new(Some(Box::new(handler))),
+            })),
+        }
+    }
+
+    /// closed creates the queue rejecting every message, for the owners which are never called from JS.
+    pub fn closed() -> Self {
+        Self { inner: None }
+    }
+
+    /// send queues the message, and starts draining the queue on the shared pool when it is idle.
+    /// The message is returned if the queue is closed or the pool rejects the job.
+    pub fn send(&self, message: M) -> Result<(), mpsc::SendError<M>> {
+        let serial = match &self.inner {
+            Some(serial) => serial,
+            None => return Err(mpsc::SendError(message)),
+        };
+        let mut state = serial.state.lock().unwrap();
+        if state.closed {
+            return Err(mpsc::SendError(message));
+        }
+        state.messages.push_back(message);
+        if state.running {
+            return Ok(());
+        }
+        let drain = Arc::clone(serial);
+        if shared().execute(move || drain.drain()).is_err() {
+            // the queue was idle, so the message is the only one waiting
+            let message = state.messages.pop_back().expect("message was just queued");
+            return Err(mpsc::SendError(message));
+        }
+        state.running = true;
+
+        Ok(())
+    }
+}
+
+impl<M> Drop for SerialQueue<M> {
+    fn drop(&mut self) {
+        let serial = match &self.inner {
+            Some(serial) => serial,
+            None => return,
+        };
+        let mut state = serial.state.lock().unwrap();
+        state.released = true;
+        if !state.running {
+            drop(state);
+            serial.handler.lock().unwrap().take();
+        }
+    }
+}
+
+impl<M> Serial<M> {
+    /// drain handles the messages until the queue is empty. Only one drain runs at a time.
+    fn drain(&self) {
+        loop {
+            let message = {
+                let mut state = self.state.lock().unwrap();
+                match state.messages.pop_front() {
+                    Some(message) => message,
+                    None => {
+                        state.running = false;
+                        if state.released {
+                            drop(state);
+                            self.handler.lock().unwrap().take();
+                        }
+                        return;
+                    },
+                }
+            };
+            let open = {
+                let mut handler = self.handler.lock().unwrap();
+                // panic of the handler stops the queue like the failure of a dedicated thread did
+                let open = match handler.as_mut() {
+                    Some(handler) => {
+                        panic::catch_unwind(AssertUnwindSafe(|| handler(message))).unwrap_or(false)
+                    },
+                    None => false,
+                };
+                if !open {
+                    handler.take();
+                }
+                open
+            };
+            if !open {
+                let mut state = self.state.lock().unwrap();
+                state.closed = true;
+                state.running = false;
+                let rejected = mem::take(&mut state.messages);
+                drop(state);
+                drop(rejected);
+                return;
+            }
+        }
+    }
+}
+
+/// shared returns the pool used by the JS bindings.
+/// It is created with a worker per available CPU when it is not configured.
+pub fn shared() -> Arc<TaskPool> {
+    let mut pool = SHARED.lock().unwrap();
+    let pool = pool.get_or_insert_with(|| {
+        let size = thread::available_parallelism()
+            .map(|size| size.get())
+            .unwrap_or(1);
+        Arc::new(TaskPool::new(size))
+    });
+    Arc::clone(pool)
+}
+
+/// configure_shared replaces the shared pool with the number of worker threads and the capacity of the queue.
+/// The jobs queued on the previous pool are completed on its workers, which exit afterwards without being waited for.
+pub fn configure_shared(size: usize, capacity: Option<usize>) {
+    let pool = match capacity {
+        Some(capacity) => TaskPool::with_capacity(size, capacity),
+        None => TaskPool::new(size),
+    };
+    let previous = SHARED.lock().unwrap().replace(Arc::new(pool));
+    drop(previous);
+}
+
+/// shutdown_shared shuts down the shared pool. Its workers complete the queued jobs and exit without being waited for.
+/// A new pool is created when a job is spawned on the shared pool afterwards.
+pub fn shutdown_shared() {
+    let previous = SHARED.lock().unwrap().take();
+    drop(previous);
+}
+
+/// js_configure is handler for JS ffi.
+/// - @params(0) - number of the worker threads.
+/// - @params(1) - capacity of the queue. null or undefined uses the default capacity for the size.
+#[cfg(feature = "neon")]
+pub fn js_configure(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    let size = ctx.argument::<JsNumber>(0)?.value(&mut ctx);
+    if !size.is_finite() || size < 1.0 {
+        return ctx.throw_range_error("Worker pool size must be a positive number");
+    }
+    let capacity = match ctx.argument_opt(1) {
+        Some(val) if val.is_a::<JsNumber, _>(&mut ctx) => {
+            let val = val
+                .downcast_or_throw::<JsNumber, _>(&mut ctx)?
+                .value(&mut ctx);
+            if !val.is_finite() || val < 1.0 {
+                return ctx
+                    .throw_range_error("Worker pool queue capacity must be a positive number");
+            }
+            Some(val as usize)
+        },
+        Some(val) if val.is_a::<JsNull, _>(&mut ctx) || val.is_a::<JsUndefined, _>(&mut ctx) => {
+            None
+        },
+        Some(_) => return ctx.throw_type_error("Worker pool queue capacity must be a number"),
+        None => None,
+    };
+    configure_shared(size as usize, capacity);
+
+    Ok(ctx.undefined())
+}
+
+/// js_shutdown is handler for JS ffi.
+#[cfg(feature = "neon")]
+pub fn js_shutdown(mut ctx: FunctionContext) -> JsResult<JsUndefined> {
+    shutdown_shared();
+
+    Ok(ctx.undefined())
+}
+
+/// js_stats is handler for JS ffi.
+/// - @returns - { size: number, queueCapacity: number } of the shared pool.
+#[cfg(feature = "neon")]
+pub fn js_stats(mut ctx: FunctionContext) -> JsResult<JsObject> {
+    let pool = shared();
+    let obj = ctx.empty_object();
+    let size = ctx.number(pool.size() as f64);
+    obj.set(&mut ctx, "size", size)?;
+    let capacity = ctx.number(pool.capacity() as f64);
+    obj.set(&mut ctx, "queueCapacity", capacity)?;
+
+    Ok(obj)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::task::Wake;
+
+    use super::*;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// block_on polls the future on the current thread until it completes.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn() {
+        let pool = TaskPool::new(2);
+        let tasks = (0..10)
+            .map(|i| pool.spawn(move || i * 2))
+            .collect::<Vec<_>>();
+        let results = tasks.into_iter().map(block_on).collect::<Vec<_>>();
+
+        assert_eq!(results, (0..10).map(|i| Ok(i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_panic_cancels_task() {
+        let pool = TaskPool::new(1);
+        let task = pool.spawn(|| -> u32 { panic!("job failed") });
+        assert_eq!(block_on(task), Err(TaskError::Cancelled));
+
+        // the worker is still alive after the panic
+        assert_eq!(block_on(pool.spawn(|| 1)), Ok(1));
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let pool = TaskPool::with_capacity(1, 1);
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let running = pool.spawn(move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            1
+        });
+        started_rx.recv().unwrap();
+
+        // the worker is busy, so one job waits in the queue and the next one is rejected
+        let queued = pool.spawn(|| 2);
+        assert_eq!(block_on(pool.spawn(|| 3)), Err(TaskError::QueueFull(1)));
+        assert_eq!(pool.execute(|| {}), Err(TaskError::QueueFull(1)));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(block_on(running), Ok(1));
+        assert_eq!(block_on(queued), Ok(2));
+    }
+
+    #[test]
+    fn test_shutdown_completes_queued_jobs() {
+        let mut pool = TaskPool::with_capacity(1, 10);
+        let tasks = (0..5).map(|i| pool.spawn(move || i)).collect::<Vec<_>>();
+        pool.shutdown();
+
+        let results = tasks.into_iter().map(block_on).collect::<Vec<_>>();
+        assert_eq!(results, (0..5).map(Ok).collect::<Vec<_>>());
+        assert_eq!(pool.size(), 0);
+        assert_eq!(block_on(pool.spawn(|| 1)), Err(TaskError::ShutDown));
+        assert_eq!(pool.execute(|| {}), Err(TaskError::ShutDown));
+    }
+
+    #[test]
+    fn test_drop_does_not_wait() {
+        let pool = TaskPool::new(1);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let running = pool.spawn(move || {
+            release_rx.recv().unwrap();
+            1
+        });
+        let queued = pool.spawn(|| 2);
+        drop(pool);
+
+        // the worker completes the jobs queued before the drop
+        release_tx.send(()).unwrap();
+        assert_eq!(block_on(running), Ok(1));
+        assert_eq!(block_on(queued), Ok(2));
+    }
+
+    #[test]
+    fn test_job_drops_last_pool() {
+        let mut pool = Arc::new(TaskPool::new(1));
+        let held = Arc::clone(&pool);
+        let task = pool.spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(10));
+            drop(held);
+            1
+        });
+        // the job drops the last reference, which must not wait for its own worker
+        pool = Arc::new(TaskPool::new(1));
+        assert_eq!(block_on(task), Ok(1));
+        assert_eq!(block_on(pool.spawn(|| 2)), Ok(2));
+    }
+
+    #[test]
+    fn test_serial_queue() {
+        let (done_tx, done_rx) = mpsc::channel();
+        let queue = SerialQueue::new(move |message: Option<u32>| match message {
+            Some(value) => {
+                done_tx.send(value).unwrap();
+                true
+            },
+            None => false,
+        });
+        for i in 0..100 {
+            queue.send(Some(i)).unwrap();
+        }
+        queue.send(None).unwrap();
+
+        // the messages are handled in the order of sending, and rejected once the handler stops
+        assert_eq!(
+            done_rx.iter().collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+        assert!(queue.send(Some(100)).is_err());
+        assert!(SerialQueue::closed().send(1).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+/// test_support generates the states reproducible from a seed with the roots expected for them,
+/// so the Rust and the JS tests fuzz commit, prove and verify against the same data.
+/// The expected root is calculated from the whole state with a new tree, independently of the commits under the test.
+use std::collections::BTreeMap;
+
+#[cfg(feature = "neon")]
+use neon::prelude::*;
+
+use crate::consts;
+#[cfg(feature = "neon")]
+use crate::database::utils;
+use crate::sparse_merkle_tree::smt::{hash_state_key, SMTError, SparseMerkleTree, UpdateData};
+use crate::sparse_merkle_tree::smt_db::InMemorySmtDB;
+use crate::types::{Cache, HashKind, HashWithKind, KVPair, NestedVec, PREFIX_SIZE};
+
+const STORE_COUNT: usize = 4;
+const MAX_SUFFIX_SIZE: usize = 32;
+const MAX_VALUE_SIZE: usize = 64;
+
+/// Rng is the SplitMix64 generator. It is implemented here, so the sequence never changes with a dependency.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// below returns the number in [0, n). n must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn bytes(&mut self, size: usize) -> Vec<u8> {
+        (0..size).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+/// Round is the changes committed at a height with the root expected after them.
+/// The empty value of a change deletes the key.
+/// The queries are the state keys to prove at the root, which include the keys not in the state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Round {
+    pub changes: Vec<KVPair>,
+    pub root: Vec<u8>,
+    pub queries: NestedVec,
+}
+
+/// StateGenerator generates the rounds of the changes to the state, which is kept to calculate the expected roots.
+pub struct StateGenerator {
+    rng: Rng,
+    stores: NestedVec,
+    state: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl StateGenerator {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let stores = (0..STORE_COUNT).map(|_| rng.bytes(PREFIX_SIZE)).collect();
+        Self {
+            rng,
+            stores,
+            state: BTreeMap::new(),
+        }
+    }
+
+    /// state returns the state after the rounds generated so far.
+    pub fn state(&self) -> &BTreeMap<Vec<u8>, Vec<u8>> {
+        &self.state
+    }
+
+    /// next_round generates the changes to `count` keys, which are created, updated or deleted.
+    pub fn next_round(&mut self, count: usize) -> Result<Round, SMTError> {
+        let mut changes = BTreeMap::new();
+        for _ in 0..count {
+            let (key, value) = match self.rng.below(10) {
+                0..=4 => (self.new_key(), self.value()),
+                5..=7 => match self.existing_key() {
+                    Some(key) => (key, self.value()),
+                    None => (self.new_key(), self.value()),
+                },
+                _ => match self.existing_key() {
+                    Some(key) => (key, vec![]),
+                    None => continue,
+                },
+            };
+            changes.insert(key, value);
+        }
+        for (key, value) in changes.iter() {
+            if value.is_empty() {
+                self.state.remove(key);
+            } else {
+                self.state.insert(key.clone(), value.clone());
+            }
+        }
+
+        let mut queries: NestedVec = changes.keys().take(count / 2 + 1).cloned().collect();
+        for _ in 0..count / 4 + 1 {
+            let key = self.new_key();
+            if !queries.contains(&key) {
+                queries.push(key);
+            }
+        }
+
+        Ok(Round {
+            changes: changes
+                .iter()
+                .map(|(key, value)| KVPair::new(key, value))
+                .collect(),
+            root: expected_root(&self.state)?,
+            queries,
+        })
+    }
+
+    fn new_key(&mut self) -> Vec<u8> {
+        let store = &self.stores[self.rng.below(self.stores.len())];
+        let suffix_size = 1 + self.rng.below(MAX_SUFFIX_SIZE);
+        [store.as_slice(), &self.rng.bytes(suffix_size)].concat()
+    }
+
+    fn existing_key(&mut self) -> Option<Vec<u8>> {
+        if self.state.is_empty() {
+            return None;
+        }
+        let index = self.rng.below(self.state.len());
+        self.state.keys().nth(index).cloned()
+    }
+
+    fn value(&mut self) -> Vec<u8> {
+        let size = 1 + self.rng.below(MAX_VALUE_SIZE);
+        self.rng.bytes(size)
+    }
+}
+
+/// generate returns the rounds generated from the seed, with `count` changes in each of them.
+pub fn generate(seed: u64, rounds: usize, count: usize) -> Result<Vec<Round>, SMTError> {
+    let mut generator = StateGenerator::new(seed);
+    (0..rounds).map(|_| generator.next_round(count)).collect()
+}
+
+/// expected_root calculates the root of the state with a new tree.
+pub fn expected_root(state: &BTreeMap<Vec<u8>, Vec<u8>>) -> Result<Vec<u8>, SMTError> {
+    let mut data = Cache::new();
+    for (key, value) in state.iter() {
+        data.insert(hash_state_key(key)?, value.hash_with_kind(HashKind::Value));
+    }
+    let mut db = InMemorySmtDB::default();
+    let mut tree = SparseMerkleTree::new(&[], consts::KEY_LENGTH, consts::SUBTREE_HEIGHT);
+    let root = tree.commit(&mut db, &UpdateData::new_from(data))?;
+    let root = root.lock().unwrap().to_vec();
+    Ok(root)
+}
+
+/// js_generate is handler for JS ffi.
+/// - @params(0) - seed.
+/// - @params(1) - number of the rounds.
+/// - @params(2) - number of the changes in each round.
+/// - @returns - [{ changes: [{ key, value }], root, queries }].
+#[cfg(feature = "neon")]
+pub fn js_generate(mut ctx: FunctionContext) -> JsResult<JsArray> {
+    let seed = ctx.argument::<JsNumber>(0)?.value(&mut ctx) as u64;
+    let rounds = ctx.argument::<JsNumber>(1)?.value(&mut ctx) as usize;
+    let count = ctx.argument::<JsNumber>(2)?.value(&mut ctx) as usize;
+    let rounds = match generate(seed, rounds, count) {
+        Ok(rounds) => rounds,
+        Err(err) => return ctx.throw_error(err.to_string()),
+    };
+
+    let result = ctx.empty_array();
+    for (i, round) in rounds.into_iter().enumerate() {
+        let obj = ctx.empty_object();
+        let changes = utils::pairs_into_js_array(&mut ctx, round.changes)?;
+        obj.set(&mut ctx, "changes", changes)?;
+        let root = JsBuffer::external(&mut ctx, round.root);
+        obj.set(&mut ctx, "root", root)?;
+        let queries = ctx.empty_array();
+        for (j, query) in round.queries.into_iter().enumerate() {
+            let query = JsBuffer::external(&mut ctx, query);
+            queries.set(&mut ctx, j as u32, query)?;
+        }
+        obj.set(&mut ctx, "queries", queries)?;
+        result.set(&mut ctx, i as u32, obj)?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse_merkle_tree::smt::EMPTY_HASH;
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let rounds = generate(7, 5, 30).unwrap();
+        assert_eq!(rounds, generate(7, 5, 30).unwrap());
+        assert_ne!(rounds, generate(8, 5, 30).unwrap());
+        assert!(rounds
+            .iter()
+            .flat_map(|round| round.changes.iter())
+            .any(|change| change.is_empty_value()));
+        assert_eq!(
+            expected_root(&BTreeMap::new()).unwrap(),
+            EMPTY_HASH.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_commit_prove_verify() {
+        for seed in 0..4 {
+            let mut db = InMemorySmtDB::default();
+            let mut root = vec![];
+            let mut generator = StateGenerator::new(seed);
+            for _ in 0..6 {
+                let round = generator.next_round(40).unwrap();
+                let data = UpdateData::new_from(round.changes.iter().map(|change| {
+                    let value = if change.is_empty_value() {
+                        vec![]
+                    } else {
+                        change.value_as_vec().hash_with_kind(HashKind::Value)
+                    };
+                    (hash_state_key(change.key()).unwrap(), value)
+                }));
+                let mut tree =
+                    SparseMerkleTree::new(&root, consts::KEY_LENGTH, consts::SUBTREE_HEIGHT);
+                root = tree
+                    .commit(&mut db, &data)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .to_vec();
+                assert_eq!(root, round.root);
+
+                let queries = round
+                    .queries
+                    .iter()
+                    .map(|key| hash_state_key(key).unwrap())
+                    .collect::<NestedVec>();
+                let proof = tree.prove(&db, &queries).unwrap();
+                assert!(
+                    SparseMerkleTree::verify(&queries, &proof, &root, consts::KEY_LENGTH).unwrap()
+                );
+                for ((key, hashed_key), query) in round
+                    .queries
+                    .iter()
+                    .zip(queries.iter())
+                    .zip(proof.queries.iter())
+                {
+                    let value_hash = match generator.state().get(key) {
+                        Some(value) if query.key() == hashed_key.as_slice() => {
+                            value.hash_with_kind(HashKind::Value)
+                        },
+                        Some(_) => panic!("the key in the state is not proven"),
+                        None if query.key() == hashed_key.as_slice() => vec![],
+                        None => continue,
+                    };
+                    assert_eq!(query.value(), value_hash.as_slice());
+                }
+            }
+        }
+    }
+}
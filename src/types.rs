@@ -1,13 +1,15 @@
 use std::collections::HashMap;
 use std::ops::{Add, Sub};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use sha2::{Digest, Sha256};
 
 use crate::codec;
 use crate::consts::PREFIX_BRANCH_HASH;
+use crate::metrics::{self, Counter};
 
-const PREFIX_SIZE: usize = 6;
+/// PREFIX_SIZE is the size of the key prefix kept before hashing the rest of the key.
+pub const PREFIX_SIZE: usize = 6;
 
 pub type NestedVecGeneric<T> = Vec<Vec<T>>;
 pub type NestedVec = NestedVecGeneric<u8>;
@@ -17,8 +19,38 @@ pub type Cache = HashMap<Vec<u8>, Vec<u8>>;
 pub type VecOption = Option<Vec<u8>>;
 pub type SharedVec = Arc<Mutex<Arc<Vec<u8>>>>;
 pub type ArcMutex<T> = Arc<Mutex<T>>;
+pub type ArcRwLock<T> = Arc<RwLock<T>>;
 pub type CommitOptions = Options<BlockHeight>;
 
+/// RecoverLock takes the lock even if a thread panicked while holding it.
+/// The panic is reported to the caller of that request, and the following requests keep using the data
+/// instead of failing for the lifetime of the process.
+pub trait RecoverLock<T: ?Sized> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T: ?Sized> RecoverLock<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// RecoverRwLock is RecoverLock of the RwLock.
+pub trait RecoverRwLock<T: ?Sized> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T: ?Sized> RecoverRwLock<T> for RwLock<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
 // Strong type of SMT with max value KEY_LENGTH * 8
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub struct Height(pub u16);
@@ -38,7 +70,7 @@ pub struct SubtreeHeight(pub SubtreeHeightKind);
 #[derive(Clone, Debug, Copy)]
 pub struct KeyLength(pub u16);
 
-// Options is a base class for type CommitOptions and DbOptions
+// Options is a base class for type CommitOptions
 #[derive(Debug, Copy, Clone)]
 pub struct Options<T> {
     readonly: bool,
@@ -267,6 +299,7 @@ impl HashWithKind for Vec<u8> {
                 hasher.update(self);
             },
             HashKind::Branch => {
+                metrics::increment(Counter::NodesHashed, 1);
                 hasher.update(PREFIX_BRANCH_HASH);
                 hasher.update(self);
             },
@@ -281,6 +314,42 @@ impl HashWithKind for Vec<u8> {
     }
 }
 
+/// hash_branch returns the same hash as the concatenation of the children hashed with HashKind::Branch,
+/// without allocating the concatenation.
+pub fn hash_branch(left: &[u8], right: &[u8]) -> Vec<u8> {
+    hash_branch_array(left, right).to_vec()
+}
+
+/// hash_branch_array is the same as hash_branch, but returns the hash on the stack,
+/// so that the hashes recalculated for each level of the tree are not allocated.
+pub fn hash_branch_array(left: &[u8], right: &[u8]) -> [u8; 32] {
+    metrics::increment(Counter::NodesHashed, 1);
+    let mut hasher = Sha256::new();
+    hasher.update(PREFIX_BRANCH_HASH);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// hash_branches returns the hashes of the branches in the order of the branches.
+/// The hasher which has already absorbed the branch prefix is cloned for each branch,
+/// so that a layer of the tree is hashed in one call.
+pub fn hash_branches<'a>(branches: impl IntoIterator<Item = (&'a [u8], &'a [u8])>) -> NestedVec {
+    let mut prefixed = Sha256::new();
+    prefixed.update(PREFIX_BRANCH_HASH);
+    let hashes: NestedVec = branches
+        .into_iter()
+        .map(|(left, right)| {
+            let mut hasher = prefixed.clone();
+            hasher.update(left);
+            hasher.update(right);
+            hasher.finalize().to_vec()
+        })
+        .collect();
+    metrics::increment(Counter::NodesHashed, hashes.len() as u64);
+    hashes
+}
+
 impl<T> Options<T> {
     #[inline]
     pub fn new(readonly: bool, number: T) -> Self {
@@ -330,6 +399,20 @@ impl KVPair {
     pub fn is_empty_value(&self) -> bool {
         self.1.is_empty()
     }
+
+    /// into_parts returns the key and the value without copying them.
+    #[inline]
+    pub fn into_parts(self) -> (Vec<u8>, Vec<u8>) {
+        (self.0, self.1)
+    }
+}
+
+/// The pairs read by the rocksdb iterator are moved into the pair without copying them.
+impl From<(Box<[u8]>, Box<[u8]>)> for KVPair {
+    #[inline]
+    fn from((key, value): (Box<[u8]>, Box<[u8]>)) -> Self {
+        Self(key.into_vec(), value.into_vec())
+    }
 }
 
 impl<'a> SharedKVPair<'a> {
@@ -455,6 +538,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hash_branch() {
+        let left = vec![1u8; 32];
+        let right = vec![2u8; 32];
+        let expected = [left.as_slice(), &right]
+            .concat()
+            .hash_with_kind(HashKind::Branch);
+        assert_eq!(hash_branch(&left, &right), expected);
+        assert_eq!(hash_branch_array(&left, &right).to_vec(), expected);
+        assert_eq!(
+            hash_branches(vec![(left.as_slice(), right.as_slice()), (&right, &left)]),
+            vec![
+                expected,
+                [right.as_slice(), &left]
+                    .concat()
+                    .hash_with_kind(HashKind::Branch)
+            ]
+        );
+        assert!(hash_branches(vec![]).is_empty());
+    }
+
     #[test]
     fn test_values_subtree_height_kind() {
         let test_data = vec![
@@ -466,4 +570,16 @@ mod tests {
             assert_eq!(SubtreeHeight(data).u16(), result);
         }
     }
+
+    #[test]
+    fn test_kv_pair_from_boxed() {
+        let value = vec![7; 4096].into_boxed_slice();
+        let ptr = value.as_ptr();
+        let pair = KVPair::from((vec![1, 2].into_boxed_slice(), value));
+        assert_eq!(pair.key(), &[1, 2]);
+        let (_, value) = pair.into_parts();
+        // The value is moved, so the buffer given to JS is the one read from the database
+        assert_eq!(value.as_ptr(), ptr);
+        assert_eq!(value.len(), 4096);
+    }
 }
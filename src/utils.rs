@@ -143,6 +143,19 @@ pub fn binary_search<T>(list: &[T], callback: impl Fn(&T) -> bool) -> i32 {
     hi
 }
 
+/// prefix_end returns the smallest key which is greater than all the keys with the prefix.
+/// It returns None when there is no such key, i.e. the prefix is empty or only has 0xff.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
@@ -453,4 +466,12 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(&[1, 2, 3]), Some(vec![1, 2, 4]));
+        assert_eq!(prefix_end(&[1, 2, 255]), Some(vec![1, 3]));
+        assert_eq!(prefix_end(&[255, 255]), None);
+        assert_eq!(prefix_end(&[]), None);
+    }
 }
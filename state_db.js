@@ -18,19 +18,67 @@ const {
     state_db_new,
     state_db_close,
     state_db_get,
+    state_db_get_many,
     state_db_get_current_state,
+    state_db_get_root_at,
+    state_db_get_root_at_height,
+    state_db_find_height_for_root,
+    state_db_get_store_root,
+    state_db_get_structure_log,
+    state_db_new_overlay,
+    state_db_overlay_update,
+    state_db_get_finalized_state,
+    state_db_get_lifetime_stats,
+    state_db_debug_report,
+    state_db_get_audit_records,
+    state_db_verify_audit_trail,
     state_db_exists,
     state_db_iterate,
     state_db_revert,
+    state_db_calculate_diff_between,
+    state_db_get_diff_stats,
     state_db_commit,
+    state_db_subscribe_commit,
+    state_db_unsubscribe_commit,
+    state_db_get_metrics,
     state_db_prove,
+    state_db_prove_many,
     state_db_verify,
+    state_db_prove_prefix,
+    state_db_generate_witness,
+    state_db_verify_prefix,
+    state_db_get_value_hash,
+    state_db_to_visualization,
+    state_db_export_chunks,
+    state_db_verify_state_root,
     state_db_clean_diff_until,
     state_db_checkpoint,
+    state_db_create_backup,
+    state_db_compact_range,
+    state_db_compact_smt,
+    state_db_set_auto_compaction,
+    state_db_flush,
+    state_db_sync_wal,
+    state_db_properties,
+    state_db_approximate_size,
+    state_db_write_stall,
+    state_db_new_secondary,
+    state_db_try_catch_up_with_primary,
     state_db_calculate_root,
     state_writer_new,
+    state_writer_new_with_db,
     state_writer_close,
+    state_writer_get,
+    state_writer_set,
+    state_writer_cache_existing,
+    state_writer_del,
+    state_writer_range,
+    state_writer_get_updated,
+    state_writer_changed_keys,
     state_writer_snapshot,
+    state_writer_delete_range,
+    state_writer_size,
+    state_writer_set_cap,
     state_writer_restore_snapshot,
     state_db_reader_new,
     state_db_reader_close,
@@ -45,25 +93,42 @@ const {
     state_db_read_writer_range,
 } = require("./bin-package/index.node");
 
-const { NotFoundError } = require('./error');
+const {
+    ErrorCode,
+    NotFoundError,
+    DatabaseClosedError,
+    convertOpenError,
+    convertCommitError,
+} = require('./error');
+const { Batch } = require('./database');
 const { Iterator } = require("./iterator");
+const { ResourceTracker } = require('./resource_tracker');
+const { ProveCoalescer } = require('./prove_coalescer');
 const { getOptionsWithDefault } = require('./options');
-const { isInclusionProofForQueryKey } = require('./utils');
+const { isInclusionProofForQueryKey, formatVisualization, followPrimary, watchWriteStall } = require('./utils');
+
+const DEFAULT_FOLLOW_INTERVAL = 1000;
+const DEFAULT_WRITE_STALL_INTERVAL = 1000;
+// IN_MEMORY_PATH is only the name of the in-memory state_db, as each of them has its own files in memory
+const IN_MEMORY_PATH = '/in_memory_state_db';
 
 class StateReader {
-    constructor(db) {
+    constructor(db, tracker) {
+        this._tracker = tracker;
+        this._trackerID = tracker.acquire(this, 'reader');
         this._db = state_db_reader_new(db);
     }
 
     close() {
         state_db_reader_close.call(this._db);
+        this._tracker.release(this, this._trackerID);
     }
 
     async get(key) {
         return new Promise((resolve, reject) => {
             state_db_reader_get.call(this._db, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Key ${key.toString('hex')} does not exist.`));
                     }
                     return reject(err);
@@ -91,34 +156,191 @@ class StateReader {
     }
 
     iterate(options = {}) {
-        return new Iterator(this._db, state_db_reader_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, state_db_reader_iterate, getOptionsWithDefault(options), this._tracker);
     }
 
     createReadStream(options = {}) {
-        return new Iterator(this._db, state_db_reader_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, state_db_reader_iterate, getOptionsWithDefault(options), this._tracker);
+    }
+}
+
+// StateOverlay applies tentative updates on top of a committed root in memory, so the root of a candidate block can be
+// computed without writing to the state_db. The updates are applied incrementally, and the overlay is dropped by discard.
+class StateOverlay {
+    constructor(db, root) {
+        this._db = db;
+        this._overlay = state_db_new_overlay.call(db._db, root);
+        this._root = root;
+    }
+
+    // root is the root after the updates applied so far, which is the committed root before the first update
+    get root() {
+        return this._root;
+    }
+
+    // update applies the batch of state keys and values on top of the previous updates and resolves the would-be root.
+    // The batch can be a Batch or the bytes encoded by Batch.encode, and the overlay is not changed if the update fails.
+    async update(batch) {
+        if (this._overlay === undefined) {
+            throw new Error('Overlay is already discarded.');
+        }
+        const target = Buffer.isBuffer(batch) ? Batch.decode(batch) : batch;
+        return new Promise((resolve, reject) => {
+            state_db_overlay_update.call(this._db._db, this._overlay, target.inner, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                this._root = result;
+                resolve(result);
+            });
+        });
+    }
+
+    // discard drops the nodes kept by the overlay
+    discard() {
+        this._overlay = undefined;
+    }
+}
+
+// changedKeys returns the keys changed by the writer starting with the prefix, grouped by the store prefix
+const changedKeys = (writer, prefix) => new Promise((resolve, reject) => {
+    state_writer_changed_keys.call(writer, prefix, (err, result) => {
+        if (err) {
+            return reject(err);
+        }
+        resolve(result);
+    });
+});
+
+// StateWriter holds the changes of the state in the native cache.
+// Without the db option, the values existing in the database must be cached with cacheExisting before they are updated or deleted,
+// so that the commit records them as updated or deleted instead of created.
+// With the db option, the values not cached are read from the StateDB on the first access, and the writer must be closed to release it.
+class StateWriter {
+    constructor(options = {}) {
+        this._writer = options.db ? state_writer_new_with_db(options.db._db) : state_writer_new();
+        if (options.maxSize !== undefined) {
+            state_writer_set_cap.call(this._writer, options.maxSize);
+        }
+    }
+
+    get writer() {
+        return this._writer;
+    }
+
+    get size() {
+        return state_writer_size.call(this._writer);
+    }
+
+    async get(key) {
+        const value = state_writer_get.call(this._writer, key);
+        if (value === undefined) {
+            throw new NotFoundError(`Key ${key.toString('hex')} does not exist.`);
+        }
+        // If value is empty, force to use different memory space from what's given from binding
+        // Issue: https://github.com/nodejs/node/issues/32463
+        if (value.length === 0) {
+            return Buffer.alloc(0);
+        }
+        return value;
+    }
+
+    async has(key) {
+        return state_writer_get.call(this._writer, key) !== undefined;
+    }
+
+    async set(key, value) {
+        state_writer_set.call(this._writer, key, value);
+    }
+
+    async cacheExisting(key, value) {
+        state_writer_cache_existing.call(this._writer, key, value);
+    }
+
+    async del(key) {
+        state_writer_del.call(this._writer, key);
+    }
+
+    deleteRange(start, end) {
+        state_writer_delete_range.call(this._writer, start, end);
+    }
+
+    async range(options = {}) {
+        const defaultOptions = {
+            ...getOptionsWithDefault(options),
+            gt: options.gt,
+            lt: options.lt,
+        };
+        return new Promise((resolve, reject) => {
+            state_writer_range.call(this._writer, defaultOptions, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getUpdated returns the created or updated pairs and the deleted keys
+    async getUpdated() {
+        return new Promise((resolve, reject) => {
+            state_writer_get_updated.call(this._writer, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async changedKeys(prefix = Buffer.alloc(0)) {
+        return changedKeys(this._writer, prefix);
+    }
+
+    snapshot() {
+        return state_writer_snapshot.call(this._writer);
+    }
+
+    restoreSnapshot(index = 0) {
+        state_writer_restore_snapshot.call(this._writer, index);
+    }
+
+    close() {
+        state_writer_close.call(this._writer);
     }
 }
 
 class StateReadWriter {
-    constructor(db) {
+    constructor(db, tracker, options = {}) {
+        this._tracker = tracker;
+        this._trackerID = tracker.acquire(this, 'readWriter');
         this._db = state_db_read_writer_new(db);
         this._writer = state_writer_new();
+        if (options.maxSize !== undefined) {
+            state_writer_set_cap.call(this._writer, options.maxSize);
+        }
     }
 
     get writer() {
         return this._writer;
     }
 
+    // size is the bytes of the keys and values held by the writer, including the journal of the snapshots
+    get size() {
+        return state_writer_size.call(this._writer);
+    }
+
     close() {
         state_db_read_writer_close.call(this._db);
         state_writer_close.call(this.writer);
+        this._tracker.release(this, this._trackerID);
     }
 
     async get(key) {
         const value = await new Promise((resolve, reject) => {
             state_db_read_writer_get_key.call(this._db, this.writer, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Key ${key.toString('hex')} does not exist.`));
                     }
                     return reject(err);
@@ -169,8 +391,18 @@ class StateReadWriter {
         });
     }
 
+    // deleteRange deletes the keys from start to end, excluding end, including the ones not read by the writer
+    deleteRange(start, end) {
+        state_writer_delete_range.call(this.writer, start, end);
+    }
+
+    // range returns the pairs sorted by the key, which are merged from the writer and the database
     async range(options = {}) {
-        const defaultOptions = getOptionsWithDefault(options);
+        const defaultOptions = {
+            ...getOptionsWithDefault(options),
+            gt: options.gt,
+            lt: options.lt,
+        };
         const result = await new Promise((resolve, reject) => {
             state_db_read_writer_range.call(this._db, this.writer, defaultOptions, (err, result) => {
                 if (err) {
@@ -179,18 +411,13 @@ class StateReadWriter {
                 resolve(result);
             });
         });
-        result.sort((a, b) => {
-            if (options.reverse) {
-                return b.key.compare(a.key);
-            }
-            return a.key.compare(b.key);
-        });
-        if (options.limit) {
-            result.splice(options.limit);
-        }
         return result;
     }
 
+    async changedKeys(prefix = Buffer.alloc(0)) {
+        return changedKeys(this._writer, prefix);
+    }
+
     snapshot() {
         let result = state_writer_snapshot.call(this._writer);
         return result;
@@ -201,16 +428,58 @@ class StateReadWriter {
     }
 }
 
+// copyEmptyProofValues replaces the empty values of the proof with new buffers.
+// If result is empty, force to use different memory space from what's given from binding
+// Issue: https://github.com/nodejs/node/issues/32463
+const copyEmptyProofValues = proof => {
+    for (const query of proof.queries) {
+        if (query.value.length === 0) {
+            query.value = Buffer.alloc(0);
+        }
+        if (query.storedValue && query.storedValue.length === 0) {
+            query.storedValue = Buffer.alloc(0);
+        }
+    }
+    return proof;
+};
+
 class StateDB {
+    // With secondaryPath, the state_db at the path is opened as a read only secondary instance,
+    // which follows the primary opened by another process
     constructor(path, opts = {}) {
-        this._db = state_db_new(path, opts);
+        try {
+            this._handle = opts.secondaryPath
+                ? state_db_new_secondary(path, opts.secondaryPath, { ...opts, readonly: true })
+                : state_db_new(path, opts);
+        } catch (err) {
+            throw convertOpenError(err);
+        }
+        this._tracker = new ResourceTracker(opts);
+        this._closed = false;
+        this._watchers = new Set();
+        // With proveBatchWindow, the prove calls for the same root within the window are proven together
+        this._proveCoalescer = opts.proveBatchWindow > 0
+            ? new ProveCoalescer(
+                opts.proveBatchWindow,
+                (root, querySets, includeValues) => this._proveMany(root, querySets, includeValues),
+                (root, queries, includeValues) => this._prove(root, queries, includeValues),
+            )
+            : undefined;
+    }
+
+    // _db is the native handle, which every operation goes through, so that they throw DatabaseClosedError after close
+    get _db() {
+        if (this._closed) {
+            throw new DatabaseClosedError();
+        }
+        return this._handle;
     }
 
     async get(key) {
         return new Promise((resolve, reject) => {
             state_db_get.call(this._db, key, (err, result) => {
                 if (err) {
-                    if (err.message === 'No data') {
+                    if (err.code === ErrorCode.NOT_FOUND) {
                         return reject(new NotFoundError(`Key ${key.toString('hex')} does not exist.`));
                     }
                     return reject(err);
@@ -226,6 +495,19 @@ class StateDB {
         });
     }
 
+    async getMany(keys) {
+        return new Promise((resolve, reject) => {
+            state_db_get_many.call(this._db, keys, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                // If result is empty, force to use different memory space from what's given from binding
+                // Issue: https://github.com/nodejs/node/issues/32463
+                resolve(result.map(value => (value !== undefined && value.length === 0 ? Buffer.alloc(0) : value)));
+            });
+        });
+    }
+
     async getCurrentState() {
         return new Promise((resolve, reject) => {
             state_db_get_current_state.call(this._db, (err, result) => {
@@ -240,6 +522,53 @@ class StateDB {
         });
     }
 
+    // getRootAt returns the root at the height only when its nodes are retained with keepRootHistory,
+    // which is the current height or the heights within rootHistoryRetention, so the root can be proven.
+    async getRootAt(height) {
+        return new Promise((resolve, reject) => {
+            state_db_get_root_at.call(this._db, height, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Root for height ${height} does not exist.`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getRootAtHeight returns the root committed at the height. Unlike getRootAt, the root is known without keepRootHistory,
+    // but the nodes of the root may be removed already.
+    async getRootAtHeight(height) {
+        return new Promise((resolve, reject) => {
+            state_db_get_root_at_height.call(this._db, height, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Root for height ${height} is not registered.`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // findHeightForRoot returns the highest height committed with the root.
+    async findHeightForRoot(root) {
+        return new Promise((resolve, reject) => {
+            state_db_find_height_for_root.call(this._db, root, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Root ${root.toString('hex')} is not registered.`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
     async has(key) {
         return new Promise((resolve, reject) => {
             state_db_exists.call(this._db, key, (err, result) => {
@@ -252,16 +581,44 @@ class StateDB {
     }
 
     iterate(options = {}) {
-        return new Iterator(this._db, state_db_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, state_db_iterate, getOptionsWithDefault(options), this._tracker, options.signal);
     }
 
     createReadStream(options = {}) {
-        return new Iterator(this._db, state_db_iterate, getOptionsWithDefault(options));
+        return new Iterator(this._db, state_db_iterate, getOptionsWithDefault(options), this._tracker, options.signal);
     }
 
+    // revert(height) reverts the current state at the height without the root given
     async revert(prev_root, height) {
+        const [root, version] = height === undefined ? [null, prev_root] : [prev_root, height];
         return new Promise((resolve, reject) => {
-            state_db_revert.call(this._db, prev_root, height, (err, result) => {
+            state_db_revert.call(this._db, root, version, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // calculateDiffBetween returns the net change from the state at fromHeight to the state at toHeight,
+    // where updated and deleted hold the values at fromHeight
+    async calculateDiffBetween(fromHeight, toHeight) {
+        return new Promise((resolve, reject) => {
+            state_db_calculate_diff_between.call(this._db, fromHeight, toHeight, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getDiffStats returns the number and the bytes of the created, updated and deleted entries
+    // and the encoded size of the diff stored for the height
+    async getDiffStats(height) {
+        return new Promise((resolve, reject) => {
+            state_db_get_diff_stats.call(this._db, height, (err, result) => {
                 if (err) {
                     return reject(err);
                 }
@@ -275,35 +632,123 @@ class StateDB {
             readonly: options.readonly !== undefined ? options.readonly : false,
             checkRoot: options.checkRoot !== undefined ? options.checkRoot : false,
             expectedRoot: options.expectedRoot !== undefined ? options.expectedRoot : Buffer.alloc(0),
+            // expectedState is the current state which the commit is built on, as returned by getCurrentState
+            expectedState: options.expectedState !== undefined ? options.expectedState : null,
         };
         return new Promise((resolve, reject) => {
-            state_db_commit.call(this._db, readWriter.writer, height, prevRoot, defaultOptions.readonly, defaultOptions.expectedRoot, defaultOptions.checkRoot, (err, result) => {
+            state_db_commit.call(this._db, readWriter.writer, height, prevRoot, defaultOptions.readonly, defaultOptions.expectedRoot, defaultOptions.checkRoot, defaultOptions.expectedState, (err, result) => {
                 if (err) {
-                    return reject(err);
+                    return reject(convertCommitError(err));
                 }
                 resolve(result);
             });
         });
     }
 
-    async prove(root, queries) {
+    // onCommit calls the listener with { root, height, diffStats } whenever a commit is written to the database.
+    // The readonly commits only calculate the root, so they are not notified.
+    onCommit(listener) {
+        const id = state_db_subscribe_commit.call(this._db, listener);
+        return this._watch(() => state_db_unsubscribe_commit.call(this._handle, id));
+    }
+
+    // getStats returns the stats recorded by the commits and the proofs of this database in the same form as getStats
+    // of the module, which returns the totals of all the databases.
+    getStats() {
+        return state_db_get_metrics.call(this._db);
+    }
+
+    // prove returns the proof of the queries. With includeValues, the queries are the state keys
+    // and the stored values which match the proven value hashes are returned as storedValue.
+    async prove(root, queries, options = {}) {
+        const includeValues = options.includeValues === true;
+        if (this._proveCoalescer !== undefined) {
+            return this._proveCoalescer.prove(root, queries, includeValues);
+        }
+        return this._prove(root, queries, includeValues);
+    }
+
+    async _prove(root, queries, includeValues) {
         return new Promise((resolve, reject) => {
-            state_db_prove.call(this._db, root, queries, (err, result) => {
+            state_db_prove.call(this._db, root, queries, includeValues, (err, result) => {
                 if (err) {
                     return reject(err);
                 }
-                // If result is empty, force to use different memory space from what's given from binding
-                // Issue: https://github.com/nodejs/node/issues/32463
-                for (const query of result.queries) {
-                    if (query.value.length === 0) {
-                        query.value = Buffer.alloc(0);
-                    }
+                resolve(copyEmptyProofValues(result));
+            });
+        });
+    }
+
+    // _proveMany returns the proof of each set of the queries, which are generated with a single traversal of the tree
+    async _proveMany(root, querySets, includeValues) {
+        return new Promise((resolve, reject) => {
+            state_db_prove_many.call(this._db, root, querySets, includeValues, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result.map(copyEmptyProofValues));
+            });
+        });
+    }
+
+    // exportChunkStream calls onChunk with each encoded chunk of chunkSize pairs of the SMT leaves under the root
+    // in the order of the index, and resolves with the number of the chunks. The next chunk is created after onChunk
+    // returns, so the leaves are never held as a whole. onChunk stops the export by returning false.
+    async exportChunkStream(root, chunkSize, onChunk) {
+        let chunkError;
+        const handleChunk = chunk => {
+            try {
+                return onChunk(chunk) !== false;
+            } catch (err) {
+                chunkError = err;
+                return false;
+            }
+        };
+        return new Promise((resolve, reject) => {
+            state_db_export_chunks.call(this._db, root, chunkSize, handleChunk, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                if (chunkError) {
+                    return reject(chunkError);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // exportChunks returns the SMT leaves under the root split into encoded chunks of chunkSize pairs
+    async exportChunks(root, chunkSize) {
+        const chunks = [];
+        await this.exportChunkStream(root, chunkSize, chunk => {
+            chunks.push(chunk);
+        });
+        return chunks;
+    }
+
+    // verifyStateRoot recomputes the root from the stored subtrees and the flat state after an unclean shutdown.
+    // With the repair option, the subtrees of the root are rewritten from the flat state when only they diverge.
+    async verifyStateRoot(expectedRoot, options = {}) {
+        return new Promise((resolve, reject) => {
+            state_db_verify_state_root.call(this._db, expectedRoot, !!options.repair, (err, result) => {
+                if (err) {
+                    return reject(err);
                 }
                 resolve(result);
             });
         });
     }
 
+    async proveAt(height, queries, options = {}) {
+        const root = await this.getRootAt(height);
+        return this.prove(root, queries, options);
+    }
+
+    async verifyAt(height, queries, proof) {
+        const root = await this.getRootAt(height);
+        return this.verify(root, queries, proof);
+    }
+
     async verify(root, queries, proof) {
         return new Promise((resolve, reject) => {
             state_db_verify.call(this._db, root, queries, proof, (err, result) => {
@@ -334,6 +779,128 @@ class StateDB {
         return this.verify(root, queries, proof);
     }
 
+    // getValueHash returns the value hash of the key in the tree of the root, and throws NotFoundError if it is not in the tree.
+    // The key is the hashed state key as in the queries of prove. With smtKeyFilter, most of the missing keys are
+    // ruled out without reading the tree.
+    async getValueHash(root, key) {
+        return new Promise((resolve, reject) => {
+            state_db_get_value_hash.call(this._db, root, key, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Key ${key.toString('hex')} does not exist in the tree.`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // provePrefix returns the root of the subtree containing all the keys starting with the prefix
+    // (e.g. the module or the store prefix) and the proof of the subtree root to the state root.
+    async provePrefix(root, prefix) {
+        return new Promise((resolve, reject) => {
+            state_db_prove_prefix.call(this._db, root, prefix, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getStoreRoot returns the root of the subtree with all the keys of the store at the height, which is the current height
+    // if it is not given. The sub-roots are kept with storeRoots, so the store must be updated since it is enabled.
+    async getStoreRoot(storePrefix, height) {
+        const version = height === undefined ? (await this.getCurrentState()).version : height;
+        return new Promise((resolve, reject) => {
+            state_db_get_store_root.call(this._db, storePrefix, version, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(
+                            new NotFoundError(`Store root of ${storePrefix.toString('hex')} for height ${version} does not exist.`),
+                        );
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getStructureLog returns the subtrees created and deleted by the commit of the height with the height of each subtree.
+    // The commits are recorded with structureLog, and the record of a reverted height is removed.
+    async getStructureLog(height) {
+        return new Promise((resolve, reject) => {
+            state_db_get_structure_log.call(this._db, height, (err, result) => {
+                if (err) {
+                    if (err.code === ErrorCode.NOT_FOUND) {
+                        return reject(new NotFoundError(`Structure log for height ${height} does not exist.`));
+                    }
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // newOverlay creates StateOverlay on top of the root, which is the current root if it is not given.
+    // The nodes of the root must be in the state_db, so the root of the past height requires keepRootHistory.
+    async newOverlay(root) {
+        const base = root === undefined ? (await this.getCurrentState()).root : root;
+        return new StateOverlay(this, base);
+    }
+
+    // proveStoreRoot returns the sub-root of the store with the proof linking it to the state root at the height,
+    // which is the current height if it is not given. The state root of the past height requires keepRootHistory.
+    async proveStoreRoot(storePrefix, height) {
+        const current = await this.getCurrentState();
+        const stateRoot = height === undefined || height === current.version ? current.root : await this.getRootAt(height);
+        const proof = await this.provePrefix(stateRoot, storePrefix);
+        return {
+            stateRoot,
+            storeRoot: proof.subtreeRoot,
+            proof,
+        };
+    }
+
+    // generateWitness returns the encoded witness of the state keys read by the block executed on the root, which holds
+    // the values and the inclusion or non-inclusion proof of the keys. The values are read from the current state.
+    async generateWitness(root, keys) {
+        return new Promise((resolve, reject) => {
+            state_db_generate_witness.call(this._db, root, keys, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async verifyPrefix(root, proof) {
+        return new Promise((resolve, reject) => {
+            state_db_verify_prefix.call(this._db, root, proof, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // toVisualization returns all the nodes of the state tree in pre-order as JSON serializable objects or CSV
+    async toVisualization(root, options = {}) {
+        const nodes = await new Promise((resolve, reject) => {
+            state_db_to_visualization.call(this._db, root, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+        return formatVisualization(nodes, options.format);
+    }
+
     async finalize(height) {
         return new Promise((resolve, reject) => {
             state_db_clean_diff_until.call(this._db, height, (err) => {
@@ -345,16 +912,114 @@ class StateDB {
         });
     }
 
+    // pruneDiffs removes the diffs below untilHeight, so the state can no longer be reverted below it
+    async pruneDiffs(untilHeight) {
+        return this.finalize(untilHeight);
+    }
+
+    // getFinalizedState returns the height and the root recorded by finalize when rollbackProtection is enabled
+    async getFinalizedState() {
+        return new Promise((resolve, reject) => {
+            state_db_get_finalized_state.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // getLifetimeStats returns the number of commits, the keys and the bytes written since the database was created
+    async getLifetimeStats() {
+        return new Promise((resolve, reject) => {
+            state_db_get_lifetime_stats.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // debugReport returns the information to attach to bug reports, including the statistics of each prefix.
+    // It scans the whole database, so it can take a while for a large state.
+    async debugReport() {
+        const report = await new Promise((resolve, reject) => {
+            state_db_debug_report.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+        return {
+            ...report,
+            environment: {
+                node: process.version,
+                platform: process.platform,
+                arch: process.arch,
+            },
+        };
+    }
+
+    async getAuditRecords() {
+        return new Promise((resolve, reject) => {
+            state_db_get_audit_records.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async verifyAuditTrail() {
+        return new Promise((resolve, reject) => {
+            state_db_verify_audit_trail.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
     newReader() {
-        return new StateReader(this._db);
+        return new StateReader(this._db, this._tracker);
     }
 
-    newReadWriter() {
-        return new StateReadWriter(this._db);
+    newReadWriter(options = {}) {
+        return new StateReadWriter(this._db, this._tracker, options);
     }
 
-    close() {
-        state_db_close.call(this._db);
+    // newWriter returns StateWriter which reads the values not cached from this database
+    newWriter(options = {}) {
+        return new StateWriter({ ...options, db: this });
+    }
+
+    getOpenResources() {
+        return this._tracker.openResources();
+    }
+
+    getLeakedResources() {
+        return this._tracker.leakedResources();
+    }
+
+    // close flushes the memtables and releases the database after the pending operations, and stops the watchers.
+    // The callback is called once the same path can be opened again.
+    close(cb) {
+        if (this._closed) {
+            if (cb) {
+                process.nextTick(cb, null);
+            }
+            return;
+        }
+        this._closed = true;
+        for (const stop of this._watchers) {
+            stop();
+        }
+        this._watchers.clear();
+        state_db_close.call(this._handle, cb);
     }
 
     async checkpoint(path) {
@@ -368,6 +1033,129 @@ class StateDB {
         });
     }
 
+    async createBackup(dir) {
+        return new Promise((resolve, reject) => {
+            state_db_create_backup.call(this._db, dir, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async compactRange(start, end) {
+        return new Promise((resolve, reject) => {
+            state_db_compact_range.call(this._db, start || null, end || null, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async compactSMT() {
+        return new Promise((resolve, reject) => {
+            state_db_compact_smt.call(this._db, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    setAutoCompaction(enabled) {
+        state_db_set_auto_compaction.call(this._db, enabled);
+    }
+
+    async flush() {
+        return new Promise((resolve, reject) => {
+            state_db_flush.call(this._db, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async syncWal() {
+        return new Promise((resolve, reject) => {
+            state_db_sync_wal.call(this._db, err => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve();
+            });
+        });
+    }
+
+    async getProperties() {
+        return new Promise((resolve, reject) => {
+            state_db_properties.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async getWriteStall() {
+        return new Promise((resolve, reject) => {
+            state_db_write_stall.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // onWriteStall calls the listener when the writes are delayed or stopped by the background flush and compaction,
+    // and when they are back to normal
+    onWriteStall(listener, interval = DEFAULT_WRITE_STALL_INTERVAL) {
+        return this._watch(watchWriteStall(() => this.getWriteStall(), listener, interval));
+    }
+
+    async approximateSize(start, end) {
+        return new Promise((resolve, reject) => {
+            state_db_approximate_size.call(this._db, start || null, end || null, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    async tryCatchUpWithPrimary() {
+        return new Promise((resolve, reject) => {
+            state_db_try_catch_up_with_primary.call(this._db, (err, result) => {
+                if (err) {
+                    return reject(err);
+                }
+                resolve(result);
+            });
+        });
+    }
+
+    // followPrimary calls the listener with the latest sequence number whenever the secondary catches up with new data
+    followPrimary(listener, interval = DEFAULT_FOLLOW_INTERVAL) {
+        return this._watch(followPrimary(() => this.tryCatchUpWithPrimary(), listener, interval));
+    }
+
+    // _watch keeps the stop function of the watcher to stop it on close
+    _watch(stop) {
+        this._watchers.add(stop);
+        return () => {
+            this._watchers.delete(stop);
+            stop();
+        };
+    }
+
     async calculateRoot(proof) {
         return new Promise((resolve, _reject) => {
             state_db_calculate_root.call(this._db, proof, (_err, result) => {
@@ -377,8 +1165,19 @@ class StateDB {
     }
 }
 
+// InMemoryStateDB is the StateDB which keeps its data in memory instead of the filesystem, and drops it on close.
+// It has the same API as StateDB, so that the tests can use it without creating the files.
+class InMemoryStateDB extends StateDB {
+    constructor(opts = {}) {
+        super(IN_MEMORY_PATH, { ...opts, secondaryPath: undefined, readonly: false, inMemory: true });
+    }
+}
+
 module.exports = {
     StateDB,
+    InMemoryStateDB,
+    StateOverlay,
+    StateWriter,
     StateReadWriter,
     StateReader,
 };
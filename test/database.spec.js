@@ -16,7 +16,18 @@
 const os = require('os');
 const path = require('path');
 const fs = require('fs');
-const { Database, Batch, NotFoundError, InMemoryDatabase } = require('../main');
+const {
+    Database,
+    Batch,
+    ErrorCode,
+    NotFoundError,
+    MaxOpenResourcesError,
+    DatabaseClosedError,
+    AlreadyLockedError,
+    BatchConflictError,
+    AbortError,
+    InMemoryDatabase,
+} = require('../main');
 const { getRandomBytes } = require('./utils');
 
 describe('database', () => {
@@ -45,6 +56,94 @@ describe('database', () => {
             await expect(reopenDB.get(key)).resolves.toEqual(value);
         });
 
+        it('should throw AlreadyLockedError when the database is already opened', () => {
+            const lockedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_locked`);
+            fs.mkdirSync(lockedPath, { recursive: true });
+            const lockedDB = new Database(lockedPath);
+            try {
+                let error;
+                try {
+                    new Database(lockedPath, { lockTimeout: 200 });
+                } catch (err) {
+                    error = err;
+                }
+                expect(error).toBeInstanceOf(AlreadyLockedError);
+                expect(error.path).toEqual(lockedPath);
+                if (process.platform === 'linux') {
+                    expect(error.pid).toEqual(process.pid);
+                }
+            } finally {
+                lockedDB.close();
+            }
+        });
+
+        it('should open with lockTimeout when the database is not locked', async () => {
+            const lockedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_wait`);
+            fs.mkdirSync(lockedPath, { recursive: true });
+            const lockedDB = new Database(lockedPath);
+            lockedDB.close();
+
+            const reopenDB = new Database(lockedPath, { lockTimeout: 1000 });
+            await expect(reopenDB.has(getRandomBytes())).resolves.toEqual(false);
+            reopenDB.close();
+        });
+
+        it('should release the database when close calls back', async () => {
+            const closedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_closed`);
+            fs.mkdirSync(closedPath, { recursive: true });
+            const closedDB = new Database(closedPath);
+            const key = getRandomBytes();
+            const value = getRandomBytes();
+            const pending = closedDB.set(key, value);
+            await new Promise((resolve, reject) => closedDB.close(err => (err ? reject(err) : resolve())));
+            await expect(pending).resolves.toBeUndefined();
+
+            const reopenDB = new Database(closedPath);
+            await expect(reopenDB.get(key)).resolves.toEqual(value);
+            reopenDB.close();
+        });
+
+        it('should throw DatabaseClosedError on the operations after close', async () => {
+            const closedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_after_close`);
+            fs.mkdirSync(closedPath, { recursive: true });
+            const closedDB = new Database(closedPath);
+            closedDB.close();
+
+            await expect(closedDB.get(getRandomBytes())).rejects.toThrow(DatabaseClosedError);
+            await expect(closedDB.set(getRandomBytes(), getRandomBytes())).rejects.toHaveProperty('code', 'ERR_CLOSED');
+            expect(() => closedDB.iterate()).toThrow(DatabaseClosedError);
+            await expect(new Promise(resolve => closedDB.close(resolve))).resolves.toBeNull();
+        });
+
+        it('should open with the RocksDB tuning options', async () => {
+            const tunedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_tuned`);
+            fs.mkdirSync(tunedPath, { recursive: true });
+            const tunedDB = new Database(tunedPath, {
+                blockCacheSize: 8 * 1024 * 1024,
+                compression: 'zstd',
+                maxOpenFiles: 64,
+                writeBufferSize: 4 * 1024 * 1024,
+                rateLimit: 16 * 1024 * 1024,
+                parallelism: 4,
+                maxBackgroundCompactions: 3,
+                maxBackgroundFlushes: 2,
+                cacheIndexAndFilterBlocks: true,
+                pinL0FilterAndIndexBlocks: true,
+            });
+            const key = getRandomBytes();
+            const value = getRandomBytes();
+            await tunedDB.set(key, value);
+            await expect(tunedDB.get(key)).resolves.toEqual(value);
+            await expect(tunedDB.getWriteStall()).resolves.toEqual({ condition: 'normal', delayedWriteRate: 0 });
+            tunedDB.close();
+        });
+
+        it('should throw when the compression is unknown', () => {
+            const tunedPath = path.join(os.tmpdir(), 'db', `${Date.now().toString()}_invalid`);
+            fs.mkdirSync(tunedPath, { recursive: true });
+            expect(() => new Database(tunedPath, { compression: 'snappy' })).toThrow('Invalid compression');
+        });
+
         it('should open DB', () => {
             expect(db).not.toBeUndefined();
         });
@@ -66,6 +165,10 @@ describe('database', () => {
             await expect(db.get(getRandomBytes())).rejects.toThrow(NotFoundError);
         });
 
+        it('should set the not found code on the error', async () => {
+            await expect(db.get(getRandomBytes())).rejects.toHaveProperty('code', ErrorCode.NOT_FOUND);
+        });
+
         it('should get the value if exist', async () => {
             const kv = { key: getRandomBytes(), value: getRandomBytes() };
             const batch = new Batch();
@@ -75,6 +178,54 @@ describe('database', () => {
             await expect(db.get(kv.key)).resolves.toEqual(kv.value);
         });
 
+        it('should write with the durability options and flush', async () => {
+            const synced = { key: getRandomBytes(), value: getRandomBytes() };
+            const syncBatch = new Batch();
+            syncBatch.set(synced.key, synced.value);
+            await db.write(syncBatch, { sync: true });
+
+            const unlogged = { key: getRandomBytes(), value: getRandomBytes() };
+            const unloggedBatch = new Batch();
+            unloggedBatch.set(unlogged.key, unlogged.value);
+            await db.write(unloggedBatch, { disableWAL: true });
+            await db.flush();
+            await db.syncWal();
+
+            await expect(db.get(synced.key)).resolves.toEqual(synced.value);
+            await expect(db.get(unlogged.key)).resolves.toEqual(unlogged.value);
+        });
+
+        it('should keep the values after compacting the range', async () => {
+            const kv = { key: getRandomBytes(), value: getRandomBytes() };
+            await db.set(kv.key, kv.value);
+
+            db.setAutoCompaction(false);
+            await db.compactRange(kv.key, kv.key);
+            await db.compactRange();
+            db.setAutoCompaction(true);
+
+            await expect(db.get(kv.key)).resolves.toEqual(kv.value);
+        });
+
+        it('should get many values with undefined for the missing keys', async () => {
+            const pairs = [
+                { key: getRandomBytes(), value: getRandomBytes() },
+                { key: getRandomBytes(), value: getRandomBytes() },
+            ];
+            const batch = new Batch();
+            for (const pair of pairs) {
+                batch.set(pair.key, pair.value);
+            }
+            await db.write(batch);
+
+            await expect(db.getMany([pairs[1].key, getRandomBytes(), pairs[0].key])).resolves.toEqual([
+                pairs[1].value,
+                undefined,
+                pairs[0].value,
+            ]);
+            await expect(db.getMany([])).resolves.toEqual([]);
+        });
+
         it('should delete value', async () => {
             const kv = { key: getRandomBytes(), value: getRandomBytes() };
             const batch = new Batch();
@@ -90,6 +241,98 @@ describe('database', () => {
 
         });
 
+        it('should write the batch reconstructed from the encoded bytes', async () => {
+            const pairs = [
+                { key: getRandomBytes(), value: getRandomBytes() },
+                { key: getRandomBytes(), value: getRandomBytes() },
+            ];
+            const batch = new Batch();
+            for (const kv of pairs) {
+                batch.set(kv.key, kv.value);
+            }
+            batch.del(pairs[1].key);
+
+            const decoded = Batch.decode(batch.encode());
+            expect(decoded.encode()).toEqual(batch.encode());
+            await db.write(decoded);
+
+            await expect(db.get(pairs[0].key)).resolves.toEqual(pairs[0].value);
+            await expect(db.has(pairs[1].key)).resolves.toEqual(false);
+        });
+
+        it('should replay the decoded batch with its conditions', async () => {
+            const kv = { key: getRandomBytes(), value: getRandomBytes() };
+            const batch = new Batch();
+            batch.setIfAbsent(kv.key, kv.value);
+            const encoded = batch.encode();
+
+            await db.write(Batch.decode(encoded));
+            await expect(db.get(kv.key)).resolves.toEqual(kv.value);
+            // replaying the same batch again conflicts, because the key exists now
+            await expect(db.write(Batch.decode(encoded))).rejects.toThrow(BatchConflictError);
+        });
+
+        it('should report the operations and the size of the batch', () => {
+            const kv = { key: getRandomBytes(), value: getRandomBytes() };
+            const batch = new Batch();
+            const emptySize = batch.size;
+            expect(batch.length).toEqual(0);
+            expect(batch.operations()).toEqual([]);
+
+            batch.set(kv.key, kv.value);
+            batch.del(kv.key);
+
+            expect(batch.length).toEqual(2);
+            expect(batch.size).toBeGreaterThan(emptySize + kv.key.length * 2 + kv.value.length);
+            expect(batch.operations()).toEqual([
+                { type: 'put', key: kv.key, value: kv.value },
+                { type: 'del', key: kv.key },
+            ]);
+        });
+
+        it('should write the conditional operations when the conditions are satisfied', async () => {
+            const existing = { key: getRandomBytes(), value: getRandomBytes() };
+            const added = { key: getRandomBytes(), value: getRandomBytes() };
+            await db.set(existing.key, existing.value);
+
+            const batch = new Batch();
+            batch.setIfAbsent(added.key, added.value);
+            batch.delIfEquals(existing.key, existing.value);
+            await db.write(batch);
+
+            await expect(db.get(added.key)).resolves.toEqual(added.value);
+            await expect(db.has(existing.key)).resolves.toEqual(false);
+        });
+
+        it('should not write any of the batch when a condition is not satisfied', async () => {
+            const existing = { key: getRandomBytes(), value: getRandomBytes() };
+            const added = { key: getRandomBytes(), value: getRandomBytes() };
+            await db.set(existing.key, existing.value);
+
+            const batch = new Batch();
+            batch.set(added.key, added.value);
+            batch.setIfAbsent(existing.key, getRandomBytes());
+            const result = db.write(batch);
+
+            await expect(result).rejects.toThrow(BatchConflictError);
+            await expect(result).rejects.toHaveProperty('key', existing.key);
+            await expect(db.has(added.key)).resolves.toEqual(false);
+            await expect(db.get(existing.key)).resolves.toEqual(existing.value);
+
+            const deletion = new Batch();
+            deletion.delIfEquals(existing.key, getRandomBytes());
+            await expect(db.write(deletion)).rejects.toThrow(BatchConflictError);
+            await expect(db.get(existing.key)).resolves.toEqual(existing.value);
+        });
+
+        it('should throw an error when decoding invalid bytes', () => {
+            const batch = new Batch();
+            batch.set(getRandomBytes(), getRandomBytes());
+            const encoded = batch.encode();
+
+            expect(() => Batch.decode(encoded.slice(0, encoded.length - 1))).toThrow();
+        });
+
         it('should clear all value', async () => {
             const pairs = [
                 { key: getRandomBytes(), value: getRandomBytes() },
@@ -236,6 +479,227 @@ describe('database', () => {
 
                 expect(values).toEqual([]);
             });
+
+            it('should iterate over multiple pages with for await', async () => {
+                const values = [];
+                for await (const kv of db.iterate({ gte: Buffer.from([0, 0, 1]), pageSize: 1 })) {
+                    values.push(kv);
+                }
+
+                expect(values).toEqual(pairs.slice(1));
+            });
+
+            it('should respect the limit across pages in reverse order', async () => {
+                const values = [];
+                for await (const kv of db.iterate({ limit: 3, reverse: true, pageSize: 2 })) {
+                    values.push(kv);
+                }
+
+                expect(values).toEqual(pairs.slice(1).reverse());
+            });
+
+            it('should stop with AbortError when the signal is aborted', async () => {
+                const controller = new AbortController();
+                const values = [];
+                const iterate = async () => {
+                    for await (const kv of db.iterate({ pageSize: 1, signal: controller.signal })) {
+                        values.push(kv);
+                        controller.abort();
+                    }
+                };
+
+                await expect(iterate()).rejects.toThrow(AbortError);
+                expect(values).toHaveLength(1);
+            });
+        });
+
+        describe('getStream', () => {
+            const chunkSize = 64 * 1024;
+            const key = Buffer.from([8, 8, 0]);
+            const value = getRandomBytes(1024 * 1024 + 100);
+
+            beforeAll(async () => {
+                await db.set(key, value);
+            });
+
+            it('should call onChunk with the value in the chunks of chunkSize', async () => {
+                const chunks = [];
+                await expect(db.getStream(key, chunk => chunks.push(chunk), { chunkSize })).resolves.toEqual(
+                    value.length,
+                );
+
+                expect(Buffer.concat(chunks)).toEqual(value);
+                expect(chunks).toHaveLength(Math.ceil(value.length / chunkSize));
+                for (const chunk of chunks.slice(0, -1)) {
+                    expect(chunk).toHaveLength(chunkSize);
+                }
+                expect(chunks[chunks.length - 1]).toHaveLength(100);
+            });
+
+            it('should stop when onChunk returns false', async () => {
+                const chunks = [];
+                await db.getStream(
+                    key,
+                    chunk => {
+                        chunks.push(chunk);
+                        return chunks.length < 2;
+                    },
+                    { chunkSize },
+                );
+
+                expect(chunks).toHaveLength(2);
+                expect(Buffer.concat(chunks)).toEqual(value.subarray(0, 2 * chunkSize));
+            });
+
+            it('should apply the prefix to the key', async () => {
+                const chunks = [];
+                await db.withPrefix(Buffer.from([8])).getStream(Buffer.from([8, 0]), chunk => chunks.push(chunk));
+
+                expect(Buffer.concat(chunks)).toEqual(value);
+            });
+
+            it('should reject with the error thrown by onChunk', async () => {
+                const onChunk = () => {
+                    throw new Error('invalid chunk');
+                };
+
+                await expect(db.getStream(key, onChunk)).rejects.toThrow('invalid chunk');
+            });
+
+            it('should reject with NotFoundError if the key does not exist', async () => {
+                await expect(db.getStream(Buffer.from([8, 8, 1]), () => true)).rejects.toThrow(NotFoundError);
+            });
+
+            it('should reject with RangeError if chunkSize is not positive', async () => {
+                await expect(db.getStream(key, () => true, { chunkSize: 0 })).rejects.toThrow(RangeError);
+            });
+        });
+
+        describe('withPrefix', () => {
+            const prefix = Buffer.from([9, 9]);
+            let prefixed;
+
+            beforeAll(async () => {
+                prefixed = db.withPrefix(prefix);
+                await db.set(Buffer.from([9, 8, 255]), getRandomBytes());
+                await db.set(Buffer.from([9, 10]), getRandomBytes());
+            });
+
+            it('should apply the prefix to the keys', async () => {
+                const key = Buffer.from([1, 2]);
+                const value = getRandomBytes();
+                await prefixed.set(key, value);
+
+                await expect(db.get(Buffer.from([9, 9, 1, 2]))).resolves.toEqual(value);
+                await expect(prefixed.get(key)).resolves.toEqual(value);
+                await expect(prefixed.has(key)).resolves.toEqual(true);
+                await expect(prefixed.getMany([key, Buffer.from([3])])).resolves.toEqual([value, undefined]);
+                await expect(prefixed.withPrefix(Buffer.from([1])).get(Buffer.from([2]))).resolves.toEqual(value);
+
+                await prefixed.del(key);
+                await expect(db.has(Buffer.from([9, 9, 1, 2]))).resolves.toEqual(false);
+            });
+
+            it('should iterate only the keys with the prefix without the prefix', async () => {
+                const pairs = [
+                    { key: Buffer.from([0]), value: getRandomBytes() },
+                    { key: Buffer.from([1, 0]), value: getRandomBytes() },
+                    { key: Buffer.from([255]), value: getRandomBytes() },
+                ];
+                for (const pair of pairs) {
+                    await prefixed.set(pair.key, pair.value);
+                }
+
+                const values = [];
+                for await (const kv of prefixed.iterate()) {
+                    values.push(kv);
+                }
+                expect(values).toEqual(pairs);
+
+                const reversed = [];
+                for await (const kv of prefixed.iterate({ reverse: true, limit: 2, lte: Buffer.from([1, 0]) })) {
+                    reversed.push(kv);
+                }
+                expect(reversed).toEqual([pairs[1], pairs[0]]);
+            });
+        });
+
+        describe('newTransaction', () => {
+            const prefix = Buffer.from([7, 7]);
+            const key = suffix => Buffer.concat([prefix, Buffer.from(suffix)]);
+
+            beforeAll(async () => {
+                await db.set(key([0]), Buffer.from([0]));
+                await db.set(key([1]), Buffer.from([1]));
+                await db.set(key([2]), Buffer.from([2]));
+            });
+
+            it('should read the writes of the transaction over the database', async () => {
+                const transaction = db.newTransaction();
+                transaction.set(key([1]), Buffer.from([10]));
+                transaction.del(key([2]));
+                transaction.set(key([3]), Buffer.from([3]));
+
+                await expect(transaction.get(key([0]))).resolves.toEqual(Buffer.from([0]));
+                await expect(transaction.get(key([1]))).resolves.toEqual(Buffer.from([10]));
+                await expect(transaction.get(key([2]))).rejects.toThrow(NotFoundError);
+                await expect(transaction.has(key([3]))).resolves.toEqual(true);
+                await expect(db.get(key([1]))).resolves.toEqual(Buffer.from([1]));
+                await expect(db.has(key([3]))).resolves.toEqual(false);
+
+                const values = [];
+                for await (const kv of transaction.iterate({ gte: key([0]), lte: key([255]) })) {
+                    values.push(kv);
+                }
+                expect(values).toEqual([
+                    { key: key([0]), value: Buffer.from([0]) },
+                    { key: key([1]), value: Buffer.from([10]) },
+                    { key: key([3]), value: Buffer.from([3]) },
+                ]);
+
+                const reversed = [];
+                for await (const kv of transaction.iterate({ gte: key([0]), lte: key([255]), reverse: true, limit: 2 })) {
+                    reversed.push(kv.key);
+                }
+                expect(reversed).toEqual([key([3]), key([1])]);
+            });
+
+            it('should write all the changes on commit', async () => {
+                const transaction = db.newTransaction();
+                transaction.set(key([0]), Buffer.from([20]));
+                transaction.del(key([1]));
+
+                await transaction.commit();
+
+                await expect(db.get(key([0]))).resolves.toEqual(Buffer.from([20]));
+                await expect(db.has(key([1]))).resolves.toEqual(false);
+            });
+        });
+
+        describe('snapshot', () => {
+            it('should not see the writes after the snapshot is created', async () => {
+                const prefix = getRandomBytes(4);
+                const existing = { key: Buffer.concat([prefix, Buffer.from([1])]), value: getRandomBytes() };
+                await db.set(existing.key, existing.value);
+
+                const snapshot = db.snapshot();
+                const added = Buffer.concat([prefix, Buffer.from([2])]);
+                await db.set(added, getRandomBytes());
+                await db.set(existing.key, getRandomBytes());
+
+                await expect(snapshot.get(existing.key)).resolves.toEqual(existing.value);
+                await expect(snapshot.get(added)).rejects.toThrow(NotFoundError);
+                await expect(snapshot.has(added)).resolves.toEqual(false);
+                const values = [];
+                for await (const kv of snapshot.iterate({ gte: prefix, lte: Buffer.concat([prefix, Buffer.from([255])]) })) {
+                    values.push(kv);
+                }
+                expect(values).toEqual([existing]);
+
+                expect(db.getOpenResources().map(resource => resource.kind)).toContain('snapshot');
+                snapshot.release();
+                expect(db.getOpenResources().map(resource => resource.kind)).not.toContain('snapshot');
+            });
         });
 
         describe('DatabaseReader', () => {
@@ -486,6 +950,41 @@ describe('database', () => {
                 await expect(db.checkpoint(tmpPath)).rejects.toThrow();
             });
         });
+        describe('open resources', () => {
+            const drain = async stream => new Promise((resolve, reject) => {
+                stream
+                    .on('data', () => {})
+                    .on('error', reject)
+                    .on('close', resolve);
+            });
+
+            it('should throw an error when the maximum number of open iterators is reached', async () => {
+                const dbPath = path.join(os.tmpdir(), 'db', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                const limitedDB = new Database(dbPath, { maxOpenIterators: 1 });
+                const stream = limitedDB.iterate();
+                expect(() => limitedDB.iterate()).toThrow(MaxOpenResourcesError);
+                await drain(stream);
+                expect(limitedDB.getOpenResources()).toHaveLength(0);
+                await drain(limitedDB.iterate());
+                limitedDB.close();
+            });
+
+            it('should return open iterators with creation stack in debug mode', async () => {
+                const dbPath = path.join(os.tmpdir(), 'db', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                const debugDB = new Database(dbPath, { debug: true });
+                const stream = debugDB.newReader().iterate();
+                const resources = debugDB.getOpenResources();
+                expect(resources).toHaveLength(1);
+                expect(resources[0].kind).toEqual('iterator');
+                expect(resources[0].stack).toContain('database.spec.js');
+                await drain(stream);
+                expect(debugDB.getOpenResources()).toHaveLength(0);
+                expect(debugDB.getLeakedResources()).toHaveLength(0);
+                debugDB.close();
+            });
+        });
     });
 
     describe('InMemoryDatabase', () => {
@@ -516,6 +1015,10 @@ describe('database', () => {
             await expect(db.get(getRandomBytes())).rejects.toThrow(NotFoundError);
         });
 
+        it('should set the not found code on the error', async () => {
+            await expect(db.get(getRandomBytes())).rejects.toHaveProperty('code', ErrorCode.NOT_FOUND);
+        });
+
         it('should get the value if exist', async () => {
             const kv = { key: getRandomBytes(), value: getRandomBytes() };
             const batch = new Batch();
@@ -525,6 +1028,25 @@ describe('database', () => {
             await expect(db.get(kv.key)).resolves.toEqual(kv.value);
         });
 
+        it('should not write any of the batch when a condition is not satisfied', async () => {
+            const existing = { key: getRandomBytes(), value: getRandomBytes() };
+            const added = { key: getRandomBytes(), value: getRandomBytes() };
+            await db.set(existing.key, existing.value);
+
+            const batch = new Batch();
+            batch.set(added.key, added.value);
+            batch.delIfEquals(existing.key, getRandomBytes());
+            await expect(db.write(batch)).rejects.toThrow(BatchConflictError);
+            await expect(db.has(added.key)).resolves.toEqual(false);
+
+            const retry = new Batch();
+            retry.set(added.key, added.value);
+            retry.delIfEquals(existing.key, existing.value);
+            await db.write(retry);
+            await expect(db.get(added.key)).resolves.toEqual(added.value);
+            await expect(db.has(existing.key)).resolves.toEqual(false);
+        });
+
         it('should clone the data', async () => {
             const kv = { key: getRandomBytes(), value: getRandomBytes() };
             const batch = new Batch();
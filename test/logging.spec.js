@@ -0,0 +1,51 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { SparseMerkleTree, configureLogging } = require('../main');
+const { getRandomBytes } = require('./utils');
+
+describe('logging', () => {
+    afterEach(() => {
+        configureLogging({ level: 'off' });
+    });
+
+    it('should call onLog with the spans of the commit', async () => {
+        const records = [];
+        configureLogging({ level: 'debug', onLog: record => records.push(record) });
+        const smt = new SparseMerkleTree(32);
+        await smt.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+        await new Promise(resolve => setImmediate(resolve));
+
+        const commit = records.find(record => record.spans.startsWith('smt_commit'));
+        expect(commit).toBeDefined();
+        expect(commit.level).toEqual('debug');
+        expect(commit.message).toMatch(/^closed in/);
+        expect(commit.timestamp).toBeGreaterThan(0);
+    });
+
+    it('should not call onLog when the level is off', async () => {
+        const records = [];
+        configureLogging({ level: 'off', onLog: record => records.push(record) });
+        const smt = new SparseMerkleTree(32);
+        await smt.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+        await new Promise(resolve => setImmediate(resolve));
+
+        expect(records).toHaveLength(0);
+    });
+
+    it('should reject an unknown level', () => {
+        expect(() => configureLogging({ level: 'verbose' })).toThrow('Invalid log level');
+    });
+});
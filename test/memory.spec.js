@@ -0,0 +1,75 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { Batch, InMemoryDatabase, getMemoryUsage, setMemoryBudget } = require('../main');
+const { getRandomBytes } = require('./utils');
+
+describe('memory', () => {
+    afterEach(() => {
+        setMemoryBudget(null);
+    });
+
+    describe('getMemoryUsage', () => {
+        it('should not have a budget by default', () => {
+            expect(getMemoryUsage().budget).toBeNull();
+        });
+
+        it('should account the bytes added to a batch', () => {
+            const before = getMemoryUsage();
+            const batch = new Batch();
+            batch.set(getRandomBytes(), getRandomBytes(100));
+            const after = getMemoryUsage();
+
+            expect(after.components.writeBatch).toBeGreaterThan(before.components.writeBatch + 132);
+            expect(after.total - before.total).toEqual(after.components.writeBatch - before.components.writeBatch);
+        });
+
+        it('should account and release the bytes of in memory database', async () => {
+            const db = new InMemoryDatabase();
+            const before = getMemoryUsage().components.inMemoryDatabase;
+            const key = getRandomBytes();
+            await db.set(key, getRandomBytes(68));
+            expect(getMemoryUsage().components.inMemoryDatabase).toEqual(before + 100);
+
+            await db.del(key);
+            expect(getMemoryUsage().components.inMemoryDatabase).toEqual(before);
+        });
+    });
+
+    describe('setMemoryBudget', () => {
+        it('should reject the operation exceeding the budget', async () => {
+            const { total } = getMemoryUsage();
+            setMemoryBudget(total + 50);
+            expect(getMemoryUsage().budget).toEqual(total + 50);
+
+            const batch = new Batch();
+            expect(() => batch.set(getRandomBytes(), getRandomBytes(100))).toThrow('Memory budget');
+
+            const db = new InMemoryDatabase();
+            await expect(db.set(getRandomBytes(), getRandomBytes(100))).rejects.toThrow('Memory budget');
+        });
+
+        it('should allow the operation after the budget is removed', () => {
+            setMemoryBudget(0);
+            setMemoryBudget(null);
+            const batch = new Batch();
+            expect(() => batch.set(getRandomBytes(), getRandomBytes(100))).not.toThrow();
+        });
+
+        it('should reject a negative budget', () => {
+            expect(() => setMemoryBudget(-1)).toThrow();
+        });
+    });
+});
@@ -0,0 +1,84 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { SparseMerkleTree, getStats, setStatsEnabled, resetStats } = require('../main');
+const { getRandomBytes } = require('./utils');
+
+describe('metrics', () => {
+    afterEach(() => {
+        setStatsEnabled(false);
+        resetStats();
+    });
+
+    it('should not record stats by default', async () => {
+        const smt = new SparseMerkleTree(32);
+        await smt.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+
+        const stats = getStats();
+        expect(stats.enabled).toBe(false);
+        expect(stats.counters.nodesHashed).toEqual(0);
+        expect(stats.histograms.commitLatency.count).toEqual(0);
+    });
+
+    it('should record counters and latencies of commit and prove', async () => {
+        setStatsEnabled(true);
+        const smt = new SparseMerkleTree(32);
+        const kvpairs = [];
+        for (let i = 0; i < 20; i += 1) {
+            kvpairs.push({ key: getRandomBytes(32), value: getRandomBytes(32) });
+        }
+        const root = await smt.update(Buffer.alloc(0), kvpairs);
+        await smt.prove(root, [kvpairs[0].key, getRandomBytes(32)]);
+
+        const stats = getStats();
+        expect(stats.enabled).toBe(true);
+        expect(stats.counters.nodesHashed).toBeGreaterThan(20);
+        expect(stats.counters.dbGets).toBeGreaterThan(0);
+        expect(stats.counters.subtreeCacheHits).toBeGreaterThan(0);
+        expect(stats.histograms.commitLatency.count).toEqual(1);
+        expect(stats.histograms.proveLatency.count).toEqual(1);
+        const { buckets } = stats.histograms.proveLatency;
+        expect(buckets[buckets.length - 1].le).toBeNull();
+        expect(buckets.reduce((sum, bucket) => sum + bucket.count, 0)).toEqual(1);
+    });
+
+    it('should tag the stats by the tree', async () => {
+        setStatsEnabled(true);
+        const first = new SparseMerkleTree(32);
+        const second = new SparseMerkleTree(32);
+        const root = await first.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+        await first.prove(root, [getRandomBytes(32)]);
+
+        const stats = first.getStats();
+        expect(stats.counters.nodesHashed).toBeGreaterThan(0);
+        expect(stats.histograms.commitLatency.count).toEqual(1);
+        expect(stats.histograms.proveLatency.count).toEqual(1);
+        expect(second.getStats().counters.nodesHashed).toEqual(0);
+        expect(second.getStats().histograms.commitLatency.count).toEqual(0);
+        expect(getStats().histograms.commitLatency.count).toEqual(1);
+    });
+
+    it('should clear the stats on reset', async () => {
+        setStatsEnabled(true);
+        const smt = new SparseMerkleTree(32);
+        await smt.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+        resetStats();
+
+        const stats = getStats();
+        expect(stats.counters.nodesHashed).toEqual(0);
+        expect(stats.histograms.commitLatency.count).toEqual(0);
+        expect(stats.histograms.commitLatency.max).toEqual(0);
+    });
+});
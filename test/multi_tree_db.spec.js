@@ -0,0 +1,122 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const os = require('os');
+const path = require('path');
+const fs = require('fs');
+const { MultiTreeDB, SparseMerkleTree } = require('../main');
+const { getRandomBytes } = require('./utils');
+
+describe('multiTreeDB', () => {
+    const keyLength = 32;
+    const createPairs = () => [...new Array(10)].map(() => ({
+        key: getRandomBytes(keyLength),
+        value: getRandomBytes(),
+    }));
+
+    let dbPath;
+    let db;
+
+    beforeEach(() => {
+        dbPath = path.join(os.tmpdir(), 'multi_tree', Date.now().toString());
+        fs.mkdirSync(dbPath, { recursive: true });
+        db = new MultiTreeDB(dbPath, { keyLength });
+    });
+
+    afterEach(() => {
+        db.close();
+    });
+
+    describe('getRoot', () => {
+        it('should return empty hash for a store which was never updated', async () => {
+            const emptyHash = Buffer.from('e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855', 'hex');
+            await expect(db.getRoot('state')).resolves.toEqual(emptyHash);
+        });
+
+        it('should reject an empty store name', async () => {
+            await expect(db.getRoot('')).rejects.toThrow();
+        });
+    });
+
+    describe('commit', () => {
+        it('should return the same root as SparseMerkleTree', async () => {
+            const pairs = createPairs();
+            const root = await db.commit('state', pairs);
+            const smt = new SparseMerkleTree(keyLength);
+            const expected = await smt.update(Buffer.alloc(0), pairs);
+
+            expect(root).toEqual(expected);
+            await expect(db.getRoot('state')).resolves.toEqual(expected);
+        });
+
+        it('should keep the stores independent', async () => {
+            const statePairs = createPairs();
+            const stateRoot = await db.commit('state', statePairs);
+            const eventsRoot = await db.commit('events', createPairs());
+            expect(eventsRoot).not.toEqual(stateRoot);
+
+            await db.commit('events', createPairs());
+            await expect(db.getRoot('state')).resolves.toEqual(stateRoot);
+        });
+    });
+
+    describe('commitAll', () => {
+        it('should update all the stores', async () => {
+            const updates = {
+                state: createPairs(),
+                events: createPairs(),
+                assets: createPairs(),
+            };
+            const roots = await db.commitAll(updates);
+
+            expect(Object.keys(roots)).toHaveLength(3);
+            for (const name of Object.keys(updates)) {
+                await expect(db.getRoot(name)).resolves.toEqual(roots[name]);
+            }
+        });
+
+        it('should not update any store when one of the updates is invalid', async () => {
+            const stateRoot = await db.getRoot('state');
+            await expect(db.commitAll({
+                state: createPairs(),
+                events: [{ key: getRandomBytes(10), value: getRandomBytes() }],
+            })).rejects.toThrow();
+
+            await expect(db.getRoot('state')).resolves.toEqual(stateRoot);
+        });
+
+        it('should persist the roots after reopening', async () => {
+            const roots = await db.commitAll({ state: createPairs(), events: createPairs() });
+            db.close();
+            db = new MultiTreeDB(dbPath, { keyLength });
+
+            await expect(db.getRoot('state')).resolves.toEqual(roots.state);
+            await expect(db.getRoot('events')).resolves.toEqual(roots.events);
+        });
+    });
+
+    describe('prove', () => {
+        it('should generate a proof verifiable against the store root', async () => {
+            const statePairs = createPairs();
+            const roots = await db.commitAll({ state: statePairs, events: createPairs() });
+            const queries = [statePairs[0].key, getRandomBytes(keyLength)];
+
+            const proof = await db.prove('state', queries);
+
+            await expect(db.verify(roots.state, queries, proof)).resolves.toEqual(true);
+            await expect(db.verify(roots.events, queries, proof)).resolves.toEqual(false);
+        });
+    });
+});
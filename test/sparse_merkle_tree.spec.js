@@ -11,7 +11,11 @@
  *
  * Removal or modification of this copyright notice is prohibited.
  */
-const { SparseMerkleTree } = require('../main');
+const os = require('os');
+const path = require('path');
+const fs = require('fs');
+const { Readable } = require('stream');
+const { SparseMerkleTree, StateDB, Batch, AbortError } = require('../main');
 const { getRandomBytes } = require('./utils');
 const { isInclusionProofForQueryKey } = require('../utils');
 
@@ -46,6 +50,307 @@ describe('SparseMerkleTree', () => {
 		}
 	});
 
+	describe('encoded pairs', () => {
+		const pairs = [];
+		for (let i = 0; i < 300; i += 1) {
+			pairs.push({ key: getRandomBytes(32), value: getRandomBytes(i + 1) });
+		}
+
+		it('should calculate the same root as the array of the pairs', async () => {
+			const expected = await new SparseMerkleTree(32).update(Buffer.alloc(0), pairs);
+			const smt = new SparseMerkleTree(32);
+			await expect(smt.update(Buffer.alloc(0), SparseMerkleTree.encodePairs(pairs))).resolves.toEqual(expected);
+
+			const sorted = [...pairs].sort((a, b) => a.key.compare(b.key));
+			const loaded = new SparseMerkleTree(32);
+			await expect(loaded.bulkLoad(SparseMerkleTree.encodePairs(sorted))).resolves.toEqual(expected);
+		});
+
+		it('should throw when the encoded pairs are invalid', async () => {
+			const encoded = SparseMerkleTree.encodePairs(pairs.slice(0, 2));
+			const smt = new SparseMerkleTree(32);
+			await expect(smt.update(Buffer.alloc(0), encoded.subarray(0, encoded.length - 1))).rejects.toThrow();
+		});
+	});
+
+	describe('empty value', () => {
+		const emptyHash = Buffer.from('e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855', 'hex');
+		const pairs = [
+			{ key: getRandomBytes(32), value: getRandomBytes(32) },
+			{ key: getRandomBytes(32), value: Buffer.alloc(0) },
+		];
+
+		it('should delete the keys with an empty value by default', async () => {
+			const expected = await new SparseMerkleTree(32).update(Buffer.alloc(0), pairs.slice(0, 1));
+			const smt = new SparseMerkleTree(32);
+			await expect(smt.update(Buffer.alloc(0), pairs)).resolves.toEqual(expected);
+			await expect(smt.update(Buffer.alloc(0), pairs, { emptyValue: 'deleteOnEmpty' })).resolves.toEqual(expected);
+		});
+
+		it('should keep the keys with the empty hash by storeEmptyHash', async () => {
+			const expected = await new SparseMerkleTree(32).update(Buffer.alloc(0), [
+				pairs[0],
+				{ key: pairs[1].key, value: emptyHash },
+			]);
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), pairs, { emptyValue: 'storeEmptyHash' });
+			expect(root).toEqual(expected);
+			await expect(
+				smt.update(Buffer.alloc(0), pairs, { emptyValue: 'storeEmptyHash', onProgress: () => {} }),
+			).resolves.toEqual(expected);
+
+			const proof = await smt.prove(root, [pairs[1].key]);
+			expect(isInclusionProofForQueryKey(pairs[1].key, proof.queries[0])).toBe(true);
+			expect(proof.queries[0].value).toEqual(emptyHash);
+		});
+
+		it('should delete the keys with a null value by storeEmptyHash', async () => {
+			const expected = await new SparseMerkleTree(32).update(Buffer.alloc(0), [pairs[0]]);
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), pairs, { emptyValue: 'storeEmptyHash' });
+			await expect(
+				smt.update(root, [{ key: pairs[1].key, value: null }], { emptyValue: 'storeEmptyHash' }),
+			).resolves.toEqual(expected);
+		});
+
+		it('should reject an unknown emptyValue', async () => {
+			const smt = new SparseMerkleTree(32);
+			await expect(smt.update(Buffer.alloc(0), pairs, { emptyValue: 'keep' })).rejects.toHaveProperty(
+				'code',
+				'ERR_INVALID_INPUT',
+			);
+		});
+	});
+
+	describe('update with progress', () => {
+		const pairs = [];
+		for (let i = 0; i < 1000; i += 1) {
+			pairs.push({ key: getRandomBytes(32), value: getRandomBytes(32) });
+		}
+
+		it('should report the progress and calculate the same root', async () => {
+			const expected = await new SparseMerkleTree(32).update(Buffer.alloc(0), pairs);
+			const progress = [];
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), pairs, { onProgress: percent => progress.push(percent) });
+
+			expect(root).toEqual(expected);
+			expect(progress.length).toBeGreaterThan(0);
+			expect(progress[progress.length - 1]).toBe(100);
+			expect([...progress].sort((a, b) => a - b)).toEqual(progress);
+		});
+
+		it('should reject and keep the tree when aborted', async () => {
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), pairs.slice(0, 10));
+			const controller = new AbortController();
+			const update = smt.update(root, pairs, { signal: controller.signal });
+			controller.abort();
+			await expect(update).rejects.toThrow(AbortError);
+
+			await expect(smt.update(root, [])).resolves.toEqual(root);
+			const proof = await smt.prove(root, [pairs[0].key, pairs[20].key]);
+			await expect(smt.verify(root, [pairs[0].key, pairs[20].key], proof)).resolves.toBe(true);
+			expect(isInclusionProofForQueryKey(pairs[0].key, proof.queries[0])).toBe(true);
+			expect(isInclusionProofForQueryKey(pairs[20].key, proof.queries[1])).toBe(false);
+		});
+
+		it('should reject immediately when the signal is already aborted', async () => {
+			const smt = new SparseMerkleTree(32);
+			const controller = new AbortController();
+			controller.abort();
+			await expect(smt.update(Buffer.alloc(0), pairs, { signal: controller.signal })).rejects.toThrow(
+				'operation was aborted',
+			);
+		});
+
+		it('should reject prove and bulkLoad with ERR_ABORTED when the signal is aborted', async () => {
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), pairs);
+			const controller = new AbortController();
+			controller.abort();
+			await expect(smt.prove(root, [pairs[0].key], { signal: controller.signal })).rejects.toHaveProperty(
+				'code',
+				'ERR_ABORTED',
+			);
+			await expect(new SparseMerkleTree(32).bulkLoad([], { signal: controller.signal })).rejects.toThrow(
+				AbortError,
+			);
+			await expect(
+				smt.prove(root, [pairs[0].key], { signal: new AbortController().signal }),
+			).resolves.toHaveProperty('queries');
+		});
+	});
+
+	describe('bulkLoad', () => {
+		for (const test of FixturesInclusionProof.testCases) {
+			// eslint-disable-next-line no-loop-func
+			it(test.description, async () => {
+				const smt = new SparseMerkleTree(32);
+				const inputKeys = test.input.keys;
+				const inputValues = test.input.values;
+				const outputMerkleRoot = test.output.merkleRoot;
+
+				const kvpair = [];
+				for (let i = 0; i < inputKeys.length; i += 1) {
+					kvpair.push({ key: Buffer.from(inputKeys[i], 'hex'), value: Buffer.from(inputValues[i], 'hex') });
+				}
+				kvpair.sort((a, b) => a.key.compare(b.key));
+
+				const rootHash = await smt.bulkLoad(Readable.from(kvpair));
+				expect(rootHash.toString('hex')).toEqual(outputMerkleRoot);
+			});
+		}
+
+		it('should reject when keys are not sorted', async () => {
+			const smt = new SparseMerkleTree(32);
+			const kvpair = [
+				{ key: Buffer.alloc(32, 2), value: getRandomBytes(32) },
+				{ key: Buffer.alloc(32, 1), value: getRandomBytes(32) },
+			];
+
+			await expect(smt.bulkLoad(kvpair)).rejects.toThrow('keys must be sorted');
+		});
+
+		it('should load the stream larger than a batch in the same way as the array', async () => {
+			const kvpair = [];
+			for (let i = 0; i < 25000; i += 1) {
+				kvpair.push({ key: getRandomBytes(32), value: getRandomBytes(32) });
+			}
+			kvpair.sort((a, b) => a.key.compare(b.key));
+			const expected = await new SparseMerkleTree(32).bulkLoad(kvpair);
+
+			await expect(new SparseMerkleTree(32).bulkLoad(Readable.from(kvpair))).resolves.toEqual(expected);
+		});
+
+		it('should reject when the stream is not sorted across the batches', async () => {
+			const kvpair = [];
+			for (let i = 0; i < 15000; i += 1) {
+				kvpair.push({ key: getRandomBytes(32), value: getRandomBytes(32) });
+			}
+			kvpair.sort((a, b) => a.key.compare(b.key));
+			kvpair.push(kvpair[0]);
+
+			await expect(new SparseMerkleTree(32).bulkLoad(Readable.from(kvpair))).rejects.toThrow(
+				'keys must be sorted',
+			);
+		});
+	});
+
+	describe('updateWithBatch', () => {
+		let db;
+
+		beforeAll(() => {
+			const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+			fs.mkdirSync(dbPath, { recursive: true });
+			db = new StateDB(dbPath);
+		});
+
+		afterAll(() => {
+			db.close();
+		});
+
+		it('should calculate the same root as the state db commit', async () => {
+			const pairs = [];
+			for (let i = 0; i < 10; i += 1) {
+				pairs.push({ key: Buffer.concat([Buffer.from([0, 0, 0, 1, 0, 0]), getRandomBytes(8)]), value: getRandomBytes(20) });
+			}
+			const writer = db.newReadWriter();
+			const batch = new Batch();
+			for (const pair of pairs) {
+				await writer.set(pair.key, pair.value);
+				batch.set(pair.key, pair.value);
+			}
+			const root = await db.commit(writer, 0, Buffer.alloc(0));
+			writer.close();
+
+			const smt = new SparseMerkleTree();
+			await expect(smt.updateWithBatch(Buffer.alloc(0), batch)).resolves.toEqual(root);
+
+			const nextWriter = db.newReadWriter();
+			const nextBatch = new Batch();
+			await nextWriter.del(pairs[0].key);
+			nextBatch.del(pairs[0].key);
+			const nextRoot = await db.commit(nextWriter, 1, root);
+			nextWriter.close();
+
+			await expect(smt.updateWithBatch(root, nextBatch.encode())).resolves.toEqual(nextRoot);
+		});
+	});
+
+	describe('save and load', () => {
+		let filePath;
+
+		beforeEach(() => {
+			const dirPath = path.join(os.tmpdir(), 'smt', Date.now().toString());
+			fs.mkdirSync(dirPath, { recursive: true });
+			filePath = path.join(dirPath, 'snapshot');
+		});
+
+		it('should restore the tree saved to the file', async () => {
+			const smt = new SparseMerkleTree(32);
+			const kvpairs = [...new Array(20)].map(() => ({ key: getRandomBytes(32), value: getRandomBytes(32) }));
+			const root = await smt.update(Buffer.alloc(0), kvpairs);
+			await smt.save(filePath, root);
+
+			const loaded = new SparseMerkleTree(32);
+			const loadedRoot = await loaded.load(filePath);
+			expect(loadedRoot).toEqual(root);
+
+			const queries = [kvpairs[3].key, getRandomBytes(32)];
+			await expect(loaded.prove(loadedRoot, queries)).resolves.toEqual(await smt.prove(root, queries));
+			const nextPairs = [{ key: kvpairs[0].key, value: getRandomBytes(32) }];
+			await expect(loaded.update(loadedRoot, nextPairs)).resolves.toEqual(await smt.update(root, nextPairs));
+		});
+
+		it('should reject when the key length does not match', async () => {
+			const smt = new SparseMerkleTree(32);
+			const root = await smt.update(Buffer.alloc(0), [{ key: getRandomBytes(32), value: getRandomBytes(32) }]);
+			await smt.save(filePath, root);
+
+			await expect(new SparseMerkleTree(38).load(filePath)).rejects.toThrow('Key length');
+		});
+
+		it('should reject when the file does not exist', async () => {
+			await expect(new SparseMerkleTree(32).load(`${filePath}_not_exist`)).rejects.toThrow();
+		});
+	});
+
+	describe('toVisualization', () => {
+		it('should return the nodes of the tree in pre-order', async () => {
+			const smt = new SparseMerkleTree(32);
+			const kvpairs = [...new Array(20)].map(() => ({ key: getRandomBytes(32), value: getRandomBytes(32) }));
+			const root = await smt.update(Buffer.alloc(0), kvpairs);
+
+			const nodes = await smt.toVisualization(root);
+			expect(nodes[0]).toEqual(expect.objectContaining({ type: 'branch', height: 0, hash: root.toString('hex') }));
+			const leaves = nodes.filter(node => node.type === 'leaf');
+			expect(leaves.map(leaf => leaf.key).sort()).toEqual(kvpairs.map(pair => pair.key.toString('hex')).sort());
+			await expect(smt.toVisualization(root)).resolves.toEqual(nodes);
+		});
+
+		it('should return the nodes as CSV', async () => {
+			const smt = new SparseMerkleTree(32);
+			const kvpairs = [...new Array(5)].map(() => ({ key: getRandomBytes(32), value: getRandomBytes(32) }));
+			const root = await smt.update(Buffer.alloc(0), kvpairs);
+
+			const nodes = await smt.toVisualization(root);
+			const csv = await smt.toVisualization(root, { format: 'csv' });
+			const lines = csv.split('\n');
+			expect(lines[0]).toBe('type,height,hash,key,value');
+			expect(lines).toHaveLength(nodes.length + 1);
+			expect(lines[1]).toBe(`branch,0,${root.toString('hex')},,`);
+		});
+
+		it('should return the empty node for the empty tree', async () => {
+			const smt = new SparseMerkleTree(32);
+			const nodes = await smt.toVisualization(Buffer.alloc(0));
+
+			expect(nodes).toHaveLength(1);
+			expect(nodes[0].type).toBe('empty');
+		});
+	});
+
 	describe('prove', () => {
 		for (const test of [...FixturesInclusionProof.testCases, ...FixturesNonInclusionProof.testCases]) {
 			// eslint-disable-next-line no-loop-func
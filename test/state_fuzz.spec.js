@@ -0,0 +1,90 @@
+/*
+ * Copyright © 2022 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const os = require('os');
+const path = require('path');
+const fs = require('fs');
+const crypto = require('crypto');
+const { test_support_generate } = require('../bin-package/index.node');
+const { StateDB, SparseMerkleTree } = require('../main');
+
+const sha256 = val => {
+    const hasher = crypto.createHash('sha256');
+    hasher.update(val);
+    return hasher.digest();
+};
+
+const treeKey = key => Buffer.concat([key.subarray(0, 6), sha256(key.subarray(6))]);
+
+// the generator is only exported by the build with the test-support feature, which is `npm run build-test`.
+const describeWithTestSupport = test_support_generate ? describe : describe.skip;
+
+describeWithTestSupport('state fuzz', () => {
+    const seeds = [1, 2, 3];
+    const roundCount = 8;
+    const changeCount = 50;
+
+    let dbPath;
+    let db;
+
+    beforeEach(() => {
+        dbPath = path.join(os.tmpdir(), 'state_fuzz', Date.now().toString());
+        fs.mkdirSync(dbPath, { recursive: true });
+        db = new StateDB(dbPath);
+    });
+
+    afterEach(() => {
+        db.close();
+        fs.rmSync(dbPath, { recursive: true, force: true });
+    });
+
+    it('should generate the same rounds from the same seed', () => {
+        expect(test_support_generate(7, 3, 10)).toEqual(test_support_generate(7, 3, 10));
+        expect(test_support_generate(7, 3, 10)).not.toEqual(test_support_generate(8, 3, 10));
+    });
+
+    it.each(seeds)('should commit, prove and revert the rounds of the seed %d', async seed => {
+        const rounds = test_support_generate(seed, roundCount, changeCount);
+        const smt = new SparseMerkleTree();
+        const roots = [Buffer.alloc(0)];
+        for (let height = 1; height <= rounds.length; height++) {
+            const round = rounds[height - 1];
+            const writer = db.newReadWriter();
+            for (const { key, value } of round.changes) {
+                if (value.length === 0) {
+                    await writer.del(key);
+                } else {
+                    await writer.set(key, value);
+                }
+            }
+            const root = await db.commit(writer, height, roots[height - 1]);
+            expect(root).toEqual(round.root);
+            roots.push(root);
+
+            const queries = round.queries.map(treeKey);
+            const proof = await db.prove(root, queries);
+            await expect(db.verify(root, queries, proof)).resolves.toBe(true);
+            await expect(db.verify(sha256(root), queries, proof)).resolves.toBe(false);
+
+            const witness = await db.generateWitness(root, round.queries);
+            const entries = await smt.verifyWitness(root, witness);
+            expect(entries.map(entry => entry.key)).toEqual(round.queries);
+        }
+
+        for (let height = rounds.length; height > 1; height--) {
+            await expect(db.revert(roots[height], height)).resolves.toEqual(roots[height - 1]);
+        }
+    });
+});
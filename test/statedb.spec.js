@@ -17,7 +17,20 @@ const os = require('os');
 const path = require('path');
 const fs = require('fs');
 const crypto = require('crypto');
-const { StateDB, NotFoundError } = require('../main');
+const {
+    Database,
+    StateDB,
+    InMemoryStateDB,
+    StateWriter,
+    SparseMerkleTree,
+    NotFoundError,
+    MaxOpenResourcesError,
+    CommitConflictError,
+    AbortError,
+    Batch,
+    restoreFromBackup,
+    purgeOldBackups,
+} = require('../main');
 const { getRandomBytes } = require('./utils');
 
 const sha256 = val => {
@@ -112,6 +125,16 @@ describe('statedb', () => {
             await expect(db.has(initState[0].key)).resolves.toEqual(true);
         });
 
+        it('should get many values with undefined for the missing keys', async () => {
+            const keys = [initState[1].key, getRandomBytes(), initState[7].key, initState[0].key];
+            await expect(db.getMany(keys)).resolves.toEqual([
+                initState[1].value,
+                undefined,
+                Buffer.alloc(0),
+                initState[0].value,
+            ]);
+        });
+
         it('should iterate with specified range with limit', async () => {
             const stream = db.iterate({
                 gte: Buffer.from([0, 0, 0, 0, 0, 0, 0, 0, 1]),
@@ -186,6 +209,18 @@ describe('statedb', () => {
             expect(values).toEqual([]);
         });
 
+        it('should destroy the stream with AbortError when the signal is aborted', async () => {
+            const controller = new AbortController();
+            controller.abort();
+            const stream = db.iterate({ signal: controller.signal });
+
+            await expect(
+                new Promise((resolve, reject) => {
+                    stream.on('error', reject).on('end', resolve);
+                }),
+            ).rejects.toThrow(AbortError);
+        });
+
         it('should get empty buffer multiple times', async () => {
             const writer = db.newReadWriter();
             const val = await writer.get(initState[7].key);
@@ -298,6 +333,33 @@ describe('statedb', () => {
                 await expect(db.commit(writer, 1, root, { readonly: true, checkRoot: true, expectedRoot: getRandomBytes() }))
                     .rejects.toThrow('Invalid state root `Not matching with expected`');
             });
+
+            it('should notify the listeners of the written commits', async () => {
+                const events = [];
+                const unsubscribe = db.onCommit(event => events.push(event));
+                const readonlyWriter = db.newReadWriter();
+                await readonlyWriter.set(initState[0].key, getRandomBytes());
+                await db.commit(readonlyWriter, 1, root, { readonly: true });
+                expect(events).toHaveLength(0);
+
+                const newKey = getRandomBytes();
+                const writer = db.newReadWriter();
+                await writer.set(newKey, getRandomBytes());
+                const nextRoot = await db.commit(writer, 1, root);
+                expect(events).toHaveLength(1);
+                expect(events[0].root).toEqual(nextRoot);
+                expect(events[0].height).toEqual(1);
+                expect(events[0].diffStats.created).toEqual({ count: 1, bytes: newKey.length });
+                expect(events[0].diffStats.updated).toEqual({ count: 0, bytes: 0 });
+
+                unsubscribe();
+                await expect(db.revert(1)).resolves.toEqual(root);
+                const next = db.newReadWriter();
+                await next.set(newKey, getRandomBytes());
+                await db.commit(next, 1, root);
+                expect(events).toHaveLength(1);
+                await expect(db.revert(1)).resolves.toEqual(root);
+            });
         });
 
         describe('revert', () => {
@@ -338,6 +400,62 @@ describe('statedb', () => {
                 await expect(db.get(initState[0].key)).resolves.toEqual(initState[0].value);
                 expect(original).toEqual(root);
             });
+
+            it('should revert the current state with only the height', async () => {
+                const writer = db.newReadWriter();
+                await writer.set(initState[0].key, getRandomBytes());
+                await db.commit(writer, 1, root);
+
+                await expect(db.revert(2)).rejects.toThrow('Cannot revert height `2` from the current height `1`');
+                const original = await db.revert(1);
+                await expect(db.get(initState[0].key)).resolves.toEqual(initState[0].value);
+                expect(original).toEqual(root);
+                await expect(db.getCurrentState()).resolves.toEqual({ root, version: 0 });
+            });
+        });
+
+        describe('calculateDiffBetween', () => {
+            it('should return the net change between the heights', async () => {
+                const newKey = getRandomBytes();
+                const first = db.newReadWriter();
+                await first.set(initState[0].key, getRandomBytes());
+                await first.set(newKey, getRandomBytes());
+                const firstRoot = await db.commit(first, 1, root);
+                const second = db.newReadWriter();
+                await second.set(initState[0].key, getRandomBytes());
+                await second.del(initState[1].key);
+                await db.commit(second, 2, firstRoot);
+
+                await expect(db.calculateDiffBetween(0, 2)).resolves.toEqual({
+                    created: [newKey],
+                    updated: [initState[0]],
+                    deleted: [initState[1]],
+                });
+                await expect(db.calculateDiffBetween(2, 2)).resolves.toEqual({ created: [], updated: [], deleted: [] });
+                await expect(db.calculateDiffBetween(2, 1)).rejects.toThrow('Invalid height range from `2` to `1`');
+                await expect(db.calculateDiffBetween(0, 3)).rejects.toThrow('Diff not found for height: `3`');
+                await db.revert(2);
+                await expect(db.revert(1)).resolves.toEqual(root);
+            });
+        });
+
+        describe('getDiffStats', () => {
+            it('should return the number and the bytes of the entries in the diff', async () => {
+                const newKey = getRandomBytes();
+                const writer = db.newReadWriter();
+                await writer.set(newKey, getRandomBytes());
+                await writer.set(initState[0].key, getRandomBytes());
+                await writer.del(initState[1].key);
+                await db.commit(writer, 1, root);
+
+                const stats = await db.getDiffStats(1);
+                expect(stats.created).toEqual({ count: 1, bytes: newKey.length });
+                expect(stats.updated).toEqual({ count: 1, bytes: initState[0].key.length + initState[0].value.length });
+                expect(stats.deleted).toEqual({ count: 1, bytes: initState[1].key.length + initState[1].value.length });
+                expect(stats.size).toBeGreaterThan(0);
+                await expect(db.getDiffStats(2)).rejects.toThrow('Diff not found for height: `2`');
+                await expect(db.revert(1)).resolves.toEqual(root);
+            });
         });
 
         describe('finalize', () => {
@@ -362,7 +480,62 @@ describe('statedb', () => {
             });
         });
 
+        describe('diff retention', () => {
+            let retentionDB;
+            let retentionRoot;
+
+            beforeEach(async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                retentionDB = new StateDB(dbPath, { diffRetention: 3 });
+                retentionRoot = Buffer.alloc(0);
+                for (let i = 1; i <= 5; i += 1) {
+                    const writer = retentionDB.newReadWriter();
+                    await writer.set(getRandomBytes(), getRandomBytes());
+                    retentionRoot = await retentionDB.commit(writer, i, retentionRoot);
+                    writer.close();
+                }
+            });
+
+            afterEach(() => {
+                retentionDB.close();
+            });
+
+            it('should keep only the diffs of the latest heights on commit', async () => {
+                retentionRoot = await retentionDB.revert(retentionRoot, 5);
+                retentionRoot = await retentionDB.revert(retentionRoot, 4);
+                retentionRoot = await retentionDB.revert(retentionRoot, 3);
+                await expect(retentionDB.revert(retentionRoot, 2)).rejects.toThrow(
+                    'Diff not found for height: `2`',
+                );
+            });
+
+            it('should remove the diffs below the height with pruneDiffs', async () => {
+                await expect(retentionDB.pruneDiffs(5)).resolves.toBeUndefined();
+                retentionRoot = await retentionDB.revert(retentionRoot, 5);
+                await expect(retentionDB.revert(retentionRoot, 4)).rejects.toThrow(
+                    'Diff not found for height: `4`',
+                );
+            });
+
+            it('should report the retention in the debug report', async () => {
+                const report = await retentionDB.debugReport();
+                expect(report.options.diffRetention).toEqual(3);
+            });
+        });
+
         describe('StateReadWriter', () => {
+            it('should reject the key shorter than the store prefix', async () => {
+                const writer = db.newReadWriter();
+                await expect(writer.set(Buffer.from([0, 0, 1]), getRandomBytes())).rejects.toThrow(
+                    'State key must be at least 6 bytes of the store prefix',
+                );
+                await expect(writer.del(Buffer.from([0, 0, 1]))).rejects.toThrow('State key must be at least 6 bytes');
+                await expect(db.prove(root, [Buffer.from([0, 0, 1])], { includeValues: true })).rejects.toThrow(
+                    'State key must be at least 6 bytes',
+                );
+            });
+
             it('should return values with range', async () => {
                 const writer = db.newReadWriter();
                 await writer.set(Buffer.from([0, 0, 0, 0, 0, 0, 0, 0, 3]), getRandomBytes());
@@ -411,6 +584,20 @@ describe('statedb', () => {
                 expect(result[1].value).toEqual(initState[2].value);
             });
 
+            it('should return sorted values with exclusive range', async () => {
+                const writer = db.newReadWriter();
+                const newKey = Buffer.from([0, 0, 0, 0, 0, 0, 1, 0, 0]);
+                await writer.set(newKey, getRandomBytes());
+                await writer.del(initState[2].key);
+
+                const result = await writer.range({
+                    gt: initState[0].key,
+                    lt: initState[3].key,
+                });
+
+                expect(result.map(pair => pair.key)).toEqual([initState[1].key, newKey]);
+            });
+
             it('should return to original value after restoreSnapshot', async () => {
                 const writer = db.newReadWriter();
                 const index = writer.snapshot();
@@ -459,6 +646,33 @@ describe('statedb', () => {
                 expect(() => writer.restoreSnapshot(99)).toThrow('Invalid usage');
             });
 
+            it('should delete the keys in the range on commit', async () => {
+                const writer = db.newReadWriter();
+                await writer.deleteRange(initState[0].key, initState[3].key);
+                await expect(writer.get(initState[1].key)).rejects.toThrow(NotFoundError);
+                await expect(writer.get(initState[3].key)).resolves.toEqual(initState[3].value);
+
+                const nextRoot = await db.commit(writer, 1, root);
+                expect(nextRoot).not.toEqual(root);
+                await expect(db.get(initState[0].key)).rejects.toThrow(NotFoundError);
+                await expect(db.get(initState[2].key)).rejects.toThrow(NotFoundError);
+                await expect(db.get(initState[3].key)).resolves.toEqual(initState[3].value);
+
+                const original = await db.revert(nextRoot, 1);
+                await expect(db.get(initState[2].key)).resolves.toEqual(initState[2].value);
+                expect(original).toEqual(root);
+            });
+
+            it('should reject the writes exceeding the max size', async () => {
+                const writer = db.newReadWriter({ maxSize: 100 });
+                await writer.set(getRandomBytes(), getRandomBytes());
+                expect(writer.size).toBeGreaterThan(0);
+                expect(writer.size).toBeLessThanOrEqual(100);
+
+                await expect(writer.set(getRandomBytes(), getRandomBytes())).rejects.toThrow('StateWriter cap of 100 bytes is exceeded');
+                writer.close();
+            });
+
             it('should throw an error when the writer is closed', async () => {
                 const writer = db.newReadWriter();
                 const newValue = getRandomBytes();
@@ -469,6 +683,91 @@ describe('statedb', () => {
             });
         });
 
+        describe('StateWriter', () => {
+            it('should hold the changes without reading the database', async () => {
+                const writer = new StateWriter();
+                const newKey = getRandomBytes();
+                const newValue = getRandomBytes();
+                await writer.cacheExisting(initState[0].key, initState[0].value);
+                await writer.cacheExisting(initState[1].key, initState[1].value);
+                await writer.set(newKey, newValue);
+                await writer.del(initState[0].key);
+
+                await expect(writer.get(newKey)).resolves.toEqual(newValue);
+                await expect(writer.get(initState[0].key)).rejects.toThrow(NotFoundError);
+                await expect(writer.has(initState[2].key)).resolves.toEqual(false);
+                await expect(writer.range({ gte: initState[0].key, lte: initState[1].key })).resolves.toEqual([initState[1]]);
+                await expect(writer.getUpdated()).resolves.toEqual({
+                    updated: [{ key: newKey, value: newValue }],
+                    deleted: [initState[0].key],
+                });
+                writer.close();
+            });
+
+            it('should list the changed keys grouped by the store prefix', async () => {
+                const writer = new StateWriter();
+                await writer.cacheExisting(initState[2].key, initState[2].value);
+                await writer.cacheExisting(initState[3].key, initState[3].value);
+                await writer.set(initState[2].key, getRandomBytes());
+                await writer.del(initState[3].key);
+                const newKey = Buffer.from([0, 0, 0, 0, 0, 2, 1]);
+                await writer.set(newKey, getRandomBytes());
+
+                await expect(writer.changedKeys(Buffer.from([0, 0, 0, 0]))).resolves.toEqual([
+                    {
+                        storePrefix: Buffer.from([0, 0, 0, 0, 0, 1]),
+                        created: [],
+                        updated: [initState[2].key],
+                        deleted: [initState[3].key],
+                    },
+                    {
+                        storePrefix: Buffer.from([0, 0, 0, 0, 0, 2]),
+                        created: [newKey],
+                        updated: [],
+                        deleted: [],
+                    },
+                ]);
+                await expect(writer.changedKeys(Buffer.from([1]))).resolves.toEqual([]);
+                writer.close();
+            });
+
+            it('should commit the same root as StateReadWriter', async () => {
+                const newKey = getRandomBytes();
+                const newValue = getRandomBytes();
+                const writer = new StateWriter();
+                await writer.cacheExisting(initState[0].key, initState[0].value);
+                await writer.set(newKey, newValue);
+                await writer.del(initState[0].key);
+                const readWriter = db.newReadWriter();
+                await readWriter.set(newKey, newValue);
+                await readWriter.del(initState[0].key);
+
+                const writerRoot = await db.commit(writer, 1, root, { readonly: true });
+                const readWriterRoot = await db.commit(readWriter, 1, root, { readonly: true });
+                expect(writerRoot).toEqual(readWriterRoot);
+                writer.close();
+                readWriter.close();
+            });
+
+            it('should read the values not cached from the database', async () => {
+                const newValue = getRandomBytes();
+                const writer = db.newWriter();
+                await expect(writer.get(initState[0].key)).resolves.toEqual(initState[0].value);
+                await writer.set(initState[1].key, newValue);
+                await writer.del(initState[2].key);
+                const readWriter = db.newReadWriter();
+                await readWriter.set(initState[1].key, newValue);
+                await readWriter.del(initState[2].key);
+
+                await expect(writer.get(initState[1].key)).resolves.toEqual(newValue);
+                const writerRoot = await db.commit(writer, 1, root, { readonly: true });
+                const readWriterRoot = await db.commit(readWriter, 1, root, { readonly: true });
+                expect(writerRoot).toEqual(readWriterRoot);
+                writer.close();
+                readWriter.close();
+            });
+        });
+
         describe('StateReader', () => {
             const nonExistingKey = Buffer.from([255, 255]);
 
@@ -560,6 +859,36 @@ describe('statedb', () => {
             });
         });
 
+        describe('open resources', () => {
+            it('should throw an error when the maximum number of open readers is reached', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                const limitedDB = new StateDB(dbPath, { maxOpenIterators: 2 });
+                const reader = limitedDB.newReader();
+                const readWriter = limitedDB.newReadWriter();
+                expect(() => limitedDB.newReader()).toThrow(MaxOpenResourcesError);
+                reader.close();
+                readWriter.close();
+                expect(limitedDB.getOpenResources()).toHaveLength(0);
+                limitedDB.newReader().close();
+                limitedDB.close();
+            });
+
+            it('should return readers which are not closed in debug mode', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                const debugDB = new StateDB(dbPath, { debug: true });
+                const reader = debugDB.newReader();
+                const resources = debugDB.getOpenResources();
+                expect(resources).toHaveLength(1);
+                expect(resources[0].kind).toEqual('reader');
+                expect(resources[0].stack).toContain('statedb.spec.js');
+                reader.close();
+                expect(debugDB.getOpenResources()).toHaveLength(0);
+                debugDB.close();
+            });
+        });
+
         describe('checkpoint', () => {
             let tmpPath;
             beforeEach(() => {
@@ -588,6 +917,124 @@ describe('statedb', () => {
             });
         });
 
+        describe('compaction', () => {
+            it('should keep the state after compacting with auto compaction disabled', async () => {
+                db.setAutoCompaction(false);
+                await db.compactRange(initState[0].key, initState[3].key);
+                await db.compactRange();
+                await db.compactSMT();
+                db.setAutoCompaction(true);
+
+                await expect(db.get(initState[0].key)).resolves.toEqual(initState[0].value);
+                const queries = [
+                    Buffer.concat([initState[0].key.slice(0, 6), sha256(initState[0].key.slice(6))]),
+                ];
+                const proof = await db.prove(root, queries);
+                await expect(db.verifyInclusionProof(root, queries, proof)).resolves.toEqual(true);
+            });
+        });
+
+        describe('durability', () => {
+            it('should flush and sync the WAL', async () => {
+                await expect(db.flush()).resolves.toBeUndefined();
+                await expect(db.syncWal()).resolves.toBeUndefined();
+                await expect(db.get(initState[0].key)).resolves.toEqual(initState[0].value);
+            });
+        });
+
+        describe('properties', () => {
+            it('should return the properties of each column family with the total', async () => {
+                const properties = await db.getProperties();
+
+                expect(Object.keys(properties.columnFamilies)).toEqual(['default', 'state', 'smt', 'diff']);
+                expect(properties.columnFamilies.state.estimatedKeys).toBeGreaterThan(0);
+                const cfs = Object.values(properties.columnFamilies);
+                expect(properties.estimatedKeys).toEqual(cfs.reduce((sum, cf) => sum + cf.estimatedKeys, 0));
+                expect(properties.memtableUsage).toEqual(cfs.reduce((sum, cf) => sum + cf.memtableUsage, 0));
+            });
+
+            it('should estimate the size of the flushed keys in the range', async () => {
+                await db.flush();
+                const all = await db.approximateSize();
+                expect(all.size).toBeGreaterThan(0);
+                expect(all.estimatedKeys).toBeGreaterThan(0);
+
+                const outside = await db.approximateSize(Buffer.alloc(40, 255));
+                expect(outside).toEqual({ size: 0, estimatedKeys: 0 });
+            });
+        });
+
+        describe('secondary', () => {
+            it('should follow the primary after catching up', async () => {
+                const basePath = path.join(os.tmpdir(), 'state', `${Date.now().toString()}_secondary`);
+                const primaryPath = path.join(basePath, 'primary');
+                fs.mkdirSync(primaryPath, { recursive: true });
+                const primary = new StateDB(primaryPath);
+                const key = getRandomBytes();
+                const value = getRandomBytes();
+                const first = primary.newReadWriter();
+                await first.set(key, value);
+                const firstRoot = await primary.commit(first, 1, Buffer.alloc(0));
+                first.close();
+
+                const secondary = new StateDB(primaryPath, { secondaryPath: path.join(basePath, 'secondary') });
+                await expect(secondary.get(key)).resolves.toEqual(value);
+                await expect(secondary.tryCatchUpWithPrimary()).resolves.toMatchObject({ changed: false });
+
+                const changes = [];
+                const stop = secondary.followPrimary((err, result) => {
+                    changes.push(err || result);
+                }, 10);
+                const nextKey = getRandomBytes();
+                const second = primary.newReadWriter();
+                await second.set(nextKey, value);
+                const nextRoot = await primary.commit(second, 2, firstRoot);
+                second.close();
+                while (changes.length === 0) {
+                    await new Promise(resolve => setTimeout(resolve, 10));
+                }
+                stop();
+
+                expect(changes[0].changed).toBe(true);
+                await expect(secondary.get(nextKey)).resolves.toEqual(value);
+                await expect(secondary.getCurrentState()).resolves.toEqual({ root: nextRoot, version: 2 });
+                secondary.close();
+                primary.close();
+            });
+        });
+
+        describe('backup', () => {
+            it('should restore the latest backup and keep only the latest backups after purge', async () => {
+                const basePath = path.join(os.tmpdir(), 'state', `${Date.now().toString()}_backup`);
+                const backupDir = path.join(basePath, 'backup');
+                fs.mkdirSync(path.join(basePath, 'db'), { recursive: true });
+                const backupDB = new StateDB(path.join(basePath, 'db'));
+                const writer = backupDB.newReadWriter();
+                await writer.set(initState[0].key, initState[0].value);
+                const firstRoot = await backupDB.commit(writer, 0, Buffer.alloc(0));
+                await backupDB.createBackup(backupDir);
+
+                const nextWriter = backupDB.newReadWriter();
+                await nextWriter.set(initState[1].key, initState[1].value);
+                const nextRoot = await backupDB.commit(nextWriter, 1, firstRoot);
+                await backupDB.createBackup(backupDir);
+                backupDB.close();
+
+                await purgeOldBackups(backupDir, 1);
+                expect(fs.readdirSync(path.join(backupDir, 'private'))).toHaveLength(1);
+
+                const restoredPath = path.join(basePath, 'restored');
+                await restoreFromBackup(backupDir, restoredPath);
+                const restoredDB = new StateDB(restoredPath);
+                await expect(restoredDB.get(initState[0].key)).resolves.toEqual(initState[0].value);
+                await expect(restoredDB.get(initState[1].key)).resolves.toEqual(initState[1].value);
+                const currentState = await restoredDB.getCurrentState();
+                expect(currentState.root).toEqual(nextRoot);
+                expect(currentState.version).toEqual(1);
+                restoredDB.close();
+            });
+        });
+
         describe('proof', () => {
             it('should generate non-inclusion proof and verify that a result is correct', async () => {
                 const queries = [getRandomBytes(38), getRandomBytes(38)];
@@ -638,6 +1085,558 @@ describe('statedb', () => {
                 await expect(db.verifyNonInclusionProof(root, queries, proof)).resolves.toEqual(false);
                 await expect(db.verifyInclusionProof(root, queries, proof)).resolves.toEqual(false);
             });
+
+            it('should include the stored values when requested', async () => {
+                const stateKeys = [initState[0].key, initState[1].key, getRandomBytes(38)];
+                const proof = await db.prove(root, stateKeys, { includeValues: true });
+                const queries = stateKeys.map(key => Buffer.concat([key.subarray(0, 6), sha256(key.subarray(6))]));
+
+                expect(proof.queries).toHaveLength(3);
+                expect(proof.queries[0].key).toEqual(queries[0]);
+                expect(proof.queries[0].storedValue).toEqual(initState[0].value);
+                expect(proof.queries[1].storedValue).toEqual(initState[1].value);
+                expect(proof.queries[2].storedValue).toBeUndefined();
+                await expect(db.verify(root, queries, proof)).resolves.toEqual(true);
+            });
+
+            it('should not include the stored values by default', async () => {
+                const queries = [Buffer.concat([initState[0].key.subarray(0, 6), sha256(initState[0].key.subarray(6))])];
+                const proof = await db.prove(root, queries);
+
+                expect(proof.queries[0].storedValue).toBeUndefined();
+            });
+        });
+
+        describe('prefix proof', () => {
+            let prefixDB;
+            let prefixRoot;
+
+            beforeAll(async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                prefixDB = new StateDB(dbPath);
+                const writer = prefixDB.newReadWriter();
+                for (let i = 0; i < 20; i += 1) {
+                    await writer.set(Buffer.from([0, 0, 0, 1, 0, i]), getRandomBytes());
+                    await writer.set(Buffer.from([0, 0, 0, 2, 0, i]), getRandomBytes());
+                }
+                await writer.set(Buffer.from([0, 0, 0, 3, 0, 0]), getRandomBytes());
+                prefixRoot = await prefixDB.commit(writer, 0, Buffer.alloc(0));
+                writer.close();
+            });
+
+            afterAll(() => {
+                prefixDB.close();
+            });
+
+            it('should prove the subtree root of the prefix', async () => {
+                const proof = await prefixDB.provePrefix(prefixRoot, Buffer.from([0, 0, 0, 1]));
+
+                expect(proof.node.kind).toBe('branch');
+                expect(proof.siblingHashes).toHaveLength(32);
+                expect(proof.subtreeRoot).toEqual(proof.node.hash);
+                await expect(prefixDB.verifyPrefix(prefixRoot, proof)).resolves.toEqual(true);
+            });
+
+            it('should prove the leaf when only one key has the prefix', async () => {
+                const proof = await prefixDB.provePrefix(prefixRoot, Buffer.from([0, 0, 0, 3]));
+
+                expect(proof.node.kind).toBe('leaf');
+                expect(proof.node.key.subarray(0, 6)).toEqual(Buffer.from([0, 0, 0, 3, 0, 0]));
+                await expect(prefixDB.verifyPrefix(prefixRoot, proof)).resolves.toEqual(true);
+            });
+
+            it('should prove the empty subtree when no key has the prefix', async () => {
+                const proof = await prefixDB.provePrefix(prefixRoot, Buffer.from([0, 0, 0, 4]));
+
+                expect(proof.subtreeRoot).toEqual(sha256(Buffer.alloc(0)));
+                await expect(prefixDB.verifyPrefix(prefixRoot, proof)).resolves.toEqual(true);
+            });
+
+            it('should not verify the modified proof', async () => {
+                const proof = await prefixDB.provePrefix(prefixRoot, Buffer.from([0, 0, 0, 2]));
+                proof.node.hash = getRandomBytes(32);
+
+                await expect(prefixDB.verifyPrefix(prefixRoot, proof)).resolves.toEqual(false);
+            });
+
+            it('should reject the prefix longer than the key prefix', async () => {
+                await expect(prefixDB.provePrefix(prefixRoot, Buffer.alloc(7))).rejects.toThrow();
+            });
+        });
+
+        describe('root history', () => {
+            const toQueryKey = key => Buffer.concat([key.slice(0, 6), sha256(key.slice(6))]);
+            let historyDB;
+            const roots = [];
+
+            beforeAll(async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                historyDB = new StateDB(dbPath, { keepRootHistory: true });
+                let prevRoot = Buffer.alloc(0);
+                for (let height = 0; height < 3; height += 1) {
+                    const writer = historyDB.newReadWriter();
+                    await writer.set(initState[0].key, Buffer.from([height + 1]));
+                    await writer.set(initState[height + 1].key, Buffer.from([height + 1]));
+                    prevRoot = await historyDB.commit(writer, height, prevRoot);
+                    writer.close();
+                    roots.push(prevRoot);
+                }
+            });
+
+            afterAll(() => {
+                historyDB.close();
+            });
+
+            it('should return the root of each committed height', async () => {
+                for (let height = 0; height < 3; height += 1) {
+                    await expect(historyDB.getRootAt(height)).resolves.toEqual(roots[height]);
+                }
+            });
+
+            it('should throw NotFoundError for height which is not committed', async () => {
+                await expect(historyDB.getRootAt(10)).rejects.toThrow(NotFoundError);
+            });
+
+            it('should generate inclusion proof against the historical root', async () => {
+                const queries = [toQueryKey(initState[0].key), toQueryKey(initState[1].key)];
+                const proof = await historyDB.proveAt(0, queries);
+
+                expect(proof.queries[0].value).toEqual(sha256(Buffer.from([1])));
+                await expect(historyDB.verifyAt(0, queries, proof)).resolves.toEqual(true);
+                await expect(historyDB.verifyInclusionProof(roots[0], queries, proof)).resolves.toEqual(true);
+                await expect(historyDB.verifyAt(2, queries, proof)).resolves.toEqual(false);
+            });
+
+            it('should remove the root of the reverted height', async () => {
+                await historyDB.revert(roots[2], 2);
+                await expect(historyDB.getRootAt(2)).rejects.toThrow(NotFoundError);
+                await expect(historyDB.getRootAt(1)).resolves.toEqual(roots[1]);
+            });
+
+            it('should not keep the root history by default', async () => {
+                await expect(db.getRootAt(0)).rejects.toThrow(NotFoundError);
+            });
+
+            it('should keep only the roots of the latest heights with rootHistoryRetention', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', `${Date.now()}-retention`);
+                fs.mkdirSync(dbPath, { recursive: true });
+                const retentionDB = new StateDB(dbPath, { keepRootHistory: true, rootHistoryRetention: 2 });
+                let prevRoot = Buffer.alloc(0);
+                for (let height = 1; height <= 4; height += 1) {
+                    const writer = retentionDB.newReadWriter();
+                    await writer.set(initState[0].key, Buffer.from([height]));
+                    prevRoot = await retentionDB.commit(writer, height, prevRoot);
+                    writer.close();
+                }
+
+                await expect(retentionDB.getRootAt(2)).rejects.toThrow(NotFoundError);
+                const root = await retentionDB.getRootAt(3);
+                const queries = [toQueryKey(initState[0].key)];
+                const proof = await retentionDB.proveAt(3, queries);
+                await expect(retentionDB.verifyInclusionProof(root, queries, proof)).resolves.toEqual(true);
+                const report = await retentionDB.debugReport();
+                expect(report.options.rootHistoryRetention).toEqual(2);
+                retentionDB.close();
+            });
+
+            it('should refuse to open the database with the other keepRootHistory', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', `${Date.now()}-mode`);
+                fs.mkdirSync(dbPath, { recursive: true });
+                const modeDB = new StateDB(dbPath, { keepRootHistory: true });
+                modeDB.close();
+
+                expect(() => new StateDB(dbPath)).toThrow(
+                    'Database was created with keepRootHistory `true`, but it is opened with `false`',
+                );
+            });
+        });
+
+        describe('root registry', () => {
+            it('should find the root of each height and the height of each root without keepRootHistory', async () => {
+                const registryDB = new InMemoryStateDB();
+                const roots = [];
+                let prevRoot = Buffer.alloc(0);
+                for (let height = 1; height <= 3; height += 1) {
+                    const writer = registryDB.newReadWriter();
+                    await writer.set(initState[height].key, Buffer.from([height]));
+                    prevRoot = await registryDB.commit(writer, height, prevRoot);
+                    roots.push(prevRoot);
+                }
+                // the root is kept at height 4 without changes
+                await registryDB.commit(registryDB.newReadWriter(), 4, prevRoot);
+
+                for (let height = 1; height <= 3; height += 1) {
+                    await expect(registryDB.getRootAtHeight(height)).resolves.toEqual(roots[height - 1]);
+                }
+                await expect(registryDB.getRootAtHeight(4)).resolves.toEqual(roots[2]);
+                await expect(registryDB.getRootAtHeight(5)).rejects.toThrow(NotFoundError);
+                await expect(registryDB.findHeightForRoot(roots[0])).resolves.toEqual(1);
+                await expect(registryDB.findHeightForRoot(roots[2])).resolves.toEqual(4);
+                await expect(registryDB.findHeightForRoot(getRandomBytes(32))).rejects.toThrow(NotFoundError);
+                await expect(registryDB.getRootAt(1)).rejects.toThrow(NotFoundError);
+
+                await registryDB.revert(roots[2], 4);
+                await expect(registryDB.getRootAtHeight(4)).rejects.toThrow(NotFoundError);
+                await expect(registryDB.findHeightForRoot(roots[2])).resolves.toEqual(3);
+                registryDB.close();
+            });
+        });
+
+        describe('lifetime stats', () => {
+            it('should keep the stats of the commits after reopening', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                let statsDB = new StateDB(dbPath);
+                await expect(statsDB.getLifetimeStats()).resolves.toEqual({ commits: 0, keysWritten: 0, bytesWritten: 0 });
+
+                const writer = statsDB.newReadWriter();
+                for (let i = 0; i < 5; i += 1) {
+                    await writer.set(getRandomBytes(), getRandomBytes());
+                }
+                const nextRoot = await statsDB.commit(writer, 1, Buffer.alloc(0));
+                writer.close();
+                const readonlyWriter = statsDB.newReadWriter();
+                await readonlyWriter.set(getRandomBytes(), getRandomBytes());
+                await statsDB.commit(readonlyWriter, 2, nextRoot, { readonly: true });
+                readonlyWriter.close();
+                statsDB.close();
+
+                statsDB = new StateDB(dbPath);
+                const stats = await statsDB.getLifetimeStats();
+                statsDB.close();
+
+                expect(stats.commits).toEqual(1);
+                expect(stats.keysWritten).toEqual(5);
+                expect(stats.bytesWritten).toBeGreaterThan(0);
+            });
+        });
+
+        describe('column families', () => {
+            it('should migrate the state written in the prefix-based layout', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', `${Date.now().toString()}_layout`);
+                fs.mkdirSync(dbPath, { recursive: true });
+                const key = getRandomBytes();
+                const value = getRandomBytes();
+                const legacyDB = new Database(dbPath);
+                await legacyDB.set(Buffer.concat([Buffer.from([0]), key]), value);
+                legacyDB.close();
+
+                const migratedDB = new StateDB(dbPath);
+                await expect(migratedDB.get(key)).resolves.toEqual(value);
+                const writer = migratedDB.newReadWriter();
+                await writer.set(getRandomBytes(), getRandomBytes());
+                const root = await migratedDB.commit(writer, 1, Buffer.alloc(0));
+                writer.close();
+                migratedDB.close();
+
+                const reopenedDB = new StateDB(dbPath, { readonly: true });
+                await expect(reopenedDB.get(key)).resolves.toEqual(value);
+                const current = await reopenedDB.getCurrentState();
+                expect(current.root).toEqual(root);
+                expect(current.version).toEqual(1);
+                reopenedDB.close();
+            });
+        });
+
+        describe('debug report', () => {
+            it('should report the options, the prefixes and the failed operations', async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                const reportDB = new StateDB(dbPath, { keepRootHistory: true });
+                const writer = reportDB.newReadWriter();
+                for (let i = 0; i < 3; i += 1) {
+                    await writer.set(getRandomBytes(), getRandomBytes());
+                }
+                await reportDB.commit(writer, 1, Buffer.alloc(0));
+                writer.close();
+                const before = await reportDB.debugReport();
+                await expect(reportDB.revert(getRandomBytes(), 10)).rejects.toThrow();
+
+                const report = await reportDB.debugReport();
+                reportDB.close();
+
+                expect(report.formatVersion.subtreeEncoding).toEqual(1);
+                expect(report.options.keepRootHistory).toBe(true);
+                expect(report.options.readonly).toBe(false);
+                const state = report.prefixes.find(p => p.name === 'state');
+                expect(state.prefix).toEqual('00');
+                expect(state.keys).toEqual(3);
+                expect(report.prefixes.find(p => p.name === 'rootHistory').keys).toEqual(1);
+                expect(report.errors.revert).toEqual(before.errors.revert + 1);
+                expect(Array.isArray(report.slowOperations)).toBe(true);
+                expect(report.environment.node).toEqual(process.version);
+                expect(() => JSON.stringify(report)).not.toThrow();
+            });
+        });
+
+        describe('deletion audit', () => {
+            let auditDB;
+
+            beforeAll(async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                auditDB = new StateDB(dbPath, { auditDeletion: true });
+                const keys = [...new Array(100)].map(() => Buffer.concat([Buffer.from([0, 0, 0, 1, 0, 0]), getRandomBytes(8)]));
+                let prevRoot = Buffer.alloc(0);
+                for (let height = 0; height < 3; height += 1) {
+                    const writer = auditDB.newReadWriter();
+                    const updated = height === 0 ? keys : keys.slice(0, 10);
+                    for (const key of updated) {
+                        await writer.set(key, getRandomBytes());
+                    }
+                    prevRoot = await auditDB.commit(writer, height, prevRoot);
+                    writer.close();
+                }
+            });
+
+            afterAll(() => {
+                auditDB.close();
+            });
+
+            it('should record the removed SMT nodes on commit', async () => {
+                const records = await auditDB.getAuditRecords();
+
+                expect(records).toHaveLength(2);
+                expect(records[0].policy).toEqual('nodeRemoval');
+                expect(records[0].height).toEqual(1);
+                expect(records[0].removedCount).toBeGreaterThan(0);
+                expect(records[0].removedBytes).toBeGreaterThan(0);
+                expect(records[0].writtenCount).toBeGreaterThan(0);
+                expect(records[1].sequence).toEqual(1);
+                expect(records[1].prevHash).toEqual(records[0].hash);
+            });
+
+            it('should record the pruned diffs on finalize', async () => {
+                await auditDB.finalize(2);
+                const records = await auditDB.getAuditRecords();
+                const last = records[records.length - 1];
+
+                expect(last.policy).toEqual('diffPruning');
+                expect(last.height).toEqual(2);
+                expect(last.removedCount).toEqual(2);
+                expect(last.writtenCount).toEqual(0);
+                await expect(auditDB.verifyAuditTrail()).resolves.toEqual(true);
+            });
+
+            it('should not record when nothing is removed', async () => {
+                const before = await auditDB.getAuditRecords();
+                await auditDB.finalize(2);
+
+                await expect(auditDB.getAuditRecords()).resolves.toHaveLength(before.length);
+            });
+
+            it('should not record by default', async () => {
+                await expect(db.getAuditRecords()).resolves.toHaveLength(0);
+            });
+        });
+
+        describe('optimistic concurrency', () => {
+            let conflictDB;
+
+            beforeEach(() => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                conflictDB = new StateDB(dbPath);
+            });
+
+            afterEach(() => {
+                conflictDB.close();
+            });
+
+            it('should commit when the current state is the expected state', async () => {
+                const expectedState = await conflictDB.getCurrentState();
+                const writer = conflictDB.newReadWriter();
+                await writer.set(getRandomBytes(), getRandomBytes());
+                const nextRoot = await conflictDB.commit(writer, 1, Buffer.alloc(0), { expectedState });
+                writer.close();
+
+                await expect(conflictDB.getCurrentState()).resolves.toEqual({ root: nextRoot, version: 1 });
+            });
+
+            it('should reject the commit when another commit landed first', async () => {
+                const expectedState = await conflictDB.getCurrentState();
+                const first = conflictDB.newReadWriter();
+                await first.set(getRandomBytes(), getRandomBytes());
+                const second = conflictDB.newReadWriter();
+                const secondKey = getRandomBytes();
+                await second.set(secondKey, getRandomBytes());
+
+                const nextRoot = await conflictDB.commit(first, 1, Buffer.alloc(0), { expectedState });
+                const result = conflictDB.commit(second, 1, Buffer.alloc(0), { expectedState });
+                await expect(result).rejects.toThrow(CommitConflictError);
+                await expect(result).rejects.toMatchObject({
+                    expected: expectedState,
+                    current: { root: nextRoot, version: 1 },
+                });
+                await expect(conflictDB.has(secondKey)).resolves.toEqual(false);
+                first.close();
+                second.close();
+            });
+        });
+
+        describe('rollback protection', () => {
+            let protectedDB;
+            let roots;
+
+            beforeEach(async () => {
+                const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+                fs.mkdirSync(dbPath, { recursive: true });
+                protectedDB = new StateDB(dbPath, { rollbackProtection: true, keepRootHistory: true });
+                roots = [];
+                let prevRoot = Buffer.alloc(0);
+                for (let height = 0; height < 3; height += 1) {
+                    const writer = protectedDB.newReadWriter();
+                    await writer.set(getRandomBytes(38), getRandomBytes());
+                    prevRoot = await protectedDB.commit(writer, height, prevRoot);
+                    writer.close();
+                    roots.push(prevRoot);
+                }
+            });
+
+            afterEach(() => {
+                protectedDB.close();
+            });
+
+            it('should record the finalized height and root', async () => {
+                await expect(protectedDB.getFinalizedState()).resolves.toBeNull();
+                await protectedDB.finalize(1);
+
+                await expect(protectedDB.getFinalizedState()).resolves.toEqual({ height: 1, root: roots[1] });
+            });
+
+            it('should not move the finalized height backward', async () => {
+                await protectedDB.finalize(2);
+                await protectedDB.finalize(1);
+
+                await expect(protectedDB.getFinalizedState()).resolves.toEqual({ height: 2, root: roots[2] });
+            });
+
+            it('should refuse to commit at or below the finalized height', async () => {
+                await protectedDB.finalize(2);
+                const writer = protectedDB.newReadWriter();
+                await writer.set(getRandomBytes(38), getRandomBytes());
+
+                await expect(protectedDB.commit(writer, 2, roots[1])).rejects.toThrow('finalized height');
+                await expect(protectedDB.commit(writer, 3, roots[2])).resolves.toBeInstanceOf(Buffer);
+                writer.close();
+            });
+
+            it('should refuse to revert the finalized height', async () => {
+                await protectedDB.finalize(2);
+
+                await expect(protectedDB.revert(roots[2], 2)).rejects.toThrow('finalized height');
+            });
+
+            it('should not record the finalized state by default', async () => {
+                await db.finalize(1);
+
+                await expect(db.getFinalizedState()).resolves.toBeNull();
+            });
+        });
+
+        describe('exportChunks', () => {
+            let root;
+
+            beforeEach(async () => {
+                const currentState = await db.getCurrentState();
+                root = currentState.root;
+            });
+
+            it('should export the same chunks for the same root', async () => {
+                const chunks = await db.exportChunks(root, 2);
+
+                expect(chunks.length).toBeGreaterThan(1);
+                await expect(db.exportChunks(root, 2)).resolves.toEqual(chunks);
+            });
+
+            it('should export chunks which are verifiable against the root', async () => {
+                const chunks = await db.exportChunks(root, 2);
+                const smt = new SparseMerkleTree();
+
+                for (const chunk of chunks) {
+                    await expect(smt.verifyChunk(root, chunk)).resolves.toEqual(true);
+                    await expect(smt.verifyChunk(getRandomBytes(32), chunk)).resolves.toEqual(false);
+                }
+            });
+
+            it('should import the chunks into the tree with the same root', async () => {
+                const chunks = await db.exportChunks(root, 2);
+                const smt = new SparseMerkleTree();
+
+                await expect(smt.importChunks(root, chunks)).resolves.toEqual(root);
+            });
+
+            it('should stream the chunks in the order of the index', async () => {
+                const chunks = await db.exportChunks(root, 2);
+                const streamed = [];
+
+                await expect(db.exportChunkStream(root, 2, chunk => {
+                    streamed.push(chunk);
+                })).resolves.toEqual(chunks.length);
+                expect(streamed).toEqual(chunks);
+            });
+
+            it('should stop the stream when onChunk returns false', async () => {
+                const streamed = [];
+
+                await db.exportChunkStream(root, 2, chunk => {
+                    streamed.push(chunk);
+                    return false;
+                });
+                expect(streamed).toHaveLength(1);
+                await expect(db.exportChunkStream(root, 2, () => {
+                    throw new Error('chunk error');
+                })).rejects.toThrow('chunk error');
+            });
+
+            it('should import the chunks one by one from the stream', async () => {
+                const chunks = await db.exportChunks(root, 2);
+                const smt = new SparseMerkleTree();
+                async function* stream() {
+                    for (const chunk of chunks) {
+                        yield chunk;
+                    }
+                }
+
+                await expect(smt.importChunks(root, stream())).resolves.toEqual(root);
+            });
+
+            it('should reject the import when a chunk is missing', async () => {
+                const chunks = await db.exportChunks(root, 2);
+                const smt = new SparseMerkleTree();
+
+                await expect(smt.importChunks(root, chunks.slice(1))).rejects.toThrow();
+                await expect(smt.importChunks(root, chunks.slice(0, -1))).rejects.toThrow();
+            });
+
+            it('should reject zero chunk size', async () => {
+                await expect(db.exportChunks(root, 0)).rejects.toThrow();
+            });
+        });
+
+        describe('verifyStateRoot', () => {
+            it('should verify the current root against the flat state and the subtrees', async () => {
+                const { root } = await db.getCurrentState();
+                const check = await db.verifyStateRoot(root);
+
+                expect(check.valid).toBe(true);
+                expect(check.repaired).toBe(false);
+                expect(check.computedRoot).toEqual(root);
+                expect(check.currentRoot).toEqual(root);
+                expect(check.inconsistentNodes).toHaveLength(0);
+            });
+
+            it('should report the root which is not the current state', async () => {
+                const expectedRoot = getRandomBytes(32);
+                const check = await db.verifyStateRoot(expectedRoot, { repair: true });
+
+                expect(check.valid).toBe(false);
+                expect(check.repaired).toBe(false);
+                expect(check.expectedRoot).toEqual(expectedRoot);
+                expect(check.inconsistentNodes).toEqual([expectedRoot]);
+            });
         });
 
         describe('calculateRoot', () => {
@@ -707,3 +1706,257 @@ describe('statedb', () => {
         });
     });
 });
+
+describe('InMemoryStateDB', () => {
+    const pairs = [
+        { key: Buffer.from([0, 0, 0, 0, 0, 0, 0, 1]), value: getRandomBytes() },
+        { key: Buffer.from([0, 0, 0, 0, 0, 0, 0, 2]), value: getRandomBytes() },
+        { key: Buffer.from([0, 0, 0, 0, 0, 1, 0, 0]), value: getRandomBytes() },
+    ];
+
+    const commitPairs = async db => {
+        const writer = db.newReadWriter();
+        for (const pair of pairs) {
+            await writer.set(pair.key, pair.value);
+        }
+        return db.commit(writer, 0, Buffer.alloc(0));
+    };
+
+    it('should commit, prove and revert without creating the files', async () => {
+        const db = new InMemoryStateDB();
+        const root = await commitPairs(db);
+
+        const dbPath = path.join(os.tmpdir(), 'state', Date.now().toString());
+        fs.mkdirSync(dbPath, { recursive: true });
+        const fileDB = new StateDB(dbPath);
+        await expect(commitPairs(fileDB)).resolves.toEqual(root);
+        fileDB.close();
+
+        await expect(db.get(pairs[0].key)).resolves.toEqual(pairs[0].value);
+        const values = await new Promise((resolve, reject) => {
+            const result = [];
+            db.iterate({ gte: pairs[0].key, lte: pairs[1].key })
+                .on('data', kv => result.push(kv))
+                .on('error', err => reject(err))
+                .on('end', () => resolve(result));
+        });
+        expect(values).toEqual([pairs[0], pairs[1]]);
+
+        const writer = db.newReadWriter();
+        await writer.del(pairs[2].key);
+        const nextRoot = await db.commit(writer, 1, root);
+        await expect(db.getDiffStats(1)).resolves.toMatchObject({ deleted: { count: 1 } });
+        const queries = [Buffer.concat([pairs[2].key.subarray(0, 6), sha256(pairs[2].key.subarray(6))])];
+        const proof = await db.prove(nextRoot, queries);
+        await expect(db.verifyNonInclusionProof(nextRoot, queries, proof)).resolves.toBe(true);
+
+        await expect(db.revert(1)).resolves.toEqual(root);
+        await expect(db.get(pairs[2].key)).resolves.toEqual(pairs[2].value);
+        db.close();
+    });
+
+    it('should not share the data between the instances', async () => {
+        const db = new InMemoryStateDB();
+        await commitPairs(db);
+        const other = new InMemoryStateDB();
+        await expect(other.get(pairs[0].key)).rejects.toThrow(NotFoundError);
+        db.close();
+        other.close();
+    });
+});
+
+describe('smtKeyFilter', () => {
+    const treeKey = key => Buffer.concat([key.subarray(0, 6), sha256(key.subarray(6))]);
+
+    it('should return the value hash of the key in the tree', async () => {
+        const db = new InMemoryStateDB({ smtKeyFilter: true });
+        const key = getRandomBytes(10);
+        const value = getRandomBytes();
+        const writer = db.newReadWriter();
+        await writer.set(key, value);
+        const root = await db.commit(writer, 0, Buffer.alloc(0));
+
+        await expect(db.getValueHash(root, treeKey(key))).resolves.toEqual(sha256(value));
+        await expect(db.getValueHash(root, treeKey(getRandomBytes(10)))).rejects.toThrow(NotFoundError);
+
+        const next = db.newReadWriter();
+        await next.del(key);
+        const nextRoot = await db.commit(next, 1, root);
+        await expect(db.getValueHash(nextRoot, treeKey(key))).rejects.toThrow(NotFoundError);
+        await expect(db.revert(1)).resolves.toEqual(root);
+        await expect(db.getValueHash(root, treeKey(key))).resolves.toEqual(sha256(value));
+        db.close();
+    });
+});
+
+describe('storeRoots', () => {
+    const storeA = Buffer.from('000000010000', 'hex');
+    const storeB = Buffer.from('000000020000', 'hex');
+
+    it('should keep the sub-root of each store updated by the commit', async () => {
+        const db = new InMemoryStateDB({ storeRoots: true, keepRootHistory: true });
+        const writer = db.newReadWriter();
+        await writer.set(Buffer.concat([storeA, getRandomBytes(4)]), getRandomBytes());
+        await writer.set(Buffer.concat([storeB, getRandomBytes(4)]), getRandomBytes());
+        const root = await db.commit(writer, 1, Buffer.alloc(0));
+        const storeARoot = await db.getStoreRoot(storeA, 1);
+        const storeBRoot = await db.getStoreRoot(storeB, 1);
+
+        const next = db.newReadWriter();
+        await next.set(Buffer.concat([storeA, getRandomBytes(4)]), getRandomBytes());
+        const nextRoot = await db.commit(next, 2, root);
+        await expect(db.getStoreRoot(storeA)).resolves.not.toEqual(storeARoot);
+        await expect(db.getStoreRoot(storeB)).resolves.toEqual(storeBRoot);
+        await expect(db.getStoreRoot(Buffer.from('000000030000', 'hex'))).rejects.toThrow(NotFoundError);
+
+        const current = await db.proveStoreRoot(storeA);
+        expect(current.stateRoot).toEqual(nextRoot);
+        await expect(db.getStoreRoot(storeA)).resolves.toEqual(current.storeRoot);
+        await expect(db.verifyPrefix(nextRoot, current.proof)).resolves.toBe(true);
+        const past = await db.proveStoreRoot(storeA, 1);
+        expect(past.stateRoot).toEqual(root);
+        expect(past.storeRoot).toEqual(storeARoot);
+
+        await expect(db.revert(2)).resolves.toEqual(root);
+        await expect(db.getStoreRoot(storeA)).resolves.toEqual(storeARoot);
+        db.close();
+    });
+});
+
+describe('structureLog', () => {
+    it('should record the subtrees created and deleted by each commit', async () => {
+        const db = new InMemoryStateDB({ structureLog: true });
+        const key = Buffer.concat([Buffer.from('000000010000', 'hex'), getRandomBytes(4)]);
+        const writer = db.newReadWriter();
+        await writer.set(key, getRandomBytes());
+        for (let i = 0; i < 50; i += 1) {
+            await writer.set(Buffer.concat([Buffer.from('000000010000', 'hex'), getRandomBytes(4)]), getRandomBytes());
+        }
+        const root = await db.commit(writer, 1, Buffer.alloc(0));
+        const first = await db.getStructureLog(1);
+        expect(first.deleted).toEqual([]);
+        expect(first.created.map(change => change.hash)).toContainEqual(root);
+        for (const change of first.created) {
+            expect(change.height).toBeGreaterThanOrEqual(0);
+            expect(change.height).toBeLessThanOrEqual(4);
+        }
+
+        const next = db.newReadWriter();
+        await next.set(key, getRandomBytes());
+        await db.commit(next, 2, root);
+        const second = await db.getStructureLog(2);
+        expect(second.deleted.length).toBeGreaterThan(0);
+        for (const change of second.deleted) {
+            expect(first.created).toContainEqual(change);
+        }
+
+        await expect(db.revert(2)).resolves.toEqual(root);
+        await expect(db.getStructureLog(2)).rejects.toThrow(NotFoundError);
+        db.close();
+    });
+});
+
+describe('newOverlay', () => {
+    it('should compute the roots of the tentative updates without writing them', async () => {
+        const db = new InMemoryStateDB();
+        const keys = Array.from({ length: 20 }, () => Buffer.concat([Buffer.from('000000010000', 'hex'), getRandomBytes(4)]));
+        const writer = db.newReadWriter();
+        for (const key of keys) {
+            await writer.set(key, getRandomBytes());
+        }
+        const root = await db.commit(writer, 1, Buffer.alloc(0));
+
+        const overlay = await db.newOverlay();
+        expect(overlay.root).toEqual(root);
+        const newKey = Buffer.concat([Buffer.from('000000020000', 'hex'), getRandomBytes(4)]);
+        const newValue = getRandomBytes();
+        const first = new Batch();
+        first.set(newKey, newValue);
+        first.del(keys[3]);
+        const firstRoot = await overlay.update(first);
+        const updatedValue = getRandomBytes();
+        const second = new Batch();
+        second.set(keys[5], updatedValue);
+        const secondRoot = await overlay.update(second.encode());
+        expect(overlay.root).toEqual(secondRoot);
+        await expect(overlay.update(Batch.decode(new Batch().encode()))).resolves.toEqual(secondRoot);
+
+        // the state is not changed by the overlay
+        await expect(db.getCurrentState()).resolves.toEqual({ root, version: 1 });
+        await expect(db.get(newKey)).rejects.toThrow(NotFoundError);
+
+        const next = db.newReadWriter();
+        await next.set(newKey, newValue);
+        await next.del(keys[3]);
+        await expect(db.commit(next, 2, root)).resolves.toEqual(firstRoot);
+        const last = db.newReadWriter();
+        await last.set(keys[5], updatedValue);
+        await expect(db.commit(last, 3, firstRoot)).resolves.toEqual(secondRoot);
+
+        const invalid = new Batch();
+        invalid.set(Buffer.alloc(3), getRandomBytes());
+        await expect(overlay.update(invalid)).rejects.toThrow();
+        expect(overlay.root).toEqual(secondRoot);
+        overlay.discard();
+        await expect(overlay.update(second)).rejects.toThrow('Overlay is already discarded.');
+        db.close();
+    });
+});
+
+describe('generateWitness', () => {
+    it('should prove the values and the missing keys read by the block against the root', async () => {
+        const db = new InMemoryStateDB();
+        const pairs = Array.from({ length: 10 }, () => ({ key: getRandomBytes(10), value: getRandomBytes() }));
+        const writer = db.newReadWriter();
+        for (const pair of pairs) {
+            await writer.set(pair.key, pair.value);
+        }
+        const root = await db.commit(writer, 1, Buffer.alloc(0));
+        const missingKey = getRandomBytes(10);
+
+        const witness = await db.generateWitness(root, [pairs[2].key, missingKey, pairs[5].key]);
+        const smt = new SparseMerkleTree();
+        const entries = await smt.verifyWitness(root, witness);
+        expect(entries).toEqual([
+            { key: pairs[2].key, value: pairs[2].value },
+            { key: missingKey },
+            { key: pairs[5].key, value: pairs[5].value },
+        ]);
+        await expect(smt.verifyWitness(getRandomBytes(32), witness)).rejects.toThrow();
+        await expect(db.generateWitness(root, [Buffer.alloc(3)])).rejects.toThrow();
+        db.close();
+    });
+});
+
+describe('proveBatchWindow', () => {
+    it('should return the same proofs for the coalesced prove calls', async () => {
+        const db = new InMemoryStateDB({ proveBatchWindow: 5 });
+        const keys = Array.from({ length: 20 }, () => getRandomBytes(10));
+        const writer = db.newReadWriter();
+        for (const key of keys) {
+            await writer.set(key, getRandomBytes());
+        }
+        const root = await db.commit(writer, 1, Buffer.alloc(0));
+        const treeKeys = keys.map(key => Buffer.concat([key.subarray(0, 6), sha256(key.subarray(6))]));
+        const querySets = [treeKeys.slice(0, 10), treeKeys.slice(5, 15), [getRandomBytes(38), treeKeys[3]]];
+
+        const proofs = await Promise.all(querySets.map(queries => db.prove(root, queries)));
+        // the proofs of the calls proven one by one
+        const expected = [];
+        for (const queries of querySets) {
+            expected.push(await db.prove(root, queries));
+        }
+        expect(proofs).toEqual(expected);
+        for (let i = 0; i < querySets.length; i++) {
+            await expect(db.verify(root, querySets[i], proofs[i])).resolves.toBe(true);
+        }
+
+        const [valid, invalid] = await Promise.allSettled([
+            db.prove(root, treeKeys.slice(0, 3)),
+            db.prove(root, [getRandomBytes(5)]),
+        ]);
+        expect(valid.status).toEqual('fulfilled');
+        expect(invalid.status).toEqual('rejected');
+        db.close();
+    });
+});
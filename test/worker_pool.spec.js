@@ -0,0 +1,79 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const { SparseMerkleTree, configureWorkerPool, shutdownWorkerPool, getWorkerPoolStats } = require('../main');
+const { getRandomBytes } = require('./utils');
+
+describe('worker pool', () => {
+    const updateTree = async () => {
+        const smt = new SparseMerkleTree();
+        const pairs = [];
+        for (let i = 0; i < 10; i += 1) {
+            pairs.push({ key: getRandomBytes(), value: getRandomBytes() });
+        }
+        const root = await smt.update(Buffer.alloc(0), pairs);
+        const proof = await smt.prove(root, pairs.map(p => p.key));
+        return { smt, root, pairs, proof };
+    };
+
+    afterAll(() => {
+        shutdownWorkerPool();
+    });
+
+    it('should create the pool with at least one worker by default', () => {
+        const stats = getWorkerPoolStats();
+
+        expect(stats.size).toBeGreaterThanOrEqual(1);
+        expect(stats.queueCapacity).toBeGreaterThanOrEqual(stats.size);
+    });
+
+    it('should run the operations on the configured pool', async () => {
+        configureWorkerPool({ size: 2, queueCapacity: 1000 });
+        expect(getWorkerPoolStats()).toEqual({ size: 2, queueCapacity: 1000 });
+
+        const results = await Promise.all(new Array(100).fill(0).map(async () => updateTree()));
+        for (const { smt, root, pairs, proof } of results) {
+            await expect(smt.verify(root, pairs.map(p => p.key), proof)).resolves.toEqual(true);
+        }
+    });
+
+    it('should reject the operations exceeding the queue capacity', async () => {
+        configureWorkerPool({ size: 1, queueCapacity: 1 });
+        const smt = new SparseMerkleTree();
+        const updates = new Array(20)
+            .fill(0)
+            .map(async () => smt.update(Buffer.alloc(0), [{ key: getRandomBytes(), value: getRandomBytes() }]));
+        const results = await Promise.allSettled(updates);
+
+        const rejected = results.filter(r => r.status === 'rejected');
+        expect(rejected.length).toBeGreaterThan(0);
+        expect(rejected[0].reason.message).toMatch('Task queue is full');
+        expect(results.filter(r => r.status === 'fulfilled').length).toBeGreaterThan(0);
+    });
+
+    it('should create a new pool after shutting down', async () => {
+        configureWorkerPool({ size: 2 });
+        shutdownWorkerPool();
+
+        await expect(updateTree()).resolves.toBeDefined();
+    });
+
+    it('should throw for an invalid size', () => {
+        expect(() => configureWorkerPool({ size: 0 })).toThrow('Worker pool size must be a positive number');
+        expect(() => configureWorkerPool({ size: 1, queueCapacity: 'large' })).toThrow(
+            'Worker pool queue capacity must be a number',
+        );
+    });
+});
@@ -0,0 +1,174 @@
+/// lisk-db-tool inspects the data directory of lisk-db while the node is stopped or running.
+/// The data directory is opened read only, except for the target of the import.
+use std::env;
+use std::error::Error;
+use std::process;
+
+use lisk_db::consts;
+use lisk_db::state::inspect;
+use lisk_db::types::KeyLength;
+
+const USAGE: &str = "Usage: lisk-db-tool <command> [arguments]
+
+Commands:
+  keys <data-dir> [prefix] [--limit <n>]      dump the keys and the values starting with the prefix
+  root <data-dir>                             show the root and the version of the current state
+  verify <data-dir> [--root <root>] [--key-length <n>]
+                                              verify the root against the state and the tree
+  diffs <data-dir> [--from <height>] [--to <height>]
+                                              print the keys changed by each height
+  export <data-dir> <backup-dir>              create a new backup of the data directory
+  import <backup-dir> <data-dir>              restore the latest backup to the data directory
+
+The prefix and the root are hex encoded.";
+
+/// Args holds the positional arguments and the options of the command.
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut positional = vec![];
+        let mut options = vec![];
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing the value of --{}", name))?;
+                    options.push((name.to_string(), value));
+                },
+                None => positional.push(arg),
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.positional
+            .get(index)
+            .map(|arg| arg.as_str())
+            .ok_or_else(|| format!("missing <{}>", name).into())
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn option_u32(&self, name: &str) -> Result<Option<u32>, Box<dyn Error>> {
+        match self.option(name) {
+            Some(value) => Ok(Some(value.parse()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn keys(args: &Args) -> Result<(), Box<dyn Error>> {
+    let conn = inspect::open(args.positional(0, "data-dir")?)?;
+    let prefix = match args.positional.get(1) {
+        Some(prefix) => hex::decode(prefix)?,
+        None => vec![],
+    };
+    let limit = args
+        .option_u32("limit")?
+        .map_or(usize::MAX, |limit| limit as usize);
+    for key_val in inspect::pairs(&conn, &prefix).take(limit) {
+        let (key, value) = key_val?;
+        println!("{} {}", hex::encode(key), hex::encode(value));
+    }
+    Ok(())
+}
+
+fn root(args: &Args) -> Result<(), Box<dyn Error>> {
+    let conn = inspect::open(args.positional(0, "data-dir")?)?;
+    let (root, version) = inspect::current_state(&conn)?;
+    println!("root: {}", hex::encode(root));
+    println!("version: {}", u32::from(version));
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<(), Box<dyn Error>> {
+    let conn = inspect::open(args.positional(0, "data-dir")?)?;
+    let expected_root = args.option("root").map(hex::decode).transpose()?;
+    let key_length = args
+        .option_u32("key-length")?
+        .map_or(consts::KEY_LENGTH, |length| KeyLength(length as u16));
+    let check = inspect::verify(conn, expected_root.as_deref(), key_length)?;
+    println!("expected root: {}", hex::encode(&check.expected_root));
+    println!("current root: {}", hex::encode(&check.current_root));
+    println!("computed root: {}", hex::encode(&check.computed_root));
+    for node in check.inconsistent_nodes.iter() {
+        println!("inconsistent node: {}", hex::encode(node));
+    }
+    if !check.is_valid() {
+        return Err("the state does not match the root".into());
+    }
+    println!("valid");
+    Ok(())
+}
+
+fn diffs(args: &Args) -> Result<(), Box<dyn Error>> {
+    let conn = inspect::open(args.positional(0, "data-dir")?)?;
+    let from = args.option_u32("from")?.unwrap_or(0);
+    let to = args.option_u32("to")?.unwrap_or(u32::MAX);
+    for record in inspect::diffs(&conn, from, to)? {
+        println!("height {}", record.height);
+        for (name, keys) in [
+            ("created", &record.created),
+            ("updated", &record.updated),
+            ("deleted", &record.deleted),
+        ] {
+            for key in keys.iter() {
+                println!("  {} {}", name, hex::encode(key));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export(args: &Args) -> Result<(), Box<dyn Error>> {
+    let conn = inspect::open(args.positional(0, "data-dir")?)?;
+    inspect::export_backup(&conn, args.positional(1, "backup-dir")?)?;
+    Ok(())
+}
+
+fn import(args: &Args) -> Result<(), Box<dyn Error>> {
+    inspect::import_backup(
+        args.positional(0, "backup-dir")?,
+        args.positional(1, "data-dir")?,
+    )?;
+    Ok(())
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(USAGE.into()),
+    };
+    let args = Args::parse(args)?;
+    match command.as_str() {
+        "keys" => keys(&args),
+        "root" => root(&args),
+        "verify" => verify(&args),
+        "diffs" => diffs(&args),
+        "export" => export(&args),
+        "import" => import(&args),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -12,13 +12,62 @@
  * Removal or modification of this copyright notice is prohibited.
  */
 
-export interface Options {
+export interface TuningOptions {
+    // size of the block cache in bytes
+    blockCacheSize?: number;
+    compression?: 'lz4' | 'zstd' | 'none';
+    maxOpenFiles?: number;
+    // size of the memtable in bytes
+    writeBufferSize?: number;
+    // bytes per second written by the flush and the compaction
+    rateLimit?: number;
+    // threads of the flush and the compaction in the background, which is 1 by default
+    parallelism?: number;
+    maxBackgroundCompactions?: number;
+    maxBackgroundFlushes?: number;
+    // read the files bypassing the page cache of the OS, which is ignored for inMemory
+    directReads?: boolean;
+    // keep the index and the filter blocks in the block cache, and the ones of the level 0 files pinned in it
+    cacheIndexAndFilterBlocks?: boolean;
+    pinL0FilterAndIndexBlocks?: boolean;
+    // keep the files in memory instead of the path, which are dropped on close
+    inMemory?: boolean;
+}
+
+export interface Options extends TuningOptions {
     readonly?: boolean;
+    lockTimeout?: number;
+    secondaryPath?: string;
+    maxOpenIterators?: number;
+    debug?: boolean;
 }
 
-export interface StateDBOptions {
+export interface StateDBOptions extends TuningOptions {
     readonly?: boolean;
+    lockTimeout?: number;
     keyLength?: number;
+    keepRootHistory?: boolean;
+    // keep only the roots and the nodes of the latest heights in the root history
+    rootHistoryRetention?: number;
+    auditDeletion?: boolean;
+    rollbackProtection?: boolean;
+    diffRetention?: number;
+    // keep the bloom filter of the SMT keys of the roots to rule out the missing keys in getValueHash
+    smtKeyFilter?: boolean;
+    // keep the sub-root of each store prefix updated by the commit for getStoreRoot
+    storeRoots?: boolean;
+    // record the subtrees created and deleted by each commit for getStructureLog
+    structureLog?: boolean;
+    // prove the prove calls for the same root arriving within the window in milliseconds with a single traversal of the tree
+    proveBatchWindow?: number;
+    secondaryPath?: string;
+    maxOpenIterators?: number;
+    debug?: boolean;
+}
+
+export interface OpenResource {
+    kind: string;
+    stack?: string;
 }
 
 export interface IterateOptions {
@@ -28,7 +77,105 @@ export interface IterateOptions {
     lte?: Buffer;
 }
 
-export class NotFoundError extends Error { }
+export interface RangeOptions extends IterateOptions {
+    gt?: Buffer;
+    lt?: Buffer;
+}
+
+export interface PagedIterateOptions extends IterateOptions {
+    pageSize?: number;
+    signal?: AbortSignal;
+}
+
+export interface AbortableIterateOptions extends IterateOptions {
+    signal?: AbortSignal;
+}
+
+export interface AbortOptions {
+    signal?: AbortSignal;
+}
+
+export interface GetStreamOptions {
+    // chunkSize is the size of the chunks in bytes. It defaults to 64KiB.
+    chunkSize?: number;
+}
+
+export interface WriteOptions {
+    sync?: boolean;
+    disableWAL?: boolean;
+}
+
+export interface CatchUpResult {
+    changed: boolean;
+    sequenceNumber: number;
+}
+
+export type PrimaryChangeListener = (err: Error | null, result?: CatchUpResult) => void | Promise<void>;
+
+export interface ColumnFamilyProperties {
+    estimatedKeys: number;
+    totalSstSize: number;
+    memtableUsage: number;
+    pendingCompactionBytes: number;
+}
+
+export interface DatabaseProperties extends ColumnFamilyProperties {
+    columnFamilies: { [name: string]: ColumnFamilyProperties };
+}
+
+export interface WriteStall {
+    condition: 'normal' | 'delayed' | 'stopped';
+    // bytes per second of the delayed writes, 0 unless the condition is delayed
+    delayedWriteRate: number;
+}
+
+export type WriteStallListener = (err: Error | null, stall?: WriteStall) => void | Promise<void>;
+
+export interface RangeSize {
+    size: number;
+    estimatedKeys: number;
+}
+
+export declare const ErrorCode: {
+    readonly NOT_FOUND: 'ERR_NOT_FOUND';
+    readonly INVALID_ROOT: 'ERR_INVALID_ROOT';
+    readonly INVALID_INPUT: 'ERR_INVALID_INPUT';
+    readonly INVALID_USAGE: 'ERR_INVALID_USAGE';
+    readonly ABORTED: 'ERR_ABORTED';
+    readonly BUSY: 'ERR_BUSY';
+    readonly LIMIT_EXCEEDED: 'ERR_LIMIT_EXCEEDED';
+    readonly CORRUPTION: 'ERR_CORRUPTION';
+    readonly IO: 'ERR_IO';
+    readonly DATABASE: 'ERR_DATABASE';
+    readonly UNKNOWN: 'ERR_UNKNOWN';
+    readonly CLOSED: 'ERR_CLOSED';
+    readonly INTERNAL: 'ERR_INTERNAL';
+};
+export type ErrorCodeValue = typeof ErrorCode[keyof typeof ErrorCode];
+export interface CodedError extends Error {
+    code?: ErrorCodeValue | string;
+}
+export class NotFoundError extends Error {
+    code: 'ERR_NOT_FOUND';
+}
+export class MaxOpenResourcesError extends Error { }
+export class AbortError extends Error {
+    code: 'ERR_ABORTED';
+}
+export class DatabaseClosedError extends Error {
+    code: 'ERR_CLOSED';
+}
+export class AlreadyLockedError extends Error {
+    path: string;
+    pid: number | null;
+}
+export class CommitConflictError extends Error {
+    expected: CurrentState;
+    current: CurrentState;
+}
+export class BatchConflictError extends Error {
+    key: Buffer;
+}
 
 interface DatabaseReader {
     get(key: Buffer): Promise<Buffer>;
@@ -40,16 +187,65 @@ interface DatabaseReader {
 export class Database {
     constructor(path: string, option?: Options);
     get(key: Buffer): Promise<Buffer>;
+    getMany(keys: Buffer[]): Promise<(Buffer | undefined)[]>;
+    getStream(key: Buffer, onChunk: (chunk: Buffer) => boolean | void, options?: GetStreamOptions): Promise<number>;
     has(key: Buffer): Promise<boolean>;
     set(key: Buffer, value: Buffer): Promise<void>;
     del(key: Buffer): Promise<void>;
-    write(batch: Batch): Promise<void>;
-    iterate(options?: IterateOptions): NodeJS.ReadableStream;
-    createReadStream(options?: IterateOptions): NodeJS.ReadableStream;
+    write(batch: Batch, options?: WriteOptions): Promise<void>;
+    iterate(options?: PagedIterateOptions): NodeJS.ReadableStream;
+    createReadStream(options?: PagedIterateOptions): NodeJS.ReadableStream;
     clear(options?: IterateOptions): Promise<void>;
-    close(): void;
+    close(cb?: (err: Error | null) => void): void;
     newReader(): DatabaseReader;
+    snapshot(): Snapshot;
     checkpoint(path: string): Promise<void>;
+    createBackup(dir: string): Promise<void>;
+    compactRange(start?: Buffer, end?: Buffer): Promise<void>;
+    setAutoCompaction(enabled: boolean): void;
+    flush(): Promise<void>;
+    syncWal(): Promise<void>;
+    getProperties(): Promise<DatabaseProperties>;
+    approximateSize(start?: Buffer, end?: Buffer): Promise<RangeSize>;
+    getWriteStall(): Promise<WriteStall>;
+    onWriteStall(listener: WriteStallListener, interval?: number): () => void;
+    withPrefix(prefix: Buffer): PrefixedDatabase;
+    tryCatchUpWithPrimary(): Promise<CatchUpResult>;
+    followPrimary(listener: PrimaryChangeListener, interval?: number): () => void;
+    newTransaction(): Transaction;
+    getOpenResources(): OpenResource[];
+    getLeakedResources(): OpenResource[];
+}
+
+export class Snapshot {
+    get(key: Buffer): Promise<Buffer>;
+    has(key: Buffer): Promise<boolean>;
+    iterate(options?: IterateOptions): NodeJS.ReadableStream;
+    createReadStream(options?: IterateOptions): NodeJS.ReadableStream;
+    release(): void;
+}
+
+export class PrefixedDatabase {
+    get(key: Buffer): Promise<Buffer>;
+    getMany(keys: Buffer[]): Promise<(Buffer | undefined)[]>;
+    getStream(key: Buffer, onChunk: (chunk: Buffer) => boolean | void, options?: GetStreamOptions): Promise<number>;
+    has(key: Buffer): Promise<boolean>;
+    set(key: Buffer, value: Buffer): Promise<void>;
+    del(key: Buffer): Promise<void>;
+    withPrefix(prefix: Buffer): PrefixedDatabase;
+    approximateSize(start?: Buffer, end?: Buffer): Promise<RangeSize>;
+    iterate(options?: PagedIterateOptions): NodeJS.ReadableStream;
+    createReadStream(options?: PagedIterateOptions): NodeJS.ReadableStream;
+}
+
+export class Transaction {
+    get(key: Buffer): Promise<Buffer>;
+    has(key: Buffer): Promise<boolean>;
+    set(key: Buffer, value: Buffer): void;
+    del(key: Buffer): void;
+    iterate(options?: IterateOptions): NodeJS.ReadableStream;
+    createReadStream(options?: IterateOptions): NodeJS.ReadableStream;
+    commit(options?: WriteOptions): Promise<void>;
 }
 
 export class InMemoryDatabase {
@@ -66,9 +262,22 @@ export class InMemoryDatabase {
     close(): void;
 }
 
+export interface BatchOperation {
+    type: 'put' | 'del';
+    key: Buffer;
+    value?: Buffer;
+}
+
 export class Batch {
+    static decode(data: Buffer): Batch;
+    readonly length: number;
+    readonly size: number;
     set(key: Buffer, value: Buffer): void;
     del(key: Buffer): void;
+    setIfAbsent(key: Buffer, value: Buffer): void;
+    delIfEquals(key: Buffer, value: Buffer): void;
+    encode(): Buffer;
+    operations(): BatchOperation[];
 }
 
 declare class StateReader {
@@ -79,12 +288,58 @@ declare class StateReader {
     close(): void;
 }
 
+declare class StateOverlay {
+    readonly root: Buffer;
+    update(batch: Batch | Buffer): Promise<Buffer>;
+    discard(): void;
+}
+
+export interface ReadWriterOptions {
+    maxSize?: number;
+}
+
+export interface StateWriterUpdates {
+    updated: { key: Buffer, value: Buffer }[];
+    deleted: Buffer[];
+}
+
+export interface ChangedKeys {
+    storePrefix: Buffer;
+    created: Buffer[];
+    updated: Buffer[];
+    deleted: Buffer[];
+}
+
+export interface StateWriterOptions extends ReadWriterOptions {
+    db?: StateDB;
+}
+
+export class StateWriter {
+    constructor(options?: StateWriterOptions);
+    readonly size: number;
+    get(key: Buffer): Promise<Buffer>;
+    has(key: Buffer): Promise<boolean>;
+    set(key: Buffer, value: Buffer): Promise<void>;
+    cacheExisting(key: Buffer, value: Buffer): Promise<void>;
+    del(key: Buffer): Promise<void>;
+    deleteRange(start: Buffer, end: Buffer): void;
+    range(options?: RangeOptions): Promise<{ key: Buffer, value: Buffer }[]>;
+    getUpdated(): Promise<StateWriterUpdates>;
+    changedKeys(prefix?: Buffer): Promise<ChangedKeys[]>;
+    snapshot(): number;
+    restoreSnapshot(index: number): void;
+    close(): void;
+}
+
 declare class StateReadWriter {
+    readonly size: number;
     get(key: Buffer): Promise<Buffer>;
     has(key: Buffer): Promise<boolean>;
     set(key: Buffer, value: Buffer): Promise<void>;
     del(key: Buffer): Promise<void>;
-    range(options?: IterateOptions): Promise<{ key: Buffer, value: Buffer }[]>;
+    deleteRange(start: Buffer, end: Buffer): void;
+    range(options?: RangeOptions): Promise<{ key: Buffer, value: Buffer }[]>;
+    changedKeys(prefix?: Buffer): Promise<ChangedKeys[]>;
     snapshot(): number;
     restoreSnapshot(index: number): void;
     close(): void;
@@ -94,6 +349,7 @@ interface StateCommitOption {
     readonly?: boolean;
     checkRoot?: boolean;
     expectedRoot?: Buffer;
+    expectedState?: CurrentState;
 }
 
 interface Proof {
@@ -102,41 +358,338 @@ interface Proof {
         key: Buffer;
         value: Buffer;
         bitmap: Buffer;
+        storedValue?: Buffer;
     }[];
 }
 
+interface PrefixProof {
+    prefix: Buffer;
+    subtreeRoot: Buffer;
+    node: {
+        kind: 'empty' | 'leaf' | 'branch';
+        key?: Buffer;
+        value?: Buffer;
+        hash?: Buffer;
+    };
+    siblingHashes: Buffer[];
+}
+
+interface WitnessEntry {
+    key: Buffer;
+    value?: Buffer;
+}
+
+interface StoreRootProof {
+    stateRoot: Buffer;
+    storeRoot: Buffer;
+    proof: PrefixProof;
+}
+
+interface SubtreeChange {
+    hash: Buffer;
+    // height of the deepest node in the subtree
+    height: number;
+}
+
+interface StructureLog {
+    created: SubtreeChange[];
+    deleted: SubtreeChange[];
+}
+
+interface VisualizationNode {
+    type: 'branch' | 'leaf' | 'empty';
+    height: number;
+    hash: string;
+    key: string;
+    value: string;
+}
+
+interface ProveOptions {
+    includeValues?: boolean;
+}
+
 interface CurrentState {
     root: Buffer;
     version: number;
 }
 
+export interface StateDiff {
+    created: Buffer[];
+    updated: { key: Buffer; value: Buffer }[];
+    deleted: { key: Buffer; value: Buffer }[];
+}
+
+export interface DiffEntryStats {
+    count: number;
+    bytes: number;
+}
+
+export interface DiffStats {
+    created: DiffEntryStats;
+    updated: DiffEntryStats;
+    deleted: DiffEntryStats;
+    size: number;
+}
+
+export interface CommitEvent {
+    root: Buffer;
+    height: number;
+    diffStats: Omit<DiffStats, 'size'>;
+}
+
+export type CommitListener = (event: CommitEvent) => void;
+
+export interface FinalizedState {
+    height: number;
+    root: Buffer;
+}
+
+export interface AuditRecord {
+    sequence: number;
+    policy: 'diffPruning' | 'nodeRemoval';
+    height: number;
+    removedCount: number;
+    removedBytes: number;
+    writtenCount: number;
+    writtenBytes: number;
+    removedDigest: Buffer;
+    prevHash: Buffer;
+    hash: Buffer;
+}
+
+export interface VerifyStateRootOptions {
+    repair?: boolean;
+}
+
+export interface StateRootCheck {
+    valid: boolean;
+    repaired: boolean;
+    expectedRoot: Buffer;
+    currentRoot: Buffer;
+    computedRoot: Buffer;
+    inconsistentNodes: Buffer[];
+}
+
+export interface LifetimeStats {
+    commits: number;
+    keysWritten: number;
+    bytesWritten: number;
+}
+
+export interface DebugReport {
+    formatVersion: {
+        package: string;
+        subtreeEncoding: number;
+    };
+    options: {
+        readonly: boolean;
+        keyLength: number;
+        keepRootHistory: boolean;
+        auditDeletion: boolean;
+        rollbackProtection: boolean;
+        lockTimeout: number | null;
+        diffRetention: number | null;
+        rootHistoryRetention: number | null;
+    };
+    prefixes: { name: string; prefix: string; keys: number; bytes: number }[];
+    slowOperations: { operation: string; durationMs: number; timestamp: number }[];
+    errors: {
+        commit: number;
+        revert: number;
+        prove: number;
+        exportChunks: number;
+    };
+    environment: {
+        node: string;
+        platform: string;
+        arch: string;
+    };
+}
+
 export class StateDB {
     constructor(path: string, option?: StateDBOptions);
     get(key: Buffer): Promise<Buffer>;
+    getMany(keys: Buffer[]): Promise<(Buffer | undefined)[]>;
     has(key: Buffer): Promise<boolean>;
-    iterate(options?: IterateOptions): NodeJS.ReadableStream;
-    createReadStream(options?: IterateOptions): NodeJS.ReadableStream;
+    iterate(options?: AbortableIterateOptions): NodeJS.ReadableStream;
+    createReadStream(options?: AbortableIterateOptions): NodeJS.ReadableStream;
     revert(prevRoot: Buffer, height: number): Promise<Buffer>;
-    commit(readWriter: StateReadWriter, height: number, prevRoot: Buffer, options?: StateCommitOption): Promise<Buffer>;
-    prove(root: Buffer, queries: Buffer[]): Promise<Proof>;
+    revert(height: number): Promise<Buffer>;
+    calculateDiffBetween(fromHeight: number, toHeight: number): Promise<StateDiff>;
+    getDiffStats(height: number): Promise<DiffStats>;
+    commit(readWriter: StateReadWriter | StateWriter, height: number, prevRoot: Buffer, options?: StateCommitOption): Promise<Buffer>;
+    onCommit(listener: CommitListener): () => void;
+    getStats(): Stats;
+    prove(root: Buffer, queries: Buffer[], options?: ProveOptions): Promise<Proof>;
     verify(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
     verifyInclusionProof(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
     verifyNonInclusionProof(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
+    getValueHash(root: Buffer, key: Buffer): Promise<Buffer>;
+    provePrefix(root: Buffer, prefix: Buffer): Promise<PrefixProof>;
+    generateWitness(root: Buffer, keys: Buffer[]): Promise<Buffer>;
+    verifyPrefix(root: Buffer, proof: PrefixProof): Promise<boolean>;
+    getStoreRoot(storePrefix: Buffer, height?: number): Promise<Buffer>;
+    proveStoreRoot(storePrefix: Buffer, height?: number): Promise<StoreRootProof>;
+    getStructureLog(height: number): Promise<StructureLog>;
+    newOverlay(root?: Buffer): Promise<StateOverlay>;
+    toVisualization(root: Buffer, options?: { format?: 'json' }): Promise<VisualizationNode[]>;
+    toVisualization(root: Buffer, options: { format: 'csv' }): Promise<string>;
+    getRootAt(height: number): Promise<Buffer>;
+    getRootAtHeight(height: number): Promise<Buffer>;
+    findHeightForRoot(root: Buffer): Promise<number>;
+    proveAt(height: number, queries: Buffer[], options?: ProveOptions): Promise<Proof>;
+    exportChunkStream(root: Buffer, chunkSize: number, onChunk: (chunk: Buffer) => boolean | void): Promise<number>;
+    exportChunks(root: Buffer, chunkSize: number): Promise<Buffer[]>;
+    verifyStateRoot(expectedRoot: Buffer, options?: VerifyStateRootOptions): Promise<StateRootCheck>;
+    verifyAt(height: number, queries: Buffer[], proof: Proof): Promise<boolean>;
     finalize(height: number): Promise<void>;
+    pruneDiffs(untilHeight: number): Promise<void>;
+    getFinalizedState(): Promise<FinalizedState | null>;
+    getLifetimeStats(): Promise<LifetimeStats>;
+    debugReport(): Promise<DebugReport>;
+    getAuditRecords(): Promise<AuditRecord[]>;
+    verifyAuditTrail(): Promise<boolean>;
     newReader(): StateReader;
-    newReadWriter(): StateReadWriter;
-    close(): void;
+    newReadWriter(options?: ReadWriterOptions): StateReadWriter;
+    newWriter(options?: ReadWriterOptions): StateWriter;
+    close(cb?: (err: Error | null) => void): void;
     checkpoint(path: string): Promise<void>;
+    createBackup(dir: string): Promise<void>;
+    compactRange(start?: Buffer, end?: Buffer): Promise<void>;
+    setAutoCompaction(enabled: boolean): void;
+    flush(): Promise<void>;
+    syncWal(): Promise<void>;
+    tryCatchUpWithPrimary(): Promise<CatchUpResult>;
+    followPrimary(listener: PrimaryChangeListener, interval?: number): () => void;
+    getProperties(): Promise<DatabaseProperties>;
+    approximateSize(start?: Buffer, end?: Buffer): Promise<RangeSize>;
+    getWriteStall(): Promise<WriteStall>;
+    onWriteStall(listener: WriteStallListener, interval?: number): () => void;
+    compactSMT(): Promise<void>;
     getCurrentState(): Promise<CurrentState>;
     calculateRoot(proof: Proof): Promise<Buffer>;
+    getOpenResources(): OpenResource[];
+    getLeakedResources(): OpenResource[];
+}
+
+export class InMemoryStateDB extends StateDB {
+    constructor(option?: Omit<StateDBOptions, 'readonly' | 'secondaryPath' | 'inMemory'>);
+}
+
+export interface MultiTreeDBOptions {
+    readonly?: boolean;
+    lockTimeout?: number;
+    keyLength?: number;
+}
+
+export class MultiTreeDB {
+    constructor(path: string, option?: MultiTreeDBOptions);
+    getRoot(store: string): Promise<Buffer>;
+    commit(store: string, kvpairs: { key: Buffer, value: Buffer }[]): Promise<Buffer>;
+    commitAll(updates: Record<string, { key: Buffer, value: Buffer }[]>): Promise<Record<string, Buffer>>;
+    prove(store: string, queries: Buffer[]): Promise<Proof>;
+    verify(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
+    close(cb?: (err: Error | null) => void): void;
+}
+
+export interface UpdateOptions {
+    onProgress?: (percent: number) => void;
+    signal?: AbortSignal;
+    // keys with an empty value are deleted by default, or kept with the empty hash as the value by storeEmptyHash.
+    // A null value always deletes the key.
+    emptyValue?: 'deleteOnEmpty' | 'storeEmptyHash';
 }
 
 export class SparseMerkleTree {
     constructor(keyLength?: number);
-    update(root: Buffer, kvpair: { key: Buffer, value: Buffer }[]): Promise<Buffer>;
-    prove(root: Buffer, queries: Buffer[]): Promise<Proof>;
+    static encodePairs(kvpairs: { key: Buffer, value: Buffer }[]): Buffer;
+    update(root: Buffer, kvpair: { key: Buffer, value: Buffer | null }[] | Buffer, options?: UpdateOptions): Promise<Buffer>;
+    updateWithBatch(root: Buffer, batch: Batch | Buffer): Promise<Buffer>;
+    bulkLoad(kvpairs: { key: Buffer, value: Buffer }[] | AsyncIterable<{ key: Buffer, value: Buffer }> | Buffer, options?: AbortOptions): Promise<Buffer>;
+    toVisualization(root: Buffer, options?: { format?: 'json' }): Promise<VisualizationNode[]>;
+    toVisualization(root: Buffer, options: { format: 'csv' }): Promise<string>;
+    save(path: string, root: Buffer): Promise<void>;
+    load(path: string): Promise<Buffer>;
+    getStats(): Stats;
+    verifyChunk(root: Buffer, chunk: Buffer): Promise<boolean>;
+    verifyWitness(root: Buffer, witness: Buffer): Promise<WitnessEntry[]>;
+    importChunks(root: Buffer, chunks: Buffer[] | AsyncIterable<Buffer>): Promise<Buffer>;
+    prove(root: Buffer, queries: Buffer[], options?: AbortOptions): Promise<Proof>;
     verify(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
     verifyInclusionProof(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
     verifyNonInclusionProof(root: Buffer, queries: Buffer[], proof: Proof): Promise<boolean>;
     calculateRoot(proof: Proof): Promise<Buffer>;
 }
+
+export interface MemoryUsage {
+    budget: number | null;
+    total: number;
+    components: {
+        writeBatch: number;
+        stateWriter: number;
+        inMemoryDatabase: number;
+        inMemorySMT: number;
+    };
+}
+
+export function getMemoryUsage(): MemoryUsage;
+export function setMemoryBudget(budget: number | null): void;
+
+export interface WorkerPoolOptions {
+    size: number;
+    queueCapacity?: number;
+}
+
+export interface WorkerPoolStats {
+    size: number;
+    queueCapacity: number;
+}
+
+export function configureWorkerPool(options: WorkerPoolOptions): void;
+export function shutdownWorkerPool(): void;
+export function getWorkerPoolStats(): WorkerPoolStats;
+
+export interface LatencyHistogram {
+    count: number;
+    sum: number;
+    max: number;
+    buckets: { le: number | null; count: number }[];
+}
+
+export interface Stats {
+    enabled: boolean;
+    counters: {
+        subtreeCacheHits: number;
+        dbGets: number;
+        nodesHashed: number;
+    };
+    histograms: {
+        commitLatency: LatencyHistogram;
+        proveLatency: LatencyHistogram;
+    };
+}
+
+export function getStats(): Stats;
+export function setStatsEnabled(enabled: boolean): void;
+export function resetStats(): void;
+
+export type LogLevel = 'off' | 'error' | 'warn' | 'info' | 'debug' | 'trace';
+
+export interface LogRecord {
+    level: Exclude<LogLevel, 'off'>;
+    target: string;
+    spans: string;
+    message: string;
+    timestamp: number;
+}
+
+export interface LoggingOptions {
+    level?: LogLevel;
+    file?: string;
+    onLog?: (record: LogRecord) => void;
+}
+
+export function configureLogging(options?: LoggingOptions): void;
+
+export function restoreFromBackup(dir: string, target: string): Promise<void>;
+export function purgeOldBackups(dir: string, numBackupsToKeep: number): Promise<void>;
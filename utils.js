@@ -15,7 +15,134 @@
 const isInclusionProofForQueryKey = (queryKey, proofQuery) =>
         queryKey.equals(proofQuery.key) && !proofQuery.value.equals(Buffer.alloc(0));
 
+// formatVisualization converts the nodes of the tree dump to JSON serializable objects or CSV
+const formatVisualization = (nodes, format = 'json') => {
+    const rows = nodes.map(node => ({
+        type: node.type,
+        height: node.height,
+        hash: node.hash.toString('hex'),
+        key: node.key.toString('hex'),
+        value: node.value.toString('hex'),
+    }));
+    if (format === 'json') {
+        return rows;
+    }
+    if (format === 'csv') {
+        const lines = rows.map(row => `${row.type},${row.height},${row.hash},${row.key},${row.value}`);
+        return ['type,height,hash,key,value', ...lines].join('\n');
+    }
+    throw new Error(`Unknown visualization format ${format}`);
+};
+
+// followPrimary catches up with the primary every interval and calls the listener when the data has changed.
+// It returns the function to stop following.
+const followPrimary = (catchUp, listener, interval) => {
+    let stopped = false;
+    let timer;
+    const poll = async () => {
+        try {
+            const result = await catchUp();
+            if (result.changed && !stopped) {
+                await listener(null, result);
+            }
+        } catch (error) {
+            if (!stopped) {
+                await listener(error);
+            }
+        }
+        if (!stopped) {
+            timer = setTimeout(poll, interval);
+            timer.unref();
+        }
+    };
+    timer = setTimeout(poll, interval);
+    timer.unref();
+    return () => {
+        stopped = true;
+        clearTimeout(timer);
+    };
+};
+
+// watchWriteStall reads the condition of the writes every interval and calls the listener when it has changed.
+// The stalls shorter than the interval may not be observed. It returns the function to stop watching.
+const watchWriteStall = (read, listener, interval) => {
+    let stopped = false;
+    let timer;
+    let last = 'normal';
+    const poll = async () => {
+        try {
+            const result = await read();
+            if (result.condition !== last && !stopped) {
+                last = result.condition;
+                await listener(null, result);
+            }
+        } catch (error) {
+            if (!stopped) {
+                await listener(error);
+            }
+        }
+        if (!stopped) {
+            timer = setTimeout(poll, interval);
+            timer.unref();
+        }
+    };
+    timer = setTimeout(poll, interval);
+    timer.unref();
+    return () => {
+        stopped = true;
+        clearTimeout(timer);
+    };
+};
+
+const varintSize = value => {
+    let size = 1;
+    for (let rest = value; rest > 0x7f; rest = Math.floor(rest / 128)) {
+        size += 1;
+    }
+    return size;
+};
+
+const writeVarint = (bytes, offset, value) => {
+    let index = offset;
+    let rest = value;
+    while (rest > 0x7f) {
+        bytes[index] = (rest & 0x7f) | 0x80;
+        rest = Math.floor(rest / 128);
+        index += 1;
+    }
+    bytes[index] = rest;
+    return index + 1;
+};
+
+const writeField = (bytes, offset, tag, value) => {
+    bytes[offset] = tag;
+    const start = writeVarint(bytes, offset + 1, value.length);
+    bytes.set(value, start);
+    return start + value.length;
+};
+
+// encodePairs encodes the key-value pairs as the repeated messages of { key: field 1, value: field 2 },
+// so that the native update and bulkLoad read all the pairs from a single buffer
+const encodePairs = pairs => {
+    const fieldSize = value => 1 + varintSize(value.length) + value.length;
+    const sizes = pairs.map(({ key, value }) => fieldSize(key) + fieldSize(value));
+    const total = sizes.reduce((sum, size) => sum + 1 + varintSize(size) + size, 0);
+    const encoded = Buffer.alloc(total);
+    let offset = 0;
+    pairs.forEach(({ key, value }, i) => {
+        encoded[offset] = 0x0a;
+        offset = writeVarint(encoded, offset + 1, sizes[i]);
+        offset = writeField(encoded, offset, 0x0a, key);
+        offset = writeField(encoded, offset, 0x12, value);
+    });
+    return encoded;
+};
+
 module.exports = {
     isInclusionProofForQueryKey,
+    formatVisualization,
+    followPrimary,
+    watchWriteStall,
+    encodePairs,
 };
 
@@ -0,0 +1,36 @@
+/*
+ * Copyright © 2023 Lisk Foundation
+ *
+ * See the LICENSE file at the top-level directory of this distribution
+ * for licensing information.
+ *
+ * Unless otherwise agreed in a custom licensing agreement with the Lisk Foundation,
+ * no part of this software, including this file, may be copied, modified,
+ * propagated, or distributed except according to the terms contained in the
+ * LICENSE file.
+ *
+ * Removal or modification of this copyright notice is prohibited.
+ */
+'use strict';
+
+const {
+    worker_pool_configure,
+    worker_pool_shutdown,
+    worker_pool_stats,
+} = require("./bin-package/index.node");
+
+// configureWorkerPool replaces the pool running the proofs, the in-memory tree updates and the writer scans.
+// Operations are rejected while queueCapacity operations are waiting for a worker.
+const configureWorkerPool = (options = {}) => worker_pool_configure(options.size, options.queueCapacity);
+
+// shutdownWorkerPool stops the pool after the queued operations complete. A new pool is created on the next operation.
+const shutdownWorkerPool = () => worker_pool_shutdown();
+
+// getWorkerPoolStats returns the size and the queue capacity of the pool.
+const getWorkerPoolStats = () => worker_pool_stats();
+
+module.exports = {
+    configureWorkerPool,
+    shutdownWorkerPool,
+    getWorkerPoolStats,
+};